@@ -6,47 +6,41 @@ extern crate gotham;
 extern crate hyper;
 extern crate mime;
 
-use hyper::header::{HeaderMap, COOKIE, SET_COOKIE};
 use hyper::{Body, Response, StatusCode};
 
-use cookie::Cookie;
-
+use gotham::cookies::{cookie_jar, Cookie};
 use gotham::helpers::http::response::create_response;
-use gotham::state::{FromState, State};
+use gotham::state::State;
 
 /// The first request will set a cookie, and subsequent requests will echo it back.
-fn handler(state: State) -> (State, Response<Body>) {
+fn handler(mut state: State) -> (State, Response<Body>) {
     // Define a narrow scope so that state can be borrowed/moved later in the function.
     let adjective = {
-        // Get the request headers.
-        let headers = HeaderMap::borrow_from(&state);
-        // Get the Cookie header from the request.
-        headers
-            .get_all(COOKIE)
-            .iter()
-            .flat_map(|hv| hv.to_str())
-            .flat_map(|cv| Cookie::parse(cv.to_owned()))
-            .find(|cookie| cookie.name() == "adjective")
+        // Get the cookies sent with the request.
+        let jar = cookie_jar(&mut state);
+
+        let adjective = jar
+            .get("adjective")
             .map(|adj_cookie| adj_cookie.value().to_owned())
-            .unwrap_or_else(|| "first time".to_string())
+            .unwrap_or_else(|| "first time".to_string());
+
+        // Cookies added to the jar are sent with the response.
+        jar.add(
+            Cookie::build("adjective", "repeat")
+                .http_only(true)
+                .finish(),
+        );
+
+        adjective
     };
 
-    let mut response = create_response(
+    let response = create_response(
         &state,
         StatusCode::OK,
         mime::TEXT_PLAIN,
         format!("Hello {} visitor\n", adjective),
     );
 
-    {
-        let cookie = Cookie::build("adjective", "repeat")
-            .http_only(true)
-            .finish();
-        response
-            .headers_mut()
-            .append(SET_COOKIE, cookie.to_string().parse().unwrap());
-    }
-
     (state, response)
 }
 
@@ -62,6 +56,7 @@ mod tests {
     use super::*;
     use cookie::Cookie;
     use gotham::test::TestServer;
+    use hyper::header::{COOKIE, SET_COOKIE};
 
     #[test]
     fn cookie_is_set_and_counter_increments() {
@@ -0,0 +1,376 @@
+//! Defines `HttpClient`, which handlers use to make requests to other services.
+//!
+//! A single `HttpClient` is shared by every request a server handles, so that connections to
+//! other services are pooled and reused rather than opened for each request. It runs on the
+//! `Runtime` of the server, and is made available to handlers in `State` by
+//! `ServerBuilder::with_http_client`.
+//!
+//! Requests made with an `HttpClient` carry the context of the request being handled: the
+//! request ID is sent as `X-Request-ID`, the trace is continued with a `traceparent` header, and
+//! the request is given up on once the `Deadline` of the request, if any, has passed.
+//!
+//! # Examples
+//!
+//! ```rust
+//! # extern crate futures;
+//! # extern crate gotham;
+//! # extern crate hyper;
+//! #
+//! # use futures::{future, Future};
+//! # use hyper::header::HeaderValue;
+//! # use hyper::Uri;
+//! # use gotham::client::HttpClient;
+//! # use gotham::handler::{HandlerFuture, IntoHandlerError};
+//! # use gotham::state::{request_id, FromState, State};
+//! # use gotham::test::TestServer;
+//! # use gotham::ServerBuilder;
+//! #
+//! fn proxy(state: State, upstream: Uri) -> Box<HandlerFuture> {
+//!     let response = HttpClient::borrow_from(&state).get(&state, upstream);
+//!
+//!     Box::new(response.then(move |result| match result {
+//!         Ok(response) => future::ok((state, response)),
+//!         Err(e) => {
+//!             let status = e.status();
+//!             future::err((state, e.into_handler_error().with_status(status)))
+//!         }
+//!     }))
+//! }
+//! #
+//! # fn upstream(state: State) -> (State, String) {
+//! #   let id = request_id(&state).to_owned();
+//! #   (state, id)
+//! # }
+//!
+//! # fn main() {
+//! #   let upstream_server = TestServer::new(|| Ok(upstream)).unwrap();
+//! #   let addr = upstream_server.addr();
+//! #
+//! let builder = ServerBuilder::new().with_http_client(HttpClient::new());
+//! #
+//! #   let test_server = TestServer::with_server_builder(builder, move || {
+//! #       let uri: Uri = format!("http://{}/", addr).parse().unwrap();
+//! #       Ok(move |state| proxy(state, uri))
+//! #   })
+//! #   .unwrap();
+//! #
+//! #   let response = test_server
+//! #       .client()
+//! #       .get("http://example.com/")
+//! #       .with_header("x-request-id", HeaderValue::from_static("abc123"))
+//! #       .perform()
+//! #       .unwrap();
+//! #   assert_eq!(response.read_utf8_body().unwrap(), "abc123");
+//! # }
+//! ```
+
+use futures::Future;
+use hyper::client::HttpConnector;
+use hyper::header::{HeaderMap, HeaderName, HeaderValue};
+use hyper::{Body, Client, Request, Response, StatusCode, Uri};
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::time::Duration;
+use tokio::timer::{timeout, Timeout};
+
+use helpers::http::header::X_REQUEST_ID;
+use middleware::timeout::Deadline;
+use middleware::tracing::{Span, SpanContext};
+use state::request_id::RequestId;
+use state::{FromState, State, StateData};
+
+const TRACEPARENT: &str = "traceparent";
+
+// The number of threads which resolve host names.
+const DNS_THREADS: usize = 4;
+
+// How long an idle pooled connection is kept open for, in seconds.
+const IDLE_TIMEOUT_SECS: u64 = 90;
+
+// The default time allowed for a response to be received, in seconds.
+const DEFAULT_TIMEOUT_SECS: u64 = 30;
+
+/// The future of a response to a request made with an `HttpClient`.
+pub type ClientFuture = Future<Item = Response<Body>, Error = ClientError> + Send;
+
+/// An HTTP client for making requests to other services while handling a request.
+///
+/// Connections are pooled, and idle connections are closed after 90 seconds. A request fails
+/// with `ClientError::TimedOut` when its response headers aren't received within the timeout of
+/// the client, which is 30 seconds by default, or before the `Deadline` of the request being
+/// handled, whichever is sooner.
+///
+/// Each request is sent with the context of the request being handled:
+///
+/// * the request ID, as `X-Request-ID`;
+/// * the trace, as `traceparent`. With `TracingMiddleware`, each request is recorded as a child
+///   span of the request being handled, and the trace continues from it. Otherwise, the trace
+///   context of the request being handled, if any, is passed on unchanged; and
+/// * the values of any headers of the request being handled which were given to
+///   `with_propagated_header`, such as `Authorization`.
+///
+/// Headers which are already set on the outgoing request are left unchanged.
+///
+/// Cloning an `HttpClient` shares its connection pool. See the `client` module for an example.
+#[derive(Clone)]
+pub struct HttpClient {
+    client: Client<HttpConnector, Body>,
+    timeout: Option<Duration>,
+    propagated: Vec<HeaderName>,
+}
+
+impl StateData for HttpClient {}
+
+impl Default for HttpClient {
+    fn default() -> HttpClient {
+        let mut connector = HttpConnector::new(DNS_THREADS);
+        connector.set_nodelay(true);
+
+        let client = Client::builder()
+            .keep_alive_timeout(Duration::from_secs(IDLE_TIMEOUT_SECS))
+            .build(connector);
+
+        HttpClient::from_client(client)
+    }
+}
+
+impl HttpClient {
+    /// Creates an `HttpClient` with the default connection pool and timeout.
+    pub fn new() -> HttpClient {
+        HttpClient::default()
+    }
+
+    /// Creates an `HttpClient` which makes requests with `client`, such as one built with a
+    /// differently configured connection pool.
+    pub fn from_client(client: Client<HttpConnector, Body>) -> HttpClient {
+        HttpClient {
+            client,
+            timeout: Some(Duration::from_secs(DEFAULT_TIMEOUT_SECS)),
+            propagated: Vec::new(),
+        }
+    }
+
+    /// Sets the time allowed for the response headers of each request to be received, or `None`
+    /// to allow any time, other than the `Deadline` of the request being handled.
+    pub fn with_timeout(self, timeout: Option<Duration>) -> HttpClient {
+        HttpClient { timeout, ..self }
+    }
+
+    /// Passes on the values of the header `name` from the request being handled to each request
+    /// made.
+    pub fn with_propagated_header(mut self, name: HeaderName) -> HttpClient {
+        self.propagated.push(name);
+        self
+    }
+
+    /// Makes a `GET` request to `uri`, on behalf of the request in `state`.
+    pub fn get(&self, state: &State, uri: Uri) -> Box<ClientFuture> {
+        let mut request = Request::new(Body::empty());
+        *request.uri_mut() = uri;
+        self.request(state, request)
+    }
+
+    /// Makes `request`, on behalf of the request in `state`.
+    pub fn request(&self, state: &State, mut request: Request<Body>) -> Box<ClientFuture> {
+        let span = self.prepare(state, &mut request);
+
+        let timeout = match (self.timeout, Deadline::try_borrow_from(state)) {
+            (Some(timeout), Some(deadline)) => Some(deadline.min(timeout)),
+            (None, Some(deadline)) => Some(deadline.remaining()),
+            (timeout, None) => timeout,
+        };
+
+        let response = self.client.request(request);
+        let f: Box<ClientFuture> = match timeout {
+            Some(timeout) => Box::new(Timeout::new(response, timeout).map_err(timeout_error)),
+            None => Box::new(response.map_err(ClientError::Http)),
+        };
+
+        match span {
+            Some(mut span) => Box::new(f.then(move |result| {
+                match result {
+                    Ok(ref response) => {
+                        span.set_attribute("http.status_code", response.status().as_u16())
+                    }
+                    Err(_) => span.set_attribute("error", true),
+                }
+                span.end();
+                result
+            })),
+            None => f,
+        }
+    }
+
+    // Adds the context of the request in `state` to the headers of `request`, returning the span
+    // which records the request when the request in `state` is being traced.
+    fn prepare(&self, state: &State, request: &mut Request<Body>) -> Option<Span> {
+        let span = Span::try_borrow_from(state).map(|parent| {
+            let mut span = parent.child(format!("HTTP {}", request.method()));
+            span.set_attribute("http.method", request.method());
+            span.set_attribute("http.url", request.uri());
+            span
+        });
+
+        let incoming = HeaderMap::try_borrow_from(state);
+        let traceparent = match span {
+            Some(ref span) => Some(span.context().to_traceparent()),
+            None => incoming
+                .and_then(SpanContext::from_headers)
+                .map(|context| context.to_traceparent()),
+        };
+
+        let headers = request.headers_mut();
+
+        if let Some(id) = RequestId::try_borrow_from(state) {
+            insert_absent(headers, X_REQUEST_ID, id.as_str());
+        }
+
+        if let Some(traceparent) = traceparent {
+            insert_absent(headers, TRACEPARENT, &traceparent);
+        }
+
+        if let Some(incoming) = incoming {
+            for name in &self.propagated {
+                if headers.contains_key(name) {
+                    continue;
+                }
+
+                for value in incoming.get_all(name) {
+                    headers.append(name.clone(), value.clone());
+                }
+            }
+        }
+
+        span
+    }
+}
+
+fn timeout_error(e: timeout::Error<::hyper::Error>) -> ClientError {
+    match e.into_inner() {
+        Some(e) => ClientError::Http(e),
+        None => ClientError::TimedOut,
+    }
+}
+
+fn insert_absent(headers: &mut HeaderMap, name: &'static str, value: &str) {
+    if headers.contains_key(name) {
+        return;
+    }
+
+    if let Ok(value) = HeaderValue::from_str(value) {
+        headers.insert(name, value);
+    }
+}
+
+/// The error of a request made with an `HttpClient`.
+#[derive(Debug)]
+pub enum ClientError {
+    /// The request could not be sent, or the response could not be received.
+    Http(::hyper::Error),
+    /// The response was not received within the timeout of the client, or before the deadline
+    /// of the request being handled.
+    TimedOut,
+}
+
+impl ClientError {
+    /// The status of the response for this error, when the request being handled depended on
+    /// the request which failed: `504 Gateway Timeout` when it timed out, or `502 Bad Gateway`
+    /// otherwise.
+    pub fn status(&self) -> StatusCode {
+        match *self {
+            ClientError::Http(_) => StatusCode::BAD_GATEWAY,
+            ClientError::TimedOut => StatusCode::GATEWAY_TIMEOUT,
+        }
+    }
+}
+
+impl Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ClientError::Http(ref e) => write!(f, "outbound request failed: {}", e),
+            ClientError::TimedOut => write!(f, "outbound request timed out"),
+        }
+    }
+}
+
+impl Error for ClientError {
+    fn description(&self) -> &str {
+        match *self {
+            ClientError::Http(_) => "outbound request failed",
+            ClientError::TimedOut => "outbound request timed out",
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            ClientError::Http(ref e) => Some(e),
+            ClientError::TimedOut => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use hyper::header::{ACCEPT_LANGUAGE, AUTHORIZATION};
+
+    use state::set_request_id;
+
+    fn state(headers: &[(&'static str, &'static str)]) -> State {
+        let mut map = HeaderMap::new();
+        for &(name, value) in headers {
+            map.append(name, HeaderValue::from_static(value));
+        }
+
+        let mut state = State::new();
+        state.put(map);
+        set_request_id(&mut state);
+        state
+    }
+
+    fn prepare(client: &HttpClient, state: &State, request: &mut Request<Body>) {
+        assert!(client.prepare(state, request).is_none());
+    }
+
+    #[test]
+    fn propagates_request_context() {
+        let traceparent = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+        let state = state(&[
+            ("x-request-id", "abc123"),
+            ("traceparent", traceparent),
+            ("authorization", "Bearer token"),
+            ("accept-language", "en"),
+        ]);
+
+        let client = HttpClient::new().with_propagated_header(AUTHORIZATION);
+        let mut request = Request::new(Body::empty());
+        prepare(&client, &state, &mut request);
+
+        let headers = request.headers();
+        assert_eq!(headers.get(X_REQUEST_ID).unwrap(), "abc123");
+        assert_eq!(headers.get(TRACEPARENT).unwrap(), traceparent);
+        assert_eq!(headers.get(AUTHORIZATION).unwrap(), "Bearer token");
+        assert!(headers.get(ACCEPT_LANGUAGE).is_none());
+    }
+
+    #[test]
+    fn keeps_existing_headers() {
+        let state = state(&[("x-request-id", "abc123"), ("authorization", "Bearer a")]);
+
+        let client = HttpClient::new().with_propagated_header(AUTHORIZATION);
+        let mut request = Request::new(Body::empty());
+        request
+            .headers_mut()
+            .insert(X_REQUEST_ID, HeaderValue::from_static("def456"));
+        request
+            .headers_mut()
+            .insert(AUTHORIZATION, HeaderValue::from_static("Bearer b"));
+        prepare(&client, &state, &mut request);
+
+        let headers = request.headers();
+        assert_eq!(headers.get(X_REQUEST_ID).unwrap(), "def456");
+        assert_eq!(headers.get(AUTHORIZATION).unwrap(), "Bearer b");
+        assert!(headers.get(TRACEPARENT).is_none());
+    }
+}
@@ -0,0 +1,814 @@
+//! Loads the settings of a Gotham application from a TOML file and environment variables, so that
+//! a deployment can be configured without rebuilding the application.
+//!
+//! A `ConfigLoader` reads the settings into a `Config`, which is given to
+//! `ServerBuilder::with_config`, while its addresses are given to `ServerBuilder::start_all`. Every
+//! setting is optional, and is checked as it is loaded, so that an application with a mistake in
+//! its configuration fails at startup with a `ConfigError` listing each problem, rather than
+//! when the setting is first used.
+//!
+//! The settings of a file are grouped into tables:
+//!
+//! ```toml
+//! [server]
+//! bind = ["0.0.0.0:7878", "unix:/run/app.sock"]
+//! threads = 8
+//! backlog = 1024
+//! max_connections = 10000
+//! request_timeout = "30s"
+//! tcp_keepalive = "2m"
+//!
+//! [tls]
+//! certificate = "/etc/app/cert.pem"
+//! private_key = "/etc/app/key.pem"
+//!
+//! [session]
+//! cookie_name = "_app_session"
+//! cookie_path = "/"
+//! cookie_domain = "example.com"
+//! secure = true
+//! same_site = "strict"
+//! ```
+//!
+//! Each setting can also be given by an environment variable, named with the prefix `GOTHAM` by
+//! default, which takes precedence over the file: `GOTHAM_BIND` (with addresses separated by
+//! commas), `GOTHAM_THREADS`, `GOTHAM_BACKLOG`, `GOTHAM_MAX_CONNECTIONS`,
+//! `GOTHAM_REQUEST_TIMEOUT`, `GOTHAM_TCP_KEEPALIVE`, `GOTHAM_TLS_CERTIFICATE`,
+//! `GOTHAM_TLS_PRIVATE_KEY`, `GOTHAM_SESSION_COOKIE_NAME`, `GOTHAM_SESSION_COOKIE_PATH`,
+//! `GOTHAM_SESSION_COOKIE_DOMAIN`, `GOTHAM_SESSION_SECURE` and `GOTHAM_SESSION_SAME_SITE`.
+//!
+//! Durations are written with a unit of `ms`, `s`, `m` or `h`. When no address is given, the
+//! server listens on `127.0.0.1:7878`.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! # extern crate gotham;
+//! # extern crate hyper;
+//! #
+//! # use std::process;
+//! # use hyper::{Body, Response};
+//! # use gotham::config::ConfigLoader;
+//! # use gotham::ServerBuilder;
+//! # use gotham::state::State;
+//! #
+//! fn hello(state: State) -> (State, Response<Body>) {
+//!     (state, Response::new(Body::from("Hello, world!")))
+//! }
+//!
+//! # fn main() {
+//! let config = match ConfigLoader::new().with_file("app.toml").load() {
+//!     Ok(config) => config,
+//!     Err(e) => {
+//!         eprintln!("{}", e);
+//!         process::exit(1);
+//!     }
+//! };
+//!
+//! ServerBuilder::new()
+//!     .with_config(&config)
+//!     .start_all(config.bind().to_vec(), || Ok(hello));
+//! # }
+//! ```
+
+use std::collections::BTreeMap;
+use std::env;
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use toml::{self, Value};
+
+use server::ListenAddr;
+
+const DEFAULT_BIND: &str = "127.0.0.1:7878";
+
+// The key of each setting in a file, and the suffix of its environment variable.
+const SETTINGS: &[(&str, &str)] = &[
+    ("server.bind", "BIND"),
+    ("server.threads", "THREADS"),
+    ("server.backlog", "BACKLOG"),
+    ("server.max_connections", "MAX_CONNECTIONS"),
+    ("server.request_timeout", "REQUEST_TIMEOUT"),
+    ("server.tcp_keepalive", "TCP_KEEPALIVE"),
+    ("tls.certificate", "TLS_CERTIFICATE"),
+    ("tls.private_key", "TLS_PRIVATE_KEY"),
+    ("session.cookie_name", "SESSION_COOKIE_NAME"),
+    ("session.cookie_path", "SESSION_COOKIE_PATH"),
+    ("session.cookie_domain", "SESSION_COOKIE_DOMAIN"),
+    ("session.secure", "SESSION_SECURE"),
+    ("session.same_site", "SESSION_SAME_SITE"),
+];
+
+/// The settings of a Gotham application, as loaded by a `ConfigLoader`.
+#[derive(Clone, Debug)]
+pub struct Config {
+    bind: Vec<ListenAddr>,
+    threads: Option<usize>,
+    backlog: Option<i32>,
+    max_connections: Option<usize>,
+    request_timeout: Option<Duration>,
+    tcp_keepalive: Option<Duration>,
+    tls: Option<TlsConfig>,
+    session: SessionConfig,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            bind: vec![ListenAddr::Tcp(DEFAULT_BIND.parse().unwrap())],
+            threads: None,
+            backlog: None,
+            max_connections: None,
+            request_timeout: None,
+            tcp_keepalive: None,
+            tls: None,
+            session: SessionConfig::default(),
+        }
+    }
+}
+
+impl Config {
+    /// The addresses which the server listens on, to be given to `ServerBuilder::start_all`.
+    pub fn bind(&self) -> &[ListenAddr] {
+        &self.bind
+    }
+
+    /// The number of threads used to serve connections.
+    pub fn threads(&self) -> Option<usize> {
+        self.threads
+    }
+
+    /// The maximum number of pending connections queued for each listening socket.
+    pub fn backlog(&self) -> Option<i32> {
+        self.backlog
+    }
+
+    /// The maximum number of connections which are served at once.
+    pub fn max_connections(&self) -> Option<usize> {
+        self.max_connections
+    }
+
+    /// The time allowed to handle each request, to be given to `RequestTimeout::new`.
+    pub fn request_timeout(&self) -> Option<Duration> {
+        self.request_timeout
+    }
+
+    /// The TCP keep-alive interval of accepted connections.
+    pub fn tcp_keepalive(&self) -> Option<Duration> {
+        self.tcp_keepalive
+    }
+
+    /// The certificate and private key to serve TLS with. Gotham serves plain HTTP, so these are
+    /// checked to exist and then left for the application, such as to configure the TLS
+    /// terminating proxy or acceptor in front of it.
+    pub fn tls(&self) -> Option<&TlsConfig> {
+        self.tls.as_ref()
+    }
+
+    /// The session cookie settings, to be given to `NewSessionMiddleware::with_config`.
+    pub fn session(&self) -> &SessionConfig {
+        &self.session
+    }
+}
+
+/// The paths of the certificate and private key which TLS is served with.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TlsConfig {
+    certificate: PathBuf,
+    private_key: PathBuf,
+}
+
+impl TlsConfig {
+    /// The path of the PEM encoded certificate chain.
+    pub fn certificate(&self) -> &Path {
+        &self.certificate
+    }
+
+    /// The path of the PEM encoded private key.
+    pub fn private_key(&self) -> &Path {
+        &self.private_key
+    }
+}
+
+/// The settings of the session cookie. Settings which are not given keep the defaults of
+/// `NewSessionMiddleware`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SessionConfig {
+    pub(crate) cookie_name: Option<String>,
+    pub(crate) cookie_path: Option<String>,
+    pub(crate) cookie_domain: Option<String>,
+    pub(crate) secure: Option<bool>,
+    pub(crate) same_site: Option<SameSitePolicy>,
+}
+
+impl SessionConfig {
+    /// The name of the cookie.
+    pub fn cookie_name(&self) -> Option<&str> {
+        self.cookie_name.as_ref().map(String::as_str)
+    }
+
+    /// The `Path` attribute of the cookie.
+    pub fn cookie_path(&self) -> Option<&str> {
+        self.cookie_path.as_ref().map(String::as_str)
+    }
+
+    /// The `Domain` attribute of the cookie.
+    pub fn cookie_domain(&self) -> Option<&str> {
+        self.cookie_domain.as_ref().map(String::as_str)
+    }
+
+    /// Whether the cookie has the `Secure` attribute.
+    pub fn secure(&self) -> Option<bool> {
+        self.secure
+    }
+
+    /// The `SameSite` attribute of the cookie.
+    pub fn same_site(&self) -> Option<SameSitePolicy> {
+        self.same_site
+    }
+}
+
+/// The `SameSite` attribute of the session cookie, written as `strict`, `lax` or `disabled`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SameSitePolicy {
+    /// The cookie is never sent with cross-site requests.
+    Strict,
+    /// The cookie is only sent with cross-site requests which are top-level navigations using a
+    /// safe method.
+    Lax,
+    /// The attribute is left out, so that the cookie is sent with cross-site requests.
+    Disabled,
+}
+
+/// Loads a `Config` from a file and environment variables.
+#[derive(Clone, Debug)]
+pub struct ConfigLoader {
+    file: Option<PathBuf>,
+    env_prefix: Option<String>,
+}
+
+impl Default for ConfigLoader {
+    fn default() -> ConfigLoader {
+        ConfigLoader {
+            file: None,
+            env_prefix: Some("GOTHAM".to_owned()),
+        }
+    }
+}
+
+impl ConfigLoader {
+    /// Creates a `ConfigLoader` which reads environment variables with the prefix `GOTHAM`.
+    pub fn new() -> ConfigLoader {
+        ConfigLoader::default()
+    }
+
+    /// Reads the TOML file at `path`, which must exist. Environment variables take precedence
+    /// over the settings of the file.
+    pub fn with_file<P>(self, path: P) -> ConfigLoader
+    where
+        P: AsRef<Path>,
+    {
+        ConfigLoader {
+            file: Some(path.as_ref().to_owned()),
+            ..self
+        }
+    }
+
+    /// Reads environment variables with `prefix` rather than `GOTHAM`, such as `MYAPP_THREADS`
+    /// for the prefix `MYAPP`.
+    pub fn with_env_prefix<S>(self, prefix: S) -> ConfigLoader
+    where
+        S: AsRef<str>,
+    {
+        ConfigLoader {
+            env_prefix: Some(prefix.as_ref().to_owned()),
+            ..self
+        }
+    }
+
+    /// Ignores environment variables, so that settings are only read from the file.
+    pub fn without_env(self) -> ConfigLoader {
+        ConfigLoader {
+            env_prefix: None,
+            ..self
+        }
+    }
+
+    /// Loads the `Config`, failing with every problem found in the settings.
+    pub fn load(&self) -> Result<Config, ConfigError> {
+        let toml = match self.file {
+            Some(ref path) => match fs::read_to_string(path) {
+                Ok(toml) => Some((toml, path.display().to_string())),
+                Err(e) => return Err(ConfigError::Io(path.clone(), e)),
+            },
+            None => None,
+        };
+
+        self.load_from(toml, |name| env::var(name).ok())
+    }
+
+    fn load_from<F>(&self, toml: Option<(String, String)>, var: F) -> Result<Config, ConfigError>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let mut settings = Settings::new();
+        let mut problems = Vec::new();
+
+        if let Some((toml, origin)) = toml {
+            match toml.parse::<Value>() {
+                Ok(document) => read_document(&document, &origin, &mut settings, &mut problems),
+                Err(e) => return Err(ConfigError::Parse(origin, e)),
+            }
+        }
+
+        if let Some(ref prefix) = self.env_prefix {
+            read_env(prefix, var, &mut settings);
+        }
+
+        let config = validate(&settings, &mut problems);
+        if problems.is_empty() {
+            Ok(config)
+        } else {
+            Err(ConfigError::Invalid(problems))
+        }
+    }
+}
+
+/// The error of loading a `Config`.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The file could not be read.
+    Io(PathBuf, io::Error),
+    /// The file is not valid TOML.
+    Parse(String, toml::de::Error),
+    /// The settings are invalid, with a description of each problem naming the setting.
+    Invalid(Vec<String>),
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConfigError::Io(ref path, ref e) => {
+                write!(
+                    f,
+                    "unable to read configuration from {}: {}",
+                    path.display(),
+                    e
+                )
+            }
+            ConfigError::Parse(ref origin, ref e) => {
+                write!(f, "unable to parse configuration in {}: {}", origin, e)
+            }
+            ConfigError::Invalid(ref problems) => {
+                write!(f, "invalid configuration:")?;
+                for problem in problems {
+                    write!(f, "\n  {}", problem)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Error for ConfigError {
+    fn description(&self) -> &str {
+        match *self {
+            ConfigError::Io(..) => "unable to read configuration",
+            ConfigError::Parse(..) => "unable to parse configuration",
+            ConfigError::Invalid(_) => "invalid configuration",
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            ConfigError::Io(_, ref e) => Some(e),
+            ConfigError::Parse(_, ref e) => Some(e),
+            ConfigError::Invalid(_) => None,
+        }
+    }
+}
+
+// A setting as given, with a description of where it was given for reporting problems.
+struct Setting {
+    source: String,
+    values: Vec<String>,
+}
+
+type Settings = BTreeMap<&'static str, Setting>;
+
+fn read_document(
+    document: &Value,
+    origin: &str,
+    settings: &mut Settings,
+    problems: &mut Vec<String>,
+) {
+    let sections = match document.as_table() {
+        Some(sections) => sections,
+        None => return,
+    };
+
+    for (section, table) in sections {
+        let table = match table.as_table() {
+            Some(table) => table,
+            None => {
+                problems.push(format!("`{}` in {}: unknown setting", section, origin));
+                continue;
+            }
+        };
+
+        for (name, value) in table {
+            let key = format!("{}.{}", section, name);
+            let source = format!("`{}` in {}", key, origin);
+            let key = match SETTINGS.iter().find(|&&(k, _)| k == key) {
+                Some(&(key, _)) => key,
+                None => {
+                    problems.push(format!("{}: unknown setting", source));
+                    continue;
+                }
+            };
+
+            match read_values(value, key == "server.bind") {
+                Some(values) => {
+                    settings.insert(key, Setting { source, values });
+                }
+                None if key == "server.bind" => problems.push(format!(
+                    "{}: expected an address or list of addresses",
+                    source
+                )),
+                None => problems.push(format!("{}: expected a single value", source)),
+            }
+        }
+    }
+}
+
+fn read_values(value: &Value, list: bool) -> Option<Vec<String>> {
+    match *value {
+        Value::Array(ref items) if list => items.iter().map(read_value).collect(),
+        _ => read_value(value).map(|value| vec![value]),
+    }
+}
+
+fn read_value(value: &Value) -> Option<String> {
+    match *value {
+        Value::String(ref s) => Some(s.clone()),
+        Value::Integer(i) => Some(i.to_string()),
+        Value::Float(f) => Some(f.to_string()),
+        Value::Boolean(b) => Some(b.to_string()),
+        Value::Datetime(ref d) => Some(d.to_string()),
+        Value::Array(_) | Value::Table(_) => None,
+    }
+}
+
+fn read_env<F>(prefix: &str, var: F, settings: &mut Settings)
+where
+    F: Fn(&str) -> Option<String>,
+{
+    for &(key, suffix) in SETTINGS {
+        let name = format!("{}_{}", prefix, suffix);
+        if let Some(value) = var(&name) {
+            let values = if key == "server.bind" {
+                value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|addr| !addr.is_empty())
+                    .map(str::to_owned)
+                    .collect()
+            } else {
+                vec![value]
+            };
+
+            settings.insert(
+                key,
+                Setting {
+                    source: name,
+                    values,
+                },
+            );
+        }
+    }
+}
+
+// Converts the settings into a `Config`, adding a problem for each one which is invalid.
+fn validate(settings: &Settings, problems: &mut Vec<String>) -> Config {
+    let mut config = Config::default();
+
+    if let Some(setting) = settings.get("server.bind") {
+        let mut bind: Vec<ListenAddr> = Vec::new();
+        for value in &setting.values {
+            match parse_listen_addr(value) {
+                Ok(ref addr) if bind.contains(addr) => {
+                    problems.push(format!("{}: `{}` is given twice", setting.source, value))
+                }
+                Ok(addr) => bind.push(addr),
+                Err(e) => problems.push(format!("{}: {}", setting.source, e)),
+            }
+        }
+
+        if setting.values.is_empty() {
+            problems.push(format!(
+                "{}: at least one address is required",
+                setting.source
+            ));
+        }
+        config.bind = bind;
+    }
+
+    config.threads = setting(settings, "server.threads", problems, parse_positive);
+    config.backlog = setting(settings, "server.backlog", problems, parse_positive);
+    config.max_connections = setting(settings, "server.max_connections", problems, parse_positive);
+    config.request_timeout = setting(settings, "server.request_timeout", problems, parse_duration);
+    config.tcp_keepalive = setting(settings, "server.tcp_keepalive", problems, parse_duration);
+
+    let certificate = setting(settings, "tls.certificate", problems, parse_file);
+    let private_key = setting(settings, "tls.private_key", problems, parse_file);
+    match (
+        settings.get("tls.certificate"),
+        settings.get("tls.private_key"),
+    ) {
+        (Some(_), None) | (None, Some(_)) => problems
+            .push("`tls.certificate` and `tls.private_key` must be given together".to_owned()),
+        _ => (),
+    }
+    if let (Some(certificate), Some(private_key)) = (certificate, private_key) {
+        config.tls = Some(TlsConfig {
+            certificate,
+            private_key,
+        });
+    }
+
+    config.session = SessionConfig {
+        cookie_name: setting(settings, "session.cookie_name", problems, parse_cookie_name),
+        cookie_path: setting(settings, "session.cookie_path", problems, parse_cookie_path),
+        cookie_domain: setting(settings, "session.cookie_domain", problems, parse_non_empty),
+        secure: setting(settings, "session.secure", problems, parse_bool),
+        same_site: setting(settings, "session.same_site", problems, parse_same_site),
+    };
+
+    config
+}
+
+fn setting<T, F>(settings: &Settings, key: &str, problems: &mut Vec<String>, parse: F) -> Option<T>
+where
+    F: Fn(&str) -> Result<T, String>,
+{
+    let setting = settings.get(key)?;
+    match parse(&setting.values[0]) {
+        Ok(value) => Some(value),
+        Err(e) => {
+            problems.push(format!("{}: {}", setting.source, e));
+            None
+        }
+    }
+}
+
+fn parse_listen_addr(value: &str) -> Result<ListenAddr, String> {
+    if value.starts_with("unix:") {
+        return parse_unix_addr(&value["unix:".len()..]);
+    }
+
+    let addr = value.trim_left_matches("http://");
+    match addr.parse::<SocketAddr>() {
+        Ok(addr) => Ok(ListenAddr::Tcp(addr)),
+        Err(_) => Err(format!(
+            "`{}` is not an address, such as `0.0.0.0:7878` or `unix:/run/app.sock`",
+            value
+        )),
+    }
+}
+
+#[cfg(unix)]
+fn parse_unix_addr(path: &str) -> Result<ListenAddr, String> {
+    if path.is_empty() {
+        return Err("`unix:` is missing the path of the socket".to_owned());
+    }
+
+    Ok(ListenAddr::Unix(PathBuf::from(path)))
+}
+
+#[cfg(not(unix))]
+fn parse_unix_addr(path: &str) -> Result<ListenAddr, String> {
+    Err(format!(
+        "`unix:{}` is not supported, as this platform has no Unix domain sockets",
+        path
+    ))
+}
+
+fn parse_positive<T>(value: &str) -> Result<T, String>
+where
+    T: FromStr + Default + PartialOrd,
+{
+    match value.parse::<T>() {
+        Ok(n) if n > T::default() => Ok(n),
+        _ => Err(format!("`{}` is not a positive number", value)),
+    }
+}
+
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let digits = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (amount, unit) = value.split_at(digits);
+    let duration = match (amount.parse::<u64>(), unit.trim()) {
+        (Ok(n), "ms") => Some(Duration::from_millis(n)),
+        (Ok(n), "s") => Some(Duration::from_secs(n)),
+        (Ok(n), "m") => n.checked_mul(60).map(Duration::from_secs),
+        (Ok(n), "h") => n.checked_mul(60 * 60).map(Duration::from_secs),
+        _ => None,
+    };
+
+    match duration {
+        Some(duration) if duration > Duration::from_secs(0) => Ok(duration),
+        _ => Err(format!(
+            "`{}` is not a positive duration, such as `30s` or `500ms`",
+            value
+        )),
+    }
+}
+
+fn parse_file(value: &str) -> Result<PathBuf, String> {
+    let path = PathBuf::from(value);
+    if path.is_file() {
+        Ok(path)
+    } else {
+        Err(format!("`{}` is not a file", value))
+    }
+}
+
+fn parse_cookie_name(value: &str) -> Result<String, String> {
+    let separator =
+        |c: char| c.is_whitespace() || c.is_control() || "()<>@,;:\\\"/[]?={}".contains(c);
+    if value.is_empty() || value.contains(separator) {
+        Err(format!("`{}` is not a valid cookie name", value))
+    } else {
+        Ok(value.to_owned())
+    }
+}
+
+fn parse_cookie_path(value: &str) -> Result<String, String> {
+    if value.starts_with('/') && !value.contains(';') {
+        Ok(value.to_owned())
+    } else {
+        Err(format!("`{}` is not a path starting with `/`", value))
+    }
+}
+
+fn parse_non_empty(value: &str) -> Result<String, String> {
+    if value.is_empty() || value.contains(';') {
+        Err(format!("`{}` is not a valid cookie domain", value))
+    } else {
+        Ok(value.to_owned())
+    }
+}
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+    value
+        .parse()
+        .map_err(|_| format!("`{}` is not `true` or `false`", value))
+}
+
+fn parse_same_site(value: &str) -> Result<SameSitePolicy, String> {
+    match value {
+        "strict" => Ok(SameSitePolicy::Strict),
+        "lax" => Ok(SameSitePolicy::Lax),
+        "disabled" => Ok(SameSitePolicy::Disabled),
+        _ => Err(format!("`{}` is not `strict`, `lax` or `disabled`", value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    fn load(toml: &str, vars: &[(&str, &str)]) -> Result<Config, ConfigError> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|&(name, value)| (name.to_owned(), value.to_owned()))
+            .collect();
+
+        ConfigLoader::new().load_from(Some((toml.to_owned(), "app.toml".to_owned())), |name| {
+            vars.get(name).cloned()
+        })
+    }
+
+    fn problems(toml: &str, vars: &[(&str, &str)]) -> Vec<String> {
+        match load(toml, vars) {
+            Err(ConfigError::Invalid(problems)) => problems,
+            other => panic!("expected invalid configuration, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn loads_settings_from_file_and_env() {
+        let manifest = concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml");
+        let toml = format!(
+            r#"
+            [server]
+            bind = ["0.0.0.0:7878", "http://[::1]:7878"]
+            threads = 8
+            request_timeout = "30s"
+
+            [tls]
+            certificate = "{0}"
+            private_key = "{0}"
+
+            [session]
+            cookie_name = "_app_session"
+            same_site = "strict"
+            "#,
+            manifest
+        );
+        let vars = [
+            ("GOTHAM_THREADS", "2"),
+            ("GOTHAM_TCP_KEEPALIVE", "2m"),
+            ("GOTHAM_SESSION_SECURE", "false"),
+            ("OTHER_BACKLOG", "1"),
+        ];
+
+        let config = load(&toml, &vars).unwrap();
+        assert_eq!(
+            config.bind(),
+            &[
+                ListenAddr::Tcp("0.0.0.0:7878".parse().unwrap()),
+                ListenAddr::Tcp("[::1]:7878".parse().unwrap()),
+            ]
+        );
+        assert_eq!(config.threads(), Some(2));
+        assert_eq!(config.backlog(), None);
+        assert_eq!(config.request_timeout(), Some(Duration::from_secs(30)));
+        assert_eq!(config.tcp_keepalive(), Some(Duration::from_secs(120)));
+        assert_eq!(config.tls().unwrap().private_key(), Path::new(manifest));
+        assert_eq!(config.session().cookie_name(), Some("_app_session"));
+        assert_eq!(config.session().secure(), Some(false));
+        assert_eq!(config.session().same_site(), Some(SameSitePolicy::Strict));
+
+        let config = load("", &[("GOTHAM_BIND", "127.0.0.1:80, unix:/run/app.sock")]).unwrap();
+        assert_eq!(config.bind().len(), 2);
+        assert_eq!(
+            config.bind()[0],
+            ListenAddr::Tcp("127.0.0.1:80".parse().unwrap())
+        );
+
+        let config = load("", &[]).unwrap();
+        assert_eq!(
+            config.bind(),
+            &[ListenAddr::Tcp(DEFAULT_BIND.parse().unwrap())]
+        );
+    }
+
+    #[test]
+    fn reports_every_problem() {
+        let toml = r#"
+            [server]
+            bind = ["localhost", "0.0.0.0:80", "0.0.0.0:80"]
+            threads = 0
+            workers = 4
+
+            [tls]
+            certificate = "/missing/cert.pem"
+
+            [session]
+            cookie_path = "app"
+            "#;
+        let vars = [
+            ("GOTHAM_REQUEST_TIMEOUT", "30"),
+            ("GOTHAM_SESSION_SAME_SITE", "none"),
+        ];
+
+        assert_eq!(
+            problems(toml, &vars),
+            vec![
+                "`server.workers` in app.toml: unknown setting",
+                "`server.bind` in app.toml: `localhost` is not an address, such as \
+                 `0.0.0.0:7878` or `unix:/run/app.sock`",
+                "`server.bind` in app.toml: `0.0.0.0:80` is given twice",
+                "`server.threads` in app.toml: `0` is not a positive number",
+                "GOTHAM_REQUEST_TIMEOUT: `30` is not a positive duration, such as `30s` or `500ms`",
+                "`tls.certificate` in app.toml: `/missing/cert.pem` is not a file",
+                "`tls.certificate` and `tls.private_key` must be given together",
+                "`session.cookie_path` in app.toml: `app` is not a path starting with `/`",
+                "GOTHAM_SESSION_SAME_SITE: `none` is not `strict`, `lax` or `disabled`",
+            ]
+        );
+
+        match load("[server", &[]) {
+            Err(ConfigError::Parse(ref origin, _)) => assert_eq!(origin, "app.toml"),
+            other => panic!("expected a parse error, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_duration("1h"), Ok(Duration::from_secs(3600)));
+        assert!(parse_duration("0s").is_err());
+        assert!(parse_duration("1.5s").is_err());
+        assert!(parse_duration("s").is_err());
+    }
+}
@@ -0,0 +1,233 @@
+//! Defines the cookie jar which holds the cookies sent with a request, and collects the cookies to
+//! be sent with the response.
+//!
+//! The `Cookie` header of the request is parsed the first time `cookie_jar` is called, and the
+//! resulting `CookieJar` is kept in `State` for the rest of the request. Cookies which are added to
+//! or removed from the jar are sent to the client as `Set-Cookie` headers once the response has
+//! been produced, so handlers and middleware don't need to format those headers themselves.
+//!
+//! Small values which the application needs to trust can be stored in signed or private cookies,
+//! using the `SignedJar` and `PrivateJar` views of the jar which are returned by `CookieKeys`.
+//!
+//! # Examples
+//!
+//! ```rust
+//! # extern crate gotham;
+//! # extern crate hyper;
+//! #
+//! # use hyper::header::{COOKIE, SET_COOKIE};
+//! # use hyper::{Body, Response, StatusCode};
+//! # use gotham::cookies::{cookie_jar, Cookie, SameSite};
+//! # use gotham::state::State;
+//! # use gotham::test::TestServer;
+//! #
+//! fn handler(mut state: State) -> (State, Response<Body>) {
+//!     let visits = {
+//!         let jar = cookie_jar(&mut state);
+//!
+//!         let visits = jar
+//!             .get("visits")
+//!             .and_then(|cookie| cookie.value().parse().ok())
+//!             .unwrap_or(0u32);
+//!
+//!         jar.add(
+//!             Cookie::build("visits", (visits + 1).to_string())
+//!                 .path("/")
+//!                 .same_site(SameSite::Lax)
+//!                 .http_only(true)
+//!                 .finish(),
+//!         );
+//!
+//!         visits
+//!     };
+//!
+//!     let body = format!("You have visited {} times before", visits);
+//!     (state, Response::new(Body::from(body)))
+//! }
+//!
+//! # fn main() {
+//! #   let test_server = TestServer::new(|| Ok(handler)).unwrap();
+//! #   let response = test_server
+//! #       .client()
+//! #       .get("http://localhost/")
+//! #       .with_header(COOKIE, "theme=dark; visits=2".parse().unwrap())
+//! #       .perform()
+//! #       .unwrap();
+//! #
+//! #   assert_eq!(response.status(), StatusCode::OK);
+//! #   assert_eq!(
+//! #       response.headers()[SET_COOKIE],
+//! #       "visits=3; HttpOnly; SameSite=Lax; Path=/"
+//! #   );
+//! #
+//! #   let body = response.read_utf8_body().unwrap();
+//! #   assert_eq!(body, "You have visited 2 times before");
+//! # }
+//! ```
+
+use hyper::header::{HeaderMap, HeaderValue, COOKIE, SET_COOKIE};
+use hyper::{Body, Response};
+
+use state::{FromState, State, StateData};
+
+mod secure;
+
+pub use self::secure::{CookieKeys, PrivateJar, SignedJar};
+pub use cookie::{Cookie, CookieBuilder, CookieJar, Key, SameSite};
+
+impl StateData for CookieJar {}
+
+/// Returns the `CookieJar` for the request, parsing the request `Cookie` header the first time it
+/// is called.
+///
+/// The cookies sent by the client are available from `CookieJar::get`. Cookies passed to
+/// `CookieJar::add` are sent to the client with the response, and cookies passed to
+/// `CookieJar::remove` are expired on the client, when they were sent with the request.
+pub fn cookie_jar(state: &mut State) -> &mut CookieJar {
+    if !state.has::<CookieJar>() {
+        let jar = parse_cookies(HeaderMap::try_borrow_from(state));
+        state.put(jar);
+    }
+
+    state.borrow_mut::<CookieJar>()
+}
+
+/// Appends a `Set-Cookie` header to `response` for each cookie which has been added to or removed
+/// from the `CookieJar` in `state`, if it has been used.
+pub(crate) fn write_cookies(state: &State, response: &mut Response<Body>) {
+    let jar = match CookieJar::try_borrow_from(state) {
+        Some(jar) => jar,
+        None => return,
+    };
+
+    for cookie in jar.delta() {
+        match HeaderValue::from_str(&cookie.to_string()) {
+            Ok(value) => {
+                response.headers_mut().append(SET_COOKIE, value);
+            }
+            Err(_) => request_warn!(
+                state,
+                "unable to send cookie {}, the value is not a valid header",
+                cookie.name()
+            ),
+        }
+    }
+}
+
+// Builds a `CookieJar` containing each of the cookies in the `Cookie` headers, ignoring any which
+// cannot be parsed.
+fn parse_cookies(headers: Option<&HeaderMap>) -> CookieJar {
+    let mut jar = CookieJar::new();
+
+    let pairs = headers
+        .into_iter()
+        .flat_map(|headers| headers.get_all(COOKIE).iter())
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty());
+
+    for pair in pairs {
+        if let Ok(cookie) = Cookie::parse(pair.to_owned()) {
+            jar.add_original(cookie);
+        }
+    }
+
+    jar
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use hyper::StatusCode;
+
+    use test::TestServer;
+
+    fn state_with_cookies(values: &[&'static str]) -> State {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append(COOKIE, HeaderValue::from_static(value));
+        }
+
+        let mut state = State::new();
+        state.put(headers);
+        state
+    }
+
+    fn set_cookies(state: &State) -> Vec<String> {
+        let mut response = Response::new(Body::empty());
+        write_cookies(state, &mut response);
+
+        response
+            .headers()
+            .get_all(SET_COOKIE)
+            .iter()
+            .map(|value| value.to_str().unwrap().to_owned())
+            .collect()
+    }
+
+    #[test]
+    fn parses_request_cookies() {
+        let mut state = state_with_cookies(&["a=1; b=2", "c=3;;invalid"]);
+        let jar = cookie_jar(&mut state);
+
+        assert_eq!(jar.get("a").map(Cookie::value), Some("1"));
+        assert_eq!(jar.get("b").map(Cookie::value), Some("2"));
+        assert_eq!(jar.get("c").map(Cookie::value), Some("3"));
+        assert!(jar.get("invalid").is_none());
+    }
+
+    #[test]
+    fn empty_without_headers() {
+        let mut state = State::new();
+        assert_eq!(cookie_jar(&mut state).iter().count(), 0);
+    }
+
+    #[test]
+    fn writes_only_changes() {
+        let mut state = state_with_cookies(&["kept=1; removed=2"]);
+        assert!(set_cookies(&state).is_empty());
+
+        {
+            let jar = cookie_jar(&mut state);
+            jar.add(
+                Cookie::build("added", "3")
+                    .domain("example.com")
+                    .secure(true)
+                    .finish(),
+            );
+            jar.remove(Cookie::named("removed"));
+        }
+
+        let mut set_cookies = set_cookies(&state);
+        set_cookies.sort();
+
+        assert_eq!(set_cookies.len(), 2);
+        assert_eq!(set_cookies[0], "added=3; Secure; Domain=example.com");
+        assert!(set_cookies[1].starts_with("removed=; Max-Age=0; Expires="));
+    }
+
+    #[test]
+    fn flushed_on_error_response() {
+        use futures::future;
+        use handler::{HandlerFuture, IntoHandlerError};
+        use std::io;
+
+        fn handler(mut state: State) -> Box<HandlerFuture> {
+            cookie_jar(&mut state).add(Cookie::new("seen", "true"));
+            let err = io::Error::new(io::ErrorKind::Other, "failed").into_handler_error();
+            Box::new(future::err((state, err)))
+        }
+
+        let test_server = TestServer::new(|| Ok(handler)).unwrap();
+        let response = test_server
+            .client()
+            .get("http://localhost/")
+            .perform()
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(response.headers()[SET_COOKIE], "seen=true");
+    }
+}
@@ -0,0 +1,335 @@
+//! Defines signed and private cookies, which are protected by keys held by the application.
+
+use cookie::{Cookie, CookieJar, Key};
+
+use state::StateData;
+
+/// The keys used to protect signed and private cookies.
+///
+/// The first key is used to sign or encrypt new cookies. Previous keys are only used to read
+/// cookies which were sent before the key was rotated, and a cookie which is read with a previous
+/// key is sent again protected by the current key. A key can be retired once all the clients which
+/// might hold a cookie protected by it have received the replacement.
+///
+/// A cookie sent by the client carries only its name and value, so the replacement is sent with
+/// the attributes given to `with_reissue_attributes`, such as the `Path` the cookie was originally
+/// set with.
+///
+/// `CookieKeys` can be put into `State` by a `StateMiddleware`, so that handlers can use the same
+/// keys.
+#[derive(Clone)]
+pub struct CookieKeys {
+    keys: Vec<Key>,
+    reissue: Option<Cookie<'static>>,
+}
+
+impl CookieKeys {
+    /// Creates `CookieKeys` which protect cookies with `key`.
+    pub fn new(key: Key) -> CookieKeys {
+        CookieKeys {
+            keys: vec![key],
+            reissue: None,
+        }
+    }
+
+    /// Creates `CookieKeys` which protect cookies with a key derived from `master`, which must be
+    /// at least 32 bytes of secret, random data.
+    ///
+    /// # Panics
+    ///
+    /// If `master` is shorter than 32 bytes.
+    pub fn from_master(master: &[u8]) -> CookieKeys {
+        CookieKeys::new(Key::from_master(master))
+    }
+
+    /// Adds a key which was previously used to protect cookies. Cookies protected by the previous
+    /// key are still accepted, and are replaced by cookies protected by the current key when they
+    /// are read.
+    pub fn with_previous_key(mut self, key: Key) -> CookieKeys {
+        self.keys.push(key);
+        self
+    }
+
+    /// Sets the attributes of the cookies which are sent again protected by the current key, after
+    /// being read with a previous key. The `Path`, `Domain`, `Secure`, `HttpOnly`, `SameSite`,
+    /// `Max-Age` and `Expires` attributes are taken from `template`, while its name and value are
+    /// ignored. Without a template, the cookies are sent without any attributes.
+    ///
+    /// ```rust
+    /// # extern crate gotham;
+    /// #
+    /// # use gotham::cookies::{Cookie, CookieKeys, Key};
+    /// #
+    /// # fn main() {
+    /// let keys = CookieKeys::from_master(&[2; 64])
+    ///     .with_previous_key(Key::from_master(&[1; 64]))
+    ///     .with_reissue_attributes(Cookie::build("", "").path("/").secure(true).finish());
+    /// # drop(keys);
+    /// # }
+    /// ```
+    pub fn with_reissue_attributes(mut self, template: Cookie<'static>) -> CookieKeys {
+        self.reissue = Some(template);
+        self
+    }
+
+    /// Returns a `SignedJar`, which reads and adds cookies in `jar` whose values can be read by
+    /// the client but not changed.
+    pub fn signed<'a>(&'a self, jar: &'a mut CookieJar) -> SignedJar<'a> {
+        SignedJar { keys: self, jar }
+    }
+
+    /// Returns a `PrivateJar`, which reads and adds cookies in `jar` whose values can be neither
+    /// read nor changed by the client.
+    pub fn private<'a>(&'a self, jar: &'a mut CookieJar) -> PrivateJar<'a> {
+        PrivateJar { keys: self, jar }
+    }
+
+    fn current(&self) -> &Key {
+        &self.keys[0]
+    }
+
+    fn previous(&self) -> &[Key] {
+        &self.keys[1..]
+    }
+
+    // Copies the attributes of the reissue template onto a cookie read with a previous key.
+    fn reissue(&self, mut cookie: Cookie<'static>) -> Cookie<'static> {
+        if let Some(ref template) = self.reissue {
+            if let Some(path) = template.path() {
+                cookie.set_path(path.to_owned());
+            }
+            if let Some(domain) = template.domain() {
+                cookie.set_domain(domain.to_owned());
+            }
+            if let Some(same_site) = template.same_site() {
+                cookie.set_same_site(same_site);
+            }
+            if let Some(max_age) = template.max_age() {
+                cookie.set_max_age(max_age);
+            }
+            if let Some(expires) = template.expires() {
+                cookie.set_expires(expires);
+            }
+            cookie.set_secure(template.secure().unwrap_or(false));
+            cookie.set_http_only(template.http_only().unwrap_or(false));
+        }
+        cookie
+    }
+}
+
+impl StateData for CookieKeys {}
+
+/// A view of a `CookieJar` in which each cookie value is signed with HMAC-SHA256, so that the
+/// application can trust that it was not changed by the client.
+///
+/// Created by `CookieKeys::signed`.
+pub struct SignedJar<'a> {
+    keys: &'a CookieKeys,
+    jar: &'a mut CookieJar,
+}
+
+impl<'a> SignedJar<'a> {
+    /// Returns the cookie named `name` with the signature removed from its value, if it was sent
+    /// with the request and the signature is valid.
+    pub fn get(&mut self, name: &str) -> Option<Cookie<'static>> {
+        if let Some(cookie) = self.jar.signed(self.keys.current()).get(name) {
+            return Some(cookie);
+        }
+
+        for key in self.keys.previous() {
+            if let Some(cookie) = self.jar.signed(key).get(name) {
+                let reissued = self.keys.reissue(cookie.clone());
+                self.jar.signed(self.keys.current()).add(reissued);
+                return Some(cookie);
+            }
+        }
+
+        None
+    }
+
+    /// Signs the value of `cookie`, and adds it to the jar to be sent with the response.
+    pub fn add(&mut self, cookie: Cookie<'static>) {
+        self.jar.signed(self.keys.current()).add(cookie)
+    }
+
+    /// Removes `cookie` from the client.
+    pub fn remove(&mut self, cookie: Cookie<'static>) {
+        self.jar.remove(cookie)
+    }
+}
+
+/// A view of a `CookieJar` in which each cookie value is encrypted with AEAD_AES_256_GCM, so that
+/// the value can't be read or changed by the client.
+///
+/// Created by `CookieKeys::private`.
+pub struct PrivateJar<'a> {
+    keys: &'a CookieKeys,
+    jar: &'a mut CookieJar,
+}
+
+impl<'a> PrivateJar<'a> {
+    /// Returns the cookie named `name` with its value decrypted, if it was sent with the request
+    /// and the value can be decrypted.
+    pub fn get(&mut self, name: &str) -> Option<Cookie<'static>> {
+        if let Some(cookie) = self.jar.private(self.keys.current()).get(name) {
+            return Some(cookie);
+        }
+
+        for key in self.keys.previous() {
+            if let Some(cookie) = self.jar.private(key).get(name) {
+                let reissued = self.keys.reissue(cookie.clone());
+                self.jar.private(self.keys.current()).add(reissued);
+                return Some(cookie);
+            }
+        }
+
+        None
+    }
+
+    /// Encrypts the value of `cookie`, and adds it to the jar to be sent with the response.
+    pub fn add(&mut self, cookie: Cookie<'static>) {
+        self.jar.private(self.keys.current()).add(cookie)
+    }
+
+    /// Removes `cookie` from the client.
+    pub fn remove(&mut self, cookie: Cookie<'static>) {
+        self.jar.remove(cookie)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(master: u8) -> CookieKeys {
+        CookieKeys::from_master(&[master; 64])
+    }
+
+    // Builds a jar as it would be parsed from a request containing the cookies in `response`.
+    fn request_jar(response: &CookieJar) -> CookieJar {
+        let mut jar = CookieJar::new();
+        for cookie in response.delta() {
+            jar.add_original(cookie.clone());
+        }
+        jar
+    }
+
+    #[test]
+    fn signed_round_trip() {
+        let cookie_keys = keys(1);
+
+        let mut response = CookieJar::new();
+        cookie_keys
+            .signed(&mut response)
+            .add(Cookie::new("user", "42"));
+        assert!(response.get("user").unwrap().value().ends_with("42"));
+        assert_ne!(response.get("user").unwrap().value(), "42");
+
+        let mut request = request_jar(&response);
+        let cookie = cookie_keys.signed(&mut request).get("user").unwrap();
+        assert_eq!(cookie.value(), "42");
+        assert_eq!(request.delta().count(), 0);
+    }
+
+    #[test]
+    fn signed_rejects_tampering() {
+        let cookie_keys = keys(1);
+
+        let mut response = CookieJar::new();
+        cookie_keys
+            .signed(&mut response)
+            .add(Cookie::new("user", "42"));
+
+        let tampered = response.get("user").unwrap().value().replace("42", "43");
+        let mut request = CookieJar::new();
+        request.add_original(Cookie::new("user", tampered));
+
+        assert!(cookie_keys.signed(&mut request).get("user").is_none());
+        assert!(keys(2)
+            .signed(&mut request_jar(&response))
+            .get("user")
+            .is_none());
+    }
+
+    #[test]
+    fn private_round_trip() {
+        let cookie_keys = keys(1);
+
+        let mut response = CookieJar::new();
+        cookie_keys
+            .private(&mut response)
+            .add(Cookie::new("token", "secret"));
+        assert!(!response.get("token").unwrap().value().contains("secret"));
+
+        let mut request = request_jar(&response);
+        let cookie = cookie_keys.private(&mut request).get("token").unwrap();
+        assert_eq!(cookie.value(), "secret");
+
+        assert!(keys(2)
+            .private(&mut request_jar(&response))
+            .get("token")
+            .is_none());
+    }
+
+    #[test]
+    fn previous_keys_are_rotated() {
+        let mut response = CookieJar::new();
+        keys(1)
+            .private(&mut response)
+            .add(Cookie::new("token", "secret"));
+        keys(1).signed(&mut response).add(Cookie::new("user", "42"));
+
+        let rotated = keys(2).with_previous_key(Key::from_master(&[1; 64]));
+        let mut request = request_jar(&response);
+
+        assert_eq!(
+            rotated.private(&mut request).get("token").unwrap().value(),
+            "secret"
+        );
+        assert_eq!(
+            rotated.signed(&mut request).get("user").unwrap().value(),
+            "42"
+        );
+
+        // Both cookies are sent again, protected by the current key.
+        assert_eq!(request.delta().count(), 2);
+        let mut reissued = request_jar(&request);
+        assert_eq!(
+            keys(2).private(&mut reissued).get("token").unwrap().value(),
+            "secret"
+        );
+        assert_eq!(
+            keys(2).signed(&mut reissued).get("user").unwrap().value(),
+            "42"
+        );
+    }
+
+    #[test]
+    fn rotated_cookies_are_reissued_with_attributes() {
+        let mut response = CookieJar::new();
+        keys(1).signed(&mut response).add(Cookie::new("user", "42"));
+        keys(1)
+            .private(&mut response)
+            .add(Cookie::new("token", "secret"));
+
+        let rotated = keys(2)
+            .with_previous_key(Key::from_master(&[1; 64]))
+            .with_reissue_attributes(Cookie::build("", "").path("/app").http_only(true).finish());
+        let mut request = request_jar(&response);
+
+        assert_eq!(
+            rotated.signed(&mut request).get("user").unwrap().value(),
+            "42"
+        );
+        assert_eq!(
+            rotated.private(&mut request).get("token").unwrap().value(),
+            "secret"
+        );
+
+        assert_eq!(request.delta().count(), 2);
+        for cookie in request.delta() {
+            assert_eq!(cookie.path(), Some("/app"));
+            assert_eq!(cookie.http_only(), Some(true));
+        }
+    }
+}
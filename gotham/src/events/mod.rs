@@ -0,0 +1,338 @@
+//! Defines the events which are published as requests and connections are handled, so that
+//! components such as audit logs and anomaly detectors can observe every request without adding a
+//! middleware of their own.
+//!
+//! A `Subscriber` is given to `ServerBuilder::with_event_subscriber`, and receives each `Event` in
+//! the order it happens:
+//!
+//! 1. `RequestStarted`, once a request has been received, before it is dispatched to the handler;
+//! 2. `Routed`, once the `Router` has matched the request to a route;
+//! 3. `HandlerCompleted`, once the middleware and handler of the route have produced a response,
+//!    or failed;
+//! 4. `ResponseWritten`, once the final response has been handed to the connection to be written;
+//!    and
+//! 5. `ConnectionClosed`, once the connection which the requests were made over has closed.
+//!
+//! A request which matches no route, or is rejected before it reaches the `Router`, is published
+//! without `Routed` and `HandlerCompleted` events.
+//!
+//! Subscribers are called on the thread handling the request, so work which may be slow, such as
+//! writing to a remote audit log, should be handed off to another thread or task. A panic in a
+//! subscriber is caught and logged, and does not affect the request.
+//!
+//! # Examples
+//!
+//! ```rust
+//! # extern crate gotham;
+//! # extern crate hyper;
+//! #
+//! # use hyper::{Body, Response};
+//! # use gotham::events::Event;
+//! # use gotham::state::State;
+//! # use gotham::test::TestServer;
+//! # use gotham::ServerBuilder;
+//! #
+//! fn audit(event: &Event) {
+//!     if let Event::ResponseWritten(request, status) = *event {
+//!         println!(
+//!             "{} {} {} responded with {} after {:?}",
+//!             request.id(),
+//!             request.method(),
+//!             request.uri(),
+//!             status,
+//!             request.elapsed()
+//!         );
+//!     }
+//! }
+//! #
+//! # fn handler(state: State) -> (State, Response<Body>) {
+//! #   (state, Response::new(Body::empty()))
+//! # }
+//!
+//! # fn main() {
+//! let builder = ServerBuilder::new().with_event_subscriber(audit);
+//! #
+//! #   let test_server = TestServer::with_server_builder(builder, || Ok(handler)).unwrap();
+//! #   test_server.client().get("http://example.com/").perform().unwrap();
+//! # }
+//! ```
+
+use hyper::{Method, StatusCode, Uri};
+use std::net::SocketAddr;
+use std::panic::{catch_unwind, AssertUnwindSafe, RefUnwindSafe};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use state::{client_addr, request_id, FromState, State, StateData};
+
+/// Receives each `Event` published while requests and connections are handled.
+///
+/// `Subscriber` is implemented for closures, so that a subscriber can be written as a function.
+pub trait Subscriber: Send + Sync + RefUnwindSafe {
+    /// Handles an event.
+    fn notify(&self, event: &Event);
+}
+
+impl<F> Subscriber for F
+where
+    F: Fn(&Event) + Send + Sync + RefUnwindSafe,
+{
+    fn notify(&self, event: &Event) {
+        self(event)
+    }
+}
+
+/// An event in the handling of a request or connection, as received by a `Subscriber`.
+#[derive(Debug)]
+pub enum Event<'a> {
+    /// A request has been received, and is about to be dispatched to the handler.
+    RequestStarted(&'a RequestInfo),
+
+    /// The request has been matched to a route, whose template is now known.
+    Routed(&'a RequestInfo),
+
+    /// The middleware and handler of the route have completed, with the status of the response
+    /// they produced, or of the response which will be generated for their error.
+    HandlerCompleted(&'a RequestInfo, StatusCode),
+
+    /// The final response has been handed to the connection to be written, with its status. A
+    /// streamed response body may still be being written.
+    ResponseWritten(&'a RequestInfo, StatusCode),
+
+    /// A connection has closed.
+    ConnectionClosed(&'a ConnectionInfo),
+}
+
+/// Describes the request which an `Event` is about.
+#[derive(Clone, Debug)]
+pub struct RequestInfo {
+    id: String,
+    method: Method,
+    uri: Uri,
+    client_addr: Option<SocketAddr>,
+    route: Option<String>,
+    started: Instant,
+}
+
+impl RequestInfo {
+    /// The ID of the request, as returned by `state::request_id`.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// The method of the request.
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
+    /// The URI of the request.
+    pub fn uri(&self) -> &Uri {
+        &self.uri
+    }
+
+    /// The address of the client, when the request was made over a transport with addresses.
+    pub fn client_addr(&self) -> Option<SocketAddr> {
+        self.client_addr
+    }
+
+    /// The template of the route which the request matched, such as `/users/:id`, once it has
+    /// been routed.
+    pub fn route(&self) -> Option<&str> {
+        self.route.as_ref().map(String::as_str)
+    }
+
+    /// The time since the request was received.
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+}
+
+/// Describes the connection which an `Event` is about.
+#[derive(Clone, Debug)]
+pub struct ConnectionInfo {
+    client_addr: Option<SocketAddr>,
+    opened: Instant,
+}
+
+impl ConnectionInfo {
+    pub(crate) fn new(client_addr: Option<SocketAddr>) -> ConnectionInfo {
+        ConnectionInfo {
+            client_addr,
+            opened: Instant::now(),
+        }
+    }
+
+    /// The address of the client, when the connection is over a transport with addresses.
+    pub fn client_addr(&self) -> Option<SocketAddr> {
+        self.client_addr
+    }
+
+    /// The time since the connection was accepted.
+    pub fn elapsed(&self) -> Duration {
+        self.opened.elapsed()
+    }
+}
+
+/// The subscribers which events are published to.
+#[derive(Clone)]
+pub(crate) struct EventBus {
+    subscribers: Arc<Vec<Arc<Subscriber>>>,
+}
+
+impl EventBus {
+    /// Creates an `EventBus` which publishes to `subscribers`, or `None` when there are none.
+    pub(crate) fn new(subscribers: &[Arc<Subscriber>]) -> Option<EventBus> {
+        if subscribers.is_empty() {
+            return None;
+        }
+
+        Some(EventBus {
+            subscribers: Arc::new(subscribers.to_vec()),
+        })
+    }
+
+    /// Passes `event` to each subscriber, logging any panic rather than allowing it to escape.
+    pub(crate) fn publish(&self, event: &Event) {
+        for subscriber in self.subscribers.iter() {
+            if catch_unwind(AssertUnwindSafe(|| subscriber.notify(event))).is_err() {
+                error!(" an event subscriber panicked while handling {:?}", event);
+            }
+        }
+    }
+}
+
+/// The events of the request in `State`, which are published as it is handled.
+#[derive(Clone)]
+pub(crate) struct RequestEvents {
+    bus: EventBus,
+    info: Arc<Mutex<RequestInfo>>,
+}
+
+impl StateData for RequestEvents {}
+
+impl RequestEvents {
+    /// Publishes that the final response has been produced with `status`.
+    pub(crate) fn response_written(&self, status: StatusCode) {
+        self.bus
+            .publish(&Event::ResponseWritten(&self.info(), status));
+    }
+
+    fn info(&self) -> MutexGuard<RequestInfo> {
+        match self.info.lock() {
+            Ok(info) => info,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
+
+/// Publishes that the request in `state` has started, and puts its `RequestEvents` into `state`
+/// so that the rest of its events are published to `bus`.
+pub(crate) fn request_started(state: &mut State, bus: &EventBus) {
+    let info = RequestInfo {
+        id: request_id(state).to_owned(),
+        method: Method::borrow_from(state).clone(),
+        uri: Uri::borrow_from(state).clone(),
+        client_addr: client_addr(state),
+        route: None,
+        started: Instant::now(),
+    };
+
+    bus.publish(&Event::RequestStarted(&info));
+
+    state.put(RequestEvents {
+        bus: bus.clone(),
+        info: Arc::new(Mutex::new(info)),
+    });
+}
+
+/// Publishes that the request in `state` has matched the route with the template `route`,
+/// returning whether the events of the request are being published.
+pub(crate) fn routed(state: &State, route: &str) -> bool {
+    match RequestEvents::try_borrow_from(state) {
+        Some(events) => {
+            let mut info = events.info();
+            info.route = Some(route.to_owned());
+            events.bus.publish(&Event::Routed(&info));
+            true
+        }
+        None => false,
+    }
+}
+
+/// Publishes that the middleware and handler of the request in `state` have completed.
+pub(crate) fn handler_completed(state: &State, status: StatusCode) {
+    if let Some(events) = RequestEvents::try_borrow_from(state) {
+        events
+            .bus
+            .publish(&Event::HandlerCompleted(&events.info(), status));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use hyper::{Body, Response};
+
+    use router::builder::*;
+    use router::Router;
+    use test::TestServer;
+    use ServerBuilder;
+
+    fn handler(state: State) -> (State, Response<Body>) {
+        (state, Response::new(Body::empty()))
+    }
+
+    fn describe(event: &Event) -> String {
+        match *event {
+            Event::RequestStarted(request) => format!("started {}", request.uri().path()),
+            Event::Routed(request) => format!("routed {}", request.route().unwrap()),
+            Event::HandlerCompleted(request, status) => {
+                format!("completed {} {}", request.route().unwrap(), status.as_u16())
+            }
+            Event::ResponseWritten(request, status) => {
+                format!("written {:?} {}", request.route(), status.as_u16())
+            }
+            Event::ConnectionClosed(_) => "closed".to_owned(),
+        }
+    }
+
+    fn router() -> Router {
+        build_simple_router(|route| {
+            route.get("/users/:id").to(handler);
+        })
+    }
+
+    #[test]
+    fn publishes_request_events() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let builder = {
+            let events = events.clone();
+            ServerBuilder::new()
+                .with_event_subscriber(move |event: &Event| {
+                    if let Event::ConnectionClosed(_) = *event {
+                        return;
+                    }
+                    events.lock().unwrap().push(describe(event));
+                })
+                .with_event_subscriber(|_: &Event| panic!("subscriber failed"))
+        };
+
+        let test_server = TestServer::with_server_builder(builder, || Ok(router())).unwrap();
+        let client = test_server.client();
+        client.get("http://localhost/users/1").perform().unwrap();
+        client.get("http://localhost/missing").perform().unwrap();
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                "started /users/1",
+                "routed /users/:id",
+                "completed /users/:id 200",
+                "written Some(\"/users/:id\") 200",
+                "started /missing",
+                "written None 404",
+            ]
+        );
+    }
+}
@@ -417,7 +417,8 @@ where
     }
 }
 
-/// Deserializes an identifier string into an identifier. Just serde boilerplate.
+/// Deserializes an identifier string into an identifier, or a string when the extractor is a map
+/// such as `HashMap<String, String>`. Just serde boilerplate.
 struct DeserializeKey<'de> {
     key: &'de str,
 }
@@ -432,6 +433,20 @@ impl<'de> Deserializer<'de> for DeserializeKey<'de> {
         visitor.visit_str(self.key)
     }
 
+    fn deserialize_str<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_borrowed_str(self.key)
+    }
+
+    fn deserialize_string<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_str(self.key)
+    }
+
     fn deserialize_any<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
@@ -441,7 +456,7 @@ impl<'de> Deserializer<'de> for DeserializeKey<'de> {
     }
 
     forward_to_deserialize_any! {
-        bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string bytes
+        bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char bytes
         byte_buf option unit unit_struct newtype_struct seq tuple
         tuple_struct map struct enum ignored_any
     }
@@ -1151,4 +1166,20 @@ mod tests {
 
         assert_eq!(p.wrapped_int_val, IntWrapper(100));
     }
+
+    #[test]
+    fn map_path_tests() {
+        let id = PercentDecoded::new("42").unwrap();
+        let rest_1 = PercentDecoded::new("a").unwrap();
+        let rest_2 = PercentDecoded::new("b").unwrap();
+
+        let mut sm = SegmentMapping::new();
+        sm.insert("id", vec![&id]);
+        sm.insert("rest", vec![&rest_1, &rest_2]);
+
+        let p = from_segment_mapping::<std::collections::HashMap<String, Vec<String>>>(sm).unwrap();
+
+        assert_eq!(p["id"], vec!["42".to_owned()]);
+        assert_eq!(p["rest"], vec!["a".to_owned(), "b".to_owned()]);
+    }
 }
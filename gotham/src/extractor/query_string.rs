@@ -14,7 +14,9 @@ use state::{State, StateData};
 ///
 /// The default behaviour given by deriving all three traits will use the automatically derived
 /// behaviour from Serde, and result in a `400 Bad Request` HTTP response if the query string is
-/// not able to be deserialized.
+/// not able to be deserialized. The `Response` passed to `StaticResponseExtender::extend` already
+/// carries a `400 Bad Request` status, so a manual implementation only needs to change it when a
+/// different status is required.
 ///
 /// # Examples
 ///
@@ -0,0 +1,319 @@
+//! Defines `AssetManifest`, which serves the static assets under a directory at URLs containing a
+//! hash of their content, so that browsers can cache them indefinitely.
+
+use futures::future;
+use hyper::StatusCode;
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fs;
+use std::hash::Hasher;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use super::{create_file_response, FileOptions, FilePathExtractor};
+use error::Result;
+use handler::{Handler, HandlerFuture, IntoHandlerError, NewHandler};
+use state::{FromState, State, StateData};
+
+// The `Cache-Control` header of assets requested by their fingerprinted path, which can't change.
+const IMMUTABLE: &str = "public, max-age=31536000, immutable";
+
+/// The fingerprinted paths of the static assets under a directory, computed from their content
+/// when the manifest is created.
+///
+/// An asset such as `styles/app.css` is given a fingerprinted path such as
+/// `styles/app.5f3e9a0c2b1d4e68.css`, which changes whenever the content of the file changes.
+/// Routes added with `DefineSingleRoute::to_assets` serve each asset at its fingerprinted path
+/// with a far-future, immutable `Cache-Control` header, and at its original path with the
+/// `Cache-Control` header of the `FileOptions` the manifest was created with. Compressed files
+/// alongside the assets are served according to the `FileOptions`, as with `to_dir`.
+///
+/// Handlers and templates find the URL of an asset with `AssetManifest::url`, after the manifest
+/// has been placed into `State` by a `StateMiddleware`. Files added to the directory after the
+/// manifest was created are not served.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// # extern crate mime;
+/// #
+/// # use hyper::{Body, Response, StatusCode};
+/// # use hyper::header::CACHE_CONTROL;
+/// # use gotham::handler::assets::AssetManifest;
+/// # use gotham::helpers::http::response::create_response;
+/// # use gotham::middleware::state::StateMiddleware;
+/// # use gotham::pipeline::single_middleware;
+/// # use gotham::pipeline::single::single_pipeline;
+/// # use gotham::router::Router;
+/// # use gotham::router::builder::*;
+/// # use gotham::state::{FromState, State};
+/// # use gotham::test::TestServer;
+/// #
+/// fn index(state: State) -> (State, Response<Body>) {
+///     let html = {
+///         let assets = AssetManifest::borrow_from(&state);
+///         format!(
+///             r#"<link rel="stylesheet" href="{}">"#,
+///             assets.url("styles/style.css").unwrap()
+///         )
+///     };
+///
+///     let res = create_response(&state, StatusCode::OK, mime::TEXT_HTML, html);
+///     (state, res)
+/// }
+///
+/// fn router() -> Router {
+///     let assets = AssetManifest::new("/assets", "resources/test/assets").unwrap();
+///
+///     let (chain, pipelines) =
+///         single_pipeline(single_middleware(StateMiddleware::new(assets.clone())));
+///
+///     build_router(chain, pipelines, |route| {
+///         route.get("/").to(index);
+///         route.get("/assets/*").to_assets(assets);
+///     })
+/// }
+/// #
+/// # fn main() {
+/// #   let test_server = TestServer::new(router()).unwrap();
+/// #   let client = test_server.client();
+/// #
+/// #   let body = client.get("http://example.com/").perform().unwrap().read_utf8_body().unwrap();
+/// #   let href = body.split('"').nth(3).unwrap();
+/// #   assert!(href.starts_with("/assets/styles/style."));
+/// #
+/// #   let response = client.get(&format!("http://example.com{}", href)).perform().unwrap();
+/// #   assert_eq!(response.status(), StatusCode::OK);
+/// #   assert_eq!(
+/// #       response.headers()[CACHE_CONTROL],
+/// #       "public, max-age=31536000, immutable"
+/// #   );
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct AssetManifest {
+    inner: Arc<Manifest>,
+}
+
+struct Manifest {
+    mount: String,
+    options: FileOptions,
+    // the fingerprinted path of each asset, by its path relative to the directory
+    fingerprinted: HashMap<String, String>,
+    // the path of each asset relative to the directory, by its fingerprinted path
+    assets: HashMap<String, String>,
+}
+
+impl StateData for AssetManifest {}
+
+impl AssetManifest {
+    /// Reads every file under the directory of `options` to compute its fingerprinted path, for
+    /// assets served by a route whose path is `mount` followed by a glob, such as
+    /// `/assets/*` for the mount `/assets`.
+    pub fn new<P>(mount: &str, options: P) -> io::Result<AssetManifest>
+    where
+        FileOptions: From<P>,
+    {
+        let options = FileOptions::from(options);
+
+        let mut files = Vec::new();
+        collect_files(&options.path, "", &mut files)?;
+
+        let mut fingerprinted = HashMap::new();
+        let mut assets = HashMap::new();
+        for (path, name) in files {
+            let fingerprint = fingerprint(&name, &fs::read(&path)?);
+            assets.insert(fingerprint.clone(), name.clone());
+            fingerprinted.insert(name, fingerprint);
+        }
+
+        Ok(AssetManifest {
+            inner: Arc::new(Manifest {
+                mount: mount.trim_right_matches('/').to_owned(),
+                options,
+                fingerprinted,
+                assets,
+            }),
+        })
+    }
+
+    /// The URL of the fingerprinted path of an asset, given its path relative to the directory,
+    /// or `None` if there is no such asset.
+    pub fn url(&self, asset: &str) -> Option<String> {
+        self.inner
+            .fingerprinted
+            .get(asset.trim_left_matches('/'))
+            .map(|fingerprinted| format!("{}/{}", self.inner.mount, fingerprinted))
+    }
+}
+
+// Collects every file beneath `dir`, along with its path relative to the directory the collection
+// started from, using `/` as the separator.
+fn collect_files(dir: &Path, prefix: &str, files: &mut Vec<(PathBuf, String)>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+
+        if entry.file_type()?.is_dir() {
+            collect_files(&entry.path(), &format!("{}/", name), files)?;
+        } else {
+            files.push((entry.path(), name));
+        }
+    }
+
+    Ok(())
+}
+
+// Inserts a hash of `content` before the extension of the file name, or after it when there is no
+// extension.
+fn fingerprint(name: &str, content: &[u8]) -> String {
+    let mut hasher = DefaultHasher::new();
+    hasher.write(content);
+    let hash = format!("{:016x}", hasher.finish());
+
+    let file_name = name.rfind('/').map(|i| i + 1).unwrap_or(0);
+    match name[file_name..].rfind('.') {
+        Some(dot) if dot > 0 => {
+            let (stem, extension) = name.split_at(file_name + dot);
+            format!("{}.{}{}", stem, hash, extension)
+        }
+        _ => format!("{}.{}", name, hash),
+    }
+}
+
+/// Serves the assets of an `AssetManifest`, as added by `DefineSingleRoute::to_assets`.
+#[derive(Clone)]
+pub struct AssetHandler {
+    manifest: AssetManifest,
+}
+
+impl AssetHandler {
+    /// Create a new `AssetHandler` serving the assets of the given manifest.
+    pub fn new(manifest: AssetManifest) -> AssetHandler {
+        AssetHandler { manifest }
+    }
+}
+
+impl NewHandler for AssetHandler {
+    type Instance = Self;
+
+    fn new_handler(&self) -> Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+impl Handler for AssetHandler {
+    fn handle(self, state: State) -> Box<HandlerFuture> {
+        let manifest = &self.manifest.inner;
+        let requested = FilePathExtractor::borrow_from(&state).parts.join("/");
+
+        let (name, cache_control) = match manifest.assets.get(&requested) {
+            Some(name) => (name.clone(), IMMUTABLE.to_owned()),
+            None if manifest.fingerprinted.contains_key(&requested) => {
+                (requested, manifest.options.cache_control.clone())
+            }
+            None => {
+                let err = io::Error::new(io::ErrorKind::NotFound, "no such asset")
+                    .into_handler_error()
+                    .with_status(StatusCode::NOT_FOUND);
+                return Box::new(future::err((state, err)));
+            }
+        };
+
+        let mut path = manifest.options.path.clone();
+        path.extend(name.split('/'));
+
+        create_file_response(
+            FileOptions {
+                path,
+                cache_control,
+                ..manifest.options.clone()
+            },
+            state,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use hyper::header::{HeaderValue, ACCEPT_ENCODING, CACHE_CONTROL, CONTENT_ENCODING};
+    use router::builder::{build_simple_router, DefineSingleRoute, DrawRoutes};
+    use test::TestServer;
+
+    #[test]
+    fn fingerprints_file_names() {
+        let hash = fingerprint("a", b"content");
+        let hash = &hash[2..];
+        assert_eq!(hash.len(), 16);
+
+        assert_eq!(
+            fingerprint("styles/app.css", b"content"),
+            format!("styles/app.{}.css", hash)
+        );
+        assert_eq!(
+            fingerprint("app.min.js", b"content"),
+            format!("app.min.{}.js", hash)
+        );
+        assert_eq!(
+            fingerprint("dir.d/LICENSE", b"content"),
+            format!("dir.d/LICENSE.{}", hash)
+        );
+        assert_eq!(
+            fingerprint(".htaccess", b"content"),
+            format!(".htaccess.{}", hash)
+        );
+        assert_ne!(fingerprint("a", b"other"), fingerprint("a", b"content"));
+    }
+
+    #[test]
+    fn serves_fingerprinted_assets() {
+        let manifest = AssetManifest::new(
+            "/assets/",
+            FileOptions::new("resources/test/assets")
+                .with_gzip(true)
+                .build(),
+        )
+        .unwrap();
+
+        let url = manifest.url("/doc.html").unwrap();
+        assert!(url.starts_with("/assets/doc."));
+        assert!(url.ends_with(".html"));
+        assert_eq!(manifest.url("missing.html"), None);
+
+        let router = build_simple_router(|route| route.get("/assets/*").to_assets(manifest));
+        let server = TestServer::new(router).unwrap();
+        let client = server.client();
+
+        let response = client
+            .get(&format!("http://localhost{}", url))
+            .with_header(ACCEPT_ENCODING, HeaderValue::from_static("gzip"))
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[CACHE_CONTROL], IMMUTABLE);
+        assert_eq!(response.headers()[CONTENT_ENCODING], "gzip");
+        assert_eq!(
+            response.read_body().unwrap(),
+            fs::read("resources/test/assets/doc.html.gz").unwrap()
+        );
+
+        let response = client
+            .get("http://localhost/assets/styles/style.css")
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[CACHE_CONTROL], "public");
+
+        let response = client
+            .get("http://localhost/assets/doc.0000000000000000.html")
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
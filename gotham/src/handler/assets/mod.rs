@@ -3,8 +3,11 @@
 //! file modification.
 //! Side-by-side compressed files for gzip and brotli are supported if enabled
 //! See 'FileOptions' for more details.
+//! Assets can also be served at fingerprinted URLs, which are cached indefinitely, by
+//! `to_assets` routes. See 'AssetManifest' for more details.
 
 mod accepted_encoding;
+mod fingerprint;
 
 use bytes::{BufMut, BytesMut};
 use error::Result;
@@ -23,6 +26,8 @@ use handler::{Handler, HandlerFuture, IntoHandlerError, NewHandler};
 use router::response::extender::StaticResponseExtender;
 use state::{FromState, State, StateData};
 
+pub use self::fingerprint::{AssetHandler, AssetManifest};
+
 use std::cmp;
 use std::convert::From;
 use std::fs::Metadata;
@@ -5,7 +5,7 @@ use hyper::{Body, Response, StatusCode};
 
 use handler::IntoResponse;
 use helpers::http::response::create_empty_response;
-use state::{request_id, State};
+use state::State;
 
 /// Describes an error which occurred during handler execution, and allows the creation of a HTTP
 /// `Response`.
@@ -86,6 +86,35 @@ impl Error for HandlerError {
 }
 
 impl HandlerError {
+    /// Returns the HTTP status code of the response which is generated by the `IntoResponse`
+    /// implementation.
+    pub fn status(&self) -> StatusCode {
+        self.status_code
+    }
+
+    /// Returns a reference to the error which caused this `HandlerError`, if it is of type `E`.
+    ///
+    /// ```rust
+    /// # extern crate gotham;
+    /// #
+    /// # use std::io;
+    /// # use gotham::handler::IntoHandlerError;
+    /// #
+    /// # fn main() {
+    /// let handler_error = io::Error::new(io::ErrorKind::NotFound, "missing").into_handler_error();
+    ///
+    /// let cause = handler_error.downcast_ref::<io::Error>().unwrap();
+    /// assert_eq!(cause.kind(), io::ErrorKind::NotFound);
+    /// assert!(handler_error.downcast_ref::<std::fmt::Error>().is_none());
+    /// # }
+    /// ```
+    pub fn downcast_ref<E>(&self) -> Option<&E>
+    where
+        E: Error + 'static,
+    {
+        self.cause.downcast_ref::<E>()
+    }
+
     /// Sets the HTTP status code of the response which is generated by the `IntoResponse`
     /// implementation.
     ///
@@ -129,14 +158,14 @@ impl HandlerError {
 
 impl IntoResponse for HandlerError {
     fn into_response(self, state: &State) -> Response<Body> {
-        debug!(
-            "[{}] HandlerError generating {} {} response: {}",
-            request_id(state),
+        request_debug!(
+            state,
+            "HandlerError generating {} {} response: {}",
             self.status_code.as_u16(),
             self.status_code
                 .canonical_reason()
                 .unwrap_or("(unregistered)",),
-            self.cause().map(|e| e.description()).unwrap_or("(none)"),
+            self.cause().map(|e| e.description()).unwrap_or("(none)")
         );
 
         create_empty_response(state, self.status_code)
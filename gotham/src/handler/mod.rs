@@ -19,6 +19,8 @@ use error::*;
 
 /// Defines handlers for serving static assets.
 pub mod assets;
+pub mod proxy;
+pub mod service;
 
 pub use self::error::{HandlerError, IntoHandlerError};
 
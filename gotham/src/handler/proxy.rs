@@ -0,0 +1,401 @@
+//! Defines `ProxyHandler`, which forwards requests to an upstream HTTP server.
+
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::panic::AssertUnwindSafe;
+use std::time::Duration;
+
+use futures::{future, Future};
+use hyper::client::HttpConnector;
+use hyper::header::{
+    HeaderMap, HeaderName, HeaderValue, CONNECTION, HOST, PROXY_AUTHENTICATE, PROXY_AUTHORIZATION,
+    TE, TRAILER, TRANSFER_ENCODING, UPGRADE,
+};
+use hyper::http::uri::{Authority, Scheme};
+use hyper::{Body, Client, Request, StatusCode, Uri};
+use tokio::timer::Timeout;
+
+use error::Result;
+use handler::service::forwarded_request;
+use handler::{Handler, HandlerFuture, IntoHandlerError, NewHandler};
+use middleware::timeout::Deadline;
+use middleware::tracing::Span;
+use state::{client_addr, scheme, FromState, State};
+
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+const X_FORWARDED_HOST: &str = "x-forwarded-host";
+const X_FORWARDED_PROTO: &str = "x-forwarded-proto";
+const TRACEPARENT: &str = "traceparent";
+
+/// A `Handler` which forwards requests to an upstream HTTP server, and streams the upstream
+/// response back to the client.
+///
+/// The upstream request has the same method, headers and body as the original request. The path
+/// and query are appended to the path of the upstream URI, after removing the delegated prefix
+/// when the `ProxyHandler` is reached via `DelegateRouteBuilder::to_proxy`. The `Host` header is
+/// set to the upstream authority, the original host, client address and scheme are reported in
+/// the `X-Forwarded-Host`, `X-Forwarded-For` and `X-Forwarded-Proto` headers, and hop-by-hop
+/// headers are removed in both directions. When the request is traced by `TracingMiddleware`, the
+/// `traceparent` header is set so that the upstream server continues the trace.
+///
+/// Requests are made with a pooled hyper `Client` which is shared by clones of the
+/// `ProxyHandler`. If the upstream server fails, a `502 Bad Gateway` response is sent, or `504
+/// Gateway Timeout` if it does not respond before the timeout.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate gotham;
+/// #
+/// # use std::time::Duration;
+/// # use gotham::handler::proxy::ProxyHandler;
+/// # use gotham::router::Router;
+/// # use gotham::router::builder::*;
+/// #
+/// fn router() -> Router {
+///     let api = ProxyHandler::new("http://127.0.0.1:9000/v1".parse().unwrap())
+///         .unwrap()
+///         .with_timeout(Duration::from_secs(10));
+///
+///     build_simple_router(|route| {
+///         // GET /api/users/1 is forwarded to http://127.0.0.1:9000/v1/users/1
+///         route.delegate("/api").to_proxy(api);
+///     })
+/// }
+/// #
+/// # fn main() {
+/// #   router();
+/// # }
+/// ```
+pub struct ProxyHandler {
+    scheme: Scheme,
+    authority: Authority,
+    path: String,
+    client: AssertUnwindSafe<Client<HttpConnector, Body>>,
+    timeout: Duration,
+}
+
+impl ProxyHandler {
+    /// Creates a `ProxyHandler` which forwards requests to `upstream`, with a timeout of 30
+    /// seconds.
+    ///
+    /// Returns an `InvalidUpstream` error if `upstream` is not an absolute `http` URI. `https`
+    /// upstream servers are not supported, as the requests are made without TLS.
+    pub fn new(upstream: Uri) -> Result<ProxyHandler> {
+        ProxyHandler::with_client(upstream, Client::new())
+    }
+
+    /// Creates a `ProxyHandler` which forwards requests to `upstream` using `client`, which allows
+    /// the connection pool to be configured.
+    ///
+    /// Returns an `InvalidUpstream` error if `upstream` is not an absolute `http` URI.
+    pub fn with_client(upstream: Uri, client: Client<HttpConnector, Body>) -> Result<ProxyHandler> {
+        let (scheme, authority) = match (upstream.scheme_part(), upstream.authority_part()) {
+            (Some(scheme), Some(authority)) if scheme == &Scheme::HTTP => {
+                (scheme.clone(), authority.clone())
+            }
+            _ => return Err(InvalidUpstream { upstream }.into()),
+        };
+
+        Ok(ProxyHandler {
+            scheme,
+            authority,
+            path: upstream.path().trim_end_matches('/').to_owned(),
+            client: AssertUnwindSafe(client),
+            timeout: Duration::from_secs(30),
+        })
+    }
+
+    /// Sets the time allowed for the upstream server to respond, including connecting to it. When
+    /// a `Deadline` for the request is in `State`, the upstream server is only allowed the time
+    /// which remains, if that is shorter.
+    pub fn with_timeout(self, timeout: Duration) -> ProxyHandler {
+        ProxyHandler { timeout, ..self }
+    }
+
+    // Builds the upstream URI from the path and query being forwarded.
+    fn upstream_uri(&self, forwarded: &Uri) -> Result<Uri> {
+        let path_and_query = forwarded
+            .path_and_query()
+            .map(|pq| pq.as_str())
+            .unwrap_or("/");
+
+        let uri = Uri::builder()
+            .scheme(self.scheme.clone())
+            .authority(self.authority.clone())
+            .path_and_query(format!("{}{}", self.path, path_and_query).as_str())
+            .build()?;
+
+        Ok(uri)
+    }
+
+    fn upstream_request(&self, state: &mut State) -> Result<Request<Body>> {
+        let mut req = forwarded_request(state)?;
+        *req.uri_mut() = self.upstream_uri(req.uri())?;
+
+        let headers = req.headers_mut();
+        remove_hop_by_hop_headers(headers);
+
+        if let Some(host) = headers.remove(HOST) {
+            headers.insert(X_FORWARDED_HOST, host);
+        }
+        headers.insert(HOST, HeaderValue::from_str(self.authority.as_str())?);
+
+        if let Some(addr) = client_addr(state) {
+            let forwarded_for = match headers.get(X_FORWARDED_FOR).and_then(|v| v.to_str().ok()) {
+                Some(existing) => format!("{}, {}", existing, addr.ip()),
+                None => addr.ip().to_string(),
+            };
+            headers.insert(X_FORWARDED_FOR, HeaderValue::from_str(&forwarded_for)?);
+        }
+        headers.insert(
+            X_FORWARDED_PROTO,
+            HeaderValue::from_static(scheme(state).as_str()),
+        );
+
+        if let Some(span) = Span::try_borrow_from(state) {
+            let traceparent = span.context().to_traceparent();
+            headers.insert(TRACEPARENT, HeaderValue::from_str(&traceparent)?);
+        }
+
+        Ok(req)
+    }
+}
+
+impl Clone for ProxyHandler {
+    fn clone(&self) -> ProxyHandler {
+        ProxyHandler {
+            scheme: self.scheme.clone(),
+            authority: self.authority.clone(),
+            path: self.path.clone(),
+            client: AssertUnwindSafe(self.client.clone()),
+            timeout: self.timeout,
+        }
+    }
+}
+
+impl NewHandler for ProxyHandler {
+    type Instance = Self;
+
+    fn new_handler(&self) -> Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+impl Handler for ProxyHandler {
+    fn handle(self, mut state: State) -> Box<HandlerFuture> {
+        let req = match self.upstream_request(&mut state) {
+            Ok(req) => req,
+            Err(e) => {
+                let err = e.compat().into_handler_error();
+                return Box::new(future::err((
+                    state,
+                    err.with_status(StatusCode::BAD_GATEWAY),
+                )));
+            }
+        };
+
+        request_trace!(
+            &state,
+            "proxying request to upstream: {} {}",
+            req.method(),
+            req.uri()
+        );
+
+        let timeout = match Deadline::try_borrow_from(&state) {
+            Some(deadline) => deadline.min(self.timeout),
+            None => self.timeout,
+        };
+
+        let response = self.client.request(req);
+        let f = Timeout::new(response, timeout).then(move |result| match result {
+            Ok(mut res) => {
+                remove_hop_by_hop_headers(res.headers_mut());
+                Ok((state, res))
+            }
+            Err(e) => {
+                let status = if e.is_elapsed() {
+                    StatusCode::GATEWAY_TIMEOUT
+                } else {
+                    StatusCode::BAD_GATEWAY
+                };
+
+                request_debug!(&state, "upstream request failed: {}", e);
+
+                Err((state, e.into_handler_error().with_status(status)))
+            }
+        });
+
+        Box::new(f)
+    }
+}
+
+/// The error returned when creating a `ProxyHandler` with an upstream URI which is not an
+/// absolute `http` URI.
+#[derive(Debug)]
+pub struct InvalidUpstream {
+    upstream: Uri,
+}
+
+impl InvalidUpstream {
+    /// The upstream URI which was rejected.
+    pub fn upstream(&self) -> &Uri {
+        &self.upstream
+    }
+}
+
+impl Display for InvalidUpstream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "upstream must be an absolute http URI: {}",
+            self.upstream
+        )
+    }
+}
+
+impl Error for InvalidUpstream {
+    fn description(&self) -> &str {
+        "upstream must be an absolute http URI"
+    }
+}
+
+// Removes the headers which apply only to a single connection, including any listed in the
+// `Connection` header.
+fn remove_hop_by_hop_headers(headers: &mut HeaderMap) {
+    let listed: Vec<HeaderName> = headers
+        .get_all(CONNECTION)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|name| HeaderName::from_bytes(name.trim().as_bytes()).ok())
+        .collect();
+
+    for name in listed {
+        headers.remove(name);
+    }
+
+    for name in &[
+        CONNECTION,
+        PROXY_AUTHENTICATE,
+        PROXY_AUTHORIZATION,
+        TE,
+        TRAILER,
+        TRANSFER_ENCODING,
+        UPGRADE,
+    ] {
+        headers.remove(name);
+    }
+
+    headers.remove("keep-alive");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use hyper::service::service_fn_ok;
+    use hyper::{Response, Server};
+    use std::net::SocketAddr;
+
+    use router::builder::*;
+    use test::TestServer;
+
+    fn proxy(path: &str, upstream: &str) -> Uri {
+        let handler = ProxyHandler::new(upstream.parse().unwrap()).unwrap();
+        handler.upstream_uri(&path.parse().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn joins_upstream_path() {
+        assert_eq!(
+            proxy("/users/1?q=1", "http://backend:9000/v1/"),
+            "http://backend:9000/v1/users/1?q=1"
+        );
+        assert_eq!(proxy("/", "http://backend:9000"), "http://backend:9000/");
+    }
+
+    #[test]
+    fn rejects_invalid_upstreams() {
+        for upstream in &["https://backend:9000/v1", "/v1", "backend:9000"] {
+            let err = ProxyHandler::new(upstream.parse().unwrap()).err().unwrap();
+            let err = err.downcast_ref::<InvalidUpstream>().unwrap();
+            assert_eq!(err.upstream(), upstream);
+        }
+    }
+
+    #[test]
+    fn removes_hop_by_hop_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONNECTION, "close, x-session".parse().unwrap());
+        headers.insert("x-session", "abc".parse().unwrap());
+        headers.insert("keep-alive", "timeout=5".parse().unwrap());
+        headers.insert("x-request", "1".parse().unwrap());
+
+        remove_hop_by_hop_headers(&mut headers);
+
+        assert_eq!(headers.len(), 1);
+        assert!(headers.contains_key("x-request"));
+    }
+
+    #[test]
+    fn forwards_to_upstream() {
+        // The upstream server echoes what it received.
+        let upstream = Server::bind(&"127.0.0.1:0".parse::<SocketAddr>().unwrap()).serve(|| {
+            service_fn_ok(|req: Request<Body>| {
+                let headers = req.headers();
+                let echo = format!(
+                    "{} {} host={} xfh={} xff={} xfp={}",
+                    req.method(),
+                    req.uri(),
+                    headers[HOST].to_str().unwrap(),
+                    headers[X_FORWARDED_HOST].to_str().unwrap(),
+                    headers[X_FORWARDED_FOR].to_str().unwrap(),
+                    headers[X_FORWARDED_PROTO].to_str().unwrap(),
+                );
+                Response::new(Body::from(echo))
+            })
+        });
+        let upstream_addr = upstream.local_addr();
+
+        let handler =
+            ProxyHandler::new(format!("http://{}/v1", upstream_addr).parse().unwrap()).unwrap();
+        let proxy_server = TestServer::new(build_simple_router(|route| {
+            route.delegate("/api").to_proxy(handler);
+        }))
+        .unwrap();
+        proxy_server.spawn(upstream.map_err(|_| ()));
+
+        let response = proxy_server
+            .client()
+            .delete("http://example.com/api/users/1?force=true")
+            .perform()
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.read_utf8_body().unwrap(),
+            format!(
+                "DELETE /v1/users/1?force=true host={} xfh=example.com xff=127.0.0.1 xfp=http",
+                upstream_addr
+            )
+        );
+    }
+
+    #[test]
+    fn bad_gateway_when_upstream_unavailable() {
+        let handler = ProxyHandler::new("http://127.0.0.1:1/".parse().unwrap()).unwrap();
+        let test_server = TestServer::new(build_simple_router(|route| {
+            route.delegate("/").to_proxy(handler);
+        }))
+        .unwrap();
+
+        let response = test_server
+            .client()
+            .get("http://example.com/")
+            .perform()
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+    }
+}
@@ -0,0 +1,221 @@
+//! Defines `ServiceHandler`, which allows a Hyper `NewService` to be used as a `Handler`.
+
+use std::error::Error as StdError;
+use std::fmt::{self, Display};
+use std::panic::RefUnwindSafe;
+use std::sync::Arc;
+
+use futures::{future, Future};
+use hyper::service::{NewService, Service};
+use hyper::{Body, HeaderMap, Method, Request, Uri, Version};
+
+use error::Result;
+use handler::{Handler, HandlerFuture, IntoHandlerError, NewHandler};
+use helpers::http::request::path::RequestPathSegments;
+use state::{FromState, State};
+
+/// A `Handler` which forwards requests to a Hyper `NewService`, such as an existing application
+/// being migrated into Gotham.
+///
+/// When the `ServiceHandler` is reached via `DelegateRouteBuilder::to_service`, the delegated
+/// prefix is removed from the request path before the request is forwarded. Otherwise, the
+/// request path is forwarded unchanged.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// #
+/// # use hyper::{Body, Request, Response, StatusCode};
+/// # use hyper::service::service_fn_ok;
+/// # use gotham::router::Router;
+/// # use gotham::router::builder::*;
+/// # use gotham::test::TestServer;
+/// #
+/// fn legacy(req: Request<Body>) -> Response<Body> {
+///     Response::new(Body::from(req.uri().to_string()))
+/// }
+///
+/// fn router() -> Router {
+///     build_simple_router(|route| {
+///         route
+///             .delegate("/legacy")
+///             .to_service(|| service_fn_ok(legacy));
+///     })
+/// }
+/// #
+/// # fn main() {
+/// #   let test_server = TestServer::new(router()).unwrap();
+/// #   let response = test_server.client()
+/// #       .get("http://example.com/legacy/users/1?active=true")
+/// #       .perform()
+/// #       .unwrap();
+/// #   assert_eq!(response.status(), StatusCode::OK);
+/// #   assert_eq!(response.read_body().unwrap(), b"/users/1?active=true");
+/// # }
+/// ```
+pub struct ServiceHandler<S>
+where
+    S: NewService<ReqBody = Body, ResBody = Body>,
+{
+    new_service: Arc<S>,
+}
+
+impl<S> ServiceHandler<S>
+where
+    S: NewService<ReqBody = Body, ResBody = Body>,
+{
+    /// Creates a new `ServiceHandler` which forwards requests to services created by
+    /// `new_service`.
+    pub fn new(new_service: S) -> ServiceHandler<S> {
+        ServiceHandler {
+            new_service: Arc::new(new_service),
+        }
+    }
+}
+
+impl<S> Clone for ServiceHandler<S>
+where
+    S: NewService<ReqBody = Body, ResBody = Body>,
+{
+    fn clone(&self) -> Self {
+        ServiceHandler {
+            new_service: self.new_service.clone(),
+        }
+    }
+}
+
+impl<S> NewHandler for ServiceHandler<S>
+where
+    S: NewService<ReqBody = Body, ResBody = Body> + Send + Sync + RefUnwindSafe + 'static,
+    S::Future: Send + 'static,
+    S::Service: Send + 'static,
+    <S::Service as Service>::Future: Send + 'static,
+{
+    type Instance = Self;
+
+    fn new_handler(&self) -> Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+impl<S> Handler for ServiceHandler<S>
+where
+    S: NewService<ReqBody = Body, ResBody = Body> + Send + Sync + 'static,
+    S::Future: Send + 'static,
+    S::Service: Send + 'static,
+    <S::Service as Service>::Future: Send + 'static,
+{
+    fn handle(self, mut state: State) -> Box<HandlerFuture> {
+        let req = match forwarded_request(&mut state) {
+            Ok(req) => req,
+            Err(e) => return Box::new(future::err((state, e.compat().into_handler_error()))),
+        };
+
+        request_trace!(&state, "forwarding request to service: {}", req.uri());
+
+        let f = self
+            .new_service
+            .new_service()
+            .map_err(|e| ServiceError(e.into()))
+            .and_then(|mut service| service.call(req).map_err(|e| ServiceError(e.into())))
+            .then(move |result| match result {
+                Ok(res) => Ok((state, res)),
+                Err(e) => Err((state, e.into_handler_error())),
+            });
+
+        Box::new(f)
+    }
+}
+
+// Reassembles the `Request` from `State`, without the path segments already processed by the
+// `Router`. The body is moved out of `State`; everything else is copied.
+pub(crate) fn forwarded_request(state: &mut State) -> Result<Request<Body>> {
+    let uri = forwarded_uri(
+        Uri::borrow_from(state),
+        state
+            .try_borrow::<RequestPathSegments>()
+            .map(|rps| rps.segments().len()),
+    );
+
+    let mut req = Request::new(Body::try_take_from(state).unwrap_or_else(Body::empty));
+    *req.method_mut() = Method::borrow_from(state).clone();
+    *req.uri_mut() = uri.parse()?;
+    *req.version_mut() = state.try_borrow::<Version>().cloned().unwrap_or_default();
+    *req.headers_mut() = HeaderMap::borrow_from(state).clone();
+
+    Ok(req)
+}
+
+// Builds the path and query to forward, keeping only the last `remaining` segments of the path.
+fn forwarded_uri(uri: &Uri, remaining: Option<usize>) -> String {
+    let segments: Vec<&str> = uri.path().split('/').filter(|s| !s.is_empty()).collect();
+    let skip = remaining.map_or(0, |r| segments.len().saturating_sub(r));
+
+    let mut forwarded = String::new();
+    for segment in &segments[skip..] {
+        forwarded.push('/');
+        forwarded.push_str(segment);
+    }
+
+    if forwarded.is_empty() || (uri.path().ends_with('/') && uri.path().len() > 1) {
+        forwarded.push('/');
+    }
+
+    if let Some(query) = uri.query() {
+        forwarded.push('?');
+        forwarded.push_str(query);
+    }
+
+    forwarded
+}
+
+// Wraps errors from the `NewService`, which are not otherwise compatible with `HandlerError`.
+#[derive(Debug)]
+struct ServiceError(Box<StdError + Send + Sync>);
+
+impl Display for ServiceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "service error: {}", self.0)
+    }
+}
+
+impl StdError for ServiceError {
+    fn description(&self) -> &str {
+        "service error"
+    }
+
+    fn cause(&self) -> Option<&StdError> {
+        Some(&*self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::str::FromStr;
+
+    fn forwarded(uri: &str, remaining: Option<usize>) -> String {
+        forwarded_uri(&Uri::from_str(uri).unwrap(), remaining)
+    }
+
+    #[test]
+    fn strips_processed_segments() {
+        assert_eq!(forwarded("/legacy/users/1", Some(2)), "/users/1");
+        assert_eq!(
+            forwarded("/legacy//users/%20?q=1", Some(2)),
+            "/users/%20?q=1"
+        );
+        assert_eq!(forwarded("/legacy/users/", Some(1)), "/users/");
+        assert_eq!(forwarded("/legacy", Some(0)), "/");
+        assert_eq!(forwarded("/legacy/?q=1", Some(0)), "/?q=1");
+    }
+
+    #[test]
+    fn forwards_unchanged_without_segments() {
+        assert_eq!(forwarded("/users/1?q=1", None), "/users/1?q=1");
+        assert_eq!(forwarded("/", None), "/");
+    }
+}
@@ -0,0 +1,346 @@
+//! Defines `HealthChecks`, which reports the health of an application to load balancers and
+//! orchestrators via `/healthz` and `/readyz` endpoints.
+
+use std::collections::BTreeMap;
+use std::panic::RefUnwindSafe;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures::future::{self, Either, IntoFuture};
+use futures::Future;
+use hyper::StatusCode;
+use mime;
+use serde_json;
+use tokio::timer::Delay;
+
+use error::Result;
+use handler::{Handler, HandlerFuture, NewHandler};
+use helpers::http::response::create_response;
+use state::State;
+
+/// The future returned by a health check, which resolves to `()` when the check passes, or to a
+/// description of the problem when it fails.
+pub type CheckFuture = Future<Item = (), Error = String> + Send;
+
+struct Check {
+    name: String,
+    check: Box<Fn() -> Box<CheckFuture> + Send + Sync + RefUnwindSafe>,
+}
+
+/// A set of named checks which determine whether an application is healthy.
+///
+/// Checks are divided into two kinds, following the conventions of orchestrators such as
+/// Kubernetes:
+///
+/// * Liveness checks determine whether the application is working at all, and should be
+///   restarted if not. They are reported by `/healthz`.
+/// * Readiness checks determine whether the application is able to serve requests, such as
+///   whether its database can be reached, and should be sent no traffic if not. They are
+///   reported by `/readyz`, along with the liveness checks.
+///
+/// Each endpoint runs its checks concurrently, and responds with `200 OK` if they all pass, or
+/// `503 Service Unavailable` otherwise. A check which does not complete within the timeout, which
+/// is 5 seconds by default, has failed. The body is a JSON document describing each check:
+///
+/// ```json
+/// {
+///   "status": "error",
+///   "checks": {
+///     "cache": { "status": "ok" },
+///     "database": { "status": "error", "error": "connection refused" }
+///   }
+/// }
+/// ```
+///
+/// The endpoints are added to a router via `DrawRoutes::health`.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate futures;
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// #
+/// # use futures::future;
+/// # use hyper::StatusCode;
+/// # use gotham::health::HealthChecks;
+/// # use gotham::router::Router;
+/// # use gotham::router::builder::*;
+/// # use gotham::test::TestServer;
+/// #
+/// fn router() -> Router {
+///     let checks = HealthChecks::new()
+///         .readiness("database", || future::err::<(), _>("connection refused".to_owned()))
+///         .readiness("cache", || Ok(()));
+///
+///     build_simple_router(|route| {
+///         route.health(checks);
+///     })
+/// }
+/// #
+/// # fn main() {
+/// #   let test_server = TestServer::new(router()).unwrap();
+/// #
+/// #   let response = test_server.client().get("http://example.com/healthz").perform().unwrap();
+/// #   assert_eq!(response.status(), StatusCode::OK);
+/// #   assert_eq!(response.read_utf8_body().unwrap(), r#"{"status":"ok","checks":{}}"#);
+/// #
+/// #   let response = test_server.client().get("http://example.com/readyz").perform().unwrap();
+/// #   assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+/// #   assert_eq!(
+/// #       response.read_utf8_body().unwrap(),
+/// #       concat!(
+/// #           r#"{"status":"error","checks":{"cache":{"status":"ok"},"#,
+/// #           r#""database":{"status":"error","error":"connection refused"}}}"#
+/// #       )
+/// #   );
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct HealthChecks {
+    liveness: Vec<Arc<Check>>,
+    readiness: Vec<Arc<Check>>,
+    timeout: Duration,
+}
+
+impl Default for HealthChecks {
+    fn default() -> HealthChecks {
+        HealthChecks {
+            liveness: Vec::new(),
+            readiness: Vec::new(),
+            timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+impl HealthChecks {
+    /// Creates an empty set of checks. With no checks, both endpoints report the application as
+    /// healthy whenever it is able to respond.
+    pub fn new() -> HealthChecks {
+        HealthChecks::default()
+    }
+
+    /// Adds a liveness check, which is run by both `/healthz` and `/readyz`.
+    pub fn liveness<F, R>(mut self, name: &str, check: F) -> HealthChecks
+    where
+        F: Fn() -> R + Send + Sync + RefUnwindSafe + 'static,
+        R: IntoFuture<Item = (), Error = String>,
+        R::Future: Send + 'static,
+    {
+        self.liveness.push(Arc::new(Check::new(name, check)));
+        self
+    }
+
+    /// Adds a readiness check, which is run by `/readyz`.
+    pub fn readiness<F, R>(mut self, name: &str, check: F) -> HealthChecks
+    where
+        F: Fn() -> R + Send + Sync + RefUnwindSafe + 'static,
+        R: IntoFuture<Item = (), Error = String>,
+        R::Future: Send + 'static,
+    {
+        self.readiness.push(Arc::new(Check::new(name, check)));
+        self
+    }
+
+    /// Sets the time allowed for each check to complete before it is considered to have failed.
+    pub fn with_timeout(self, timeout: Duration) -> HealthChecks {
+        HealthChecks { timeout, ..self }
+    }
+
+    /// Creates the `Handler` for `/healthz`, which runs the liveness checks.
+    pub fn liveness_handler(&self) -> HealthHandler {
+        HealthHandler {
+            checks: Arc::new(self.liveness.clone()),
+            timeout: self.timeout,
+        }
+    }
+
+    /// Creates the `Handler` for `/readyz`, which runs the liveness and readiness checks.
+    pub fn readiness_handler(&self) -> HealthHandler {
+        let mut checks = self.liveness.clone();
+        checks.extend(self.readiness.iter().cloned());
+
+        HealthHandler {
+            checks: Arc::new(checks),
+            timeout: self.timeout,
+        }
+    }
+}
+
+impl Check {
+    fn new<F, R>(name: &str, check: F) -> Check
+    where
+        F: Fn() -> R + Send + Sync + RefUnwindSafe + 'static,
+        R: IntoFuture<Item = (), Error = String>,
+        R::Future: Send + 'static,
+    {
+        Check {
+            name: name.to_owned(),
+            check: Box::new(move || Box::new(check().into_future())),
+        }
+    }
+
+    // Runs the check, failing it if the timeout elapses first.
+    fn run(&self, timeout: Duration) -> Box<Future<Item = Outcome, Error = ()> + Send> {
+        let deadline = Delay::new(Instant::now() + timeout);
+
+        let f = (self.check)()
+            .select2(deadline)
+            .then(|result| match result {
+                Ok(Either::A(_)) => Ok(Outcome::ok()),
+                Err(Either::A((error, _))) => Ok(Outcome::error(error)),
+                Ok(Either::B(_)) => Ok(Outcome::error("timed out".to_owned())),
+                Err(Either::B((error, _))) => Ok(Outcome::error(error.to_string())),
+            });
+
+        Box::new(f)
+    }
+}
+
+// The outcome of a single check, as reported in the response body.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+struct Outcome {
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl Outcome {
+    fn ok() -> Outcome {
+        Outcome {
+            status: "ok",
+            error: None,
+        }
+    }
+
+    fn error(error: String) -> Outcome {
+        Outcome {
+            status: "error",
+            error: Some(error),
+        }
+    }
+}
+
+// The response body reported by a `HealthHandler`.
+#[derive(Serialize)]
+struct Report {
+    status: &'static str,
+    checks: BTreeMap<String, Outcome>,
+}
+
+impl Report {
+    fn new(checks: BTreeMap<String, Outcome>) -> Report {
+        let healthy = checks.values().all(|outcome| outcome.error.is_none());
+
+        Report {
+            status: if healthy { "ok" } else { "error" },
+            checks,
+        }
+    }
+
+    fn status_code(&self) -> StatusCode {
+        if self.status == "ok" {
+            StatusCode::OK
+        } else {
+            StatusCode::SERVICE_UNAVAILABLE
+        }
+    }
+}
+
+/// A `Handler` which runs a set of health checks and reports their outcome. Created by
+/// `HealthChecks::liveness_handler` and `HealthChecks::readiness_handler`.
+#[derive(Clone)]
+pub struct HealthHandler {
+    checks: Arc<Vec<Arc<Check>>>,
+    timeout: Duration,
+}
+
+impl NewHandler for HealthHandler {
+    type Instance = Self;
+
+    fn new_handler(&self) -> Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+impl Handler for HealthHandler {
+    fn handle(self, state: State) -> Box<HandlerFuture> {
+        let timeout = self.timeout;
+        let checks = self
+            .checks
+            .iter()
+            .map(move |check| {
+                let name = check.name.clone();
+                check.run(timeout).map(move |outcome| (name, outcome))
+            })
+            .collect::<Vec<_>>();
+
+        let f = future::join_all(checks).then(move |result| {
+            let report = Report::new(result.unwrap_or_default().into_iter().collect());
+
+            if let Some((name, outcome)) = report
+                .checks
+                .iter()
+                .find(|&(_, outcome)| outcome.error.is_some())
+            {
+                request_warn!(
+                    &state,
+                    "health check {} failed: {}",
+                    name,
+                    outcome.error.as_ref().map(|e| e.as_str()).unwrap_or("")
+                );
+            }
+
+            let body = serde_json::to_vec(&report).expect("health report is serializable");
+            let res = create_response(&state, report.status_code(), mime::APPLICATION_JSON, body);
+            Ok((state, res))
+        });
+
+        Box::new(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_checks_with_timeout() {
+        let checks = HealthChecks::new()
+            .with_timeout(Duration::from_millis(10))
+            .liveness("live", || Ok(()))
+            .readiness("slow", || {
+                Delay::new(Instant::now() + Duration::from_secs(5)).map_err(|e| e.to_string())
+            });
+
+        assert_eq!(checks.liveness_handler().checks.len(), 1);
+        assert_eq!(checks.readiness_handler().checks.len(), 2);
+
+        let mut runtime = ::tokio::runtime::Runtime::new().unwrap();
+
+        let check = &checks.readiness[0];
+        let outcome = runtime.block_on(check.run(checks.timeout)).unwrap();
+        assert_eq!(outcome, Outcome::error("timed out".to_owned()));
+
+        let check = &checks.liveness[0];
+        let outcome = runtime.block_on(check.run(checks.timeout)).unwrap();
+        assert_eq!(outcome, Outcome::ok());
+    }
+
+    #[test]
+    fn reports_status() {
+        let mut checks = BTreeMap::new();
+        checks.insert("a".to_owned(), Outcome::ok());
+
+        let report = Report::new(checks.clone());
+        assert_eq!(report.status_code(), StatusCode::OK);
+        assert_eq!(
+            serde_json::to_string(&report).unwrap(),
+            r#"{"status":"ok","checks":{"a":{"status":"ok"}}}"#
+        );
+
+        checks.insert("b".to_owned(), Outcome::error("down".to_owned()));
+        let report = Report::new(checks);
+        assert_eq!(report.status_code(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+}
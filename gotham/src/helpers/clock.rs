@@ -0,0 +1,191 @@
+//! Defines the `Clock` which Gotham reads the current time from, so that tests can control the
+//! passage of time.
+//!
+//! The clock is set for a server with `ServerBuilder::with_clock`, and is used for session expiry
+//! in the `MemoryBackend` (which is given the clock with `MemoryBackend::with_clock`) and for the
+//! times recorded by the request loggers. Without a clock, the system clock is used. Timers which
+//! are run by Tokio, such as the deadline of `RequestTimeout`, always use the system clock.
+//!
+//! # Examples
+//!
+//! ```rust
+//! # extern crate gotham;
+//! # extern crate hyper;
+//! #
+//! # use std::time::{Duration, UNIX_EPOCH};
+//! # use hyper::{Body, Response};
+//! # use gotham::helpers::clock::{self, ManualClock};
+//! # use gotham::state::State;
+//! # use gotham::test::TestServer;
+//! # use gotham::ServerBuilder;
+//! #
+//! fn handler(state: State) -> (State, Response<Body>) {
+//!     let secs = clock::system_time(&state)
+//!         .duration_since(UNIX_EPOCH)
+//!         .unwrap()
+//!         .as_secs();
+//!
+//!     (state, Response::new(Body::from(secs.to_string())))
+//! }
+//!
+//! # fn main() {
+//! let clock = ManualClock::at(UNIX_EPOCH + Duration::from_secs(1000));
+//! let builder = ServerBuilder::new().with_clock(clock.clone());
+//! let test_server = TestServer::with_server_builder(builder, || Ok(handler)).unwrap();
+//!
+//! let response = test_server.client().get("/").perform().unwrap();
+//! assert_eq!(response.read_utf8_body().unwrap(), "1000");
+//!
+//! clock.advance(Duration::from_secs(60));
+//! let response = test_server.client().get("/").perform().unwrap();
+//! assert_eq!(response.read_utf8_body().unwrap(), "1060");
+//! # }
+//! ```
+
+use std::panic::RefUnwindSafe;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use state::{FromState, State, StateData};
+
+/// A source of the current time.
+pub trait Clock: Send + Sync + RefUnwindSafe {
+    /// Returns the current `Instant`, which is used to measure durations.
+    fn now(&self) -> Instant;
+
+    /// Returns the current `SystemTime`, which is used for timestamps.
+    fn system_time(&self) -> SystemTime;
+}
+
+/// The `Clock` which reads the time from the operating system.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn system_time(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A `Clock` which stands still until it is advanced, for making tests which depend on the time
+/// reproducible. Clones of a `ManualClock` share the same time.
+#[derive(Clone)]
+pub struct ManualClock {
+    time: Arc<Mutex<(Instant, SystemTime)>>,
+}
+
+impl ManualClock {
+    /// Creates a `ManualClock` which starts at the current time.
+    pub fn new() -> ManualClock {
+        ManualClock::at(SystemTime::now())
+    }
+
+    /// Creates a `ManualClock` which starts at `system_time`.
+    pub fn at(system_time: SystemTime) -> ManualClock {
+        ManualClock {
+            time: Arc::new(Mutex::new((Instant::now(), system_time))),
+        }
+    }
+
+    /// Moves the time of the clock forward by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        let mut time = self.time.lock().unwrap();
+        time.0 += duration;
+        time.1 += duration;
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> ManualClock {
+        ManualClock::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.time.lock().unwrap().0
+    }
+
+    fn system_time(&self) -> SystemTime {
+        self.time.lock().unwrap().1
+    }
+}
+
+// The `Clock` for the request, when one has been set for the server.
+struct RequestClock(Arc<Clock>);
+
+impl StateData for RequestClock {}
+
+/// Puts the `Clock` for the request into `state`.
+pub(crate) fn put_clock(state: &mut State, clock: Arc<Clock>) {
+    state.put(RequestClock(clock));
+}
+
+/// Returns the current `Instant` of the `Clock` for the request.
+pub fn now(state: &State) -> Instant {
+    match RequestClock::try_borrow_from(state) {
+        Some(clock) => clock.0.now(),
+        None => Instant::now(),
+    }
+}
+
+/// Returns the current `SystemTime` of the `Clock` for the request.
+pub fn system_time(state: &State) -> SystemTime {
+    match RequestClock::try_borrow_from(state) {
+        Some(clock) => clock.0.system_time(),
+        None => SystemTime::now(),
+    }
+}
+
+/// Returns the time elapsed between `earlier` and `now`, or zero if `earlier` is later, which can
+/// happen when a `ManualClock` has been advanced past the system clock.
+pub(crate) fn elapsed_between(earlier: Instant, now: Instant) -> Duration {
+    if now > earlier {
+        now - earlier
+    } else {
+        Duration::from_secs(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::UNIX_EPOCH;
+
+    #[test]
+    fn manual_clock_advances() {
+        let clock = ManualClock::at(UNIX_EPOCH);
+        let shared = clock.clone();
+        let start = clock.now();
+
+        shared.advance(Duration::from_secs(5));
+
+        assert_eq!(clock.now() - start, Duration::from_secs(5));
+        assert_eq!(clock.system_time(), UNIX_EPOCH + Duration::from_secs(5));
+    }
+
+    #[test]
+    fn reads_clock_from_state() {
+        let clock = ManualClock::at(UNIX_EPOCH);
+        let mut state = State::new();
+        assert!(system_time(&state) > UNIX_EPOCH);
+
+        put_clock(&mut state, Arc::new(clock.clone()));
+        assert_eq!(system_time(&state), UNIX_EPOCH);
+        assert_eq!(now(&state), clock.now());
+    }
+
+    #[test]
+    fn elapsed_is_never_negative() {
+        let earlier = Instant::now();
+        let later = earlier + Duration::from_secs(1);
+
+        assert_eq!(elapsed_between(earlier, later), Duration::from_secs(1));
+        assert_eq!(elapsed_between(later, earlier), Duration::from_secs(0));
+    }
+}
@@ -6,3 +6,13 @@ pub const X_REQUEST_ID: &'static str = "x-request-id";
 
 /// Marks the execution time of a Gotham request.
 pub const X_RUNTIME_DURATION: &'static str = "x-runtime-duration";
+
+/// Carries the method which a `POST` request should be treated as, for clients which cannot send
+/// the method directly.
+pub const X_HTTP_METHOD_OVERRIDE: &'static str = "x-http-method-override";
+
+/// Lists the addresses of the client and each proxy which has forwarded a request.
+pub const X_FORWARDED_FOR: &'static str = "x-forwarded-for";
+
+/// Marks the scheme used by the client to make a request which has been forwarded by a proxy.
+pub const X_FORWARDED_PROTO: &'static str = "x-forwarded-proto";
@@ -1,6 +1,8 @@
 //! Helpers for HTTP request handling and response generation
 
 pub mod header;
+pub mod negotiate;
+pub mod precondition;
 pub mod request;
 pub mod response;
 
@@ -0,0 +1,327 @@
+//! Helpers for choosing the format of a response from the `Accept` header of the request.
+
+use hyper::header::{HeaderMap, HeaderValue, ACCEPT, VARY};
+use hyper::{Body, Response, StatusCode};
+use mime::{self, Mime};
+use std::cmp::Ordering;
+
+use handler::IntoResponse;
+use helpers::http::response::{create_empty_response, create_response};
+use state::{FromState, State};
+
+/// A media range from the `Accept` header, with its quality.
+struct MediaRange {
+    range: Mime,
+    quality: f32,
+}
+
+impl MediaRange {
+    // How closely the range matches `mime`, where a higher value is more specific, or `None` when
+    // it does not match at all.
+    fn specificity(&self, mime: &Mime) -> Option<u8> {
+        let range = &self.range;
+
+        if range.type_() == mime::STAR && range.subtype() == mime::STAR {
+            Some(0)
+        } else if range.type_() != mime.type_() {
+            None
+        } else if range.subtype() == mime::STAR {
+            Some(1)
+        } else if range.subtype() == mime.subtype() {
+            Some(2)
+        } else {
+            None
+        }
+    }
+}
+
+// Parses the `Accept` headers into their media ranges, ignoring any which are not valid.
+fn parse_accept(headers: &HeaderMap) -> Vec<MediaRange> {
+    headers
+        .get_all(ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|item| item.trim().parse::<Mime>().ok())
+        .map(|range| {
+            let quality = range
+                .get_param("q")
+                .and_then(|q| q.as_str().parse::<f32>().ok())
+                .unwrap_or(1.0);
+
+            MediaRange { range, quality }
+        })
+        .collect()
+}
+
+/// Chooses the media type from `available` which best suits the client, according to the
+/// `Accept` header of the request.
+///
+/// Each media type is given the quality of the most specific media range in the `Accept` header
+/// which matches it, and the media type with the highest quality is chosen. When several have the
+/// same quality, the one given first in `available` is chosen, so `available` should be ordered by
+/// the application's own preference. When the request has no `Accept` header, the first media type
+/// is chosen. Returns `None` when the client does not accept any of `available`.
+///
+/// ```rust
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// # extern crate mime;
+/// #
+/// # use hyper::header::{HeaderMap, ACCEPT};
+/// # use gotham::helpers::http::negotiate::negotiate_mime;
+/// # use gotham::state::State;
+/// #
+/// # fn main() {
+/// # State::with_new(|state| {
+/// # let mut headers = HeaderMap::new();
+/// # headers.insert(ACCEPT, "text/html, application/*;q=0.9".parse().unwrap());
+/// # state.put(headers);
+/// // Given a client which accepts `text/html, application/*;q=0.9`.
+/// let available = [mime::APPLICATION_JSON, mime::TEXT_HTML, mime::TEXT_CSV];
+/// assert_eq!(negotiate_mime(state, &available), Some(&mime::TEXT_HTML));
+/// assert_eq!(negotiate_mime(state, &available[..1]), Some(&mime::APPLICATION_JSON));
+/// assert_eq!(negotiate_mime(state, &available[2..]), None);
+/// # });
+/// # }
+/// ```
+pub fn negotiate_mime<'a>(state: &State, available: &'a [Mime]) -> Option<&'a Mime> {
+    let ranges = match HeaderMap::try_borrow_from(state) {
+        Some(headers) => parse_accept(headers),
+        None => Vec::new(),
+    };
+
+    if ranges.is_empty() {
+        return available.first();
+    }
+
+    let mut best: Option<(f32, &Mime)> = None;
+
+    for mime in available {
+        let quality = ranges
+            .iter()
+            .filter_map(|range| range.specificity(mime).map(|s| (s, range.quality)))
+            .max_by(|a, b| a.0.cmp(&b.0))
+            .map(|(_, quality)| quality)
+            .unwrap_or(0.0);
+
+        let better = match best {
+            Some((best_quality, _)) => {
+                quality.partial_cmp(&best_quality) == Some(Ordering::Greater)
+            }
+            None => true,
+        };
+
+        if quality > 0.0 && better {
+            best = Some((quality, mime));
+        }
+    }
+
+    best.map(|(_, mime)| mime)
+}
+
+/// The representations which a handler is able to produce for a resource, one of which is sent
+/// to the client based on the `Accept` header of the request.
+///
+/// Each representation is registered with its media type and a function which renders the body,
+/// so that only the chosen representation is rendered. The representation is chosen by
+/// `negotiate_mime`, with representations registered first being preferred when the client has no
+/// preference between them. A `406 Not Acceptable` response is sent when the client accepts none
+/// of them. The response includes `Vary: Accept` in both cases, so that caches store each
+/// representation separately.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// # extern crate mime;
+/// #
+/// # use hyper::header::{ACCEPT, CONTENT_TYPE, VARY};
+/// # use hyper::StatusCode;
+/// # use gotham::helpers::http::negotiate::Representations;
+/// # use gotham::state::State;
+/// # use gotham::test::TestServer;
+/// #
+/// fn user(state: State) -> (State, Representations<'static>) {
+///     let (id, name) = (1, "Alice");
+///
+///     let representations = Representations::new()
+///         .with(mime::APPLICATION_JSON, move || {
+///             format!(r#"{{"id":{},"name":"{}"}}"#, id, name)
+///         })
+///         .with(mime::TEXT_HTML, move || format!("<h1>{}</h1>", name))
+///         .with(mime::TEXT_CSV, move || format!("id,name\n{},{}\n", id, name));
+///
+///     (state, representations)
+/// }
+/// #
+/// # fn main() {
+/// #   let test_server = TestServer::new(|| Ok(user)).unwrap();
+/// #
+/// #   let response = test_server
+/// #       .client()
+/// #       .get("http://localhost/")
+/// #       .with_header(ACCEPT, "text/html;q=0.9, text/csv".parse().unwrap())
+/// #       .perform()
+/// #       .unwrap();
+/// #   assert_eq!(response.status(), StatusCode::OK);
+/// #   assert_eq!(response.headers()[CONTENT_TYPE], "text/csv");
+/// #   assert_eq!(response.headers()[VARY], "accept");
+/// #   assert_eq!(response.read_utf8_body().unwrap(), "id,name\n1,Alice\n");
+/// #
+/// #   let response = test_server
+/// #       .client()
+/// #       .get("http://localhost/")
+/// #       .with_header(ACCEPT, "image/png".parse().unwrap())
+/// #       .perform()
+/// #       .unwrap();
+/// #   assert_eq!(response.status(), StatusCode::NOT_ACCEPTABLE);
+/// # }
+/// ```
+pub struct Representations<'a> {
+    representations: Vec<(Mime, Box<FnOnce() -> Body + Send + 'a>)>,
+}
+
+impl<'a> Representations<'a> {
+    /// Creates `Representations` with nothing registered, which always responds with `406 Not
+    /// Acceptable`.
+    pub fn new() -> Representations<'a> {
+        Representations {
+            representations: Vec::new(),
+        }
+    }
+
+    /// Registers a representation of the resource with the media type `mime`, whose body is
+    /// rendered by `render` if it is chosen.
+    pub fn with<F, B>(self, mime: Mime, render: F) -> Representations<'a>
+    where
+        F: FnOnce() -> B + Send + 'a,
+        B: Into<Body>,
+    {
+        let mut representations = self.representations;
+        representations.push((mime, Box::new(move || render().into())));
+        Representations { representations }
+    }
+}
+
+impl<'a> Default for Representations<'a> {
+    fn default() -> Representations<'a> {
+        Representations::new()
+    }
+}
+
+impl<'a> IntoResponse for Representations<'a> {
+    fn into_response(self, state: &State) -> Response<Body> {
+        let chosen = {
+            let available: Vec<Mime> = self
+                .representations
+                .iter()
+                .map(|&(ref mime, _)| mime.clone())
+                .collect();
+
+            negotiate_mime(state, &available)
+                .and_then(|mime| available.iter().position(|m| m == mime))
+        };
+
+        let mut res = match chosen {
+            Some(index) => {
+                let (mime, render) = self.representations.into_iter().nth(index).unwrap();
+                create_response(state, StatusCode::OK, mime, render())
+            }
+            None => create_empty_response(state, StatusCode::NOT_ACCEPTABLE),
+        };
+
+        res.headers_mut()
+            .append(VARY, HeaderValue::from_static("accept"));
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use hyper::Method;
+
+    use state::set_request_id;
+
+    fn state(accept: Option<&'static str>) -> State {
+        let mut headers = HeaderMap::new();
+        if let Some(accept) = accept {
+            headers.insert(ACCEPT, HeaderValue::from_static(accept));
+        }
+
+        let mut state = State::new();
+        state.put(Method::GET);
+        state.put(headers);
+        set_request_id(&mut state);
+        state
+    }
+
+    fn negotiate(accept: Option<&'static str>, available: &[Mime]) -> Option<Mime> {
+        negotiate_mime(&state(accept), available).cloned()
+    }
+
+    #[test]
+    fn prefers_highest_quality() {
+        let available = [mime::TEXT_HTML, mime::APPLICATION_JSON];
+
+        assert_eq!(
+            negotiate(Some("text/html;q=0.5, application/json"), &available),
+            Some(mime::APPLICATION_JSON)
+        );
+        assert_eq!(
+            negotiate(Some("*/*;q=0.1, text/*;q=0.8"), &available),
+            Some(mime::TEXT_HTML)
+        );
+    }
+
+    #[test]
+    fn most_specific_range_wins() {
+        let available = [mime::TEXT_HTML, mime::TEXT_CSV];
+
+        assert_eq!(
+            negotiate(Some("text/*, text/html;q=0"), &available),
+            Some(mime::TEXT_CSV)
+        );
+    }
+
+    #[test]
+    fn ties_prefer_registration_order() {
+        let available = [mime::TEXT_CSV, mime::TEXT_HTML];
+
+        assert_eq!(negotiate(Some("text/*"), &available), Some(mime::TEXT_CSV));
+        assert_eq!(negotiate(Some("*/*"), &available), Some(mime::TEXT_CSV));
+        assert_eq!(negotiate(None, &available), Some(mime::TEXT_CSV));
+        assert_eq!(negotiate(Some("invalid"), &available), Some(mime::TEXT_CSV));
+    }
+
+    #[test]
+    fn nothing_acceptable() {
+        let available = [mime::TEXT_HTML];
+
+        assert_eq!(negotiate(Some("application/json"), &available), None);
+        assert_eq!(negotiate(Some("text/html;q=0"), &available), None);
+        assert_eq!(negotiate(Some("text/html"), &[]), None);
+    }
+
+    #[test]
+    fn renders_only_chosen_representation() {
+        let res = Representations::new()
+            .with(mime::TEXT_HTML, || -> String { panic!("not chosen") })
+            .with(mime::APPLICATION_JSON, || "{}")
+            .into_response(&state(Some("application/json")));
+
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()[VARY], "accept");
+    }
+
+    #[test]
+    fn not_acceptable() {
+        let res = Representations::new().into_response(&state(None));
+
+        assert_eq!(res.status(), StatusCode::NOT_ACCEPTABLE);
+        assert_eq!(res.headers()[VARY], "accept");
+    }
+}
@@ -0,0 +1,427 @@
+//! Helpers for evaluating the `If-Match` and `If-Unmodified-Since` preconditions of a request, as
+//! used for optimistic concurrency control.
+
+use httpdate::parse_http_date;
+use hyper::header::{HeaderMap, HeaderValue, ETAG, IF_MATCH, IF_UNMODIFIED_SINCE};
+use hyper::{Body, Response, StatusCode};
+use std::collections::hash_map::DefaultHasher;
+use std::fmt::{self, Display};
+use std::hash::{Hash, Hasher};
+use std::time::{Duration, SystemTime};
+
+use helpers::http::response::create_empty_response;
+use state::{FromState, State};
+
+/// An entity tag, which identifies a version of a resource in the `ETag` header of a response and
+/// the `If-Match` and `If-None-Match` headers of a request.
+///
+/// A strong tag changes whenever the representation of the resource changes in any way, while a
+/// weak tag may be kept for representations which are equivalent but not identical.
+///
+/// ```rust
+/// # extern crate gotham;
+/// # use gotham::helpers::http::precondition::EntityTag;
+/// # fn main() {
+/// let tag = EntityTag::from_hash(&("user", 1, 42u64));
+/// assert!(!tag.is_weak());
+/// assert_eq!(tag, EntityTag::from_hash(&("user", 1, 42u64)));
+///
+/// assert_eq!(EntityTag::strong("v7").to_string(), "\"v7\"");
+/// assert_eq!(EntityTag::weak("v7").to_string(), "W/\"v7\"");
+/// # }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct EntityTag {
+    weak: bool,
+    tag: String,
+}
+
+impl EntityTag {
+    /// Creates a strong `EntityTag` with the given opaque tag, which excludes the surrounding
+    /// quotes.
+    ///
+    /// # Panics
+    ///
+    /// If the tag contains a `"`, or a control or whitespace character.
+    pub fn strong<T: Into<String>>(tag: T) -> EntityTag {
+        EntityTag::new(false, tag.into())
+    }
+
+    /// Creates a weak `EntityTag` with the given opaque tag, which excludes the surrounding
+    /// quotes.
+    ///
+    /// # Panics
+    ///
+    /// If the tag contains a `"`, or a control or whitespace character.
+    pub fn weak<T: Into<String>>(tag: T) -> EntityTag {
+        EntityTag::new(true, tag.into())
+    }
+
+    /// Creates a strong `EntityTag` from the length and a hash of `content`, which is the same tag
+    /// given to the body by `ETagMiddleware`.
+    pub fn from_content(content: &[u8]) -> EntityTag {
+        let mut hasher = DefaultHasher::new();
+        hasher.write(content);
+
+        EntityTag {
+            weak: false,
+            tag: format!("{:x}-{:x}", content.len(), hasher.finish()),
+        }
+    }
+
+    /// Creates a strong `EntityTag` from a hash of `value`, such as the identifier and version
+    /// number of a record.
+    pub fn from_hash<T: Hash + ?Sized>(value: &T) -> EntityTag {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+
+        EntityTag {
+            weak: false,
+            tag: format!("{:x}", hasher.finish()),
+        }
+    }
+
+    fn new(weak: bool, tag: String) -> EntityTag {
+        assert!(tag.bytes().all(is_etagc), "invalid entity tag: {:?}", tag);
+
+        EntityTag { weak, tag }
+    }
+
+    /// Whether the tag is weak.
+    pub fn is_weak(&self) -> bool {
+        self.weak
+    }
+
+    /// The opaque tag, without the surrounding quotes.
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    /// Compares two tags using the strong comparison of RFC 7232, where both tags must be strong.
+    pub fn strong_eq(&self, other: &EntityTag) -> bool {
+        !self.weak && !other.weak && self.tag == other.tag
+    }
+
+    /// Compares two tags using the weak comparison of RFC 7232, which ignores whether they are
+    /// weak.
+    pub fn weak_eq(&self, other: &EntityTag) -> bool {
+        self.tag == other.tag
+    }
+
+    /// The tag as the value of an `ETag` header.
+    pub fn to_header_value(&self) -> HeaderValue {
+        HeaderValue::from_str(&self.to_string()).expect("entity tags are valid header values")
+    }
+}
+
+impl Display for EntityTag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.weak {
+            f.write_str("W/")?;
+        }
+
+        write!(f, "\"{}\"", self.tag)
+    }
+}
+
+// Whether `b` may appear within the quotes of an entity tag, as defined by RFC 7232.
+fn is_etagc(b: u8) -> bool {
+    b == 0x21 || (b >= 0x23 && b != 0x7f)
+}
+
+/// The entity tags listed in the `If-Match` header of a request.
+#[derive(Clone, Debug, PartialEq)]
+pub enum IfMatch {
+    /// `*`, which matches any current representation of the resource.
+    Any,
+
+    /// A list of entity tags, one of which must match the current representation of the resource.
+    Tags(Vec<EntityTag>),
+}
+
+impl IfMatch {
+    /// Whether the current entity tag of the resource satisfies the precondition, using the strong
+    /// comparison. `current` is `None` when the resource does not exist, which never matches.
+    pub fn matches(&self, current: Option<&EntityTag>) -> bool {
+        match (self, current) {
+            (_, None) => false,
+            (&IfMatch::Any, Some(_)) => true,
+            (&IfMatch::Tags(ref tags), Some(current)) => tags.iter().any(|t| t.strong_eq(current)),
+        }
+    }
+}
+
+/// Parses the `If-Match` headers of the request, or returns `None` when there are none. Entity
+/// tags which can't be parsed are ignored, so they never match.
+pub fn if_match(state: &State) -> Option<IfMatch> {
+    let headers = HeaderMap::try_borrow_from(state)?;
+    if !headers.contains_key(IF_MATCH) {
+        return None;
+    }
+
+    let mut tags = Vec::new();
+    for value in headers.get_all(IF_MATCH) {
+        let value = match value.to_str() {
+            Ok(value) => value,
+            Err(_) => continue,
+        };
+
+        if value.trim() == "*" {
+            return Some(IfMatch::Any);
+        }
+
+        tags.extend(parse_list(value));
+    }
+
+    Some(IfMatch::Tags(tags))
+}
+
+/// Parses the `If-Unmodified-Since` header of the request, or returns `None` when it is missing or
+/// is not a valid date.
+pub fn if_unmodified_since(state: &State) -> Option<SystemTime> {
+    HeaderMap::try_borrow_from(state)?
+        .get(IF_UNMODIFIED_SINCE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| parse_http_date(v).ok())
+}
+
+/// Evaluates the `If-Match` and `If-Unmodified-Since` preconditions of a request against the
+/// current entity tag and modification time of the resource, as described by RFC 7232.
+///
+/// Returns a `412 Precondition Failed` response when a precondition fails, which should be sent
+/// instead of performing the request, such as when a client tries to update a resource which has
+/// changed since the client last read it. The response carries the current `ETag` when there is
+/// one. `If-Unmodified-Since` is only evaluated when there is no `If-Match`, and is ignored when
+/// `last_modified` is `None`. Pass `None` for `current` when the resource does not exist.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// #
+/// # use hyper::{Body, Response, StatusCode};
+/// # use hyper::header::{ETAG, IF_MATCH};
+/// # use gotham::helpers::http::precondition::{check_preconditions, EntityTag};
+/// # use gotham::helpers::http::response::create_empty_response;
+/// # use gotham::router::builder::*;
+/// # use gotham::state::State;
+/// # use gotham::test::TestServer;
+/// #
+/// fn update_user(state: State) -> (State, Response<Body>) {
+///     // In a real application, the version would be loaded along with the user.
+///     let current = EntityTag::from_hash(&("user", 1, 42u64));
+///
+///     if let Err(res) = check_preconditions(&state, Some(&current), None) {
+///         return (state, res);
+///     }
+///
+///     // ... update the user, then respond with the new version.
+///     let mut res = create_empty_response(&state, StatusCode::NO_CONTENT);
+///     let updated = EntityTag::from_hash(&("user", 1, 43u64));
+///     res.headers_mut().insert(ETAG, updated.to_header_value());
+///     (state, res)
+/// }
+/// #
+/// # fn main() {
+/// #   let test_server = TestServer::new(build_simple_router(|route| {
+/// #       route.put("/users/1").to(update_user);
+/// #   })).unwrap();
+/// #
+/// #   let current = EntityTag::from_hash(&("user", 1, 42u64));
+/// #   let response = test_server.client()
+/// #       .put("http://example.com/users/1", "{}", "application/json".parse().unwrap())
+/// #       .with_header(IF_MATCH, current.to_header_value())
+/// #       .perform()
+/// #       .unwrap();
+/// #   assert_eq!(response.status(), StatusCode::NO_CONTENT);
+/// #
+/// #   let response = test_server.client()
+/// #       .put("http://example.com/users/1", "{}", "application/json".parse().unwrap())
+/// #       .with_header(IF_MATCH, "\"stale\"".parse().unwrap())
+/// #       .perform()
+/// #       .unwrap();
+/// #   assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+/// #   assert_eq!(response.headers()[ETAG], current.to_header_value());
+/// # }
+/// ```
+pub fn check_preconditions(
+    state: &State,
+    current: Option<&EntityTag>,
+    last_modified: Option<SystemTime>,
+) -> Result<(), Response<Body>> {
+    let satisfied = match if_match(state) {
+        Some(if_match) => if_match.matches(current),
+        None => match (if_unmodified_since(state), last_modified) {
+            (Some(since), Some(modified)) => !modified_since(modified, since),
+            _ => true,
+        },
+    };
+
+    if satisfied {
+        return Ok(());
+    }
+
+    let mut res = create_empty_response(state, StatusCode::PRECONDITION_FAILED);
+    if let Some(current) = current {
+        res.headers_mut().insert(ETAG, current.to_header_value());
+    }
+
+    Err(res)
+}
+
+// Whether `modified` is later than `since`, which only has a precision of one second as it came
+// from an HTTP date.
+fn modified_since(modified: SystemTime, since: SystemTime) -> bool {
+    modified
+        .duration_since(since)
+        .map(|d| d >= Duration::from_secs(1))
+        .unwrap_or(false)
+}
+
+// Parses a comma separated list of entity tags, stopping at the first which is malformed.
+fn parse_list(value: &str) -> Vec<EntityTag> {
+    let mut tags = Vec::new();
+    let mut rest = value;
+
+    loop {
+        rest = rest.trim_left_matches(|c: char| c == ',' || c == ' ' || c == '\t');
+        if rest.is_empty() {
+            return tags;
+        }
+
+        let weak = rest.starts_with("W/");
+        if weak {
+            rest = &rest[2..];
+        }
+
+        if !rest.starts_with('"') {
+            return tags;
+        }
+
+        let end = match rest[1..].find('"') {
+            Some(end) => end + 1,
+            None => return tags,
+        };
+
+        let tag = &rest[1..end];
+        if !tag.bytes().all(is_etagc) {
+            return tags;
+        }
+
+        tags.push(EntityTag {
+            weak,
+            tag: tag.to_owned(),
+        });
+        rest = &rest[end + 1..];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use hyper::header::HeaderName;
+
+    use state::set_request_id;
+
+    fn state(pairs: &[(HeaderName, &str)]) -> State {
+        let mut headers = HeaderMap::new();
+        for &(ref name, value) in pairs {
+            headers.append(name.clone(), value.parse().unwrap());
+        }
+
+        let mut state = State::new();
+        state.put(headers);
+        set_request_id(&mut state);
+        state
+    }
+
+    fn date(value: &str) -> SystemTime {
+        parse_http_date(value).unwrap()
+    }
+
+    #[test]
+    fn compares_entity_tags() {
+        let strong = EntityTag::strong("abc");
+        let weak = EntityTag::weak("abc");
+
+        assert!(strong.strong_eq(&EntityTag::strong("abc")));
+        assert!(!strong.strong_eq(&weak));
+        assert!(!weak.strong_eq(&weak));
+        assert!(strong.weak_eq(&weak));
+        assert!(!strong.weak_eq(&EntityTag::strong("xyz")));
+
+        let tag = EntityTag::from_content(b"body");
+        assert!(tag.tag().starts_with("4-"));
+        assert_eq!(tag, EntityTag::from_content(b"body"));
+        assert_ne!(tag, EntityTag::from_content(b"other"));
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid entity tag")]
+    fn rejects_invalid_entity_tags() {
+        EntityTag::strong("a\"b");
+    }
+
+    #[test]
+    fn parses_if_match() {
+        assert_eq!(if_match(&state(&[])), None);
+        assert_eq!(if_match(&state(&[(IF_MATCH, "*")])), Some(IfMatch::Any));
+        assert_eq!(
+            if_match(&state(&[
+                (IF_MATCH, "\"a,b\", W/\"c\""),
+                (IF_MATCH, "\"d\""),
+            ])),
+            Some(IfMatch::Tags(vec![
+                EntityTag::strong("a,b"),
+                EntityTag::weak("c"),
+                EntityTag::strong("d"),
+            ]))
+        );
+        assert_eq!(
+            if_match(&state(&[(IF_MATCH, "\"a\", b")])),
+            Some(IfMatch::Tags(vec![EntityTag::strong("a")]))
+        );
+    }
+
+    #[test]
+    fn evaluates_if_match() {
+        let current = EntityTag::strong("abc");
+        let check = |value: &str, current: Option<&EntityTag>| {
+            check_preconditions(&state(&[(IF_MATCH, value)]), current, None)
+                .map_err(|res| res.status())
+        };
+
+        assert_eq!(check("\"xyz\", \"abc\"", Some(&current)), Ok(()));
+        assert_eq!(check("*", Some(&current)), Ok(()));
+        assert_eq!(
+            check("W/\"abc\"", Some(&current)),
+            Err(StatusCode::PRECONDITION_FAILED)
+        );
+        assert_eq!(check("*", None), Err(StatusCode::PRECONDITION_FAILED));
+        assert!(check_preconditions(&state(&[]), Some(&current), None).is_ok());
+    }
+
+    #[test]
+    fn evaluates_if_unmodified_since() {
+        let modified = date("Sun, 06 Nov 1994 08:49:37 GMT");
+        let check = |pairs: &[(HeaderName, &str)], modified: Option<SystemTime>| {
+            check_preconditions(&state(pairs), None, modified).is_ok()
+        };
+
+        let since = [(IF_UNMODIFIED_SINCE, "Sun, 06 Nov 1994 08:49:37 GMT")];
+        assert!(check(&since, Some(modified)));
+        assert!(check(&since, Some(modified + Duration::from_millis(500))));
+        assert!(!check(&since, Some(modified + Duration::from_secs(1))));
+        assert!(check(&since, None));
+
+        // `If-Match` takes precedence over `If-Unmodified-Since`
+        let both = state(&[
+            (IF_MATCH, "*"),
+            (IF_UNMODIFIED_SINCE, "Sat, 05 Nov 1994 08:49:37 GMT"),
+        ]);
+        let current = EntityTag::strong("abc");
+        assert!(check_preconditions(&both, Some(&current), Some(modified)).is_ok());
+    }
+}
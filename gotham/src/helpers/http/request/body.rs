@@ -0,0 +1,396 @@
+//! Parses request bodies into types implementing `Deserialize`, according to their `Content-Type`.
+
+use futures::{future, Future, Stream};
+use hyper::header::{HeaderMap, CONTENT_TYPE};
+use hyper::{Body, StatusCode};
+use serde::de::DeserializeOwned;
+use serde_json::{self, Value};
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::panic::RefUnwindSafe;
+use std::str;
+use std::sync::Arc;
+
+use extractor::internal::from_query_string_mapping;
+use handler::{HandlerError, IntoHandlerError};
+use helpers::http::request::query_string;
+use state::{FromState, State, StateData};
+
+type Custom =
+    Arc<Fn(&[u8]) -> Result<Value, Box<Error + Send + Sync>> + Send + Sync + RefUnwindSafe>;
+
+#[derive(Clone)]
+enum Parser {
+    Json,
+    Form,
+    Custom(Custom),
+}
+
+/// The parsers used by `parse_body`, by the media type of the request body.
+///
+/// `BodyParsers::new` parses `application/json` and `application/x-www-form-urlencoded` bodies,
+/// and further media types are added with `BodyParsers::with_parser`. A parser for a format such
+/// as MessagePack decodes the body into a `serde_json::Value`, which is then deserialized into the
+/// type requested by the handler.
+///
+/// The parsers are made available to handlers by adding them to `State`, usually with a
+/// `StateMiddleware`. When there are no `BodyParsers` in `State`, `parse_body` uses the parsers of
+/// `BodyParsers::new`.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate gotham;
+/// # extern crate serde_json;
+/// #
+/// # use gotham::helpers::http::request::body::BodyParsers;
+/// # use gotham::middleware::state::StateMiddleware;
+/// # use gotham::pipeline::single_middleware;
+/// # use gotham::pipeline::single::single_pipeline;
+/// #
+/// # fn main() {
+/// // A parser for `text/plain` bodies containing a single JSON value per line.
+/// let parsers = BodyParsers::new().with_parser("text/plain", |body| {
+///     body.split(|b| *b == b'\n')
+///         .filter(|line| !line.is_empty())
+///         .map(serde_json::from_slice)
+///         .collect::<Result<Vec<serde_json::Value>, serde_json::Error>>()
+///         .map(serde_json::Value::Array)
+/// });
+///
+/// let (chain, pipelines) = single_pipeline(single_middleware(StateMiddleware::new(parsers)));
+/// # let _ = (chain, pipelines);
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct BodyParsers {
+    parsers: Arc<Vec<(String, Parser)>>,
+}
+
+impl StateData for BodyParsers {}
+
+impl BodyParsers {
+    /// Creates the parsers for `application/json` and `application/x-www-form-urlencoded` bodies.
+    pub fn new() -> BodyParsers {
+        BodyParsers {
+            parsers: Arc::new(vec![
+                ("application/json".to_owned(), Parser::Json),
+                ("application/x-www-form-urlencoded".to_owned(), Parser::Form),
+            ]),
+        }
+    }
+
+    /// Adds a parser for bodies with the media type `content_type`, such as
+    /// `application/msgpack`, replacing any existing parser for it. Parameters of the
+    /// `Content-Type`, such as `charset`, are ignored when choosing a parser.
+    pub fn with_parser<F, E>(self, content_type: &str, parser: F) -> BodyParsers
+    where
+        F: Fn(&[u8]) -> Result<Value, E> + Send + Sync + RefUnwindSafe + 'static,
+        E: Into<Box<Error + Send + Sync>>,
+    {
+        let custom: Custom = Arc::new(move |body: &[u8]| parser(body).map_err(Into::into));
+        self.with(content_type, Parser::Custom(custom))
+    }
+
+    fn with(self, content_type: &str, parser: Parser) -> BodyParsers {
+        let content_type = content_type.to_lowercase();
+
+        let mut parsers: Vec<_> = self
+            .parsers
+            .iter()
+            .filter(|&&(ref registered, _)| *registered != content_type)
+            .cloned()
+            .collect();
+        parsers.push((content_type, parser));
+
+        BodyParsers {
+            parsers: Arc::new(parsers),
+        }
+    }
+
+    fn find(&self, content_type: &str) -> Option<&Parser> {
+        self.parsers
+            .iter()
+            .find(|&&(ref registered, _)| registered.eq_ignore_ascii_case(content_type))
+            .map(|&(_, ref parser)| parser)
+    }
+}
+
+impl Default for BodyParsers {
+    fn default() -> BodyParsers {
+        BodyParsers::new()
+    }
+}
+
+/// The error returned by `parse_body`, which is converted into a `HandlerError` with the status
+/// given by `BodyError::status`.
+#[derive(Debug)]
+pub enum BodyError {
+    /// There is no parser for the `Content-Type` of the request, or the request has none.
+    UnsupportedMediaType(Option<String>),
+    /// The body could not be read from the connection.
+    Read(::hyper::Error),
+    /// The body could not be parsed into the requested type.
+    Invalid(Box<Error + Send + Sync>),
+}
+
+impl BodyError {
+    /// The status of the response for this error: `415 Unsupported Media Type` when there is no
+    /// parser for the body, or `400 Bad Request` otherwise.
+    pub fn status(&self) -> StatusCode {
+        match *self {
+            BodyError::UnsupportedMediaType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            BodyError::Read(_) | BodyError::Invalid(_) => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+impl Display for BodyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BodyError::UnsupportedMediaType(Some(ref content_type)) => {
+                write!(f, "unsupported request body type: {}", content_type)
+            }
+            BodyError::UnsupportedMediaType(None) => write!(f, "request body has no Content-Type"),
+            BodyError::Read(ref e) => write!(f, "unable to read request body: {}", e),
+            BodyError::Invalid(ref e) => write!(f, "invalid request body: {}", e),
+        }
+    }
+}
+
+impl Error for BodyError {
+    fn description(&self) -> &str {
+        "unable to parse request body"
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            BodyError::Read(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Reads the request body and parses it into `T`, using the parser registered in the
+/// `BodyParsers` in `State` for the `Content-Type` of the request.
+///
+/// When the body can't be parsed, the future fails with a `HandlerError` carrying a `BodyError`:
+/// with the `415 Unsupported Media Type` status when there is no parser for the `Content-Type`, or
+/// with the `400 Bad Request` status when the body is not valid.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate futures;
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// # extern crate mime;
+/// # extern crate serde;
+/// # #[macro_use]
+/// # extern crate serde_derive;
+/// #
+/// # use futures::Future;
+/// # use hyper::StatusCode;
+/// # use gotham::handler::HandlerFuture;
+/// # use gotham::helpers::http::request::body::parse_body;
+/// # use gotham::helpers::http::response::create_response;
+/// # use gotham::router::builder::*;
+/// # use gotham::test::TestServer;
+/// # use gotham::state::State;
+/// #
+/// #[derive(Deserialize)]
+/// struct Signup {
+///     name: String,
+///     age: u8,
+/// }
+///
+/// fn signup(state: State) -> Box<HandlerFuture> {
+///     let f = parse_body(state).map(|(state, signup): (State, Signup)| {
+///         let body = format!("{} is {}", signup.name, signup.age);
+///         let res = create_response(&state, StatusCode::OK, mime::TEXT_PLAIN, body);
+///         (state, res)
+///     });
+///
+///     Box::new(f)
+/// }
+/// #
+/// # fn main() {
+/// #   let test_server = TestServer::new(build_simple_router(|route| {
+/// #       route.post("/signup").to(signup);
+/// #   })).unwrap();
+/// #   let client = test_server.client();
+/// #
+/// #   let response = client
+/// #       .post("http://localhost/signup", r#"{"name":"Ada","age":36}"#, mime::APPLICATION_JSON)
+/// #       .perform()
+/// #       .unwrap();
+/// #   assert_eq!(response.read_utf8_body().unwrap(), "Ada is 36");
+/// #
+/// #   let response = client
+/// #       .post(
+/// #           "http://localhost/signup",
+/// #           "name=Ada&age=36",
+/// #           mime::APPLICATION_WWW_FORM_URLENCODED,
+/// #       )
+/// #       .perform()
+/// #       .unwrap();
+/// #   assert_eq!(response.read_utf8_body().unwrap(), "Ada is 36");
+/// #
+/// #   let response = client
+/// #       .post("http://localhost/signup", "Ada,36", mime::TEXT_CSV)
+/// #       .perform()
+/// #       .unwrap();
+/// #   assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+/// # }
+/// ```
+pub fn parse_body<T>(
+    mut state: State,
+) -> Box<Future<Item = (State, T), Error = (State, HandlerError)> + Send>
+where
+    T: DeserializeOwned + Send + 'static,
+{
+    let content_type = HeaderMap::borrow_from(&state)
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .map(|value| value.trim().to_owned());
+
+    let parser = {
+        let parser = match BodyParsers::try_borrow_from(&state) {
+            Some(parsers) => content_type.as_ref().and_then(|c| parsers.find(c).cloned()),
+            None => content_type
+                .as_ref()
+                .and_then(|c| BodyParsers::new().find(c).cloned()),
+        };
+
+        match parser {
+            Some(parser) => parser,
+            None => {
+                let err = BodyError::UnsupportedMediaType(content_type);
+                return Box::new(future::err((state, into_handler_error(err))));
+            }
+        }
+    };
+
+    let f = Body::take_from(&mut state).concat2().then(move |body| {
+        let parsed = body
+            .map_err(BodyError::Read)
+            .and_then(|body| parse(&parser, &body).map_err(BodyError::Invalid));
+
+        match parsed {
+            Ok(value) => Ok((state, value)),
+            Err(err) => Err((state, into_handler_error(err))),
+        }
+    });
+
+    Box::new(f)
+}
+
+fn parse<T>(parser: &Parser, body: &[u8]) -> Result<T, Box<Error + Send + Sync>>
+where
+    T: DeserializeOwned,
+{
+    match *parser {
+        Parser::Json => Ok(serde_json::from_slice(body)?),
+        Parser::Form => {
+            let mapping = query_string::split(Some(str::from_utf8(body)?));
+            Ok(from_query_string_mapping(&mapping)?)
+        }
+        Parser::Custom(ref parser) => Ok(serde_json::from_value(parser(body)?)?),
+    }
+}
+
+fn into_handler_error(err: BodyError) -> HandlerError {
+    let status = err.status();
+    err.into_handler_error().with_status(status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use hyper::{Response, StatusCode};
+    use mime;
+
+    use handler::HandlerFuture;
+    use middleware::state::StateMiddleware;
+    use pipeline::single::single_pipeline;
+    use pipeline::single_middleware;
+    use router::builder::{build_router, DefineSingleRoute, DrawRoutes};
+    use test::TestServer;
+
+    #[derive(Deserialize)]
+    struct Point {
+        x: i32,
+        y: Option<i32>,
+    }
+
+    fn handler(state: State) -> Box<HandlerFuture> {
+        let f = parse_body(state).map(|(state, point): (State, Point)| {
+            let body = format!("{} {:?}", point.x, point.y);
+            (state, Response::new(Body::from(body)))
+        });
+
+        Box::new(f)
+    }
+
+    fn test_server() -> TestServer {
+        let parsers = BodyParsers::new().with_parser("text/plain", |body| {
+            let mut parts = str::from_utf8(body)?.splitn(2, ',');
+            let x = parts.next().unwrap_or("").parse::<i32>()?;
+            let y = parts.next().unwrap_or("").parse::<i32>()?;
+            Ok::<_, Box<Error + Send + Sync>>(json!({ "x": x, "y": y }))
+        });
+
+        let (chain, pipelines) = single_pipeline(single_middleware(StateMiddleware::new(parsers)));
+        TestServer::new(build_router(chain, pipelines, |route| {
+            route.post("/").to(handler);
+        }))
+        .unwrap()
+    }
+
+    fn post(body: &'static str, content_type: &str) -> (StatusCode, String) {
+        let response = test_server()
+            .client()
+            .post(
+                "http://localhost/",
+                body,
+                content_type.parse::<mime::Mime>().unwrap(),
+            )
+            .perform()
+            .unwrap();
+
+        (response.status(), response.read_utf8_body().unwrap())
+    }
+
+    #[test]
+    fn parses_by_content_type() {
+        let ok = |body: &str| (StatusCode::OK, body.to_owned());
+
+        assert_eq!(post(r#"{"x":1}"#, "application/json"), ok("1 None"));
+        assert_eq!(
+            post(r#"{"x":1,"y":2}"#, "Application/JSON; charset=utf-8"),
+            ok("1 Some(2)")
+        );
+        assert_eq!(
+            post("x=1&y=2", "application/x-www-form-urlencoded"),
+            ok("1 Some(2)")
+        );
+        assert_eq!(post("3,4", "text/plain; charset=utf-8"), ok("3 Some(4)"));
+    }
+
+    #[test]
+    fn rejects_unparseable_bodies() {
+        let (status, _) = post("x=1", "text/csv");
+        assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+        let (status, _) = post(r#"{"y":1}"#, "application/json");
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (status, _) = post("x=a", "application/x-www-form-urlencoded");
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (status, _) = post("3;4", "text/plain");
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+}
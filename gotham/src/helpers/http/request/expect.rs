@@ -0,0 +1,188 @@
+//! Helpers for requests which carry an `Expect` header, such as `Expect: 100-continue`.
+//!
+//! A client sending `Expect: 100-continue` waits for a `100 Continue` interim response before
+//! sending the body of its request, so that the server can reject a large upload without it being
+//! transmitted. The server sends `100 Continue` the first time the request `Body` is read, so a
+//! handler defers the transmission of the body until it opts in by reading it, and can respond
+//! without reading it to reject the request. A `RequestBodyLimit` rejects requests which declare
+//! an oversized body in this way.
+//!
+//! Response trailers are not supported, since hyper only sends them over HTTP/2 and the response
+//! `Body` provides no way to set them.
+
+use hyper::header::{HeaderMap, EXPECT};
+use hyper::{Body, Response, StatusCode};
+
+use helpers::http::response::create_empty_response;
+use state::{FromState, State};
+
+/// Determines whether the client is waiting for a `100 Continue` response before sending the
+/// body of its request.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate futures;
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// #
+/// # use futures::{future, Future, Stream};
+/// # use hyper::{Body, Response, StatusCode};
+/// # use gotham::handler::{HandlerFuture, IntoHandlerError};
+/// # use gotham::helpers::http::request::expect::expects_continue;
+/// # use gotham::helpers::http::response::create_empty_response;
+/// # use gotham::router::builder::*;
+/// # use gotham::state::{FromState, State};
+/// # use gotham::test::TestServer;
+/// #
+/// # fn authorized(_state: &State) -> bool {
+/// #   false
+/// # }
+/// #
+/// fn upload(mut state: State) -> Box<HandlerFuture> {
+///     // Rejecting the request without reading the body means that it is never sent, when the
+///     // client is waiting for `100 Continue`.
+///     if expects_continue(&state) && !authorized(&state) {
+///         let res = create_empty_response(&state, StatusCode::FORBIDDEN);
+///         return Box::new(future::ok((state, res)));
+///     }
+///
+///     // Reading the body sends `100 Continue`, if the client is waiting for it.
+///     let f = Body::take_from(&mut state)
+///         .concat2()
+///         .then(|body| match body {
+///             Ok(body) => {
+///                 let res = Response::new(Body::from(format!("{} bytes", body.len())));
+///                 future::ok((state, res))
+///             }
+///             Err(e) => future::err((state, e.into_handler_error())),
+///         });
+///
+///     Box::new(f)
+/// }
+/// #
+/// # fn main() {
+/// #   let test_server = TestServer::new(build_simple_router(|route| {
+/// #       route.post("/upload").to(upload);
+/// #   })).unwrap();
+/// #
+/// #   let response = test_server
+/// #       .send_raw(
+/// #           b"POST /upload HTTP/1.1\r\nHost: localhost\r\nExpect: 100-continue\r\n\
+/// #             Content-Length: 5\r\n\r\nhello",
+/// #       )
+/// #       .unwrap();
+/// #   assert_eq!(response.status(), Some(StatusCode::FORBIDDEN));
+/// # }
+/// ```
+pub fn expects_continue(state: &State) -> bool {
+    expectations(state).any(|e| e.eq_ignore_ascii_case("100-continue"))
+}
+
+/// Creates a `417 Expectation Failed` response when the `Expect` header of the request lists
+/// anything other than `100-continue`, which is the only expectation defined by RFC 7231. The
+/// response should be sent instead of performing the request.
+pub fn unsupported_expectation(state: &State) -> Option<Response<Body>> {
+    if expectations(state).all(|e| e.eq_ignore_ascii_case("100-continue")) {
+        return None;
+    }
+
+    Some(create_empty_response(state, StatusCode::EXPECTATION_FAILED))
+}
+
+// The expectations listed in the `Expect` headers of the request.
+fn expectations<'a>(state: &'a State) -> Box<Iterator<Item = &'a str> + 'a> {
+    match HeaderMap::try_borrow_from(state) {
+        Some(headers) => Box::new(
+            headers
+                .get_all(EXPECT)
+                .iter()
+                .map(|value| value.to_str().unwrap_or("?"))
+                .flat_map(|value| value.split(','))
+                .map(|e| e.trim())
+                .filter(|e| !e.is_empty()),
+        ),
+        None => Box::new(None.into_iter()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::{future, Future, Stream};
+    use hyper::header::HeaderValue;
+
+    use handler::{HandlerFuture, IntoHandlerError};
+    use router::builder::{build_simple_router, DefineSingleRoute, DrawRoutes};
+    use state::set_request_id;
+    use test::TestServer;
+
+    fn state(expect: &[&'static str]) -> State {
+        let mut headers = HeaderMap::new();
+        for value in expect {
+            headers.append(EXPECT, HeaderValue::from_static(*value));
+        }
+
+        let mut state = State::new();
+        state.put(headers);
+        set_request_id(&mut state);
+        state
+    }
+
+    #[test]
+    fn detects_expectations() {
+        assert!(!expects_continue(&state(&[])));
+        assert!(expects_continue(&state(&["100-Continue"])));
+        assert!(unsupported_expectation(&state(&[])).is_none());
+        assert!(unsupported_expectation(&state(&["100-continue"])).is_none());
+
+        let state = state(&["100-continue, teapot"]);
+        assert!(expects_continue(&state));
+        assert_eq!(
+            unsupported_expectation(&state).unwrap().status(),
+            StatusCode::EXPECTATION_FAILED
+        );
+    }
+
+    #[test]
+    fn continues_when_body_is_read() {
+        fn read(mut state: State) -> Box<HandlerFuture> {
+            let f = Body::take_from(&mut state)
+                .concat2()
+                .then(|body| match body {
+                    Ok(body) => future::ok((state, Response::new(Body::from(body)))),
+                    Err(e) => future::err((state, e.into_handler_error())),
+                });
+
+            Box::new(f)
+        }
+
+        fn reject(state: State) -> (State, Response<Body>) {
+            let res = create_empty_response(&state, StatusCode::PAYLOAD_TOO_LARGE);
+            (state, res)
+        }
+
+        let test_server = TestServer::new(build_simple_router(|route| {
+            route.post("/read").to(read);
+            route.post("/reject").to(reject);
+        }))
+        .unwrap();
+
+        let request = |path: &str| {
+            let request = format!(
+                "POST {} HTTP/1.1\r\nHost: localhost\r\nExpect: 100-continue\r\n\
+                 Content-Length: 5\r\n\r\nhello",
+                path
+            );
+            test_server.send_raw(request.as_bytes()).unwrap()
+        };
+
+        let response = request("/read");
+        assert_eq!(response.status(), Some(StatusCode::CONTINUE));
+        assert!(response.bytes().ends_with(b"\r\n\r\nhello"));
+
+        let response = request("/reject");
+        assert_eq!(response.status(), Some(StatusCode::PAYLOAD_TOO_LARGE));
+    }
+}
@@ -1,4 +1,6 @@
 //! Helpers for HTTP request handling
 
+pub mod body;
+pub mod expect;
 pub mod path;
 pub mod query_string;
@@ -1,6 +1,7 @@
 //! Helpers for HTTP response generation
 
-use hyper::header::{CONTENT_TYPE, LOCATION};
+use hyper::body::Payload;
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE, LOCATION};
 use hyper::{Body, Method, Response, StatusCode};
 use mime::Mime;
 use std::borrow::Cow;
@@ -74,9 +75,13 @@ where
     res.headers_mut()
         .insert(CONTENT_TYPE, mime.as_ref().parse().unwrap());
 
-    // add the body on non-HEAD requests
+    // add the body on non-HEAD requests, otherwise only advertise its length
+    let body = body.into();
     if Method::borrow_from(state) != Method::HEAD {
-        *res.body_mut() = body.into();
+        *res.body_mut() = body;
+    } else if let Some(len) = body.content_length() {
+        res.headers_mut()
+            .insert(CONTENT_LENGTH, len.to_string().parse().unwrap());
     }
 
     res
@@ -125,7 +130,7 @@ pub fn create_empty_response(state: &State, status: StatusCode) -> Response<Body
     built.expect("Response built from a compatible type")
 }
 
-/// Produces a simple empty `Response` with a `Location` header and a 301
+/// Produces a simple empty `Response` with a `Location` header and a 308
 /// status.
 ///
 /// # Examples
@@ -163,13 +168,10 @@ pub fn create_permanent_redirect<L: Into<Cow<'static, str>>>(
     state: &State,
     location: L,
 ) -> Response<Body> {
-    let mut res = create_empty_response(state, StatusCode::PERMANENT_REDIRECT);
-    res.headers_mut()
-        .insert(LOCATION, location.into().to_string().parse().unwrap());
-    res
+    create_redirect(state, StatusCode::PERMANENT_REDIRECT, location)
 }
 
-/// Produces a simple empty `Response` with a `Location` header and a 302
+/// Produces a simple empty `Response` with a `Location` header and a 307
 /// status.
 ///
 /// # Examples
@@ -207,7 +209,49 @@ pub fn create_temporary_redirect<L: Into<Cow<'static, str>>>(
     state: &State,
     location: L,
 ) -> Response<Body> {
-    let mut res = create_empty_response(state, StatusCode::TEMPORARY_REDIRECT);
+    create_redirect(state, StatusCode::TEMPORARY_REDIRECT, location)
+}
+
+/// Produces a simple empty `Response` with a `Location` header and the given status, such as
+/// `301 Moved Permanently` or `303 See Other`.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// #
+/// # use hyper::{Body, Response, StatusCode};
+/// # use gotham::state::State;
+/// # use gotham::helpers::http::response::create_redirect;
+/// # use gotham::test::TestServer;
+/// # use hyper::header::LOCATION;
+/// fn handler(state: State) -> (State, Response<Body>) {
+///     let resp = create_redirect(&state, StatusCode::SEE_OTHER, "/created");
+///
+///     (state, resp)
+/// }
+/// # fn main() {
+/// #     let test_server = TestServer::new(|| Ok(handler)).unwrap();
+/// #     let response = test_server
+/// #         .client()
+/// #         .get("http://example.com/")
+/// #         .perform()
+/// #         .unwrap();
+/// #
+/// #     assert_eq!(response.status(), StatusCode::SEE_OTHER);
+/// #     assert_eq!(
+/// #         response.headers().get(LOCATION).unwrap(),
+/// #         "/created"
+/// #     );
+/// # }
+/// ```
+pub fn create_redirect<L: Into<Cow<'static, str>>>(
+    state: &State,
+    status: StatusCode,
+    location: L,
+) -> Response<Body> {
+    let mut res = create_empty_response(state, status);
     res.headers_mut()
         .insert(LOCATION, location.into().to_string().parse().unwrap());
     res
@@ -1,4 +1,6 @@
 //! Helpers, e.g. for HTTP request handling and response generation
 
+pub mod clock;
 pub mod http;
+pub mod random;
 pub(crate) mod timing;
@@ -0,0 +1,135 @@
+//! Defines the `RandomSource` which Gotham reads random bytes from, so that tests can make
+//! generated values such as session identifiers reproducible.
+//!
+//! The source is set for a server with `ServerBuilder::with_random_source`, and is used to
+//! generate session identifiers. Without a source, each use has its own secure random number
+//! generator. A `SeededRandom` source is predictable, and must only be used for testing.
+//!
+//! # Examples
+//!
+//! ```rust
+//! # extern crate gotham;
+//! # extern crate hyper;
+//! #
+//! # use hyper::{Body, Response};
+//! # use gotham::helpers::random::{self, SeededRandom};
+//! # use gotham::state::State;
+//! # use gotham::test::TestServer;
+//! # use gotham::ServerBuilder;
+//! #
+//! fn handler(state: State) -> (State, Response<Body>) {
+//!     let mut bytes = [0u8; 4];
+//!     random::fill_bytes(&state, &mut bytes);
+//!     (state, Response::new(Body::from(format!("{:?}", bytes))))
+//! }
+//!
+//! fn first_response(seed: u64) -> String {
+//!     let builder = ServerBuilder::new().with_random_source(SeededRandom::new(seed));
+//!     let test_server = TestServer::with_server_builder(builder, || Ok(handler)).unwrap();
+//!     let response = test_server.client().get("/").perform().unwrap();
+//!     response.read_utf8_body().unwrap()
+//! }
+//!
+//! # fn main() {
+//! assert_eq!(first_response(42), first_response(42));
+//! # }
+//! ```
+
+use std::panic::RefUnwindSafe;
+use std::sync::{Arc, Mutex};
+
+use rand::prng::ChaChaRng;
+use rand::{self, RngCore, SeedableRng};
+
+use state::{FromState, State, StateData};
+
+/// A source of random bytes.
+pub trait RandomSource: Send + Sync + RefUnwindSafe {
+    /// Fills `dest` with random bytes.
+    fn fill_bytes(&self, dest: &mut [u8]);
+}
+
+/// A `RandomSource` which produces the same sequence of bytes each time it is created with the
+/// same seed. Clones of a `SeededRandom` share the same sequence.
+#[derive(Clone)]
+pub struct SeededRandom {
+    rng: Arc<Mutex<ChaChaRng>>,
+}
+
+impl SeededRandom {
+    /// Creates a `SeededRandom` from `seed`.
+    pub fn new(seed: u64) -> SeededRandom {
+        let mut bytes = [0u8; 32];
+        for (i, byte) in bytes.iter_mut().take(8).enumerate() {
+            *byte = (seed >> (i * 8)) as u8;
+        }
+
+        SeededRandom {
+            rng: Arc::new(Mutex::new(ChaChaRng::from_seed(bytes))),
+        }
+    }
+}
+
+impl RandomSource for SeededRandom {
+    fn fill_bytes(&self, dest: &mut [u8]) {
+        self.rng.lock().unwrap().fill_bytes(dest)
+    }
+}
+
+// The `RandomSource` for the request, when one has been set for the server.
+struct RequestRandomSource(Arc<RandomSource>);
+
+impl StateData for RequestRandomSource {}
+
+/// Puts the `RandomSource` for the request into `state`.
+pub(crate) fn put_random_source(state: &mut State, source: Arc<RandomSource>) {
+    state.put(RequestRandomSource(source));
+}
+
+/// Returns the `RandomSource` for the request, if one has been set for the server.
+pub(crate) fn random_source(state: &State) -> Option<Arc<RandomSource>> {
+    RequestRandomSource::try_borrow_from(state).map(|source| source.0.clone())
+}
+
+/// Fills `dest` with bytes from the `RandomSource` for the request, or from a secure random
+/// number generator when none has been set.
+pub fn fill_bytes(state: &State, dest: &mut [u8]) {
+    match RequestRandomSource::try_borrow_from(state) {
+        Some(source) => source.0.fill_bytes(dest),
+        None => rand::thread_rng().fill_bytes(dest),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bytes(source: &RandomSource) -> [u8; 16] {
+        let mut bytes = [0u8; 16];
+        source.fill_bytes(&mut bytes);
+        bytes
+    }
+
+    #[test]
+    fn seeded_sources_repeat() {
+        let a = SeededRandom::new(7);
+        let b = SeededRandom::new(7);
+
+        let first = bytes(&a);
+        assert_eq!(first, bytes(&b));
+        assert_ne!(first, bytes(&a));
+        assert_ne!(bytes(&SeededRandom::new(8)), bytes(&SeededRandom::new(7)));
+    }
+
+    #[test]
+    fn reads_source_from_state() {
+        let mut state = State::new();
+        assert!(random_source(&state).is_none());
+
+        put_random_source(&mut state, Arc::new(SeededRandom::new(1)));
+
+        let mut from_state = [0u8; 16];
+        fill_bytes(&state, &mut from_state);
+        assert_eq!(from_state, bytes(&SeededRandom::new(1)));
+    }
+}
@@ -2,6 +2,9 @@
 use chrono::prelude::*;
 use std::fmt::{self, Display, Formatter};
 
+use helpers::clock;
+use state::State;
+
 /// Timer struct used to record execution times of requests.
 ///
 /// The `elapsed` function returns the elapsed time in an easy to format way,
@@ -17,11 +20,26 @@ impl Timer {
         Timer { start: Utc::now() }
     }
 
+    /// Begins measuring from the current time of the `Clock` for the request.
+    pub fn for_request(state: &State) -> Timer {
+        Timer {
+            start: clock::system_time(state).into(),
+        }
+    }
+
     /// Finishes measuring, and returns the elapsed time as a `Timing` value.
     pub fn elapsed(&self) -> Timing {
-        let duration = Utc::now()
-            .signed_duration_since(self.start)
-            .num_microseconds();
+        self.elapsed_until(Utc::now())
+    }
+
+    /// Finishes measuring at the current time of the `Clock` for the request, and returns the
+    /// elapsed time as a `Timing` value.
+    pub fn elapsed_for_request(&self, state: &State) -> Timing {
+        self.elapsed_until(clock::system_time(state).into())
+    }
+
+    fn elapsed_until(&self, end: DateTime<Utc>) -> Timing {
+        let duration = end.signed_duration_since(self.start).num_microseconds();
 
         match duration {
             Some(dur) => Timing::Microseconds(dur),
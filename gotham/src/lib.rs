@@ -15,54 +15,82 @@
 extern crate base64;
 extern crate bincode;
 extern crate borrow_bag;
+extern crate brotli;
 extern crate bytes;
 extern crate chrono;
 extern crate cookie;
 extern crate failure;
+extern crate flate2;
 #[macro_use]
 extern crate futures;
+extern crate futures_cpupool;
+#[cfg(feature = "derive")]
+extern crate gotham_derive;
 extern crate http;
 extern crate hyper;
+extern crate jsonwebtoken;
 extern crate linked_hash_map;
 #[macro_use]
 extern crate log;
 extern crate mime;
 extern crate mime_guess;
 extern crate mio;
+extern crate net2;
 extern crate num_cpus;
 extern crate rand;
 extern crate regex;
+extern crate ring;
 #[macro_use]
 extern crate serde;
+#[macro_use]
+extern crate serde_json;
 extern crate httpdate;
+#[cfg(feature = "templates")]
+extern crate tera;
 extern crate tokio;
+extern crate tokio_signal;
+extern crate toml;
 extern crate url;
 extern crate uuid;
 #[macro_use]
 extern crate serde_derive;
 
+// Defines the `request_*!` logging macros, so must come before the modules which use them.
+#[macro_use]
+pub mod logging;
+
+pub mod client;
+pub mod config;
+pub mod cookies;
 pub mod error;
+pub mod events;
 pub mod extractor;
 pub mod handler;
+pub mod health;
 pub mod helpers;
 pub mod middleware;
+pub mod openapi;
 pub mod pipeline;
+pub mod reporting;
+pub mod resilience;
 pub mod router;
+pub mod server;
 mod service;
 pub mod state;
+pub mod task;
+#[cfg(feature = "templates")]
+pub mod templates;
 pub mod test;
 
-use std::net::{SocketAddr, ToSocketAddrs};
-use std::sync::Arc;
+use std::net::ToSocketAddrs;
 
-use futures::{Future, Stream};
-use hyper::server::conn::Http;
-use tokio::executor;
-use tokio::net::TcpListener;
-use tokio::runtime::{self, Runtime, TaskExecutor};
+use futures::Future;
+use tokio::runtime::TaskExecutor;
 
 use handler::NewHandler;
-use service::GothamService;
+
+pub use server::ServerBuilder;
+pub use task::spawn;
 
 /// Starts a Gotham application with the default number of threads.
 pub fn start<NH, A>(addr: A, new_handler: NH)
@@ -70,7 +98,7 @@ where
     NH: NewHandler + 'static,
     A: ToSocketAddrs + 'static,
 {
-    start_with_num_threads(addr, new_handler, num_cpus::get())
+    ServerBuilder::new().start(addr, new_handler)
 }
 
 /// Starts a Gotham application with a designated number of threads.
@@ -79,9 +107,9 @@ where
     NH: NewHandler + 'static,
     A: ToSocketAddrs + 'static,
 {
-    let runtime = new_runtime(threads);
-    start_on_executor(addr, new_handler, runtime.executor());
-    runtime.shutdown_on_idle().wait().unwrap();
+    ServerBuilder::new()
+        .with_threads(threads)
+        .start(addr, new_handler)
 }
 
 /// Starts a Gotham application with a designated backing `TaskExecutor`.
@@ -92,7 +120,7 @@ where
     NH: NewHandler + 'static,
     A: ToSocketAddrs + 'static,
 {
-    executor.spawn(init_server(addr, new_handler));
+    ServerBuilder::new().start_on_executor(addr, new_handler, executor)
 }
 
 /// Returns a `Future` used to spawn an Gotham application.
@@ -105,56 +133,5 @@ where
     NH: NewHandler + 'static,
     A: ToSocketAddrs + 'static,
 {
-    let (listener, addr) = tcp_listener(addr);
-
-    info!(
-        target: "gotham::start",
-        " Gotham listening on http://{}",
-        addr
-    );
-
-    bind_server(listener, new_handler)
-}
-
-fn bind_server<NH>(listener: TcpListener, new_handler: NH) -> impl Future<Item = (), Error = ()>
-where
-    NH: NewHandler + 'static,
-{
-    let protocol = Arc::new(Http::new());
-    let gotham_service = GothamService::new(new_handler);
-
-    listener
-        .incoming()
-        .map_err(|e| panic!("socket error = {:?}", e))
-        .for_each(move |socket| {
-            let service = gotham_service.connect(socket.peer_addr().unwrap());
-            let handler = protocol.serve_connection(socket, service).then(|_| Ok(()));
-
-            executor::spawn(handler);
-
-            Ok(())
-        })
-}
-
-fn new_runtime(threads: usize) -> Runtime {
-    runtime::Builder::new()
-        .core_threads(threads)
-        .name_prefix("gotham-worker-")
-        .build()
-        .unwrap()
-}
-
-fn tcp_listener<A>(addr: A) -> (TcpListener, SocketAddr)
-where
-    A: ToSocketAddrs + 'static,
-{
-    let addr = match addr.to_socket_addrs().map(|ref mut i| i.next()) {
-        Ok(Some(a)) => a,
-        Ok(_) => panic!("unable to resolve listener address"),
-        Err(_) => panic!("unable to parse listener address"),
-    };
-
-    let listener = TcpListener::bind(&addr).expect("unable to open TCP listener");
-
-    (listener, addr)
+    ServerBuilder::new().init(addr, new_handler)
 }
@@ -0,0 +1,350 @@
+//! Defines logging for the processing of a request, which attaches the context of the request to
+//! each log line.
+//!
+//! The `request_trace!`, `request_debug!`, `request_info!`, `request_warn!` and `request_error!`
+//! macros are used in the same way as the macros of the `log` crate, with the `State` of the
+//! request as an extra first argument. Each log line is prefixed by the `RequestContext`, which
+//! contains the request ID, and the route template and session identifier once they are known.
+//!
+//! Lines are written to the logger given to `ServerBuilder::with_logger`, or to the global logger
+//! of the `log` crate when none was given.
+//!
+//! # Examples
+//!
+//! ```rust
+//! # #[macro_use]
+//! # extern crate gotham;
+//! # extern crate hyper;
+//! #
+//! # use hyper::{Body, Response, StatusCode};
+//! # use gotham::helpers::http::response::create_empty_response;
+//! # use gotham::state::State;
+//! # use gotham::test::TestServer;
+//! #
+//! fn handler(state: State) -> (State, Response<Body>) {
+//!     // Logged as "[<request id>] looking up user 42".
+//!     request_debug!(&state, "looking up user {}", 42);
+//!
+//!     let res = create_empty_response(&state, StatusCode::NO_CONTENT);
+//!     (state, res)
+//! }
+//! #
+//! # fn main() {
+//! #   let test_server = TestServer::new(|| Ok(handler)).unwrap();
+//! #   let response = test_server
+//! #       .client()
+//! #       .get("http://localhost/")
+//! #       .perform()
+//! #       .unwrap();
+//! #   assert_eq!(response.status(), StatusCode::NO_CONTENT);
+//! # }
+//! ```
+
+use std::fmt;
+use std::sync::Arc;
+
+use log::{self, Log, Metadata, Record};
+
+use middleware::session::SessionIdentifier;
+use router::description::RouteTemplate;
+use state::request_id::RequestId;
+use state::{FromState, State, StateData};
+
+pub use log::Level;
+
+/// The context of a request, which is attached to each line logged while processing it.
+///
+/// Displayed as the request ID, followed by the route template, route name and session identifier
+/// when they are present in `State`, such as `[3f1c..] route=/users/:id route_name=user
+/// session=u0G6..`.
+pub struct RequestContext<'a> {
+    request_id: Option<&'a str>,
+    route: Option<&'a str>,
+    route_name: Option<&'a str>,
+    session: Option<&'a str>,
+}
+
+impl<'a> RequestContext<'a> {
+    /// Gathers the context of the request from `State`.
+    pub fn from_state(state: &'a State) -> RequestContext<'a> {
+        let template = RouteTemplate::try_borrow_from(state);
+
+        RequestContext {
+            request_id: RequestId::try_borrow_from(state).map(RequestId::as_str),
+            route: template.map(RouteTemplate::as_str),
+            route_name: template.and_then(RouteTemplate::name),
+            session: SessionIdentifier::try_borrow_from(state).map(|id| id.value.as_str()),
+        }
+    }
+
+    /// The ID of the request.
+    pub fn request_id(&self) -> Option<&'a str> {
+        self.request_id
+    }
+
+    /// The path template of the route which matched the request, once it has been routed.
+    pub fn route(&self) -> Option<&'a str> {
+        self.route
+    }
+
+    /// The name of the route which matched the request, if it was named.
+    pub fn route_name(&self) -> Option<&'a str> {
+        self.route_name
+    }
+
+    /// The identifier of the session, once the session has been loaded by `SessionMiddleware`.
+    pub fn session(&self) -> Option<&'a str> {
+        self.session
+    }
+}
+
+impl<'a> fmt::Display for RequestContext<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{}]", self.request_id.unwrap_or("-"))?;
+
+        if let Some(route) = self.route {
+            write!(f, " route={}", route)?;
+        }
+
+        if let Some(route_name) = self.route_name {
+            write!(f, " route_name={}", route_name)?;
+        }
+
+        if let Some(session) = self.session {
+            write!(f, " session={}", session)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The logger which lines about a request are written to, placed into `State` when the
+/// application was given a logger by `ServerBuilder::with_logger`.
+#[derive(Clone)]
+pub(crate) struct RequestLogger {
+    logger: Arc<Log>,
+}
+
+impl RequestLogger {
+    pub(crate) fn new(logger: Arc<Log>) -> RequestLogger {
+        RequestLogger { logger }
+    }
+}
+
+impl StateData for RequestLogger {}
+
+/// Writes a log line about the request to the application's logger. Used by the `request_*!`
+/// macros, which should be preferred.
+#[doc(hidden)]
+pub fn __log(
+    state: &State,
+    level: Level,
+    target: &'static str,
+    file: &'static str,
+    line: u32,
+    args: fmt::Arguments,
+) {
+    let logger: &Log = match RequestLogger::try_borrow_from(state) {
+        Some(request_logger) => &*request_logger.logger,
+        None if level <= log::max_level() => log::logger(),
+        None => return,
+    };
+
+    let metadata = Metadata::builder().level(level).target(target).build();
+    if !logger.enabled(&metadata) {
+        return;
+    }
+
+    logger.log(
+        &Record::builder()
+            .metadata(metadata)
+            .args(format_args!(
+                "{} {}",
+                RequestContext::from_state(state),
+                args
+            ))
+            .module_path(Some(target))
+            .file(Some(file))
+            .line(Some(line))
+            .build(),
+    );
+}
+
+/// Logs a message about a request at the given `Level`, with the context of the request.
+///
+/// ```rust
+/// # #[macro_use]
+/// # extern crate gotham;
+/// #
+/// # use gotham::logging::Level;
+/// # use gotham::state::State;
+/// #
+/// # fn main() {
+/// # State::with_new(|state| {
+/// let attempts = 3;
+/// request_log!(state, Level::Warn, "retrying after {} attempts", attempts);
+/// # });
+/// # }
+/// ```
+#[macro_export]
+macro_rules! request_log {
+    ($state:expr, $lvl:expr, $($arg:tt)+) => {
+        $crate::logging::__log(
+            $state,
+            $lvl,
+            module_path!(),
+            file!(),
+            line!(),
+            format_args!($($arg)+),
+        )
+    };
+}
+
+/// Logs a message about a request at the trace level. See `request_log!`.
+#[macro_export]
+macro_rules! request_trace {
+    ($state:expr, $($arg:tt)+) => {
+        $crate::logging::__log(
+            $state,
+            $crate::logging::Level::Trace,
+            module_path!(),
+            file!(),
+            line!(),
+            format_args!($($arg)+),
+        )
+    };
+}
+
+/// Logs a message about a request at the debug level. See `request_log!`.
+#[macro_export]
+macro_rules! request_debug {
+    ($state:expr, $($arg:tt)+) => {
+        $crate::logging::__log(
+            $state,
+            $crate::logging::Level::Debug,
+            module_path!(),
+            file!(),
+            line!(),
+            format_args!($($arg)+),
+        )
+    };
+}
+
+/// Logs a message about a request at the info level. See `request_log!`.
+#[macro_export]
+macro_rules! request_info {
+    ($state:expr, $($arg:tt)+) => {
+        $crate::logging::__log(
+            $state,
+            $crate::logging::Level::Info,
+            module_path!(),
+            file!(),
+            line!(),
+            format_args!($($arg)+),
+        )
+    };
+}
+
+/// Logs a message about a request at the warn level. See `request_log!`.
+#[macro_export]
+macro_rules! request_warn {
+    ($state:expr, $($arg:tt)+) => {
+        $crate::logging::__log(
+            $state,
+            $crate::logging::Level::Warn,
+            module_path!(),
+            file!(),
+            line!(),
+            format_args!($($arg)+),
+        )
+    };
+}
+
+/// Logs a message about a request at the error level. See `request_log!`.
+#[macro_export]
+macro_rules! request_error {
+    ($state:expr, $($arg:tt)+) => {
+        $crate::logging::__log(
+            $state,
+            $crate::logging::Level::Error,
+            module_path!(),
+            file!(),
+            line!(),
+            format_args!($($arg)+),
+        )
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Mutex;
+
+    use log::LevelFilter;
+
+    use middleware::session::SessionIdentifier;
+    use state::set_request_id;
+
+    struct Capture {
+        level: LevelFilter,
+        lines: Mutex<Vec<String>>,
+    }
+
+    impl Log for Capture {
+        fn enabled(&self, metadata: &Metadata) -> bool {
+            metadata.level() <= self.level
+        }
+
+        fn log(&self, record: &Record) {
+            self.lines
+                .lock()
+                .unwrap()
+                .push(format!("{} {}", record.level(), record.args()));
+        }
+
+        fn flush(&self) {}
+    }
+
+    #[test]
+    fn attaches_context() {
+        let capture = Arc::new(Capture {
+            level: LevelFilter::Debug,
+            lines: Mutex::new(Vec::new()),
+        });
+
+        let mut state = State::new();
+        state.put(RequestLogger::new(capture.clone()));
+        state.put(::hyper::HeaderMap::new());
+        let id = set_request_id(&mut state).to_owned();
+
+        request_debug!(&state, "received {}", 1);
+
+        state.put(RouteTemplate::new("/users/:id", Some("user")));
+        state.put(SessionIdentifier {
+            value: "abc".to_owned(),
+        });
+
+        request_warn!(&state, "received {}", 2);
+        request_trace!(&state, "not enabled");
+
+        let lines = capture.lines.lock().unwrap();
+        assert_eq!(
+            *lines,
+            vec![
+                format!("DEBUG [{}] received 1", id),
+                format!(
+                    "WARN [{}] route=/users/:id route_name=user session=abc received 2",
+                    id
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn context_without_request_id() {
+        State::with_new(|state| {
+            assert_eq!(RequestContext::from_state(state).to_string(), "[-]");
+        });
+    }
+}
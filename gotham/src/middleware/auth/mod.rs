@@ -0,0 +1,470 @@
+//! Authentication middleware, supporting HTTP Basic credentials and Bearer tokens.
+use base64;
+use futures::future;
+use hyper::header::{HeaderMap, HeaderValue, AUTHORIZATION, WWW_AUTHENTICATE};
+use hyper::StatusCode;
+use jsonwebtoken::{self, Validation};
+use serde::de::DeserializeOwned;
+use serde_json::{self, Value};
+use std::io;
+use std::marker::PhantomData;
+use std::panic::RefUnwindSafe;
+use std::sync::Arc;
+
+use handler::HandlerFuture;
+use helpers::http::response::create_empty_response;
+use middleware::{Middleware, NewMiddleware};
+use state::{FromState, State, StateData};
+
+/// The identity of a client which has been authenticated by `AuthMiddleware`, which is placed
+/// into `State` before the rest of the pipeline is invoked.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AuthenticatedUser {
+    id: String,
+}
+
+impl AuthenticatedUser {
+    /// The identifier of the user, being the user name for Basic credentials, or the identifier
+    /// provided by the verifier or the `sub` claim for Bearer tokens.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+impl StateData for AuthenticatedUser {}
+
+/// The claims of a JSON Web Token which has been validated by an `AuthMiddleware` created with
+/// `AuthMiddleware::jwt`, which are placed into `State` alongside the `AuthenticatedUser`.
+pub struct JwtClaims<T>(pub T);
+
+impl<T> StateData for JwtClaims<T> where T: Send + 'static {}
+
+// The outcome of checking the credentials sent in an `Authorization` header.
+enum Outcome {
+    Authenticated(AuthenticatedUser),
+    // The credentials were not sent, or used a different scheme.
+    Missing,
+    Invalid,
+}
+
+trait Authenticate: Send + Sync + RefUnwindSafe {
+    // The authentication scheme, such as `Basic`.
+    fn scheme(&self) -> &'static str;
+
+    // Checks the credentials following the scheme in the `Authorization` header, placing any
+    // further data about the user into `State`.
+    fn authenticate(&self, credentials: &str, state: &mut State) -> Option<AuthenticatedUser>;
+}
+
+struct Basic<F>(F);
+
+impl<F> Authenticate for Basic<F>
+where
+    F: Fn(&str, &str) -> bool + Send + Sync + RefUnwindSafe,
+{
+    fn scheme(&self) -> &'static str {
+        "Basic"
+    }
+
+    fn authenticate(&self, credentials: &str, _state: &mut State) -> Option<AuthenticatedUser> {
+        let (user, password) = decode_basic(credentials)?;
+        if (self.0)(&user, &password) {
+            Some(AuthenticatedUser { id: user })
+        } else {
+            None
+        }
+    }
+}
+
+struct Bearer<F>(F);
+
+impl<F> Authenticate for Bearer<F>
+where
+    F: Fn(&str) -> Option<String> + Send + Sync + RefUnwindSafe,
+{
+    fn scheme(&self) -> &'static str {
+        "Bearer"
+    }
+
+    fn authenticate(&self, token: &str, _state: &mut State) -> Option<AuthenticatedUser> {
+        (self.0)(token).map(|id| AuthenticatedUser { id })
+    }
+}
+
+struct Jwt<T> {
+    key: Vec<u8>,
+    validation: Validation,
+    phantom: PhantomData<fn() -> T>,
+}
+
+impl<T> Authenticate for Jwt<T>
+where
+    T: DeserializeOwned + Send + 'static,
+{
+    fn scheme(&self) -> &'static str {
+        "Bearer"
+    }
+
+    fn authenticate(&self, token: &str, state: &mut State) -> Option<AuthenticatedUser> {
+        let claims = match jsonwebtoken::decode::<Value>(token, &self.key, &self.validation) {
+            Ok(data) => data.claims,
+            Err(e) => {
+                request_debug!(state, "rejecting bearer token: {}", e);
+                return None;
+            }
+        };
+
+        let id = match claims.get("sub").and_then(|sub| sub.as_str()) {
+            Some(sub) if !sub.is_empty() => sub.to_owned(),
+            _ => {
+                request_debug!(state, "rejecting bearer token without a subject");
+                return None;
+            }
+        };
+
+        match serde_json::from_value::<T>(claims) {
+            Ok(claims) => {
+                state.put(JwtClaims(claims));
+                Some(AuthenticatedUser { id })
+            }
+            Err(e) => {
+                request_debug!(state, "rejecting bearer token claims: {}", e);
+                None
+            }
+        }
+    }
+}
+
+/// Middleware binding which requires requests to be authenticated, via the `Authorization`
+/// header.
+///
+/// When the credentials are accepted, an `AuthenticatedUser` is placed into `State` and the rest
+/// of the pipeline is invoked. Otherwise, a `401 Unauthorized` response is sent with a
+/// `WWW-Authenticate` header describing the expected scheme. As with any `Middleware`, only the
+/// routes which use a `Pipeline` containing the `AuthMiddleware` require authentication.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// #
+/// # use hyper::{Body, Response, StatusCode};
+/// # use hyper::header::{AUTHORIZATION, WWW_AUTHENTICATE};
+/// # use gotham::middleware::auth::{AuthMiddleware, AuthenticatedUser};
+/// # use gotham::pipeline::new_pipeline;
+/// # use gotham::pipeline::single::single_pipeline;
+/// # use gotham::router::Router;
+/// # use gotham::router::builder::*;
+/// # use gotham::state::{FromState, State};
+/// # use gotham::test::TestServer;
+/// #
+/// fn handler(state: State) -> (State, Response<Body>) {
+///     let greeting = format!("Hello, {}!", AuthenticatedUser::borrow_from(&state).id());
+///     (state, Response::new(Body::from(greeting)))
+/// }
+///
+/// fn router() -> Router {
+///     let auth = AuthMiddleware::basic(|user, password| user == "admin" && password == "secret")
+///         .with_realm("admin area");
+///
+///     let (chain, pipelines) = single_pipeline(new_pipeline().add(auth).build());
+///
+///     build_router(chain, pipelines, |route| {
+///         route.get("/").to(handler);
+///     })
+/// }
+/// #
+/// # fn main() {
+/// #   let test_server = TestServer::new(router()).unwrap();
+/// #
+/// #   let response = test_server.client().get("http://example.com/").perform().unwrap();
+/// #   assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+/// #   assert_eq!(
+/// #       response.headers()[WWW_AUTHENTICATE],
+/// #       "Basic realm=\"admin area\", charset=\"UTF-8\""
+/// #   );
+/// #
+/// #   let response = test_server.client()
+/// #       .get("http://example.com/")
+/// #       .with_header(AUTHORIZATION, "Basic YWRtaW46c2VjcmV0".parse().unwrap())
+/// #       .perform()
+/// #       .unwrap();
+/// #   assert_eq!(response.status(), StatusCode::OK);
+/// #   assert_eq!(response.read_utf8_body().unwrap(), "Hello, admin!");
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct AuthMiddleware {
+    authenticator: Arc<Authenticate>,
+    realm: Arc<String>,
+}
+
+impl AuthMiddleware {
+    /// Creates an `AuthMiddleware` which accepts HTTP Basic credentials for which `verifier`
+    /// returns `true`, given the user name and password.
+    ///
+    /// Basic credentials are sent in the clear, so should only be accepted over HTTPS.
+    pub fn basic<F>(verifier: F) -> AuthMiddleware
+    where
+        F: Fn(&str, &str) -> bool + Send + Sync + RefUnwindSafe + 'static,
+    {
+        AuthMiddleware::with_authenticator(Basic(verifier))
+    }
+
+    /// Creates an `AuthMiddleware` which accepts Bearer tokens for which `verifier` returns the
+    /// identifier of a user.
+    pub fn bearer<F>(verifier: F) -> AuthMiddleware
+    where
+        F: Fn(&str) -> Option<String> + Send + Sync + RefUnwindSafe + 'static,
+    {
+        AuthMiddleware::with_authenticator(Bearer(verifier))
+    }
+
+    /// Creates an `AuthMiddleware` which accepts Bearer tokens which are JSON Web Tokens signed
+    /// with `key`, and which satisfy `validation`. The `Validation` determines the accepted
+    /// algorithms and the claims which are checked, such as the expiry, issuer and audience.
+    ///
+    /// The claims of a valid token are deserialized into `T` and placed into `State` as
+    /// `JwtClaims<T>`, and the `sub` claim is used as the identifier of the
+    /// `AuthenticatedUser`. Tokens without a `sub` claim are rejected.
+    pub fn jwt<T>(key: &[u8], validation: Validation) -> AuthMiddleware
+    where
+        T: DeserializeOwned + Send + 'static,
+    {
+        AuthMiddleware::with_authenticator(Jwt::<T> {
+            key: key.to_vec(),
+            validation,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Sets the realm sent in the `WWW-Authenticate` header, which defaults to `gotham`.
+    pub fn with_realm<S>(self, realm: S) -> AuthMiddleware
+    where
+        S: Into<String>,
+    {
+        AuthMiddleware {
+            realm: Arc::new(realm.into()),
+            ..self
+        }
+    }
+
+    fn with_authenticator<A>(authenticator: A) -> AuthMiddleware
+    where
+        A: Authenticate + 'static,
+    {
+        AuthMiddleware {
+            authenticator: Arc::new(authenticator),
+            realm: Arc::new("gotham".to_owned()),
+        }
+    }
+
+    fn authenticate(&self, state: &mut State) -> Outcome {
+        let header = HeaderMap::borrow_from(state)
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.trim().to_owned());
+
+        let header = match header {
+            Some(header) => header,
+            None => return Outcome::Missing,
+        };
+
+        let scheme = self.authenticator.scheme();
+        let credentials = match header.find(' ') {
+            Some(n) if header[..n].eq_ignore_ascii_case(scheme) => header[n + 1..].trim(),
+            _ => return Outcome::Missing,
+        };
+
+        match self.authenticator.authenticate(credentials, state) {
+            Some(user) => Outcome::Authenticated(user),
+            None => Outcome::Invalid,
+        }
+    }
+
+    // The value of the `WWW-Authenticate` header sent with a `401 Unauthorized` response.
+    fn challenge(&self, invalid: bool) -> String {
+        let realm = self.realm.replace('\\', "\\\\").replace('"', "\\\"");
+        let mut challenge = format!("{} realm=\"{}\"", self.authenticator.scheme(), realm);
+
+        match self.authenticator.scheme() {
+            "Basic" => challenge.push_str(", charset=\"UTF-8\""),
+            _ if invalid => challenge.push_str(", error=\"invalid_token\""),
+            _ => (),
+        }
+
+        challenge
+    }
+}
+
+// Decodes the user name and password from HTTP Basic credentials.
+fn decode_basic(credentials: &str) -> Option<(String, String)> {
+    let decoded = base64::decode(credentials).ok()?;
+    let decoded = String::from_utf8(decoded).ok()?;
+    let n = decoded.find(':')?;
+
+    Some((decoded[..n].to_owned(), decoded[n + 1..].to_owned()))
+}
+
+/// `Middleware` trait implementation.
+impl Middleware for AuthMiddleware {
+    /// Authenticates the request, and invokes the rest of the chain only if it succeeds.
+    fn call<Chain>(self, mut state: State, chain: Chain) -> Box<HandlerFuture>
+    where
+        Chain: FnOnce(State) -> Box<HandlerFuture>,
+    {
+        let invalid = match self.authenticate(&mut state) {
+            Outcome::Authenticated(user) => {
+                request_trace!(&state, "authenticated as {}", user.id);
+                state.put(user);
+                return chain(state);
+            }
+            Outcome::Missing => false,
+            Outcome::Invalid => true,
+        };
+
+        request_debug!(&state, "request is not authenticated");
+
+        let mut res = create_empty_response(&state, StatusCode::UNAUTHORIZED);
+        if let Ok(challenge) = HeaderValue::from_str(&self.challenge(invalid)) {
+            res.headers_mut().insert(WWW_AUTHENTICATE, challenge);
+        }
+
+        Box::new(future::ok((state, res)))
+    }
+}
+
+/// `NewMiddleware` trait implementation.
+impl NewMiddleware for AuthMiddleware {
+    type Instance = Self;
+
+    /// Clones the current middleware to a new instance.
+    fn new_middleware(&self) -> io::Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use jsonwebtoken::{encode, Header};
+
+    #[derive(Serialize, Deserialize)]
+    struct Claims {
+        sub: String,
+        exp: u64,
+        admin: bool,
+    }
+
+    fn authenticate(auth: &AuthMiddleware, header: Option<&str>) -> (Outcome, State) {
+        let mut state = State::new();
+        let mut headers = HeaderMap::new();
+        if let Some(header) = header {
+            headers.insert(AUTHORIZATION, header.parse().unwrap());
+        }
+        state.put(headers);
+        ::state::set_request_id(&mut state);
+
+        (auth.authenticate(&mut state), state)
+    }
+
+    fn user(outcome: Outcome) -> Option<String> {
+        match outcome {
+            Outcome::Authenticated(user) => Some(user.id),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn decodes_basic_credentials() {
+        assert_eq!(
+            decode_basic("dXNlcjpwYXNzOndvcmQ="),
+            Some(("user".to_owned(), "pass:word".to_owned()))
+        );
+        assert_eq!(decode_basic("dXNlcg=="), None);
+        assert_eq!(decode_basic("not base64"), None);
+    }
+
+    #[test]
+    fn authenticates_basic_credentials() {
+        let auth = AuthMiddleware::basic(|user, password| user == "user" && password == "pass");
+
+        let (outcome, state) = authenticate(&auth, Some("basic dXNlcjpwYXNz"));
+        assert_eq!(user(outcome), Some("user".to_owned()));
+        assert!(!state.has::<AuthenticatedUser>());
+
+        let (outcome, _) = authenticate(&auth, Some("Basic dXNlcjp3cm9uZw=="));
+        assert!(match outcome {
+            Outcome::Invalid => true,
+            _ => false,
+        });
+
+        let (outcome, _) = authenticate(&auth, Some("Bearer dXNlcjpwYXNz"));
+        assert!(match outcome {
+            Outcome::Missing => true,
+            _ => false,
+        });
+    }
+
+    #[test]
+    fn authenticates_jwt() {
+        let auth = AuthMiddleware::jwt::<Claims>(b"secret", Validation::default());
+        let claims = Claims {
+            sub: "user".to_owned(),
+            exp: 10_000_000_000,
+            admin: true,
+        };
+
+        let token = encode(&Header::default(), &claims, b"secret").unwrap();
+        let (outcome, state) = authenticate(&auth, Some(&format!("Bearer {}", token)));
+        assert_eq!(user(outcome), Some("user".to_owned()));
+        assert!(JwtClaims::<Claims>::borrow_from(&state).0.admin);
+
+        let token = encode(&Header::default(), &claims, b"other").unwrap();
+        let (outcome, _) = authenticate(&auth, Some(&format!("Bearer {}", token)));
+        assert_eq!(user(outcome), None);
+    }
+
+    #[test]
+    fn rejects_jwt_without_subject() {
+        #[derive(Serialize, Deserialize)]
+        struct Anonymous {
+            sub: Option<String>,
+            exp: u64,
+        }
+
+        let auth = AuthMiddleware::jwt::<Anonymous>(b"secret", Validation::default());
+
+        for sub in vec![None, Some("".to_owned())] {
+            let claims = Anonymous {
+                sub,
+                exp: 10_000_000_000,
+            };
+            let token = encode(&Header::default(), &claims, b"secret").unwrap();
+            let (outcome, state) = authenticate(&auth, Some(&format!("Bearer {}", token)));
+            assert!(match outcome {
+                Outcome::Invalid => true,
+                _ => false,
+            });
+            assert!(!state.has::<JwtClaims<Anonymous>>());
+        }
+    }
+
+    #[test]
+    fn builds_challenges() {
+        let basic = AuthMiddleware::basic(|_, _| false).with_realm("my \"app\"");
+        assert_eq!(
+            basic.challenge(false),
+            "Basic realm=\"my \\\"app\\\"\", charset=\"UTF-8\""
+        );
+
+        let bearer = AuthMiddleware::bearer(|_| None);
+        assert_eq!(bearer.challenge(false), "Bearer realm=\"gotham\"");
+        assert_eq!(
+            bearer.challenge(true),
+            "Bearer realm=\"gotham\", error=\"invalid_token\""
+        );
+    }
+}
@@ -0,0 +1,238 @@
+//! Request body size limiting middleware, used to reject uploads which are too large to handle.
+use futures::{future, Async, Future, Poll, Stream};
+use hyper::header::{HeaderMap, CONTENT_LENGTH};
+use hyper::{Body, Chunk, StatusCode};
+use std::error::Error as StdError;
+use std::fmt::{self, Display};
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use handler::HandlerFuture;
+use helpers::http::response::create_empty_response;
+use middleware::{Middleware, NewMiddleware};
+use state::{FromState, State};
+
+/// Middleware binding to enforce a maximum size for request bodies.
+///
+/// A request which declares a `Content-Length` greater than the limit is rejected with
+/// `413 Payload Too Large` before the rest of the pipeline is invoked, so none of the body is
+/// read. A request without a `Content-Length`, such as one using chunked encoding, is counted as
+/// its body is read instead. Once the limit is exceeded, reading the body fails with an error,
+/// and if the handler returns that error, it is sent with the `413 Payload Too Large` status.
+///
+/// A limit for the whole application is applied by adding the middleware to a pipeline used by
+/// every route. Individual routes or scopes can be given a lower limit by adding another
+/// `RequestBodyLimit` to the pipelines they use, since the lowest limit applying to a request is
+/// the one which takes effect.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate futures;
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// # extern crate mime;
+/// #
+/// # use futures::{future, Future, Stream};
+/// # use hyper::{Body, Response, StatusCode};
+/// # use gotham::handler::{HandlerFuture, IntoHandlerError};
+/// # use gotham::middleware::body_limit::RequestBodyLimit;
+/// # use gotham::pipeline::new_pipeline;
+/// # use gotham::pipeline::set::{finalize_pipeline_set, new_pipeline_set};
+/// # use gotham::router::Router;
+/// # use gotham::router::builder::*;
+/// # use gotham::state::{FromState, State};
+/// # use gotham::test::TestServer;
+/// #
+/// fn upload(mut state: State) -> Box<HandlerFuture> {
+///     let f = Body::take_from(&mut state)
+///         .concat2()
+///         .then(|body| match body {
+///             Ok(body) => {
+///                 let res = Response::new(Body::from(format!("{} bytes", body.len())));
+///                 future::ok((state, res))
+///             }
+///             Err(e) => future::err((state, e.into_handler_error())),
+///         });
+///
+///     Box::new(f)
+/// }
+///
+/// fn router() -> Router {
+///     let pipelines = new_pipeline_set();
+///     let (pipelines, default) =
+///         pipelines.add(new_pipeline().add(RequestBodyLimit::new(1024 * 1024)).build());
+///     let (pipelines, small) =
+///         pipelines.add(new_pipeline().add(RequestBodyLimit::new(16)).build());
+///     let pipelines = finalize_pipeline_set(pipelines);
+///
+///     build_router((default, ()), pipelines, |route| {
+///         route.post("/upload").to(upload);
+///
+///         route.with_pipeline_chain((small, (default, ())), |route| {
+///             route.post("/comment").to(upload);
+///         });
+///     })
+/// }
+/// #
+/// # fn main() {
+/// #   let test_server = TestServer::new(router()).unwrap();
+/// #
+/// #   let response = test_server.client()
+/// #       .post("http://example.com/upload", vec![0; 1024], mime::APPLICATION_OCTET_STREAM)
+/// #       .perform()
+/// #       .unwrap();
+/// #   assert_eq!(response.status(), StatusCode::OK);
+/// #
+/// #   let response = test_server.client()
+/// #       .post("http://example.com/comment", vec![0; 1024], mime::APPLICATION_OCTET_STREAM)
+/// #       .perform()
+/// #       .unwrap();
+/// #   assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct RequestBodyLimit {
+    limit: u64,
+}
+
+impl RequestBodyLimit {
+    /// Creates a `RequestBodyLimit` which rejects request bodies longer than `limit` bytes.
+    pub fn new(limit: u64) -> RequestBodyLimit {
+        RequestBodyLimit { limit }
+    }
+}
+
+/// `Middleware` trait implementation.
+impl Middleware for RequestBodyLimit {
+    /// Rejects requests which declare an oversized body, and limits the body of other requests
+    /// as it is read.
+    fn call<Chain>(self, mut state: State, chain: Chain) -> Box<HandlerFuture>
+    where
+        Chain: FnOnce(State) -> Box<HandlerFuture>,
+    {
+        let declared = HeaderMap::borrow_from(&state)
+            .get(CONTENT_LENGTH)
+            .and_then(|len| len.to_str().ok())
+            .and_then(|len| len.parse::<u64>().ok());
+
+        if declared.map(|len| len > self.limit).unwrap_or(false) {
+            request_debug!(
+                &state,
+                "rejecting request body of {} bytes, limit is {}",
+                declared.unwrap_or_default(),
+                self.limit
+            );
+            let res = create_empty_response(&state, StatusCode::PAYLOAD_TOO_LARGE);
+            return Box::new(future::ok((state, res)));
+        }
+
+        let exceeded = Arc::new(AtomicBool::new(false));
+        if let Some(body) = state.try_take::<Body>() {
+            state.put(Body::wrap_stream(LimitedBody {
+                body,
+                remaining: self.limit,
+                exceeded: exceeded.clone(),
+            }));
+        }
+
+        let f = chain(state).then(move |result| match result {
+            Err((state, err)) if exceeded.load(Ordering::SeqCst) => {
+                request_debug!(&state, "request body exceeded limit");
+                Err((state, err.with_status(StatusCode::PAYLOAD_TOO_LARGE)))
+            }
+            result => result,
+        });
+
+        Box::new(f)
+    }
+}
+
+/// `NewMiddleware` trait implementation.
+impl NewMiddleware for RequestBodyLimit {
+    type Instance = Self;
+
+    /// Clones the current middleware to a new instance.
+    fn new_middleware(&self) -> io::Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+// A request `Body` which fails once more than the remaining number of bytes have been read.
+struct LimitedBody {
+    body: Body,
+    remaining: u64,
+    exceeded: Arc<AtomicBool>,
+}
+
+impl Stream for LimitedBody {
+    type Item = Chunk;
+    type Error = Box<StdError + Send + Sync>;
+
+    fn poll(&mut self) -> Poll<Option<Chunk>, Self::Error> {
+        match try_ready!(self.body.poll()) {
+            Some(chunk) => {
+                let len = chunk.len() as u64;
+                if len > self.remaining {
+                    self.exceeded.store(true, Ordering::SeqCst);
+                    return Err(Box::new(BodyTooLarge));
+                }
+
+                self.remaining -= len;
+                Ok(Async::Ready(Some(chunk)))
+            }
+            None => Ok(Async::Ready(None)),
+        }
+    }
+}
+
+// The error produced when reading a request body beyond the limit.
+#[derive(Debug)]
+struct BodyTooLarge;
+
+impl Display for BodyTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("request body exceeds the configured limit")
+    }
+}
+
+impl StdError for BodyTooLarge {
+    fn description(&self) -> &str {
+        "request body exceeds the configured limit"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(chunks: Vec<&'static str>, limit: u64) -> (Result<Chunk, String>, bool) {
+        let exceeded = Arc::new(AtomicBool::new(false));
+        let body = LimitedBody {
+            body: Body::wrap_stream(::futures::stream::iter_ok::<_, io::Error>(chunks)),
+            remaining: limit,
+            exceeded: exceeded.clone(),
+        };
+
+        let result = body.concat2().wait().map_err(|e| e.to_string());
+        (result, exceeded.load(Ordering::SeqCst))
+    }
+
+    #[test]
+    fn allows_bodies_within_limit() {
+        let (result, exceeded) = read(vec!["abc", "def"], 6);
+        assert_eq!(&result.unwrap()[..], b"abcdef");
+        assert!(!exceeded);
+    }
+
+    #[test]
+    fn rejects_bodies_over_limit() {
+        let (result, exceeded) = read(vec!["abc", "def"], 5);
+        assert_eq!(
+            result.unwrap_err(),
+            "request body exceeds the configured limit"
+        );
+        assert!(exceeded);
+    }
+}
@@ -0,0 +1,317 @@
+//! Warns when a request is slower, or its response larger, than a budget, so that performance
+//! regressions are noticed in production.
+
+use hyper::body::Payload;
+use hyper::header::CONTENT_LENGTH;
+use std::fmt::{self, Display, Formatter};
+use std::io;
+use std::panic::RefUnwindSafe;
+use std::sync::Arc;
+use std::time::Duration;
+
+use handler::HandlerFuture;
+use helpers::clock::{self, elapsed_between};
+use middleware::chain_timing::ChainTimings;
+use middleware::hook::{on_complete, Outcome};
+use middleware::{Middleware, NewMiddleware};
+use router::description::RouteTemplate;
+use state::{FromState, State};
+
+type Hook = Arc<Fn(&State, &BudgetExceeded) + Send + Sync + RefUnwindSafe>;
+
+/// Middleware binding which reports requests exceeding a latency budget or a response size
+/// budget.
+///
+/// Each request exceeding a budget is logged at the warn level with `request_warn!`, and passed
+/// to any hooks added with `with_hook`, such as to record it in an error tracker. The report
+/// includes the route template of the request and, when the request is timed by a
+/// `ChainTimingMiddleware` earlier in the pipeline, the time spent in each middleware and the
+/// handler.
+///
+/// The latency is measured from when the middleware is called until the response has been
+/// created, so it is usually added early in the first pipeline. The size of a response is known
+/// from its `Content-Length` header or its body, so streamed responses of unknown length are not
+/// checked against the size budget.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// #
+/// # use std::time::Duration;
+/// # use hyper::{Body, Response};
+/// # use gotham::middleware::budget::BudgetMiddleware;
+/// # use gotham::middleware::chain_timing::ChainTimingMiddleware;
+/// # use gotham::pipeline::new_pipeline;
+/// # use gotham::pipeline::single::single_pipeline;
+/// # use gotham::router::Router;
+/// # use gotham::router::builder::*;
+/// # use gotham::state::State;
+/// # use gotham::test::TestServer;
+/// #
+/// fn handler(state: State) -> (State, Response<Body>) {
+///     (state, Response::new(Body::from("Hello, world!")))
+/// }
+///
+/// fn router() -> Router {
+///     let budget = BudgetMiddleware::new()
+///         .with_latency_budget(Duration::from_millis(250))
+///         .with_response_size_budget(1024 * 1024)
+///         .with_hook(|_state, exceeded| println!("over budget: {}", exceeded));
+///
+///     let pipeline = new_pipeline()
+///         .add(budget)
+///         .add(ChainTimingMiddleware::new())
+///         .build();
+///
+///     let (chain, pipelines) = single_pipeline(pipeline);
+///     build_router(chain, pipelines, |route| {
+///         route.get("/").to(handler);
+///     })
+/// }
+/// #
+/// # fn main() {
+/// #   let test_server = TestServer::new(router()).unwrap();
+/// #   let response = test_server.client().get("http://example.com/").perform().unwrap();
+/// #   assert_eq!(response.read_utf8_body().unwrap(), "Hello, world!");
+/// # }
+/// ```
+#[derive(Clone, Default)]
+pub struct BudgetMiddleware {
+    latency: Option<Duration>,
+    response_size: Option<u64>,
+    hooks: Vec<Hook>,
+}
+
+impl BudgetMiddleware {
+    /// Creates a `BudgetMiddleware` without any budgets.
+    pub fn new() -> BudgetMiddleware {
+        BudgetMiddleware::default()
+    }
+
+    /// Reports requests which take longer than `latency` to respond.
+    pub fn with_latency_budget(self, latency: Duration) -> BudgetMiddleware {
+        BudgetMiddleware {
+            latency: Some(latency),
+            ..self
+        }
+    }
+
+    /// Reports responses with bodies larger than `bytes`.
+    pub fn with_response_size_budget(self, bytes: u64) -> BudgetMiddleware {
+        BudgetMiddleware {
+            response_size: Some(bytes),
+            ..self
+        }
+    }
+
+    /// Adds a hook which is called with each report, in addition to it being logged. Hooks are
+    /// called in the order they were added.
+    pub fn with_hook<F>(mut self, hook: F) -> BudgetMiddleware
+    where
+        F: Fn(&State, &BudgetExceeded) + Send + Sync + RefUnwindSafe + 'static,
+    {
+        self.hooks.push(Arc::new(hook));
+        self
+    }
+
+    fn report(&self, state: &State, budget: Budget) {
+        let exceeded = BudgetExceeded {
+            budget,
+            route: RouteTemplate::try_borrow_from(state).map(|route| route.as_str().to_owned()),
+            timings: ChainTimings::try_borrow_from(state).cloned(),
+        };
+
+        request_warn!(state, "{}", exceeded);
+
+        for hook in &self.hooks {
+            hook(state, &exceeded);
+        }
+    }
+}
+
+/// `Middleware` trait implementation.
+impl Middleware for BudgetMiddleware {
+    /// Measures the rest of the chain, and reports the request if it exceeds a budget.
+    fn call<Chain>(self, state: State, chain: Chain) -> Box<HandlerFuture>
+    where
+        Chain: FnOnce(State) -> Box<HandlerFuture>,
+    {
+        let start = clock::now(&state);
+
+        on_complete(chain(state), move |state, outcome| {
+            let elapsed = elapsed_between(start, clock::now(state));
+            if let Some(budget) = self.latency {
+                if elapsed > budget {
+                    self.report(state, Budget::Latency { elapsed, budget });
+                }
+            }
+
+            let size = match outcome {
+                Outcome::Response(response) => response
+                    .headers()
+                    .get(CONTENT_LENGTH)
+                    .and_then(|len| len.to_str().ok())
+                    .and_then(|len| len.parse().ok())
+                    .or_else(|| response.body().content_length()),
+                Outcome::Error(_) => None,
+            };
+            if let (Some(size), Some(budget)) = (size, self.response_size) {
+                if size > budget {
+                    self.report(state, Budget::ResponseSize { size, budget });
+                }
+            }
+        })
+    }
+}
+
+/// `NewMiddleware` trait implementation.
+impl NewMiddleware for BudgetMiddleware {
+    type Instance = Self;
+
+    /// Clones the current middleware to a new instance.
+    fn new_middleware(&self) -> io::Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+/// The budget which a request exceeded.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Budget {
+    /// The request took `elapsed` to respond, which is longer than `budget`.
+    Latency {
+        /// The time taken to respond.
+        elapsed: Duration,
+        /// The latency budget.
+        budget: Duration,
+    },
+
+    /// The response body is `size` bytes, which is larger than `budget`.
+    ResponseSize {
+        /// The size of the response body in bytes.
+        size: u64,
+        /// The response size budget in bytes.
+        budget: u64,
+    },
+}
+
+/// A report of a request which exceeded a budget of `BudgetMiddleware`.
+#[derive(Clone, Debug)]
+pub struct BudgetExceeded {
+    budget: Budget,
+    route: Option<String>,
+    timings: Option<ChainTimings>,
+}
+
+impl BudgetExceeded {
+    /// The budget which was exceeded.
+    pub fn budget(&self) -> &Budget {
+        &self.budget
+    }
+
+    /// The template of the route which served the request, such as `/users/:id`, or `None` if no
+    /// route matched.
+    pub fn route(&self) -> Option<&str> {
+        self.route.as_ref().map(String::as_str)
+    }
+
+    /// The time spent in each middleware and the handler, when the request was timed by
+    /// `ChainTimingMiddleware`.
+    pub fn timings(&self) -> Option<&ChainTimings> {
+        self.timings.as_ref()
+    }
+}
+
+/// Formats the report as a message such as
+/// `slow request: 412ms exceeds 250ms budget route=/users/:id chain=handler:405.12ms`.
+impl Display for BudgetExceeded {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.budget {
+            Budget::Latency { elapsed, budget } => write!(
+                f,
+                "slow request: {}ms exceeds {}ms budget",
+                millis(elapsed),
+                millis(budget)
+            )?,
+            Budget::ResponseSize { size, budget } => write!(
+                f,
+                "large response: {} bytes exceeds {} byte budget",
+                size, budget
+            )?,
+        }
+
+        if let Some(ref route) = self.route {
+            write!(f, " route={}", route)?;
+        }
+
+        if let Some(ref timings) = self.timings {
+            write!(f, " chain={}", timings)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn millis(duration: Duration) -> u64 {
+    duration.as_secs() * 1000 + u64::from(duration.subsec_millis())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::{future, Future};
+    use hyper::{Body, Response};
+    use std::sync::Mutex;
+
+    use helpers::clock::{put_clock, ManualClock};
+
+    fn run(middleware: BudgetMiddleware, millis: u64, body: &'static str) -> Vec<String> {
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let recorded = reports.clone();
+        let middleware = middleware.with_hook(move |_, exceeded| {
+            recorded.lock().unwrap().push(exceeded.to_string());
+        });
+
+        let clock = ManualClock::new();
+        let mut state = State::new();
+        put_clock(&mut state, Arc::new(clock.clone()));
+        state.put(RouteTemplate::new("/users/:id", None));
+
+        let chain = move |state| {
+            clock.advance(Duration::from_millis(millis));
+            let f = future::ok((state, Response::new(Body::from(body))));
+            Box::new(f) as Box<HandlerFuture>
+        };
+
+        assert!(middleware.call(state, chain).wait().is_ok());
+
+        let reports = reports.lock().unwrap();
+        reports.clone()
+    }
+
+    #[test]
+    fn reports_requests_over_budget() {
+        let middleware = BudgetMiddleware::new()
+            .with_latency_budget(Duration::from_millis(100))
+            .with_response_size_budget(4);
+
+        assert!(run(middleware.clone(), 100, "four").is_empty());
+
+        assert_eq!(
+            run(middleware.clone(), 250, "four"),
+            vec!["slow request: 250ms exceeds 100ms budget route=/users/:id"]
+        );
+
+        assert_eq!(
+            run(middleware, 50, "large"),
+            vec!["large response: 5 bytes exceeds 4 byte budget route=/users/:id"]
+        );
+    }
+
+    #[test]
+    fn ignores_unset_budgets() {
+        assert!(run(BudgetMiddleware::new(), 60_000, "a large response").is_empty());
+    }
+}
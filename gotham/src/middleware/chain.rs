@@ -4,8 +4,9 @@ use std::io;
 use std::panic::RefUnwindSafe;
 
 use handler::HandlerFuture;
+use middleware::chain_timing::call_middleware;
 use middleware::{Middleware, NewMiddleware};
-use state::{request_id, State};
+use state::State;
 
 /// A recursive type representing a pipeline, which is used to spawn a `MiddlewareChain`.
 ///
@@ -101,7 +102,10 @@ where
         //  }
         //
         // The resulting function is called by `<() as MiddlewareChain>::call`
-        trace!("[{}] executing middleware", request_id(&state));
-        p.call(state, move |state| m.call(state, f))
+        //
+        // Each middleware is called through `call_middleware`, which measures it when the request
+        // is being timed by `ChainTimingMiddleware`.
+        request_trace!(&state, "executing middleware");
+        p.call(state, move |state| call_middleware(m, state, f))
     }
 }
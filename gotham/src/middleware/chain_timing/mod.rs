@@ -0,0 +1,447 @@
+//! Measures the time spent in each middleware of the pipelines which serve a request, and in the
+//! handler, so that a slow middleware can be found.
+//!
+//! Measuring is opt-in, and begins when a request passes through `ChainTimingMiddleware`, which
+//! is usually the first middleware in the first pipeline. Each middleware after it is measured
+//! from when it is called until the future it returns completes, and the time spent in the rest
+//! of the chain is subtracted to give the time spent in the middleware itself.
+//!
+//! The measurements are kept in `State` as `ChainTimings`, and can also be recorded in `Metrics`
+//! to give a histogram of the time spent in each middleware across requests. When `ChainTimings`
+//! is in `State`, the `Structured` format of `RequestLogger` includes them in each log line.
+
+use std::any;
+use std::fmt::{self, Display, Formatter};
+use std::io;
+use std::time::{Duration, Instant};
+
+use handler::HandlerFuture;
+use helpers::clock::{self, elapsed_between};
+use helpers::timing::Timing;
+use middleware::hook::on_complete;
+use middleware::metrics::Metrics;
+use middleware::{Middleware, NewMiddleware};
+use state::{State, StateData};
+
+/// Middleware binding which measures the time spent in each middleware after it, and in the
+/// handler, and puts the measurements into `State` as `ChainTimings`.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// #
+/// # use hyper::{Body, Response};
+/// # use gotham::middleware::chain_timing::ChainTimingMiddleware;
+/// # use gotham::middleware::metrics::{Metrics, MetricsMiddleware};
+/// # use gotham::pipeline::new_pipeline;
+/// # use gotham::pipeline::single::single_pipeline;
+/// # use gotham::router::Router;
+/// # use gotham::router::builder::*;
+/// # use gotham::state::State;
+/// # use gotham::test::TestServer;
+/// #
+/// fn handler(state: State) -> (State, Response<Body>) {
+///     (state, Response::new(Body::from("Hello, world!")))
+/// }
+///
+/// fn router() -> Router {
+///     let metrics = Metrics::new();
+///
+///     let pipeline = new_pipeline()
+///         .add(ChainTimingMiddleware::new().with_metrics(metrics.clone()))
+///         .add(MetricsMiddleware::new(metrics.clone()))
+///         .build();
+///
+///     let (chain, pipelines) = single_pipeline(pipeline);
+///     build_router(chain, pipelines, |route| {
+///         route.get("/").to(handler);
+///         route.get("/metrics").to_new_handler(metrics);
+///     })
+/// }
+/// #
+/// # fn main() {
+/// #   let test_server = TestServer::new(router()).unwrap();
+/// #   test_server.client().get("http://example.com/").perform().unwrap();
+/// #
+/// #   let response = test_server.client().get("http://example.com/metrics").perform().unwrap();
+/// #   let body = response.read_utf8_body().unwrap();
+/// #   assert!(body.contains(
+/// #       "gotham_middleware_duration_seconds_count{middleware=\"MetricsMiddleware\"} 1\n"
+/// #   ));
+/// #   assert!(body.contains(
+/// #       "gotham_middleware_duration_seconds_count{middleware=\"handler\"} 1\n"
+/// #   ));
+/// # }
+/// ```
+#[derive(Clone, Default)]
+pub struct ChainTimingMiddleware {
+    metrics: Option<Metrics>,
+}
+
+impl ChainTimingMiddleware {
+    /// Creates a `ChainTimingMiddleware` which puts the measurements of each request into `State`.
+    pub fn new() -> ChainTimingMiddleware {
+        ChainTimingMiddleware::default()
+    }
+
+    /// Also records the measurements of each request in `metrics`, as the
+    /// `gotham_middleware_duration_seconds` histogram.
+    pub fn with_metrics(self, metrics: Metrics) -> ChainTimingMiddleware {
+        ChainTimingMiddleware {
+            metrics: Some(metrics),
+        }
+    }
+}
+
+/// `Middleware` trait implementation.
+impl Middleware for ChainTimingMiddleware {
+    /// Puts an empty `ChainTimings` into `State` for the rest of the chain to record into.
+    fn call<Chain>(self, mut state: State, chain: Chain) -> Box<HandlerFuture>
+    where
+        Chain: FnOnce(State) -> Box<HandlerFuture>,
+    {
+        state.put(ChainTimings {
+            middleware: Vec::new(),
+        });
+
+        let metrics = match self.metrics {
+            Some(metrics) => metrics,
+            None => return chain(state),
+        };
+
+        on_complete(chain(state), move |state, _| {
+            let timings = state.borrow::<ChainTimings>();
+
+            for timing in timings.middleware() {
+                if let Some(own) = timing.own() {
+                    metrics.observe_middleware(timing.name(), own);
+                }
+            }
+
+            if let Some(handler) = timings.handler() {
+                metrics.observe_middleware("handler", handler);
+            }
+        })
+    }
+}
+
+/// `NewMiddleware` trait implementation.
+impl NewMiddleware for ChainTimingMiddleware {
+    type Instance = Self;
+
+    /// Clones the current middleware to a new instance.
+    fn new_middleware(&self) -> io::Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+/// The time spent in each middleware after `ChainTimingMiddleware`, and in the handler, for the
+/// current request.
+#[derive(Clone, Debug)]
+pub struct ChainTimings {
+    middleware: Vec<MiddlewareTiming>,
+}
+
+impl StateData for ChainTimings {}
+
+impl ChainTimings {
+    /// The middleware which have been called, in the order they were called. A middleware which
+    /// is still running, such as one reading `ChainTimings` before its own future completes, has
+    /// no `total` yet.
+    pub fn middleware(&self) -> &[MiddlewareTiming] {
+        &self.middleware
+    }
+
+    /// The time spent in the handler, which is the time spent in the rest of the chain by the
+    /// last middleware to be called. This is `None` when a middleware responded without calling
+    /// the rest of the chain, or the handler hasn't completed.
+    pub fn handler(&self) -> Option<Duration> {
+        self.middleware.last().and_then(|timing| timing.chain)
+    }
+}
+
+/// Formats the completed measurements as comma separated `name:time` pairs, such as
+/// `SessionMiddleware:1.20ms,handler:3.41ms`.
+impl Display for ChainTimings {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let own = self
+            .middleware
+            .iter()
+            .filter_map(|timing| timing.own().map(|own| (timing.name(), own)));
+        let handler = self.handler().map(|handler| ("handler", handler));
+
+        for (i, (name, duration)) in own.chain(handler).enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "{}:{}", name, to_timing(duration))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The time spent in a single middleware while serving a request.
+#[derive(Clone, Debug)]
+pub struct MiddlewareTiming {
+    name: &'static str,
+    total: Option<Duration>,
+    chain: Option<Duration>,
+}
+
+impl MiddlewareTiming {
+    /// The name of the middleware type, without its module path or type parameters.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// The time from when the middleware was called until its future completed, including the
+    /// rest of the chain.
+    pub fn total(&self) -> Option<Duration> {
+        self.total
+    }
+
+    /// The time spent in the rest of the chain, or `None` if the middleware didn't call it.
+    pub fn chain(&self) -> Option<Duration> {
+        self.chain
+    }
+
+    /// The time spent in the middleware itself, excluding the rest of the chain.
+    pub fn own(&self) -> Option<Duration> {
+        let chain = self.chain.unwrap_or_else(|| Duration::from_secs(0));
+        self.total.map(|total| {
+            total
+                .checked_sub(chain)
+                .unwrap_or_else(|| Duration::from_secs(0))
+        })
+    }
+}
+
+/// Calls `m`, measuring it when the request is being timed by `ChainTimingMiddleware`.
+pub(crate) fn call_middleware<M, F>(m: M, mut state: State, f: F) -> Box<HandlerFuture>
+where
+    M: Middleware + Send + 'static,
+    F: FnOnce(State) -> Box<HandlerFuture> + Send + 'static,
+{
+    let start = clock::now(&state);
+    let index = match state.try_borrow_mut::<ChainTimings>() {
+        Some(timings) => {
+            timings.middleware.push(MiddlewareTiming {
+                name: short_name(any::type_name::<M>()),
+                total: None,
+                chain: None,
+            });
+            timings.middleware.len() - 1
+        }
+        None => return m.call(state, f),
+    };
+
+    let chain = move |state: State| {
+        let start = clock::now(&state);
+        on_complete(f(state), move |state, _| {
+            record(state, index, start, |timing, elapsed| {
+                timing.chain = Some(elapsed)
+            })
+        })
+    };
+
+    on_complete(m.call(state, chain), move |state, _| {
+        record(state, index, start, |timing, elapsed| {
+            timing.total = Some(elapsed)
+        })
+    })
+}
+
+// Records the time elapsed since `start` in the measurements of the middleware at `index`.
+fn record<R>(state: &mut State, index: usize, start: Instant, r: R)
+where
+    R: FnOnce(&mut MiddlewareTiming, Duration),
+{
+    let elapsed = elapsed_between(start, clock::now(state));
+    if let Some(timing) = state
+        .try_borrow_mut::<ChainTimings>()
+        .and_then(|timings| timings.middleware.get_mut(index))
+    {
+        r(timing, elapsed);
+    }
+}
+
+// Shortens a type name such as `gotham::middleware::session::SessionMiddleware<B, T>` to
+// `SessionMiddleware`.
+fn short_name(name: &'static str) -> &'static str {
+    let name = match name.find('<') {
+        Some(i) => &name[..i],
+        None => name,
+    };
+
+    match name.rfind("::") {
+        Some(i) => &name[i + 2..],
+        None => name,
+    }
+}
+
+fn to_timing(duration: Duration) -> Timing {
+    Timing::Microseconds(
+        duration.as_secs() as i64 * 1_000_000 + i64::from(duration.subsec_micros()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+
+    use futures::{future, Future};
+    use hyper::{Body, Response};
+
+    use helpers::clock::{put_clock, ManualClock};
+
+    // Advances the clock before calling the rest of the chain, or responds without calling it.
+    struct Slow {
+        clock: ManualClock,
+        millis: u64,
+        respond: bool,
+    }
+
+    impl Middleware for Slow {
+        fn call<Chain>(self, state: State, chain: Chain) -> Box<HandlerFuture>
+        where
+            Chain: FnOnce(State) -> Box<HandlerFuture>,
+        {
+            self.clock.advance(Duration::from_millis(self.millis));
+
+            if self.respond {
+                Box::new(future::ok((state, Response::new(Body::empty()))))
+            } else {
+                chain(state)
+            }
+        }
+    }
+
+    fn run<F>(clock: &ManualClock, middleware: ChainTimingMiddleware, chain: F) -> State
+    where
+        F: FnOnce(State) -> Box<HandlerFuture> + Send + 'static,
+    {
+        let mut state = State::new();
+        put_clock(&mut state, Arc::new(clock.clone()));
+
+        match middleware.call(state, chain).wait() {
+            Ok((state, _)) => state,
+            Err(_) => panic!("chain failed"),
+        }
+    }
+
+    fn slow(clock: &ManualClock, millis: u64) -> Slow {
+        Slow {
+            clock: clock.clone(),
+            millis,
+            respond: false,
+        }
+    }
+
+    #[test]
+    fn measures_middleware_and_handler() {
+        let clock = ManualClock::new();
+        let (first, second) = (slow(&clock, 2), slow(&clock, 3));
+        let handler_clock = clock.clone();
+
+        let state = run(&clock, ChainTimingMiddleware::new(), move |state| {
+            call_middleware(first, state, move |state| {
+                call_middleware(second, state, move |state| {
+                    handler_clock.advance(Duration::from_millis(5));
+                    Box::new(future::ok((state, Response::new(Body::empty()))))
+                })
+            })
+        });
+
+        let timings = state.borrow::<ChainTimings>();
+        let middleware = timings.middleware();
+        assert_eq!(middleware.len(), 2);
+        assert_eq!(middleware[0].name(), "Slow");
+        assert_eq!(middleware[0].total(), Some(Duration::from_millis(10)));
+        assert_eq!(middleware[0].chain(), Some(Duration::from_millis(8)));
+        assert_eq!(middleware[0].own(), Some(Duration::from_millis(2)));
+        assert_eq!(middleware[1].own(), Some(Duration::from_millis(3)));
+        assert_eq!(timings.handler(), Some(Duration::from_millis(5)));
+        assert_eq!(
+            timings.to_string(),
+            "Slow:2.00ms,Slow:3.00ms,handler:5.00ms"
+        );
+    }
+
+    #[test]
+    fn measures_middleware_which_respond() {
+        let clock = ManualClock::new();
+        let responder = Slow {
+            respond: true,
+            ..slow(&clock, 4)
+        };
+
+        let state = run(&clock, ChainTimingMiddleware::new(), move |state| {
+            call_middleware(responder, state, |_| unreachable!())
+        });
+
+        let timings = state.borrow::<ChainTimings>();
+        assert_eq!(timings.middleware()[0].chain(), None);
+        assert_eq!(
+            timings.middleware()[0].own(),
+            Some(Duration::from_millis(4))
+        );
+        assert_eq!(timings.handler(), None);
+        assert_eq!(timings.to_string(), "Slow:4.00ms");
+    }
+
+    #[test]
+    fn records_metrics() {
+        let clock = ManualClock::new();
+        let metrics = Metrics::with_buckets(vec![0.001, 0.01]);
+        let middleware = slow(&clock, 2);
+
+        run(
+            &clock,
+            ChainTimingMiddleware::new().with_metrics(metrics.clone()),
+            move |state| {
+                call_middleware(middleware, state, |state| {
+                    Box::new(future::ok((state, Response::new(Body::empty()))))
+                })
+            },
+        );
+
+        let out = metrics.render();
+        assert!(out.contains(
+            "gotham_middleware_duration_seconds_bucket{middleware=\"Slow\",le=\"0.001\"} 0\n"
+        ));
+        assert!(out.contains(
+            "gotham_middleware_duration_seconds_bucket{middleware=\"Slow\",le=\"0.01\"} 1\n"
+        ));
+        assert!(
+            out.contains("gotham_middleware_duration_seconds_count{middleware=\"handler\"} 1\n")
+        );
+    }
+
+    #[test]
+    fn ignores_untimed_requests() {
+        let state = State::new();
+
+        let (state, _) = call_middleware(slow(&ManualClock::new(), 1), state, |state| {
+            Box::new(future::ok((state, Response::new(Body::empty()))))
+        })
+        .wait()
+        .ok()
+        .unwrap();
+
+        assert!(!state.has::<ChainTimings>());
+    }
+
+    #[test]
+    fn shortens_type_names() {
+        assert_eq!(
+            short_name("gotham::middleware::session::SessionMiddleware<a::B, c::D>"),
+            "SessionMiddleware"
+        );
+        assert_eq!(short_name("Plain"), "Plain");
+    }
+}
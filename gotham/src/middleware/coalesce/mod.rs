@@ -0,0 +1,556 @@
+//! Coalesces concurrent identical `GET` requests, so that the handler runs once and its response
+//! is sent to every client which asked for it.
+
+use bytes::Bytes;
+use futures::sync::oneshot;
+use futures::{future, Future, Stream};
+use hyper::body::Payload;
+use hyper::header::{HeaderMap, HeaderName, AUTHORIZATION, COOKIE, HOST, SET_COOKIE};
+use hyper::{Body, Method, Response, StatusCode, Uri, Version};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::io;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use handler::{HandlerFuture, IntoHandlerError};
+use middleware::{Middleware, NewMiddleware};
+use state::{FromState, State};
+
+type Waiters = HashMap<String, Vec<oneshot::Sender<Result<Arc<Shared>, StatusCode>>>>;
+
+// A response produced by the request running the handler, which is copied for each waiter.
+struct Shared {
+    status: StatusCode,
+    version: Version,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+impl Shared {
+    fn to_response(&self) -> Response<Body> {
+        let mut response = Response::new(Body::from(self.body.clone()));
+        *response.status_mut() = self.status;
+        *response.version_mut() = self.version;
+        *response.headers_mut() = self.headers.clone();
+        response.headers_mut().remove(SET_COOKIE);
+        response
+    }
+}
+
+/// Middleware binding which coalesces concurrent identical `GET` requests, to protect a slow
+/// handler or upstream service from a burst of requests for the same resource.
+///
+/// The first request for a key runs the rest of the chain as usual. Requests with the same key
+/// which arrive before it completes wait for its response instead of running the chain, and are
+/// sent a copy of it once it completes. The key is the host and URI of the request, along with
+/// the values of any headers added with `with_header`, such as `Accept` when the response depends
+/// on it.
+///
+/// The response is buffered so that it can be copied, and the copies omit any `Set-Cookie`
+/// headers. A response whose body is larger than the maximum size set with `with_max_size`, or
+/// whose size isn't known in advance, is not buffered, and the waiting requests each run the
+/// chain themselves. Since each copy is identical, this middleware must only wrap routes whose responses
+/// don't depend on who is asking. Placing it after authentication or session middleware does not
+/// make that safe, as the waiting requests never run those middleware, and are sent the response
+/// produced for another client. Requests carrying an `Authorization` or `Cookie` header are
+/// therefore never coalesced, unless that header is added to the key with `with_header`.
+///
+/// When the first request fails, the waiting requests fail with the same status, and when it is
+/// abandoned before completing, such as by its client disconnecting, the waiting requests each
+/// run the chain themselves.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// #
+/// # use hyper::{Body, Response};
+/// # use hyper::header::ACCEPT;
+/// # use gotham::middleware::coalesce::CoalesceMiddleware;
+/// # use gotham::pipeline::new_pipeline;
+/// # use gotham::pipeline::single::single_pipeline;
+/// # use gotham::router::Router;
+/// # use gotham::router::builder::*;
+/// # use gotham::state::State;
+/// # use gotham::test::TestServer;
+/// #
+/// fn report(state: State) -> (State, Response<Body>) {
+///     // ... an expensive query.
+///     (state, Response::new(Body::from("report")))
+/// }
+///
+/// fn router() -> Router {
+///     let pipeline = new_pipeline()
+///         .add(CoalesceMiddleware::new().with_header(ACCEPT))
+///         .build();
+///
+///     let (chain, pipelines) = single_pipeline(pipeline);
+///     build_router(chain, pipelines, |route| {
+///         route.get("/report").to(report);
+///     })
+/// }
+/// #
+/// # fn main() {
+/// #   let test_server = TestServer::new(router()).unwrap();
+/// #   let response = test_server.client().get("http://example.com/report").perform().unwrap();
+/// #   assert_eq!(response.read_utf8_body().unwrap(), "report");
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct CoalesceMiddleware {
+    headers: Vec<HeaderName>,
+    max_size: u64,
+    waiters: Arc<Mutex<Waiters>>,
+}
+
+impl Default for CoalesceMiddleware {
+    fn default() -> CoalesceMiddleware {
+        CoalesceMiddleware {
+            headers: Vec::new(),
+            max_size: 1024 * 1024,
+            waiters: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+}
+
+impl CoalesceMiddleware {
+    /// Creates a `CoalesceMiddleware` which coalesces requests by their host and URI, and
+    /// buffers responses of up to 1MiB.
+    pub fn new() -> CoalesceMiddleware {
+        CoalesceMiddleware::default()
+    }
+
+    /// Also coalesces requests by the value of the `name` header, so that requests with
+    /// different values are not given the same response. Adding `Authorization` or `Cookie` allows
+    /// requests carrying that header to be coalesced with others carrying the same value.
+    pub fn with_header(mut self, name: HeaderName) -> CoalesceMiddleware {
+        self.headers.push(name);
+        self
+    }
+
+    /// Sets the largest response body in bytes which is buffered to be shared with the waiting
+    /// requests.
+    pub fn with_max_size(self, max_size: u64) -> CoalesceMiddleware {
+        CoalesceMiddleware { max_size, ..self }
+    }
+
+    // Determines if the request carries credentials which are not part of the key, so that its
+    // response may depend on who is asking.
+    fn has_unkeyed_credentials(&self, state: &State) -> bool {
+        let headers = HeaderMap::borrow_from(state);
+
+        [AUTHORIZATION, COOKIE]
+            .iter()
+            .any(|name| headers.contains_key(name) && !self.headers.contains(name))
+    }
+
+    fn key(&self, state: &State) -> String {
+        let uri = Uri::borrow_from(state);
+        let headers = HeaderMap::borrow_from(state);
+
+        // the same path may be served for several hosts, which needn't share responses
+        let mut key = match headers.get(HOST) {
+            Some(host) => String::from_utf8_lossy(host.as_bytes()).into_owned(),
+            None => uri
+                .authority_part()
+                .map(|a| a.to_string())
+                .unwrap_or_default(),
+        };
+        key.push('\n');
+        key.push_str(&uri.to_string());
+
+        for name in &self.headers {
+            key.push('\n');
+            for value in headers.get_all(name) {
+                key.push_str(&String::from_utf8_lossy(value.as_bytes()));
+                key.push(',');
+            }
+        }
+
+        key
+    }
+}
+
+fn lock(waiters: &Mutex<Waiters>) -> MutexGuard<Waiters> {
+    match waiters.lock() {
+        Ok(waiters) => waiters,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+// Removes the key of a request running the chain when it is dropped, so that if the request is
+// abandoned, the requests waiting for it are woken and run the chain themselves.
+struct Flight {
+    key: Option<String>,
+    waiters: Arc<Mutex<Waiters>>,
+}
+
+impl Flight {
+    fn land(mut self, outcome: Result<Arc<Shared>, StatusCode>) {
+        let key = self.key.take().unwrap();
+        let waiters = lock(&self.waiters).remove(&key).unwrap_or_default();
+
+        for waiter in waiters {
+            let _ = waiter.send(outcome.clone());
+        }
+    }
+}
+
+impl Drop for Flight {
+    fn drop(&mut self) {
+        if let Some(ref key) = self.key {
+            lock(&self.waiters).remove(key);
+        }
+    }
+}
+
+/// `Middleware` trait implementation.
+impl Middleware for CoalesceMiddleware {
+    /// Runs the rest of the chain, or waits for the response of an identical request which is
+    /// already running it.
+    fn call<Chain>(self, state: State, chain: Chain) -> Box<HandlerFuture>
+    where
+        Chain: FnOnce(State) -> Box<HandlerFuture> + Send + 'static,
+    {
+        if *Method::borrow_from(&state) != Method::GET {
+            return chain(state);
+        }
+
+        if self.has_unkeyed_credentials(&state) {
+            request_trace!(&state, "not coalescing a request carrying credentials");
+            return chain(state);
+        }
+
+        let key = self.key(&state);
+
+        let waiting = {
+            let mut waiters = lock(&self.waiters);
+            match waiters.entry(key.clone()) {
+                Entry::Occupied(mut waiting) => {
+                    let (tx, rx) = oneshot::channel();
+                    waiting.get_mut().push(tx);
+                    Some(rx)
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(Vec::new());
+                    None
+                }
+            }
+        };
+
+        if let Some(rx) = waiting {
+            request_trace!(&state, "waiting for the response of an identical request");
+
+            let f = rx.then(move |outcome| -> Box<HandlerFuture> {
+                match outcome {
+                    Ok(Ok(shared)) => Box::new(future::ok((state, shared.to_response()))),
+                    Ok(Err(status)) => {
+                        let err = CoalescedRequestFailed { status }
+                            .into_handler_error()
+                            .with_status(status);
+                        Box::new(future::err((state, err)))
+                    }
+                    Err(oneshot::Canceled) => chain(state),
+                }
+            });
+
+            return Box::new(f);
+        }
+
+        let flight = Flight {
+            key: Some(key),
+            waiters: self.waiters.clone(),
+        };
+
+        let f = chain(state).then(move |result| -> Box<HandlerFuture> {
+            let (state, response) = match result {
+                Ok(ok) => ok,
+                Err((state, err)) => {
+                    flight.land(Err(err.status()));
+                    return Box::new(future::err((state, err)));
+                }
+            };
+
+            let buffered = response
+                .body()
+                .content_length()
+                .map(|len| len <= self.max_size)
+                .unwrap_or(false);
+
+            if !buffered {
+                request_trace!(&state, "not sharing a response too large to buffer");
+
+                // the waiting requests are woken to run the chain themselves
+                drop(flight);
+                return Box::new(future::ok((state, response)));
+            }
+
+            let (parts, body) = response.into_parts();
+            let f = body.concat2().then(move |body| match body {
+                Ok(body) => {
+                    let shared = Arc::new(Shared {
+                        status: parts.status,
+                        version: parts.version,
+                        headers: parts.headers.clone(),
+                        body: body.into_bytes(),
+                    });
+
+                    let response = Response::from_parts(parts, Body::from(shared.body.clone()));
+                    flight.land(Ok(shared));
+                    future::ok((state, response))
+                }
+                Err(e) => {
+                    let err = e.into_handler_error();
+                    flight.land(Err(err.status()));
+                    future::err((state, err))
+                }
+            });
+
+            Box::new(f)
+        });
+
+        Box::new(f)
+    }
+}
+
+/// `NewMiddleware` trait implementation.
+impl NewMiddleware for CoalesceMiddleware {
+    type Instance = Self;
+
+    /// Clones the current middleware to a new instance.
+    fn new_middleware(&self) -> io::Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+/// The cause of the `HandlerError` returned for a coalesced request, when the request whose
+/// response it was waiting for failed. The `HandlerError` has the same status as the failure.
+#[derive(Debug)]
+pub struct CoalescedRequestFailed {
+    status: StatusCode,
+}
+
+impl CoalescedRequestFailed {
+    /// The status of the failed request.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+}
+
+impl Display for CoalescedRequestFailed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "coalesced request failed with {}", self.status)
+    }
+}
+
+impl Error for CoalescedRequestFailed {
+    fn description(&self) -> &str {
+        "coalesced request failed"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use hyper::header::{HeaderValue, ACCEPT, COOKIE, HOST, SET_COOKIE};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn state(uri: &str, accept: &'static str) -> State {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT, HeaderValue::from_static(accept));
+
+        let mut state = State::new();
+        state.put(Method::GET);
+        state.put(uri.parse::<Uri>().unwrap());
+        state.put(headers);
+        state
+    }
+
+    // A chain which counts its calls, and completes once `release` is sent to.
+    fn chain(
+        calls: &Arc<AtomicUsize>,
+        status: StatusCode,
+    ) -> (
+        oneshot::Sender<()>,
+        impl FnOnce(State) -> Box<HandlerFuture> + Send + 'static,
+    ) {
+        let (release, released) = oneshot::channel::<()>();
+        let calls = calls.clone();
+
+        let chain = move |state: State| -> Box<HandlerFuture> {
+            let n = calls.fetch_add(1, Ordering::SeqCst) + 1;
+            Box::new(released.then(move |_| {
+                if status.is_success() {
+                    let mut response = Response::new(Body::from(format!("call {}", n)));
+                    response
+                        .headers_mut()
+                        .insert(SET_COOKIE, HeaderValue::from_static("a=b"));
+                    Ok((state, response))
+                } else {
+                    let err = io::Error::new(io::ErrorKind::Other, "failed")
+                        .into_handler_error()
+                        .with_status(status);
+                    Err((state, err))
+                }
+            }))
+        };
+
+        (release, chain)
+    }
+
+    fn body(response: Response<Body>) -> String {
+        let body = response.into_body().concat2().wait().unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[test]
+    fn coalesces_identical_requests() {
+        let m = CoalesceMiddleware::new().with_header(ACCEPT);
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let (release, first) = chain(&calls, StatusCode::OK);
+        let first = m.clone().call(state("/a", "text/html"), first);
+
+        let (_, second) = chain(&calls, StatusCode::OK);
+        let second = m.clone().call(state("/a", "text/html"), second);
+
+        let (other, third) = chain(&calls, StatusCode::OK);
+        let third = m.clone().call(state("/a", "application/json"), third);
+
+        release.send(()).unwrap();
+        other.send(()).unwrap();
+
+        let (_, first) = first.wait().ok().unwrap();
+        assert!(first.headers().contains_key(SET_COOKIE));
+        assert_eq!(body(first), "call 1");
+
+        let (_, second) = second.wait().ok().unwrap();
+        assert!(!second.headers().contains_key(SET_COOKIE));
+        assert_eq!(body(second), "call 1");
+
+        let (_, third) = third.wait().ok().unwrap();
+        assert_eq!(body(third), "call 2");
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        // once the first request has completed, the next one runs the chain again
+        let (release, fourth) = chain(&calls, StatusCode::OK);
+        release.send(()).unwrap();
+        let (_, fourth) = m
+            .call(state("/a", "text/html"), fourth)
+            .wait()
+            .ok()
+            .unwrap();
+        assert_eq!(body(fourth), "call 3");
+    }
+
+    #[test]
+    fn runs_requests_with_credentials_separately() {
+        let m = CoalesceMiddleware::new();
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let with_cookie = |value: &'static str| {
+            let mut state = state("/a", "*/*");
+            HeaderMap::borrow_mut_from(&mut state).insert(COOKIE, HeaderValue::from_static(value));
+            state
+        };
+
+        let (first_release, first) = chain(&calls, StatusCode::OK);
+        let first = m.clone().call(with_cookie("user=alice"), first);
+        let (second_release, second) = chain(&calls, StatusCode::OK);
+        let second = m.clone().call(with_cookie("user=bob"), second);
+
+        first_release.send(()).unwrap();
+        second_release.send(()).unwrap();
+        assert_eq!(body(first.wait().ok().unwrap().1), "call 1");
+        assert_eq!(body(second.wait().ok().unwrap().1), "call 2");
+
+        // when the cookie is part of the key, requests with the same cookie are coalesced
+        let m = m.with_header(COOKIE);
+        let (release, third) = chain(&calls, StatusCode::OK);
+        let third = m.clone().call(with_cookie("user=alice"), third);
+        let (_, fourth) = chain(&calls, StatusCode::OK);
+        let fourth = m.clone().call(with_cookie("user=alice"), fourth);
+        let (other, fifth) = chain(&calls, StatusCode::OK);
+        let fifth = m.call(with_cookie("user=bob"), fifth);
+
+        release.send(()).unwrap();
+        other.send(()).unwrap();
+        assert_eq!(body(third.wait().ok().unwrap().1), "call 3");
+        assert_eq!(body(fourth.wait().ok().unwrap().1), "call 3");
+        assert_eq!(body(fifth.wait().ok().unwrap().1), "call 4");
+    }
+
+    #[test]
+    fn shares_failures_and_recovers_from_abandoned_requests() {
+        let m = CoalesceMiddleware::new();
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let (release, first) = chain(&calls, StatusCode::BAD_GATEWAY);
+        let first = m.clone().call(state("/a", "*/*"), first);
+        let (_, second) = chain(&calls, StatusCode::OK);
+        let second = m.clone().call(state("/a", "*/*"), second);
+
+        release.send(()).unwrap();
+        assert!(first.wait().is_err());
+        let (_, err) = second.wait().err().unwrap();
+        assert_eq!(err.status(), StatusCode::BAD_GATEWAY);
+        assert!(err.downcast_ref::<CoalescedRequestFailed>().is_some());
+
+        let (_, abandoned) = chain(&calls, StatusCode::OK);
+        let abandoned = m.clone().call(state("/b", "*/*"), abandoned);
+        let (release, waiting) = chain(&calls, StatusCode::OK);
+        let waiting = m.call(state("/b", "*/*"), waiting);
+
+        drop(abandoned);
+        release.send(()).unwrap();
+        let (_, response) = waiting.wait().ok().unwrap();
+        assert_eq!(body(response), "call 3");
+    }
+
+    #[test]
+    fn runs_requests_for_other_hosts_separately() {
+        let m = CoalesceMiddleware::new();
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let with_host = |host: &'static str| {
+            let mut state = state("/a", "*/*");
+            HeaderMap::borrow_mut_from(&mut state).insert(HOST, HeaderValue::from_static(host));
+            state
+        };
+
+        let (first_release, first) = chain(&calls, StatusCode::OK);
+        let first = m.clone().call(with_host("a.example.com"), first);
+        let (_, second) = chain(&calls, StatusCode::OK);
+        let second = m.clone().call(with_host("a.example.com"), second);
+        let (third_release, third) = chain(&calls, StatusCode::OK);
+        let third = m.call(with_host("b.example.com"), third);
+
+        first_release.send(()).unwrap();
+        third_release.send(()).unwrap();
+        assert_eq!(body(first.wait().ok().unwrap().1), "call 1");
+        assert_eq!(body(second.wait().ok().unwrap().1), "call 1");
+        assert_eq!(body(third.wait().ok().unwrap().1), "call 2");
+    }
+
+    #[test]
+    fn runs_requests_separately_when_response_is_too_large() {
+        let m = CoalesceMiddleware::new().with_max_size(4);
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let (first_release, first) = chain(&calls, StatusCode::OK);
+        let first = m.clone().call(state("/a", "*/*"), first);
+        let (second_release, second) = chain(&calls, StatusCode::OK);
+        let second = m.call(state("/a", "*/*"), second);
+
+        first_release.send(()).unwrap();
+        second_release.send(()).unwrap();
+        let (_, first) = first.wait().ok().unwrap();
+        assert!(first.headers().contains_key(SET_COOKIE));
+        assert_eq!(body(first), "call 1");
+        assert_eq!(body(second.wait().ok().unwrap().1), "call 2");
+    }
+}
@@ -0,0 +1,503 @@
+//! Response compression middleware, which encodes response bodies according to the
+//! `Accept-Encoding` header of the request.
+use brotli::CompressorWriter;
+use flate2::write::{GzEncoder, ZlibEncoder};
+use flate2::Compression;
+use futures::{future, Async, Future, Poll, Stream};
+use hyper::body::Payload;
+use hyper::header::{
+    HeaderMap, HeaderValue, ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_RANGE,
+    CONTENT_TYPE, ETAG, VARY,
+};
+use hyper::{Body, Chunk, Method, Response, StatusCode};
+use mime::{self, Mime};
+use std::error::Error as StdError;
+use std::io::{self, Write};
+use std::mem;
+use std::sync::{Arc, Mutex};
+
+use handler::HandlerFuture;
+use middleware::{Middleware, NewMiddleware};
+use state::{FromState, State};
+
+/// A content coding which can be applied by `CompressionMiddleware`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Encoding {
+    /// The `br` coding, as defined by RFC 7932.
+    Brotli,
+    /// The `gzip` coding, as defined by RFC 1952.
+    Gzip,
+    /// The `deflate` coding, being the zlib format defined by RFC 1950.
+    Deflate,
+}
+
+impl Encoding {
+    /// The token used for this coding in `Accept-Encoding` and `Content-Encoding`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+        }
+    }
+}
+
+/// Middleware binding which compresses response bodies.
+///
+/// A response is compressed when:
+///
+/// * The request accepts one of the configured encodings via `Accept-Encoding`. When several
+///   are equally preferred by the client, the earliest in the configured list is used, which is
+///   by default `br`, `gzip` and then `deflate`;
+/// * The `Content-Type` of the response is textual, such as `text/*`, JSON, JavaScript or XML;
+/// * The response is not already encoded, and is not a partial or empty response; and
+/// * The length of the body is at least the threshold, which is 1024 bytes by default. Bodies of
+///   unknown length, such as streaming bodies, are always compressed.
+///
+/// Compression is applied to the body as it is streamed, so the whole response is never buffered.
+/// Each chunk produced by the handler is flushed through the encoder, so streaming responses are
+/// still delivered incrementally.
+///
+/// The `Content-Length` header is removed from compressed responses, and a strong `ETag` is made
+/// weak. `Vary: Accept-Encoding` is added to every response with a compressible `Content-Type`,
+/// whether or not it was compressed, so that caches keep the encodings apart.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate flate2;
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// # extern crate mime;
+/// #
+/// # use std::io::Read;
+/// # use flate2::read::GzDecoder;
+/// # use hyper::{Body, Response, StatusCode};
+/// # use hyper::header::*;
+/// # use gotham::helpers::http::response::create_response;
+/// # use gotham::middleware::compression::CompressionMiddleware;
+/// # use gotham::pipeline::new_pipeline;
+/// # use gotham::pipeline::single::single_pipeline;
+/// # use gotham::router::Router;
+/// # use gotham::router::builder::*;
+/// # use gotham::state::State;
+/// # use gotham::test::TestServer;
+/// #
+/// fn handler(state: State) -> (State, Response<Body>) {
+///     let body = "Hello, world! ".repeat(100);
+///     let res = create_response(&state, StatusCode::OK, mime::TEXT_PLAIN, body);
+///     (state, res)
+/// }
+///
+/// fn router() -> Router {
+///     let (chain, pipelines) =
+///         single_pipeline(new_pipeline().add(CompressionMiddleware::default()).build());
+///
+///     build_router(chain, pipelines, |route| {
+///         route.get("/").to(handler);
+///     })
+/// }
+/// #
+/// # fn main() {
+/// #   let test_server = TestServer::new(router()).unwrap();
+/// #   let response = test_server.client()
+/// #       .get("http://example.com/")
+/// #       .with_header(ACCEPT_ENCODING, "gzip, deflate;q=0.5".parse().unwrap())
+/// #       .perform()
+/// #       .unwrap();
+/// #
+/// #   assert_eq!(response.status(), StatusCode::OK);
+/// #   assert_eq!(response.headers()[CONTENT_ENCODING], "gzip");
+/// #   assert_eq!(response.headers()[VARY], "accept-encoding");
+/// #   assert!(response.headers().get(CONTENT_LENGTH).is_none());
+/// #
+/// #   let compressed = response.read_body().unwrap();
+/// #   let mut body = String::new();
+/// #   GzDecoder::new(&compressed[..]).read_to_string(&mut body).unwrap();
+/// #   assert_eq!(body, "Hello, world! ".repeat(100));
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct CompressionMiddleware {
+    encodings: Vec<Encoding>,
+    threshold: u64,
+}
+
+impl Default for CompressionMiddleware {
+    fn default() -> CompressionMiddleware {
+        CompressionMiddleware {
+            encodings: vec![Encoding::Brotli, Encoding::Gzip, Encoding::Deflate],
+            threshold: 1024,
+        }
+    }
+}
+
+impl CompressionMiddleware {
+    /// Sets the encodings which may be applied, in order of preference.
+    pub fn with_encodings(self, encodings: Vec<Encoding>) -> CompressionMiddleware {
+        CompressionMiddleware { encodings, ..self }
+    }
+
+    /// Sets the minimum length in bytes of a body with a known length before it is compressed.
+    pub fn with_threshold(self, threshold: u64) -> CompressionMiddleware {
+        CompressionMiddleware { threshold, ..self }
+    }
+
+    fn compress(&self, response: Response<Body>, encoding: Option<Encoding>) -> Response<Body> {
+        if !is_compressible(response.headers()) {
+            return response;
+        }
+
+        let (mut parts, body) = response.into_parts();
+        parts
+            .headers
+            .append(VARY, HeaderValue::from_static("accept-encoding"));
+
+        let encoding = match encoding {
+            Some(encoding) => encoding,
+            None => return Response::from_parts(parts, body),
+        };
+
+        let length = parts
+            .headers
+            .get(CONTENT_LENGTH)
+            .and_then(|len| len.to_str().ok())
+            .and_then(|len| len.parse::<u64>().ok())
+            .or_else(|| body.content_length());
+
+        if parts.status == StatusCode::NO_CONTENT
+            || parts.status == StatusCode::NOT_MODIFIED
+            || parts.status == StatusCode::PARTIAL_CONTENT
+            || parts.headers.contains_key(CONTENT_ENCODING)
+            || parts.headers.contains_key(CONTENT_RANGE)
+            || length.map(|len| len < self.threshold).unwrap_or(false)
+        {
+            return Response::from_parts(parts, body);
+        }
+
+        parts.headers.remove(CONTENT_LENGTH);
+        parts.headers.insert(
+            CONTENT_ENCODING,
+            HeaderValue::from_static(encoding.as_str()),
+        );
+
+        let weakened = parts
+            .headers
+            .get(ETAG)
+            .and_then(|etag| etag.to_str().ok())
+            .filter(|etag| !etag.starts_with("W/"))
+            .and_then(|etag| format!("W/{}", etag).parse::<HeaderValue>().ok());
+
+        if let Some(etag) = weakened {
+            parts.headers.insert(ETAG, etag);
+        }
+
+        Response::from_parts(
+            parts,
+            Body::wrap_stream(CompressedBody::new(body, encoding)),
+        )
+    }
+}
+
+// Chooses the encoding to apply, from the `Accept-Encoding` header of the request.
+fn negotiate(headers: &HeaderMap, encodings: &[Encoding]) -> Option<Encoding> {
+    let accepted: Vec<(String, f32)> = headers
+        .get_all(ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|coding| {
+            let mut parts = coding.split(';');
+            let token = parts.next()?.trim().to_ascii_lowercase();
+            let q = parts
+                .filter_map(|param| {
+                    let mut kv = param.splitn(2, '=');
+                    match (kv.next()?.trim(), kv.next()?.trim()) {
+                        ("q", q) | ("Q", q) => q.parse::<f32>().ok(),
+                        _ => None,
+                    }
+                })
+                .next()
+                .unwrap_or(1.0);
+
+            if token.is_empty() {
+                None
+            } else {
+                Some((token, q))
+            }
+        })
+        .collect();
+
+    let wildcard = accepted
+        .iter()
+        .find(|&&(ref token, _)| token == "*")
+        .map(|&(_, q)| q);
+
+    let mut best: Option<(Encoding, f32)> = None;
+    for &encoding in encodings {
+        let q = accepted
+            .iter()
+            .find(|&&(ref token, _)| token == encoding.as_str())
+            .map(|&(_, q)| q)
+            .or(wildcard)
+            .unwrap_or(0.0);
+
+        if q > 0.0 && best.map(|(_, best_q)| q > best_q).unwrap_or(true) {
+            best = Some((encoding, q));
+        }
+    }
+
+    best.map(|(encoding, _)| encoding)
+}
+
+// Determines whether the `Content-Type` of a response is worth compressing.
+fn is_compressible(headers: &HeaderMap) -> bool {
+    let content_type = match headers
+        .get(CONTENT_TYPE)
+        .and_then(|ct| ct.to_str().ok())
+        .and_then(|ct| ct.parse::<Mime>().ok())
+    {
+        Some(content_type) => content_type,
+        None => return false,
+    };
+
+    let subtype = content_type.subtype();
+    let suffix = content_type.suffix();
+
+    content_type.type_() == mime::TEXT
+        || subtype == mime::JSON
+        || subtype == mime::JAVASCRIPT
+        || subtype == mime::XML
+        || suffix.map_or(false, |s| s == mime::JSON || s == mime::XML)
+}
+
+/// `Middleware` trait implementation.
+impl Middleware for CompressionMiddleware {
+    /// Compresses the response produced by the rest of the chain, when the request allows it.
+    fn call<Chain>(self, state: State, chain: Chain) -> Box<HandlerFuture>
+    where
+        Chain: FnOnce(State) -> Box<HandlerFuture>,
+    {
+        let encoding = if *Method::borrow_from(&state) == Method::HEAD {
+            None
+        } else {
+            negotiate(HeaderMap::borrow_from(&state), &self.encodings)
+        };
+
+        let f = chain(state).and_then(move |(state, response)| {
+            let response = self.compress(response, encoding);
+            future::ok((state, response))
+        });
+
+        Box::new(f)
+    }
+}
+
+/// `NewMiddleware` trait implementation.
+impl NewMiddleware for CompressionMiddleware {
+    type Instance = Self;
+
+    /// Clones the current middleware to a new instance.
+    fn new_middleware(&self) -> io::Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+// The destination of an `Encoder`, from which compressed output is taken after each write.
+#[derive(Clone, Default)]
+struct Output(Arc<Mutex<Vec<u8>>>);
+
+impl Output {
+    fn take(&self) -> Vec<u8> {
+        mem::replace(&mut *self.0.lock().unwrap(), Vec::new())
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+enum Encoder {
+    Brotli(Box<CompressorWriter<Output>>),
+    Gzip(GzEncoder<Output>),
+    Deflate(ZlibEncoder<Output>),
+}
+
+impl Encoder {
+    fn new(encoding: Encoding, output: Output) -> Encoder {
+        match encoding {
+            Encoding::Brotli => {
+                Encoder::Brotli(Box::new(CompressorWriter::new(output, 4096, 5, 22)))
+            }
+            Encoding::Gzip => Encoder::Gzip(GzEncoder::new(output, Compression::default())),
+            Encoding::Deflate => Encoder::Deflate(ZlibEncoder::new(output, Compression::default())),
+        }
+    }
+
+    // Compresses `data`, and flushes it to the output.
+    fn encode(&mut self, data: &[u8]) -> io::Result<()> {
+        let writer: &mut Write = match *self {
+            Encoder::Brotli(ref mut w) => w,
+            Encoder::Gzip(ref mut w) => w,
+            Encoder::Deflate(ref mut w) => w,
+        };
+
+        writer.write_all(data)?;
+        writer.flush()
+    }
+
+    // Completes the compressed stream, writing any trailer to the output.
+    fn finish(self) -> io::Result<()> {
+        match self {
+            Encoder::Brotli(w) => (*w).into_inner(),
+            Encoder::Gzip(w) => w.finish()?,
+            Encoder::Deflate(w) => w.finish()?,
+        };
+        Ok(())
+    }
+}
+
+// A `Body` being compressed as it is streamed.
+struct CompressedBody {
+    body: Body,
+    encoder: Option<Encoder>,
+    output: Output,
+}
+
+impl CompressedBody {
+    fn new(body: Body, encoding: Encoding) -> CompressedBody {
+        let output = Output::default();
+        CompressedBody {
+            body,
+            encoder: Some(Encoder::new(encoding, output.clone())),
+            output,
+        }
+    }
+}
+
+impl Stream for CompressedBody {
+    type Item = Chunk;
+    type Error = Box<StdError + Send + Sync>;
+
+    fn poll(&mut self) -> Poll<Option<Chunk>, Self::Error> {
+        loop {
+            if self.encoder.is_none() {
+                return Ok(Async::Ready(None));
+            }
+
+            match try_ready!(self.body.poll()) {
+                Some(chunk) => {
+                    if let Some(ref mut encoder) = self.encoder {
+                        encoder.encode(&chunk)?;
+                    }
+                }
+                None => {
+                    if let Some(encoder) = self.encoder.take() {
+                        encoder.finish()?;
+                    }
+                }
+            }
+
+            let data = self.output.take();
+            if !data.is_empty() {
+                return Ok(Async::Ready(Some(Chunk::from(data))));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use flate2::read::{GzDecoder, ZlibDecoder};
+    use std::io::Read;
+
+    fn accepting(value: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT_ENCODING, HeaderValue::from_static(value));
+        headers
+    }
+
+    fn compressed(encoding: Encoding, chunks: Vec<&'static str>) -> Vec<u8> {
+        let body = Body::wrap_stream(::futures::stream::iter_ok::<_, io::Error>(chunks));
+        CompressedBody::new(body, encoding)
+            .concat2()
+            .wait()
+            .unwrap()
+            .to_vec()
+    }
+
+    #[test]
+    fn negotiates_encoding() {
+        let all = [Encoding::Brotli, Encoding::Gzip, Encoding::Deflate];
+
+        assert_eq!(negotiate(&HeaderMap::new(), &all), None);
+        assert_eq!(negotiate(&accepting("identity"), &all), None);
+        assert_eq!(
+            negotiate(&accepting("gzip, br"), &all),
+            Some(Encoding::Brotli)
+        );
+        assert_eq!(
+            negotiate(&accepting("gzip, br;q=0.5"), &all),
+            Some(Encoding::Gzip)
+        );
+        assert_eq!(
+            negotiate(&accepting("*;q=0.1, deflate"), &all),
+            Some(Encoding::Deflate)
+        );
+        assert_eq!(
+            negotiate(&accepting("*, br;q=0"), &all),
+            Some(Encoding::Gzip)
+        );
+        assert_eq!(negotiate(&accepting("br"), &[Encoding::Gzip]), None);
+    }
+
+    #[test]
+    fn detects_compressible_types() {
+        let compressible = |ct: &'static str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(CONTENT_TYPE, HeaderValue::from_static(ct));
+            is_compressible(&headers)
+        };
+
+        assert!(compressible("text/html; charset=utf-8"));
+        assert!(compressible("application/json"));
+        assert!(compressible("application/problem+json"));
+        assert!(compressible("image/svg+xml"));
+        assert!(!compressible("image/png"));
+        assert!(!compressible("application/octet-stream"));
+        assert!(!is_compressible(&HeaderMap::new()));
+    }
+
+    #[test]
+    fn compresses_streaming_bodies() {
+        let mut body = String::new();
+        GzDecoder::new(&compressed(Encoding::Gzip, vec!["Hello, ", "world!"])[..])
+            .read_to_string(&mut body)
+            .unwrap();
+        assert_eq!(body, "Hello, world!");
+
+        let mut body = String::new();
+        ZlibDecoder::new(&compressed(Encoding::Deflate, vec!["Hello, ", "world!"])[..])
+            .read_to_string(&mut body)
+            .unwrap();
+        assert_eq!(body, "Hello, world!");
+
+        let mut body = String::new();
+        ::brotli::Decompressor::new(
+            &compressed(Encoding::Brotli, vec!["Hello, world!"])[..],
+            4096,
+        )
+        .read_to_string(&mut body)
+        .unwrap();
+        assert_eq!(body, "Hello, world!");
+    }
+}
@@ -0,0 +1,202 @@
+//! Conditional middleware, which applies another middleware to only some requests.
+use hyper::header::{HeaderMap, HeaderName};
+use hyper::{Method, Uri};
+use std::io;
+use std::panic::RefUnwindSafe;
+
+use handler::HandlerFuture;
+use middleware::{Middleware, NewMiddleware};
+use state::{FromState, State};
+
+/// Middleware binding which runs an inner middleware only for requests that satisfy a predicate,
+/// and passes all other requests directly to the rest of the chain.
+///
+/// This allows expensive middleware, such as sessions or authentication, to be skipped for some
+/// traffic without defining a separate pipeline, such as skipping sessions for requests for
+/// static assets. The predicate is evaluated against the `State` when the request reaches this
+/// point in the pipeline, so it can use anything put into `State` by earlier middleware as well as
+/// the request itself. The `path_prefix`, `method` and `header` functions provide common
+/// predicates.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// #
+/// # use hyper::{Body, Response, StatusCode};
+/// # use gotham::middleware::auth::AuthMiddleware;
+/// # use gotham::middleware::conditional::{path_prefix, ConditionalMiddleware};
+/// # use gotham::pipeline::new_pipeline;
+/// # use gotham::pipeline::single::single_pipeline;
+/// # use gotham::router::Router;
+/// # use gotham::router::builder::*;
+/// # use gotham::state::State;
+/// # use gotham::test::TestServer;
+/// #
+/// fn handler(state: State) -> (State, Response<Body>) {
+///     (state, Response::new(Body::from("Hello, world!")))
+/// }
+///
+/// fn router() -> Router {
+///     let auth = AuthMiddleware::bearer(|token: &str| {
+///         if token == "secret" {
+///             Some("admin".to_owned())
+///         } else {
+///             None
+///         }
+///     });
+///
+///     let (chain, pipelines) = single_pipeline(
+///         new_pipeline()
+///             .add(ConditionalMiddleware::new(auth, path_prefix("/admin")))
+///             .build(),
+///     );
+///
+///     build_router(chain, pipelines, |route| {
+///         route.get("/").to(handler);
+///         route.get("/admin").to(handler);
+///     })
+/// }
+/// #
+/// # fn main() {
+/// #   let test_server = TestServer::new(router()).unwrap();
+/// #
+/// #   let response = test_server.client().get("http://example.com/").perform().unwrap();
+/// #   assert_eq!(response.status(), StatusCode::OK);
+/// #
+/// #   let response = test_server.client().get("http://example.com/admin").perform().unwrap();
+/// #   assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct ConditionalMiddleware<M, P> {
+    middleware: M,
+    predicate: P,
+}
+
+impl<M, P> ConditionalMiddleware<M, P>
+where
+    P: Fn(&State) -> bool,
+{
+    /// Creates a `ConditionalMiddleware` which runs `middleware` only for requests for which
+    /// `predicate` returns `true`.
+    pub fn new(middleware: M, predicate: P) -> ConditionalMiddleware<M, P> {
+        ConditionalMiddleware {
+            middleware,
+            predicate,
+        }
+    }
+}
+
+/// `NewMiddleware` trait implementation.
+impl<M, P> NewMiddleware for ConditionalMiddleware<M, P>
+where
+    M: NewMiddleware,
+    P: Fn(&State) -> bool + Clone + Send + Sync + RefUnwindSafe + 'static,
+{
+    type Instance = ConditionalMiddleware<M::Instance, P>;
+
+    /// Creates an instance of the inner middleware, to be used if the predicate passes.
+    fn new_middleware(&self) -> io::Result<Self::Instance> {
+        Ok(ConditionalMiddleware {
+            middleware: self.middleware.new_middleware()?,
+            predicate: self.predicate.clone(),
+        })
+    }
+}
+
+/// `Middleware` trait implementation.
+impl<M, P> Middleware for ConditionalMiddleware<M, P>
+where
+    M: Middleware,
+    P: Fn(&State) -> bool,
+{
+    /// Calls the inner middleware if the predicate passes, or the rest of the chain otherwise.
+    fn call<Chain>(self, state: State, chain: Chain) -> Box<HandlerFuture>
+    where
+        Chain: FnOnce(State) -> Box<HandlerFuture> + Send + 'static,
+    {
+        if (self.predicate)(&state) {
+            self.middleware.call(state, chain)
+        } else {
+            request_trace!(&state, "skipping conditional middleware");
+            chain(state)
+        }
+    }
+}
+
+/// A predicate which passes for requests whose path is `prefix`, or begins with `prefix` followed
+/// by a `/`.
+pub fn path_prefix(prefix: &str) -> impl Fn(&State) -> bool + Clone + Send + Sync + RefUnwindSafe {
+    let prefix = prefix.trim_end_matches('/').to_owned();
+
+    move |state: &State| {
+        let path = Uri::borrow_from(state).path();
+        path.starts_with(&prefix)
+            && (path.len() == prefix.len() || path[prefix.len()..].starts_with('/'))
+    }
+}
+
+/// A predicate which passes for requests using one of `methods`.
+pub fn method(
+    methods: Vec<Method>,
+) -> impl Fn(&State) -> bool + Clone + Send + Sync + RefUnwindSafe {
+    move |state: &State| methods.contains(Method::borrow_from(state))
+}
+
+/// A predicate which passes for requests which include the header `name`.
+pub fn header(name: HeaderName) -> impl Fn(&State) -> bool + Clone + Send + Sync + RefUnwindSafe {
+    move |state: &State| HeaderMap::borrow_from(state).contains_key(&name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use hyper::header::AUTHORIZATION;
+
+    fn state(method: Method, uri: &str, headers: HeaderMap) -> State {
+        let mut state = State::new();
+        state.put(method);
+        state.put(uri.parse::<Uri>().unwrap());
+        state.put(headers);
+        state
+    }
+
+    #[test]
+    fn matches_path_prefix() {
+        let admin = path_prefix("/admin/");
+
+        assert!(admin(&state(Method::GET, "/admin", HeaderMap::new())));
+        assert!(admin(&state(
+            Method::GET,
+            "/admin/users?page=2",
+            HeaderMap::new()
+        )));
+        assert!(!admin(&state(
+            Method::GET,
+            "/administrator",
+            HeaderMap::new()
+        )));
+        assert!(!admin(&state(Method::GET, "/", HeaderMap::new())));
+
+        let all = path_prefix("/");
+        assert!(all(&state(Method::GET, "/", HeaderMap::new())));
+        assert!(all(&state(Method::GET, "/assets/app.js", HeaderMap::new())));
+    }
+
+    #[test]
+    fn matches_method_and_header() {
+        let writes = method(vec![Method::POST, Method::DELETE]);
+        assert!(writes(&state(Method::POST, "/", HeaderMap::new())));
+        assert!(!writes(&state(Method::GET, "/", HeaderMap::new())));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, "Bearer token".parse().unwrap());
+
+        let authorized = header(AUTHORIZATION);
+        assert!(authorized(&state(Method::GET, "/", headers)));
+        assert!(!authorized(&state(Method::GET, "/", HeaderMap::new())));
+    }
+}
@@ -0,0 +1,500 @@
+//! Middleware implementing Cross-Origin Resource Sharing (CORS), for routes which are requested by
+//! browsers from other origins.
+//!
+//! Both preflight requests and the actual requests which follow them are handled. A preflight
+//! request is only routed through the middleware when the route accepts `OPTIONS` requests, and
+//! the handler is never invoked for it. For other routes, the `Router` answers the preflight
+//! request itself, using the `CorsMiddleware` given to `RouterBuilder::cors`, so the same
+//! configuration should be given there as well as to the pipeline.
+use futures::{future, Future};
+use hyper::header::{
+    HeaderMap, HeaderName, HeaderValue, ACCESS_CONTROL_ALLOW_CREDENTIALS,
+    ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN,
+    ACCESS_CONTROL_EXPOSE_HEADERS, ACCESS_CONTROL_MAX_AGE, ACCESS_CONTROL_REQUEST_HEADERS,
+    ACCESS_CONTROL_REQUEST_METHOD, ORIGIN, VARY,
+};
+use hyper::{Body, Method, Response, StatusCode};
+use std::io;
+use std::panic::RefUnwindSafe;
+use std::sync::Arc;
+use std::time::Duration;
+
+use handler::HandlerFuture;
+use helpers::http::response::create_empty_response;
+use middleware::{Middleware, NewMiddleware};
+use state::{FromState, State};
+
+/// Middleware binding for Cross-Origin Resource Sharing.
+///
+/// By default, requests from any origin are allowed with the `GET`, `HEAD` and `POST` methods, no
+/// additional request headers are allowed, and credentials are not supported. Each of these can be
+/// configured using the `with_*` functions.
+///
+/// Requests from origins which are not allowed are passed through without any CORS headers, which
+/// prevents the browser from exposing the response. Preflight requests which are not allowed are
+/// answered with `403 Forbidden`.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// #
+/// # use std::time::Duration;
+/// # use hyper::{Body, Method, Response, StatusCode};
+/// # use hyper::header::*;
+/// # use gotham::middleware::cors::CorsMiddleware;
+/// # use gotham::pipeline::new_pipeline;
+/// # use gotham::pipeline::single::single_pipeline;
+/// # use gotham::router::Router;
+/// # use gotham::router::builder::*;
+/// # use gotham::state::State;
+/// # use gotham::test::TestServer;
+/// #
+/// # fn handler(state: State) -> (State, Response<Body>) {
+/// #   (state, Response::new(Body::from("data")))
+/// # }
+/// #
+/// fn router() -> Router {
+///     let cors = CorsMiddleware::default()
+///         .with_origins(vec!["https://example.com", "https://*.example.org"])
+///         .with_methods(vec![Method::GET, Method::PUT])
+///         .with_headers(vec![CONTENT_TYPE])
+///         .with_exposed_headers(vec![ETAG])
+///         .with_credentials()
+///         .with_max_age(Duration::from_secs(3600));
+///
+///     let (chain, pipelines) = single_pipeline(new_pipeline().add(cors.clone()).build());
+///
+///     build_router(chain, pipelines, |route| {
+///         // answers preflight requests for routes which don't accept `OPTIONS`
+///         route.cors(cors);
+///         route.request(vec![Method::GET, Method::PUT], "/data").to(handler);
+///     })
+/// }
+/// #
+/// # fn main() {
+/// #   let test_server = TestServer::new(router()).unwrap();
+/// #
+/// #   let response = test_server.client()
+/// #       .options("http://api.example.com/data")
+/// #       .with_header(ORIGIN, "https://app.example.org".parse().unwrap())
+/// #       .with_header(ACCESS_CONTROL_REQUEST_METHOD, "PUT".parse().unwrap())
+/// #       .with_header(ACCESS_CONTROL_REQUEST_HEADERS, "content-type".parse().unwrap())
+/// #       .perform()
+/// #       .unwrap();
+/// #   assert_eq!(response.status(), StatusCode::NO_CONTENT);
+/// #   {
+/// #       let headers = response.headers();
+/// #       assert_eq!(headers[ACCESS_CONTROL_ALLOW_ORIGIN], "https://app.example.org");
+/// #       assert_eq!(headers[ACCESS_CONTROL_ALLOW_METHODS], "GET, PUT");
+/// #       assert_eq!(headers[ACCESS_CONTROL_ALLOW_HEADERS], "content-type");
+/// #       assert_eq!(headers[ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+/// #       assert_eq!(headers[ACCESS_CONTROL_MAX_AGE], "3600");
+/// #   }
+/// #
+/// #   let response = test_server.client()
+/// #       .get("http://api.example.com/data")
+/// #       .with_header(ORIGIN, "https://example.com".parse().unwrap())
+/// #       .perform()
+/// #       .unwrap();
+/// #   assert_eq!(response.status(), StatusCode::OK);
+/// #   assert_eq!(response.headers()[ACCESS_CONTROL_ALLOW_ORIGIN], "https://example.com");
+/// #   assert_eq!(response.headers()[ACCESS_CONTROL_EXPOSE_HEADERS], "etag");
+/// #
+/// #   let response = test_server.client()
+/// #       .get("http://api.example.com/data")
+/// #       .with_header(ORIGIN, "https://example.net".parse().unwrap())
+/// #       .perform()
+/// #       .unwrap();
+/// #   assert_eq!(response.status(), StatusCode::OK);
+/// #   assert!(response.headers().get(ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct CorsMiddleware {
+    config: Arc<Config>,
+}
+
+#[derive(Clone)]
+struct Config {
+    origins: Origins,
+    methods: Vec<Method>,
+    headers: Headers,
+    exposed_headers: Vec<HeaderName>,
+    credentials: bool,
+    max_age: Option<Duration>,
+}
+
+#[derive(Clone)]
+enum Origins {
+    Any,
+    List(Vec<String>),
+    Predicate(Arc<Fn(&str) -> bool + Send + Sync + RefUnwindSafe>),
+}
+
+#[derive(Clone)]
+enum Headers {
+    Any,
+    List(Vec<HeaderName>),
+}
+
+impl Default for CorsMiddleware {
+    fn default() -> CorsMiddleware {
+        CorsMiddleware {
+            config: Arc::new(Config {
+                origins: Origins::Any,
+                methods: vec![Method::GET, Method::HEAD, Method::POST],
+                headers: Headers::List(vec![]),
+                exposed_headers: vec![],
+                credentials: false,
+                max_age: None,
+            }),
+        }
+    }
+}
+
+impl CorsMiddleware {
+    /// Allows requests only from the given origins, such as `https://example.com`. An origin may
+    /// contain a single `*` to match any sequence of characters, such as `https://*.example.com`
+    /// to allow all subdomains. Origins are compared case-insensitively.
+    pub fn with_origins<S>(self, origins: Vec<S>) -> CorsMiddleware
+    where
+        S: AsRef<str>,
+    {
+        let origins = origins
+            .iter()
+            .map(|o| o.as_ref().to_ascii_lowercase())
+            .collect();
+
+        self.configure(|config| config.origins = Origins::List(origins))
+    }
+
+    /// Allows requests from origins for which `predicate` returns `true`.
+    pub fn with_origin_predicate<F>(self, predicate: F) -> CorsMiddleware
+    where
+        F: Fn(&str) -> bool + Send + Sync + RefUnwindSafe + 'static,
+    {
+        self.configure(|config| config.origins = Origins::Predicate(Arc::new(predicate)))
+    }
+
+    /// Sets the methods which may be used for cross-origin requests.
+    pub fn with_methods(self, methods: Vec<Method>) -> CorsMiddleware {
+        self.configure(|config| config.methods = methods)
+    }
+
+    /// Sets the request headers which may be sent with cross-origin requests, beyond those which
+    /// are always allowed by browsers.
+    pub fn with_headers(self, headers: Vec<HeaderName>) -> CorsMiddleware {
+        self.configure(|config| config.headers = Headers::List(headers))
+    }
+
+    /// Allows any request headers to be sent with cross-origin requests.
+    pub fn with_any_header(self) -> CorsMiddleware {
+        self.configure(|config| config.headers = Headers::Any)
+    }
+
+    /// Sets the response headers which the browser will expose to the requesting script, beyond
+    /// those which are always exposed.
+    pub fn with_exposed_headers(self, headers: Vec<HeaderName>) -> CorsMiddleware {
+        self.configure(|config| config.exposed_headers = headers)
+    }
+
+    /// Allows cross-origin requests to include credentials, such as cookies. The requesting
+    /// origin is always sent in `Access-Control-Allow-Origin` when credentials are allowed, as
+    /// browsers reject the `*` wildcard in that case.
+    pub fn with_credentials(self) -> CorsMiddleware {
+        self.configure(|config| config.credentials = true)
+    }
+
+    /// Sets how long the browser may cache the result of a preflight request.
+    pub fn with_max_age(self, max_age: Duration) -> CorsMiddleware {
+        self.configure(|config| config.max_age = Some(max_age))
+    }
+
+    fn configure<F>(mut self, f: F) -> CorsMiddleware
+    where
+        F: FnOnce(&mut Config),
+    {
+        f(Arc::make_mut(&mut self.config));
+        self
+    }
+
+    /// Answers a preflight request on behalf of the `Router`, for a path whose routes accept
+    /// `methods`. Returns `None` when the request has no `Origin`, and so isn't a CORS request.
+    pub(crate) fn route_preflight(
+        &self,
+        state: &State,
+        methods: &[Method],
+    ) -> Option<Response<Body>> {
+        let origin = HeaderMap::borrow_from(state).get(ORIGIN)?.clone();
+
+        match requested_method(HeaderMap::borrow_from(state)) {
+            Some(ref method) if methods.contains(method) => Some(self.preflight(state, &origin)),
+            _ => {
+                request_trace!(state, "rejecting CORS preflight for an unrouted method");
+                Some(create_empty_response(state, StatusCode::FORBIDDEN))
+            }
+        }
+    }
+
+    // Answers a preflight request, with `403 Forbidden` when it's not allowed.
+    fn preflight(&self, state: &State, origin: &HeaderValue) -> Response<Body> {
+        let allowed = origin
+            .to_str()
+            .map(|o| self.config.allows_origin(o))
+            .unwrap_or(false);
+
+        let res = if allowed {
+            self.config.preflight(state, origin)
+        } else {
+            None
+        };
+
+        res.unwrap_or_else(|| {
+            request_trace!(state, "rejecting CORS preflight request");
+            create_empty_response(state, StatusCode::FORBIDDEN)
+        })
+    }
+}
+
+// The method of the actual request, as given in a preflight request.
+fn requested_method(headers: &HeaderMap) -> Option<Method> {
+    headers
+        .get(ACCESS_CONTROL_REQUEST_METHOD)
+        .and_then(|m| m.to_str().ok())
+        .and_then(|m| m.parse::<Method>().ok())
+}
+
+impl Config {
+    fn allows_origin(&self, origin: &str) -> bool {
+        match self.origins {
+            Origins::Any => true,
+            Origins::List(ref origins) => {
+                let origin = origin.to_ascii_lowercase();
+                origins
+                    .iter()
+                    .any(|allowed| origin_matches(allowed, &origin))
+            }
+            Origins::Predicate(ref predicate) => predicate(origin),
+        }
+    }
+
+    // The value of `Access-Control-Allow-Origin` for an allowed origin.
+    fn allow_origin_value(&self, origin: &HeaderValue) -> HeaderValue {
+        match self.origins {
+            Origins::Any if !self.credentials => HeaderValue::from_static("*"),
+            _ => origin.clone(),
+        }
+    }
+
+    // Adds the headers common to preflight and actual responses.
+    fn add_origin_headers(&self, headers: &mut HeaderMap, origin: &HeaderValue) {
+        let value = self.allow_origin_value(origin);
+        if value != "*" {
+            headers.append(VARY, HeaderValue::from_static("Origin"));
+        }
+        headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, value);
+
+        if self.credentials {
+            headers.insert(
+                ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                HeaderValue::from_static("true"),
+            );
+        }
+    }
+
+    // Creates the response to a preflight request, or `None` if the request is not allowed.
+    fn preflight(&self, state: &State, origin: &HeaderValue) -> Option<Response<Body>> {
+        let headers = HeaderMap::borrow_from(state);
+        let method = requested_method(headers)?;
+
+        if !self.methods.contains(&method) {
+            return None;
+        }
+
+        let requested_headers = headers
+            .get_all(ACCESS_CONTROL_REQUEST_HEADERS)
+            .iter()
+            .filter_map(|h| h.to_str().ok())
+            .flat_map(|h| h.split(','))
+            .map(|h| h.trim().to_ascii_lowercase())
+            .filter(|h| !h.is_empty())
+            .collect::<Vec<_>>();
+
+        if let Headers::List(ref allowed) = self.headers {
+            if !requested_headers
+                .iter()
+                .all(|h| allowed.iter().any(|a| a.as_str() == h))
+            {
+                return None;
+            }
+        }
+
+        let mut res = create_empty_response(state, StatusCode::NO_CONTENT);
+        {
+            let res_headers = res.headers_mut();
+            self.add_origin_headers(res_headers, origin);
+
+            let methods = self
+                .methods
+                .iter()
+                .map(|m| m.as_str())
+                .collect::<Vec<_>>()
+                .join(", ");
+            res_headers.insert(ACCESS_CONTROL_ALLOW_METHODS, methods.parse().unwrap());
+
+            if !requested_headers.is_empty() {
+                res_headers.insert(
+                    ACCESS_CONTROL_ALLOW_HEADERS,
+                    requested_headers.join(", ").parse().unwrap(),
+                );
+            }
+
+            if let Some(max_age) = self.max_age {
+                res_headers.insert(ACCESS_CONTROL_MAX_AGE, max_age.as_secs().into());
+            }
+        }
+
+        Some(res)
+    }
+}
+
+// Matches an origin against an allowed origin, which may contain a single `*` wildcard.
+fn origin_matches(allowed: &str, origin: &str) -> bool {
+    match allowed.find('*') {
+        Some(n) => {
+            let (prefix, suffix) = (&allowed[..n], &allowed[n + 1..]);
+            origin.len() > prefix.len() + suffix.len()
+                && origin.starts_with(prefix)
+                && origin.ends_with(suffix)
+        }
+        None => allowed == origin,
+    }
+}
+
+/// `Middleware` trait implementation.
+impl Middleware for CorsMiddleware {
+    /// Answers preflight requests, and attaches CORS headers to other responses.
+    fn call<Chain>(self, state: State, chain: Chain) -> Box<HandlerFuture>
+    where
+        Chain: FnOnce(State) -> Box<HandlerFuture>,
+    {
+        let origin = match HeaderMap::borrow_from(&state).get(ORIGIN) {
+            Some(origin) => origin.clone(),
+            None => return chain(state),
+        };
+
+        let is_preflight = *Method::borrow_from(&state) == Method::OPTIONS
+            && HeaderMap::borrow_from(&state).contains_key(ACCESS_CONTROL_REQUEST_METHOD);
+
+        if is_preflight {
+            let res = self.preflight(&state, &origin);
+            return Box::new(future::ok((state, res)));
+        }
+
+        let allowed = origin
+            .to_str()
+            .map(|o| self.config.allows_origin(o))
+            .unwrap_or(false);
+
+        if !allowed {
+            request_trace!(&state, "origin not allowed by CORS");
+            return chain(state);
+        }
+
+        let config = self.config;
+        let f = chain(state).and_then(move |(state, mut response)| {
+            {
+                let headers = response.headers_mut();
+                config.add_origin_headers(headers, &origin);
+
+                if !config.exposed_headers.is_empty() {
+                    let exposed = config
+                        .exposed_headers
+                        .iter()
+                        .map(|h| h.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    headers.insert(ACCESS_CONTROL_EXPOSE_HEADERS, exposed.parse().unwrap());
+                }
+            }
+            future::ok((state, response))
+        });
+
+        Box::new(f)
+    }
+}
+
+/// `NewMiddleware` trait implementation.
+impl NewMiddleware for CorsMiddleware {
+    type Instance = Self;
+
+    /// Clones the current middleware to a new instance.
+    fn new_middleware(&self) -> io::Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_origins() {
+        assert!(origin_matches("https://example.com", "https://example.com"));
+        assert!(!origin_matches(
+            "https://example.com",
+            "https://example.org"
+        ));
+
+        assert!(origin_matches(
+            "https://*.example.com",
+            "https://a.example.com"
+        ));
+        assert!(origin_matches(
+            "https://*.example.com",
+            "https://a.b.example.com"
+        ));
+        assert!(!origin_matches(
+            "https://*.example.com",
+            "https://example.com"
+        ));
+        assert!(!origin_matches(
+            "https://*.example.com",
+            "http://a.example.com"
+        ));
+        assert!(!origin_matches(
+            "https://*.example.com",
+            "https://.example.com"
+        ));
+    }
+
+    #[test]
+    fn allows_configured_origins() {
+        let cors = CorsMiddleware::default();
+        assert!(cors.config.allows_origin("https://anything.example"));
+
+        let cors = cors.with_origins(vec!["https://Example.com"]);
+        assert!(cors.config.allows_origin("HTTPS://example.COM"));
+        assert!(!cors.config.allows_origin("https://example.org"));
+
+        let cors = cors.with_origin_predicate(|origin| origin.ends_with(".test"));
+        assert!(cors.config.allows_origin("http://app.test"));
+        assert!(!cors.config.allows_origin("https://example.com"));
+    }
+
+    #[test]
+    fn wildcard_origin_unless_credentials() {
+        let origin = HeaderValue::from_static("https://example.com");
+
+        let cors = CorsMiddleware::default();
+        assert_eq!(cors.config.allow_origin_value(&origin), "*");
+
+        let cors = cors.with_credentials();
+        assert_eq!(
+            cors.config.allow_origin_value(&origin),
+            "https://example.com"
+        );
+    }
+}
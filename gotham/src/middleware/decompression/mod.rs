@@ -0,0 +1,460 @@
+//! Request decompression middleware, which decodes request bodies according to their
+//! `Content-Encoding` header.
+use flate2::write::{GzDecoder, ZlibDecoder};
+use futures::{future, Async, Future, Poll, Stream};
+use hyper::header::{HeaderMap, HeaderValue, ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_LENGTH};
+use hyper::{Body, Chunk, StatusCode};
+use std::error::Error as StdError;
+use std::fmt::{self, Display};
+use std::io::{self, Write};
+use std::mem;
+use std::sync::{Arc, Mutex};
+
+use handler::HandlerFuture;
+use helpers::http::response::create_empty_response;
+use middleware::{Middleware, NewMiddleware};
+use state::{FromState, State};
+
+/// Middleware binding which decompresses request bodies sent with `Content-Encoding: gzip` or
+/// `Content-Encoding: deflate`, where `deflate` is the zlib format defined by RFC 1950.
+///
+/// The body is decompressed as it is read, so handlers and extractors see the original content
+/// without buffering the whole request. The `Content-Encoding` and `Content-Length` headers are
+/// removed from the request, since they no longer describe the body.
+///
+/// The decompressed body is limited to the number of bytes given to `DecompressionMiddleware::new`,
+/// so that a small request can't expand to exhaust memory. Once the limit is exceeded, reading the
+/// body fails with an error, and if the handler returns that error, it is sent with the
+/// `413 Payload Too Large` status. Likewise, a body which is not validly encoded is sent with the
+/// `400 Bad Request` status. A `RequestBodyLimit` added to the pipeline before this middleware
+/// limits the size of the body as it was sent instead.
+///
+/// A request using any other encoding is rejected with `415 Unsupported Media Type`, listing the
+/// supported encodings in the `Accept-Encoding` header of the response as described by RFC 7694.
+/// Requests without a `Content-Encoding`, or with `identity`, are passed on unchanged.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate flate2;
+/// # extern crate futures;
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// # extern crate mime;
+/// #
+/// # use std::io::Write;
+/// # use flate2::Compression;
+/// # use flate2::write::GzEncoder;
+/// # use futures::{future, Future, Stream};
+/// # use hyper::{Body, Response, StatusCode};
+/// # use hyper::header::CONTENT_ENCODING;
+/// # use gotham::handler::{HandlerFuture, IntoHandlerError};
+/// # use gotham::middleware::decompression::DecompressionMiddleware;
+/// # use gotham::pipeline::new_pipeline;
+/// # use gotham::pipeline::single::single_pipeline;
+/// # use gotham::router::Router;
+/// # use gotham::router::builder::*;
+/// # use gotham::state::{FromState, State};
+/// # use gotham::test::TestServer;
+/// #
+/// fn ingest(mut state: State) -> Box<HandlerFuture> {
+///     let f = Body::take_from(&mut state)
+///         .concat2()
+///         .then(|body| match body {
+///             Ok(body) => {
+///                 let res = Response::new(Body::from(body));
+///                 future::ok((state, res))
+///             }
+///             Err(e) => future::err((state, e.into_handler_error())),
+///         });
+///
+///     Box::new(f)
+/// }
+///
+/// fn router() -> Router {
+///     let (chain, pipelines) = single_pipeline(
+///         new_pipeline()
+///             .add(DecompressionMiddleware::new(1024 * 1024))
+///             .build(),
+///     );
+///
+///     build_router(chain, pipelines, |route| {
+///         route.post("/events").to(ingest);
+///     })
+/// }
+/// #
+/// # fn main() {
+/// #   let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+/// #   encoder.write_all(b"{\"event\":\"click\"}").unwrap();
+/// #   let compressed = encoder.finish().unwrap();
+/// #
+/// #   let test_server = TestServer::new(router()).unwrap();
+/// #   let response = test_server.client()
+/// #       .post("http://example.com/events", compressed, mime::APPLICATION_JSON)
+/// #       .with_header(CONTENT_ENCODING, "gzip".parse().unwrap())
+/// #       .perform()
+/// #       .unwrap();
+/// #
+/// #   assert_eq!(response.status(), StatusCode::OK);
+/// #   assert_eq!(response.read_body().unwrap(), b"{\"event\":\"click\"}");
+/// #
+/// #   let response = test_server.client()
+/// #       .post("http://example.com/events", "{}", mime::APPLICATION_JSON)
+/// #       .with_header(CONTENT_ENCODING, "compress".parse().unwrap())
+/// #       .perform()
+/// #       .unwrap();
+/// #
+/// #   assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct DecompressionMiddleware {
+    limit: u64,
+}
+
+impl DecompressionMiddleware {
+    /// Creates a `DecompressionMiddleware` which fails request bodies once more than `limit`
+    /// bytes have been decompressed.
+    pub fn new(limit: u64) -> DecompressionMiddleware {
+        DecompressionMiddleware { limit }
+    }
+}
+
+// A content coding which can be removed by `DecompressionMiddleware`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Encoding {
+    Gzip,
+    Deflate,
+}
+
+// Determines the encoding of a request body from its `Content-Encoding` headers, which is
+// `Ok(None)` when it was not encoded, and `Err(())` when it can't be decoded.
+fn encoding(headers: &HeaderMap) -> Result<Option<Encoding>, ()> {
+    let mut codings = Vec::new();
+    for value in headers.get_all(CONTENT_ENCODING) {
+        let value = value.to_str().map_err(|_| ())?;
+        for coding in value.split(',') {
+            match coding.trim().to_ascii_lowercase().as_str() {
+                "identity" | "" => (),
+                "gzip" | "x-gzip" => codings.push(Encoding::Gzip),
+                "deflate" => codings.push(Encoding::Deflate),
+                _ => return Err(()),
+            }
+        }
+    }
+
+    match codings.len() {
+        0 => Ok(None),
+        1 => Ok(Some(codings[0])),
+        _ => Err(()),
+    }
+}
+
+/// `Middleware` trait implementation.
+impl Middleware for DecompressionMiddleware {
+    /// Replaces an encoded request body with one which is decompressed as it is read, and
+    /// rejects requests with unsupported encodings.
+    fn call<Chain>(self, mut state: State, chain: Chain) -> Box<HandlerFuture>
+    where
+        Chain: FnOnce(State) -> Box<HandlerFuture>,
+    {
+        let encoding = match encoding(HeaderMap::borrow_from(&state)) {
+            Ok(Some(encoding)) => encoding,
+            Ok(None) => return chain(state),
+            Err(()) => {
+                request_debug!(&state, "rejecting request body with unsupported encoding");
+                let mut res = create_empty_response(&state, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+                res.headers_mut()
+                    .insert(ACCEPT_ENCODING, HeaderValue::from_static("gzip, deflate"));
+                return Box::new(future::ok((state, res)));
+            }
+        };
+
+        {
+            let headers = HeaderMap::borrow_mut_from(&mut state);
+            headers.remove(CONTENT_ENCODING);
+            headers.remove(CONTENT_LENGTH);
+        }
+
+        let failure = Arc::new(Mutex::new(None));
+        if let Some(body) = state.try_take::<Body>() {
+            state.put(Body::wrap_stream(DecompressedBody::new(
+                body,
+                encoding,
+                self.limit,
+                failure.clone(),
+            )));
+        }
+
+        let f = chain(state).then(move |result| match result {
+            Err((state, err)) => match *failure.lock().unwrap() {
+                Some(status) => {
+                    request_debug!(&state, "request body failed to decompress");
+                    Err((state, err.with_status(status)))
+                }
+                None => Err((state, err)),
+            },
+            result => result,
+        });
+
+        Box::new(f)
+    }
+}
+
+/// `NewMiddleware` trait implementation.
+impl NewMiddleware for DecompressionMiddleware {
+    type Instance = Self;
+
+    /// Clones the current middleware to a new instance.
+    fn new_middleware(&self) -> io::Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+// The destination of a `Decoder`, from which decompressed output is taken after each write. Writes
+// fail once more than the remaining number of bytes have been written.
+#[derive(Clone)]
+struct Output {
+    data: Arc<Mutex<Vec<u8>>>,
+    remaining: u64,
+}
+
+impl Output {
+    fn take(&self) -> Vec<u8> {
+        mem::replace(&mut *self.data.lock().unwrap(), Vec::new())
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = buf.len() as u64;
+        if len > self.remaining {
+            return Err(io::Error::new(io::ErrorKind::Other, BodyTooLarge));
+        }
+
+        self.remaining -= len;
+        self.data.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+enum Decoder {
+    Gzip(GzDecoder<Output>),
+    Deflate(ZlibDecoder<Output>),
+}
+
+impl Decoder {
+    fn new(encoding: Encoding, output: Output) -> Decoder {
+        match encoding {
+            Encoding::Gzip => Decoder::Gzip(GzDecoder::new(output)),
+            Encoding::Deflate => Decoder::Deflate(ZlibDecoder::new(output)),
+        }
+    }
+
+    // Decompresses `data`, and flushes it to the output.
+    fn decode(&mut self, data: &[u8]) -> io::Result<()> {
+        let writer: &mut Write = match *self {
+            Decoder::Gzip(ref mut w) => w,
+            Decoder::Deflate(ref mut w) => w,
+        };
+
+        writer.write_all(data)?;
+        writer.flush()
+    }
+
+    // Completes the decompressed stream, checking any trailer.
+    fn finish(self) -> io::Result<()> {
+        match self {
+            Decoder::Gzip(w) => w.finish()?,
+            Decoder::Deflate(w) => w.finish()?,
+        };
+        Ok(())
+    }
+}
+
+// A request `Body` being decompressed as it is read. When decompression fails, the status to send
+// the resulting error with is recorded in `failure`.
+struct DecompressedBody {
+    body: Body,
+    decoder: Option<Decoder>,
+    output: Output,
+    failure: Arc<Mutex<Option<StatusCode>>>,
+}
+
+impl DecompressedBody {
+    fn new(
+        body: Body,
+        encoding: Encoding,
+        limit: u64,
+        failure: Arc<Mutex<Option<StatusCode>>>,
+    ) -> DecompressedBody {
+        let output = Output {
+            data: Arc::new(Mutex::new(Vec::new())),
+            remaining: limit,
+        };
+
+        DecompressedBody {
+            body,
+            decoder: Some(Decoder::new(encoding, output.clone())),
+            output,
+            failure,
+        }
+    }
+
+    // Records the status for a decompression error, and stops decoding.
+    fn fail(&mut self, e: io::Error) -> Box<StdError + Send + Sync> {
+        self.decoder = None;
+
+        let too_large = e.get_ref().map_or(false, |e| e.is::<BodyTooLarge>());
+        *self.failure.lock().unwrap() = Some(if too_large {
+            StatusCode::PAYLOAD_TOO_LARGE
+        } else {
+            StatusCode::BAD_REQUEST
+        });
+
+        Box::new(e)
+    }
+}
+
+impl Stream for DecompressedBody {
+    type Item = Chunk;
+    type Error = Box<StdError + Send + Sync>;
+
+    fn poll(&mut self) -> Poll<Option<Chunk>, Self::Error> {
+        loop {
+            if self.decoder.is_none() {
+                return Ok(Async::Ready(None));
+            }
+
+            let result = match try_ready!(self.body.poll()) {
+                Some(chunk) => match self.decoder {
+                    Some(ref mut decoder) => decoder.decode(&chunk),
+                    None => Ok(()),
+                },
+                None => match self.decoder.take() {
+                    Some(decoder) => decoder.finish(),
+                    None => Ok(()),
+                },
+            };
+
+            if let Err(e) = result {
+                return Err(self.fail(e));
+            }
+
+            let data = self.output.take();
+            if !data.is_empty() {
+                return Ok(Async::Ready(Some(Chunk::from(data))));
+            }
+        }
+    }
+}
+
+// The error produced when decompressing a request body beyond the limit.
+#[derive(Debug)]
+struct BodyTooLarge;
+
+impl Display for BodyTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("decompressed request body exceeds the configured limit")
+    }
+}
+
+impl StdError for BodyTooLarge {
+    fn description(&self) -> &str {
+        "decompressed request body exceeds the configured limit"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use flate2::write::{GzEncoder, ZlibEncoder};
+    use flate2::Compression;
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn decompress(
+        encoding: Encoding,
+        chunks: Vec<Vec<u8>>,
+        limit: u64,
+    ) -> (Result<Vec<u8>, String>, Option<StatusCode>) {
+        let failure = Arc::new(Mutex::new(None));
+        let body = Body::wrap_stream(::futures::stream::iter_ok::<_, io::Error>(chunks));
+        let result = DecompressedBody::new(body, encoding, limit, failure.clone())
+            .concat2()
+            .wait()
+            .map(|body| body.to_vec())
+            .map_err(|e| e.to_string());
+
+        let failure = *failure.lock().unwrap();
+        (result, failure)
+    }
+
+    #[test]
+    fn parses_encoding() {
+        let headers = |value: &'static str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(CONTENT_ENCODING, HeaderValue::from_static(value));
+            headers
+        };
+
+        assert_eq!(encoding(&HeaderMap::new()), Ok(None));
+        assert_eq!(encoding(&headers("identity")), Ok(None));
+        assert_eq!(encoding(&headers("GZIP")), Ok(Some(Encoding::Gzip)));
+        assert_eq!(encoding(&headers("deflate")), Ok(Some(Encoding::Deflate)));
+        assert_eq!(encoding(&headers("br")), Err(()));
+        assert_eq!(encoding(&headers("gzip, gzip")), Err(()));
+    }
+
+    #[test]
+    fn decompresses_streaming_bodies() {
+        let compressed = gzip(b"Hello, world!");
+        let (first, second) = compressed.split_at(compressed.len() / 2);
+        let (result, failure) =
+            decompress(Encoding::Gzip, vec![first.to_vec(), second.to_vec()], 13);
+        assert_eq!(result.unwrap(), b"Hello, world!");
+        assert_eq!(failure, None);
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"Hello, world!").unwrap();
+        let compressed = encoder.finish().unwrap();
+        let (result, failure) = decompress(Encoding::Deflate, vec![compressed], 13);
+        assert_eq!(result.unwrap(), b"Hello, world!");
+        assert_eq!(failure, None);
+    }
+
+    #[test]
+    fn limits_decompressed_size() {
+        let compressed = gzip(&[0; 64 * 1024]);
+        assert!(compressed.len() < 1024);
+
+        let (result, failure) = decompress(Encoding::Gzip, vec![compressed], 1024);
+        assert_eq!(
+            result.unwrap_err(),
+            "decompressed request body exceeds the configured limit"
+        );
+        assert_eq!(failure, Some(StatusCode::PAYLOAD_TOO_LARGE));
+    }
+
+    #[test]
+    fn rejects_invalid_bodies() {
+        let (result, failure) = decompress(Encoding::Gzip, vec![b"not gzip".to_vec()], 1024);
+        assert!(result.is_err());
+        assert_eq!(failure, Some(StatusCode::BAD_REQUEST));
+
+        let mut truncated = gzip(b"Hello, world!");
+        truncated.truncate(truncated.len() - 4);
+        let (result, failure) = decompress(Encoding::Gzip, vec![truncated], 1024);
+        assert!(result.is_err());
+        assert_eq!(failure, Some(StatusCode::BAD_REQUEST));
+    }
+}
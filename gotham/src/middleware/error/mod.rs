@@ -0,0 +1,295 @@
+//! Error mapping middleware, which turns errors returned by handlers into consistent responses.
+use futures::{future, Future};
+use hyper::{Body, Response, StatusCode};
+use mime;
+use serde_json;
+use std::error::Error;
+use std::io;
+use std::panic::RefUnwindSafe;
+use std::sync::Arc;
+
+use handler::{HandlerError, HandlerFuture};
+use helpers::http::response::{create_empty_response, create_response};
+use middleware::{Middleware, NewMiddleware};
+use state::State;
+
+type Mapper = Fn(&HandlerError) -> Option<StatusCode> + Send + Sync + RefUnwindSafe;
+
+// The JSON body of an error response.
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    error: ErrorDetail<'a>,
+}
+
+#[derive(Serialize)]
+struct ErrorDetail<'a> {
+    status: u16,
+    message: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<String>,
+}
+
+/// Middleware binding which converts a `HandlerError` returned by the rest of the chain into a
+/// response, so that handlers can simply return their errors with `IntoHandlerError`.
+///
+/// The status of the response is the status of the `HandlerError`, unless it is changed by a
+/// mapping registered for the type of error which caused it. This allows an application to
+/// describe once how its own error types are presented, such as a `NotFound` error always
+/// becoming `404 Not Found`, rather than choosing a status at every point the error can occur.
+///
+/// Responses have a JSON body by default, of the form:
+///
+/// ```json
+/// { "error": { "status": 404, "message": "Not Found" } }
+/// ```
+///
+/// The `Display` form of the error is included as `detail` for client errors (`4xx`) when enabled
+/// via `with_details`. It is never included for server errors, so that internal details are not
+/// exposed. Server errors are logged at the `error` level, and client errors at `debug`.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate futures;
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// #
+/// # use std::error::Error;
+/// # use std::fmt;
+/// # use futures::future;
+/// # use hyper::StatusCode;
+/// # use gotham::handler::{HandlerFuture, IntoHandlerError};
+/// # use gotham::middleware::error::ErrorMiddleware;
+/// # use gotham::pipeline::new_pipeline;
+/// # use gotham::pipeline::single::single_pipeline;
+/// # use gotham::router::Router;
+/// # use gotham::router::builder::*;
+/// # use gotham::state::State;
+/// # use gotham::test::TestServer;
+/// #
+/// #[derive(Debug)]
+/// struct UnknownUser;
+///
+/// impl fmt::Display for UnknownUser {
+///     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+///         f.write_str("no such user")
+///     }
+/// }
+///
+/// impl Error for UnknownUser {
+///     fn description(&self) -> &str {
+///         "no such user"
+///     }
+/// }
+///
+/// fn show_user(state: State) -> Box<HandlerFuture> {
+///     Box::new(future::err((state, UnknownUser.into_handler_error())))
+/// }
+///
+/// fn router() -> Router {
+///     let middleware = ErrorMiddleware::new()
+///         .map::<UnknownUser, _>(|_| StatusCode::NOT_FOUND)
+///         .with_details(true);
+///
+///     let (chain, pipelines) = single_pipeline(new_pipeline().add(middleware).build());
+///
+///     build_router(chain, pipelines, |route| {
+///         route.get("/users/:id").to(show_user);
+///     })
+/// }
+/// #
+/// # fn main() {
+/// #   let test_server = TestServer::new(router()).unwrap();
+/// #   let response = test_server.client().get("http://example.com/users/1").perform().unwrap();
+/// #
+/// #   assert_eq!(response.status(), StatusCode::NOT_FOUND);
+/// #   assert_eq!(
+/// #       response.read_utf8_body().unwrap(),
+/// #       r#"{"error":{"status":404,"message":"Not Found","detail":"no such user"}}"#
+/// #   );
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct ErrorMiddleware {
+    mappers: Vec<Arc<Mapper>>,
+    json: bool,
+    details: bool,
+}
+
+impl Default for ErrorMiddleware {
+    fn default() -> ErrorMiddleware {
+        ErrorMiddleware {
+            mappers: Vec::new(),
+            json: true,
+            details: false,
+        }
+    }
+}
+
+impl ErrorMiddleware {
+    /// Creates an `ErrorMiddleware` which responds with JSON bodies, and has no mappings.
+    pub fn new() -> ErrorMiddleware {
+        ErrorMiddleware::default()
+    }
+
+    /// Registers a mapping from errors of type `E` to the status of the response. Mappings are
+    /// tried in the order they were registered, and the first with a matching type is used.
+    pub fn map<E, F>(mut self, f: F) -> ErrorMiddleware
+    where
+        E: Error + 'static,
+        F: Fn(&E) -> StatusCode + Send + Sync + RefUnwindSafe + 'static,
+    {
+        self.mappers.push(Arc::new(move |err: &HandlerError| {
+            err.downcast_ref::<E>().map(&f)
+        }));
+        self
+    }
+
+    /// Sets whether responses have a JSON body. When disabled, responses have an empty body.
+    pub fn with_json(self, json: bool) -> ErrorMiddleware {
+        ErrorMiddleware { json, ..self }
+    }
+
+    /// Sets whether the description of client errors is included in the JSON body.
+    pub fn with_details(self, details: bool) -> ErrorMiddleware {
+        ErrorMiddleware { details, ..self }
+    }
+
+    // Determines the status of the response for an error.
+    fn status(&self, err: &HandlerError) -> StatusCode {
+        self.mappers
+            .iter()
+            .filter_map(|mapper| mapper(err))
+            .next()
+            .unwrap_or_else(|| err.status())
+    }
+
+    // Renders the JSON body of the response for an error.
+    fn body(&self, status: StatusCode, err: &HandlerError) -> Vec<u8> {
+        let detail = if self.details && status.is_client_error() {
+            err.cause().map(|cause| cause.to_string())
+        } else {
+            None
+        };
+
+        let body = ErrorBody {
+            error: ErrorDetail {
+                status: status.as_u16(),
+                message: status.canonical_reason().unwrap_or("Unknown Error"),
+                detail,
+            },
+        };
+
+        serde_json::to_vec(&body).expect("error body is serializable")
+    }
+
+    fn respond(&self, state: &State, err: &HandlerError) -> Response<Body> {
+        let status = self.status(err);
+        let cause = err.cause().map(|cause| cause.to_string());
+        let cause = cause.as_ref().map(|c| c.as_str()).unwrap_or("(none)");
+
+        if status.is_server_error() {
+            request_error!(state, "{} response for error: {}", status, cause);
+        } else {
+            request_debug!(state, "{} response for error: {}", status, cause);
+        }
+
+        if self.json {
+            create_response(
+                state,
+                status,
+                mime::APPLICATION_JSON,
+                self.body(status, err),
+            )
+        } else {
+            create_empty_response(state, status)
+        }
+    }
+}
+
+/// `Middleware` trait implementation.
+impl Middleware for ErrorMiddleware {
+    /// Converts an error from the rest of the chain into a response.
+    fn call<Chain>(self, state: State, chain: Chain) -> Box<HandlerFuture>
+    where
+        Chain: FnOnce(State) -> Box<HandlerFuture>,
+    {
+        let f = chain(state).or_else(move |(state, err)| {
+            let res = self.respond(&state, &err);
+            future::ok::<_, (State, HandlerError)>((state, res))
+        });
+
+        Box::new(f)
+    }
+}
+
+/// `NewMiddleware` trait implementation.
+impl NewMiddleware for ErrorMiddleware {
+    type Instance = Self;
+
+    /// Clones the current middleware to a new instance.
+    fn new_middleware(&self) -> io::Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fmt;
+
+    use handler::IntoHandlerError;
+
+    #[derive(Debug)]
+    struct Conflict(&'static str);
+
+    impl fmt::Display for Conflict {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "conflict on {}", self.0)
+        }
+    }
+
+    impl Error for Conflict {
+        fn description(&self) -> &str {
+            "conflict"
+        }
+    }
+
+    #[test]
+    fn maps_errors_by_type() {
+        let middleware = ErrorMiddleware::new()
+            .map::<io::Error, _>(|e| match e.kind() {
+                io::ErrorKind::NotFound => StatusCode::NOT_FOUND,
+                _ => StatusCode::SERVICE_UNAVAILABLE,
+            })
+            .map::<Conflict, _>(|_| StatusCode::CONFLICT);
+
+        let err = io::Error::new(io::ErrorKind::NotFound, "gone").into_handler_error();
+        assert_eq!(middleware.status(&err), StatusCode::NOT_FOUND);
+
+        let err = Conflict("name").into_handler_error();
+        assert_eq!(middleware.status(&err), StatusCode::CONFLICT);
+
+        let err = fmt::Error
+            .into_handler_error()
+            .with_status(StatusCode::BAD_REQUEST);
+        assert_eq!(middleware.status(&err), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn hides_details_of_server_errors() {
+        let middleware = ErrorMiddleware::new().with_details(true);
+        let err = Conflict("name").into_handler_error();
+
+        assert_eq!(
+            middleware.body(StatusCode::CONFLICT, &err),
+            br#"{"error":{"status":409,"message":"Conflict","detail":"conflict on name"}}"#
+                .to_vec()
+        );
+        assert_eq!(
+            middleware.body(StatusCode::INTERNAL_SERVER_ERROR, &err),
+            br#"{"error":{"status":500,"message":"Internal Server Error"}}"#.to_vec()
+        );
+    }
+}
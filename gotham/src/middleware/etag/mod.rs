@@ -0,0 +1,309 @@
+//! Entity tag middleware, which answers conditional requests with `304 Not Modified` when the
+//! client already holds the current representation of a resource.
+use futures::{future, Future, Stream};
+use httpdate::parse_http_date;
+use hyper::body::Payload;
+use hyper::header::{
+    HeaderMap, HeaderValue, CONTENT_LENGTH, CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH,
+    LAST_MODIFIED,
+};
+use hyper::{Body, Method, Response, StatusCode};
+use std::io;
+
+use handler::{HandlerFuture, IntoHandlerError};
+use helpers::http::precondition::EntityTag;
+use middleware::{Middleware, NewMiddleware};
+use state::{FromState, State};
+
+/// Middleware binding which adds an `ETag` header to responses, and honours the
+/// `If-None-Match` and `If-Modified-Since` headers of `GET` and `HEAD` requests.
+///
+/// An `ETag` is computed for successful responses whose body is already buffered, such as those
+/// created from a `String` or `Vec<u8>`, and which are no larger than the maximum size (1 MiB by
+/// default). The tag is derived from the content of the body, so it changes whenever the body
+/// does. Responses which already carry an `ETag`, such as those from the static asset handlers,
+/// keep it. Streaming bodies are never buffered, so are passed through unchanged.
+///
+/// When the `ETag` matches one listed in `If-None-Match`, or when there is no `If-None-Match` and
+/// the `Last-Modified` header of the response is no later than `If-Modified-Since`, the response
+/// is replaced with a `304 Not Modified` carrying the same headers and an empty body.
+///
+/// To have the tag describe the body as produced by the handler, add this middleware after any
+/// `CompressionMiddleware` in the pipeline.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// # extern crate mime;
+/// #
+/// # use hyper::{Body, Response, StatusCode};
+/// # use hyper::header::*;
+/// # use gotham::helpers::http::response::create_response;
+/// # use gotham::middleware::etag::ETagMiddleware;
+/// # use gotham::pipeline::new_pipeline;
+/// # use gotham::pipeline::single::single_pipeline;
+/// # use gotham::router::Router;
+/// # use gotham::router::builder::*;
+/// # use gotham::state::State;
+/// # use gotham::test::TestServer;
+/// #
+/// fn handler(state: State) -> (State, Response<Body>) {
+///     let res = create_response(&state, StatusCode::OK, mime::TEXT_PLAIN, "Hello, world!");
+///     (state, res)
+/// }
+///
+/// fn router() -> Router {
+///     let (chain, pipelines) =
+///         single_pipeline(new_pipeline().add(ETagMiddleware::default()).build());
+///
+///     build_router(chain, pipelines, |route| {
+///         route.get("/").to(handler);
+///     })
+/// }
+/// #
+/// # fn main() {
+/// #   let test_server = TestServer::new(router()).unwrap();
+/// #
+/// #   let response = test_server.client().get("http://example.com/").perform().unwrap();
+/// #   assert_eq!(response.status(), StatusCode::OK);
+/// #   let etag = response.headers()[ETAG].clone();
+/// #
+/// #   let response = test_server.client()
+/// #       .get("http://example.com/")
+/// #       .with_header(IF_NONE_MATCH, etag.clone())
+/// #       .perform()
+/// #       .unwrap();
+/// #
+/// #   assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+/// #   assert_eq!(response.headers()[ETAG], etag);
+/// #   assert!(response.read_body().unwrap().is_empty());
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct ETagMiddleware {
+    weak: bool,
+    max_size: u64,
+}
+
+impl Default for ETagMiddleware {
+    fn default() -> ETagMiddleware {
+        ETagMiddleware {
+            weak: false,
+            max_size: 1024 * 1024,
+        }
+    }
+}
+
+impl ETagMiddleware {
+    /// Sets whether the computed tags are weak, which is appropriate when equivalent but not
+    /// byte-for-byte identical bodies may be produced for the same resource.
+    pub fn with_weak(self, weak: bool) -> ETagMiddleware {
+        ETagMiddleware { weak, ..self }
+    }
+
+    /// Sets the largest body in bytes for which an `ETag` is computed.
+    pub fn with_max_size(self, max_size: u64) -> ETagMiddleware {
+        ETagMiddleware { max_size, ..self }
+    }
+
+    // Computes the entity tag of a body, from its length and a hash of its content.
+    fn entity_tag(&self, body: &[u8]) -> String {
+        let tag = EntityTag::from_content(body);
+        if self.weak {
+            EntityTag::weak(tag.tag()).to_string()
+        } else {
+            tag.to_string()
+        }
+    }
+}
+
+// Replaces the response with a `304 Not Modified` when the request headers show that the client
+// already has the current representation.
+fn not_modified(headers: &HeaderMap, response: Response<Body>) -> Response<Body> {
+    if !is_not_modified(headers, response.headers()) {
+        return response;
+    }
+
+    let (mut parts, _) = response.into_parts();
+    parts.status = StatusCode::NOT_MODIFIED;
+    parts.headers.remove(CONTENT_LENGTH);
+    parts.headers.remove(CONTENT_TYPE);
+
+    Response::from_parts(parts, Body::empty())
+}
+
+// Evaluates `If-None-Match`, or `If-Modified-Since` when it is absent, as per RFC 7232.
+fn is_not_modified(headers: &HeaderMap, response_headers: &HeaderMap) -> bool {
+    if headers.contains_key(IF_NONE_MATCH) {
+        let etag = match response_headers.get(ETAG).and_then(|v| v.to_str().ok()) {
+            Some(etag) => etag,
+            None => return false,
+        };
+
+        return headers
+            .get_all(IF_NONE_MATCH)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .any(|candidate| etag_matches(candidate.trim(), etag));
+    }
+
+    let since = headers
+        .get(IF_MODIFIED_SINCE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| parse_http_date(v).ok());
+
+    let modified = response_headers
+        .get(LAST_MODIFIED)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| parse_http_date(v).ok());
+
+    match (since, modified) {
+        (Some(since), Some(modified)) => modified <= since,
+        _ => false,
+    }
+}
+
+// Compares two entity tags using the weak comparison required for `If-None-Match`, where `*`
+// matches any tag.
+fn etag_matches(candidate: &str, etag: &str) -> bool {
+    fn opaque(tag: &str) -> &str {
+        if tag.starts_with("W/") {
+            &tag[2..]
+        } else {
+            tag
+        }
+    }
+
+    candidate == "*" || opaque(candidate) == opaque(etag)
+}
+
+/// `Middleware` trait implementation.
+impl Middleware for ETagMiddleware {
+    /// Tags the response produced by the rest of the chain, and checks it against the
+    /// preconditions of the request.
+    fn call<Chain>(self, state: State, chain: Chain) -> Box<HandlerFuture>
+    where
+        Chain: FnOnce(State) -> Box<HandlerFuture>,
+    {
+        let conditional = {
+            let method = Method::borrow_from(&state);
+            *method == Method::GET || *method == Method::HEAD
+        };
+
+        if !conditional {
+            return chain(state);
+        }
+
+        let f = chain(state).and_then(move |(state, response)| -> Box<HandlerFuture> {
+            let buffered = response
+                .body()
+                .content_length()
+                .map(|len| len <= self.max_size)
+                .unwrap_or(false);
+
+            if response.status() != StatusCode::OK
+                || response.headers().contains_key(ETAG)
+                || !buffered
+            {
+                let response = not_modified(HeaderMap::borrow_from(&state), response);
+                return Box::new(future::ok((state, response)));
+            }
+
+            let (mut parts, body) = response.into_parts();
+            let f = body.concat2().then(move |result| match result {
+                Ok(body) => {
+                    match HeaderValue::from_str(&self.entity_tag(&body)) {
+                        Ok(etag) => {
+                            parts.headers.insert(ETAG, etag);
+                        }
+                        Err(_) => request_debug!(&state, "unable to set ETag"),
+                    }
+
+                    let response = Response::from_parts(parts, Body::from(body));
+                    let response = not_modified(HeaderMap::borrow_from(&state), response);
+                    future::ok((state, response))
+                }
+                Err(e) => future::err((state, e.into_handler_error())),
+            });
+
+            Box::new(f)
+        });
+
+        Box::new(f)
+    }
+}
+
+/// `NewMiddleware` trait implementation.
+impl NewMiddleware for ETagMiddleware {
+    type Instance = Self;
+
+    /// Clones the current middleware to a new instance.
+    fn new_middleware(&self) -> io::Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use hyper::header::HeaderName;
+
+    fn headers(pairs: &[(HeaderName, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for &(ref name, value) in pairs {
+            headers.append(name.clone(), value.parse().unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn computes_entity_tags() {
+        let strong = ETagMiddleware::default();
+        let weak = ETagMiddleware::default().with_weak(true);
+
+        let tag = strong.entity_tag(b"body");
+        assert!(tag.starts_with("\"4-") && tag.ends_with('"'));
+        assert_eq!(tag, strong.entity_tag(b"body"));
+        assert_ne!(tag, strong.entity_tag(b"other"));
+        assert_eq!(weak.entity_tag(b"body"), format!("W/{}", tag));
+    }
+
+    #[test]
+    fn evaluates_if_none_match() {
+        let response = headers(&[(ETAG, "\"abc\"")]);
+
+        let request = headers(&[(IF_NONE_MATCH, "\"xyz\", W/\"abc\"")]);
+        assert!(is_not_modified(&request, &response));
+
+        let request = headers(&[(IF_NONE_MATCH, "*")]);
+        assert!(is_not_modified(&request, &response));
+
+        // `If-None-Match` takes precedence over `If-Modified-Since`
+        let request = headers(&[
+            (IF_NONE_MATCH, "\"xyz\""),
+            (IF_MODIFIED_SINCE, "Sun, 06 Nov 1994 08:49:37 GMT"),
+        ]);
+        let response = headers(&[
+            (ETAG, "\"abc\""),
+            (LAST_MODIFIED, "Sun, 06 Nov 1994 08:49:37 GMT"),
+        ]);
+        assert!(!is_not_modified(&request, &response));
+    }
+
+    #[test]
+    fn evaluates_if_modified_since() {
+        let response = headers(&[(LAST_MODIFIED, "Sun, 06 Nov 1994 08:49:37 GMT")]);
+
+        let request = headers(&[(IF_MODIFIED_SINCE, "Sun, 06 Nov 1994 08:49:37 GMT")]);
+        assert!(is_not_modified(&request, &response));
+
+        let request = headers(&[(IF_MODIFIED_SINCE, "Sat, 05 Nov 1994 08:49:37 GMT")]);
+        assert!(!is_not_modified(&request, &response));
+
+        assert!(!is_not_modified(&request, &HeaderMap::new()));
+    }
+}
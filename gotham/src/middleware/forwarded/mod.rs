@@ -0,0 +1,458 @@
+//! Forwarded headers middleware, used to recover the client address and scheme of requests which
+//! have passed through trusted proxies.
+use hyper::header::{HeaderMap, FORWARDED};
+use std::error::Error as StdError;
+use std::fmt::{self, Display};
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use handler::HandlerFuture;
+use helpers::http::header::{X_FORWARDED_FOR, X_FORWARDED_PROTO};
+use middleware::{Middleware, NewMiddleware};
+use state::client_addr::forward_client_addr;
+use state::{peer_addr, FromState, Scheme, State};
+
+/// A range of IP addresses in CIDR notation, such as `10.0.0.0/8` or `fd00::/8`. A single address
+/// without a prefix length, such as `127.0.0.1`, is also accepted.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    /// Determines whether `ip` is within the range. IPv4 addresses mapped into IPv6, such as
+    /// `::ffff:10.0.0.1`, are treated as the IPv4 address.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, canonical(ip)) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                prefix_eq(&net.octets(), &ip.octets(), self.prefix)
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                prefix_eq(&net.octets(), &ip.octets(), self.prefix)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = InvalidCidr;
+
+    fn from_str(s: &str) -> Result<Cidr, InvalidCidr> {
+        let mut parts = s.trim().splitn(2, '/');
+        let addr = parts
+            .next()
+            .and_then(|addr| addr.parse::<IpAddr>().ok())
+            .map(canonical)
+            .ok_or(InvalidCidr)?;
+
+        let max = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+
+        let prefix = match parts.next() {
+            Some(prefix) => prefix.parse::<u8>().map_err(|_| InvalidCidr)?,
+            None => max,
+        };
+
+        if prefix > max {
+            return Err(InvalidCidr);
+        }
+
+        Ok(Cidr { addr, prefix })
+    }
+}
+
+/// The error returned when parsing a `Cidr` fails.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct InvalidCidr;
+
+impl Display for InvalidCidr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("invalid CIDR notation")
+    }
+}
+
+impl StdError for InvalidCidr {
+    fn description(&self) -> &str {
+        "invalid CIDR notation"
+    }
+}
+
+// Converts an IPv4 address mapped into IPv6 back into IPv4.
+fn canonical(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => {
+            let segments = v6.segments();
+            if segments[..5].iter().all(|&s| s == 0) && segments[5] == 0xffff {
+                v6.to_ipv4().map(IpAddr::V4).unwrap_or(ip)
+            } else {
+                ip
+            }
+        }
+        _ => ip,
+    }
+}
+
+// Compares the first `prefix` bits of two addresses.
+fn prefix_eq(a: &[u8], b: &[u8], prefix: u8) -> bool {
+    let bytes = (prefix / 8) as usize;
+    let bits = prefix % 8;
+
+    if a[..bytes] != b[..bytes] {
+        return false;
+    }
+
+    if bits == 0 {
+        return true;
+    }
+
+    let mask = 0xffu8 << (8 - bits);
+    a[bytes] & mask == b[bytes] & mask
+}
+
+// A client or proxy which a request has passed through, as described by the forwarded headers.
+#[derive(Debug, Default, PartialEq)]
+struct Hop {
+    addr: Option<SocketAddr>,
+    proto: Option<Scheme>,
+}
+
+// Parses a node as used in `Forwarded` and `X-Forwarded-For`, such as `192.0.2.1`,
+// `192.0.2.1:4711` or `[2001:db8::1]:4711`. Obfuscated and `unknown` nodes give `None`.
+fn parse_node(node: &str) -> Option<SocketAddr> {
+    let node = node.trim().trim_matches('"');
+
+    if let Ok(ip) = node.parse::<IpAddr>() {
+        return Some(SocketAddr::new(canonical(ip), 0));
+    }
+
+    if node.starts_with('[') {
+        let end = node.find(']')?;
+        let ip = node[1..end].parse::<IpAddr>().ok()?;
+        let port = match &node[end + 1..] {
+            "" => 0,
+            port if port.starts_with(':') => port[1..].parse::<u16>().ok()?,
+            _ => return None,
+        };
+        return Some(SocketAddr::new(canonical(ip), port));
+    }
+
+    node.parse::<SocketAddr>()
+        .ok()
+        .map(|addr| SocketAddr::new(canonical(addr.ip()), addr.port()))
+}
+
+// Lists the hops described by the `Forwarded` header, as defined by RFC 7239.
+fn forwarded_hops(headers: &HeaderMap) -> Vec<Hop> {
+    headers
+        .get_all(FORWARDED)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|element| {
+            let mut hop = Hop::default();
+
+            for pair in element.split(';') {
+                let mut kv = pair.splitn(2, '=');
+                let (key, value) = match (kv.next(), kv.next()) {
+                    (Some(key), Some(value)) => (key.trim(), value.trim().trim_matches('"')),
+                    _ => continue,
+                };
+
+                if key.eq_ignore_ascii_case("for") {
+                    hop.addr = parse_node(value);
+                } else if key.eq_ignore_ascii_case("proto") {
+                    hop.proto = Scheme::parse(value);
+                }
+            }
+
+            hop
+        })
+        .collect()
+}
+
+// Lists the hops described by the `X-Forwarded-For` and `X-Forwarded-Proto` headers. The
+// schemes are matched to the addresses when there are as many of each, otherwise the last scheme,
+// being the one seen by the nearest proxy, applies to every hop.
+fn x_forwarded_hops(headers: &HeaderMap) -> Vec<Hop> {
+    fn values<'a>(headers: &'a HeaderMap, name: &'static str) -> Vec<&'a str> {
+        headers
+            .get_all(name)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(|value| value.trim())
+            .collect()
+    }
+
+    let addrs = values(headers, X_FORWARDED_FOR);
+    let protos = values(headers, X_FORWARDED_PROTO);
+
+    addrs
+        .iter()
+        .enumerate()
+        .map(|(i, addr)| {
+            let proto = if protos.len() == addrs.len() {
+                protos.get(i)
+            } else {
+                protos.last()
+            };
+
+            Hop {
+                addr: parse_node(addr),
+                proto: proto.and_then(|proto| Scheme::parse(proto)),
+            }
+        })
+        .collect()
+}
+
+/// Middleware binding which determines the client address and scheme of requests forwarded by
+/// trusted proxies, such as load balancers and TLS terminating reverse proxies.
+///
+/// When the connected peer is within one of the trusted ranges, the `Forwarded` header, or when
+/// it is absent the `X-Forwarded-For` and `X-Forwarded-Proto` headers, are read from the nearest
+/// hop outwards. The first address which is not itself a trusted proxy is taken to be the client,
+/// so addresses added by the client to spoof its origin are ignored. Requests from any other peer
+/// are left unchanged, since their forwarded headers cannot be trusted.
+///
+/// The client address replaces the one returned by `state::client_addr`, which is used by
+/// `RequestLogger` and the default key of `RateLimitMiddleware`, and the original peer remains
+/// available via `state::peer_addr`. The scheme is placed into `State` as a `Scheme`, which is
+/// used by `UrlFor::url`. This middleware should come before any others which use these values
+/// in the pipeline.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// # extern crate mime;
+/// #
+/// # use hyper::{Body, Response, StatusCode};
+/// # use gotham::helpers::http::header::{X_FORWARDED_FOR, X_FORWARDED_PROTO};
+/// # use gotham::helpers::http::response::create_response;
+/// # use gotham::middleware::forwarded::ForwardedMiddleware;
+/// # use gotham::pipeline::new_pipeline;
+/// # use gotham::pipeline::single::single_pipeline;
+/// # use gotham::router::Router;
+/// # use gotham::router::builder::*;
+/// # use gotham::state::{client_addr, scheme, State};
+/// # use gotham::test::TestServer;
+/// #
+/// fn handler(state: State) -> (State, Response<Body>) {
+///     let body = format!(
+///         "{} {}",
+///         scheme(&state).as_str(),
+///         client_addr(&state).unwrap().ip()
+///     );
+///
+///     let res = create_response(&state, StatusCode::OK, mime::TEXT_PLAIN, body);
+///     (state, res)
+/// }
+///
+/// fn router() -> Router {
+///     let forwarded = ForwardedMiddleware::new(vec![
+///         "127.0.0.1".parse().unwrap(),
+///         "10.0.0.0/8".parse().unwrap(),
+///     ]);
+///
+///     let (chain, pipelines) = single_pipeline(new_pipeline().add(forwarded).build());
+///
+///     build_router(chain, pipelines, |route| {
+///         route.get("/").to(handler);
+///     })
+/// }
+/// #
+/// # fn main() {
+/// #   let test_server = TestServer::new(router()).unwrap();
+/// #   let response = test_server.client()
+/// #       .get("http://example.com/")
+/// #       .with_header(X_FORWARDED_FOR, "192.0.2.1, 203.0.113.7, 10.0.0.2".parse().unwrap())
+/// #       .with_header(X_FORWARDED_PROTO, "https".parse().unwrap())
+/// #       .perform()
+/// #       .unwrap();
+/// #
+/// #   assert_eq!(response.status(), StatusCode::OK);
+/// #   assert_eq!(response.read_utf8_body().unwrap(), "https 203.0.113.7");
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct ForwardedMiddleware {
+    trusted: Arc<Vec<Cidr>>,
+}
+
+impl ForwardedMiddleware {
+    /// Creates a `ForwardedMiddleware` which trusts the forwarded headers sent by proxies within
+    /// the `trusted` ranges.
+    pub fn new(trusted: Vec<Cidr>) -> ForwardedMiddleware {
+        ForwardedMiddleware {
+            trusted: Arc::new(trusted),
+        }
+    }
+
+    fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.trusted.iter().any(|cidr| cidr.contains(ip))
+    }
+
+    // Chooses the hop describing the client, being the nearest which is not a trusted proxy.
+    fn client<'h>(&self, hops: &'h [Hop]) -> Option<&'h Hop> {
+        hops.iter()
+            .rev()
+            .find(|hop| match hop.addr {
+                Some(addr) => !self.is_trusted(addr.ip()),
+                None => true,
+            })
+            .or_else(|| hops.first())
+    }
+
+    fn apply(&self, state: &mut State) {
+        match peer_addr(state) {
+            Some(peer) if self.is_trusted(peer.ip()) => (),
+            _ => return,
+        }
+
+        let (addr, proto) = {
+            let headers = HeaderMap::borrow_from(state);
+            let mut hops = forwarded_hops(headers);
+            if hops.is_empty() {
+                hops = x_forwarded_hops(headers);
+            }
+
+            match self.client(&hops) {
+                Some(hop) => (hop.addr, hop.proto),
+                None => return,
+            }
+        };
+
+        if let Some(addr) = addr {
+            request_trace!(state, "forwarded for {}", addr);
+            forward_client_addr(state, addr);
+        }
+
+        if let Some(proto) = proto {
+            request_trace!(state, "forwarded via {}", proto.as_str());
+            state.put(proto);
+        }
+    }
+}
+
+/// `Middleware` trait implementation.
+impl Middleware for ForwardedMiddleware {
+    /// Applies the client address and scheme reported by trusted proxies to `State`, before
+    /// invoking the rest of the chain.
+    fn call<Chain>(self, mut state: State, chain: Chain) -> Box<HandlerFuture>
+    where
+        Chain: FnOnce(State) -> Box<HandlerFuture>,
+    {
+        self.apply(&mut state);
+        chain(state)
+    }
+}
+
+/// `NewMiddleware` trait implementation.
+impl NewMiddleware for ForwardedMiddleware {
+    type Instance = Self;
+
+    /// Clones the current middleware to a new instance.
+    fn new_middleware(&self) -> io::Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use hyper::header::HeaderName;
+
+    use state::client_addr::put_client_addr;
+    use state::{client_addr, scheme, set_request_id};
+
+    fn cidr(s: &str) -> Cidr {
+        s.parse().unwrap()
+    }
+
+    fn apply(peer: &str, pairs: &[(HeaderName, &str)]) -> State {
+        let mut headers = HeaderMap::new();
+        for &(ref name, value) in pairs {
+            headers.append(name.clone(), value.parse().unwrap());
+        }
+
+        let mut state = State::new();
+        state.put(headers);
+        put_client_addr(&mut state, peer.parse().unwrap());
+        set_request_id(&mut state);
+
+        ForwardedMiddleware::new(vec![cidr("10.0.0.0/8"), cidr("::1")]).apply(&mut state);
+        state
+    }
+
+    #[test]
+    fn parses_and_matches_cidrs() {
+        assert!(cidr("10.0.0.0/8").contains("10.1.2.3".parse().unwrap()));
+        assert!(!cidr("10.0.0.0/8").contains("11.0.0.0".parse().unwrap()));
+        assert!(cidr("192.168.1.0/23").contains("192.168.0.255".parse().unwrap()));
+        assert!(!cidr("192.168.1.0/24").contains("192.168.0.255".parse().unwrap()));
+        assert!(cidr("127.0.0.1").contains("::ffff:127.0.0.1".parse().unwrap()));
+        assert!(cidr("fd00::/8").contains("fd12::1".parse().unwrap()));
+        assert!(cidr("0.0.0.0/0").contains("203.0.113.7".parse().unwrap()));
+
+        assert_eq!("10.0.0.0/33".parse::<Cidr>(), Err(InvalidCidr));
+        assert_eq!("10.0.0/8".parse::<Cidr>(), Err(InvalidCidr));
+        assert_eq!("10.0.0.0/x".parse::<Cidr>(), Err(InvalidCidr));
+    }
+
+    #[test]
+    fn uses_x_forwarded_headers_from_trusted_peers() {
+        let pairs = [
+            (
+                HeaderName::from_static(X_FORWARDED_FOR),
+                "192.0.2.1, 203.0.113.7",
+            ),
+            (HeaderName::from_static(X_FORWARDED_FOR), "10.0.0.2"),
+            (HeaderName::from_static(X_FORWARDED_PROTO), "https"),
+        ];
+
+        let state = apply("10.0.0.1:5000", &pairs);
+        assert_eq!(
+            client_addr(&state),
+            Some("203.0.113.7:0".parse().unwrap())
+        );
+        assert_eq!(peer_addr(&state), Some("10.0.0.1:5000".parse().unwrap()));
+        assert_eq!(scheme(&state), Scheme::Https);
+
+        let state = apply("203.0.113.9:5000", &pairs);
+        assert_eq!(
+            client_addr(&state),
+            Some("203.0.113.9:5000".parse().unwrap())
+        );
+        assert_eq!(scheme(&state), Scheme::Http);
+    }
+
+    #[test]
+    fn prefers_forwarded_header() {
+        let pairs = [
+            (
+                FORWARDED,
+                "for=\"[2001:db8::17]:4711\";proto=https, for=10.0.0.2;proto=http",
+            ),
+            (HeaderName::from_static(X_FORWARDED_FOR), "192.0.2.1"),
+        ];
+
+        let state = apply("[::1]:5000", &pairs);
+        assert_eq!(
+            client_addr(&state),
+            Some("[2001:db8::17]:4711".parse().unwrap())
+        );
+        assert_eq!(scheme(&state), Scheme::Https);
+
+        let state = apply("[::1]:5000", &[(FORWARDED, "for=unknown;proto=https")]);
+        assert_eq!(client_addr(&state), Some("[::1]:5000".parse().unwrap()));
+        assert_eq!(scheme(&state), Scheme::Https);
+    }
+}
@@ -0,0 +1,150 @@
+//! Defines a hook for observing the outcome of the rest of a middleware chain, whether it
+//! produced a response or failed with an error.
+
+use futures::Future;
+use hyper::{Body, Response, StatusCode};
+
+use handler::{HandlerError, HandlerFuture};
+use state::State;
+
+/// The outcome of the rest of a middleware chain, as passed to an `on_complete` hook.
+pub enum Outcome<'a> {
+    /// A response was produced, which the hook may modify before it is passed back.
+    Response(&'a mut Response<Body>),
+
+    /// The chain failed. The error is passed back unchanged once the hook returns.
+    Error(&'a HandlerError),
+}
+
+impl<'a> Outcome<'a> {
+    /// Returns the status of the response, or the status of the response which will be generated
+    /// for the error.
+    pub fn status(&self) -> StatusCode {
+        match *self {
+            Outcome::Response(ref response) => response.status(),
+            Outcome::Error(err) => err.status(),
+        }
+    }
+}
+
+/// Runs `hook` once the `HandlerFuture` returned by the rest of a middleware chain completes,
+/// whether it succeeds or fails.
+///
+/// Chaining work onto the `HandlerFuture` with `and_then` only runs it on the success path, so
+/// response-phase logic such as logging or persisting data is skipped whenever a handler returns
+/// an error. `on_complete` gives a `Middleware` one place to observe both outcomes, with access to
+/// the `State` in each case.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate futures;
+/// # extern crate gotham;
+/// # #[macro_use]
+/// # extern crate gotham_derive;
+/// # extern crate hyper;
+/// #
+/// # use std::io;
+/// # use futures::future;
+/// # use hyper::StatusCode;
+/// # use gotham::handler::{HandlerFuture, IntoHandlerError};
+/// # use gotham::middleware::Middleware;
+/// # use gotham::middleware::hook::{on_complete, Outcome};
+/// # use gotham::pipeline::new_pipeline;
+/// # use gotham::pipeline::single::single_pipeline;
+/// # use gotham::router::Router;
+/// # use gotham::router::builder::*;
+/// # use gotham::state::State;
+/// # use gotham::test::TestServer;
+/// #
+/// #[derive(Clone, NewMiddleware)]
+/// struct ServerHeader;
+///
+/// impl Middleware for ServerHeader {
+///     fn call<Chain>(self, state: State, chain: Chain) -> Box<HandlerFuture>
+///     where
+///         Chain: FnOnce(State) -> Box<HandlerFuture> + Send + 'static,
+///     {
+///         on_complete(chain(state), |_state, outcome| match outcome {
+///             Outcome::Response(response) => {
+///                 response.headers_mut().insert("server", "gotham".parse().unwrap());
+///             }
+///             Outcome::Error(err) => println!("request failed with {}", err.status()),
+///         })
+///     }
+/// }
+///
+/// fn failing_handler(state: State) -> Box<HandlerFuture> {
+///     let err = io::Error::new(io::ErrorKind::Other, "failed").into_handler_error();
+///     Box::new(future::err((state, err)))
+/// }
+///
+/// fn router() -> Router {
+///     let (chain, pipelines) = single_pipeline(new_pipeline().add(ServerHeader).build());
+///
+///     build_router(chain, pipelines, |route| {
+///         route.get("/").to(failing_handler);
+///     })
+/// }
+/// #
+/// # fn main() {
+/// #   let test_server = TestServer::new(router()).unwrap();
+/// #   let response = test_server.client().get("http://example.com/").perform().unwrap();
+/// #   assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+/// # }
+/// ```
+pub fn on_complete<F>(f: Box<HandlerFuture>, hook: F) -> Box<HandlerFuture>
+where
+    F: FnOnce(&mut State, Outcome) + Send + 'static,
+{
+    let f = f.then(move |result| match result {
+        Ok((mut state, mut response)) => {
+            hook(&mut state, Outcome::Response(&mut response));
+            Ok((state, response))
+        }
+        Err((mut state, err)) => {
+            hook(&mut state, Outcome::Error(&err));
+            Err((state, err))
+        }
+    });
+
+    Box::new(f)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::future;
+    use std::io;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use handler::IntoHandlerError;
+
+    #[test]
+    fn runs_on_both_outcomes() {
+        let statuses = Arc::new(AtomicUsize::new(0));
+
+        let ok = future::ok((State::new(), Response::new(Body::empty())));
+        let recorded = statuses.clone();
+        let f = on_complete(Box::new(ok), move |_, outcome| {
+            recorded.fetch_add(outcome.status().as_u16() as usize, Ordering::SeqCst);
+        });
+        assert!(f.wait().is_ok());
+
+        let err = io::Error::new(io::ErrorKind::Other, "failed")
+            .into_handler_error()
+            .with_status(StatusCode::BAD_GATEWAY);
+        let recorded = statuses.clone();
+        let f = on_complete(
+            Box::new(future::err((State::new(), err))),
+            move |_, outcome| {
+                recorded.fetch_add(outcome.status().as_u16() as usize, Ordering::SeqCst);
+            },
+        );
+        assert!(f.wait().is_err());
+
+        assert_eq!(statuses.load(Ordering::SeqCst), 200 + 502);
+    }
+}
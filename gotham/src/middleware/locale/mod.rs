@@ -0,0 +1,378 @@
+//! Language negotiation middleware, which determines the locales preferred by the client.
+use cookie::Cookie;
+use hyper::header::{HeaderMap, ACCEPT_LANGUAGE, COOKIE};
+use hyper::Uri;
+use std::cmp::Ordering;
+use std::io;
+use url::form_urlencoded;
+
+use handler::HandlerFuture;
+use middleware::{Middleware, NewMiddleware};
+use state::{FromState, State, StateData};
+
+/// The locales preferred by the client, most preferred first, as determined by
+/// `LocaleMiddleware`.
+///
+/// Locales are language tags such as `en-GB` or `fr`, in the form they were supplied by the
+/// client. Comparisons between tags ignore case.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Locales {
+    ranked: Vec<String>,
+}
+
+impl StateData for Locales {}
+
+impl Locales {
+    /// Returns the most preferred locale, if any were given.
+    pub fn preferred(&self) -> Option<&str> {
+        self.ranked.first().map(|locale| locale.as_str())
+    }
+
+    /// Returns all of the locales, most preferred first.
+    pub fn as_slice(&self) -> &[String] {
+        &self.ranked
+    }
+
+    /// Chooses the locale from `available` which best suits the client.
+    ///
+    /// Each preferred locale is considered in turn, and is matched against `available` exactly,
+    /// and then by its primary language alone, so that a client preferring `en-GB` is served
+    /// `en` when that is all that is available. Returns `None` when nothing matches, in which
+    /// case the application should use its default.
+    ///
+    /// ```rust
+    /// # extern crate gotham;
+    /// # use gotham::middleware::locale::Locales;
+    /// # fn main() {
+    /// # let locales = Locales::from(vec!["de-AT".to_owned(), "en-GB".to_owned()]);
+    /// // Given a client which prefers `de-AT`, then `en-GB`.
+    /// assert_eq!(locales.negotiate(&["en", "de"]), Some("de"));
+    /// assert_eq!(locales.negotiate(&["en-GB", "de-DE"]), Some("en-GB"));
+    /// assert_eq!(locales.negotiate(&["fr"]), None);
+    /// # }
+    /// ```
+    pub fn negotiate<'a>(&self, available: &[&'a str]) -> Option<&'a str> {
+        self.ranked
+            .iter()
+            .filter_map(|locale| {
+                available
+                    .iter()
+                    .find(|candidate| candidate.eq_ignore_ascii_case(locale))
+                    .or_else(|| {
+                        available
+                            .iter()
+                            .find(|candidate| candidate.eq_ignore_ascii_case(primary(locale)))
+                    })
+                    .cloned()
+            })
+            .next()
+    }
+}
+
+impl From<Vec<String>> for Locales {
+    fn from(ranked: Vec<String>) -> Locales {
+        Locales { ranked }
+    }
+}
+
+// The primary language subtag of a language tag, such as `en` for `en-GB`.
+fn primary(locale: &str) -> &str {
+    locale.split('-').next().unwrap_or(locale)
+}
+
+// Parses an `Accept-Language` header value into its language tags, ordered by descending
+// quality. Tags with equal quality keep the order they were given in, and the wildcard and any
+// tags with a quality of zero are omitted.
+fn parse_accept_language(value: &str) -> Vec<String> {
+    let mut weighted: Vec<(f32, &str)> = value
+        .split(',')
+        .filter_map(|item| {
+            let mut parts = item.split(';').map(|part| part.trim());
+            let tag = parts.next().filter(|tag| !tag.is_empty() && *tag != "*")?;
+
+            let quality = parts
+                .filter_map(|param| {
+                    let mut kv = param.splitn(2, '=');
+                    match (kv.next(), kv.next()) {
+                        (Some(k), Some(v)) if k.trim().eq_ignore_ascii_case("q") => {
+                            v.trim().parse::<f32>().ok()
+                        }
+                        _ => None,
+                    }
+                })
+                .next()
+                .unwrap_or(1.0);
+
+            if quality > 0.0 {
+                Some((quality, tag))
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    // `sort_by` is stable, so tags of equal quality are kept in order.
+    weighted.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(Ordering::Equal));
+    weighted
+        .into_iter()
+        .map(|(_, tag)| tag.to_owned())
+        .collect()
+}
+
+// Determines whether a value supplied as an override is plausibly a language tag, so that
+// arbitrary input is not passed on to handlers.
+fn is_language_tag(value: &str) -> bool {
+    !value.is_empty()
+        && value.len() <= 35
+        && value.split('-').all(|part| {
+            !part.is_empty() && part.len() <= 8 && part.chars().all(|c| c.is_ascii_alphanumeric())
+        })
+}
+
+/// Middleware binding which determines the locales preferred by the client, and puts them into
+/// `State` as `Locales`.
+///
+/// The locales are taken from the `Accept-Language` header, ranked by their quality values. An
+/// explicit choice can also be made by the client via a query string parameter or a cookie,
+/// which is useful for language pickers, and is ranked ahead of the header when enabled. Where the
+/// query string parameter and cookie are both present, the query string parameter wins. A
+/// default locale can be given, which is ranked last, so that `Locales::preferred` always has a
+/// value.
+///
+/// Handlers and template integrations can then borrow `Locales` from `State`, and use
+/// `Locales::negotiate` to choose between the translations they have available.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// # extern crate mime;
+/// #
+/// # use hyper::{Response, Body, StatusCode};
+/// # use hyper::header::ACCEPT_LANGUAGE;
+/// # use gotham::helpers::http::response::create_response;
+/// # use gotham::middleware::locale::{LocaleMiddleware, Locales};
+/// # use gotham::pipeline::new_pipeline;
+/// # use gotham::pipeline::single::single_pipeline;
+/// # use gotham::router::Router;
+/// # use gotham::router::builder::*;
+/// # use gotham::state::{FromState, State};
+/// # use gotham::test::TestServer;
+/// #
+/// fn greet(state: State) -> (State, Response<Body>) {
+///     let greeting = match Locales::borrow_from(&state).negotiate(&["en", "fr"]) {
+///         Some("fr") => "Bonjour",
+///         _ => "Hello",
+///     };
+///
+///     let res = create_response(&state, StatusCode::OK, mime::TEXT_PLAIN, greeting);
+///     (state, res)
+/// }
+///
+/// fn router() -> Router {
+///     let middleware = LocaleMiddleware::new()
+///         .with_query_param("lang")
+///         .with_default("en");
+///
+///     let (chain, pipelines) = single_pipeline(new_pipeline().add(middleware).build());
+///
+///     build_router(chain, pipelines, |route| {
+///         route.get("/").to(greet);
+///     })
+/// }
+/// #
+/// # fn main() {
+/// #   let test_server = TestServer::new(router()).unwrap();
+/// #
+/// #   let response = test_server.client()
+/// #       .get("http://example.com/")
+/// #       .with_header(ACCEPT_LANGUAGE, "de-DE, fr-CA;q=0.8, en;q=0.5".parse().unwrap())
+/// #       .perform()
+/// #       .unwrap();
+/// #   assert_eq!(response.read_utf8_body().unwrap(), "Bonjour");
+/// #
+/// #   let response = test_server.client()
+/// #       .get("http://example.com/?lang=en")
+/// #       .with_header(ACCEPT_LANGUAGE, "fr".parse().unwrap())
+/// #       .perform()
+/// #       .unwrap();
+/// #   assert_eq!(response.read_utf8_body().unwrap(), "Hello");
+/// # }
+/// ```
+#[derive(Clone, Default)]
+pub struct LocaleMiddleware {
+    query_param: Option<String>,
+    cookie: Option<String>,
+    default: Option<String>,
+}
+
+impl LocaleMiddleware {
+    /// Creates a `LocaleMiddleware` which uses only the `Accept-Language` header.
+    pub fn new() -> LocaleMiddleware {
+        LocaleMiddleware::default()
+    }
+
+    /// Sets the name of a query string parameter, such as `lang`, which overrides the locales
+    /// given by the header.
+    pub fn with_query_param<S>(self, name: S) -> LocaleMiddleware
+    where
+        S: Into<String>,
+    {
+        LocaleMiddleware {
+            query_param: Some(name.into()),
+            ..self
+        }
+    }
+
+    /// Sets the name of a cookie which overrides the locales given by the header.
+    pub fn with_cookie<S>(self, name: S) -> LocaleMiddleware
+    where
+        S: Into<String>,
+    {
+        LocaleMiddleware {
+            cookie: Some(name.into()),
+            ..self
+        }
+    }
+
+    /// Sets a locale which is ranked after those chosen by the client.
+    pub fn with_default<S>(self, locale: S) -> LocaleMiddleware
+    where
+        S: Into<String>,
+    {
+        LocaleMiddleware {
+            default: Some(locale.into()),
+            ..self
+        }
+    }
+
+    // Finds a locale given in the query string.
+    fn query_override(&self, state: &State) -> Option<String> {
+        let name = self.query_param.as_ref()?;
+        let query = Uri::borrow_from(state).query()?;
+
+        form_urlencoded::parse(query.as_bytes())
+            .find(|&(ref k, _)| k == name)
+            .map(|(_, v)| v.into_owned())
+    }
+
+    // Finds a locale given in a cookie.
+    fn cookie_override(&self, state: &State) -> Option<String> {
+        let name = self.cookie.as_ref()?;
+
+        HeaderMap::borrow_from(state)
+            .get_all(COOKIE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(';'))
+            .filter_map(|pair| Cookie::parse(pair.trim()).ok())
+            .find(|cookie| cookie.name() == name)
+            .map(|cookie| cookie.value().to_owned())
+    }
+
+    // Ranks the locales for the request.
+    fn locales(&self, state: &State) -> Locales {
+        let mut ranked = Vec::new();
+
+        let overrides = self.query_override(state).into_iter();
+        for locale in overrides.chain(self.cookie_override(state)) {
+            if is_language_tag(&locale) {
+                ranked.push(locale);
+            } else {
+                request_debug!(state, "ignoring invalid locale override");
+            }
+        }
+
+        for value in HeaderMap::borrow_from(state).get_all(ACCEPT_LANGUAGE) {
+            if let Ok(value) = value.to_str() {
+                ranked.extend(parse_accept_language(value));
+            }
+        }
+
+        ranked.extend(self.default.clone());
+
+        let mut unique: Vec<String> = Vec::with_capacity(ranked.len());
+        for locale in ranked {
+            if !unique.iter().any(|seen| seen.eq_ignore_ascii_case(&locale)) {
+                unique.push(locale);
+            }
+        }
+
+        Locales { ranked: unique }
+    }
+}
+
+/// `Middleware` trait implementation.
+impl Middleware for LocaleMiddleware {
+    /// Puts the ranked `Locales` into `State` before continuing the chain.
+    fn call<Chain>(self, mut state: State, chain: Chain) -> Box<HandlerFuture>
+    where
+        Chain: FnOnce(State) -> Box<HandlerFuture>,
+    {
+        let locales = self.locales(&state);
+        request_trace!(&state, "client prefers locales {:?}", locales.as_slice());
+
+        state.put(locales);
+        chain(state)
+    }
+}
+
+/// `NewMiddleware` trait implementation.
+impl NewMiddleware for LocaleMiddleware {
+    type Instance = Self;
+
+    /// Clones the current middleware to a new instance.
+    fn new_middleware(&self) -> io::Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use hyper::header::{HeaderName, HeaderValue};
+
+    fn locales(uri: &str, headers: &[(HeaderName, &str)]) -> Locales {
+        let mut map = HeaderMap::new();
+        for &(ref name, value) in headers {
+            map.append(name.clone(), HeaderValue::from_str(value).unwrap());
+        }
+
+        let mut state = State::new();
+        state.put(uri.parse::<Uri>().unwrap());
+        state.put(map);
+
+        LocaleMiddleware::new()
+            .with_query_param("lang")
+            .with_cookie("locale")
+            .with_default("en")
+            .locales(&state)
+    }
+
+    #[test]
+    fn parses_accept_language() {
+        assert_eq!(
+            parse_accept_language("fr-CA;q=0.8, de, *;q=0.5, en;q=0.8, es;q=0"),
+            vec!["de", "fr-CA", "en"]
+        );
+        assert!(parse_accept_language("").is_empty());
+    }
+
+    #[test]
+    fn ranks_overrides_first() {
+        let ranked = locales(
+            "/?lang=pt-BR",
+            &[
+                (ACCEPT_LANGUAGE, "de, EN"),
+                (COOKIE, "session=abc; locale=fr"),
+            ],
+        );
+        assert_eq!(ranked.as_slice(), &["pt-BR", "fr", "de", "EN"]);
+        assert_eq!(ranked.preferred(), Some("pt-BR"));
+
+        let invalid = locales("/?lang=%3Cscript%3E", &[]);
+        assert_eq!(invalid.as_slice(), &["en"]);
+    }
+}
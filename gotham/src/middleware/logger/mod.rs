@@ -5,30 +5,62 @@
 //! [Common Log Format](https://en.wikipedia.org/wiki/Common_Log_Format) (CLF).
 //!
 //! There is also a `SimpleLogger` which emits only basic request logs.
-use futures::{future, Future};
-use hyper::{header::CONTENT_LENGTH, Method, Uri, Version};
+use hyper::body::Payload;
+use hyper::header::{HeaderMap, CONTENT_LENGTH, REFERER, USER_AGENT};
+use hyper::{Method, Uri, Version};
 use log::Level;
 use std::io;
 
 use handler::HandlerFuture;
 use helpers::timing::Timer;
+use middleware::chain_timing::ChainTimings;
+use middleware::hook::{on_complete, Outcome};
 use middleware::{Middleware, NewMiddleware};
 use state::request_id::request_id;
 use state::{client_addr, FromState, State};
 
+/// The formats in which `RequestLogger` can record each request.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LogFormat {
+    /// The [Common Log Format](https://en.wikipedia.org/wiki/Common_Log_Format), followed by the
+    /// time taken to respond. This is the default.
+    Common,
+
+    /// The Combined Log Format, which extends `Common` with the `Referer` and `User-Agent`
+    /// request headers.
+    Combined,
+
+    /// Space separated `key=value` pairs, which are simpler for log processors to consume.
+    /// Includes the request ID, and the time spent in each middleware when the request is timed
+    /// by `ChainTimingMiddleware`.
+    Structured,
+}
+
 /// A struct that can act as a logging middleware for Gotham.
 ///
 /// We implement `NewMiddleware` here for Gotham to allow us to work with the request
 /// lifecycle correctly. This trait requires `Clone`, so that is also included.
+///
+/// Each request is logged once the response has been created, or the request has failed,
+/// including the method, path, status, response size, latency and remote address.
 #[derive(Copy, Clone)]
 pub struct RequestLogger {
     level: Level,
+    format: LogFormat,
 }
 
 impl RequestLogger {
-    /// Constructs a new `RequestLogger` instance.
+    /// Constructs a new `RequestLogger` instance, using `LogFormat::Common`.
     pub fn new(level: Level) -> Self {
-        RequestLogger { level }
+        RequestLogger {
+            level,
+            format: LogFormat::Common,
+        }
+    }
+
+    /// Changes the format used when logging requests.
+    pub fn with_format(self, format: LogFormat) -> Self {
+        RequestLogger { format, ..self }
     }
 }
 
@@ -58,51 +90,103 @@ impl Middleware for RequestLogger {
         }
 
         // extract the current time
-        let timer = Timer::new();
+        let timer = Timer::for_request(&state);
 
-        // hook onto the end of the request to log the access
-        let f = chain(state).and_then(move |(state, response)| {
-            // format the start time to the CLF formats
-            let datetime = timer.start_time().format("%d/%b/%Y:%H:%M:%S %z");
+        // hook onto the end of the request to log the access, including failed requests
+        on_complete(chain(state), move |state, outcome| {
+            // take the size from the header, as HEAD responses have no body
+            let length = match outcome {
+                Outcome::Response(ref response) => response
+                    .headers()
+                    .get(CONTENT_LENGTH)
+                    .and_then(|len| len.to_str().ok())
+                    .and_then(|len| len.parse().ok())
+                    .or_else(|| response.body().content_length()),
+                Outcome::Error(_) => None,
+            };
 
-            // grab the ip address from the state
-            let ip = client_addr(&state).unwrap().ip();
+            let line = format_line(
+                self.format,
+                state,
+                outcome.status().as_u16(),
+                length,
+                &timer,
+            );
 
-            {
-                // borrows from the state
-                let path = Uri::borrow_from(&state);
-                let method = Method::borrow_from(&state);
-                let version = Version::borrow_from(&state);
+            // log out
+            log!(self.level, "{}", line);
+        })
+    }
+}
 
-                // take references based on the response
-                let status = response.status().as_u16();
-                let length = response
-                    .headers()
-                    .get(CONTENT_LENGTH)
-                    .map(|len| len.to_str().unwrap())
-                    .unwrap_or("0");
-
-                // log out
-                log!(
-                    self.level,
-                    "{} - - [{}] \"{} {} {:?}\" {} {} - {}",
-                    ip,
-                    datetime,
-                    method,
-                    path,
-                    version,
-                    status,
-                    length,
-                    timer.elapsed()
-                );
-            }
+// Formats the log line for a request, using the request data from `State`.
+fn format_line(
+    format: LogFormat,
+    state: &State,
+    status: u16,
+    length: Option<u64>,
+    timer: &Timer,
+) -> String {
+    // format the start time to the CLF formats
+    let datetime = timer.start_time().format("%d/%b/%Y:%H:%M:%S %z");
+
+    // grab the ip address from the state, if there is one
+    let ip = client_addr(state)
+        .map(|addr| addr.ip().to_string())
+        .unwrap_or_else(|| "-".to_owned());
 
-            // continue the response chain
-            future::ok((state, response))
-        });
+    // borrows from the state
+    let path = Uri::borrow_from(state);
+    let method = Method::borrow_from(state);
+    let version = Version::borrow_from(state);
+    let headers = HeaderMap::borrow_from(state);
+    let header = |name| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("-")
+    };
 
-        // box it up
-        Box::new(f)
+    let length = length.map_or_else(|| "-".to_owned(), |len| len.to_string());
+    let elapsed = timer.elapsed_for_request(state);
+
+    match format {
+        LogFormat::Common => format!(
+            "{} - - [{}] \"{} {} {:?}\" {} {} - {}",
+            ip, datetime, method, path, version, status, length, elapsed
+        ),
+        LogFormat::Combined => format!(
+            "{} - - [{}] \"{} {} {:?}\" {} {} \"{}\" \"{}\" {}",
+            ip,
+            datetime,
+            method,
+            path,
+            version,
+            status,
+            length,
+            header(REFERER),
+            header(USER_AGENT),
+            elapsed
+        ),
+        LogFormat::Structured => {
+            let line = format!(
+                "request_id={} remote_addr={} method={} path={:?} version={:?} status={} size={} \
+                 duration={}",
+                request_id(state),
+                ip,
+                method,
+                path.to_string(),
+                version,
+                status,
+                length,
+                elapsed
+            );
+
+            match ChainTimings::try_borrow_from(state) {
+                Some(timings) => format!("{} chain={}", line, timings),
+                None => line,
+            }
+        }
     }
 }
 
@@ -148,22 +232,92 @@ impl Middleware for SimpleLogger {
         }
 
         // extract the current time
-        let timer = Timer::new();
+        let timer = Timer::for_request(&state);
 
         // execute the request and chain the logging call
-        let f = chain(state).and_then(move |(state, response)| {
+        on_complete(chain(state), move |state, outcome| {
+            let version = match outcome {
+                Outcome::Response(ref response) => response.version(),
+                Outcome::Error(_) => *Version::borrow_from(state),
+            };
+
             log!(
                 self.level,
                 "[RESPONSE][{}][{:?}][{}][{}]",
-                request_id(&state),
-                response.version(),
-                response.status(),
-                timer.elapsed()
+                request_id(state),
+                version,
+                outcome.status(),
+                timer.elapsed_for_request(state)
             );
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use hyper::header::HeaderValue;
+    use state::client_addr::put_client_addr;
+    use state::set_request_id;
+
+    fn state() -> State {
+        let mut state = State::new();
+        let mut headers = HeaderMap::new();
+        headers.insert(REFERER, HeaderValue::from_static("https://example.com/"));
+        headers.insert(USER_AGENT, HeaderValue::from_static("curl/7.54.0"));
+        headers.insert("X-Request-ID", HeaderValue::from_static("abc"));
+        state.put(headers);
+        state.put(Method::GET);
+        state.put("/users?page=2".parse::<Uri>().unwrap());
+        state.put(Version::HTTP_11);
+        put_client_addr(&mut state, "127.0.0.1:10000".parse().unwrap());
+        set_request_id(&mut state);
+        state
+    }
+
+    #[test]
+    fn formats_common_log_lines() {
+        let line = format_line(LogFormat::Common, &state(), 200, Some(12), &Timer::new());
+        assert!(line.starts_with("127.0.0.1 - - ["));
+        assert!(line.contains("] \"GET /users?page=2 HTTP/1.1\" 200 12 - "));
+    }
+
+    #[test]
+    fn formats_combined_log_lines() {
+        let line = format_line(LogFormat::Combined, &state(), 404, None, &Timer::new());
+        assert!(line.contains(
+            "\"GET /users?page=2 HTTP/1.1\" 404 - \"https://example.com/\" \"curl/7.54.0\" "
+        ));
+    }
+
+    #[test]
+    fn formats_structured_log_lines() {
+        let line = format_line(LogFormat::Structured, &state(), 201, Some(0), &Timer::new());
+        assert!(line.starts_with(
+            "request_id=abc remote_addr=127.0.0.1 method=GET path=\"/users?page=2\" \
+             version=HTTP/1.1 status=201 size=0 duration="
+        ));
+    }
+
+    #[test]
+    fn formats_times_from_request_clock() {
+        use helpers::clock::{put_clock, ManualClock};
+        use std::sync::Arc;
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let clock = ManualClock::at(UNIX_EPOCH + Duration::from_secs(86400));
+        let mut state = state();
+        put_clock(&mut state, Arc::new(clock.clone()));
 
-            future::ok((state, response))
-        });
+        let timer = Timer::for_request(&state);
+        clock.advance(Duration::from_millis(5));
 
-        Box::new(f)
+        let line = format_line(LogFormat::Common, &state, 200, Some(12), &timer);
+        assert_eq!(
+            line,
+            "127.0.0.1 - - [02/Jan/1970:00:00:00 +0000] \"GET /users?page=2 HTTP/1.1\" 200 12 - \
+             5.00ms"
+        );
     }
 }
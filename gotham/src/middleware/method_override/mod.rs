@@ -0,0 +1,328 @@
+//! Method override support, allowing HTML forms and restricted clients to use routes for methods
+//! other than `GET` and `POST`.
+use futures::{future, Future, Stream};
+use hyper::header::{HeaderMap, CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Method};
+use std::sync::Arc;
+use url::form_urlencoded;
+
+use error::Result;
+use handler::{Handler, HandlerFuture, IntoHandlerError, NewHandler};
+use helpers::http::header::X_HTTP_METHOD_OVERRIDE;
+use state::{FromState, State};
+
+#[derive(Clone)]
+struct Config {
+    header: bool,
+    form_field: Option<String>,
+    form_limit: u64,
+    methods: Vec<Method>,
+}
+
+impl Config {
+    // Parses an overriding method, accepting only those which have been allowed.
+    fn parse(&self, method: &str) -> Option<Method> {
+        Method::from_bytes(method.trim().to_ascii_uppercase().as_bytes())
+            .ok()
+            .filter(|method| self.methods.contains(method))
+    }
+
+    // Determines whether the body of the request is a form which should be searched for the
+    // override field.
+    fn is_form(&self, headers: &HeaderMap) -> bool {
+        let urlencoded = headers
+            .get(CONTENT_TYPE)
+            .and_then(|ct| ct.to_str().ok())
+            .map(|ct| {
+                ct.trim()
+                    .to_ascii_lowercase()
+                    .starts_with("application/x-www-form-urlencoded")
+            })
+            .unwrap_or(false);
+
+        let length = headers
+            .get(CONTENT_LENGTH)
+            .and_then(|len| len.to_str().ok())
+            .and_then(|len| len.parse::<u64>().ok());
+
+        urlencoded && length.map(|len| len <= self.form_limit).unwrap_or(false)
+    }
+}
+
+/// Wraps a `NewHandler`, such as a `Router`, to replace the method of `POST` requests with the
+/// one they ask to be treated as.
+///
+/// The method is taken from the `X-HTTP-Method-Override` header, or failing that from the
+/// `_method` field of an `application/x-www-form-urlencoded` body, allowing an HTML form to be
+/// submitted to a `PUT`, `PATCH` or `DELETE` route. Only those three methods are accepted by
+/// default, and requests using any method other than `POST` are never changed.
+///
+/// Since the method must be known before a route is chosen, this wraps the whole `Router` rather
+/// than being added to a `Pipeline`. A form body is read into memory to find the field, and is
+/// then restored for the handler, so only forms with a `Content-Length` no larger than the form
+/// limit (64 KiB by default) are considered.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// # extern crate mime;
+/// #
+/// # use hyper::{Body, Response, StatusCode};
+/// # use gotham::middleware::method_override::MethodOverride;
+/// # use gotham::router::Router;
+/// # use gotham::router::builder::*;
+/// # use gotham::state::State;
+/// # use gotham::test::TestServer;
+/// #
+/// fn delete_post(state: State) -> (State, Response<Body>) {
+///     (state, Response::new(Body::from("deleted")))
+/// }
+///
+/// fn router() -> Router {
+///     build_simple_router(|route| {
+///         route.delete("/posts/1").to(delete_post);
+///     })
+/// }
+/// #
+/// # fn main() {
+/// #   let test_server = TestServer::new(MethodOverride::new(router())).unwrap();
+/// #   let response = test_server.client()
+/// #       .post(
+/// #           "http://example.com/posts/1",
+/// #           "_method=DELETE",
+/// #           mime::APPLICATION_WWW_FORM_URLENCODED,
+/// #       )
+/// #       .perform()
+/// #       .unwrap();
+/// #
+/// #   assert_eq!(response.status(), StatusCode::OK);
+/// #   assert_eq!(response.read_utf8_body().unwrap(), "deleted");
+/// # }
+/// ```
+pub struct MethodOverride<T> {
+    inner: T,
+    config: Arc<Config>,
+}
+
+impl<T> MethodOverride<T>
+where
+    T: NewHandler,
+{
+    /// Wraps `inner`, accepting overrides from both the header and the `_method` form field.
+    pub fn new(inner: T) -> MethodOverride<T> {
+        MethodOverride {
+            inner,
+            config: Arc::new(Config {
+                header: true,
+                form_field: Some("_method".to_owned()),
+                form_limit: 64 * 1024,
+                methods: vec![Method::PUT, Method::PATCH, Method::DELETE],
+            }),
+        }
+    }
+
+    /// Sets whether the `X-HTTP-Method-Override` header is used.
+    pub fn with_header(mut self, header: bool) -> MethodOverride<T> {
+        Arc::make_mut(&mut self.config).header = header;
+        self
+    }
+
+    /// Sets the name of the form field holding the method.
+    pub fn with_form_field<S>(mut self, name: S) -> MethodOverride<T>
+    where
+        S: Into<String>,
+    {
+        Arc::make_mut(&mut self.config).form_field = Some(name.into());
+        self
+    }
+
+    /// Disables overriding the method from a form field, so that request bodies are never read.
+    pub fn without_form_field(mut self) -> MethodOverride<T> {
+        Arc::make_mut(&mut self.config).form_field = None;
+        self
+    }
+
+    /// Sets the largest form body in bytes which is searched for the form field.
+    pub fn with_form_limit(mut self, form_limit: u64) -> MethodOverride<T> {
+        Arc::make_mut(&mut self.config).form_limit = form_limit;
+        self
+    }
+
+    /// Sets the methods which a `POST` request may be treated as.
+    pub fn with_methods(mut self, methods: Vec<Method>) -> MethodOverride<T> {
+        Arc::make_mut(&mut self.config).methods = methods;
+        self
+    }
+}
+
+impl<T> NewHandler for MethodOverride<T>
+where
+    T: NewHandler,
+    T::Instance: 'static,
+{
+    type Instance = MethodOverride<T::Instance>;
+
+    fn new_handler(&self) -> Result<Self::Instance> {
+        Ok(MethodOverride {
+            inner: self.inner.new_handler()?,
+            config: self.config.clone(),
+        })
+    }
+}
+
+impl<H> Handler for MethodOverride<H>
+where
+    H: Handler + 'static,
+{
+    fn handle(self, mut state: State) -> Box<HandlerFuture> {
+        let MethodOverride { inner, config } = self;
+
+        if *Method::borrow_from(&state) != Method::POST {
+            return inner.handle(state);
+        }
+
+        if config.header {
+            let method = HeaderMap::borrow_from(&state)
+                .get(X_HTTP_METHOD_OVERRIDE)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| config.parse(value));
+
+            if let Some(method) = method {
+                override_method(&mut state, method);
+                return inner.handle(state);
+            }
+        }
+
+        let field = match config.form_field {
+            Some(ref field) if config.is_form(HeaderMap::borrow_from(&state)) => field.clone(),
+            _ => return inner.handle(state),
+        };
+
+        let f = Body::take_from(&mut state)
+            .concat2()
+            .then(move |result| match result {
+                Ok(body) => {
+                    let method = form_urlencoded::parse(&body)
+                        .find(|&(ref name, _)| *name == field)
+                        .and_then(|(_, value)| config.parse(&value));
+
+                    if let Some(method) = method {
+                        override_method(&mut state, method);
+                    }
+
+                    state.put(Body::from(body));
+                    inner.handle(state)
+                }
+                Err(e) => {
+                    Box::new(future::err((state, e.into_handler_error()))) as Box<HandlerFuture>
+                }
+            });
+
+        Box::new(f)
+    }
+}
+
+fn override_method(state: &mut State, method: Method) {
+    request_trace!(state, "treating POST request as {}", method);
+    state.put(method);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use hyper::{Response, StatusCode};
+    use mime;
+
+    use router::builder::*;
+    use router::Router;
+    use test::TestServer;
+
+    fn echo_method(mut state: State) -> Box<HandlerFuture> {
+        let method = Method::borrow_from(&state).clone();
+        let f = Body::take_from(&mut state)
+            .concat2()
+            .then(move |result| match result {
+                Ok(body) => {
+                    let body = format!("{} {}", method, String::from_utf8_lossy(&body));
+                    future::ok((state, Response::new(Body::from(body))))
+                }
+                Err(e) => future::err((state, e.into_handler_error())),
+            });
+
+        Box::new(f)
+    }
+
+    fn router() -> Router {
+        build_simple_router(|route| {
+            route.post("/").to(echo_method);
+            route.put("/").to(echo_method);
+            route.delete("/").to(echo_method);
+        })
+    }
+
+    #[test]
+    fn overrides_with_header() {
+        let test_server = TestServer::new(MethodOverride::new(router())).unwrap();
+
+        let response = test_server
+            .client()
+            .post("http://localhost/", "data", mime::TEXT_PLAIN)
+            .with_header(X_HTTP_METHOD_OVERRIDE, "put".parse().unwrap())
+            .perform()
+            .unwrap();
+        assert_eq!(response.read_utf8_body().unwrap(), "PUT data");
+
+        let response = test_server
+            .client()
+            .post("http://localhost/", "data", mime::TEXT_PLAIN)
+            .with_header(X_HTTP_METHOD_OVERRIDE, "CONNECT".parse().unwrap())
+            .perform()
+            .unwrap();
+        assert_eq!(response.read_utf8_body().unwrap(), "POST data");
+
+        let response = test_server
+            .client()
+            .put("http://localhost/", "data", mime::TEXT_PLAIN)
+            .with_header(X_HTTP_METHOD_OVERRIDE, "DELETE".parse().unwrap())
+            .perform()
+            .unwrap();
+        assert_eq!(response.read_utf8_body().unwrap(), "PUT data");
+    }
+
+    #[test]
+    fn overrides_with_form_field() {
+        let test_server = TestServer::new(MethodOverride::new(router())).unwrap();
+
+        let response = test_server
+            .client()
+            .post(
+                "http://localhost/",
+                "name=value&_method=delete",
+                mime::APPLICATION_WWW_FORM_URLENCODED,
+            )
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.read_utf8_body().unwrap(),
+            "DELETE name=value&_method=delete"
+        );
+
+        let test_server =
+            TestServer::new(MethodOverride::new(router()).without_form_field()).unwrap();
+
+        let response = test_server
+            .client()
+            .post(
+                "http://localhost/",
+                "_method=DELETE",
+                mime::APPLICATION_WWW_FORM_URLENCODED,
+            )
+            .perform()
+            .unwrap();
+        assert_eq!(response.read_utf8_body().unwrap(), "POST _method=DELETE");
+    }
+}
@@ -0,0 +1,519 @@
+//! Metrics middleware, which records the number, latency and concurrency of requests, and a
+//! handler which exposes them in the Prometheus text format.
+use futures::{future, Future};
+use hyper::{Method, StatusCode};
+use mime::Mime;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::io;
+use std::sync::atomic::{AtomicIsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use error::Result;
+use handler::{Handler, HandlerFuture, NewHandler};
+use helpers::http::response::create_response;
+use middleware::{Middleware, NewMiddleware};
+use resilience::BreakerState;
+use router::description::RouteTemplate;
+use state::{FromState, State};
+
+// The content type of the Prometheus text exposition format.
+const CONTENT_TYPE_TEXT: &'static str = "text/plain; version=0.0.4";
+
+// The default histogram buckets, in seconds, as used by the Prometheus client libraries.
+const DEFAULT_BUCKETS: &'static [f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+// The labels identifying a series of requests.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Labels {
+    method: String,
+    route: String,
+    status: u16,
+}
+
+// The observations recorded for a series of requests.
+struct Series {
+    count: u64,
+    sum: f64,
+    buckets: Vec<u64>,
+}
+
+impl Series {
+    fn new(buckets: &[f64]) -> Series {
+        Series {
+            count: 0,
+            sum: 0.0,
+            buckets: vec![0; buckets.len()],
+        }
+    }
+
+    fn observe(&mut self, buckets: &[f64], duration: Duration) {
+        let seconds = duration.as_secs() as f64 + f64::from(duration.subsec_nanos()) / 1e9;
+
+        self.count += 1;
+        self.sum += seconds;
+        for (count, bound) in self.buckets.iter_mut().zip(buckets) {
+            if seconds <= *bound {
+                *count += 1;
+            }
+        }
+    }
+}
+
+// The state of a circuit breaker, and the number of calls it has rejected.
+struct Breaker {
+    state: BreakerState,
+    rejected: u64,
+}
+
+struct Registry {
+    buckets: Vec<f64>,
+    series: Mutex<BTreeMap<Labels, Series>>,
+    middleware: Mutex<BTreeMap<String, Series>>,
+    breakers: Mutex<BTreeMap<String, Breaker>>,
+    in_flight: AtomicIsize,
+}
+
+/// A registry of request metrics, which are recorded by `MetricsMiddleware` and exposed in the
+/// Prometheus text format when a `Metrics` value is used as the handler of a route.
+///
+/// The following metrics are recorded, where requests are labelled by their method, the path
+/// template of the matched route (such as `/users/:id`) and the status code of the response:
+///
+/// * `gotham_requests_total`, a counter of the requests which have been handled;
+/// * `gotham_request_duration_seconds`, a histogram of the time taken to handle requests; and
+/// * `gotham_requests_in_flight`, a gauge of the requests currently being handled.
+///
+/// When `Metrics` is given to `ChainTimingMiddleware`, the time spent in each middleware and
+/// handler is also recorded in `gotham_middleware_duration_seconds`, a histogram labelled by the
+/// name of the middleware, or `handler`.
+///
+/// When `Metrics` is given to a `resilience::CircuitBreaker`, the state of the breaker is
+/// recorded in `gotham_circuit_breaker_state`, a gauge labelled by the name of the breaker and
+/// each state, which is 1 for the current state and 0 otherwise. The calls rejected by the
+/// breaker are counted in `gotham_circuit_breaker_rejected_total`.
+///
+/// `Metrics` is cheap to clone, with each clone sharing the same registry.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// #
+/// # use hyper::{Body, Response, StatusCode};
+/// # use gotham::middleware::metrics::{Metrics, MetricsMiddleware};
+/// # use gotham::pipeline::new_pipeline;
+/// # use gotham::pipeline::single::single_pipeline;
+/// # use gotham::router::Router;
+/// # use gotham::router::builder::*;
+/// # use gotham::state::State;
+/// # use gotham::test::TestServer;
+/// #
+/// fn handler(state: State) -> (State, Response<Body>) {
+///     (state, Response::new(Body::from("Hello, world!")))
+/// }
+///
+/// fn router() -> Router {
+///     let metrics = Metrics::new();
+///     let middleware = MetricsMiddleware::new(metrics.clone());
+///
+///     let (chain, pipelines) = single_pipeline(new_pipeline().add(middleware).build());
+///
+///     build_router(chain, pipelines, |route| {
+///         route.get("/users/:id").to(handler);
+///         route.get("/metrics").to_new_handler(metrics);
+///     })
+/// }
+/// #
+/// # fn main() {
+/// #   let test_server = TestServer::new(router()).unwrap();
+/// #   test_server.client().get("http://example.com/users/1").perform().unwrap();
+/// #
+/// #   let response = test_server.client().get("http://example.com/metrics").perform().unwrap();
+/// #   assert_eq!(response.status(), StatusCode::OK);
+/// #
+/// #   let body = response.read_utf8_body().unwrap();
+/// #   assert!(body.contains(
+/// #       "gotham_requests_total{method=\"GET\",route=\"/users/:id\",status=\"200\"} 1\n"
+/// #   ));
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Arc<Registry>,
+}
+
+impl Default for Metrics {
+    fn default() -> Metrics {
+        Metrics::with_buckets(DEFAULT_BUCKETS.to_vec())
+    }
+}
+
+impl Metrics {
+    /// Creates an empty registry, using the default histogram buckets of 5ms to 10s.
+    pub fn new() -> Metrics {
+        Metrics::default()
+    }
+
+    /// Creates an empty registry, using `buckets` as the upper bounds in seconds of the request
+    /// duration histogram.
+    pub fn with_buckets(mut buckets: Vec<f64>) -> Metrics {
+        buckets.sort_by(|a, b| a.partial_cmp(b).expect("histogram buckets must not be NaN"));
+        buckets.dedup();
+
+        Metrics {
+            registry: Arc::new(Registry {
+                buckets,
+                series: Mutex::new(BTreeMap::new()),
+                middleware: Mutex::new(BTreeMap::new()),
+                breakers: Mutex::new(BTreeMap::new()),
+                in_flight: AtomicIsize::new(0),
+            }),
+        }
+    }
+
+    fn observe(&self, labels: Labels, duration: Duration) {
+        let buckets = &self.registry.buckets;
+        let mut all = self.registry.series.lock().unwrap();
+        all.entry(labels)
+            .or_insert_with(|| Series::new(buckets))
+            .observe(buckets, duration);
+    }
+
+    // Records the time spent in a middleware or handler, as measured by `ChainTimingMiddleware`.
+    pub(crate) fn observe_middleware(&self, name: &str, duration: Duration) {
+        let buckets = &self.registry.buckets;
+        let mut all = self.registry.middleware.lock().unwrap();
+        all.entry(name.to_owned())
+            .or_insert_with(|| Series::new(buckets))
+            .observe(buckets, duration);
+    }
+
+    // Records the current state of a circuit breaker.
+    pub(crate) fn observe_breaker_state(&self, name: &str, state: BreakerState) {
+        let mut all = self.registry.breakers.lock().unwrap();
+        all.entry(name.to_owned())
+            .or_insert(Breaker { state, rejected: 0 })
+            .state = state;
+    }
+
+    // Records a call rejected by an open circuit breaker.
+    pub(crate) fn observe_breaker_rejection(&self, name: &str) {
+        let mut all = self.registry.breakers.lock().unwrap();
+        all.entry(name.to_owned())
+            .or_insert(Breaker {
+                state: BreakerState::Open,
+                rejected: 0,
+            })
+            .rejected += 1;
+    }
+
+    /// Renders the recorded metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let series = self.registry.series.lock().unwrap();
+        let mut out = String::new();
+
+        out.push_str("# HELP gotham_requests_total The number of requests handled.\n");
+        out.push_str("# TYPE gotham_requests_total counter\n");
+        for (labels, series) in series.iter() {
+            let _ = writeln!(
+                out,
+                "gotham_requests_total{{{}}} {}",
+                format_labels(labels),
+                series.count
+            );
+        }
+
+        out.push_str("# HELP gotham_request_duration_seconds The time taken to handle requests.\n");
+        out.push_str("# TYPE gotham_request_duration_seconds histogram\n");
+        for (labels, series) in series.iter() {
+            self.render_histogram(
+                &mut out,
+                "gotham_request_duration_seconds",
+                &format_labels(labels),
+                series,
+            );
+        }
+
+        out.push_str("# HELP gotham_requests_in_flight The number of requests being handled.\n");
+        out.push_str("# TYPE gotham_requests_in_flight gauge\n");
+        let _ = writeln!(
+            out,
+            "gotham_requests_in_flight {}",
+            self.registry.in_flight.load(Ordering::SeqCst)
+        );
+
+        let middleware = self.registry.middleware.lock().unwrap();
+        if !middleware.is_empty() {
+            out.push_str(
+                "# HELP gotham_middleware_duration_seconds The time spent in each middleware and \
+                 handler.\n",
+            );
+            out.push_str("# TYPE gotham_middleware_duration_seconds histogram\n");
+            for (name, series) in middleware.iter() {
+                self.render_histogram(
+                    &mut out,
+                    "gotham_middleware_duration_seconds",
+                    &format!("middleware=\"{}\"", escape(name)),
+                    series,
+                );
+            }
+        }
+
+        let breakers = self.registry.breakers.lock().unwrap();
+        if !breakers.is_empty() {
+            out.push_str(
+                "# HELP gotham_circuit_breaker_state The state of each circuit breaker, which is 1 \
+                 for the current state.\n",
+            );
+            out.push_str("# TYPE gotham_circuit_breaker_state gauge\n");
+            for (name, breaker) in breakers.iter() {
+                for state in &[
+                    BreakerState::Closed,
+                    BreakerState::Open,
+                    BreakerState::HalfOpen,
+                ] {
+                    let _ = writeln!(
+                        out,
+                        "gotham_circuit_breaker_state{{breaker=\"{}\",state=\"{}\"}} {}",
+                        escape(name),
+                        state,
+                        if *state == breaker.state { 1 } else { 0 }
+                    );
+                }
+            }
+
+            out.push_str(
+                "# HELP gotham_circuit_breaker_rejected_total The number of calls rejected by each \
+                 circuit breaker.\n",
+            );
+            out.push_str("# TYPE gotham_circuit_breaker_rejected_total counter\n");
+            for (name, breaker) in breakers.iter() {
+                let _ = writeln!(
+                    out,
+                    "gotham_circuit_breaker_rejected_total{{breaker=\"{}\"}} {}",
+                    escape(name),
+                    breaker.rejected
+                );
+            }
+        }
+
+        out
+    }
+
+    fn render_histogram(&self, out: &mut String, name: &str, labels: &str, series: &Series) {
+        for (count, bound) in series.buckets.iter().zip(&self.registry.buckets) {
+            let _ = writeln!(
+                out,
+                "{}_bucket{{{},le=\"{}\"}} {}",
+                name, labels, bound, count
+            );
+        }
+
+        let _ = writeln!(
+            out,
+            "{}_bucket{{{},le=\"+Inf\"}} {}",
+            name, labels, series.count
+        );
+        let _ = writeln!(out, "{}_sum{{{}}} {}", name, labels, series.sum);
+        let _ = writeln!(out, "{}_count{{{}}} {}", name, labels, series.count);
+    }
+}
+
+fn format_labels(labels: &Labels) -> String {
+    format!(
+        "method=\"{}\",route=\"{}\",status=\"{}\"",
+        escape(&labels.method),
+        escape(&labels.route),
+        labels.status
+    )
+}
+
+// Escapes a label value, as required by the text exposition format.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Serves the recorded metrics in the Prometheus text exposition format.
+impl NewHandler for Metrics {
+    type Instance = Self;
+
+    fn new_handler(&self) -> Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+impl Handler for Metrics {
+    fn handle(self, state: State) -> Box<HandlerFuture> {
+        let mime = CONTENT_TYPE_TEXT.parse::<Mime>().unwrap();
+        let res = create_response(&state, StatusCode::OK, mime, self.render());
+
+        Box::new(future::ok((state, res)))
+    }
+}
+
+// Counts a request as in flight until dropped, so that requests which are abandoned are also
+// accounted for.
+struct InFlight(Arc<Registry>);
+
+impl InFlight {
+    fn new(registry: &Arc<Registry>) -> InFlight {
+        registry.in_flight.fetch_add(1, Ordering::SeqCst);
+        InFlight(registry.clone())
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Middleware binding which records metrics about each request in a `Metrics` registry.
+///
+/// Only requests which pass through a pipeline containing the middleware are recorded, so it is
+/// usually added to a pipeline used by every route. See `Metrics` for an example.
+#[derive(Clone)]
+pub struct MetricsMiddleware {
+    metrics: Metrics,
+}
+
+impl MetricsMiddleware {
+    /// Creates a `MetricsMiddleware` which records requests in `metrics`.
+    pub fn new(metrics: Metrics) -> MetricsMiddleware {
+        MetricsMiddleware { metrics }
+    }
+}
+
+/// `Middleware` trait implementation.
+impl Middleware for MetricsMiddleware {
+    /// Records the duration and outcome of the rest of the chain.
+    fn call<Chain>(self, state: State, chain: Chain) -> Box<HandlerFuture>
+    where
+        Chain: FnOnce(State) -> Box<HandlerFuture>,
+    {
+        let method = Method::borrow_from(&state).to_string();
+        let route = RouteTemplate::try_borrow_from(&state)
+            .map(|template| template.as_str().to_owned())
+            .unwrap_or_else(|| "unknown".to_owned());
+
+        let in_flight = InFlight::new(&self.metrics.registry);
+        let start = Instant::now();
+
+        let f = chain(state).then(move |result| {
+            let status = match result {
+                Ok((_, ref response)) => response.status(),
+                Err((_, ref err)) => err.status(),
+            };
+
+            let labels = Labels {
+                method,
+                route,
+                status: status.as_u16(),
+            };
+
+            self.metrics.observe(labels, start.elapsed());
+            drop(in_flight);
+
+            result
+        });
+
+        Box::new(f)
+    }
+}
+
+/// `NewMiddleware` trait implementation.
+impl NewMiddleware for MetricsMiddleware {
+    type Instance = Self;
+
+    /// Clones the current middleware to a new instance.
+    fn new_middleware(&self) -> io::Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(route: &str, status: u16) -> Labels {
+        Labels {
+            method: "GET".to_owned(),
+            route: route.to_owned(),
+            status,
+        }
+    }
+
+    #[test]
+    fn renders_histograms() {
+        let metrics = Metrics::with_buckets(vec![1.0, 0.1]);
+        metrics.observe(labels("/", 200), Duration::from_millis(50));
+        metrics.observe(labels("/", 200), Duration::from_millis(500));
+        metrics.observe(labels("/users/:id", 404), Duration::from_secs(2));
+
+        let _in_flight = InFlight::new(&metrics.registry);
+        let out = metrics.render();
+
+        let root = "method=\"GET\",route=\"/\",status=\"200\"";
+        assert!(out.contains(&format!("gotham_requests_total{{{}}} 2\n", root)));
+        assert!(out.contains(&format!(
+            "gotham_request_duration_seconds_bucket{{{},le=\"0.1\"}} 1\n",
+            root
+        )));
+        assert!(out.contains(&format!(
+            "gotham_request_duration_seconds_bucket{{{},le=\"1\"}} 2\n",
+            root
+        )));
+        assert!(out.contains(&format!(
+            "gotham_request_duration_seconds_bucket{{{},le=\"+Inf\"}} 2\n",
+            root
+        )));
+
+        let user = "method=\"GET\",route=\"/users/:id\",status=\"404\"";
+        assert!(out.contains(&format!(
+            "gotham_request_duration_seconds_bucket{{{},le=\"1\"}} 0\n",
+            user
+        )));
+        assert!(out.contains(&format!(
+            "gotham_request_duration_seconds_sum{{{}}} 2\n",
+            user
+        )));
+        assert!(out.contains(&format!(
+            "gotham_request_duration_seconds_count{{{}}} 1\n",
+            user
+        )));
+
+        assert!(out.contains("gotham_requests_in_flight 1\n"));
+        assert!(!out.contains("gotham_middleware_duration_seconds"));
+
+        metrics.observe_middleware("handler", Duration::from_millis(50));
+        let out = metrics.render();
+        assert!(out.contains(
+            "gotham_middleware_duration_seconds_bucket{middleware=\"handler\",le=\"0.1\"} 1\n"
+        ));
+        assert!(
+            out.contains("gotham_middleware_duration_seconds_count{middleware=\"handler\"} 1\n")
+        );
+
+        assert!(!out.contains("gotham_circuit_breaker"));
+        metrics.observe_breaker_state("db", BreakerState::Closed);
+        metrics.observe_breaker_rejection("db");
+        metrics.observe_breaker_state("db", BreakerState::Open);
+        let out = metrics.render();
+        assert!(out.contains("gotham_circuit_breaker_state{breaker=\"db\",state=\"closed\"} 0\n"));
+        assert!(out.contains("gotham_circuit_breaker_state{breaker=\"db\",state=\"open\"} 1\n"));
+        assert!(out.contains("gotham_circuit_breaker_rejected_total{breaker=\"db\"} 1\n"));
+    }
+
+    #[test]
+    fn escapes_label_values() {
+        assert_eq!(escape("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+    }
+}
@@ -7,12 +7,32 @@ use std::panic::RefUnwindSafe;
 use handler::HandlerFuture;
 use state::State;
 
+pub mod auth;
+pub mod body_limit;
+pub mod budget;
 pub mod chain;
+pub mod chain_timing;
+pub mod coalesce;
+pub mod compression;
+pub mod conditional;
+pub mod cors;
+pub mod decompression;
+pub mod error;
+pub mod etag;
+pub mod forwarded;
+pub mod hook;
+pub mod locale;
 pub mod logger;
+pub mod method_override;
+pub mod metrics;
+pub mod rate_limit;
+pub mod request_id;
 pub mod security;
 pub mod session;
 pub mod state;
+pub mod timeout;
 pub mod timer;
+pub mod tracing;
 
 /// `Middleware` has the opportunity to provide additional behaviour to the `Request` / `Response`
 /// interaction. For example:
@@ -69,7 +89,7 @@ pub mod timer;
 /// #   });
 /// #
 /// #   let test_server = TestServer::new(router).unwrap();
-/// #   let response = test_server.client().get("https://example.com/").perform().unwrap();
+/// #   let response = test_server.client().get("http://example.com/").perform().unwrap();
 /// #   assert_eq!(response.status(), StatusCode::ACCEPTED);
 /// # }
 /// ```
@@ -128,14 +148,15 @@ pub mod timer;
 /// #   });
 /// #
 /// #   let test_server = TestServer::new(router).unwrap();
-/// #   let response = test_server.client().get("https://example.com/").perform().unwrap();
+/// #   let response = test_server.client().get("http://example.com/").perform().unwrap();
 /// #   assert_eq!(response.status(), StatusCode::OK);
 /// #   let body = response.read_utf8_body().unwrap();
 /// #   assert_eq!(&body, "10");
 /// # }
 /// ```
 ///
-/// Decorating the response after the request has completed:
+/// Decorating the response after the request has completed. Note that `map` and `and_then` only
+/// run when the rest of the chain succeeds; use `hook::on_complete` to also observe failures:
 ///
 /// ```rust
 /// # extern crate gotham;
@@ -188,7 +209,7 @@ pub mod timer;
 /// #   });
 /// #
 /// #   let test_server = TestServer::new(router).unwrap();
-/// #   let response = test_server.client().get("https://example.com/").perform().unwrap();
+/// #   let response = test_server.client().get("http://example.com/").perform().unwrap();
 /// #   assert_eq!(response.status(), StatusCode::ACCEPTED);
 /// #
 /// #   {
@@ -251,10 +272,10 @@ pub mod timer;
 /// #
 /// #   let test_server = TestServer::new(router).unwrap();
 /// #
-/// #   let response = test_server.client().get("https://example.com/").perform().unwrap();
+/// #   let response = test_server.client().get("http://example.com/").perform().unwrap();
 /// #   assert_eq!(response.status(), StatusCode::ACCEPTED);
 /// #
-/// #   let response = test_server.client().head("https://example.com/").perform().unwrap();
+/// #   let response = test_server.client().head("http://example.com/").perform().unwrap();
 /// #   assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
 /// # }
 /// ```
@@ -308,7 +329,7 @@ pub mod timer;
 /// #   });
 /// #
 /// #   let test_server = TestServer::new(router).unwrap();
-/// #   let response = test_server.client().get("https://example.com/").perform().unwrap();
+/// #   let response = test_server.client().get("http://example.com/").perform().unwrap();
 /// #   assert_eq!(response.status(), StatusCode::ACCEPTED);
 /// # }
 /// ```
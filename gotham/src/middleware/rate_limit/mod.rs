@@ -0,0 +1,316 @@
+//! Defines a rate limiting middleware with a pluggable store.
+
+use std::io;
+use std::panic::RefUnwindSafe;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::{future, Future};
+use hyper::header::RETRY_AFTER;
+use hyper::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use handler::HandlerFuture;
+use helpers::http::response::create_empty_response;
+use middleware::session::SessionData;
+use middleware::{Middleware, NewMiddleware};
+use state::{client_addr, State};
+
+mod store;
+
+pub use self::store::memory::MemoryStore;
+pub use self::store::{Decision, HitFuture, Store};
+
+/// The number of requests permitted within a period of time.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quota {
+    burst: u32,
+    period: Duration,
+}
+
+impl Quota {
+    /// Creates a `Quota` which permits `burst` requests within each `period`.
+    ///
+    /// # Panics
+    ///
+    /// If `burst` is zero, or `period` is empty.
+    pub fn new(burst: u32, period: Duration) -> Quota {
+        assert!(
+            burst > 0,
+            "rate limit quota must permit at least one request"
+        );
+        assert!(
+            period > Duration::from_secs(0),
+            "rate limit quota must have a non-empty period"
+        );
+
+        Quota { burst, period }
+    }
+
+    /// Creates a `Quota` which permits `burst` requests per second.
+    pub fn per_second(burst: u32) -> Quota {
+        Quota::new(burst, Duration::from_secs(1))
+    }
+
+    /// Creates a `Quota` which permits `burst` requests per minute.
+    pub fn per_minute(burst: u32) -> Quota {
+        Quota::new(burst, Duration::from_secs(60))
+    }
+
+    /// Creates a `Quota` which permits `burst` requests per hour.
+    pub fn per_hour(burst: u32) -> Quota {
+        Quota::new(burst, Duration::from_secs(3600))
+    }
+
+    /// The number of requests permitted within each period.
+    pub fn burst(&self) -> u32 {
+        self.burst
+    }
+
+    /// The period over which `burst` requests are permitted.
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+}
+
+/// The kind of failure which occurred trying to enforce a rate limit.
+#[derive(Debug)]
+pub enum RateLimitError {
+    /// The store failed, and the included message describes the problem.
+    Store(String),
+    /// Exhaustive match against this enum is unsupported.
+    #[doc(hidden)]
+    __NonExhaustive,
+}
+
+type KeyFn = Fn(&State) -> Option<String> + Send + Sync + RefUnwindSafe;
+
+/// Middleware binding which limits the rate of requests, responding with
+/// `429 Too Many Requests` to requests beyond the `Quota`.
+///
+/// Requests are counted separately for each key, which by default is the IP address of the
+/// client. Use `with_session_key` to count requests for each session instead, or `with_key` to
+/// provide a key from any value in `State`. Requests for which no key is available are not
+/// limited.
+///
+/// The counts are held by a `Store`, which is a `MemoryStore` by default. If the store fails, the
+/// failure is logged and the request is permitted, so that an unavailable store does not make the
+/// application unavailable.
+///
+/// Limited requests include a `Retry-After` header, giving the number of seconds until a request
+/// will be permitted.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// #
+/// # use hyper::{Body, Response, StatusCode};
+/// # use hyper::header::RETRY_AFTER;
+/// # use gotham::middleware::rate_limit::{Quota, RateLimitMiddleware};
+/// # use gotham::pipeline::new_pipeline;
+/// # use gotham::pipeline::single::single_pipeline;
+/// # use gotham::router::Router;
+/// # use gotham::router::builder::*;
+/// # use gotham::state::State;
+/// # use gotham::test::TestServer;
+/// #
+/// # fn handler(state: State) -> (State, Response<Body>) {
+/// #   (state, Response::new(Body::empty()))
+/// # }
+/// #
+/// fn router() -> Router {
+///     let (chain, pipelines) = single_pipeline(
+///         new_pipeline()
+///             .add(RateLimitMiddleware::new(Quota::per_minute(2)))
+///             .build(),
+///     );
+///
+///     build_router(chain, pipelines, |route| {
+///         route.get("/").to(handler);
+///     })
+/// }
+/// #
+/// # fn main() {
+/// #   let test_server = TestServer::new(router()).unwrap();
+/// #
+/// #   for _ in 0..2 {
+/// #       let response = test_server.client().get("http://example.com/").perform().unwrap();
+/// #       assert_eq!(response.status(), StatusCode::OK);
+/// #   }
+/// #
+/// #   let response = test_server.client().get("http://example.com/").perform().unwrap();
+/// #   assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+/// #   assert_eq!(response.headers()[RETRY_AFTER], "30");
+/// # }
+/// ```
+pub struct RateLimitMiddleware<S = MemoryStore>
+where
+    S: Store,
+{
+    store: Arc<S>,
+    quota: Quota,
+    key: Arc<KeyFn>,
+}
+
+impl<S> Clone for RateLimitMiddleware<S>
+where
+    S: Store,
+{
+    fn clone(&self) -> Self {
+        RateLimitMiddleware {
+            store: self.store.clone(),
+            quota: self.quota,
+            key: self.key.clone(),
+        }
+    }
+}
+
+impl RateLimitMiddleware<MemoryStore> {
+    /// Creates a `RateLimitMiddleware` which enforces `quota` for each client IP address, using a
+    /// new `MemoryStore`.
+    pub fn new(quota: Quota) -> RateLimitMiddleware<MemoryStore> {
+        RateLimitMiddleware {
+            store: Arc::new(MemoryStore::new()),
+            quota,
+            key: Arc::new(|state: &State| client_addr(state).map(|addr| addr.ip().to_string())),
+        }
+    }
+}
+
+impl<S> RateLimitMiddleware<S>
+where
+    S: Store,
+{
+    /// Changes the store used to hold request counts, such as one which is shared between
+    /// instances of the application.
+    pub fn with_store<NS>(self, store: NS) -> RateLimitMiddleware<NS>
+    where
+        NS: Store,
+    {
+        RateLimitMiddleware {
+            store: Arc::new(store),
+            quota: self.quota,
+            key: self.key,
+        }
+    }
+
+    /// Counts requests using the key returned by `f`, such as an API key or authenticated user.
+    /// Requests for which `f` returns `None` are not limited.
+    pub fn with_key<F>(self, f: F) -> RateLimitMiddleware<S>
+    where
+        F: Fn(&State) -> Option<String> + Send + Sync + RefUnwindSafe + 'static,
+    {
+        RateLimitMiddleware {
+            key: Arc::new(f),
+            ..self
+        }
+    }
+
+    /// Counts requests for each session established by a `NewSessionMiddleware` with the session
+    /// type `T`, which must precede this middleware in the pipeline.
+    ///
+    /// A client which does not keep its session cookie is given a new session for each request,
+    /// so this is best combined with another limit, such as one for each IP address.
+    pub fn with_session_key<T>(self) -> RateLimitMiddleware<S>
+    where
+        T: Default + Serialize + for<'de> Deserialize<'de> + Send + 'static,
+    {
+        self.with_key(|state| {
+            state
+                .try_borrow::<SessionData<T>>()
+                .map(|session| session.identifier().value.clone())
+        })
+    }
+}
+
+/// `Middleware` trait implementation.
+impl<S> Middleware for RateLimitMiddleware<S>
+where
+    S: Store + 'static,
+{
+    /// Records the request against its limit, and invokes the rest of the chain only if it is
+    /// permitted.
+    fn call<Chain>(self, state: State, chain: Chain) -> Box<HandlerFuture>
+    where
+        Chain: FnOnce(State) -> Box<HandlerFuture> + Send + 'static,
+    {
+        let key = match (self.key)(&state) {
+            Some(key) => key,
+            None => return chain(state),
+        };
+
+        let f = self
+            .store
+            .hit(&key, self.quota)
+            .then(move |decision| -> Box<HandlerFuture> {
+                match decision {
+                    Ok(Decision::Allowed { .. }) => chain(state),
+                    Ok(Decision::Limited { retry_after }) => {
+                        request_debug!(
+                            &state,
+                            "rate limit exceeded for {}, retry after {:?}",
+                            key,
+                            retry_after
+                        );
+
+                        let mut res = create_empty_response(&state, StatusCode::TOO_MANY_REQUESTS);
+                        res.headers_mut()
+                            .insert(RETRY_AFTER, retry_after_secs(retry_after).into());
+
+                        Box::new(future::ok((state, res)))
+                    }
+                    Err(e) => {
+                        request_error!(&state, "unable to enforce rate limit for {}: {:?}", key, e);
+                        chain(state)
+                    }
+                }
+            });
+
+        Box::new(f)
+    }
+}
+
+/// `NewMiddleware` trait implementation.
+impl<S> NewMiddleware for RateLimitMiddleware<S>
+where
+    S: Store + 'static,
+{
+    type Instance = Self;
+
+    /// Clones the current middleware to a new instance.
+    fn new_middleware(&self) -> io::Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+// The whole number of seconds to send in `Retry-After`, rounding up so that a retry is never
+// attempted too soon.
+fn retry_after_secs(retry_after: Duration) -> u64 {
+    let secs = retry_after.as_secs();
+    if retry_after.subsec_nanos() > 0 || secs == 0 {
+        secs + 1
+    } else {
+        secs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rounds_retry_after_up() {
+        assert_eq!(retry_after_secs(Duration::from_secs(30)), 30);
+        assert_eq!(retry_after_secs(Duration::from_millis(29_001)), 30);
+        assert_eq!(retry_after_secs(Duration::from_millis(1)), 1);
+        assert_eq!(retry_after_secs(Duration::from_secs(0)), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "must permit at least one request")]
+    fn rejects_empty_quota() {
+        Quota::per_minute(0);
+    }
+}
@@ -0,0 +1,154 @@
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use futures::future;
+use linked_hash_map::LinkedHashMap;
+
+use middleware::rate_limit::store::{Decision, HitFuture, Store};
+use middleware::rate_limit::Quota;
+
+/// Defines the in-process memory based rate limit storage, enforcing each `Quota` as a token
+/// bucket.
+///
+/// Each key starts with a full bucket of `Quota::burst` tokens, one of which is taken by each
+/// request. Tokens are replenished continuously, such that the bucket refills over
+/// `Quota::period`. Keys which have not been used for long enough to refill their bucket are
+/// removed as other keys are used.
+///
+/// This is the default implementation which is used by `RateLimitMiddleware::new`.
+#[derive(Clone, Default)]
+pub struct MemoryStore {
+    storage: Arc<Mutex<LinkedHashMap<String, Bucket>>>,
+}
+
+#[derive(Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    // When the bucket will have refilled, after which it need not be stored.
+    full: Instant,
+}
+
+impl MemoryStore {
+    /// Creates a new, empty `MemoryStore`.
+    pub fn new() -> MemoryStore {
+        MemoryStore::default()
+    }
+}
+
+impl Store for MemoryStore {
+    fn hit(&self, key: &str, quota: Quota) -> Box<HitFuture> {
+        match self.storage.lock() {
+            Ok(mut storage) => {
+                Box::new(future::ok(decide(&mut storage, key, quota, Instant::now())))
+            }
+            Err(PoisonError { .. }) => {
+                unreachable!("rate limit memory store lock poisoned, HashMap panicked?")
+            }
+        }
+    }
+}
+
+fn decide(
+    storage: &mut LinkedHashMap<String, Bucket>,
+    key: &str,
+    quota: Quota,
+    now: Instant,
+) -> Decision {
+    // Buckets are kept in order of last use, so the front is the most likely to have refilled.
+    while storage.front().map(|(_, b)| b.full <= now).unwrap_or(false) {
+        storage.pop_front();
+    }
+
+    let capacity = f64::from(quota.burst());
+    let rate = capacity / secs(quota.period());
+
+    let tokens = match storage.remove(key) {
+        Some(bucket) => (bucket.tokens + secs(now - bucket.updated) * rate).min(capacity),
+        None => capacity,
+    };
+
+    let (tokens, decision) = if tokens >= 1.0 {
+        let remaining = tokens - 1.0;
+        let decision = Decision::Allowed {
+            remaining: remaining.floor() as u32,
+        };
+        (remaining, decision)
+    } else {
+        let retry_after = duration((1.0 - tokens) / rate);
+        (tokens, Decision::Limited { retry_after })
+    };
+
+    storage.insert(
+        key.to_owned(),
+        Bucket {
+            tokens,
+            updated: now,
+            full: now + duration((capacity - tokens) / rate),
+        },
+    );
+
+    decision
+}
+
+fn secs(d: Duration) -> f64 {
+    d.as_secs() as f64 + f64::from(d.subsec_nanos()) / 1e9
+}
+
+fn duration(secs: f64) -> Duration {
+    Duration::new(secs.trunc() as u64, (secs.fract() * 1e9) as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn enforces_token_bucket() {
+        let mut storage = LinkedHashMap::new();
+        let quota = Quota::new(2, Duration::from_secs(8));
+        let start = Instant::now();
+
+        assert_eq!(
+            decide(&mut storage, "a", quota, start),
+            Decision::Allowed { remaining: 1 }
+        );
+        assert_eq!(
+            decide(&mut storage, "a", quota, start),
+            Decision::Allowed { remaining: 0 }
+        );
+        assert_eq!(
+            decide(&mut storage, "a", quota, start),
+            Decision::Limited {
+                retry_after: Duration::from_secs(4)
+            }
+        );
+
+        // other keys are limited separately
+        assert_eq!(
+            decide(&mut storage, "b", quota, start),
+            Decision::Allowed { remaining: 1 }
+        );
+
+        // a token is replenished every four seconds
+        assert_eq!(
+            decide(&mut storage, "a", quota, start + Duration::from_secs(4)),
+            Decision::Allowed { remaining: 0 }
+        );
+    }
+
+    #[test]
+    fn removes_refilled_buckets() {
+        let mut storage = LinkedHashMap::new();
+        let quota = Quota::new(2, Duration::from_secs(8));
+        let start = Instant::now();
+
+        decide(&mut storage, "a", quota, start);
+        decide(&mut storage, "b", quota, start + Duration::from_secs(3));
+        assert_eq!(storage.len(), 2);
+
+        decide(&mut storage, "c", quota, start + Duration::from_secs(5));
+        assert_eq!(storage.len(), 2);
+        assert!(!storage.contains_key("a"));
+    }
+}
@@ -0,0 +1,38 @@
+pub(super) mod memory;
+
+use std::panic::RefUnwindSafe;
+use std::time::Duration;
+
+use futures::Future;
+
+use middleware::rate_limit::{Quota, RateLimitError};
+
+/// Type alias for the trait objects returned by `Store`.
+pub type HitFuture = Future<Item = Decision, Error = RateLimitError> + Send;
+
+/// The outcome of recording a request against a rate limit.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Decision {
+    /// The request is within the limit, and the given number of further requests are currently
+    /// permitted.
+    Allowed {
+        /// The number of further requests which would currently be permitted.
+        remaining: u32,
+    },
+    /// The request exceeds the limit, and should not be retried until the given time has passed.
+    Limited {
+        /// How long until the next request will be permitted.
+        retry_after: Duration,
+    },
+}
+
+/// A `Store` holds the state of each rate limit, and decides whether requests are permitted.
+///
+/// The algorithm used to enforce a `Quota` is up to the `Store`. Implementations which keep their
+/// state in a shared service, such as Redis, allow a limit to be enforced across several
+/// instances of an application.
+pub trait Store: Send + Sync + RefUnwindSafe {
+    /// Records a request against the limit identified by `key`, deciding whether it is permitted
+    /// under `quota`.
+    fn hit(&self, key: &str, quota: Quota) -> Box<HitFuture>;
+}
@@ -0,0 +1,93 @@
+//! Request ID middleware, used to return the identifier of each request to the client.
+use futures::{future, Future};
+use handler::HandlerFuture;
+use helpers::http::header::X_REQUEST_ID;
+use middleware::{Middleware, NewMiddleware};
+use state::{request_id, State};
+use std::io;
+
+/// Middleware binding to attach the identifier of the request to the response headers.
+///
+/// Every request is given an identifier before it reaches the `Router`, which is taken from the
+/// `X-Request-ID` request header when one is provided by a client or proxy, or generated as a
+/// UUID otherwise. The identifier is available via `state::request_id`, and is included in the
+/// log output of Gotham and its middleware.
+///
+/// The response helpers in `helpers::http::response` already include the identifier in an
+/// `X-Request-ID` header. This middleware adds the same header to any response which lacks it,
+/// such as those built directly by a handler, so that the identifier can be used to correlate
+/// the response with logs in this and other services.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// #
+/// # use hyper::{Body, Response, StatusCode};
+/// # use gotham::helpers::http::header::X_REQUEST_ID;
+/// # use gotham::middleware::request_id::RequestIdMiddleware;
+/// # use gotham::pipeline::new_pipeline;
+/// # use gotham::pipeline::single::single_pipeline;
+/// # use gotham::router::Router;
+/// # use gotham::router::builder::*;
+/// # use gotham::state::State;
+/// # use gotham::test::TestServer;
+/// #
+/// fn handler(state: State) -> (State, Response<Body>) {
+///     (state, Response::new(Body::from("Hello, world!")))
+/// }
+///
+/// fn router() -> Router {
+///     let (chain, pipelines) = single_pipeline(new_pipeline().add(RequestIdMiddleware).build());
+///
+///     build_router(chain, pipelines, |route| {
+///         route.get("/").to(handler);
+///     })
+/// }
+/// #
+/// # fn main() {
+/// #   let test_server = TestServer::new(router()).unwrap();
+/// #   let response = test_server.client()
+/// #       .get("http://example.com/")
+/// #       .with_header(X_REQUEST_ID, "1-2-3-4".parse().unwrap())
+/// #       .perform()
+/// #       .unwrap();
+/// #
+/// #   assert_eq!(response.status(), StatusCode::OK);
+/// #   assert_eq!(response.headers()[X_REQUEST_ID], "1-2-3-4");
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct RequestIdMiddleware;
+
+/// `Middleware` trait implementation.
+impl Middleware for RequestIdMiddleware {
+    /// Attaches the request identifier to the response headers.
+    fn call<Chain>(self, state: State, chain: Chain) -> Box<HandlerFuture>
+    where
+        Chain: FnOnce(State) -> Box<HandlerFuture>,
+    {
+        let f = chain(state).and_then(|(state, mut response)| {
+            if !response.headers().contains_key(X_REQUEST_ID) {
+                if let Ok(id) = request_id(&state).parse() {
+                    response.headers_mut().insert(X_REQUEST_ID, id);
+                }
+            }
+
+            future::ok((state, response))
+        });
+
+        Box::new(f)
+    }
+}
+
+/// `NewMiddleware` trait implementation.
+impl NewMiddleware for RequestIdMiddleware {
+    type Instance = Self;
+
+    /// Clones the current middleware to a new instance.
+    fn new_middleware(&self) -> io::Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
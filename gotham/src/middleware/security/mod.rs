@@ -4,47 +4,232 @@
 //! now been separated to allow optional usage. You can attach as a middleware
 //! at startup to include behaviour as was present before.
 //!
-//! Currently this middleware will set the following headers:
+//! `SecurityMiddleware` will set the following headers:
 //!
 //! - X-CONTENT-TYPE-OPTIONS: "nosniff"
 //! - X-FRAME-OPTIONS: "DENY"
 //! - X-XSS-PROTECTION: "1; mode=block"
+//! - REFERRER-POLICY: "strict-origin-when-cross-origin"
 //!
-//! More may be added in future, but these headers provide compatibility with
-//! previous versions of Gotham.
+//! `SecurityHeaders` sets the same headers by default, and allows each of them
+//! to be changed or removed. It can also set a `Content-Security-Policy`, and a
+//! `Strict-Transport-Security` header which is only sent over HTTPS.
 use futures::{future, Future};
 use handler::HandlerFuture;
-use hyper::header::{HeaderValue, X_CONTENT_TYPE_OPTIONS, X_FRAME_OPTIONS, X_XSS_PROTECTION};
+use hyper::header::{
+    HeaderName, HeaderValue, CONTENT_SECURITY_POLICY, REFERRER_POLICY, STRICT_TRANSPORT_SECURITY,
+    X_CONTENT_TYPE_OPTIONS, X_FRAME_OPTIONS, X_XSS_PROTECTION,
+};
 use middleware::{Middleware, NewMiddleware};
-use state::State;
+use state::{scheme, Scheme, State};
 use std::io;
+use std::sync::Arc;
+use std::time::Duration;
 
 // constant strings to be used as header values
 const XFO_VALUE: &'static str = "DENY";
 const XXP_VALUE: &'static str = "1; mode=block";
 const XCTO_VALUE: &'static str = "nosniff";
+const RP_VALUE: &'static str = "strict-origin-when-cross-origin";
 
-/// Middleware binding for the Gotham security handlers.
-///
-/// This acts as nothing more than a trait implementation for the time
-/// being; there are no fields on the struct in use (yet).
-#[derive(Clone)]
+/// Middleware binding for the Gotham security handlers, which sets the default
+/// headers. Use `SecurityHeaders` to configure the headers which are set.
+#[derive(Clone, Copy, Default)]
 pub struct SecurityMiddleware;
 
 /// `Middleware` trait implementation.
 impl Middleware for SecurityMiddleware {
+    /// Attaches security headers to the response.
+    fn call<Chain>(self, state: State, chain: Chain) -> Box<HandlerFuture>
+    where
+        Chain: FnOnce(State) -> Box<HandlerFuture> + Send + 'static,
+    {
+        SecurityHeaders::default().call(state, chain)
+    }
+}
+
+/// `NewMiddleware` trait implementation.
+impl NewMiddleware for SecurityMiddleware {
+    type Instance = Self;
+
+    /// Clones the current middleware to a new instance.
+    fn new_middleware(&self) -> io::Result<Self::Instance> {
+        Ok(*self)
+    }
+}
+
+/// Middleware binding for configurable security headers, which starts with the
+/// headers set by `SecurityMiddleware`.
+///
+/// Headers are only added to responses which do not already include them, so
+/// the defaults can be overridden for individual routes or scopes by setting
+/// the header in the handler, or by adding a differently configured
+/// `SecurityHeaders` to a pipeline which only those routes use.
+///
+/// `Strict-Transport-Security` is not set unless it is configured, and is only
+/// added to responses to requests made over HTTPS, as determined by `scheme`.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// #
+/// # use hyper::{Body, Response, StatusCode};
+/// # use hyper::header::*;
+/// # use std::time::Duration;
+/// # use gotham::middleware::security::SecurityHeaders;
+/// # use gotham::pipeline::new_pipeline;
+/// # use gotham::pipeline::single::single_pipeline;
+/// # use gotham::router::Router;
+/// # use gotham::router::builder::*;
+/// # use gotham::state::State;
+/// # use gotham::test::TestServer;
+/// #
+/// fn handler(state: State) -> (State, Response<Body>) {
+///     (state, Response::new(Body::empty()))
+/// }
+///
+/// fn embeddable(state: State) -> (State, Response<Body>) {
+///     let mut res = Response::new(Body::empty());
+///     res.headers_mut()
+///         .insert(X_FRAME_OPTIONS, HeaderValue::from_static("SAMEORIGIN"));
+///     (state, res)
+/// }
+///
+/// fn router() -> Router {
+///     let security = SecurityHeaders::default()
+///         .with_content_security_policy(HeaderValue::from_static("default-src 'self'"))
+///         .with_strict_transport_security(Duration::from_secs(31536000), true)
+///         .without(X_XSS_PROTECTION);
+///
+///     let (chain, pipelines) = single_pipeline(new_pipeline().add(security).build());
+///
+///     build_router(chain, pipelines, |route| {
+///         route.get("/").to(handler);
+///         route.get("/widget").to(embeddable);
+///     })
+/// }
+/// #
+/// # fn main() {
+/// #   let test_server = TestServer::new(router()).unwrap();
+/// #
+/// #   let response = test_server.client().get("http://example.com/").perform().unwrap();
+/// #   assert_eq!(response.status(), StatusCode::OK);
+/// #   {
+/// #       let headers = response.headers();
+/// #       assert_eq!(headers[X_FRAME_OPTIONS], "DENY");
+/// #       assert_eq!(headers[X_CONTENT_TYPE_OPTIONS], "nosniff");
+/// #       // the request wasn't made over HTTPS
+/// #       assert!(headers.get(STRICT_TRANSPORT_SECURITY).is_none());
+/// #       assert_eq!(headers[REFERRER_POLICY], "strict-origin-when-cross-origin");
+/// #       assert_eq!(headers[CONTENT_SECURITY_POLICY], "default-src 'self'");
+/// #       assert!(headers.get(X_XSS_PROTECTION).is_none());
+/// #   }
+/// #
+/// #   let response = test_server.client().get("http://example.com/widget").perform().unwrap();
+/// #   assert_eq!(response.headers()[X_FRAME_OPTIONS], "SAMEORIGIN");
+/// #
+/// #   let response = test_server.client().get("http://example.com/").perform().unwrap();
+/// #   assert!(response.headers().get(STRICT_TRANSPORT_SECURITY).is_none());
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct SecurityHeaders {
+    headers: Arc<Vec<(HeaderName, HeaderValue)>>,
+}
+
+impl Default for SecurityHeaders {
+    fn default() -> SecurityHeaders {
+        SecurityHeaders {
+            headers: Arc::new(vec![
+                (X_FRAME_OPTIONS, HeaderValue::from_static(XFO_VALUE)),
+                (X_XSS_PROTECTION, HeaderValue::from_static(XXP_VALUE)),
+                (X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static(XCTO_VALUE)),
+                (REFERRER_POLICY, HeaderValue::from_static(RP_VALUE)),
+            ]),
+        }
+    }
+}
+
+impl SecurityHeaders {
+    /// Creates a `SecurityHeaders` which sets the default headers.
+    pub fn new() -> SecurityHeaders {
+        SecurityHeaders::default()
+    }
+
+    /// Sets the `Strict-Transport-Security` header, instructing browsers to only
+    /// use HTTPS to access this host for `max_age`, and optionally its subdomains.
+    /// The header is only sent in responses to requests made over HTTPS.
+    pub fn with_strict_transport_security(
+        self,
+        max_age: Duration,
+        include_subdomains: bool,
+    ) -> SecurityHeaders {
+        let mut value = format!("max-age={}", max_age.as_secs());
+        if include_subdomains {
+            value.push_str("; includeSubDomains");
+        }
+
+        // digits and a fixed string are always a valid header value
+        self.with_header(STRICT_TRANSPORT_SECURITY, value.parse().unwrap())
+    }
+
+    /// Sets the `X-Frame-Options` header, such as `SAMEORIGIN`.
+    pub fn with_frame_options(self, value: HeaderValue) -> SecurityHeaders {
+        self.with_header(X_FRAME_OPTIONS, value)
+    }
+
+    /// Sets the `Referrer-Policy` header, such as `no-referrer`.
+    pub fn with_referrer_policy(self, value: HeaderValue) -> SecurityHeaders {
+        self.with_header(REFERRER_POLICY, value)
+    }
+
+    /// Sets the `Content-Security-Policy` header, which is not set by default.
+    pub fn with_content_security_policy(self, value: HeaderValue) -> SecurityHeaders {
+        self.with_header(CONTENT_SECURITY_POLICY, value)
+    }
+
+    /// Sets any other header to be attached to responses, replacing the value
+    /// of a default header with the same name.
+    pub fn with_header(mut self, name: HeaderName, value: HeaderValue) -> SecurityHeaders {
+        {
+            let headers = Arc::make_mut(&mut self.headers);
+            headers.retain(|&(ref n, _)| *n != name);
+            headers.push((name, value));
+        }
+        self
+    }
+
+    /// Stops a header from being attached to responses, such as one of the
+    /// default headers.
+    pub fn without(mut self, name: HeaderName) -> SecurityHeaders {
+        Arc::make_mut(&mut self.headers).retain(|&(ref n, _)| *n != name);
+        self
+    }
+}
+
+/// `Middleware` trait implementation.
+impl Middleware for SecurityHeaders {
     /// Attaches security headers to the response.
     fn call<Chain>(self, state: State, chain: Chain) -> Box<HandlerFuture>
     where
         Chain: FnOnce(State) -> Box<HandlerFuture>,
     {
-        let f = chain(state).and_then(|(state, mut response)| {
+        let f = chain(state).and_then(move |(state, mut response)| {
             {
+                let https = scheme(&state) == Scheme::Https;
                 let headers = response.headers_mut();
 
-                headers.insert(X_FRAME_OPTIONS, HeaderValue::from_static(XFO_VALUE));
-                headers.insert(X_XSS_PROTECTION, HeaderValue::from_static(XXP_VALUE));
-                headers.insert(X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static(XCTO_VALUE));
+                for &(ref name, ref value) in self.headers.iter() {
+                    if *name == STRICT_TRANSPORT_SECURITY && !https {
+                        continue;
+                    }
+
+                    if !headers.contains_key(name) {
+                        headers.insert(name.clone(), value.clone());
+                    }
+                }
             }
             future::ok((state, response))
         });
@@ -54,7 +239,7 @@ impl Middleware for SecurityMiddleware {
 }
 
 /// `NewMiddleware` trait implementation.
-impl NewMiddleware for SecurityMiddleware {
+impl NewMiddleware for SecurityHeaders {
     type Instance = Self;
 
     /// Clones the current middleware to a new instance.
@@ -62,3 +247,60 @@ impl NewMiddleware for SecurityMiddleware {
         Ok(self.clone())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use hyper::{Body, Response};
+
+    fn respond<M: Middleware>(middleware: M, scheme: Scheme) -> Response<Body> {
+        let mut state = State::new();
+        state.put(scheme);
+
+        let chain = |state| -> Box<HandlerFuture> {
+            Box::new(future::ok((state, Response::new(Body::empty()))))
+        };
+
+        middleware.call(state, chain).wait().ok().unwrap().1
+    }
+
+    #[test]
+    fn configures_headers() {
+        let security = SecurityHeaders::default()
+            .with_strict_transport_security(Duration::from_secs(60), false)
+            .without(X_XSS_PROTECTION)
+            .without(REFERRER_POLICY);
+
+        assert_eq!(
+            *security.headers,
+            vec![
+                (X_FRAME_OPTIONS, HeaderValue::from_static(XFO_VALUE)),
+                (X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static(XCTO_VALUE)),
+                (
+                    STRICT_TRANSPORT_SECURITY,
+                    HeaderValue::from_static("max-age=60")
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn only_sends_hsts_when_configured_over_https() {
+        let res = respond(SecurityMiddleware, Scheme::Https);
+        assert_eq!(res.headers()[X_FRAME_OPTIONS], XFO_VALUE);
+        assert!(res.headers().get(STRICT_TRANSPORT_SECURITY).is_none());
+
+        let security = SecurityHeaders::default()
+            .with_strict_transport_security(Duration::from_secs(60), true);
+
+        let res = respond(security.clone(), Scheme::Https);
+        assert_eq!(
+            res.headers()[STRICT_TRANSPORT_SECURITY],
+            "max-age=60; includeSubDomains"
+        );
+
+        let res = respond(security, Scheme::Http);
+        assert!(res.headers().get(STRICT_TRANSPORT_SECURITY).is_none());
+    }
+}
@@ -0,0 +1,350 @@
+use std::io;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+use futures::{future, Future};
+use linked_hash_map::LinkedHashMap;
+
+use helpers::clock::{elapsed_between, Clock, SystemClock};
+use middleware::session::backend::{Backend, NewBackend, SessionFuture};
+use middleware::session::{SessionError, SessionIdentifier};
+
+/// A `Backend` which keeps the most recently used sessions of another backend in memory, so that
+/// a hot session can be read without a round trip to a remote session store.
+///
+/// A session read from the cache may be at most `ttl` old, so changes made to it by another
+/// process are seen once its entry expires. Sessions written through the `CachedBackend` replace
+/// their cached entry, and dropped sessions are removed from the cache. When the cache is full,
+/// the least recently used session is removed from it.
+///
+/// ## Examples
+///
+/// ```rust
+/// # extern crate gotham;
+/// # use std::time::Duration;
+/// # use gotham::middleware::session::{CachedBackend, MemoryBackend, NewSessionMiddleware};
+/// # fn main() {
+/// // In a real application, the inner backend would be a remote session store.
+/// let backend = CachedBackend::new(MemoryBackend::default())
+///     .with_capacity(10_000)
+///     .with_ttl(Duration::from_secs(5));
+///
+/// NewSessionMiddleware::new(backend)
+/// # ;}
+/// ```
+#[derive(Clone)]
+pub struct CachedBackend<B> {
+    inner: B,
+    cache: Arc<Mutex<LinkedHashMap<String, (Instant, Vec<u8>)>>>,
+    capacity: usize,
+    ttl: Duration,
+    clock: Arc<Clock>,
+}
+
+impl<B> CachedBackend<B> {
+    /// Creates a `CachedBackend` in front of `inner`, which caches up to 1024 sessions for up to
+    /// 30 seconds.
+    pub fn new(inner: B) -> CachedBackend<B> {
+        CachedBackend {
+            inner,
+            cache: Arc::new(Mutex::new(LinkedHashMap::new())),
+            capacity: 1024,
+            ttl: Duration::from_secs(30),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Sets the maximum number of sessions which are cached.
+    pub fn with_capacity(self, capacity: usize) -> CachedBackend<B> {
+        CachedBackend { capacity, ..self }
+    }
+
+    /// Sets how long a session is read from the cache before it is read again from the inner
+    /// backend.
+    pub fn with_ttl(self, ttl: Duration) -> CachedBackend<B> {
+        CachedBackend { ttl, ..self }
+    }
+
+    /// Sets the `Clock` which decides when cached sessions have expired, such as a `ManualClock`
+    /// in tests.
+    pub fn with_clock<C>(self, clock: C) -> CachedBackend<B>
+    where
+        C: Clock + 'static,
+    {
+        CachedBackend {
+            clock: Arc::new(clock),
+            ..self
+        }
+    }
+
+    fn cache(&self) -> MutexGuard<LinkedHashMap<String, (Instant, Vec<u8>)>> {
+        lock(&self.cache)
+    }
+}
+
+impl<B> NewBackend for CachedBackend<B>
+where
+    B: NewBackend,
+{
+    type Instance = CachedBackend<B::Instance>;
+
+    fn new_backend(&self) -> io::Result<Self::Instance> {
+        Ok(CachedBackend {
+            inner: self.inner.new_backend()?,
+            cache: self.cache.clone(),
+            capacity: self.capacity,
+            ttl: self.ttl,
+            clock: self.clock.clone(),
+        })
+    }
+}
+
+impl<B> Backend for CachedBackend<B>
+where
+    B: Backend,
+{
+    fn persist_session(
+        &self,
+        identifier: SessionIdentifier,
+        content: &[u8],
+    ) -> Result<(), SessionError> {
+        match self.inner.persist_session(identifier.clone(), content) {
+            Ok(()) => {
+                insert(
+                    &mut self.cache(),
+                    identifier.value,
+                    (self.clock.now(), Vec::from(content)),
+                    self.capacity,
+                );
+                Ok(())
+            }
+            Err(e) => {
+                // The inner backend may or may not hold the new data, so the cache can't be
+                // trusted either way.
+                self.cache().remove(&identifier.value);
+                Err(e)
+            }
+        }
+    }
+
+    fn read_session(&self, identifier: SessionIdentifier) -> Box<SessionFuture> {
+        let now = self.clock.now();
+
+        {
+            let mut cache = self.cache();
+            if let Some(&mut (instant, ref value)) = cache.get_refresh(&identifier.value) {
+                if elapsed_between(instant, now) < self.ttl {
+                    trace!(" session {} read from CachedBackend", identifier.value);
+                    return Box::new(future::ok(Some(value.clone())));
+                }
+            }
+
+            cache.remove(&identifier.value);
+        }
+
+        let cache = self.cache.clone();
+        let capacity = self.capacity;
+        let key = identifier.value.clone();
+
+        Box::new(self.inner.read_session(identifier).map(move |value| {
+            if let Some(ref value) = value {
+                insert(&mut lock(&cache), key, (now, value.clone()), capacity);
+            }
+
+            value
+        }))
+    }
+
+    fn drop_session(&self, identifier: SessionIdentifier) -> Result<(), SessionError> {
+        self.cache().remove(&identifier.value);
+        self.inner.drop_session(identifier)
+    }
+}
+
+fn lock(
+    cache: &Mutex<LinkedHashMap<String, (Instant, Vec<u8>)>>,
+) -> MutexGuard<LinkedHashMap<String, (Instant, Vec<u8>)>> {
+    match cache.lock() {
+        Ok(cache) => cache,
+        Err(PoisonError { .. }) => unreachable!("session cache lock poisoned, HashMap panicked?"),
+    }
+}
+
+// Inserts the session as the most recently used, removing the least recently used sessions when
+// the cache is full.
+fn insert(
+    cache: &mut LinkedHashMap<String, (Instant, Vec<u8>)>,
+    key: String,
+    entry: (Instant, Vec<u8>),
+    capacity: usize,
+) {
+    cache.remove(&key);
+
+    if capacity == 0 {
+        return;
+    }
+
+    while cache.len() >= capacity {
+        cache.pop_front();
+    }
+
+    cache.insert(key, entry);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use helpers::clock::ManualClock;
+    use middleware::session::MemoryBackend;
+
+    // Counts the reads which reach the inner backend.
+    #[derive(Clone)]
+    struct CountingBackend {
+        inner: MemoryBackend,
+        reads: Arc<AtomicUsize>,
+    }
+
+    impl NewBackend for CountingBackend {
+        type Instance = CountingBackend;
+
+        fn new_backend(&self) -> io::Result<Self::Instance> {
+            Ok(self.clone())
+        }
+    }
+
+    impl Backend for CountingBackend {
+        fn persist_session(
+            &self,
+            identifier: SessionIdentifier,
+            content: &[u8],
+        ) -> Result<(), SessionError> {
+            self.inner.persist_session(identifier, content)
+        }
+
+        fn read_session(&self, identifier: SessionIdentifier) -> Box<SessionFuture> {
+            self.reads.fetch_add(1, Ordering::SeqCst);
+            self.inner.read_session(identifier)
+        }
+
+        fn drop_session(&self, identifier: SessionIdentifier) -> Result<(), SessionError> {
+            self.inner.drop_session(identifier)
+        }
+    }
+
+    fn identifier(value: &str) -> SessionIdentifier {
+        SessionIdentifier {
+            value: value.to_owned(),
+        }
+    }
+
+    fn backend(clock: &ManualClock) -> (CachedBackend<CountingBackend>, Arc<AtomicUsize>) {
+        let reads = Arc::new(AtomicUsize::new(0));
+        let inner = CountingBackend {
+            inner: MemoryBackend::default(),
+            reads: reads.clone(),
+        };
+
+        let backend = CachedBackend::new(inner)
+            .with_capacity(2)
+            .with_ttl(Duration::from_secs(10))
+            .with_clock(clock.clone());
+
+        (backend, reads)
+    }
+
+    #[test]
+    fn reads_through_cache() {
+        let clock = ManualClock::new();
+        let (backend, reads) = backend(&clock);
+
+        backend
+            .inner
+            .inner
+            .persist_session(identifier("a"), b"1")
+            .unwrap();
+
+        let read = || backend.read_session(identifier("a")).wait().unwrap();
+        assert_eq!(read(), Some(b"1".to_vec()));
+        assert_eq!(read(), Some(b"1".to_vec()));
+        assert_eq!(reads.load(Ordering::SeqCst), 1);
+
+        // A change made elsewhere is seen once the cached entry expires.
+        backend
+            .inner
+            .inner
+            .persist_session(identifier("a"), b"2")
+            .unwrap();
+        assert_eq!(read(), Some(b"1".to_vec()));
+
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(read(), Some(b"2".to_vec()));
+        assert_eq!(reads.load(Ordering::SeqCst), 2);
+
+        // Missing sessions aren't cached.
+        assert!(backend
+            .read_session(identifier("b"))
+            .wait()
+            .unwrap()
+            .is_none());
+        assert!(backend
+            .read_session(identifier("b"))
+            .wait()
+            .unwrap()
+            .is_none());
+        assert_eq!(reads.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn writes_replace_cached_sessions() {
+        let clock = ManualClock::new();
+        let (backend, reads) = backend(&clock);
+
+        backend.persist_session(identifier("a"), b"1").unwrap();
+        backend.persist_session(identifier("a"), b"2").unwrap();
+
+        let read = || backend.read_session(identifier("a")).wait().unwrap();
+        assert_eq!(read(), Some(b"2".to_vec()));
+        assert_eq!(reads.load(Ordering::SeqCst), 0);
+
+        backend.drop_session(identifier("a")).unwrap();
+        assert_eq!(read(), None);
+        assert_eq!(reads.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let clock = ManualClock::new();
+        let (backend, reads) = backend(&clock);
+
+        backend.persist_session(identifier("a"), b"1").unwrap();
+        backend.persist_session(identifier("b"), b"2").unwrap();
+
+        // Reading "a" makes "b" the least recently used.
+        backend.read_session(identifier("a")).wait().unwrap();
+        backend.persist_session(identifier("c"), b"3").unwrap();
+
+        backend.read_session(identifier("a")).wait().unwrap();
+        backend.read_session(identifier("c")).wait().unwrap();
+        assert_eq!(reads.load(Ordering::SeqCst), 0);
+
+        backend.read_session(identifier("b")).wait().unwrap();
+        assert_eq!(reads.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn shares_cache_between_instances() {
+        let clock = ManualClock::new();
+        let (new_backend, reads) = backend(&clock);
+
+        let first = new_backend.new_backend().unwrap();
+        first.persist_session(identifier("a"), b"1").unwrap();
+
+        let second = new_backend.new_backend().unwrap();
+        let data = second.read_session(identifier("a")).wait().unwrap();
+        assert_eq!(data, Some(b"1".to_vec()));
+        assert_eq!(reads.load(Ordering::SeqCst), 0);
+    }
+}
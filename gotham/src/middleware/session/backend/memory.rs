@@ -1,10 +1,14 @@
-use std::sync::{Arc, Mutex, PoisonError, Weak};
+use std::cmp;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, Weak};
 use std::time::{Duration, Instant};
 use std::{io, thread};
 
 use futures::future;
 use linked_hash_map::LinkedHashMap;
 
+use helpers::clock::{elapsed_between, Clock, SystemClock};
 use middleware::session::backend::{Backend, NewBackend, SessionFuture};
 use middleware::session::{SessionError, SessionIdentifier};
 
@@ -27,7 +31,117 @@ pub struct MemoryBackend {
     // similarly naive benchmark using `wrk` and a lightweight sample app. Real-world use cases
     // might show a need to replace this with a smarter implementation, but today there's very
     // little overhead here.
-    storage: Arc<Mutex<LinkedHashMap<String, (Instant, Vec<u8>)>>>,
+    //
+    // With many threads serving requests, that mutex became the point of contention, so the
+    // sessions are split between several shards by the hash of their identifier, each with its
+    // own mutex.
+    storage: Arc<Vec<Mutex<Storage>>>,
+    ttl: Duration,
+    clock: Arc<Clock>,
+    max_sessions: Option<usize>,
+    max_bytes: Option<usize>,
+}
+
+/// A snapshot of the sessions held by a `MemoryBackend`, and counts of how they have been used,
+/// as returned by `MemoryBackend::stats`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MemoryBackendStats {
+    sessions: usize,
+    bytes: usize,
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+}
+
+impl MemoryBackendStats {
+    /// The number of sessions currently held, including any which have expired but not yet been
+    /// removed.
+    pub fn sessions(&self) -> usize {
+        self.sessions
+    }
+
+    /// The number of bytes used by the identifiers and data of the sessions currently held.
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// The number of reads which found a session.
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    /// The number of reads which found no session, or one which had expired.
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    /// The number of sessions removed to keep within the limits set by
+    /// `MemoryBackend::with_max_sessions` and `MemoryBackend::with_max_bytes`.
+    pub fn evictions(&self) -> u64 {
+        self.evictions
+    }
+}
+
+// The sessions held by a `MemoryBackend`, from least to most recently used, along with the total
+// size of the sessions and the counts reported by `MemoryBackendStats`.
+struct Storage {
+    sessions: LinkedHashMap<String, (Instant, Vec<u8>)>,
+    stats: MemoryBackendStats,
+}
+
+impl Storage {
+    fn new() -> Storage {
+        Storage {
+            sessions: LinkedHashMap::new(),
+            stats: MemoryBackendStats::default(),
+        }
+    }
+
+    // Inserts the session as the most recently used.
+    fn insert(&mut self, key: String, instant: Instant, content: Vec<u8>) {
+        self.remove(&key);
+        self.stats.bytes += key.len() + content.len();
+        self.sessions.insert(key, (instant, content));
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some((_, content)) = self.sessions.remove(key) {
+            self.stats.bytes -= key.len() + content.len();
+        }
+    }
+
+    // Removes the least recently used session.
+    fn pop_front(&mut self) -> Option<String> {
+        self.sessions.pop_front().map(|(key, (_, content))| {
+            self.stats.bytes -= key.len() + content.len();
+            key
+        })
+    }
+
+    // Removes the least recently used sessions until both limits are met.
+    fn evict(&mut self, max_sessions: Option<usize>, max_bytes: Option<usize>) {
+        let over = |storage: &Storage| {
+            max_sessions.map_or(false, |max| storage.sessions.len() > max)
+                || max_bytes.map_or(false, |max| storage.stats.bytes > max)
+        };
+
+        while over(self) {
+            match self.pop_front() {
+                Some(key) => {
+                    self.stats.evictions += 1;
+                    trace!(" evicted session {} from MemoryBackend", key);
+                }
+                None => break,
+            }
+        }
+    }
+
+    fn stats(&self) -> MemoryBackendStats {
+        MemoryBackendStats {
+            sessions: self.sessions.len(),
+            ..self.stats
+        }
+    }
 }
 
 impl MemoryBackend {
@@ -47,14 +161,155 @@ impl MemoryBackend {
     /// # ;}
     /// ```
     pub fn new(ttl: Duration) -> MemoryBackend {
-        let storage = Arc::new(Mutex::new(LinkedHashMap::new()));
+        MemoryBackend {
+            storage: new_storage(DEFAULT_SHARDS, ttl),
+            ttl,
+            clock: Arc::new(SystemClock),
+            max_sessions: None,
+            max_bytes: None,
+        }
+    }
 
-        {
-            let storage = Arc::downgrade(&storage);
-            thread::spawn(move || cleanup_loop(storage, ttl));
+    /// Sets the `Clock` which decides when sessions have expired, such as a `ManualClock` which
+    /// lets tests expire sessions without waiting. A session which has expired by this clock is
+    /// never read, even before it has been removed from memory.
+    ///
+    /// ## Examples
+    ///
+    /// ```rust
+    /// # extern crate gotham;
+    /// # use std::time::Duration;
+    /// # use gotham::helpers::clock::ManualClock;
+    /// # use gotham::middleware::session::{MemoryBackend, NewSessionMiddleware};
+    /// # fn main() {
+    /// let clock = ManualClock::new();
+    /// NewSessionMiddleware::new(MemoryBackend::new(Duration::from_secs(3600)).with_clock(clock))
+    /// # ;}
+    /// ```
+    pub fn with_clock<C>(self, clock: C) -> MemoryBackend
+    where
+        C: Clock + 'static,
+    {
+        MemoryBackend {
+            clock: Arc::new(clock),
+            ..self
+        }
+    }
+
+    /// Sets the number of shards which the sessions are split between, each of which can be used
+    /// by one thread at a time. The default is 16 shards.
+    ///
+    /// This replaces the storage of the `MemoryBackend`, so any sessions already written to it
+    /// are lost.
+    ///
+    /// ## Examples
+    ///
+    /// ```rust
+    /// # extern crate gotham;
+    /// # use std::time::Duration;
+    /// # use gotham::middleware::session::{MemoryBackend, NewSessionMiddleware};
+    /// # fn main() {
+    /// NewSessionMiddleware::new(MemoryBackend::new(Duration::from_secs(3600)).with_shards(64))
+    /// # ;}
+    /// ```
+    pub fn with_shards(self, shards: usize) -> MemoryBackend {
+        MemoryBackend {
+            storage: new_storage(cmp::max(shards, 1), self.ttl),
+            ..self
         }
+    }
 
-        MemoryBackend { storage }
+    /// Sets the maximum number of sessions which are held. When a new session would exceed the
+    /// limit, the least recently used sessions are removed to make room for it.
+    ///
+    /// The limit is divided evenly between the shards, and applies to each shard separately, so
+    /// sessions may be removed before the total reaches the limit.
+    ///
+    /// ## Examples
+    ///
+    /// ```rust
+    /// # extern crate gotham;
+    /// # use std::time::Duration;
+    /// # use gotham::middleware::session::{MemoryBackend, NewSessionMiddleware};
+    /// # fn main() {
+    /// NewSessionMiddleware::new(
+    ///     MemoryBackend::new(Duration::from_secs(3600))
+    ///         .with_max_sessions(100_000)
+    ///         .with_max_bytes(64 * 1024 * 1024),
+    /// )
+    /// # ;}
+    /// ```
+    pub fn with_max_sessions(self, max_sessions: usize) -> MemoryBackend {
+        MemoryBackend {
+            max_sessions: Some(max_sessions),
+            ..self
+        }
+    }
+
+    /// Sets the maximum number of bytes used by the identifiers and data of the sessions which
+    /// are held. When a session is written which would exceed the limit, the least recently used
+    /// sessions are removed to make room for it.
+    ///
+    /// As with `with_max_sessions`, the limit is divided evenly between the shards. A single
+    /// session larger than the limit of a shard can't be written.
+    pub fn with_max_bytes(self, max_bytes: usize) -> MemoryBackend {
+        MemoryBackend {
+            max_bytes: Some(max_bytes),
+            ..self
+        }
+    }
+
+    /// Returns the number of sessions held and bytes used, along with how many reads have found
+    /// a session and how many sessions have been evicted, since the `MemoryBackend` was created.
+    /// All `MemoryBackend` values cloned from the same original share the same sessions and
+    /// counts.
+    pub fn stats(&self) -> MemoryBackendStats {
+        self.storage.iter().map(|shard| lock(shard).stats()).fold(
+            MemoryBackendStats::default(),
+            |total, stats| MemoryBackendStats {
+                sessions: total.sessions + stats.sessions,
+                bytes: total.bytes + stats.bytes,
+                hits: total.hits + stats.hits,
+                misses: total.misses + stats.misses,
+                evictions: total.evictions + stats.evictions,
+            },
+        )
+    }
+
+    // Locks the shard which holds the session with `identifier`.
+    fn shard(&self, identifier: &SessionIdentifier) -> MutexGuard<Storage> {
+        let mut hasher = DefaultHasher::new();
+        identifier.value.hash(&mut hasher);
+        let index = (hasher.finish() % self.storage.len() as u64) as usize;
+        lock(&self.storage[index])
+    }
+
+    // Divides a limit set for the whole `MemoryBackend` between its shards.
+    fn shard_limit(&self, limit: Option<usize>) -> Option<usize> {
+        let shards = self.storage.len();
+        limit.map(|limit| (limit + shards - 1) / shards)
+    }
+}
+
+const DEFAULT_SHARDS: usize = 16;
+
+fn new_storage(shards: usize, ttl: Duration) -> Arc<Vec<Mutex<Storage>>> {
+    let storage: Arc<Vec<_>> = Arc::new((0..shards).map(|_| Mutex::new(Storage::new())).collect());
+
+    {
+        let storage = Arc::downgrade(&storage);
+        thread::spawn(move || cleanup_loop(storage, ttl));
+    }
+
+    storage
+}
+
+fn lock(shard: &Mutex<Storage>) -> MutexGuard<Storage> {
+    match shard.lock() {
+        Ok(storage) => storage,
+        Err(PoisonError { .. }) => {
+            unreachable!("session memory backend lock poisoned, HashMap panicked?")
+        }
     }
 }
 
@@ -78,46 +333,53 @@ impl Backend for MemoryBackend {
         identifier: SessionIdentifier,
         content: &[u8],
     ) -> Result<(), SessionError> {
-        match self.storage.lock() {
-            Ok(mut storage) => {
-                storage.insert(identifier.value, (Instant::now(), Vec::from(content)));
-                Ok(())
-            }
-            Err(PoisonError { .. }) => {
-                unreachable!("session memory backend lock poisoned, HashMap panicked?")
+        let max_sessions = self.shard_limit(self.max_sessions);
+        let max_bytes = self.shard_limit(self.max_bytes);
+
+        if let Some(max_bytes) = max_bytes {
+            if identifier.value.len() + content.len() > max_bytes {
+                return Err(SessionError::Backend(format!(
+                    "session of {} bytes exceeds the MemoryBackend limit of {} bytes per shard",
+                    content.len(),
+                    max_bytes
+                )));
             }
         }
+
+        let mut storage = self.shard(&identifier);
+        storage.insert(identifier.value, self.clock.now(), Vec::from(content));
+        storage.evict(max_sessions, max_bytes);
+        Ok(())
     }
 
     fn read_session(&self, identifier: SessionIdentifier) -> Box<SessionFuture> {
-        match self.storage.lock() {
-            Ok(mut storage) => match storage.get_refresh(&identifier.value) {
-                Some(&mut (ref mut instant, ref value)) => {
-                    *instant = Instant::now();
-                    Box::new(future::ok(Some(value.clone())))
-                }
-                None => Box::new(future::ok(None)),
-            },
-            Err(PoisonError { .. }) => {
-                unreachable!("session memory backend lock poisoned, HashMap panicked?")
+        let mut storage = self.shard(&identifier);
+        let now = self.clock.now();
+
+        if let Some(&mut (ref mut instant, ref value)) =
+            storage.sessions.get_refresh(&identifier.value)
+        {
+            if elapsed_between(*instant, now) < self.ttl {
+                *instant = now;
+                let value = value.clone();
+                storage.stats.hits += 1;
+                return Box::new(future::ok(Some(value)));
             }
         }
+
+        // The session is missing, or has expired but not yet been removed.
+        storage.remove(&identifier.value);
+        storage.stats.misses += 1;
+        Box::new(future::ok(None))
     }
 
     fn drop_session(&self, identifier: SessionIdentifier) -> Result<(), SessionError> {
-        match self.storage.lock() {
-            Ok(mut storage) => {
-                storage.remove(&identifier.value);
-                Ok(())
-            }
-            Err(PoisonError { .. }) => {
-                unreachable!("session memory backend lock poisoned, HashMap panicked?")
-            }
-        }
+        self.shard(&identifier).remove(&identifier.value);
+        Ok(())
     }
 }
 
-fn cleanup_loop(storage: Weak<Mutex<LinkedHashMap<String, (Instant, Vec<u8>)>>>, ttl: Duration) {
+fn cleanup_loop(storage: Weak<Vec<Mutex<Storage>>>, ttl: Duration) {
     loop {
         // If the original `Arc<_>` goes away, we don't need to keep sweeping the cache, because
         // it's gone too. We can bail out of this thread when the weak ref fails to upgrade.
@@ -126,27 +388,35 @@ fn cleanup_loop(storage: Weak<Mutex<LinkedHashMap<String, (Instant, Vec<u8>)>>>,
             Some(storage) => storage,
         };
 
-        let duration = match storage.lock() {
-            Err(PoisonError { .. }) => break,
-            Ok(mut storage) => cleanup_once(&mut storage, ttl),
-        };
-
-        if let Some(duration) = duration {
-            thread::sleep(duration);
+        // Sweep each shard until its next entry hasn't expired, and then sleep until the first
+        // of those entries will expire.
+        let mut sleep = ttl;
+        for shard in storage.iter() {
+            loop {
+                let duration = match shard.lock() {
+                    Err(PoisonError { .. }) => return,
+                    Ok(mut shard) => cleanup_once(&mut shard, ttl),
+                };
+
+                if let Some(duration) = duration {
+                    sleep = cmp::min(sleep, duration);
+                    break;
+                }
+            }
         }
+
+        drop(storage);
+        thread::sleep(sleep);
     }
 }
 
-fn cleanup_once(
-    storage: &mut LinkedHashMap<String, (Instant, Vec<u8>)>,
-    ttl: Duration,
-) -> Option<Duration> {
-    match storage.front() {
+fn cleanup_once(storage: &mut Storage, ttl: Duration) -> Option<Duration> {
+    match storage.sessions.front() {
         Some((_, &(instant, _))) => {
-            let age = instant.elapsed();
+            let age = elapsed_between(instant, Instant::now());
 
             if age >= ttl {
-                if let Some((key, _)) = storage.pop_front() {
+                if let Some(key) = storage.pop_front() {
                     trace!(" expired session {} and removed from MemoryBackend", key);
                 }
 
@@ -161,22 +431,22 @@ fn cleanup_once(
                 // The arbitrary numbers here were chosen to avoid the resizes being extremely
                 // frequent. Powers of 2 seemed like a reasonable idea, to let the optimiser
                 // potentially shave off a few CPU cycles. Totally unscientific though.
-                let cap = storage.capacity();
-                let len = storage.len();
+                let cap = storage.sessions.capacity();
+                let len = storage.sessions.len();
 
                 if cap >= 65536 && cap / 8 > len {
-                    storage.shrink_to_fit();
+                    storage.sessions.shrink_to_fit();
 
                     trace!(
                         " session backend had capacity {} and {} sessions, new capacity: {}",
                         cap,
                         len,
-                        storage.capacity()
+                        storage.sessions.capacity()
                     );
                 }
 
                 // Sleep until the next entry expires, but for at least 1 second
-                Some(::std::cmp::max(ttl - age, Duration::from_secs(1)))
+                Some(cmp::max(ttl - age, Duration::from_secs(1)))
             }
         }
         // No sessions; sleep for the TTL, because that's the soonest we'll need to expire anything
@@ -193,20 +463,23 @@ mod tests {
 
     #[test]
     fn cleanup_test() {
-        let mut storage = LinkedHashMap::new();
+        let mut storage = Storage::new();
 
         storage.insert(
             "abcd".to_owned(),
-            (Instant::now() - Duration::from_secs(2), vec![]),
+            Instant::now() - Duration::from_secs(2),
+            vec![1, 2, 3],
         );
+        assert_eq!(storage.stats().bytes(), 7);
 
         cleanup_once(&mut storage, Duration::from_secs(1));
-        assert!(storage.is_empty());
+        assert!(storage.sessions.is_empty());
+        assert_eq!(storage.stats().bytes(), 0);
     }
 
     #[test]
     fn cleanup_join_test() {
-        let storage = Arc::new(Mutex::new(LinkedHashMap::new()));
+        let storage = Arc::new(vec![Mutex::new(Storage::new())]);
         let weak = Arc::downgrade(&storage);
 
         let handle = thread::spawn(move || cleanup_loop(weak, Duration::from_millis(1)));
@@ -240,9 +513,39 @@ mod tests {
         assert_eq!(bytes, received);
     }
 
+    #[test]
+    fn memory_backend_clock_test() {
+        use helpers::clock::ManualClock;
+
+        let clock = ManualClock::new();
+        let backend = MemoryBackend::new(Duration::from_secs(60)).with_clock(clock.clone());
+        let identifier = SessionIdentifier {
+            value: "totally_random_identifier".to_owned(),
+        };
+
+        backend
+            .persist_session(identifier.clone(), b"data")
+            .expect("failed to persist");
+
+        clock.advance(Duration::from_secs(59));
+        let received = backend.read_session(identifier.clone()).wait().unwrap();
+        assert_eq!(received, Some(b"data".to_vec()));
+
+        // Reading the session refreshed it.
+        clock.advance(Duration::from_secs(59));
+        assert!(backend
+            .read_session(identifier.clone())
+            .wait()
+            .unwrap()
+            .is_some());
+
+        clock.advance(Duration::from_secs(60));
+        assert!(backend.read_session(identifier).wait().unwrap().is_none());
+    }
+
     #[test]
     fn memory_backend_refresh_test() {
-        let new_backend = MemoryBackend::new(Duration::from_millis(100));
+        let new_backend = MemoryBackend::new(Duration::from_millis(100)).with_shards(1);
         let bytes: Vec<u8> = (0..64).map(|_| rand::random()).collect();
         let identifier = SessionIdentifier {
             value: "totally_random_identifier".to_owned(),
@@ -265,14 +568,14 @@ mod tests {
             .expect("failed to persist");
 
         {
-            let mut storage = backend.storage.lock().expect("couldn't lock storage");
+            let mut storage = backend.storage[0].lock().expect("couldn't lock storage");
             assert_eq!(
-                storage.front().expect("no front element").0,
+                storage.sessions.front().expect("no front element").0,
                 &identifier.value
             );
 
             assert_eq!(
-                storage.back().expect("no back element").0,
+                storage.sessions.back().expect("no back element").0,
                 &identifier2.value
             );
         }
@@ -284,16 +587,151 @@ mod tests {
 
         {
             // Identifiers have swapped
-            let mut storage = backend.storage.lock().expect("couldn't lock storage");
+            let mut storage = backend.storage[0].lock().expect("couldn't lock storage");
             assert_eq!(
-                storage.front().expect("no front element").0,
+                storage.sessions.front().expect("no front element").0,
                 &identifier2.value
             );
 
             assert_eq!(
-                storage.back().expect("no back element").0,
+                storage.sessions.back().expect("no back element").0,
                 &identifier.value
             );
         }
     }
+
+    #[test]
+    fn memory_backend_limits_test() {
+        let backend = MemoryBackend::new(Duration::from_secs(60))
+            .with_shards(1)
+            .with_max_sessions(2);
+        let identifier = |value: &str| SessionIdentifier {
+            value: value.to_owned(),
+        };
+        let read = |value| backend.read_session(identifier(value)).wait().unwrap();
+
+        backend.persist_session(identifier("a"), b"1").unwrap();
+        backend.persist_session(identifier("b"), b"2").unwrap();
+
+        // Reading "a" makes "b" the least recently used.
+        assert!(read("a").is_some());
+        backend.persist_session(identifier("c"), b"3").unwrap();
+
+        assert!(read("b").is_none());
+        assert!(read("a").is_some());
+
+        let stats = backend.stats();
+        assert_eq!(stats.sessions(), 2);
+        assert_eq!(stats.bytes(), 4);
+        assert_eq!(stats.hits(), 2);
+        assert_eq!(stats.misses(), 1);
+        assert_eq!(stats.evictions(), 1);
+    }
+
+    #[test]
+    fn memory_backend_max_bytes_test() {
+        let backend = MemoryBackend::new(Duration::from_secs(60))
+            .with_shards(1)
+            .with_max_bytes(30);
+        let identifier = |value: &str| SessionIdentifier {
+            value: value.to_owned(),
+        };
+
+        backend.persist_session(identifier("a"), &[0; 9]).unwrap();
+        backend.persist_session(identifier("b"), &[0; 9]).unwrap();
+        backend.persist_session(identifier("c"), &[0; 9]).unwrap();
+        assert_eq!(backend.stats().bytes(), 30);
+        assert_eq!(backend.stats().evictions(), 0);
+
+        // Replacing a session doesn't count its old data.
+        backend.persist_session(identifier("c"), &[0; 4]).unwrap();
+        assert_eq!(backend.stats().bytes(), 25);
+
+        // "a" is the least recently used, and is evicted to keep within 30 bytes.
+        backend.persist_session(identifier("d"), &[0; 9]).unwrap();
+        let stats = backend.stats();
+        assert_eq!(stats.sessions(), 3);
+        assert_eq!(stats.bytes(), 25);
+        assert_eq!(stats.evictions(), 1);
+        assert!(backend
+            .read_session(identifier("a"))
+            .wait()
+            .unwrap()
+            .is_none());
+
+        // A session which could never fit is refused, without evicting anything.
+        assert!(backend.persist_session(identifier("e"), &[0; 30]).is_err());
+        assert_eq!(backend.stats().sessions(), 3);
+
+        backend.drop_session(identifier("d")).unwrap();
+        assert_eq!(backend.stats().bytes(), 15);
+    }
+
+    #[test]
+    fn memory_backend_shards_test() {
+        let backend = MemoryBackend::new(Duration::from_secs(60))
+            .with_shards(4)
+            .with_max_sessions(400);
+
+        for i in 0..100 {
+            let identifier = SessionIdentifier {
+                value: format!("identifier-{}", i),
+            };
+            backend.persist_session(identifier, b"data").unwrap();
+        }
+
+        let used = backend
+            .storage
+            .iter()
+            .filter(|shard| !lock(shard).sessions.is_empty())
+            .count();
+        assert!(used > 1);
+
+        let stats = backend.stats();
+        assert_eq!(stats.sessions(), 100);
+        assert_eq!(stats.evictions(), 0);
+
+        for i in 0..100 {
+            let identifier = SessionIdentifier {
+                value: format!("identifier-{}", i),
+            };
+            let data = backend.read_session(identifier).wait().unwrap();
+            assert_eq!(data, Some(b"data".to_vec()));
+        }
+        assert_eq!(backend.stats().hits(), 100);
+    }
+
+    #[test]
+    fn memory_backend_shards_concurrent_test() {
+        let backend = MemoryBackend::new(Duration::from_secs(60));
+        let threads = 8;
+        let sessions = 50;
+
+        let handles: Vec<_> = (0..threads)
+            .map(|t| {
+                let backend = backend.clone();
+                thread::spawn(move || {
+                    for i in 0..sessions {
+                        let identifier = SessionIdentifier {
+                            value: format!("session-{}-{}", t, i),
+                        };
+                        let data = format!("{}-{}", t, i).into_bytes();
+                        backend.persist_session(identifier.clone(), &data).unwrap();
+
+                        let read = backend.read_session(identifier).wait().unwrap();
+                        assert_eq!(read, Some(data));
+                    }
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let stats = backend.stats();
+        assert_eq!(stats.sessions(), threads * sessions);
+        assert_eq!(stats.hits(), (threads * sessions) as u64);
+        assert_eq!(stats.evictions(), 0);
+    }
 }
@@ -1,3 +1,4 @@
+pub(super) mod cached;
 pub(super) mod memory;
 
 use std::io;
@@ -39,5 +40,13 @@ pub trait Backend: Send {
     fn read_session(&self, identifier: SessionIdentifier) -> Box<SessionFuture>;
 
     /// Drops a session from the underlying storage.
-    fn drop_session(&self, identifier: SessionIdentifier) -> Result<(), SessionError>;
+    ///
+    /// The default implementation returns an error, so that a backend written before sessions
+    /// could be dropped still compiles, but fails `SessionData::discard` rather than leaving the
+    /// session in storage.
+    fn drop_session(&self, _identifier: SessionIdentifier) -> Result<(), SessionError> {
+        Err(SessionError::Backend(
+            "backend does not support dropping sessions".to_owned(),
+        ))
+    }
 }
@@ -0,0 +1,99 @@
+//! Defines the circuit breaker which stops `SessionMiddleware` reading sessions from a backend
+//! which keeps failing.
+
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+pub(super) struct CircuitBreaker {
+    threshold: usize,
+    cool_down: Duration,
+    circuit: Mutex<Circuit>,
+}
+
+struct Circuit {
+    // consecutive failed reads
+    failures: usize,
+    // when the circuit is open, the time at which reads are attempted again
+    open_until: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    /// Creates a `CircuitBreaker` which opens after `threshold` consecutive failures, for
+    /// `cool_down`.
+    pub(super) fn new(threshold: usize, cool_down: Duration) -> CircuitBreaker {
+        assert!(threshold > 0, "threshold must not be zero");
+
+        CircuitBreaker {
+            threshold,
+            cool_down,
+            circuit: Mutex::new(Circuit {
+                failures: 0,
+                open_until: None,
+            }),
+        }
+    }
+
+    /// Determines whether the backend should be read at `now`, which is false while the circuit
+    /// is open.
+    pub(super) fn allows(&self, now: Instant) -> bool {
+        match self.circuit().open_until {
+            Some(until) => now >= until,
+            None => true,
+        }
+    }
+
+    /// Records the outcome of a read which completed at `now`. Once the cool-down has passed,
+    /// the circuit reopens after a single further failure, and closes after a success.
+    pub(super) fn record(&self, success: bool, now: Instant) {
+        let mut circuit = self.circuit();
+
+        if success {
+            circuit.failures = 0;
+            circuit.open_until = None;
+            return;
+        }
+
+        circuit.failures += 1;
+        if circuit.failures >= self.threshold {
+            circuit.open_until = Some(now + self.cool_down);
+        }
+    }
+
+    fn circuit(&self) -> MutexGuard<Circuit> {
+        match self.circuit.lock() {
+            Ok(circuit) => circuit,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opens_after_consecutive_failures() {
+        let start = Instant::now();
+        let later = |secs| start + Duration::from_secs(secs);
+        let breaker = CircuitBreaker::new(2, Duration::from_secs(10));
+
+        breaker.record(false, start);
+        breaker.record(true, start);
+        breaker.record(false, start);
+        assert!(breaker.allows(start));
+
+        breaker.record(false, later(1));
+        assert!(!breaker.allows(later(1)));
+        assert!(!breaker.allows(later(10)));
+        assert!(breaker.allows(later(11)));
+
+        // a single failure after the cool-down reopens the circuit
+        breaker.record(false, later(11));
+        assert!(!breaker.allows(later(12)));
+        assert!(breaker.allows(later(21)));
+
+        breaker.record(true, later(21));
+        breaker.record(false, later(21));
+        assert!(breaker.allows(later(21)));
+    }
+}
@@ -1,44 +1,58 @@
 //! Defines a session middleware with a pluggable backend.
 
+use std::error::Error;
+use std::fmt;
 use std::io;
 use std::marker::PhantomData;
 use std::ops::{Deref, DerefMut};
 use std::panic::RefUnwindSafe;
 use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
 
 use base64;
 use bincode;
-use cookie::{Cookie, CookieJar};
 use futures::{
     future::{self, FutureResult},
     Future,
 };
-use hyper::header::{HeaderMap, COOKIE, SET_COOKIE};
-use hyper::{Body, Response, StatusCode};
+use hyper::{Body, Response};
 use rand::RngCore;
 use serde::{Deserialize, Serialize};
 
 use super::{Middleware, NewMiddleware};
+use config::{SameSitePolicy, SessionConfig};
+use cookies::{cookie_jar, Cookie, SameSite};
 use handler::{HandlerError, HandlerFuture, IntoHandlerError};
-use helpers::http::response::create_empty_response;
-use state::{self, FromState, State, StateData};
+use helpers::clock;
+use helpers::random::{random_source, RandomSource};
+use state::{State, StateData};
 
 mod backend;
+mod breaker;
 mod rng;
 
-pub use self::backend::memory::MemoryBackend;
+use self::backend::SessionFuture;
+use self::breaker::CircuitBreaker;
+
+pub use self::backend::cached::CachedBackend;
+pub use self::backend::memory::{MemoryBackend, MemoryBackendStats};
 pub use self::backend::{Backend, NewBackend};
 
 const SECURE_COOKIE_PREFIX: &str = "__Secure-";
 const HOST_COOKIE_PREFIX: &str = "__Host-";
 
 /// Represents the session identifier which is held in the user agent's session cookie.
+///
+/// The identifier of the current session is placed into `State` by `SessionMiddleware`, so that
+/// it can be attached to log lines about the request.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct SessionIdentifier {
     /// The value which is passed as a cookie, identifying the session.
     pub value: String,
 }
 
+impl StateData for SessionIdentifier {}
+
 /// The kind of failure which occurred trying to perform a session operation.
 #[derive(Debug)]
 pub enum SessionError {
@@ -46,14 +60,82 @@ pub enum SessionError {
     Backend(String),
     /// The session was unable to be deserialized.
     Deserialize,
+    /// The session was unable to be serialized, and the included message describes the problem.
+    Serialize(String),
     /// Exhaustive match against this enum is unsupported.
     #[doc(hidden)]
     __NonExhaustive,
 }
 
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SessionError::Backend(ref message) => write!(f, "session backend failed: {}", message),
+            SessionError::Deserialize => f.write_str("session could not be deserialized"),
+            SessionError::Serialize(ref message) => {
+                write!(f, "session could not be serialized: {}", message)
+            }
+            SessionError::__NonExhaustive => unreachable!(),
+        }
+    }
+}
+
+impl Error for SessionError {
+    fn description(&self) -> &str {
+        match *self {
+            SessionError::Backend(_) => "session backend failed",
+            SessionError::Deserialize => "session could not be deserialized",
+            SessionError::Serialize(_) => "session could not be serialized",
+            SessionError::__NonExhaustive => unreachable!(),
+        }
+    }
+}
+
+/// The cause of the `HandlerError` returned by `SessionMiddleware` when the session data changed
+/// by a request could not be written to the backend.
+///
+/// The `HandlerError` has a status of `500 Internal Server Error`, and is passed to any
+/// `ErrorReporter` set for the server. A middleware running before `SessionMiddleware` can find
+/// this cause with `HandlerError::downcast_ref` to log the failure or replace the response.
+#[derive(Debug)]
+pub struct SessionPersistError {
+    identifier: SessionIdentifier,
+    error: SessionError,
+}
+
+impl SessionPersistError {
+    /// The identifier of the session which could not be written.
+    pub fn identifier(&self) -> &SessionIdentifier {
+        &self.identifier
+    }
+
+    /// The failure which prevented the session from being written.
+    pub fn error(&self) -> &SessionError {
+        &self.error
+    }
+}
+
+impl fmt::Display for SessionPersistError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "failed to persist session: {}", self.error)
+    }
+}
+
+impl Error for SessionPersistError {
+    fn description(&self) -> &str {
+        "failed to persist session"
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        Some(&self.error)
+    }
+}
+
 enum SessionCookieState {
     New,
     Existing,
+    // created in place of a session which couldn't be read, so is neither sent nor persisted
+    Detached,
 }
 
 enum SessionDataState {
@@ -61,6 +143,22 @@ enum SessionDataState {
     Dirty,
 }
 
+/// How `SessionMiddleware` continues when a session can't be read from the backend, as set by
+/// `NewSessionMiddleware::with_backend_failure_policy`.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum BackendFailurePolicy {
+    /// The request fails with a `HandlerError` caused by the `SessionError`, which has a status of
+    /// `500 Internal Server Error`. This is the default.
+    Fail,
+    /// The request continues with a new, empty session. The new session is not persisted, and
+    /// the session cookie of the user agent is left unchanged, so that the existing session is
+    /// used again once the backend has recovered.
+    NewSession,
+    /// The request continues without any `SessionData` in `State`, so handlers must use
+    /// `try_borrow_from` to access the session.
+    NoSession,
+}
+
 #[derive(Copy, Clone, PartialEq, Debug)]
 enum SameSiteEnforcement {
     Disabled,
@@ -98,37 +196,23 @@ impl Default for SessionCookieConfig {
 }
 
 impl SessionCookieConfig {
-    fn to_cookie_string(&self, value: &str) -> String {
-        // Ensure this is always enough to prevent re-allocs
-        let mut cookie_value = String::with_capacity(255);
-
-        cookie_value.push_str(&self.name);
-        cookie_value.push('=');
-        cookie_value.push_str(value);
-
-        if self.secure {
-            cookie_value.push_str("; Secure")
-        }
-
-        if self.http_only {
-            cookie_value.push_str("; HttpOnly")
-        }
+    fn to_cookie(&self, value: String) -> Cookie<'static> {
+        let mut cookie = Cookie::build(self.name.clone(), value)
+            .secure(self.secure)
+            .http_only(self.http_only)
+            .path(self.path.clone());
 
         match self.same_site {
-            SameSiteEnforcement::Strict => cookie_value.push_str("; SameSite=Strict"),
-            SameSiteEnforcement::Lax => cookie_value.push_str("; SameSite=Lax"),
+            SameSiteEnforcement::Strict => cookie = cookie.same_site(SameSite::Strict),
+            SameSiteEnforcement::Lax => cookie = cookie.same_site(SameSite::Lax),
             SameSiteEnforcement::Disabled => (),
         }
 
         if let Some(ref domain) = self.domain {
-            cookie_value.push_str("; Domain=");
-            cookie_value.push_str(domain);
+            cookie = cookie.domain(domain.clone());
         }
 
-        cookie_value.push_str("; Path=");
-        cookie_value.push_str(&self.path);
-
-        cookie_value
+        cookie.finish()
     }
 
     /// Validates cookie attributes if the name includes a Cookie Prefix.
@@ -285,7 +369,6 @@ where
 {
     /// Discards the session, invalidating it for future use and removing the data from the
     /// `Backend`.
-    // TODO: Add test case that covers this.
     pub fn discard(self, state: &mut State) -> Result<(), SessionError> {
         state.put(SessionDropData {
             cookie_config: self.cookie_config,
@@ -293,6 +376,11 @@ where
         self.backend.drop_session(self.identifier)
     }
 
+    // The identifier of the session, used by other middleware to tell sessions apart.
+    pub(crate) fn identifier(&self) -> &SessionIdentifier {
+        &self.identifier
+    }
+
     // Create a new, blank `SessionData<T>`
     fn new<B>(middleware: SessionMiddleware<B, T>) -> SessionData<T>
     where
@@ -320,6 +408,17 @@ where
         }
     }
 
+    // Create a new, blank `SessionData<T>` standing in for a session which couldn't be read
+    fn detached<B>(middleware: SessionMiddleware<B, T>) -> SessionData<T>
+    where
+        B: Backend + Send + 'static,
+    {
+        SessionData {
+            cookie_state: SessionCookieState::Detached,
+            ..SessionData::new(middleware)
+        }
+    }
+
     // Load an existing, serialized session into a `SessionData<T>`
     fn construct<B>(
         middleware: SessionMiddleware<B, T>,
@@ -460,6 +559,8 @@ where
     new_backend: B,
     identifier_rng: Arc<Mutex<rng::SessionIdentifierRng>>,
     cookie_config: Arc<SessionCookieConfig>,
+    backend_failure: BackendFailurePolicy,
+    circuit_breaker: Option<Arc<CircuitBreaker>>,
     phantom: PhantomData<SessionTypePhantom<T>>,
 }
 
@@ -473,7 +574,10 @@ where
 {
     backend: B,
     identifier_rng: Arc<Mutex<rng::SessionIdentifierRng>>,
+    random_source: Option<Arc<RandomSource>>,
     cookie_config: Arc<SessionCookieConfig>,
+    backend_failure: BackendFailurePolicy,
+    circuit_breaker: Option<Arc<CircuitBreaker>>,
     phantom: PhantomData<T>,
 }
 
@@ -490,7 +594,10 @@ where
             .map(|backend| SessionMiddleware {
                 backend,
                 identifier_rng: self.identifier_rng.clone(),
+                random_source: None,
                 cookie_config: self.cookie_config.clone(),
+                backend_failure: self.backend_failure,
+                circuit_breaker: self.circuit_breaker.clone(),
                 phantom: PhantomData,
             })
     }
@@ -506,6 +613,8 @@ where
             new_backend: self.new_backend.clone(),
             identifier_rng: self.identifier_rng.clone(),
             cookie_config: self.cookie_config.clone(),
+            backend_failure: self.backend_failure,
+            circuit_breaker: self.circuit_breaker.clone(),
             phantom: PhantomData,
         }
     }
@@ -528,6 +637,8 @@ where
             new_backend: b,
             identifier_rng: Arc::new(Mutex::new(rng::session_identifier_rng())),
             cookie_config: Arc::new(SessionCookieConfig::default()),
+            backend_failure: BackendFailurePolicy::Fail,
+            circuit_breaker: None,
             phantom: PhantomData,
         }
     }
@@ -755,6 +866,128 @@ where
         self.rebuild_new_session_middleware(cookie_config)
     }
 
+    /// Applies the session cookie settings of a `Config` which are given, as loaded by a
+    /// `ConfigLoader`. The settings which aren't given keep their current values.
+    ///
+    /// ```rust
+    /// # extern crate gotham;
+    /// # #[macro_use]
+    /// # extern crate serde_derive;
+    /// #
+    /// # use gotham::config::ConfigLoader;
+    /// # use gotham::middleware::session::NewSessionMiddleware;
+    /// #
+    /// # #[derive(Default, Serialize, Deserialize)]
+    /// # struct MySessionType {
+    /// #   items: Vec<String>,
+    /// # }
+    /// #
+    /// # fn main() {
+    /// let config = ConfigLoader::new().load().unwrap();
+    ///
+    /// NewSessionMiddleware::default()
+    ///     .with_session_type::<MySessionType>()
+    ///     .with_config(config.session())
+    /// # ;}
+    /// ```
+    pub fn with_config(self, config: &SessionConfig) -> NewSessionMiddleware<B, T> {
+        let mut cookie_config = (*self.cookie_config).clone();
+
+        if let Some(name) = config.cookie_name() {
+            cookie_config.name = name.to_owned();
+        }
+        if let Some(path) = config.cookie_path() {
+            cookie_config.path = path.to_owned();
+        }
+        if let Some(domain) = config.cookie_domain() {
+            cookie_config.domain = Some(domain.to_owned());
+        }
+        if let Some(secure) = config.secure() {
+            cookie_config.secure = secure;
+        }
+        if let Some(same_site) = config.same_site() {
+            cookie_config.same_site = match same_site {
+                SameSitePolicy::Strict => SameSiteEnforcement::Strict,
+                SameSitePolicy::Lax => SameSiteEnforcement::Lax,
+                SameSitePolicy::Disabled => SameSiteEnforcement::Disabled,
+            };
+        }
+
+        self.rebuild_new_session_middleware(cookie_config)
+    }
+
+    /// Sets how requests continue when their session can't be read from the backend, such as
+    /// while it is unavailable. By default, the request fails.
+    ///
+    /// ```rust
+    /// # extern crate gotham;
+    /// # #[macro_use]
+    /// # extern crate serde_derive;
+    /// #
+    /// # use gotham::middleware::session::{BackendFailurePolicy, NewSessionMiddleware};
+    /// #
+    /// # #[derive(Default, Serialize, Deserialize)]
+    /// # struct MySessionType {
+    /// #   items: Vec<String>,
+    /// # }
+    /// #
+    /// # fn main() {
+    /// NewSessionMiddleware::default()
+    ///     .with_session_type::<MySessionType>()
+    ///     .with_backend_failure_policy(BackendFailurePolicy::NewSession)
+    /// # ;}
+    /// ```
+    pub fn with_backend_failure_policy(
+        self,
+        backend_failure: BackendFailurePolicy,
+    ) -> NewSessionMiddleware<B, T> {
+        NewSessionMiddleware {
+            backend_failure,
+            ..self
+        }
+    }
+
+    /// Stops reading sessions from the backend for `cool_down` once `failures` consecutive reads
+    /// have failed, so that a backend which is down isn't sent a read for every request. While
+    /// reads are stopped, requests with a session are handled according to the
+    /// `BackendFailurePolicy`. After the cool-down reads resume, and stop again after a single
+    /// further failure.
+    ///
+    /// ```rust
+    /// # extern crate gotham;
+    /// # #[macro_use]
+    /// # extern crate serde_derive;
+    /// #
+    /// # use std::time::Duration;
+    /// # use gotham::middleware::session::{BackendFailurePolicy, NewSessionMiddleware};
+    /// #
+    /// # #[derive(Default, Serialize, Deserialize)]
+    /// # struct MySessionType {
+    /// #   items: Vec<String>,
+    /// # }
+    /// #
+    /// # fn main() {
+    /// NewSessionMiddleware::default()
+    ///     .with_session_type::<MySessionType>()
+    ///     .with_backend_failure_policy(BackendFailurePolicy::NoSession)
+    ///     .with_circuit_breaker(5, Duration::from_secs(30))
+    /// # ;}
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// If `failures` is zero.
+    pub fn with_circuit_breaker(
+        self,
+        failures: usize,
+        cool_down: Duration,
+    ) -> NewSessionMiddleware<B, T> {
+        NewSessionMiddleware {
+            circuit_breaker: Some(Arc::new(CircuitBreaker::new(failures, cool_down))),
+            ..self
+        }
+    }
+
     /// Changes the session type to the provided type parameter. This is required to override the
     /// default (unusable) session type of `()`.
     ///
@@ -782,6 +1015,8 @@ where
             new_backend: self.new_backend,
             identifier_rng: self.identifier_rng,
             cookie_config: self.cookie_config,
+            backend_failure: self.backend_failure,
+            circuit_breaker: self.circuit_breaker,
             phantom: PhantomData,
         }
     }
@@ -792,24 +1027,14 @@ where
     B: Backend + Send + 'static,
     T: Default + Serialize + for<'de> Deserialize<'de> + Send + 'static,
 {
-    fn call<Chain>(self, state: State, chain: Chain) -> Box<HandlerFuture>
+    fn call<Chain>(mut self, mut state: State, chain: Chain) -> Box<HandlerFuture>
     where
         Chain: FnOnce(State) -> Box<HandlerFuture> + Send + 'static,
         Self: Sized,
     {
-        let state_cookies = {
-            HeaderMap::borrow_from(&state)
-                .get_all(COOKIE)
-                .iter()
-                .flat_map(|cv| cv.to_str())
-                .flat_map(|cs| Cookie::parse(cs.to_owned()))
-                .fold(CookieJar::new(), |mut jar, cookie| {
-                    jar.add_original(cookie);
-                    jar
-                })
-        };
+        self.random_source = random_source(&state);
 
-        let session_identifier = state_cookies
+        let session_identifier = cookie_jar(&mut state)
             .get(&self.cookie_config.name)
             .map(|cookie| cookie.value())
             .map(|value| SessionIdentifier {
@@ -818,31 +1043,61 @@ where
 
         match session_identifier {
             Some(id) => {
-                trace!(
-                    "[{}] SessionIdentifier {} found in cookie from user-agent",
-                    state::request_id(&state),
+                request_trace!(
+                    &state,
+                    "SessionIdentifier {} found in cookie from user-agent",
                     id.value
                 );
 
-                let f = self
-                    .backend
-                    .read_session(id.clone())
-                    .then(move |r| self.load_session_into_state(state, id, r))
+                let open = match self.circuit_breaker {
+                    Some(ref breaker) => !breaker.allows(clock::now(&state)),
+                    None => false,
+                };
+
+                let read: Box<SessionFuture> = if open {
+                    request_trace!(
+                        &state,
+                        "session backend circuit breaker is open, not reading session ({})",
+                        id.value
+                    );
+
+                    let e = SessionError::Backend("circuit breaker is open".to_owned());
+                    Box::new(future::err(e))
+                } else {
+                    self.backend.read_session(id.clone())
+                };
+
+                // the outcome of a read which was attempted is recorded by the circuit breaker
+                let breaker = if open {
+                    None
+                } else {
+                    self.circuit_breaker.clone()
+                };
+
+                let f = read
+                    .then(move |r| {
+                        if let Some(breaker) = breaker {
+                            breaker.record(r.is_ok(), clock::now(&state));
+                        }
+                        self.load_session_into_state(state, id, r)
+                    })
                     .and_then(|state| chain(state))
-                    .and_then(persist_session::<T>);
+                    .and_then(persist_session::<T>)
+                    .or_else(persist_session_on_error::<T>);
 
                 Box::new(f)
             }
             None => {
-                trace!(
-                    "[{}] No SessionIdentifier found in cookie from user-agent",
-                    state::request_id(&state),
+                request_trace!(
+                    &state,
+                    "No SessionIdentifier found in cookie from user-agent"
                 );
 
                 let f = self
                     .new_session(state)
                     .and_then(|state| chain(state))
-                    .and_then(persist_session::<T>);
+                    .and_then(persist_session::<T>)
+                    .or_else(persist_session_on_error::<T>);
 
                 Box::new(f)
             }
@@ -855,13 +1110,21 @@ where
     B: Backend + 'static,
     T: Default + Serialize + for<'de> Deserialize<'de> + Send + 'static,
 {
+    // Generates an identifier from the `RandomSource` set for the server, if any, which is only
+    // done in tests.
     fn random_identifier(&self) -> SessionIdentifier {
         let mut bytes = [0u8; 64];
 
-        match self.identifier_rng.lock() {
-            Ok(mut rng) => rng.fill_bytes(&mut bytes),
-            Err(PoisonError { .. }) => unreachable!("identifier_rng lock poisoned. Rng panicked?"),
-        };
+        if let Some(ref source) = self.random_source {
+            source.fill_bytes(&mut bytes);
+        } else {
+            match self.identifier_rng.lock() {
+                Ok(mut rng) => rng.fill_bytes(&mut bytes),
+                Err(PoisonError { .. }) => {
+                    unreachable!("identifier_rng lock poisoned. Rng panicked?")
+                }
+            };
+        }
 
         SessionIdentifier {
             value: base64::encode_config(&bytes[..], base64::URL_SAFE_NO_PAD),
@@ -870,32 +1133,41 @@ where
 }
 
 fn persist_session<T>(
-    (mut state, mut response): (State, Response<Body>),
+    (mut state, response): (State, Response<Body>),
 ) -> FutureResult<(State, Response<Body>), (State, HandlerError)>
 where
     T: Default + Serialize + for<'de> Deserialize<'de> + Send + 'static,
 {
     match state.try_take::<SessionDropData>() {
         Some(ref session_drop_data) => {
-            trace!(
-                "[{}] SessionDropData found in state, removing session cookie from user agent",
-                state::request_id(&state)
+            request_trace!(
+                &state,
+                "SessionDropData found in state, removing session cookie from user agent"
             );
-            reset_cookie(&mut response, session_drop_data);
+            reset_cookie(&mut state, session_drop_data);
             return future::ok((state, response));
         }
         None => {
-            trace!(
-                "[{}] SessionDropData is not present, retaining session cookie",
-                state::request_id(&state)
+            request_trace!(
+                &state,
+                "SessionDropData is not present, retaining session cookie"
             );
         }
     }
 
     match state.try_take::<SessionData<T>>() {
         Some(session_data) => {
-            if let SessionCookieState::New = session_data.cookie_state {
-                send_cookie(&mut response, &session_data);
+            match session_data.cookie_state {
+                SessionCookieState::New => send_cookie(&mut state, &session_data),
+                SessionCookieState::Existing => (),
+                SessionCookieState::Detached => {
+                    request_trace!(
+                        &state,
+                        "session ({}) stands in for one which couldn't be read, not persisting",
+                        session_data.identifier.value
+                    );
+                    return future::ok((state, response));
+                }
             }
 
             match session_data.state {
@@ -908,31 +1180,66 @@ where
     }
 }
 
-fn send_cookie<B, T>(response: &mut Response<B>, session_data: &SessionData<T>)
+// Persists changes to an existing session when the rest of the chain fails, so that they are not
+// lost just because a response could not be produced. A new session is discarded instead, as its
+// cookie can't be sent without a response.
+fn persist_session_on_error<T>(
+    (mut state, err): (State, HandlerError),
+) -> FutureResult<(State, Response<Body>), (State, HandlerError)>
 where
     T: Default + Serialize + for<'de> Deserialize<'de> + Send + 'static,
 {
-    let cookie_string = session_data
-        .cookie_config
-        .to_cookie_string(&session_data.identifier.value);
-    write_cookie(cookie_string, response);
+    if state.try_take::<SessionDropData>().is_some() {
+        return future::err((state, err));
+    }
+
+    let session_data = match state.try_take::<SessionData<T>>() {
+        Some(session_data) => session_data,
+        None => return future::err((state, err)),
+    };
+
+    if let (&SessionCookieState::Existing, &SessionDataState::Dirty) =
+        (&session_data.cookie_state, &session_data.state)
+    {
+        let result = bincode::serialize(&session_data.value)
+            .map_err(|e| format!("{:?}", e))
+            .and_then(|bytes| {
+                session_data
+                    .backend
+                    .persist_session(session_data.identifier.clone(), &bytes[..])
+                    .map_err(|e| format!("{:?}", e))
+            });
+
+        match result {
+            Ok(_) => request_trace!(
+                &state,
+                "persisted session ({}) after handler error",
+                session_data.identifier.value
+            ),
+            Err(e) => request_error!(
+                &state,
+                "failed to persist session after handler error: {}",
+                e
+            ),
+        }
+    }
+
+    future::err((state, err))
 }
 
-fn reset_cookie<B>(response: &mut Response<B>, session_drop_data: &SessionDropData) {
-    let cookie_string = session_drop_data
+fn send_cookie<T>(state: &mut State, session_data: &SessionData<T>)
+where
+    T: Default + Serialize + for<'de> Deserialize<'de> + Send + 'static,
+{
+    let cookie = session_data
         .cookie_config
-        .to_cookie_string("discarded");
-    let cookie_string = format!(
-        "{}; expires=Thu, 01 Jan 1970 00:00:00 GMT; max-age=0",
-        cookie_string
-    );
-    write_cookie(cookie_string, response);
+        .to_cookie(session_data.identifier.value.clone());
+    cookie_jar(state).add(cookie);
 }
 
-fn write_cookie<B>(cookie: String, response: &mut Response<B>) {
-    response
-        .headers_mut()
-        .append(SET_COOKIE, cookie.parse().unwrap());
+fn reset_cookie(state: &mut State, session_drop_data: &SessionDropData) {
+    let cookie = session_drop_data.cookie_config.to_cookie(String::new());
+    cookie_jar(state).remove(cookie);
 }
 
 fn write_session<T>(
@@ -943,42 +1250,36 @@ fn write_session<T>(
 where
     T: Default + Serialize + for<'de> Deserialize<'de> + Send + 'static,
 {
-    let bytes = match bincode::serialize(&session_data.value) {
-        Ok(bytes) => bytes,
-        Err(e) => {
-            error!(
-                "[{}] failed to serialize session: {:?}",
-                state::request_id(&state),
-                e
-            );
-
-            let response = create_empty_response(&state, StatusCode::INTERNAL_SERVER_ERROR);
-
-            return future::ok((state, response));
-        }
-    };
-
-    let identifier = session_data.identifier;
-    let slice = &bytes[..];
-
-    let result = session_data
-        .backend
-        .persist_session(identifier.clone(), slice);
+    let identifier = session_data.identifier.clone();
+
+    let result = bincode::serialize(&session_data.value)
+        .map_err(|e| SessionError::Serialize(format!("{:?}", e)))
+        .and_then(|bytes| {
+            session_data
+                .backend
+                .persist_session(identifier.clone(), &bytes[..])
+        });
 
     match result {
         Ok(_) => {
-            trace!(
-                "[{}] persisted session ({}) successfully",
-                state::request_id(&state),
+            request_trace!(
+                &state,
+                "persisted session ({}) successfully",
                 identifier.value
             );
 
             future::ok((state, response))
         }
-        Err(_) => {
-            let response = create_empty_response(&state, StatusCode::INTERNAL_SERVER_ERROR);
+        Err(error) => {
+            request_error!(
+                &state,
+                "failed to persist session ({}): {}",
+                identifier.value,
+                error
+            );
 
-            future::ok((state, response))
+            let e = SessionPersistError { identifier, error };
+            future::err((state, e.into_handler_error()))
         }
     }
 }
@@ -994,11 +1295,13 @@ where
         identifier: SessionIdentifier,
         result: Result<Option<Vec<u8>>, SessionError>,
     ) -> future::FutureResult<State, (State, HandlerError)> {
+        state.put(identifier.clone());
+
         match result {
             Ok(v) => {
-                trace!(
-                    "[{}] got response for session ({}) from backend, data located: {}",
-                    state::request_id(&state),
+                request_trace!(
+                    &state,
+                    "got response for session ({}) from backend, data located: {}",
                     identifier.value,
                     v.is_some()
                 );
@@ -1009,29 +1312,36 @@ where
                 future::ok(state)
             }
             Err(e) => {
-                error!(
-                    "[{}] failed to retrieve session ({}) from backend: {:?}",
-                    state::request_id(&state),
+                request_error!(
+                    &state,
+                    "failed to retrieve session ({}) from backend: {:?}",
                     identifier.value,
                     e
                 );
 
-                let e = io::Error::new(
-                    io::ErrorKind::Other,
-                    format!("backend failed to return session: {:?}", e),
-                );
-
-                future::err((state, e.into_handler_error()))
+                match self.backend_failure {
+                    BackendFailurePolicy::Fail => future::err((state, e.into_handler_error())),
+                    BackendFailurePolicy::NewSession => {
+                        request_warn!(&state, "continuing with a new session");
+                        state.put(SessionData::<T>::detached(self));
+                        future::ok(state)
+                    }
+                    BackendFailurePolicy::NoSession => {
+                        request_warn!(&state, "continuing without a session");
+                        future::ok(state)
+                    }
+                }
             }
         }
     }
 
     fn new_session(self, mut state: State) -> future::FutureResult<State, (State, HandlerError)> {
         let session_data = SessionData::<T>::new(self);
+        state.put(session_data.identifier.clone());
 
-        trace!(
-            "[{}] created new session ({})",
-            state::request_id(&state),
+        request_trace!(
+            &state,
+            "created new session ({})",
             session_data.identifier.value
         );
 
@@ -1044,6 +1354,8 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
+    use futures::Stream;
+    use helpers::clock::{put_clock, ManualClock};
     use hyper::header::{HeaderMap, COOKIE};
     use hyper::{Response, StatusCode};
     use rand;
@@ -1055,6 +1367,15 @@ mod tests {
         val: u64,
     }
 
+    // Splits a `Set-Cookie` value into its attributes, ignoring their order, which is chosen by
+    // the `cookie` crate.
+    fn attributes<T: ToString>(cookie: &T) -> Vec<String> {
+        let mut attributes: Vec<String> =
+            cookie.to_string().split("; ").map(str::to_owned).collect();
+        attributes.sort();
+        attributes
+    }
+
     #[test]
     fn new_session() {
         let backend = MemoryBackend::new(Duration::from_secs(1));
@@ -1079,14 +1400,29 @@ mod tests {
         assert!(m.cookie_config.domain.is_none());
 
         assert_eq!(
-            m.cookie_config.to_cookie_string(&identifier.value),
-            format!(
+            attributes(&m.cookie_config.to_cookie(identifier.value.clone())),
+            attributes(&format!(
                 "_gotham_session={}; Secure; HttpOnly; SameSite=Lax; Path=/",
                 &identifier.value
-            )
+            ))
         );
     }
 
+    #[test]
+    fn seeded_identifiers() {
+        use helpers::random::SeededRandom;
+
+        let identifier = || {
+            let backend = MemoryBackend::new(Duration::from_secs(1));
+            let nm = NewSessionMiddleware::new(backend).with_session_type::<TestSession>();
+            let mut m = nm.new_middleware().unwrap();
+            m.random_source = Some(Arc::new(SeededRandom::new(3)));
+            m.random_identifier()
+        };
+
+        assert_eq!(identifier(), identifier());
+    }
+
     #[test]
     fn enforce_secure_cookie_prefix_attributes() {
         let backend = MemoryBackend::new(Duration::from_secs(1));
@@ -1131,11 +1467,11 @@ mod tests {
         assert_eq!(identifier.value.len(), 86);
 
         assert_eq!(
-            m.cookie_config.to_cookie_string(&identifier.value),
-            format!(
+            attributes(&m.cookie_config.to_cookie(identifier.value.clone())),
+            attributes(&format!(
                 "_my_session={}; HttpOnly; SameSite=Strict; Domain=example.com; Path=/myapp",
                 &identifier.value
-            )
+            ))
         );
 
         let nm = NewSessionMiddleware::new(backend)
@@ -1149,11 +1485,36 @@ mod tests {
         assert_eq!(identifier.value.len(), 86);
 
         assert_eq!(
-            m.cookie_config.to_cookie_string(&identifier.value),
-            format!(
+            attributes(&m.cookie_config.to_cookie(identifier.value.clone())),
+            attributes(&format!(
                 "x_session={}; Secure; HttpOnly; Path=/xapp",
                 &identifier.value
-            )
+            ))
+        );
+    }
+
+    #[test]
+    fn new_session_config_settings() {
+        let config = SessionConfig {
+            cookie_name: Some("_my_session".to_owned()),
+            secure: Some(false),
+            same_site: Some(SameSitePolicy::Disabled),
+            ..SessionConfig::default()
+        };
+        let nm = NewSessionMiddleware::default()
+            .with_cookie_path("/myapp")
+            .with_config(&config)
+            .with_session_type::<TestSession>();
+
+        let m = nm.new_middleware().unwrap();
+        let identifier = m.random_identifier();
+
+        assert_eq!(
+            attributes(&m.cookie_config.to_cookie(identifier.value.clone())),
+            attributes(&format!(
+                "_my_session={}; HttpOnly; Path=/myapp",
+                &identifier.value
+            ))
         );
     }
 
@@ -1220,4 +1581,210 @@ mod tests {
 
         assert_eq!(updated.val, session.val + 1);
     }
+
+    #[test]
+    fn backend_failures_are_handler_errors() {
+        #[derive(Clone)]
+        struct FailingBackend;
+
+        impl NewBackend for FailingBackend {
+            type Instance = FailingBackend;
+
+            fn new_backend(&self) -> io::Result<Self::Instance> {
+                Ok(FailingBackend)
+            }
+        }
+
+        impl Backend for FailingBackend {
+            fn persist_session(&self, _: SessionIdentifier, _: &[u8]) -> Result<(), SessionError> {
+                Err(SessionError::Backend("unavailable".to_owned()))
+            }
+
+            fn read_session(&self, _: SessionIdentifier) -> Box<backend::SessionFuture> {
+                Box::new(future::err(SessionError::Backend("unavailable".to_owned())))
+            }
+
+            fn drop_session(&self, _: SessionIdentifier) -> Result<(), SessionError> {
+                Ok(())
+            }
+        }
+
+        fn handler(mut state: State) -> Box<HandlerFuture> {
+            state.borrow_mut::<SessionData<TestSession>>().val += 1;
+            Box::new(future::ok((state, Response::new(Body::empty()))))
+        }
+
+        let nm = NewSessionMiddleware::new(FailingBackend).with_session_type::<TestSession>();
+
+        // reading an existing session fails
+        let mut state = State::new();
+        let mut headers = HeaderMap::new();
+        let cookie = Cookie::build("_gotham_session", "abc").finish();
+        headers.insert(COOKIE, cookie.to_string().parse().unwrap());
+        state.put(headers);
+
+        let m = nm.new_middleware().unwrap();
+        let (_, e) = m.call(state, handler).wait().err().unwrap();
+        assert_eq!(e.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        match e.downcast_ref::<SessionError>() {
+            Some(SessionError::Backend(message)) => assert_eq!(message, "unavailable"),
+            other => panic!("unexpected cause: {:?}", other),
+        }
+
+        // persisting a new session fails
+        let mut state = State::new();
+        state.put(HeaderMap::new());
+
+        let m = nm.new_middleware().unwrap();
+        let (state, e) = m.call(state, handler).wait().err().unwrap();
+        assert_eq!(e.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        let cause = e.downcast_ref::<SessionPersistError>().unwrap();
+        assert_eq!(cause.identifier(), state.borrow::<SessionIdentifier>());
+        match cause.error() {
+            SessionError::Backend(message) => assert_eq!(message, "unavailable"),
+            other => panic!("unexpected cause: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn backend_failure_policies() {
+        #[derive(Clone)]
+        struct DownBackend(Arc<Mutex<usize>>);
+
+        impl NewBackend for DownBackend {
+            type Instance = DownBackend;
+
+            fn new_backend(&self) -> io::Result<Self::Instance> {
+                Ok(self.clone())
+            }
+        }
+
+        impl Backend for DownBackend {
+            fn persist_session(&self, _: SessionIdentifier, _: &[u8]) -> Result<(), SessionError> {
+                Err(SessionError::Backend("unavailable".to_owned()))
+            }
+
+            fn read_session(&self, _: SessionIdentifier) -> Box<backend::SessionFuture> {
+                *self.0.lock().unwrap() += 1;
+                Box::new(future::err(SessionError::Backend("unavailable".to_owned())))
+            }
+
+            fn drop_session(&self, _: SessionIdentifier) -> Result<(), SessionError> {
+                Ok(())
+            }
+        }
+
+        fn handler(mut state: State) -> Box<HandlerFuture> {
+            let body = match state.try_borrow_mut::<SessionData<TestSession>>() {
+                Some(session) => {
+                    session.val += 1;
+                    format!("session {}", session.val)
+                }
+                None => "no session".to_owned(),
+            };
+            Box::new(future::ok((state, Response::new(Body::from(body)))))
+        }
+
+        let clock = ManualClock::new();
+        let reads = Arc::new(Mutex::new(0));
+        let call = |nm: &NewSessionMiddleware<DownBackend, TestSession>| {
+            let mut state = State::new();
+            let mut headers = HeaderMap::new();
+            let cookie = Cookie::build("_gotham_session", "abc").finish();
+            headers.insert(COOKIE, cookie.to_string().parse().unwrap());
+            state.put(headers);
+            put_clock(&mut state, Arc::new(clock.clone()));
+
+            let m = nm.new_middleware().unwrap();
+            match m.call(state, handler).wait() {
+                Ok((mut state, response)) => {
+                    assert_eq!(cookie_jar(&mut state).delta().count(), 0);
+                    let body = response.into_body().concat2().wait().unwrap();
+                    String::from_utf8(body.to_vec()).unwrap()
+                }
+                Err((_, e)) => format!("error {}", e.status().as_u16()),
+            }
+        };
+
+        let nm = NewSessionMiddleware::new(DownBackend(reads.clone()))
+            .with_session_type::<TestSession>();
+        assert_eq!(call(&nm), "error 500");
+
+        let nm = nm.with_backend_failure_policy(BackendFailurePolicy::NewSession);
+        assert_eq!(call(&nm), "session 1");
+
+        let nm = nm
+            .with_backend_failure_policy(BackendFailurePolicy::NoSession)
+            .with_circuit_breaker(2, Duration::from_secs(10));
+        assert_eq!(call(&nm), "no session");
+        assert_eq!(call(&nm), "no session");
+        assert_eq!(*reads.lock().unwrap(), 4);
+
+        // the circuit is open, so the backend isn't read until the cool-down has passed
+        assert_eq!(call(&nm), "no session");
+        assert_eq!(*reads.lock().unwrap(), 4);
+
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(call(&nm), "no session");
+        assert_eq!(*reads.lock().unwrap(), 5);
+    }
+
+    #[test]
+    fn discard_session() {
+        let nm = NewSessionMiddleware::default().with_session_type::<TestSession>();
+        let m = nm.new_middleware().unwrap();
+
+        let identifier = m.random_identifier();
+        let bytes = bincode::serialize(&TestSession { val: 1 }).unwrap();
+        m.backend
+            .persist_session(identifier.clone(), &bytes)
+            .unwrap();
+
+        let discarded = Arc::new(Mutex::new(None));
+        let d = discarded.clone();
+
+        let handler = move |mut state: State| {
+            let session_data = state.take::<SessionData<TestSession>>();
+            *d.lock().unwrap() = Some(session_data.discard(&mut state).is_ok());
+            Box::new(future::ok((state, Response::new(Body::empty())))) as Box<HandlerFuture>
+        };
+
+        let mut state = State::new();
+        let mut headers = HeaderMap::new();
+        let cookie = Cookie::build("_gotham_session", identifier.value.clone()).finish();
+        headers.insert(COOKIE, cookie.to_string().parse().unwrap());
+        state.put(headers);
+
+        let (mut state, _) = m.call(state, handler).wait().ok().unwrap();
+        assert_eq!(*discarded.lock().unwrap(), Some(true));
+        assert!(cookie_jar(&mut state).get("_gotham_session").is_none());
+
+        let m = nm.new_middleware().unwrap();
+        assert!(m.backend.read_session(identifier).wait().unwrap().is_none());
+    }
+
+    #[test]
+    fn drop_session_is_unsupported_by_default() {
+        struct LegacyBackend;
+
+        impl Backend for LegacyBackend {
+            fn persist_session(&self, _: SessionIdentifier, _: &[u8]) -> Result<(), SessionError> {
+                Ok(())
+            }
+
+            fn read_session(&self, _: SessionIdentifier) -> Box<backend::SessionFuture> {
+                Box::new(future::ok(None))
+            }
+        }
+
+        let identifier = SessionIdentifier {
+            value: "abc".to_owned(),
+        };
+
+        match LegacyBackend.drop_session(identifier) {
+            Err(SessionError::Backend(_)) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
 }
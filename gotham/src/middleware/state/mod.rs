@@ -2,7 +2,7 @@
 //!
 //! This module provides generics to enable attaching (appropriate) values to
 //! the state of a request, through the use of `Middleware`. Middleware can
-//! be created via `StateMiddleware::with`, with the provided value being the
+//! be created via `StateMiddleware::new`, with the provided value being the
 //! value to attach to the request state.
 use handler::HandlerFuture;
 use middleware::{Middleware, NewMiddleware};
@@ -18,6 +18,56 @@ use std::panic::RefUnwindSafe;
 ///
 /// The generic types inside this struct can (and will) be cloned
 /// often, so wrap your expensive types in reference counts as needed.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate gotham;
+/// # #[macro_use]
+/// # extern crate gotham_derive;
+/// # extern crate hyper;
+/// # extern crate mime;
+/// #
+/// # use std::sync::Arc;
+/// # use hyper::{Body, Response, StatusCode};
+/// # use gotham::helpers::http::response::create_response;
+/// # use gotham::middleware::state::StateMiddleware;
+/// # use gotham::pipeline::single_middleware;
+/// # use gotham::pipeline::single::single_pipeline;
+/// # use gotham::router::Router;
+/// # use gotham::router::builder::*;
+/// # use gotham::state::{FromState, State};
+/// # use gotham::test::TestServer;
+/// #
+/// #[derive(Clone, StateData)]
+/// struct Config {
+///     greeting: Arc<String>,
+/// }
+///
+/// fn greet(state: State) -> (State, Response<Body>) {
+///     let greeting = Config::borrow_from(&state).greeting.to_string();
+///     let res = create_response(&state, StatusCode::OK, mime::TEXT_PLAIN, greeting);
+///     (state, res)
+/// }
+///
+/// fn router() -> Router {
+///     let config = Config {
+///         greeting: Arc::new("Hello, world!".to_owned()),
+///     };
+///
+///     let (chain, pipelines) = single_pipeline(single_middleware(StateMiddleware::new(config)));
+///
+///     build_router(chain, pipelines, |route| {
+///         route.get("/").to(greet);
+///     })
+/// }
+/// #
+/// # fn main() {
+/// #   let test_server = TestServer::new(router()).unwrap();
+/// #   let response = test_server.client().get("http://example.com/").perform().unwrap();
+/// #   assert_eq!(response.read_utf8_body().unwrap(), "Hello, world!");
+/// # }
+/// ```
 #[derive(Clone)]
 pub struct StateMiddleware<T>
 where
@@ -66,3 +116,44 @@ where
         Ok(self.clone())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::{future, Future};
+    use hyper::{Body, Response};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use state::FromState;
+
+    #[derive(Clone)]
+    struct Counter {
+        hits: Arc<AtomicUsize>,
+    }
+
+    impl StateData for Counter {}
+
+    #[test]
+    fn shares_value_between_requests() {
+        let hits = Arc::new(AtomicUsize::new(0));
+        let middleware = StateMiddleware::new(Counter { hits: hits.clone() });
+
+        for _ in 0..2 {
+            let instance = middleware.new_middleware().unwrap();
+            let result = instance
+                .call(State::new(), |state| {
+                    Counter::borrow_from(&state)
+                        .hits
+                        .fetch_add(1, Ordering::SeqCst);
+                    Box::new(future::ok((state, Response::new(Body::empty()))))
+                })
+                .wait();
+
+            assert!(result.is_ok());
+        }
+
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,378 @@
+//! Request timeout middleware, used to bound the time taken to respond to a request.
+use futures::future::Either;
+use futures::Future;
+use hyper::header::{HeaderMap, HeaderName};
+use hyper::StatusCode;
+use std::io;
+use std::time::{Duration, Instant};
+use tokio::timer::{Delay, Timeout};
+
+use handler::{HandlerFuture, IntoHandlerError};
+use helpers::http::response::create_empty_response;
+use middleware::{Middleware, NewMiddleware};
+use state::{FromState, State, StateData};
+
+/// The time by which a response to the current request is due, as placed into `State` by
+/// `RequestTimeout`.
+///
+/// Middleware and handlers can consult the `Deadline` to bound their own work by the time which
+/// remains, such as a backend read or a call to an upstream server, rather than continuing with
+/// work whose result will be discarded.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate futures;
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// # extern crate tokio;
+/// #
+/// # use std::time::{Duration, Instant};
+/// # use futures::{future, Future};
+/// # use hyper::{Body, Response, StatusCode};
+/// # use tokio::timer::Delay;
+/// # use gotham::handler::{HandlerFuture, IntoHandlerError};
+/// # use gotham::middleware::timeout::{Deadline, RequestTimeout};
+/// # use gotham::pipeline::single_middleware;
+/// # use gotham::pipeline::single::single_pipeline;
+/// # use gotham::router::Router;
+/// # use gotham::router::builder::*;
+/// # use gotham::state::{FromState, State};
+/// # use gotham::test::TestServer;
+/// #
+/// fn slow_backend() -> impl Future<Item = String, Error = tokio::timer::Error> {
+///     Delay::new(Instant::now() + Duration::from_secs(5)).map(|_| "data".to_owned())
+/// }
+///
+/// fn handler(state: State) -> Box<HandlerFuture> {
+///     let read = Deadline::borrow_from(&state).bound(slow_backend());
+///
+///     Box::new(read.then(move |result| match result {
+///         Ok(data) => future::ok((state, Response::new(Body::from(data)))),
+///         Err(e) => {
+///             let err = e.into_handler_error().with_status(StatusCode::GATEWAY_TIMEOUT);
+///             future::err((state, err))
+///         }
+///     }))
+/// }
+///
+/// fn router() -> Router {
+///     let timeout = RequestTimeout::new(Duration::from_millis(50));
+///     let (chain, pipelines) = single_pipeline(single_middleware(timeout));
+///
+///     build_router(chain, pipelines, |route| {
+///         route.get("/").to(handler);
+///     })
+/// }
+/// #
+/// # fn main() {
+/// #   let test_server = TestServer::new(router()).unwrap();
+/// #   let response = test_server.client()
+/// #       .get("http://example.com/")
+/// #       .perform()
+/// #       .unwrap();
+/// #   assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+/// # }
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Deadline {
+    instant: Instant,
+}
+
+impl StateData for Deadline {}
+
+impl Deadline {
+    /// Creates a `Deadline` at `instant`.
+    pub fn new(instant: Instant) -> Deadline {
+        Deadline { instant }
+    }
+
+    /// Returns the `Instant` at which the deadline passes.
+    pub fn instant(&self) -> Instant {
+        self.instant
+    }
+
+    /// Returns the time remaining until the deadline, which is zero once it has passed.
+    pub fn remaining(&self) -> Duration {
+        let now = Instant::now();
+        if self.instant > now {
+            self.instant - now
+        } else {
+            Duration::from_secs(0)
+        }
+    }
+
+    /// Returns `true` if the deadline has passed.
+    pub fn is_expired(&self) -> bool {
+        Instant::now() >= self.instant
+    }
+
+    /// Bounds `f` by the deadline, so that it fails with an elapsed `timeout::Error` if it has not
+    /// completed in the remaining time.
+    pub fn bound<F>(&self, f: F) -> Timeout<F>
+    where
+        F: Future,
+    {
+        Timeout::new_at(f, self.instant)
+    }
+
+    /// Returns the earlier of this deadline and `timeout` from now, which can be used to bound a
+    /// sub-operation which has its own timeout.
+    pub fn min(&self, timeout: Duration) -> Duration {
+        ::std::cmp::min(self.remaining(), timeout)
+    }
+}
+
+/// Middleware binding to enforce a deadline on the handling of a request.
+///
+/// If the rest of the pipeline and the handler have not produced a response within the given
+/// `Duration`, the request is abandoned by dropping the handler future, and a
+/// `503 Service Unavailable` response is sent instead. The status can be changed via
+/// `with_status`.
+///
+/// Because the `State` of an abandoned request is dropped along with the handler future, the
+/// response is produced using a new `State` which holds only copies of the request data, such as
+/// the method, URI and headers.
+///
+/// As with any `Middleware`, the deadline applies to the routes which use the `Pipeline` it is
+/// added to, so different scopes can be given different deadlines.
+///
+/// The deadline is placed into `State` as a `Deadline`, so that the rest of the pipeline and the
+/// handler can bound their own work by it. A client may ask for a shorter deadline by sending the
+/// number of milliseconds it is prepared to wait in the header set via `with_header`. Where
+/// `RequestTimeout` is used more than once for a request, the earliest deadline applies.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate futures;
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// #
+/// # use std::time::Duration;
+/// # use futures::future;
+/// # use hyper::StatusCode;
+/// # use gotham::handler::HandlerFuture;
+/// # use gotham::middleware::timeout::RequestTimeout;
+/// # use gotham::pipeline::new_pipeline;
+/// # use gotham::pipeline::single::single_pipeline;
+/// # use gotham::router::Router;
+/// # use gotham::router::builder::*;
+/// # use gotham::state::State;
+/// # use gotham::test::TestServer;
+/// #
+/// fn never_responds(_state: State) -> Box<HandlerFuture> {
+///     Box::new(future::empty())
+/// }
+///
+/// fn router() -> Router {
+///     let (chain, pipelines) = single_pipeline(
+///         new_pipeline()
+///             .add(RequestTimeout::new(Duration::from_millis(50)))
+///             .build(),
+///     );
+///
+///     build_router(chain, pipelines, |route| {
+///         route.get("/").to(never_responds);
+///     })
+/// }
+/// #
+/// # fn main() {
+/// #   let test_server = TestServer::new(router()).unwrap();
+/// #   let response = test_server.client()
+/// #       .get("http://example.com/")
+/// #       .perform()
+/// #       .unwrap();
+/// #   assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct RequestTimeout {
+    duration: Duration,
+    status: StatusCode,
+    header: Option<HeaderName>,
+}
+
+impl RequestTimeout {
+    /// Creates a `RequestTimeout` which abandons requests after `duration`.
+    pub fn new(duration: Duration) -> RequestTimeout {
+        RequestTimeout {
+            duration,
+            status: StatusCode::SERVICE_UNAVAILABLE,
+            header: None,
+        }
+    }
+
+    /// Sets the status of the response sent for an abandoned request, such as
+    /// `504 Gateway Timeout`.
+    pub fn with_status(self, status: StatusCode) -> RequestTimeout {
+        RequestTimeout { status, ..self }
+    }
+
+    /// Sets a request header, such as `x-request-timeout`, in which a client can give the number
+    /// of milliseconds it will wait for a response. The header can shorten the deadline, but never
+    /// extend it.
+    pub fn with_header(self, header: HeaderName) -> RequestTimeout {
+        RequestTimeout {
+            header: Some(header),
+            ..self
+        }
+    }
+
+    // Determines the deadline for the request, from this middleware, the request header, and any
+    // `Deadline` already in `State`.
+    fn deadline(&self, state: &State) -> Deadline {
+        let mut duration = self.duration;
+
+        if let Some(ref header) = self.header {
+            let requested = HeaderMap::try_borrow_from(state)
+                .and_then(|headers| headers.get(header))
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.trim().parse::<u64>().ok())
+                .map(Duration::from_millis);
+
+            if let Some(requested) = requested {
+                duration = ::std::cmp::min(duration, requested);
+            }
+        }
+
+        let deadline = Deadline::new(Instant::now() + duration);
+        match Deadline::try_borrow_from(state) {
+            Some(existing) if existing.instant() < deadline.instant() => *existing,
+            _ => deadline,
+        }
+    }
+}
+
+/// `Middleware` trait implementation.
+impl Middleware for RequestTimeout {
+    /// Races the rest of the chain against the deadline.
+    fn call<Chain>(self, mut state: State, chain: Chain) -> Box<HandlerFuture>
+    where
+        Chain: FnOnce(State) -> Box<HandlerFuture>,
+    {
+        let deadline = self.deadline(&state);
+        state.put(deadline);
+
+        let fallback = state.copy_request_data();
+        let duration = deadline.remaining();
+        let status = self.status;
+        let deadline = Delay::new(deadline.instant());
+
+        let f = chain(state)
+            .select2(deadline)
+            .then(move |result| match result {
+                Ok(Either::A((item, _))) => Ok(item),
+                Err(Either::A((err, _))) => Err(err),
+                Ok(Either::B(((), _abandoned))) => {
+                    request_warn!(&fallback, "request abandoned after {:?}", duration);
+                    let res = create_empty_response(&fallback, status);
+                    Ok((fallback, res))
+                }
+                Err(Either::B((e, _abandoned))) => Err((fallback, e.into_handler_error())),
+            });
+
+        Box::new(f)
+    }
+}
+
+/// `NewMiddleware` trait implementation.
+impl NewMiddleware for RequestTimeout {
+    type Instance = Self;
+
+    /// Clones the current middleware to a new instance.
+    fn new_middleware(&self) -> io::Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::future;
+    use hyper::{Body, Response};
+
+    use pipeline::new_pipeline;
+    use pipeline::set::{finalize_pipeline_set, new_pipeline_set};
+    use router::builder::*;
+    use router::Router;
+    use test::TestServer;
+
+    fn slow(state: State) -> Box<HandlerFuture> {
+        let f = Delay::new(Instant::now() + Duration::from_millis(200)).then(move |_| {
+            let res = Response::new(Body::from("done"));
+            future::ok((state, res))
+        });
+
+        Box::new(f)
+    }
+
+    fn router() -> Router {
+        let pipelines = new_pipeline_set();
+        let (pipelines, strict) = pipelines.add(
+            new_pipeline()
+                .add(
+                    RequestTimeout::new(Duration::from_millis(20))
+                        .with_status(StatusCode::GATEWAY_TIMEOUT),
+                )
+                .build(),
+        );
+        let (pipelines, lenient) = pipelines.add(
+            new_pipeline()
+                .add(RequestTimeout::new(Duration::from_secs(10)))
+                .build(),
+        );
+        let pipelines = finalize_pipeline_set(pipelines);
+
+        build_router((), pipelines, |route| {
+            route.with_pipeline_chain((strict, ()), |route| {
+                route.get("/strict").to(slow);
+            });
+
+            route.with_pipeline_chain((lenient, ()), |route| {
+                route.get("/lenient").to(slow);
+            });
+        })
+    }
+
+    #[test]
+    fn header_shortens_deadline() {
+        let timeout = RequestTimeout::new(Duration::from_secs(10))
+            .with_header(HeaderName::from_static("x-request-timeout"));
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-request-timeout", "100".parse().unwrap());
+        let mut state = State::new();
+        state.put(headers);
+        assert!(timeout.deadline(&state).remaining() <= Duration::from_millis(100));
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-request-timeout", "60000".parse().unwrap());
+        state.put(headers);
+        assert!(timeout.deadline(&state).remaining() > Duration::from_secs(9));
+
+        state.put(Deadline::new(Instant::now() + Duration::from_secs(1)));
+        assert!(timeout.deadline(&state).remaining() <= Duration::from_secs(1));
+    }
+
+    #[test]
+    fn applies_deadline_per_pipeline() {
+        let test_server = TestServer::new(router()).unwrap();
+
+        let response = test_server
+            .client()
+            .get("http://localhost/strict")
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+
+        let response = test_server
+            .client()
+            .get("http://localhost/lenient")
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.read_utf8_body().unwrap(), "done");
+    }
+}
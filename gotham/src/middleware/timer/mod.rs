@@ -22,14 +22,18 @@ impl Middleware for RequestTimer {
         Chain: FnOnce(State) -> Box<HandlerFuture>,
     {
         // start the timer
-        let timer = Timer::new();
+        let timer = Timer::for_request(&state);
 
         // execute the chain and attach the time on complete
         let f = chain(state).and_then(move |(state, mut response)| {
             // attach the formatted header
             response.headers_mut().insert(
                 X_RUNTIME_DURATION,
-                timer.elapsed().to_string().parse().unwrap(),
+                timer
+                    .elapsed_for_request(&state)
+                    .to_string()
+                    .parse()
+                    .unwrap(),
             );
 
             future::ok((state, response))
@@ -0,0 +1,700 @@
+//! Distributed tracing middleware, which records a span for each request and continues traces
+//! started by upstream services.
+use hyper::header::HeaderMap;
+use hyper::{Method, Uri};
+use rand;
+use std::fmt;
+use std::io;
+use std::panic::RefUnwindSafe;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use handler::HandlerFuture;
+use middleware::hook::{on_complete, Outcome};
+use middleware::{Middleware, NewMiddleware};
+use router::description::RouteTemplate;
+use state::{FromState, State, StateData};
+
+const TRACEPARENT: &str = "traceparent";
+const B3: &str = "b3";
+const X_B3_TRACE_ID: &str = "x-b3-traceid";
+const X_B3_SPAN_ID: &str = "x-b3-spanid";
+const X_B3_SAMPLED: &str = "x-b3-sampled";
+const X_B3_FLAGS: &str = "x-b3-flags";
+
+/// Identifies a span within a trace, in the form which is propagated between services.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpanContext {
+    trace_id: u128,
+    span_id: u64,
+    sampled: bool,
+}
+
+impl SpanContext {
+    /// Extracts the context of the calling span from the W3C `traceparent` header, or the B3
+    /// `b3` or `X-B3-*` headers, in that order. Returns `None` when none of the headers are
+    /// present and valid.
+    pub fn from_headers(headers: &HeaderMap) -> Option<SpanContext> {
+        let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+
+        if let Some(context) = header(TRACEPARENT).and_then(parse_traceparent) {
+            return Some(context);
+        }
+
+        if let Some(context) = header(B3).and_then(parse_b3) {
+            return Some(context);
+        }
+
+        let trace_id = header(X_B3_TRACE_ID).and_then(parse_b3_trace_id)?;
+        let span_id = header(X_B3_SPAN_ID).and_then(parse_hex_u64)?;
+        let sampled = match (header(X_B3_SAMPLED), header(X_B3_FLAGS)) {
+            (_, Some("1")) => true,
+            (Some(sampled), _) => sampled == "1" || sampled.eq_ignore_ascii_case("true"),
+            // flags other than debug leave the sampling decision to this service
+            (None, Some(_)) | (None, None) => true,
+        };
+
+        Some(SpanContext {
+            trace_id,
+            span_id,
+            sampled,
+        })
+    }
+
+    /// The ID of the trace which the span is part of.
+    pub fn trace_id(&self) -> u128 {
+        self.trace_id
+    }
+
+    /// The ID of the span.
+    pub fn span_id(&self) -> u64 {
+        self.span_id
+    }
+
+    /// Whether the span is being recorded, as decided by the service which started the trace.
+    pub fn is_sampled(&self) -> bool {
+        self.sampled
+    }
+
+    /// Formats the context as the value of a W3C `traceparent` header, so that a request made to
+    /// another service continues the trace.
+    pub fn to_traceparent(&self) -> String {
+        format!(
+            "00-{:032x}-{:016x}-{:02x}",
+            self.trace_id,
+            self.span_id,
+            if self.sampled { 1 } else { 0 }
+        )
+    }
+
+    // The context of a span which starts a new trace.
+    fn root() -> SpanContext {
+        SpanContext {
+            trace_id: random_id(|| {
+                (u128::from(rand::random::<u64>()) << 64) | u128::from(rand::random::<u64>())
+            }),
+            span_id: random_id(rand::random::<u64>),
+            sampled: true,
+        }
+    }
+
+    // The context of a span which is a child of this one.
+    fn child(&self) -> SpanContext {
+        SpanContext {
+            span_id: random_id(rand::random::<u64>),
+            ..*self
+        }
+    }
+}
+
+// Generates an ID, which must not be zero.
+fn random_id<T, F>(f: F) -> T
+where
+    T: Default + PartialEq,
+    F: Fn() -> T,
+{
+    loop {
+        let id = f();
+        if id != T::default() {
+            return id;
+        }
+    }
+}
+
+fn is_hex(value: &str, len: usize) -> bool {
+    value.len() == len && value.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+fn parse_hex_u64(value: &str) -> Option<u64> {
+    if !is_hex(value, 16) {
+        return None;
+    }
+
+    u64::from_str_radix(value, 16).ok().filter(|id| *id != 0)
+}
+
+fn parse_hex_u128(value: &str) -> Option<u128> {
+    if !is_hex(value, 32) {
+        return None;
+    }
+
+    u128::from_str_radix(value, 16).ok().filter(|id| *id != 0)
+}
+
+// B3 trace IDs may be 64 or 128 bits.
+fn parse_b3_trace_id(value: &str) -> Option<u128> {
+    if value.len() == 16 {
+        parse_hex_u64(value).map(u128::from)
+    } else {
+        parse_hex_u128(value)
+    }
+}
+
+// Parses a `traceparent` header, such as
+// `00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01`.
+fn parse_traceparent(value: &str) -> Option<SpanContext> {
+    let parts: Vec<&str> = value.trim().split('-').collect();
+    if parts.len() < 4 || !is_hex(parts[0], 2) || parts[0] == "ff" {
+        return None;
+    }
+
+    // Later versions may append fields, which are ignored.
+    if parts[0] == "00" && parts.len() != 4 {
+        return None;
+    }
+
+    let trace_id = parse_hex_u128(parts[1])?;
+    let span_id = parse_hex_u64(parts[2])?;
+    if !is_hex(parts[3], 2) {
+        return None;
+    }
+    let flags = u8::from_str_radix(parts[3], 16).ok()?;
+
+    Some(SpanContext {
+        trace_id,
+        span_id,
+        sampled: flags & 1 == 1,
+    })
+}
+
+// Parses a single `b3` header, such as `80f198ee56343ba864fe8b2a57d3eff7-e457b5a2e4d86bd1-1`.
+fn parse_b3(value: &str) -> Option<SpanContext> {
+    let parts: Vec<&str> = value.trim().split('-').collect();
+    if parts.len() < 2 {
+        return None;
+    }
+
+    let trace_id = parse_b3_trace_id(parts[0])?;
+    let span_id = parse_hex_u64(parts[1])?;
+    let sampled = match parts.get(2) {
+        Some(&"0") => false,
+        Some(&"1") | Some(&"d") | None => true,
+        Some(_) => return None,
+    };
+
+    Some(SpanContext {
+        trace_id,
+        span_id,
+        sampled,
+    })
+}
+
+/// Receives spans once they have ended, such as to send them to a tracing system.
+///
+/// Only sampled spans are exported. `SpanExporter` is implemented for closures, so that an
+/// exporter can be written as a function.
+pub trait SpanExporter: Send + Sync + RefUnwindSafe {
+    /// Exports a span which has ended.
+    fn export(&self, span: FinishedSpan);
+}
+
+impl<F> SpanExporter for F
+where
+    F: Fn(FinishedSpan) + Send + Sync + RefUnwindSafe,
+{
+    fn export(&self, span: FinishedSpan) {
+        self(span)
+    }
+}
+
+/// An operation within a trace, which is being timed.
+///
+/// `TracingMiddleware` places the span for the request into `State`, so that a handler can start
+/// child spans for the work it does, such as querying a database. A child span is exported when
+/// `end` is called, and is discarded if it is dropped instead.
+pub struct Span {
+    name: String,
+    context: SpanContext,
+    parent_id: Option<u64>,
+    start: SystemTime,
+    started: Instant,
+    attributes: Vec<(String, String)>,
+    exporter: Arc<SpanExporter>,
+}
+
+impl Span {
+    fn new<N>(
+        name: N,
+        context: SpanContext,
+        parent_id: Option<u64>,
+        exporter: Arc<SpanExporter>,
+    ) -> Span
+    where
+        N: Into<String>,
+    {
+        Span {
+            name: name.into(),
+            context,
+            parent_id,
+            start: SystemTime::now(),
+            started: Instant::now(),
+            attributes: Vec::new(),
+            exporter,
+        }
+    }
+
+    /// The name of the operation.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The context of the span, which identifies it within the trace.
+    pub fn context(&self) -> SpanContext {
+        self.context
+    }
+
+    /// Starts a span for an operation which is part of this one.
+    pub fn child<N>(&self, name: N) -> Span
+    where
+        N: Into<String>,
+    {
+        Span::new(
+            name,
+            self.context.child(),
+            Some(self.context.span_id),
+            self.exporter.clone(),
+        )
+    }
+
+    /// Sets an attribute describing the operation, replacing any existing value for `key`.
+    pub fn set_attribute<K, V>(&mut self, key: K, value: V)
+    where
+        K: Into<String>,
+        V: fmt::Display,
+    {
+        let key = key.into();
+        let value = value.to_string();
+
+        match self
+            .attributes
+            .iter_mut()
+            .find(|&&mut (ref k, _)| *k == key)
+        {
+            Some(attribute) => attribute.1 = value,
+            None => self.attributes.push((key, value)),
+        }
+    }
+
+    /// Ends the span, and exports it if it is sampled.
+    pub fn end(self) {
+        if !self.context.sampled {
+            return;
+        }
+
+        let finished = FinishedSpan {
+            duration: self.started.elapsed(),
+            name: self.name,
+            context: self.context,
+            parent_id: self.parent_id,
+            start: self.start,
+            attributes: self.attributes,
+        };
+
+        self.exporter.export(finished);
+    }
+}
+
+impl StateData for Span {}
+
+/// A span which has ended, as received by a `SpanExporter`.
+#[derive(Clone, Debug)]
+pub struct FinishedSpan {
+    name: String,
+    context: SpanContext,
+    parent_id: Option<u64>,
+    start: SystemTime,
+    duration: Duration,
+    attributes: Vec<(String, String)>,
+}
+
+impl FinishedSpan {
+    /// The name of the operation.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The context of the span, which identifies it within the trace.
+    pub fn context(&self) -> SpanContext {
+        self.context
+    }
+
+    /// The ID of the parent span, which may belong to another service. `None` when the span
+    /// started the trace.
+    pub fn parent_id(&self) -> Option<u64> {
+        self.parent_id
+    }
+
+    /// The time at which the span started.
+    pub fn start(&self) -> SystemTime {
+        self.start
+    }
+
+    /// The time taken by the operation.
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// The attributes describing the operation, in the order they were first set.
+    pub fn attributes(&self) -> &[(String, String)] {
+        &self.attributes
+    }
+
+    /// Returns the value of the attribute `key`, if it was set.
+    pub fn attribute(&self, key: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|&&(ref k, _)| k == key)
+            .map(|&(_, ref v)| v.as_str())
+    }
+}
+
+/// Middleware binding which records a `Span` for each request, and passes it to a `SpanExporter`
+/// once the response has been produced.
+///
+/// When the request carries the context of a trace in a W3C `traceparent` header or B3 headers,
+/// the span continues that trace, and is only exported when the caller sampled it. Otherwise a
+/// new trace is started. The span is named after the method and the path template of the matched
+/// route, and has the following attributes:
+///
+/// * `http.method`, the method of the request;
+/// * `http.target`, the path and query of the request;
+/// * `http.route`, the path template of the matched route, such as `/users/:id`;
+/// * `gotham.route_name`, the name of the matched route, when it was named;
+/// * `http.status_code`, the status of the response; and
+/// * `error`, set to `true` when the rest of the chain failed with an error.
+///
+/// The span is placed into `State` while the request is processed, so that handlers can start
+/// child spans with `Span::child`, or pass `SpanContext::to_traceparent` on to other services.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// #
+/// # use std::sync::{Arc, Mutex};
+/// # use hyper::{Body, Response, StatusCode};
+/// # use gotham::middleware::tracing::{FinishedSpan, Span, TracingMiddleware};
+/// # use gotham::pipeline::new_pipeline;
+/// # use gotham::pipeline::single::single_pipeline;
+/// # use gotham::router::Router;
+/// # use gotham::router::builder::*;
+/// # use gotham::state::{FromState, State};
+/// # use gotham::test::TestServer;
+/// #
+/// fn handler(state: State) -> (State, Response<Body>) {
+///     let mut span = Span::borrow_from(&state).child("load user");
+///     span.set_attribute("user.id", 1);
+///     // ...
+///     span.end();
+///
+///     (state, Response::new(Body::from("Alice")))
+/// }
+///
+/// fn router(exported: Arc<Mutex<Vec<FinishedSpan>>>) -> Router {
+///     let middleware = TracingMiddleware::new(move |span: FinishedSpan| {
+///         // Usually sent to a tracing system.
+///         exported.lock().unwrap().push(span);
+///     });
+///
+///     let (chain, pipelines) = single_pipeline(new_pipeline().add(middleware).build());
+///
+///     build_router(chain, pipelines, |route| {
+///         route.get("/users/:id").to(handler);
+///     })
+/// }
+/// #
+/// # fn main() {
+/// #   let exported = Arc::new(Mutex::new(Vec::new()));
+/// #   let test_server = TestServer::new(router(exported.clone())).unwrap();
+/// #   let response = test_server
+/// #       .client()
+/// #       .get("http://localhost/users/1")
+/// #       .with_header(
+/// #           "traceparent",
+/// #           "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01".parse().unwrap(),
+/// #       )
+/// #       .perform()
+/// #       .unwrap();
+/// #   assert_eq!(response.status(), StatusCode::OK);
+/// #
+/// #   let exported = exported.lock().unwrap();
+/// #   assert_eq!(exported.len(), 2);
+/// #   assert_eq!(exported[0].name(), "load user");
+/// #   assert_eq!(exported[1].name(), "GET /users/:id");
+/// #   assert_eq!(exported[0].parent_id(), Some(exported[1].context().span_id()));
+/// #   assert_eq!(exported[1].parent_id(), Some(0x00f0_67aa_0ba9_02b7));
+/// #   assert_eq!(exported[1].attribute("http.status_code"), Some("200"));
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct TracingMiddleware {
+    exporter: Arc<SpanExporter>,
+}
+
+impl TracingMiddleware {
+    /// Creates a `TracingMiddleware` which passes spans to `exporter`.
+    pub fn new<E>(exporter: E) -> TracingMiddleware
+    where
+        E: SpanExporter + 'static,
+    {
+        TracingMiddleware {
+            exporter: Arc::new(exporter),
+        }
+    }
+
+    // Starts the span for the request, continuing the caller's trace when there is one.
+    fn start_span(&self, state: &State) -> Span {
+        let parent = HeaderMap::try_borrow_from(state).and_then(SpanContext::from_headers);
+        let context = match parent {
+            Some(parent) => parent.child(),
+            None => SpanContext::root(),
+        };
+
+        let method = Method::borrow_from(state);
+        let uri = Uri::borrow_from(state);
+        let template = RouteTemplate::try_borrow_from(state);
+        let route = template.map(RouteTemplate::as_str);
+
+        let name = format!("{} {}", method, route.unwrap_or_else(|| uri.path()));
+        let mut span = Span::new(
+            name,
+            context,
+            parent.map(|p| p.span_id),
+            self.exporter.clone(),
+        );
+
+        span.set_attribute("http.method", method);
+        span.set_attribute(
+            "http.target",
+            uri.path_and_query().map(|pq| pq.as_str()).unwrap_or("/"),
+        );
+        if let Some(route) = route {
+            span.set_attribute("http.route", route);
+        }
+        if let Some(route_name) = template.and_then(RouteTemplate::name) {
+            span.set_attribute("gotham.route_name", route_name);
+        }
+
+        span
+    }
+}
+
+/// `Middleware` trait implementation.
+impl Middleware for TracingMiddleware {
+    /// Records a span covering the rest of the chain.
+    fn call<Chain>(self, mut state: State, chain: Chain) -> Box<HandlerFuture>
+    where
+        Chain: FnOnce(State) -> Box<HandlerFuture>,
+    {
+        let span = self.start_span(&state);
+        state.put(span);
+
+        on_complete(chain(state), |state, outcome| {
+            if let Some(mut span) = state.try_take::<Span>() {
+                span.set_attribute("http.status_code", outcome.status().as_u16());
+                if let Outcome::Error(_) = outcome {
+                    span.set_attribute("error", true);
+                }
+                span.end();
+            }
+        })
+    }
+}
+
+/// `NewMiddleware` trait implementation.
+impl NewMiddleware for TracingMiddleware {
+    type Instance = Self;
+
+    /// Clones the current middleware to a new instance.
+    fn new_middleware(&self) -> io::Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::future;
+    use hyper::header::HeaderValue;
+    use hyper::{Body, Response, StatusCode};
+    use std::sync::Mutex;
+
+    use handler::IntoHandlerError;
+    use pipeline::new_pipeline;
+    use pipeline::single::single_pipeline;
+    use router::builder::*;
+    use test::TestServer;
+
+    fn context(headers: &[(&'static str, &'static str)]) -> Option<SpanContext> {
+        let mut map = HeaderMap::new();
+        for &(name, value) in headers {
+            map.insert(name, HeaderValue::from_static(value));
+        }
+        SpanContext::from_headers(&map)
+    }
+
+    #[test]
+    fn parses_traceparent() {
+        let c = context(&[(
+            "traceparent",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+        )])
+        .unwrap();
+
+        assert_eq!(c.trace_id(), 0x4bf9_2f35_77b3_4da6_a3ce_929d_0e0e_4736);
+        assert_eq!(c.span_id(), 0x00f0_67aa_0ba9_02b7);
+        assert!(c.is_sampled());
+        assert_eq!(
+            c.to_traceparent(),
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"
+        );
+
+        let unsampled = context(&[(
+            "traceparent",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00",
+        )])
+        .unwrap();
+        assert!(!unsampled.is_sampled());
+    }
+
+    #[test]
+    fn rejects_invalid_traceparent() {
+        for value in &[
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e473-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra",
+            "00-+bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+        ] {
+            assert_eq!(context(&[("traceparent", value)]), None, "{}", value);
+        }
+    }
+
+    #[test]
+    fn parses_b3() {
+        let c = context(&[("b3", "80f198ee56343ba864fe8b2a57d3eff7-e457b5a2e4d86bd1-0")]).unwrap();
+        assert_eq!(c.trace_id(), 0x80f1_98ee_5634_3ba8_64fe_8b2a_57d3_eff7);
+        assert_eq!(c.span_id(), 0xe457_b5a2_e4d8_6bd1);
+        assert!(!c.is_sampled());
+
+        let c = context(&[
+            ("x-b3-traceid", "a3ce929d0e0e4736"),
+            ("x-b3-spanid", "00f067aa0ba902b7"),
+            ("x-b3-sampled", "1"),
+        ])
+        .unwrap();
+        assert_eq!(c.trace_id(), 0xa3ce_929d_0e0e_4736);
+        assert!(c.is_sampled());
+
+        let headers = |flags| {
+            vec![
+                ("x-b3-traceid", "a3ce929d0e0e4736"),
+                ("x-b3-spanid", "00f067aa0ba902b7"),
+                ("x-b3-sampled", "0"),
+                ("x-b3-flags", flags),
+            ]
+        };
+        assert!(context(&headers("1")).unwrap().is_sampled());
+        assert!(!context(&headers("0")).unwrap().is_sampled());
+
+        // `X-B3-Flags: 0` without `X-B3-Sampled` leaves the decision to this service
+        let c = context(&[
+            ("x-b3-traceid", "a3ce929d0e0e4736"),
+            ("x-b3-spanid", "00f067aa0ba902b7"),
+            ("x-b3-flags", "0"),
+        ])
+        .unwrap();
+        assert!(c.is_sampled());
+
+        assert_eq!(context(&[("b3", "1")]), None);
+        assert_eq!(context(&[]), None);
+    }
+
+    #[test]
+    fn records_failed_requests() {
+        let exported = Arc::new(Mutex::new(Vec::new()));
+        let spans = exported.clone();
+        let middleware = TracingMiddleware::new(move |span: FinishedSpan| {
+            spans.lock().unwrap().push(span);
+        });
+
+        let (chain, pipelines) = single_pipeline(new_pipeline().add(middleware).build());
+        let router = build_router(chain, pipelines, |route| {
+            route
+                .get("/ok")
+                .named("ok")
+                .to(|state| (state, Response::new(Body::empty())));
+            route.get("/fail").to(|state| -> Box<HandlerFuture> {
+                let err = io::Error::new(io::ErrorKind::Other, "failed").into_handler_error();
+                Box::new(future::err((state, err)))
+            });
+        });
+
+        let test_server = TestServer::new(router).unwrap();
+        let client = test_server.client();
+        client.get("http://localhost/ok?q=1").perform().unwrap();
+        let response = client.get("http://localhost/fail").perform().unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        let exported = exported.lock().unwrap();
+        assert_eq!(exported.len(), 2);
+
+        let ok = &exported[0];
+        assert_eq!(ok.name(), "GET /ok");
+        assert_eq!(ok.parent_id(), None);
+        assert_eq!(ok.attribute("http.method"), Some("GET"));
+        assert_eq!(ok.attribute("http.target"), Some("/ok?q=1"));
+        assert_eq!(ok.attribute("http.route"), Some("/ok"));
+        assert_eq!(ok.attribute("gotham.route_name"), Some("ok"));
+        assert_eq!(ok.attribute("http.status_code"), Some("200"));
+        assert_eq!(ok.attribute("error"), None);
+
+        let fail = &exported[1];
+        assert_eq!(fail.attribute("http.status_code"), Some("500"));
+        assert_eq!(fail.attribute("error"), Some("true"));
+        assert_ne!(fail.context().trace_id(), ok.context().trace_id());
+    }
+
+    #[test]
+    fn unsampled_spans_are_not_exported() {
+        let exported = Arc::new(Mutex::new(Vec::new()));
+        let spans = exported.clone();
+        let exporter: Arc<SpanExporter> = Arc::new(move |span: FinishedSpan| {
+            spans.lock().unwrap().push(span);
+        });
+
+        let parent = SpanContext {
+            trace_id: 1,
+            span_id: 2,
+            sampled: false,
+        };
+        let span = Span::new("request", parent.child(), Some(2), exporter);
+        span.child("query").end();
+        span.end();
+
+        assert!(exported.lock().unwrap().is_empty());
+    }
+}
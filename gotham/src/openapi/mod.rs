@@ -0,0 +1,594 @@
+//! Generates an OpenAPI 3 document describing the routes of a `Router`, so that the consumers of
+//! an API have documentation which always matches the application.
+//!
+//! The document is built from the finished route tree, so every route restricted to particular
+//! methods is included without being listed again. The parameters of each route are taken from
+//! its path, and from the types of its `PathExtractor` and `QueryStringExtractor`, which are
+//! described by tracing their `Deserialize` implementations. The request and response bodies of
+//! an operation are not known to the router, so they are declared with `OpenApi::with_operation`.
+//!
+//! The document is served at `/openapi.json` by adding it to a router with
+//! `RouterBuilder::openapi`, or can be generated directly with `OpenApi::document`.
+//!
+//! # Examples
+//!
+//! ```rust
+//! # extern crate gotham;
+//! # #[macro_use]
+//! # extern crate gotham_derive;
+//! # extern crate hyper;
+//! # #[macro_use]
+//! # extern crate serde_derive;
+//! # #[macro_use]
+//! # extern crate serde_json;
+//! #
+//! # use hyper::{Body, Method, Response, StatusCode};
+//! # use gotham::openapi::{OpenApi, Operation, Schema};
+//! # use gotham::router::Router;
+//! # use gotham::router::builder::*;
+//! # use gotham::state::State;
+//! # use gotham::test::TestServer;
+//! #
+//! # #[allow(dead_code)]
+//! #[derive(Deserialize, StateData, StaticResponseExtender)]
+//! struct UserPath {
+//!     id: u64,
+//! }
+//!
+//! # #[allow(dead_code)]
+//! #[derive(Deserialize)]
+//! struct User {
+//!     id: u64,
+//!     name: String,
+//! }
+//!
+//! # fn handler(state: State) -> (State, Response<Body>) {
+//! #   (state, Response::new(Body::empty()))
+//! # }
+//! #
+//! fn router() -> Router {
+//!     let openapi = OpenApi::new("Users", "1.0.0").with_operation(
+//!         Method::GET,
+//!         "/users/:id",
+//!         Operation::new()
+//!             .with_summary("Fetches a user")
+//!             .with_response(StatusCode::OK, Schema::of::<User>())
+//!             .with_empty_response(StatusCode::NOT_FOUND),
+//!     );
+//!
+//!     build_simple_router(|route| {
+//!         route
+//!             .get("/users/:id")
+//!             .with_path_extractor::<UserPath>()
+//!             .to(handler);
+//!
+//!         route.openapi(openapi);
+//!     })
+//! }
+//! #
+//! # fn main() {
+//! #   let test_server = TestServer::new(router()).unwrap();
+//! #   let response = test_server
+//! #       .client()
+//! #       .get("http://localhost/openapi.json")
+//! #       .perform()
+//! #       .unwrap();
+//! #   assert_eq!(response.status(), StatusCode::OK);
+//! #
+//! #   let body = response.read_body().unwrap();
+//! #   let document: serde_json::Value = serde_json::from_slice(&body).unwrap();
+//! #   let operation = &document["paths"]["/users/{id}"]["get"];
+//! #   assert_eq!(operation["summary"], "Fetches a user");
+//! #   assert_eq!(operation["parameters"][0]["name"], "id");
+//! #   assert_eq!(operation["parameters"][0]["schema"]["type"], "integer");
+//! #   assert_eq!(
+//! #       operation["responses"]["200"]["content"]["application/json"]["schema"]["required"],
+//! #       json!(["id", "name"])
+//! #   );
+//! #   assert_eq!(operation["responses"]["404"]["description"], "Not Found");
+//! #   assert!(document["paths"].get("/openapi.json").is_none());
+//! # }
+//! ```
+
+use std::sync::{Arc, RwLock};
+
+use futures::future;
+use hyper::{Method, StatusCode};
+use mime;
+use serde_json::{self, Map, Value};
+
+use error::Result;
+use handler::{Handler, HandlerFuture, NewHandler};
+use helpers::http::response::create_response;
+use router::description::RouteDescription;
+use router::Router;
+use state::State;
+
+mod schema;
+
+pub use self::schema::Schema;
+
+/// The path at which the document is served by `RouterBuilder::openapi`.
+pub const OPENAPI_PATH: &str = "/openapi.json";
+
+/// Describes a single operation, which is a route accepting a particular method, beyond what can
+/// be learned from the router.
+///
+/// An operation without any responses is documented as responding with `200 OK`.
+#[derive(Clone, Debug, Default)]
+pub struct Operation {
+    summary: Option<String>,
+    description: Option<String>,
+    tags: Vec<String>,
+    request_body: Option<Schema>,
+    responses: Vec<(StatusCode, Option<Schema>)>,
+}
+
+impl Operation {
+    /// Creates an `Operation` with nothing declared.
+    pub fn new() -> Operation {
+        Operation::default()
+    }
+
+    /// Sets a short summary of what the operation does.
+    pub fn with_summary(self, summary: &str) -> Operation {
+        Operation {
+            summary: Some(summary.to_owned()),
+            ..self
+        }
+    }
+
+    /// Sets a longer description of the operation, which may use CommonMark.
+    pub fn with_description(self, description: &str) -> Operation {
+        Operation {
+            description: Some(description.to_owned()),
+            ..self
+        }
+    }
+
+    /// Adds a tag, which is used by documentation tools to group operations.
+    pub fn with_tag(mut self, tag: &str) -> Operation {
+        self.tags.push(tag.to_owned());
+        self
+    }
+
+    /// Declares that the operation requires a JSON request body described by `schema`.
+    pub fn with_request_body(self, schema: Schema) -> Operation {
+        Operation {
+            request_body: Some(schema),
+            ..self
+        }
+    }
+
+    /// Declares that the operation may respond with `status`, and a JSON body described by
+    /// `schema`.
+    pub fn with_response(mut self, status: StatusCode, schema: Schema) -> Operation {
+        self.responses.push((status, Some(schema)));
+        self
+    }
+
+    /// Declares that the operation may respond with `status`, and no body.
+    pub fn with_empty_response(mut self, status: StatusCode) -> Operation {
+        self.responses.push((status, None));
+        self
+    }
+
+    // Describes the operation for `route`, whose path has the parameters named `parameters`.
+    fn describe(&self, route: &RouteDescription, parameters: &[String]) -> Value {
+        let mut operation = Map::new();
+
+        if let Some(ref summary) = self.summary {
+            operation.insert("summary".to_owned(), json!(summary));
+        }
+        if let Some(ref description) = self.description {
+            operation.insert("description".to_owned(), json!(description));
+        }
+        if !self.tags.is_empty() {
+            operation.insert("tags".to_owned(), json!(self.tags));
+        }
+
+        let parameters = describe_parameters(route, parameters);
+        if !parameters.is_empty() {
+            operation.insert("parameters".to_owned(), Value::Array(parameters));
+        }
+
+        if let Some(ref schema) = self.request_body {
+            operation.insert(
+                "requestBody".to_owned(),
+                json!({ "required": true, "content": json_content(schema) }),
+            );
+        }
+
+        let mut responses = Map::new();
+        for &(status, ref schema) in &self.responses {
+            let mut response = Map::new();
+            response.insert(
+                "description".to_owned(),
+                json!(status.canonical_reason().unwrap_or("Response")),
+            );
+            if let Some(ref schema) = *schema {
+                response.insert("content".to_owned(), json_content(schema));
+            }
+            responses.insert(status.as_u16().to_string(), Value::Object(response));
+        }
+        if responses.is_empty() {
+            responses.insert("200".to_owned(), json!({ "description": "OK" }));
+        }
+        operation.insert("responses".to_owned(), Value::Object(responses));
+
+        Value::Object(operation)
+    }
+}
+
+fn json_content(schema: &Schema) -> Value {
+    json!({ "application/json": { "schema": schema.as_json() } })
+}
+
+// Describes the path and query string parameters of `route`. Path parameters are described by
+// the `PathExtractor` where it has a matching field, and are otherwise strings.
+fn describe_parameters(route: &RouteDescription, path_parameters: &[String]) -> Vec<Value> {
+    let extracted = route
+        .path_parameters()
+        .map(Schema::properties)
+        .unwrap_or_default();
+
+    let path = path_parameters.iter().map(|name| {
+        let schema = extracted
+            .iter()
+            .find(|&&(field, _, _)| field == name.as_str())
+            .map(|&(_, schema, _)| schema.clone())
+            .unwrap_or_else(|| json!({ "type": "string" }));
+
+        json!({ "name": name, "in": "path", "required": true, "schema": schema })
+    });
+
+    let query = route
+        .query_parameters()
+        .map(Schema::properties)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(name, schema, required)| {
+            json!({ "name": name, "in": "query", "required": required, "schema": schema })
+        });
+
+    path.chain(query).collect()
+}
+
+// Converts a path template in the syntax of the router builder to the syntax of OpenAPI, returning
+// it along with the names of its parameters. `/users/:id` becomes `/users/{id}`, as does a named
+// glob such as `/users/*id`.
+fn openapi_path(template: &str) -> (String, Vec<String>) {
+    let mut parameters = Vec::new();
+
+    let segments: Vec<String> = template
+        .split('/')
+        .map(|segment| {
+            if segment.starts_with('\\') {
+                segment[1..].to_owned()
+            } else if segment.starts_with(':') || (segment.starts_with('*') && segment.len() > 1) {
+                let name = segment[1..].to_owned();
+                let segment = format!("{{{}}}", name);
+                parameters.push(name);
+                segment
+            } else {
+                segment.to_owned()
+            }
+        })
+        .collect();
+
+    (segments.join("/"), parameters)
+}
+
+// The key of an operation in an OpenAPI path item, for the methods which OpenAPI supports.
+fn method_key(method: &Method) -> Option<&'static str> {
+    match *method {
+        Method::GET => Some("get"),
+        Method::PUT => Some("put"),
+        Method::POST => Some("post"),
+        Method::DELETE => Some("delete"),
+        Method::OPTIONS => Some("options"),
+        Method::HEAD => Some("head"),
+        Method::PATCH => Some("patch"),
+        Method::TRACE => Some("trace"),
+        _ => None,
+    }
+}
+
+/// Builds an OpenAPI 3 document describing the routes of a `Router`. See the module documentation
+/// for an example.
+///
+/// Routes which accept any method, such as delegated routes, are not included. Nor are the routes
+/// of a `Router` which is delegated to, or the route which serves the document.
+#[derive(Clone, Debug)]
+pub struct OpenApi {
+    title: String,
+    version: String,
+    description: Option<String>,
+    operations: Vec<(Method, String, Operation)>,
+}
+
+impl OpenApi {
+    /// Creates an `OpenApi` for the API named `title`, at the given version of the API.
+    pub fn new(title: &str, version: &str) -> OpenApi {
+        OpenApi {
+            title: title.to_owned(),
+            version: version.to_owned(),
+            description: None,
+            operations: Vec::new(),
+        }
+    }
+
+    /// Sets a description of the API, which may use CommonMark.
+    pub fn with_description(self, description: &str) -> OpenApi {
+        OpenApi {
+            description: Some(description.to_owned()),
+            ..self
+        }
+    }
+
+    /// Describes the operation for requests with `method` to the route at `path`, where `path` is
+    /// the path template given to the router builder, such as `/users/:id`.
+    pub fn with_operation(mut self, method: Method, path: &str, operation: Operation) -> OpenApi {
+        self.operations.push((method, path.to_owned(), operation));
+        self
+    }
+
+    /// Generates the document describing the routes of `router`.
+    ///
+    /// # Panics
+    ///
+    /// If an operation was given for a method and path which don't match any route, since the
+    /// document would otherwise be silently incomplete.
+    pub fn document(&self, router: &Router) -> Value {
+        let mut paths = Map::new();
+        let mut documented = vec![false; self.operations.len()];
+        let undocumented = Operation::new();
+
+        for route in router.routes() {
+            if route.path() == OPENAPI_PATH {
+                continue;
+            }
+
+            let methods = match route.methods() {
+                Some(methods) => methods.to_vec(),
+                None => continue,
+            };
+
+            let (path, parameters) = openapi_path(route.path());
+
+            for method in methods {
+                let key = match method_key(&method) {
+                    Some(key) => key,
+                    None => continue,
+                };
+
+                let operation = match self
+                    .operations
+                    .iter()
+                    .position(|&(ref m, ref p, _)| *m == method && p == route.path())
+                {
+                    Some(index) => {
+                        documented[index] = true;
+                        &self.operations[index].2
+                    }
+                    None => &undocumented,
+                };
+
+                let item = paths
+                    .entry(path.clone())
+                    .or_insert_with(|| Value::Object(Map::new()));
+
+                // The first route to accept a method is the one which requests are dispatched to.
+                if item.get(key).is_none() {
+                    item[key] = operation.describe(&route, &parameters);
+                }
+            }
+        }
+
+        if let Some(index) = documented.iter().position(|documented| !documented) {
+            let (ref method, ref path, _) = self.operations[index];
+            panic!("operation `{} {}` does not match any route", method, path);
+        }
+
+        let mut info = json!({ "title": self.title, "version": self.version });
+        if let Some(ref description) = self.description {
+            info["description"] = json!(description);
+        }
+
+        json!({ "openapi": "3.0.0", "info": info, "paths": paths })
+    }
+
+    /// Creates the `Handler` which serves the document, once it has been generated for a `Router`
+    /// by `OpenApiHandler::publish`.
+    pub(crate) fn handler(self) -> OpenApiHandler {
+        OpenApiHandler {
+            openapi: Arc::new(self),
+            document: Arc::new(RwLock::new(Vec::new())),
+        }
+    }
+}
+
+/// A `Handler` which serves an OpenAPI document as JSON. Created by `RouterBuilder::openapi`.
+#[derive(Clone)]
+pub struct OpenApiHandler {
+    openapi: Arc<OpenApi>,
+    document: Arc<RwLock<Vec<u8>>>,
+}
+
+impl OpenApiHandler {
+    /// Generates the document for `router`, which is the `Router` this handler was added to.
+    pub(crate) fn publish(&self, router: &Router) {
+        let document = self.openapi.document(router);
+        *self.document.write().unwrap() =
+            serde_json::to_vec(&document).expect("OpenAPI document is serializable");
+    }
+}
+
+impl NewHandler for OpenApiHandler {
+    type Instance = Self;
+
+    fn new_handler(&self) -> Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+impl Handler for OpenApiHandler {
+    fn handle(self, state: State) -> Box<HandlerFuture> {
+        let body = self.document.read().unwrap().clone();
+        let res = create_response(&state, StatusCode::OK, mime::APPLICATION_JSON, body);
+        Box::new(future::ok((state, res)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use hyper::{Body, Response};
+
+    use router::builder::*;
+    use router::response::extender::StaticResponseExtender;
+    use state::StateData;
+
+    #[derive(Deserialize)]
+    #[allow(dead_code)]
+    struct ItemPath {
+        id: u32,
+    }
+
+    impl StateData for ItemPath {}
+
+    impl StaticResponseExtender for ItemPath {
+        type ResBody = Body;
+        fn extend(_: &mut State, _: &mut Response<Body>) {}
+    }
+
+    #[derive(Deserialize)]
+    #[allow(dead_code)]
+    struct Search {
+        q: String,
+        page: Option<u32>,
+    }
+
+    impl StateData for Search {}
+
+    impl StaticResponseExtender for Search {
+        type ResBody = Body;
+        fn extend(_: &mut State, _: &mut Response<Body>) {}
+    }
+
+    fn handler(state: State) -> (State, Response<Body>) {
+        (state, Response::new(Body::empty()))
+    }
+
+    fn router() -> Router {
+        build_simple_router(|route| {
+            route
+                .get("/items")
+                .with_query_string_extractor::<Search>()
+                .to(handler);
+            route.post("/items").to(handler);
+            route
+                .get_or_head("/items/:id/files/*path")
+                .with_path_extractor::<ItemPath>()
+                .to(handler);
+            route.get("/\\:literal").to(handler);
+            route
+                .delegate("/legacy")
+                .to_router(build_simple_router(|route| {
+                    route.get("/").to(handler);
+                }));
+        })
+    }
+
+    #[test]
+    fn converts_paths() {
+        assert_eq!(
+            openapi_path("/items/:id/files/*path"),
+            (
+                "/items/{id}/files/{path}".to_owned(),
+                vec!["id".to_owned(), "path".to_owned()]
+            )
+        );
+        assert_eq!(
+            openapi_path("/\\:literal/*"),
+            ("/:literal/*".to_owned(), vec![])
+        );
+        assert_eq!(openapi_path("/"), ("/".to_owned(), vec![]));
+    }
+
+    #[test]
+    fn documents_routes() {
+        let document = OpenApi::new("Items", "2.0.0")
+            .with_description("Manages items")
+            .with_operation(
+                Method::POST,
+                "/items",
+                Operation::new()
+                    .with_tag("items")
+                    .with_request_body(Schema::of::<Search>())
+                    .with_empty_response(StatusCode::CREATED),
+            )
+            .document(&router());
+
+        assert_eq!(document["openapi"], "3.0.0");
+        assert_eq!(
+            document["info"],
+            json!({ "title": "Items", "version": "2.0.0", "description": "Manages items" })
+        );
+
+        let paths = document["paths"].as_object().unwrap();
+        let mut keys: Vec<&String> = paths.keys().collect();
+        keys.sort();
+        assert_eq!(
+            keys,
+            vec!["/:literal", "/items", "/items/{id}/files/{path}"]
+        );
+
+        assert_eq!(
+            paths["/items"]["get"],
+            json!({
+                "parameters": [
+                    { "name": "page", "in": "query", "required": false,
+                      "schema": { "type": "integer", "format": "int64", "minimum": 0,
+                                  "nullable": true } },
+                    { "name": "q", "in": "query", "required": true,
+                      "schema": { "type": "string" } },
+                ],
+                "responses": { "200": { "description": "OK" } },
+            })
+        );
+
+        let post = &paths["/items"]["post"];
+        assert_eq!(post["tags"], json!(["items"]));
+        assert_eq!(post["requestBody"]["required"], true);
+        assert_eq!(
+            post["requestBody"]["content"]["application/json"]["schema"]["required"],
+            json!(["q"])
+        );
+        assert_eq!(
+            post["responses"],
+            json!({ "201": { "description": "Created" } })
+        );
+
+        let files = &paths["/items/{id}/files/{path}"];
+        assert_eq!(files["get"], files["head"]);
+        assert_eq!(
+            files["get"]["parameters"],
+            json!([
+                { "name": "id", "in": "path", "required": true,
+                  "schema": { "type": "integer", "format": "int64", "minimum": 0 } },
+                { "name": "path", "in": "path", "required": true,
+                  "schema": { "type": "string" } },
+            ])
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "operation `DELETE /items` does not match any route")]
+    fn unknown_operations_panic() {
+        OpenApi::new("Items", "2.0.0")
+            .with_operation(Method::DELETE, "/items", Operation::new())
+            .document(&router());
+    }
+}
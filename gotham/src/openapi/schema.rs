@@ -0,0 +1,732 @@
+//! Defines `Schema`, which describes a type as a JSON Schema, and the deserializer which traces a
+//! `Deserialize` implementation to produce it.
+
+use std::error::Error;
+use std::fmt;
+
+use serde::de::{
+    self, DeserializeOwned, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess, SeqAccess,
+    VariantAccess, Visitor,
+};
+use serde_json::{Map, Value};
+
+// The depth of nested values which are traced before values of unbounded size, such as options
+// and sequences, are assumed to be empty. This bounds the tracing of recursive types.
+const MAX_DEPTH: usize = 16;
+
+/// A JSON Schema describing the values of a type, in the dialect used by OpenAPI 3.
+///
+/// The schema of a type which implements `Deserialize` is produced by `Schema::of`, so the
+/// extractors and bodies of a route are described without any extra code. A schema can also be
+/// written by hand with `Schema::new`, for types whose `Deserialize` implementation does not
+/// describe them well.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate gotham;
+/// # #[macro_use]
+/// # extern crate serde_derive;
+/// # #[macro_use]
+/// # extern crate serde_json;
+/// #
+/// # use gotham::openapi::Schema;
+/// #
+/// # #[allow(dead_code)]
+/// #[derive(Deserialize)]
+/// struct User {
+///     id: u64,
+///     name: String,
+///     email: Option<String>,
+/// }
+///
+/// # fn main() {
+/// assert_eq!(
+///     Schema::of::<User>().as_json(),
+///     &json!({
+///         "type": "object",
+///         "properties": {
+///             "id": { "type": "integer", "format": "int64", "minimum": 0 },
+///             "name": { "type": "string" },
+///             "email": { "type": "string", "nullable": true },
+///         },
+///         "required": ["id", "name"],
+///     })
+/// );
+/// # }
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Schema {
+    value: Value,
+}
+
+impl Schema {
+    /// Creates a `Schema` from a JSON Schema document.
+    pub fn new(value: Value) -> Schema {
+        Schema { value }
+    }
+
+    /// Describes the type `T`, by tracing the calls which its `Deserialize` implementation makes.
+    ///
+    /// Structs are described as objects, with each field which is not an `Option` being required.
+    /// Enums are described as strings naming their variants. A type which can't be traced, such as
+    /// one which deserializes differently depending on the input, is described by the empty
+    /// schema, which allows any value.
+    pub fn of<T>() -> Schema
+    where
+        T: DeserializeOwned,
+    {
+        Schema::trace::<T>().unwrap_or_else(|| Schema::new(Value::Object(Map::new())))
+    }
+
+    /// Describes the type `T` as `Schema::of` does, or returns `None` when `T` does not
+    /// deserialize anything, as for the extractors used by routes which don't extract anything.
+    pub(crate) fn trace<T>() -> Option<Schema>
+    where
+        T: DeserializeOwned,
+    {
+        let mut out = None;
+        match T::deserialize(Tracer::new(&mut out, 0)) {
+            Ok(_) => out.map(Schema::new),
+            Err(_) => None,
+        }
+    }
+
+    /// The JSON Schema document.
+    pub fn as_json(&self) -> &Value {
+        &self.value
+    }
+
+    /// The properties of an object schema, with whether each of them is required.
+    pub(crate) fn properties(&self) -> Vec<(&str, &Value, bool)> {
+        let required: Vec<&str> = self.value["required"]
+            .as_array()
+            .map(|names| names.iter().filter_map(Value::as_str).collect())
+            .unwrap_or_default();
+
+        match self.value["properties"].as_object() {
+            Some(properties) => properties
+                .iter()
+                .map(|(name, schema)| (name.as_str(), schema, required.contains(&name.as_str())))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+}
+
+/// The error raised when a type can't be traced.
+#[derive(Debug)]
+struct TraceError(String);
+
+impl fmt::Display for TraceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Error for TraceError {
+    fn description(&self) -> &str {
+        &self.0
+    }
+}
+
+impl de::Error for TraceError {
+    fn custom<T: fmt::Display>(msg: T) -> TraceError {
+        TraceError(msg.to_string())
+    }
+}
+
+/// A `Deserializer` which records the schema of the value requested from it in `out`, and then
+/// provides a placeholder value of that type.
+struct Tracer<'a> {
+    out: &'a mut Option<Value>,
+    depth: usize,
+}
+
+impl<'a> Tracer<'a> {
+    fn new(out: &'a mut Option<Value>, depth: usize) -> Tracer<'a> {
+        Tracer { out, depth }
+    }
+
+    fn record(self, schema: Value) {
+        *self.out = Some(schema);
+    }
+
+    // Traces a nested value, returning its schema.
+    fn nested<'de, S>(&self, seed: S) -> Result<(S::Value, Value), TraceError>
+    where
+        S: DeserializeSeed<'de>,
+    {
+        let mut out = None;
+        let value = seed.deserialize(Tracer::new(&mut out, self.depth + 1))?;
+        Ok((value, out.unwrap_or_else(|| json!({}))))
+    }
+}
+
+macro_rules! trace_scalar {
+    ($method:ident, $visit:ident, $value:expr, $schema:expr) => {
+        fn $method<V>(self, visitor: V) -> Result<V::Value, TraceError>
+        where
+            V: Visitor<'de>,
+        {
+            self.record($schema);
+            visitor.$visit($value)
+        }
+    };
+}
+
+impl<'de, 'a> de::Deserializer<'de> for Tracer<'a> {
+    type Error = TraceError;
+
+    fn deserialize_any<V>(self, _visitor: V) -> Result<V::Value, TraceError>
+    where
+        V: Visitor<'de>,
+    {
+        Err(TraceError(
+            "the type does not describe its format".to_owned(),
+        ))
+    }
+
+    trace_scalar!(
+        deserialize_bool,
+        visit_bool,
+        false,
+        json!({ "type": "boolean" })
+    );
+    trace_scalar!(
+        deserialize_i8,
+        visit_i8,
+        0,
+        json!({ "type": "integer", "format": "int32" })
+    );
+    trace_scalar!(
+        deserialize_i16,
+        visit_i16,
+        0,
+        json!({ "type": "integer", "format": "int32" })
+    );
+    trace_scalar!(
+        deserialize_i32,
+        visit_i32,
+        0,
+        json!({ "type": "integer", "format": "int32" })
+    );
+    trace_scalar!(
+        deserialize_i64,
+        visit_i64,
+        0,
+        json!({ "type": "integer", "format": "int64" })
+    );
+    trace_scalar!(
+        deserialize_u8,
+        visit_u8,
+        0,
+        json!({ "type": "integer", "format": "int32", "minimum": 0 })
+    );
+    trace_scalar!(
+        deserialize_u16,
+        visit_u16,
+        0,
+        json!({ "type": "integer", "format": "int32", "minimum": 0 })
+    );
+    trace_scalar!(
+        deserialize_u32,
+        visit_u32,
+        0,
+        json!({ "type": "integer", "format": "int64", "minimum": 0 })
+    );
+    trace_scalar!(
+        deserialize_u64,
+        visit_u64,
+        0,
+        json!({ "type": "integer", "format": "int64", "minimum": 0 })
+    );
+    trace_scalar!(
+        deserialize_f32,
+        visit_f32,
+        0.0,
+        json!({ "type": "number", "format": "float" })
+    );
+    trace_scalar!(
+        deserialize_f64,
+        visit_f64,
+        0.0,
+        json!({ "type": "number", "format": "double" })
+    );
+    trace_scalar!(
+        deserialize_char,
+        visit_char,
+        'a',
+        json!({ "type": "string", "minLength": 1, "maxLength": 1 })
+    );
+    trace_scalar!(deserialize_str, visit_str, "", json!({ "type": "string" }));
+    trace_scalar!(
+        deserialize_string,
+        visit_str,
+        "",
+        json!({ "type": "string" })
+    );
+    trace_scalar!(
+        deserialize_identifier,
+        visit_str,
+        "",
+        json!({ "type": "string" })
+    );
+    trace_scalar!(
+        deserialize_bytes,
+        visit_bytes,
+        &[],
+        json!({ "type": "string", "format": "binary" })
+    );
+    trace_scalar!(
+        deserialize_byte_buf,
+        visit_bytes,
+        &[],
+        json!({ "type": "string", "format": "binary" })
+    );
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, TraceError>
+    where
+        V: Visitor<'de>,
+    {
+        if self.depth >= MAX_DEPTH {
+            self.record(json!({ "nullable": true }));
+            return visitor.visit_none();
+        }
+
+        let mut out = None;
+        let value = visitor.visit_some(Tracer::new(&mut out, self.depth + 1))?;
+
+        let mut schema = out.unwrap_or_else(|| json!({}));
+        if let Some(object) = schema.as_object_mut() {
+            object.insert("nullable".to_owned(), Value::Bool(true));
+        }
+        self.record(schema);
+        Ok(value)
+    }
+
+    fn deserialize_unit<V>(self, visitor: V) -> Result<V::Value, TraceError>
+    where
+        V: Visitor<'de>,
+    {
+        self.record(json!({ "nullable": true, "enum": [null] }));
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, TraceError>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, TraceError>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, TraceError>
+    where
+        V: Visitor<'de>,
+    {
+        let (value, mut items) = {
+            let mut access = SeqTracer {
+                parent: &self,
+                remaining: if self.depth >= MAX_DEPTH { 0 } else { 1 },
+                items: Vec::new(),
+            };
+            (visitor.visit_seq(&mut access)?, access.items)
+        };
+        let items = items.pop().unwrap_or_else(|| json!({}));
+
+        self.record(json!({ "type": "array", "items": items }));
+        Ok(value)
+    }
+
+    fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value, TraceError>
+    where
+        V: Visitor<'de>,
+    {
+        let (value, items) = {
+            let mut access = SeqTracer {
+                parent: &self,
+                remaining: len,
+                items: Vec::new(),
+            };
+            (visitor.visit_seq(&mut access)?, access.items)
+        };
+
+        // OpenAPI 3 can't describe the type of each item, so the items are only described when
+        // they all have the same type.
+        let items = match items.split_first() {
+            Some((first, rest)) if rest.iter().all(|item| item == first) => first.clone(),
+            _ => json!({}),
+        };
+
+        self.record(json!({
+            "type": "array",
+            "items": items,
+            "minItems": len,
+            "maxItems": len,
+        }));
+        Ok(value)
+    }
+
+    fn deserialize_tuple_struct<V>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, TraceError>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_map<V>(self, visitor: V) -> Result<V::Value, TraceError>
+    where
+        V: Visitor<'de>,
+    {
+        let (value, mut values) = {
+            let mut access = MapTracer {
+                parent: &self,
+                remaining: if self.depth >= MAX_DEPTH { 0 } else { 1 },
+                values: Vec::new(),
+            };
+            (visitor.visit_map(&mut access)?, access.values)
+        };
+        let values = values.pop().unwrap_or_else(|| json!({}));
+
+        self.record(json!({ "type": "object", "additionalProperties": values }));
+        Ok(value)
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, TraceError>
+    where
+        V: Visitor<'de>,
+    {
+        let (value, fields) = {
+            let mut access = StructTracer {
+                parent: &self,
+                fields,
+                index: 0,
+                properties: Vec::new(),
+            };
+            (visitor.visit_map(&mut access)?, access.properties)
+        };
+
+        let mut properties = Map::new();
+        let mut required = Vec::new();
+        for (name, schema) in fields {
+            if schema.get("nullable") != Some(&Value::Bool(true)) {
+                required.push(Value::from(name));
+            }
+            properties.insert(name.to_owned(), schema);
+        }
+
+        let mut schema = json!({ "type": "object", "properties": properties });
+        if !required.is_empty() {
+            schema["required"] = Value::Array(required);
+        }
+        self.record(schema);
+        Ok(value)
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, TraceError>
+    where
+        V: Visitor<'de>,
+    {
+        let variant = variants
+            .first()
+            .ok_or_else(|| TraceError("the enum has no variants".to_owned()))?;
+        let value = visitor.visit_enum(EnumTracer {
+            parent: &self,
+            variant: *variant,
+        })?;
+
+        self.record(json!({ "type": "string", "enum": variants }));
+        Ok(value)
+    }
+
+    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value, TraceError>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_unit()
+    }
+}
+
+/// Provides the items of a sequence or tuple, recording the schema of each.
+struct SeqTracer<'a, 'b: 'a> {
+    parent: &'a Tracer<'b>,
+    remaining: usize,
+    items: Vec<Value>,
+}
+
+impl<'de, 'a, 'b, 'c> SeqAccess<'de> for &'c mut SeqTracer<'a, 'b> {
+    type Error = TraceError;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, TraceError>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+
+        let (value, schema) = self.parent.nested(seed)?;
+        self.items.push(schema);
+        Ok(Some(value))
+    }
+}
+
+/// Provides the entries of a map, recording the schema of each value.
+struct MapTracer<'a, 'b: 'a> {
+    parent: &'a Tracer<'b>,
+    remaining: usize,
+    values: Vec<Value>,
+}
+
+impl<'de, 'a, 'b, 'c> MapAccess<'de> for &'c mut MapTracer<'a, 'b> {
+    type Error = TraceError;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, TraceError>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+
+        self.parent.nested(seed).map(|(key, _)| Some(key))
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, TraceError>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let (value, schema) = self.parent.nested(seed)?;
+        self.values.push(schema);
+        Ok(value)
+    }
+}
+
+/// Provides each field of a struct, recording the schema of each.
+struct StructTracer<'a, 'b: 'a> {
+    parent: &'a Tracer<'b>,
+    fields: &'static [&'static str],
+    index: usize,
+    properties: Vec<(&'static str, Value)>,
+}
+
+impl<'de, 'a, 'b, 'c> MapAccess<'de> for &'c mut StructTracer<'a, 'b> {
+    type Error = TraceError;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, TraceError>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        match self.fields.get(self.index) {
+            Some(field) => seed.deserialize(field.into_deserializer()).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, TraceError>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let field = self.fields[self.index];
+        self.index += 1;
+
+        let (value, schema) = self.parent.nested(seed)?;
+        self.properties.push((field, schema));
+        Ok(value)
+    }
+}
+
+/// Selects the first variant of an enum.
+struct EnumTracer<'a, 'b: 'a> {
+    parent: &'a Tracer<'b>,
+    variant: &'static str,
+}
+
+impl<'de, 'a, 'b> EnumAccess<'de> for EnumTracer<'a, 'b> {
+    type Error = TraceError;
+    type Variant = Self;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self), TraceError>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let variant = seed.deserialize(self.variant.into_deserializer())?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de, 'a, 'b> VariantAccess<'de> for EnumTracer<'a, 'b> {
+    type Error = TraceError;
+
+    fn unit_variant(self) -> Result<(), TraceError> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, TraceError>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        self.parent.nested(seed).map(|(value, _)| value)
+    }
+
+    fn tuple_variant<V>(self, len: usize, visitor: V) -> Result<V::Value, TraceError>
+    where
+        V: Visitor<'de>,
+    {
+        let mut out = None;
+        de::Deserializer::deserialize_tuple(
+            Tracer::new(&mut out, self.parent.depth + 1),
+            len,
+            visitor,
+        )
+    }
+
+    fn struct_variant<V>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, TraceError>
+    where
+        V: Visitor<'de>,
+    {
+        let mut out = None;
+        de::Deserializer::deserialize_struct(
+            Tracer::new(&mut out, self.parent.depth + 1),
+            "",
+            fields,
+            visitor,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    #[derive(Deserialize)]
+    #[allow(dead_code)]
+    struct Params {
+        id: u32,
+        #[serde(rename = "q")]
+        query: Option<String>,
+        tags: Vec<String>,
+        scores: HashMap<String, f64>,
+        point: (i32, i32),
+        order: Order,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    enum Order {
+        Ascending,
+        Descending,
+    }
+
+    #[derive(Deserialize)]
+    #[allow(dead_code)]
+    struct Tree {
+        value: bool,
+        children: Vec<Tree>,
+    }
+
+    #[derive(Deserialize)]
+    struct Id(u64);
+
+    #[test]
+    fn describes_structs() {
+        assert_eq!(
+            Schema::of::<Params>().as_json(),
+            &json!({
+                "type": "object",
+                "properties": {
+                    "id": { "type": "integer", "format": "int64", "minimum": 0 },
+                    "q": { "type": "string", "nullable": true },
+                    "tags": { "type": "array", "items": { "type": "string" } },
+                    "scores": {
+                        "type": "object",
+                        "additionalProperties": { "type": "number", "format": "double" },
+                    },
+                    "point": {
+                        "type": "array",
+                        "items": { "type": "integer", "format": "int32" },
+                        "minItems": 2,
+                        "maxItems": 2,
+                    },
+                    "order": { "type": "string", "enum": ["ascending", "descending"] },
+                },
+                "required": ["id", "tags", "scores", "point", "order"],
+            })
+        );
+    }
+
+    #[test]
+    fn describes_newtypes() {
+        assert_eq!(
+            Schema::of::<Id>().as_json(),
+            &json!({ "type": "integer", "format": "int64", "minimum": 0 })
+        );
+    }
+
+    #[test]
+    fn recursive_types_are_bounded() {
+        let schema = Schema::of::<Tree>();
+        assert_eq!(schema.as_json()["properties"]["value"]["type"], "boolean");
+        assert_eq!(
+            schema.as_json()["properties"]["children"]["items"]["properties"]["value"]["type"],
+            "boolean"
+        );
+    }
+
+    #[test]
+    fn untraceable_types_allow_anything() {
+        assert_eq!(Schema::of::<Value>().as_json(), &json!({}));
+        assert_eq!(Schema::trace::<Value>(), None);
+    }
+
+    #[test]
+    fn lists_properties() {
+        let schema = Schema::of::<Params>();
+        let properties = schema.properties();
+
+        let required = |name| properties.iter().find(|p| p.0 == name).map(|p| p.2);
+
+        assert_eq!(properties.len(), 6);
+        assert_eq!(required("id"), Some(true));
+        assert_eq!(required("q"), Some(false));
+        assert_eq!(required("missing"), None);
+    }
+}
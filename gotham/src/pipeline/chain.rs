@@ -9,7 +9,7 @@ use handler::{HandlerFuture, IntoHandlerError};
 use middleware::chain::NewMiddlewareChain;
 use pipeline::set::PipelineSet;
 use pipeline::Pipeline;
-use state::{request_id, State};
+use state::State;
 
 /// A heterogeneous list of `Handle<P, _>` values, where `P` is a pipeline type. The pipelines are
 /// borrowed and invoked in order to serve a request.
@@ -30,6 +30,9 @@ pub trait PipelineHandleChain<P>: RefUnwindSafe {
     fn call<F>(&self, pipelines: &PipelineSet<P>, state: State, f: F) -> Box<HandlerFuture>
     where
         F: FnOnce(State) -> Box<HandlerFuture> + Send + 'static;
+
+    /// Returns the number of `Pipeline` handles in this part of the `PipelineHandleChain`.
+    fn pipeline_count(&self) -> usize;
 }
 
 /// Part of a `PipelineHandleChain` which references a `Pipeline` and continues with a tail element.
@@ -49,11 +52,15 @@ where
         match pipelines.borrow(handle).construct() {
             Ok(p) => chain.call(pipelines, state, move |state| p.call(state, f)),
             Err(e) => {
-                trace!("[{}] error borrowing pipeline", request_id(&state));
+                request_trace!(&state, "error borrowing pipeline");
                 Box::new(future::err((state, e.into_handler_error())))
             }
         }
     }
+
+    fn pipeline_count(&self) -> usize {
+        1 + self.1.pipeline_count()
+    }
 }
 
 /// The marker for the end of a `PipelineHandleChain`.
@@ -62,7 +69,11 @@ impl<P> PipelineHandleChain<P> for () {
     where
         F: FnOnce(State) -> Box<HandlerFuture> + Send + 'static,
     {
-        trace!("[{}] start pipeline", request_id(&state));
+        request_trace!(&state, "start pipeline");
         f(state)
     }
+
+    fn pipeline_count(&self) -> usize {
+        0
+    }
 }
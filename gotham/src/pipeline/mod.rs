@@ -8,8 +8,8 @@ use std::io;
 
 use handler::HandlerFuture;
 use middleware::chain::{MiddlewareChain, NewMiddlewareChain};
-use middleware::NewMiddleware;
-use state::{request_id, State};
+use middleware::{Middleware, NewMiddleware};
+use state::State;
 
 /// When using middleware, one or more `Middleware` are combined to form a `Pipeline`.
 /// `Middleware` are invoked strictly in the order they're added to the `Pipeline`.
@@ -124,8 +124,20 @@ where
     chain: T,
 }
 
-/// Represents an instance of a `Pipeline`. Returned from `Pipeline::construct()`.
-struct PipelineInstance<T>
+impl<T> Clone for Pipeline<T>
+where
+    T: NewMiddlewareChain + Clone,
+{
+    fn clone(&self) -> Pipeline<T> {
+        Pipeline {
+            chain: self.chain.clone(),
+        }
+    }
+}
+
+/// Represents an instance of a `Pipeline`. Returned from `Pipeline::construct()`, and used as the
+/// `Middleware` when a `Pipeline` is added to another.
+pub struct PipelineInstance<T>
 where
     T: MiddlewareChain,
 {
@@ -155,11 +167,63 @@ where
     where
         F: FnOnce(State) -> Box<HandlerFuture> + Send + 'static,
     {
-        trace!("[{}] calling middleware", request_id(&state));
+        request_trace!(&state, "calling middleware");
         self.chain.call(state, f)
     }
 }
 
+/// Allows a `Pipeline` to be added to another `Pipeline` as a single unit, so that a group of
+/// middleware which is common to several pipelines can be defined once.
+///
+/// ```rust
+/// # extern crate gotham;
+/// #
+/// # use gotham::middleware::cors::CorsMiddleware;
+/// # use gotham::middleware::request_id::RequestIdMiddleware;
+/// # use gotham::middleware::security::SecurityMiddleware;
+/// # use gotham::pipeline::new_pipeline;
+/// # use gotham::pipeline::set::{finalize_pipeline_set, new_pipeline_set};
+/// #
+/// # fn main() {
+/// let common = new_pipeline()
+///     .add(RequestIdMiddleware)
+///     .add(SecurityMiddleware)
+///     .build();
+///
+/// let pipelines = new_pipeline_set();
+/// let (pipelines, web) = pipelines.add(new_pipeline().add(common.clone()).build());
+/// let (pipelines, api) = pipelines.add(
+///     new_pipeline()
+///         .add(common)
+///         .add(CorsMiddleware::default())
+///         .build(),
+/// );
+/// # let _ = (finalize_pipeline_set(pipelines), web, api);
+/// # }
+/// ```
+impl<T> NewMiddleware for Pipeline<T>
+where
+    T: NewMiddlewareChain + Sync,
+{
+    type Instance = PipelineInstance<T::Instance>;
+
+    fn new_middleware(&self) -> io::Result<Self::Instance> {
+        self.construct()
+    }
+}
+
+impl<T> Middleware for PipelineInstance<T>
+where
+    T: MiddlewareChain,
+{
+    fn call<Chain>(self, state: State, chain: Chain) -> Box<HandlerFuture>
+    where
+        Chain: FnOnce(State) -> Box<HandlerFuture> + Send + 'static,
+    {
+        PipelineInstance::call(self, state, chain)
+    }
+}
+
 /// Begins defining a new pipeline.
 ///
 /// See `PipelineBuilder` for information on using `new_pipeline()`.
@@ -405,4 +469,34 @@ mod tests {
         let buf = response.read_body().unwrap();
         assert_eq!(buf.as_slice(), "24".as_bytes());
     }
+
+    #[test]
+    fn nested_pipeline_test() {
+        let test_server = TestServer::new(|| {
+            let inner = new_pipeline()
+                .add(Addition { value: 1 })
+                .add(Multiplication { value: 2 })
+                .build();
+
+            let pipeline = new_pipeline()
+                .add(Number { value: 1 }) // 1
+                .add(inner) // 4
+                .add(Addition { value: 3 }) // 7
+                .build();
+
+            Ok(move |state| match pipeline.construct() {
+                Ok(p) => p.call(state, |state| handler.handle(state)),
+                Err(e) => Box::new(future::err((state, e.into_handler_error()))),
+            })
+        }).unwrap();
+
+        let response = test_server
+            .client()
+            .get("http://localhost/")
+            .perform()
+            .unwrap();
+
+        let buf = response.read_body().unwrap();
+        assert_eq!(buf.as_slice(), "7".as_bytes());
+    }
 }
@@ -0,0 +1,200 @@
+//! Defines the hook which is told about errors and panics from handlers, so that they can be sent
+//! to an error tracking service such as Sentry or Rollbar.
+//!
+//! An `ErrorReporter` is given to `ServerBuilder::with_error_reporter`, and receives an
+//! `ErrorReport` whenever a handler or middleware fails with an error, or panics. The report
+//! gives access to the `State` of the request, so that an integration can be written as a small
+//! adapter which extracts the details it needs.
+//!
+//! # Examples
+//!
+//! ```rust
+//! # extern crate gotham;
+//! # extern crate hyper;
+//! #
+//! # use hyper::{Method, Uri};
+//! # use gotham::reporting::ErrorReport;
+//! # use gotham::state::FromState;
+//! #
+//! fn report_error(report: &ErrorReport) {
+//!     let state = report.state();
+//!
+//!     // Usually sent to an error tracking service.
+//!     eprintln!(
+//!         "{} {} {} failed with {}: {}",
+//!         report.context(),
+//!         Method::borrow_from(state),
+//!         Uri::borrow_from(state),
+//!         report.status(),
+//!         report.error(),
+//!     );
+//! }
+//! #
+//! # fn main() {
+//! #   let _ = gotham::ServerBuilder::new().with_error_reporter(report_error);
+//! # }
+//! ```
+
+use std::error::Error;
+use std::fmt;
+use std::panic::{catch_unwind, AssertUnwindSafe, RefUnwindSafe};
+
+use hyper::StatusCode;
+
+use handler::HandlerError;
+use logging::RequestContext;
+use state::State;
+
+/// Receives an `ErrorReport` for each request which fails with an error, or panics.
+///
+/// `ErrorReporter` is implemented for closures, so that a reporter can be written as a function.
+/// A panic in the reporter is caught and logged, and does not affect the response.
+pub trait ErrorReporter: Send + Sync + RefUnwindSafe {
+    /// Reports the failure of a request.
+    fn report(&self, report: &ErrorReport);
+}
+
+impl<F> ErrorReporter for F
+where
+    F: Fn(&ErrorReport) + Send + Sync + RefUnwindSafe,
+{
+    fn report(&self, report: &ErrorReport) {
+        self(report)
+    }
+}
+
+/// The cause of a failed request.
+#[derive(Debug)]
+pub enum ReportedError<'a> {
+    /// An error returned by a handler or middleware.
+    Handler(&'a HandlerError),
+
+    /// An error which prevented a handler from being created, or a response from being produced.
+    Internal(&'a Error),
+
+    /// A panic, with the message given to `panic!` when it is available.
+    Panic(&'a str),
+}
+
+impl<'a> fmt::Display for ReportedError<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ReportedError::Handler(err) => match err.cause() {
+                Some(cause) => write!(f, "{}", cause),
+                None => write!(f, "{}", err),
+            },
+            ReportedError::Internal(err) => write!(f, "{}", err),
+            ReportedError::Panic(message) => write!(f, "panic: {}", message),
+        }
+    }
+}
+
+/// Describes a request which failed, as received by an `ErrorReporter`.
+pub struct ErrorReport<'a> {
+    state: &'a State,
+    error: ReportedError<'a>,
+}
+
+impl<'a> ErrorReport<'a> {
+    /// The `State` of the request.
+    ///
+    /// When the handler returned an error this is the `State` it returned, so it holds everything
+    /// which was put into it while processing the request. When the handler panicked, the
+    /// `State` was lost, and this holds only the request data which Gotham stores before invoking
+    /// the `Router`: the method, URI, version, headers, request ID and client address.
+    pub fn state(&self) -> &State {
+        self.state
+    }
+
+    /// The names of the types held in `State`, in sorted order.
+    pub fn state_keys(&self) -> Vec<&'static str> {
+        self.state.type_names()
+    }
+
+    /// The request ID, route template and session identifier of the request, where known.
+    pub fn context(&self) -> RequestContext {
+        RequestContext::from_state(self.state)
+    }
+
+    /// The cause of the failure.
+    pub fn error(&self) -> &ReportedError<'a> {
+        &self.error
+    }
+
+    /// The status of the response which is sent to the client.
+    pub fn status(&self) -> StatusCode {
+        match self.error {
+            ReportedError::Handler(err) => err.status(),
+            ReportedError::Internal(_) | ReportedError::Panic(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+}
+
+/// Passes a report of `error` to `reporter`, logging any panic from the reporter rather than
+/// allowing it to escape.
+pub(crate) fn report(reporter: &ErrorReporter, state: &State, error: ReportedError) {
+    let report = ErrorReport { state, error };
+
+    if catch_unwind(AssertUnwindSafe(|| reporter.report(&report))).is_err() {
+        request_error!(
+            state,
+            "the error reporter panicked while reporting: {}",
+            report.error
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use hyper::{HeaderMap, Method};
+    use std::io;
+    use std::sync::Mutex;
+
+    use handler::IntoHandlerError;
+    use state::set_request_id;
+
+    fn state() -> State {
+        let mut state = State::new();
+        state.put(Method::GET);
+        state.put(HeaderMap::new());
+        set_request_id(&mut state);
+        state
+    }
+
+    #[test]
+    fn describes_errors() {
+        let reports = Mutex::new(Vec::new());
+        let reporter = |report: &ErrorReport| {
+            reports.lock().unwrap().push((
+                report.status(),
+                report.error().to_string(),
+                report.state_keys(),
+            ));
+        };
+
+        let state = state();
+        let err = io::Error::new(io::ErrorKind::Other, "disk full")
+            .into_handler_error()
+            .with_status(StatusCode::SERVICE_UNAVAILABLE);
+        report(&reporter, &state, ReportedError::Handler(&err));
+        report(&reporter, &state, ReportedError::Panic("oops"));
+
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports[0].0, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(reports[0].1, "disk full");
+        assert_eq!(reports[0].2.len(), 3);
+        assert!(reports[0].2.iter().any(|key| key.ends_with("RequestId")));
+        assert_eq!(reports[1].0, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(reports[1].1, "panic: oops");
+    }
+
+    #[test]
+    fn reporter_panics_are_caught() {
+        let reporter = |_: &ErrorReport| panic!("reporter failed");
+        report(&reporter, &state(), ReportedError::Panic("oops"));
+    }
+}
@@ -0,0 +1,512 @@
+//! Defines helpers for handlers which depend on other services, such as a proxied API or a
+//! database, so that a service which is failing doesn't also take down the application.
+//!
+//! A `CircuitBreaker` wraps the futures of calls to a service. Once too many of them fail, the
+//! breaker opens and further calls fail immediately, or are answered by a fallback, rather than
+//! waiting on the failing service and adding to its load. Calls which fail intermittently, and
+//! are safe to repeat, can instead be retried with a `Retry`.
+
+use futures::{future, Future, IntoFuture};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use middleware::metrics::Metrics;
+use state::StateData;
+
+mod retry;
+
+pub use self::retry::Retry;
+
+/// The state of a `CircuitBreaker`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BreakerState {
+    /// Calls are made, and their outcomes recorded.
+    Closed,
+    /// Calls are rejected without being made, until the open duration has passed.
+    Open,
+    /// The open duration has passed, and a single trial call is made to decide whether the
+    /// breaker closes or opens again. Other calls are rejected until it completes.
+    HalfOpen,
+}
+
+/// Formats the state as `closed`, `open` or `half_open`, as used in metric labels.
+impl Display for BreakerState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            BreakerState::Closed => "closed",
+            BreakerState::Open => "open",
+            BreakerState::HalfOpen => "half_open",
+        })
+    }
+}
+
+/// A circuit breaker around the calls made to another service.
+///
+/// The breaker records whether each of the most recent calls succeeded, over a window of
+/// `with_window` calls. Once at least `with_minimum_calls` have been recorded, and the
+/// proportion which failed reaches `with_failure_rate`, the breaker opens. While it is open,
+/// calls fail with `BreakerError::Open` without being made. After `with_open_duration` the
+/// breaker is half-open, and the next call is made as a trial: the breaker closes if it succeeds,
+/// and opens again if it fails.
+///
+/// By default, the breaker opens when half of the last 20 calls failed, with at least 10 calls
+/// recorded, and stays open for 30 seconds.
+///
+/// Cloning a `CircuitBreaker` shares its state, so that it can be created once and given to each
+/// handler, or added to `State` with a `StateMiddleware`. When given `Metrics`, the state of the
+/// breaker and the calls it rejects are recorded there, labelled by its name.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate futures;
+/// # extern crate gotham;
+/// #
+/// # use std::io;
+/// # use std::time::Duration;
+/// # use futures::{future, Future};
+/// # use gotham::resilience::CircuitBreaker;
+/// #
+/// fn fetch_profile(_id: u64) -> future::FutureResult<String, io::Error> {
+///     // a call to another service, which is unavailable
+///     future::err(io::Error::new(io::ErrorKind::Other, "unavailable"))
+/// }
+///
+/// # fn main() {
+/// let breaker = CircuitBreaker::new("profiles")
+///     .with_failure_rate(0.5)
+///     .with_minimum_calls(10)
+///     .with_open_duration(Duration::from_secs(30));
+///
+/// let profile = breaker
+///     .call_with_fallback(|| fetch_profile(7), |_| Ok("anonymous".to_owned()))
+///     .wait()
+///     .unwrap();
+///
+/// assert_eq!(profile, "anonymous");
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct CircuitBreaker {
+    name: String,
+    failure_rate: f64,
+    window: usize,
+    minimum_calls: usize,
+    open_duration: Duration,
+    metrics: Option<Metrics>,
+    circuit: Arc<Mutex<Circuit>>,
+}
+
+struct Circuit {
+    state: BreakerState,
+    // whether each of the most recent calls failed, while the breaker is closed
+    outcomes: VecDeque<bool>,
+    // when the breaker last opened
+    opened_at: Option<Instant>,
+    // whether the trial call is in progress, while the breaker is half-open
+    trial: bool,
+}
+
+impl CircuitBreaker {
+    /// Creates a closed `CircuitBreaker`, named in logs and metrics by `name`.
+    pub fn new<S>(name: S) -> CircuitBreaker
+    where
+        S: Into<String>,
+    {
+        CircuitBreaker {
+            name: name.into(),
+            failure_rate: 0.5,
+            window: 20,
+            minimum_calls: 10,
+            open_duration: Duration::from_secs(30),
+            metrics: None,
+            circuit: Arc::new(Mutex::new(Circuit {
+                state: BreakerState::Closed,
+                outcomes: VecDeque::new(),
+                opened_at: None,
+                trial: false,
+            })),
+        }
+    }
+
+    /// Opens the breaker when the proportion of recent calls which failed reaches `rate`, which
+    /// is between 0 and 1.
+    pub fn with_failure_rate(self, rate: f64) -> CircuitBreaker {
+        assert!(
+            rate > 0.0 && rate <= 1.0,
+            "failure rate must be greater than 0 and at most 1"
+        );
+
+        CircuitBreaker {
+            failure_rate: rate,
+            ..self
+        }
+    }
+
+    /// Records the outcomes of the last `calls` calls to determine the failure rate.
+    pub fn with_window(self, calls: usize) -> CircuitBreaker {
+        assert!(calls > 0, "window must not be empty");
+
+        CircuitBreaker {
+            window: calls,
+            minimum_calls: self.minimum_calls.min(calls),
+            ..self
+        }
+    }
+
+    /// Leaves the breaker closed until at least `calls` calls have been recorded, so that a
+    /// single early failure doesn't open it. This is at most the size of the window.
+    pub fn with_minimum_calls(self, calls: usize) -> CircuitBreaker {
+        CircuitBreaker {
+            minimum_calls: calls.max(1).min(self.window),
+            ..self
+        }
+    }
+
+    /// Rejects calls for `duration` after the breaker opens, before making a trial call.
+    pub fn with_open_duration(self, duration: Duration) -> CircuitBreaker {
+        CircuitBreaker {
+            open_duration: duration,
+            ..self
+        }
+    }
+
+    /// Records the state of the breaker, and the calls it rejects, in `metrics`.
+    pub fn with_metrics(self, metrics: Metrics) -> CircuitBreaker {
+        metrics.observe_breaker_state(&self.name, self.state());
+
+        CircuitBreaker {
+            metrics: Some(metrics),
+            ..self
+        }
+    }
+
+    /// The name of the breaker.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The current state of the breaker. An open breaker whose open duration has passed is
+    /// reported as open until the next call is made.
+    pub fn state(&self) -> BreakerState {
+        self.circuit().state
+    }
+
+    /// Makes a call through the breaker, where `f` creates the future of the call. When the
+    /// breaker is open, `f` is not called and the future fails with `BreakerError::Open`.
+    /// Otherwise the outcome of the call is recorded once its future completes, and a failure is
+    /// returned as `BreakerError::Failed`.
+    ///
+    /// A call whose future is dropped before completing is not recorded.
+    pub fn call<F, R>(
+        &self,
+        f: F,
+    ) -> Box<Future<Item = R::Item, Error = BreakerError<R::Error>> + Send>
+    where
+        F: FnOnce() -> R,
+        R: IntoFuture,
+        R::Future: Send + 'static,
+        R::Item: Send + 'static,
+        R::Error: Send + 'static,
+    {
+        let permit = match self.permit(Instant::now()) {
+            Some(permit) => permit,
+            None => return Box::new(future::err(BreakerError::Open)),
+        };
+
+        Box::new(f().into_future().then(move |result| {
+            permit.record(result.is_ok(), Instant::now());
+            result.map_err(BreakerError::Failed)
+        }))
+    }
+
+    /// Makes a call through the breaker as `call` does, but when the breaker is open or the call
+    /// fails, the future resolves to the result of `fallback` instead, such as a cached or
+    /// default value.
+    pub fn call_with_fallback<F, R, G, T>(
+        &self,
+        f: F,
+        fallback: G,
+    ) -> Box<Future<Item = R::Item, Error = R::Error> + Send>
+    where
+        F: FnOnce() -> R,
+        R: IntoFuture + 'static,
+        R::Future: Send + 'static,
+        R::Item: Send + 'static,
+        R::Error: Send + 'static,
+        G: FnOnce(BreakerError<R::Error>) -> T + Send + 'static,
+        T: IntoFuture<Item = R::Item, Error = R::Error> + 'static,
+        T::Future: Send + 'static,
+    {
+        Box::new(self.call(f).or_else(fallback))
+    }
+
+    // Determines whether a call may be made at `now`, moving an open breaker whose open duration
+    // has passed to half-open.
+    fn permit(&self, now: Instant) -> Option<Permit> {
+        let mut circuit = self.circuit();
+
+        if circuit.state == BreakerState::Open {
+            let opened_at = circuit.opened_at.unwrap_or(now);
+            if now < opened_at + self.open_duration {
+                drop(circuit);
+                self.reject();
+                return None;
+            }
+
+            self.transition(&mut circuit, BreakerState::HalfOpen, now);
+        }
+
+        let trial = circuit.state == BreakerState::HalfOpen;
+        if trial {
+            if circuit.trial {
+                drop(circuit);
+                self.reject();
+                return None;
+            }
+
+            circuit.trial = true;
+        }
+
+        Some(Permit {
+            breaker: self.clone(),
+            trial,
+            recorded: false,
+        })
+    }
+
+    fn record(&self, trial: bool, success: bool, now: Instant) {
+        let mut circuit = self.circuit();
+
+        if trial {
+            circuit.trial = false;
+            if circuit.state == BreakerState::HalfOpen {
+                let state = if success {
+                    BreakerState::Closed
+                } else {
+                    BreakerState::Open
+                };
+                self.transition(&mut circuit, state, now);
+            }
+            return;
+        }
+
+        // calls which were made before the breaker opened are not recorded
+        if circuit.state != BreakerState::Closed {
+            return;
+        }
+
+        circuit.outcomes.push_back(!success);
+        if circuit.outcomes.len() > self.window {
+            circuit.outcomes.pop_front();
+        }
+
+        let calls = circuit.outcomes.len();
+        let failures = circuit.outcomes.iter().filter(|failed| **failed).count();
+        if calls >= self.minimum_calls && failures as f64 >= self.failure_rate * calls as f64 {
+            self.transition(&mut circuit, BreakerState::Open, now);
+        }
+    }
+
+    fn transition(&self, circuit: &mut Circuit, state: BreakerState, now: Instant) {
+        match state {
+            BreakerState::Open => {
+                warn!(" circuit breaker {} opened", self.name);
+                circuit.opened_at = Some(now);
+            }
+            BreakerState::Closed => {
+                info!(" circuit breaker {} closed", self.name);
+                circuit.outcomes.clear();
+            }
+            BreakerState::HalfOpen => {
+                debug!(" circuit breaker {} is half-open", self.name);
+            }
+        }
+
+        circuit.state = state;
+        if let Some(ref metrics) = self.metrics {
+            metrics.observe_breaker_state(&self.name, state);
+        }
+    }
+
+    fn reject(&self) {
+        if let Some(ref metrics) = self.metrics {
+            metrics.observe_breaker_rejection(&self.name);
+        }
+    }
+
+    fn circuit(&self) -> MutexGuard<Circuit> {
+        match self.circuit.lock() {
+            Ok(circuit) => circuit,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
+
+impl StateData for CircuitBreaker {}
+
+// Permission to make a call through the breaker. A trial call which is dropped without being
+// recorded allows another trial to be made.
+struct Permit {
+    breaker: CircuitBreaker,
+    trial: bool,
+    recorded: bool,
+}
+
+impl Permit {
+    fn record(mut self, success: bool, now: Instant) {
+        self.recorded = true;
+        self.breaker.record(self.trial, success, now);
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if self.trial && !self.recorded {
+            self.breaker.circuit().trial = false;
+        }
+    }
+}
+
+/// The error of a call made through a `CircuitBreaker`.
+#[derive(Debug)]
+pub enum BreakerError<E> {
+    /// The breaker was open, so the call was not made.
+    Open,
+    /// The call was made, and failed with the given error.
+    Failed(E),
+}
+
+impl<E> BreakerError<E> {
+    /// Determines whether the call was rejected by an open breaker.
+    pub fn is_open(&self) -> bool {
+        match *self {
+            BreakerError::Open => true,
+            BreakerError::Failed(_) => false,
+        }
+    }
+}
+
+impl<E> Display for BreakerError<E>
+where
+    E: Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BreakerError::Open => write!(f, "circuit breaker is open"),
+            BreakerError::Failed(ref e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl<E> Error for BreakerError<E>
+where
+    E: Error,
+{
+    fn description(&self) -> &str {
+        match *self {
+            BreakerError::Open => "circuit breaker is open",
+            BreakerError::Failed(ref e) => e.description(),
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            BreakerError::Open => None,
+            BreakerError::Failed(ref e) => Some(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(breaker: &CircuitBreaker, success: bool, now: Instant) {
+        breaker
+            .permit(now)
+            .expect("call was rejected")
+            .record(success, now);
+    }
+
+    #[test]
+    fn opens_at_failure_rate() {
+        let start = Instant::now();
+        let later = |secs| start + Duration::from_secs(secs);
+        let metrics = Metrics::new();
+        let breaker = CircuitBreaker::new("db")
+            .with_window(4)
+            .with_minimum_calls(3)
+            .with_failure_rate(0.5)
+            .with_open_duration(Duration::from_secs(10))
+            .with_metrics(metrics.clone());
+
+        // too few calls to open, however many failed
+        record(&breaker, false, start);
+        record(&breaker, true, start);
+        assert_eq!(breaker.state(), BreakerState::Closed);
+
+        // the first failure leaves the window, so 1 of the last 4 calls failed
+        record(&breaker, true, start);
+        record(&breaker, true, start);
+        record(&breaker, false, start);
+        assert_eq!(breaker.state(), BreakerState::Closed);
+
+        record(&breaker, false, later(1));
+        assert_eq!(breaker.state(), BreakerState::Open);
+
+        assert!(breaker.permit(later(10)).is_none());
+        let out = metrics.render();
+        assert!(out.contains("gotham_circuit_breaker_state{breaker=\"db\",state=\"open\"} 1\n"));
+        assert!(out.contains("gotham_circuit_breaker_rejected_total{breaker=\"db\"} 1\n"));
+
+        // a single trial is made once the open duration has passed
+        let trial = breaker.permit(later(11)).expect("trial was rejected");
+        assert_eq!(breaker.state(), BreakerState::HalfOpen);
+        assert!(breaker.permit(later(11)).is_none());
+        trial.record(false, later(12));
+        assert_eq!(breaker.state(), BreakerState::Open);
+        assert!(breaker.permit(later(21)).is_none());
+
+        // a trial which is abandoned allows another to be made
+        drop(breaker.permit(later(22)));
+        assert_eq!(breaker.state(), BreakerState::HalfOpen);
+        record(&breaker, true, later(22));
+        assert_eq!(breaker.state(), BreakerState::Closed);
+
+        // failures from before the breaker closed are forgotten
+        record(&breaker, false, later(23));
+        assert_eq!(breaker.state(), BreakerState::Closed);
+    }
+
+    #[test]
+    fn calls_through_breaker() {
+        let breaker = CircuitBreaker::new("api")
+            .with_window(1)
+            .with_failure_rate(1.0);
+
+        let called = breaker.call(|| future::ok::<_, ()>(1)).wait();
+        assert_eq!(called.unwrap(), 1);
+
+        match breaker.call(|| future::err::<(), _>("down")).wait() {
+            Err(BreakerError::Failed("down")) => {}
+            _ => panic!("call did not fail"),
+        }
+        assert_eq!(breaker.state(), BreakerState::Open);
+
+        let rejected = breaker.call(|| -> Result<(), ()> { panic!("call was made") });
+        assert!(rejected.wait().unwrap_err().is_open());
+
+        let fallback = breaker.call_with_fallback(
+            || -> Result<u32, ()> { panic!("call was made") },
+            |e| {
+                assert!(e.is_open());
+                Ok(2)
+            },
+        );
+        assert_eq!(fallback.wait().unwrap(), 2);
+    }
+}
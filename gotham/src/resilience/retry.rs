@@ -0,0 +1,320 @@
+//! Defines `Retry`, which repeats a failed call after an exponential backoff.
+
+use futures::future::{self, Either, Loop};
+use futures::{Future, IntoFuture};
+use rand;
+use std::cmp;
+use std::time::{Duration, Instant};
+use tokio::timer::Delay;
+
+use middleware::timeout::Deadline;
+use state::{FromState, State};
+
+/// Retries a call which failed, waiting for a backoff before each further attempt.
+///
+/// The backoff starts at the initial backoff given to `with_backoff`, and is multiplied by
+/// `with_multiplier` after each attempt, up to the maximum backoff. With jitter, which is enabled
+/// by default, each wait is chosen at random between half and all of the backoff, so that clients
+/// which failed together don't all retry together.
+///
+/// The attempts can be bounded by a deadline, usually the `Deadline` of the request given to
+/// `with_deadline_from`: no further attempt is made when the backoff would end after the
+/// deadline, and the last error is returned instead. The attempts themselves aren't bounded by
+/// the deadline, which can be done with `Deadline::bound`.
+///
+/// By default, a call is attempted 3 times, with a backoff starting at 50ms which doubles up to
+/// 1 second. Only idempotent calls, such as reads, should be retried.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate futures;
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// #
+/// # use std::io;
+/// # use std::sync::Arc;
+/// # use std::sync::atomic::{AtomicUsize, Ordering};
+/// # use std::time::Duration;
+/// # use futures::{future, Future};
+/// # use hyper::{Body, Response, StatusCode};
+/// # use gotham::handler::{HandlerFuture, IntoHandlerError};
+/// # use gotham::resilience::Retry;
+/// # use gotham::state::State;
+/// # use gotham::test::TestServer;
+/// #
+/// fn read_profile(attempts: &AtomicUsize) -> Result<String, io::Error> {
+///     // a read from another service, which fails the first time
+///     match attempts.fetch_add(1, Ordering::SeqCst) {
+///         0 => Err(io::Error::new(io::ErrorKind::Other, "unavailable")),
+///         _ => Ok("profile".to_owned()),
+///     }
+/// }
+///
+/// fn handler(state: State) -> Box<HandlerFuture> {
+///     let attempts = Arc::new(AtomicUsize::new(0));
+///     let read = Retry::new()
+///         .with_backoff(Duration::from_millis(10), Duration::from_millis(100))
+///         .with_deadline_from(&state)
+///         .run(move || read_profile(&attempts));
+///
+///     Box::new(read.then(move |result| match result {
+///         Ok(profile) => future::ok((state, Response::new(Body::from(profile)))),
+///         Err(e) => {
+///             let err = e.into_handler_error().with_status(StatusCode::BAD_GATEWAY);
+///             future::err((state, err))
+///         }
+///     }))
+/// }
+/// #
+/// # fn main() {
+/// #   let test_server = TestServer::new(|| Ok(handler)).unwrap();
+/// #   let response = test_server.client().get("http://example.com/").perform().unwrap();
+/// #   assert_eq!(response.read_utf8_body().unwrap(), "profile");
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Retry {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    multiplier: f64,
+    jitter: bool,
+    deadline: Option<Instant>,
+}
+
+impl Default for Retry {
+    fn default() -> Retry {
+        Retry {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(50),
+            max_backoff: Duration::from_secs(1),
+            multiplier: 2.0,
+            jitter: true,
+            deadline: None,
+        }
+    }
+}
+
+impl Retry {
+    /// Creates a `Retry` with the default attempts and backoff.
+    pub fn new() -> Retry {
+        Retry::default()
+    }
+
+    /// Makes at most `attempts` attempts at a call, including the first.
+    ///
+    /// # Panics
+    ///
+    /// If `attempts` is zero.
+    pub fn with_max_attempts(self, attempts: u32) -> Retry {
+        assert!(attempts > 0, "attempts must not be zero");
+
+        Retry {
+            max_attempts: attempts,
+            ..self
+        }
+    }
+
+    /// Waits for `initial` before the second attempt, and at most `max` before any attempt.
+    pub fn with_backoff(self, initial: Duration, max: Duration) -> Retry {
+        Retry {
+            initial_backoff: initial,
+            max_backoff: cmp::max(initial, max),
+            ..self
+        }
+    }
+
+    /// Multiplies the backoff by `multiplier` after each attempt.
+    ///
+    /// # Panics
+    ///
+    /// If `multiplier` is less than 1.
+    pub fn with_multiplier(self, multiplier: f64) -> Retry {
+        assert!(multiplier >= 1.0, "multiplier must be at least 1");
+
+        Retry { multiplier, ..self }
+    }
+
+    /// Enables or disables jitter. Without jitter, each wait is the whole backoff.
+    pub fn with_jitter(self, jitter: bool) -> Retry {
+        Retry { jitter, ..self }
+    }
+
+    /// Makes no further attempts which would start after `deadline`.
+    pub fn with_deadline(self, deadline: Deadline) -> Retry {
+        Retry {
+            deadline: Some(deadline.instant()),
+            ..self
+        }
+    }
+
+    /// Makes no further attempts which would start after the `Deadline` of the request in
+    /// `state`, if it has one.
+    pub fn with_deadline_from(self, state: &State) -> Retry {
+        match Deadline::try_borrow_from(state) {
+            Some(deadline) => self.with_deadline(*deadline),
+            None => self,
+        }
+    }
+
+    /// Makes a call, where `f` creates the future of each attempt, retrying it while it fails.
+    /// The future fails with the error of the last attempt.
+    pub fn run<F, R>(&self, f: F) -> Box<Future<Item = R::Item, Error = R::Error> + Send>
+    where
+        F: FnMut() -> R + Send + 'static,
+        R: IntoFuture,
+        R::Future: Send + 'static,
+        R::Item: Send + 'static,
+        R::Error: Send + 'static,
+    {
+        self.run_if(f, |_| true)
+    }
+
+    /// Makes a call as `run` does, but only retries it when `retryable` returns `true` for the
+    /// error, so that errors which won't be resolved by retrying, such as a missing record, are
+    /// returned immediately.
+    pub fn run_if<F, R, P>(
+        &self,
+        f: F,
+        retryable: P,
+    ) -> Box<Future<Item = R::Item, Error = R::Error> + Send>
+    where
+        F: FnMut() -> R + Send + 'static,
+        R: IntoFuture,
+        R::Future: Send + 'static,
+        R::Item: Send + 'static,
+        R::Error: Send + 'static,
+        P: Fn(&R::Error) -> bool + Send + 'static,
+    {
+        let retry = self.clone();
+
+        Box::new(future::loop_fn(
+            (f, retryable, 1),
+            move |(mut f, retryable, attempt)| {
+                let retry = retry.clone();
+
+                f().into_future().then(move |result| {
+                    let e = match result {
+                        Ok(item) => return Either::A(future::ok(Loop::Break(item))),
+                        Err(e) => e,
+                    };
+
+                    let backoff = if retryable(&e) {
+                        retry.backoff(attempt, Instant::now())
+                    } else {
+                        None
+                    };
+
+                    match backoff {
+                        Some(backoff) => {
+                            let delay = Delay::new(Instant::now() + backoff);
+                            Either::B(delay.then(move |delayed| match delayed {
+                                Ok(()) => Ok(Loop::Continue((f, retryable, attempt + 1))),
+                                Err(_) => Err(e),
+                            }))
+                        }
+                        None => Either::A(future::err(e)),
+                    }
+                })
+            },
+        ))
+    }
+
+    // The time to wait after the given attempt failed at `now`, or `None` if no further attempt
+    // should be made.
+    fn backoff(&self, attempt: u32, now: Instant) -> Option<Duration> {
+        if attempt >= self.max_attempts {
+            return None;
+        }
+
+        let max = seconds(self.max_backoff);
+        let exponent = cmp::min(attempt - 1, i32::max_value() as u32) as i32;
+        let mut backoff = (seconds(self.initial_backoff) * self.multiplier.powi(exponent)).min(max);
+        if self.jitter {
+            backoff *= 0.5 + rand::random::<f64>() * 0.5;
+        }
+
+        let backoff = from_seconds(backoff);
+        match self.deadline {
+            Some(deadline) if now + backoff >= deadline => None,
+            _ => Some(backoff),
+        }
+    }
+}
+
+fn seconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + f64::from(duration.subsec_nanos()) / 1e9
+}
+
+fn from_seconds(seconds: f64) -> Duration {
+    let nanos = (seconds * 1e9).round() as u64;
+    Duration::new(nanos / 1_000_000_000, (nanos % 1_000_000_000) as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tokio::runtime::Runtime;
+
+    fn millis(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn backs_off_exponentially() {
+        let now = Instant::now();
+        let retry = Retry::new()
+            .with_max_attempts(5)
+            .with_backoff(millis(100), millis(300))
+            .with_jitter(false);
+
+        assert_eq!(retry.backoff(1, now), Some(millis(100)));
+        assert_eq!(retry.backoff(2, now), Some(millis(200)));
+        assert_eq!(retry.backoff(3, now), Some(millis(300)));
+        assert_eq!(retry.backoff(4, now), Some(millis(300)));
+        assert_eq!(retry.backoff(5, now), None);
+
+        // no attempt is made which would start after the deadline
+        let retry = retry.with_deadline(Deadline::new(now + millis(250)));
+        assert_eq!(retry.backoff(2, now), Some(millis(200)));
+        assert_eq!(retry.backoff(3, now), None);
+
+        let retry = Retry::new().with_backoff(millis(100), millis(100));
+        for _ in 0..20 {
+            let backoff = retry.backoff(1, now).unwrap();
+            assert!(backoff >= millis(50) && backoff <= millis(100));
+        }
+    }
+
+    #[test]
+    fn retries_failed_calls() {
+        let mut runtime = Runtime::new().unwrap();
+        let retry = Retry::new().with_backoff(millis(1), millis(10));
+
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let counted = attempts.clone();
+        let call = retry.run(move || match counted.fetch_add(1, Ordering::SeqCst) {
+            0 | 1 => Err("unavailable"),
+            n => Ok(n),
+        });
+        assert_eq!(runtime.block_on(call), Ok(2));
+
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let counted = attempts.clone();
+        let call = retry.run(move || Err::<(), _>(counted.fetch_add(1, Ordering::SeqCst)));
+        assert_eq!(runtime.block_on(call), Err(2));
+
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let counted = attempts.clone();
+        let call = retry.run_if(
+            move || Err::<(), _>(counted.fetch_add(1, Ordering::SeqCst)),
+            |_| false,
+        );
+        assert_eq!(runtime.block_on(call), Err(0));
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+}
@@ -101,14 +101,14 @@ where
     /// #   let test_server = TestServer::new(router()).unwrap();
     /// #
     /// #   let response = test_server.client()
-    /// #       .get("https://example.com/resource/path")
+    /// #       .get("http://example.com/resource/path")
     /// #       .with_header(ACCEPT, mime::APPLICATION_JSON.to_string().parse().unwrap())
     /// #       .perform()
     /// #       .unwrap();
     /// #   assert_eq!(response.status(), StatusCode::ACCEPTED);
     /// #
     /// #   let response = test_server.client()
-    /// #       .get("https://example.com/resource/path")
+    /// #       .get("http://example.com/resource/path")
     /// #       .with_header(ACCEPT, mime::TEXT_PLAIN.to_string().parse().unwrap())
     /// #       .perform()
     /// #       .unwrap();
@@ -175,7 +175,7 @@ where
     /// # fn main() {
     /// #   let test_server = TestServer::new(router()).unwrap();
     /// #   let response = test_server.client()
-    /// #       .get("https://example.com/resource/42")
+    /// #       .get("http://example.com/resource/42")
     /// #       .perform()
     /// #       .unwrap();
     /// #   assert_eq!(response.status(), StatusCode::ACCEPTED);
@@ -240,7 +240,7 @@ where
     /// # fn main() {
     /// #   let test_server = TestServer::new(router()).unwrap();
     /// #   let response = test_server.client()
-    /// #       .get("https://example.com/resource?val=test_val")
+    /// #       .get("http://example.com/resource?val=test_val")
     /// #       .perform()
     /// #       .unwrap();
     /// #   assert_eq!(response.status(), StatusCode::ACCEPTED);
@@ -295,19 +295,19 @@ where
     /// #   let test_server = TestServer::new(router()).unwrap();
     /// #
     /// #   let response = test_server.client()
-    /// #       .get("https://example.com/resource")
+    /// #       .get("http://example.com/resource")
     /// #       .perform()
     /// #       .unwrap();
     /// #   assert_eq!(response.status(), StatusCode::ACCEPTED);
     /// #
     /// #   let response = test_server.client()
-    /// #       .head("https://example.com/resource")
+    /// #       .head("http://example.com/resource")
     /// #       .perform()
     /// #       .unwrap();
     /// #   assert_eq!(response.status(), StatusCode::ACCEPTED);
     /// #
     /// #   let response = test_server.client()
-    /// #       .post("https://example.com/resource", b"".to_vec(), mime::TEXT_PLAIN)
+    /// #       .post("http://example.com/resource", b"".to_vec(), mime::TEXT_PLAIN)
     /// #       .perform()
     /// #       .unwrap();
     /// #   assert_eq!(response.status(), StatusCode::ACCEPTED);
@@ -365,7 +365,7 @@ where
     /// # fn main() {
     /// #   let test_server = TestServer::new(router()).unwrap();
     /// #   let response = test_server.client()
-    /// #       .head("https://example.com/resource")
+    /// #       .head("http://example.com/resource")
     /// #       .perform()
     /// #       .unwrap();
     /// #   assert_eq!(response.status(), StatusCode::ACCEPTED);
@@ -409,13 +409,13 @@ where
     /// #   let test_server = TestServer::new(router()).unwrap();
     /// #
     /// #   let response = test_server.client()
-    /// #       .get("https://example.com/resource")
+    /// #       .get("http://example.com/resource")
     /// #       .perform()
     /// #       .unwrap();
     /// #   assert_eq!(response.status(), StatusCode::ACCEPTED);
     /// #
     /// #   let response = test_server.client()
-    /// #       .head("https://example.com/resource")
+    /// #       .head("http://example.com/resource")
     /// #       .perform()
     /// #       .unwrap();
     /// #   assert_eq!(response.status(), StatusCode::ACCEPTED);
@@ -458,7 +458,7 @@ where
     /// # fn main() {
     /// #   let test_server = TestServer::new(router()).unwrap();
     /// #   let response = test_server.client()
-    /// #       .get("https://example.com/resource")
+    /// #       .get("http://example.com/resource")
     /// #       .perform()
     /// #       .unwrap();
     /// #   assert_eq!(response.status(), StatusCode::ACCEPTED);
@@ -502,7 +502,7 @@ where
     /// # fn main() {
     /// #   let test_server = TestServer::new(router()).unwrap();
     /// #   let response = test_server.client()
-    /// #       .post("https://example.com/resource", b"".to_vec(), mime::TEXT_PLAIN)
+    /// #       .post("http://example.com/resource", b"".to_vec(), mime::TEXT_PLAIN)
     /// #       .perform()
     /// #       .unwrap();
     /// #   assert_eq!(response.status(), StatusCode::ACCEPTED);
@@ -546,7 +546,7 @@ where
     /// # fn main() {
     /// #   let test_server = TestServer::new(router()).unwrap();
     /// #   let response = test_server.client()
-    /// #       .put("https://example.com/resource", b"".to_vec(), mime::TEXT_PLAIN)
+    /// #       .put("http://example.com/resource", b"".to_vec(), mime::TEXT_PLAIN)
     /// #       .perform()
     /// #       .unwrap();
     /// #   assert_eq!(response.status(), StatusCode::ACCEPTED);
@@ -590,7 +590,7 @@ where
     /// # fn main() {
     /// #   let test_server = TestServer::new(router()).unwrap();
     /// #   let response = test_server.client()
-    /// #       .patch("https://example.com/resource", b"".to_vec(), mime::TEXT_PLAIN)
+    /// #       .patch("http://example.com/resource", b"".to_vec(), mime::TEXT_PLAIN)
     /// #       .perform()
     /// #       .unwrap();
     /// #   assert_eq!(response.status(), StatusCode::ACCEPTED);
@@ -633,7 +633,7 @@ where
     /// # fn main() {
     /// #   let test_server = TestServer::new(router()).unwrap();
     /// #   let response = test_server.client()
-    /// #       .delete("https://example.com/resource")
+    /// #       .delete("http://example.com/resource")
     /// #       .perform()
     /// #       .unwrap();
     /// #   assert_eq!(response.status(), StatusCode::ACCEPTED);
@@ -676,7 +676,7 @@ where
     /// # fn main() {
     /// #   let test_server = TestServer::new(router()).unwrap();
     /// #   let response = test_server.client()
-    /// #       .options("https://example.com/resource")
+    /// #       .options("http://example.com/resource")
     /// #       .perform()
     /// #       .unwrap();
     /// #   assert_eq!(response.status(), StatusCode::ACCEPTED);
@@ -1,13 +1,16 @@
 use std::marker::PhantomData;
 use std::panic::RefUnwindSafe;
 
-use hyper::Method;
+use hyper::{Method, StatusCode};
 
 use extractor::{NoopPathExtractor, NoopQueryStringExtractor};
+use health::HealthChecks;
 use pipeline::chain::PipelineHandleChain;
 use pipeline::set::PipelineSet;
+use router::builder::redirect::{RedirectHandler, RedirectParams};
 use router::builder::{
-    AssociatedRouteBuilder, DelegateRouteBuilder, RouterBuilder, ScopeBuilder, SingleRouteBuilder,
+    AssociatedRouteBuilder, DefineSingleRoute, DelegateRouteBuilder, RouterBuilder, ScopeBuilder,
+    SingleRouteBuilder,
 };
 use router::route::matcher::{
     AnyRouteMatcher, IntoRouteMatcher, MethodOnlyRouteMatcher, RouteMatcher,
@@ -43,6 +46,16 @@ pub type DefaultAssociatedRouteBuilder<'a, M, C, P> =
 /// Defines functions used by a builder to determine which request paths will be dispatched to a
 /// route. This trait is implemented by the top-level `RouterBuilder`, and also the `ScopedBuilder`
 /// created by `DrawRoutes::scope`.
+///
+/// Paths are made up of `/` separated segments, where each segment is one of:
+///
+/// * `name` - matched exactly against the request path segment;
+/// * `:name` - matches any single segment, captured as `name` for the `PathExtractor`;
+/// * `:name:regex` - as above, but the segment must match `regex`;
+/// * `*` or `*name` - a glob, which captures the remaining segments (percent-decoded) as `*` or
+///   `name` respectively. Requests with a `.` or `..` segment, or a segment containing an encoded
+///   `/`, within the glob are not matched;
+/// * `\:name` or `\*` - an escaped static segment which is matched literally.
 pub trait DrawRoutes<C, P>
 where
     C: PipelineHandleChain<P> + Copy + Send + Sync + 'static,
@@ -75,13 +88,13 @@ where
     /// #   let test_server = TestServer::new(router()).unwrap();
     /// #
     /// #   let response = test_server.client()
-    /// #       .get("https://example.com/request/path")
+    /// #       .get("http://example.com/request/path")
     /// #       .perform()
     /// #       .unwrap();
     /// #   assert_eq!(response.status(), StatusCode::ACCEPTED);
     /// #
     /// #   let response = test_server.client()
-    /// #       .head("https://example.com/request/path")
+    /// #       .head("http://example.com/request/path")
     /// #       .perform()
     /// #       .unwrap();
     /// #   assert_eq!(response.status(), StatusCode::ACCEPTED);
@@ -119,7 +132,7 @@ where
     /// # fn main() {
     /// #   let test_server = TestServer::new(router()).unwrap();
     /// #   let response = test_server.client()
-    /// #       .get("https://example.com/request/path")
+    /// #       .get("http://example.com/request/path")
     /// #       .perform()
     /// #       .unwrap();
     /// #   assert_eq!(response.status(), StatusCode::ACCEPTED);
@@ -156,7 +169,7 @@ where
     /// # fn main() {
     /// #   let test_server = TestServer::new(router()).unwrap();
     /// #   let response = test_server.client()
-    /// #       .head("https://example.com/request/path")
+    /// #       .head("http://example.com/request/path")
     /// #       .perform()
     /// #       .unwrap();
     /// #   assert_eq!(response.status(), StatusCode::ACCEPTED);
@@ -194,7 +207,7 @@ where
     /// # fn main() {
     /// #   let test_server = TestServer::new(router()).unwrap();
     /// #   let response = test_server.client()
-    /// #       .post("https://example.com/request/path", b"".to_vec(), mime::TEXT_PLAIN)
+    /// #       .post("http://example.com/request/path", b"".to_vec(), mime::TEXT_PLAIN)
     /// #       .perform()
     /// #       .unwrap();
     /// #   assert_eq!(response.status(), StatusCode::ACCEPTED);
@@ -232,7 +245,7 @@ where
     /// # fn main() {
     /// #   let test_server = TestServer::new(router()).unwrap();
     /// #   let response = test_server.client()
-    /// #       .put("https://example.com/request/path", b"".to_vec(), mime::TEXT_PLAIN)
+    /// #       .put("http://example.com/request/path", b"".to_vec(), mime::TEXT_PLAIN)
     /// #       .perform()
     /// #       .unwrap();
     /// #   assert_eq!(response.status(), StatusCode::ACCEPTED);
@@ -270,7 +283,7 @@ where
     /// # fn main() {
     /// #   let test_server = TestServer::new(router()).unwrap();
     /// #   let response = test_server.client()
-    /// #       .patch("https://example.com/request/path", b"".to_vec(), mime::TEXT_PLAIN)
+    /// #       .patch("http://example.com/request/path", b"".to_vec(), mime::TEXT_PLAIN)
     /// #       .perform()
     /// #       .unwrap();
     /// #   assert_eq!(response.status(), StatusCode::ACCEPTED);
@@ -307,7 +320,7 @@ where
     /// # fn main() {
     /// #   let test_server = TestServer::new(router()).unwrap();
     /// #   let response = test_server.client()
-    /// #       .delete("https://example.com/request/path")
+    /// #       .delete("http://example.com/request/path")
     /// #       .perform()
     /// #       .unwrap();
     /// #   assert_eq!(response.status(), StatusCode::ACCEPTED);
@@ -344,7 +357,7 @@ where
     /// # fn main() {
     /// #   let test_server = TestServer::new(router()).unwrap();
     /// #   let response = test_server.client()
-    /// #       .options("https://example.com/request/path")
+    /// #       .options("http://example.com/request/path")
     /// #       .perform()
     /// #       .unwrap();
     /// #   assert_eq!(response.status(), StatusCode::ACCEPTED);
@@ -387,13 +400,13 @@ where
     /// #   let test_server = TestServer::new(router()).unwrap();
     /// #
     /// #   let response = test_server.client()
-    /// #       .get("https://example.com/request/path")
+    /// #       .get("http://example.com/request/path")
     /// #       .perform()
     /// #       .unwrap();
     /// #   assert_eq!(response.status(), StatusCode::ACCEPTED);
     /// #
     /// #   let response = test_server.client()
-    /// #       .head("https://example.com/request/path")
+    /// #       .head("http://example.com/request/path")
     /// #       .perform()
     /// #       .unwrap();
     /// #   assert_eq!(response.status(), StatusCode::ACCEPTED);
@@ -429,14 +442,14 @@ where
     /// #   let test_server = TestServer::new(router()).unwrap();
     /// #
     /// #   let response = test_server.client()
-    /// #       .get("https://example.com/request/path")
+    /// #       .get("http://example.com/request/path")
     /// #       .with_header(ACCEPT, mime::APPLICATION_JSON.to_string().parse().unwrap())
     /// #       .perform()
     /// #       .unwrap();
     /// #   assert_eq!(response.status(), StatusCode::ACCEPTED);
     /// #
     /// #   let response = test_server.client()
-    /// #       .get("https://example.com/request/path")
+    /// #       .get("http://example.com/request/path")
     /// #       .with_header(ACCEPT, mime::TEXT_PLAIN.to_string().parse().unwrap())
     /// #       .perform()
     /// #       .unwrap();
@@ -445,7 +458,7 @@ where
     /// #   // No Accept type being provided is valid for the AcceptHeaderRouterMatcher
     /// #   // Proves the method is not considered
     /// #   let response = test_server.client()
-    /// #       .delete("https://example.com/request/path")
+    /// #       .delete("http://example.com/request/path")
     /// #       .perform()
     /// #       .unwrap();
     /// #   assert_eq!(response.status(), StatusCode::ACCEPTED);
@@ -506,7 +519,7 @@ where
     /// # fn main() {
     /// #   let test_server = TestServer::new(router()).unwrap();
     /// #   let response = test_server.client()
-    /// #       .get("https://example.com/api/list")
+    /// #       .get("http://example.com/api/list")
     /// #       .perform()
     /// #       .unwrap();
     /// #   assert_eq!(response.status(), StatusCode::ACCEPTED);
@@ -617,13 +630,13 @@ where
     /// #   let test_server = TestServer::new(router()).unwrap();
     /// #
     /// #   let response = test_server.client()
-    /// #       .get("https://example.com/")
+    /// #       .get("http://example.com/")
     /// #       .perform()
     /// #       .unwrap();
     /// #   assert_eq!(response.status(), StatusCode::ACCEPTED);
     /// #
     /// #   let response = test_server.client()
-    /// #       .get("https://example.com/resource/list")
+    /// #       .get("http://example.com/resource/list")
     /// #       .perform()
     /// #       .unwrap();
     /// #   assert_eq!(response.status(), StatusCode::ACCEPTED);
@@ -679,7 +692,7 @@ where
     /// # fn main() {
     /// #   let test_server = TestServer::new(router()).unwrap();
     /// #   let response = test_server.client()
-    /// #       .get("https://example.com/admin")
+    /// #       .get("http://example.com/admin")
     /// #       .perform()
     /// #       .unwrap();
     /// #   assert_eq!(response.status(), StatusCode::ACCEPTED);
@@ -691,6 +704,7 @@ where
 
         DelegateRouteBuilder {
             node_builder,
+            matcher: AnyRouteMatcher::new(),
             pipeline_chain: *pipeline_chain,
             pipelines: pipelines.clone(),
         }
@@ -755,13 +769,13 @@ where
     /// # fn main() {
     /// #   let test_server = TestServer::new(router()).unwrap();
     /// #   let response = test_server.client()
-    /// #       .get("https://example.com/")
+    /// #       .get("http://example.com/")
     /// #       .perform()
     /// #       .unwrap();
     /// #   assert_eq!(response.status(), StatusCode::ACCEPTED);
     /// #
     /// #   let response = test_server.client()
-    /// #       .get("https://example.com/api")
+    /// #       .get("http://example.com/api")
     /// #       .perform()
     /// #       .unwrap();
     /// #   assert_eq!(response.status(), StatusCode::ACCEPTED);
@@ -773,11 +787,109 @@ where
 
         DelegateRouteBuilder {
             node_builder,
+            matcher: AnyRouteMatcher::new(),
             pipeline_chain: (),
             pipelines: pipelines.clone(),
         }
     }
 
+    /// Creates a route which responds to requests for `path`, using any method, with a redirect to
+    /// `target`. Dynamic and glob segments captured by `path` can be used in `target` with the same
+    /// syntax, and are substituted into the `Location` header. The query string of the request is
+    /// retained, unless `target` has a query string of its own.
+    ///
+    /// `target` can be an absolute path, or a URL with a scheme and host.
+    ///
+    /// # Panics
+    ///
+    /// If `status` is not a `3xx` redirection status, or `target` uses a segment which is not
+    /// captured by `path`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # extern crate gotham;
+    /// # extern crate hyper;
+    /// #
+    /// # use hyper::StatusCode;
+    /// # use hyper::header::LOCATION;
+    /// # use gotham::router::Router;
+    /// # use gotham::router::builder::*;
+    /// # use gotham::test::TestServer;
+    /// #
+    /// # fn router() -> Router {
+    /// build_simple_router(|route| {
+    ///     route.redirect("/users/:id", "/people/:id", StatusCode::MOVED_PERMANENTLY);
+    ///     route.redirect("/docs/*path", "https://docs.example.com/*path", StatusCode::FOUND);
+    /// })
+    /// # }
+    /// #
+    /// # fn main() {
+    /// #   let test_server = TestServer::new(router()).unwrap();
+    /// #   let response = test_server.client()
+    /// #       .get("http://example.com/users/42?tab=posts")
+    /// #       .perform()
+    /// #       .unwrap();
+    /// #   assert_eq!(response.status(), StatusCode::MOVED_PERMANENTLY);
+    /// #   assert_eq!(response.headers().get(LOCATION).unwrap(), "/people/42?tab=posts");
+    /// #
+    /// #   let response = test_server.client()
+    /// #       .get("http://example.com/docs/guide/routing.html")
+    /// #       .perform()
+    /// #       .unwrap();
+    /// #   assert_eq!(response.status(), StatusCode::FOUND);
+    /// #   assert_eq!(
+    /// #       response.headers().get(LOCATION).unwrap(),
+    /// #       "https://docs.example.com/guide/routing.html"
+    /// #   );
+    /// # }
+    /// ```
+    fn redirect(&mut self, path: &str, target: &str, status: StatusCode) {
+        let handler = RedirectHandler::new(path, target, status);
+
+        self.request(AnyRouteMatcher::new(), path)
+            .with_path_extractor::<RedirectParams>()
+            .to_new_handler(handler);
+    }
+
+    /// Creates the `/healthz` and `/readyz` routes, which respond to `GET` and `HEAD` requests by
+    /// running the liveness and readiness checks respectively. See `HealthChecks` for details of
+    /// the responses.
+    ///
+    /// The routes are created in the current scope, so they are placed beneath any path prefix
+    /// and pass through the pipelines in use.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # extern crate gotham;
+    /// # extern crate hyper;
+    /// #
+    /// # use hyper::StatusCode;
+    /// # use gotham::health::HealthChecks;
+    /// # use gotham::router::Router;
+    /// # use gotham::router::builder::*;
+    /// # use gotham::test::TestServer;
+    /// #
+    /// # fn router() -> Router {
+    /// build_simple_router(|route| {
+    ///     route.health(HealthChecks::new().readiness("cache", || Ok(())));
+    /// })
+    /// # }
+    /// #
+    /// # fn main() {
+    /// #   let test_server = TestServer::new(router()).unwrap();
+    /// #   let response = test_server.client().get("http://example.com/readyz").perform().unwrap();
+    /// #   assert_eq!(response.status(), StatusCode::OK);
+    /// # }
+    /// ```
+    fn health(&mut self, checks: HealthChecks) {
+        self.get_or_head("/healthz")
+            .to_new_handler(checks.liveness_handler());
+        self.get_or_head("/readyz")
+            .to_new_handler(checks.readiness_handler());
+    }
+
     /// Begins associating routes with a fixed path in the tree. In this way, multiple routes can
     /// be quickly associated with a single location.
     ///
@@ -826,19 +938,19 @@ where
     /// # fn main() {
     /// #   let test_server = TestServer::new(router()).unwrap();
     /// #   let response = test_server.client()
-    /// #       .get("https://example.com/resource")
+    /// #       .get("http://example.com/resource")
     /// #       .perform()
     /// #       .unwrap();
     /// #   assert_eq!(response.status(), StatusCode::NO_CONTENT);
     /// #
     /// #   let response = test_server.client()
-    /// #       .patch("https://example.com/resource", b"".to_vec(), mime::TEXT_PLAIN)
+    /// #       .patch("http://example.com/resource", b"".to_vec(), mime::TEXT_PLAIN)
     /// #       .perform()
     /// #       .unwrap();
     /// #   assert_eq!(response.status(), StatusCode::CREATED);
     /// #
     /// #   let response = test_server.client()
-    /// #       .delete("https://example.com/resource")
+    /// #       .delete("http://example.com/resource")
     /// #       .perform()
     /// #       .unwrap();
     /// #   assert_eq!(response.status(), StatusCode::ACCEPTED);
@@ -899,6 +1011,7 @@ where
                     }
                 }
                 Some('*') if segment.len() == 1 => (segment, SegmentType::Glob),
+                Some('*') => (&segment[1..], SegmentType::Glob),
                 Some('\\') => (&segment[1..], SegmentType::Static),
                 _ => (segment, SegmentType::Static),
             };
@@ -1038,4 +1151,64 @@ mod tests {
 
         assert_eq!(response.status(), StatusCode::ACCEPTED);
     }
+
+    #[test]
+    fn nested_scopes_share_pipelines() {
+        let (chain, pipelines) = single_pipeline(new_pipeline().add(QuickExitMiddleware).build());
+
+        let router = build_router(chain, pipelines, |route| {
+            route.scope("/api", |route| {
+                route.scope("/v1", |route| {
+                    route.get("/users").to(test_handler);
+                });
+            });
+        });
+
+        let test_server = TestServer::new(router).unwrap();
+
+        let response = test_server
+            .client()
+            .get("http://localhost/api/v1/users")
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        let response = test_server
+            .client()
+            .get("http://localhost/api/users")
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn scoped_pipeline_chains_are_independent() {
+        let (chain, pipelines) = single_pipeline(new_pipeline().add(QuickExitMiddleware).build());
+
+        let router = build_router((), pipelines, |route| {
+            route.get("/healthz").to(test_handler);
+
+            route.scope("/admin", |route| {
+                route.with_pipeline_chain(chain, |route| {
+                    route.get("/").to(test_handler);
+                });
+            });
+        });
+
+        let test_server = TestServer::new(router).unwrap();
+
+        let response = test_server
+            .client()
+            .get("http://localhost/healthz")
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+
+        let response = test_server
+            .client()
+            .get("http://localhost/admin")
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
 }
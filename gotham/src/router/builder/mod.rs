@@ -3,21 +3,29 @@
 mod associated;
 mod draw;
 mod modify;
+mod redirect;
 mod single;
 
 use std::marker::PhantomData;
 use std::panic::RefUnwindSafe;
 
+use hyper::service::{NewService, Service};
 use hyper::{Body, StatusCode};
 
 use extractor::{NoopPathExtractor, NoopQueryStringExtractor, PathExtractor, QueryStringExtractor};
+use handler::proxy::ProxyHandler;
+use handler::service::ServiceHandler;
+use handler::{Handler, NewHandler};
+use middleware::cors::CorsMiddleware;
+use openapi::{OpenApi, OpenApiHandler, OPENAPI_PATH};
 use pipeline::chain::PipelineHandleChain;
 use pipeline::set::{finalize_pipeline_set, new_pipeline_set, PipelineSet};
 use router::response::extender::ResponseExtender;
 use router::response::finalizer::ResponseFinalizerBuilder;
-use router::route::dispatch::DispatcherImpl;
-use router::route::matcher::{AnyRouteMatcher, RouteMatcher};
+use router::route::dispatch::{Dispatcher, DispatcherImpl};
+use router::route::matcher::{AndRouteMatcher, AnyRouteMatcher, RouteMatcher};
 use router::route::{Delegation, Extractors, RouteImpl};
+use router::trailing_slash::TrailingSlash;
 use router::tree::node::Node;
 use router::tree::Tree;
 use router::Router;
@@ -68,7 +76,7 @@ pub use self::single::DefineSingleRoute;
 /// # fn main() {
 /// #   let test_server = TestServer::new(router()).unwrap();
 /// #   let response = test_server.client()
-/// #       .get("https://example.com/request/path")
+/// #       .get("http://example.com/request/path")
 /// #       .perform()
 /// #       .unwrap();
 /// #   assert_eq!(response.status(), StatusCode::ACCEPTED);
@@ -82,20 +90,40 @@ where
 {
     let mut tree = Tree::new();
 
-    let response_finalizer = {
+    let (response_finalizer, fallback, trailing_slash, cors, openapi) = {
         let mut builder = RouterBuilder {
             node_builder: tree.borrow_root_mut(),
             pipeline_chain,
             pipelines,
             response_finalizer_builder: ResponseFinalizerBuilder::internal_new(),
+            fallback: None,
+            trailing_slash: TrailingSlash::default(),
+            cors: None,
+            openapi: None,
         };
 
         f(&mut builder);
 
-        builder.response_finalizer_builder.finalize()
+        (
+            builder.response_finalizer_builder.finalize(),
+            builder.fallback,
+            builder.trailing_slash,
+            builder.cors,
+            builder.openapi,
+        )
     };
 
-    Router::internal_new(tree, response_finalizer)
+    let router = Router::internal_new(
+        tree,
+        response_finalizer,
+        fallback,
+        trailing_slash,
+        cors,
+    );
+    if let Some(openapi) = openapi {
+        openapi.publish(&router);
+    }
+    router
 }
 
 /// Builds a `Router` with **no** middleware using the provided closure. Routes are defined using
@@ -125,7 +153,7 @@ where
 /// # fn main() {
 /// #   let test_server = TestServer::new(router()).unwrap();
 /// #   let response = test_server.client()
-/// #       .get("https://example.com/request/path")
+/// #       .get("http://example.com/request/path")
 /// #       .perform()
 /// #       .unwrap();
 /// #   assert_eq!(response.status(), StatusCode::ACCEPTED);
@@ -152,6 +180,10 @@ where
     pipeline_chain: C,
     pipelines: PipelineSet<P>,
     response_finalizer_builder: ResponseFinalizerBuilder,
+    fallback: Option<Box<Dispatcher + Send + Sync>>,
+    trailing_slash: TrailingSlash,
+    cors: Option<CorsMiddleware>,
+    openapi: Option<OpenApiHandler>,
 }
 
 impl<'a, C, P> RouterBuilder<'a, C, P>
@@ -198,7 +230,7 @@ where
     /// # fn main() {
     /// #   let test_server = TestServer::new(router()).unwrap();
     /// #   let response = test_server.client()
-    /// #       .get("https://example.com/")
+    /// #       .get("http://example.com/")
     /// #       .perform()
     /// #       .unwrap();
     /// #   assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
@@ -216,6 +248,178 @@ where
         self.response_finalizer_builder
             .add(status_code, Box::new(extender))
     }
+
+    /// Sets the policy used for request paths which end with a `/`. Defaults to
+    /// `TrailingSlash::Merge`, which routes `/foo/` and `/foo` identically.
+    ///
+    /// ```rust
+    /// # extern crate gotham;
+    /// # extern crate hyper;
+    /// #
+    /// # use hyper::{Body, Response, StatusCode};
+    /// # use hyper::header::LOCATION;
+    /// # use gotham::state::State;
+    /// # use gotham::router::Router;
+    /// # use gotham::router::builder::*;
+    /// # use gotham::router::trailing_slash::TrailingSlash;
+    /// # use gotham::test::TestServer;
+    /// #
+    /// # fn my_handler(state: State) -> (State, Response<Body>) {
+    /// #   (state, Response::builder().status(StatusCode::ACCEPTED).body(Body::empty()).unwrap())
+    /// # }
+    /// #
+    /// fn router() -> Router {
+    ///     build_simple_router(|route| {
+    ///         route.trailing_slash(TrailingSlash::Redirect);
+    ///         route.get("/request/path").to(my_handler);
+    ///     })
+    /// }
+    /// #
+    /// # fn main() {
+    /// #   let test_server = TestServer::new(router()).unwrap();
+    /// #   let response = test_server.client()
+    /// #       .get("http://example.com/request/path/")
+    /// #       .perform()
+    /// #       .unwrap();
+    /// #   assert_eq!(response.status(), StatusCode::PERMANENT_REDIRECT);
+    /// #   assert_eq!(response.headers().get(LOCATION).unwrap(), "/request/path");
+    /// # }
+    /// ```
+    pub fn trailing_slash(&mut self, policy: TrailingSlash) {
+        self.trailing_slash = policy;
+    }
+
+    /// Answers CORS preflight requests for routes which don't accept `OPTIONS` requests using the
+    /// given `CorsMiddleware`, in place of the automatic `OPTIONS` response. A preflight request is
+    /// rejected with `403 Forbidden` when the requested method isn't accepted by any route for the
+    /// path, or isn't allowed by the `CorsMiddleware`.
+    ///
+    /// The actual requests are still dispatched via the pipeline chain, which should include the
+    /// same `CorsMiddleware` so that their responses carry the CORS headers.
+    ///
+    /// ```rust
+    /// # extern crate gotham;
+    /// # extern crate hyper;
+    /// #
+    /// # use hyper::{Body, Method, Response, StatusCode};
+    /// # use hyper::header::*;
+    /// # use gotham::middleware::cors::CorsMiddleware;
+    /// # use gotham::pipeline::new_pipeline;
+    /// # use gotham::pipeline::single::single_pipeline;
+    /// # use gotham::state::State;
+    /// # use gotham::router::Router;
+    /// # use gotham::router::builder::*;
+    /// # use gotham::test::TestServer;
+    /// #
+    /// # fn my_handler(state: State) -> (State, Response<Body>) {
+    /// #   (state, Response::new(Body::empty()))
+    /// # }
+    /// #
+    /// fn router() -> Router {
+    ///     let cors = CorsMiddleware::default().with_methods(vec![Method::GET, Method::DELETE]);
+    ///     let (chain, pipelines) = single_pipeline(new_pipeline().add(cors.clone()).build());
+    ///
+    ///     build_router(chain, pipelines, |route| {
+    ///         route.cors(cors);
+    ///         route.delete("/items/:id").to(my_handler);
+    ///     })
+    /// }
+    /// #
+    /// # fn main() {
+    /// #   let test_server = TestServer::new(router()).unwrap();
+    /// #   let response = test_server.client()
+    /// #       .options("http://example.com/items/1")
+    /// #       .with_header(ORIGIN, "https://app.example.org".parse().unwrap())
+    /// #       .with_header(ACCESS_CONTROL_REQUEST_METHOD, "DELETE".parse().unwrap())
+    /// #       .perform()
+    /// #       .unwrap();
+    /// #   assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    /// #   assert_eq!(response.headers()[ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+    /// #   assert_eq!(response.headers()[ACCESS_CONTROL_ALLOW_METHODS], "GET, DELETE");
+    /// # }
+    /// ```
+    pub fn cors(&mut self, cors: CorsMiddleware) {
+        self.cors = Some(cors);
+    }
+
+    /// Directs requests which do not match any route to the given `Handler`, instead of
+    /// responding with an empty `404 Not Found`. The `Handler` is dispatched via the pipeline
+    /// chain given to `build_router`, and is responsible for setting the response status.
+    ///
+    /// ```rust
+    /// # extern crate gotham;
+    /// # extern crate hyper;
+    /// # extern crate mime;
+    /// #
+    /// # use hyper::{Body, Response, StatusCode};
+    /// # use gotham::helpers::http::response::create_response;
+    /// # use gotham::state::State;
+    /// # use gotham::router::Router;
+    /// # use gotham::router::builder::*;
+    /// # use gotham::test::TestServer;
+    /// #
+    /// fn not_found(state: State) -> (State, Response<Body>) {
+    ///     let res = create_response(
+    ///         &state,
+    ///         StatusCode::NOT_FOUND,
+    ///         mime::TEXT_HTML,
+    ///         "<h1>Nothing to see here</h1>",
+    ///     );
+    ///     (state, res)
+    /// }
+    ///
+    /// fn router() -> Router {
+    ///     build_simple_router(|route| {
+    ///         route.fallback(not_found);
+    ///     })
+    /// }
+    /// #
+    /// # fn main() {
+    /// #   let test_server = TestServer::new(router()).unwrap();
+    /// #   let response = test_server.client()
+    /// #       .get("http://example.com/missing")
+    /// #       .perform()
+    /// #       .unwrap();
+    /// #   assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    /// #   assert_eq!(response.read_body().unwrap(), b"<h1>Nothing to see here</h1>");
+    /// # }
+    /// ```
+    pub fn fallback<H>(&mut self, handler: H)
+    where
+        H: Handler + RefUnwindSafe + Copy + Send + Sync + 'static,
+        P: RefUnwindSafe,
+    {
+        self.fallback_to_new_handler(move || Ok(handler))
+    }
+
+    /// Directs requests which do not match any route to the given `NewHandler`. See
+    /// `RouterBuilder::fallback` for details.
+    pub fn fallback_to_new_handler<NH>(&mut self, new_handler: NH)
+    where
+        NH: NewHandler + 'static,
+        P: RefUnwindSafe,
+    {
+        let dispatcher =
+            DispatcherImpl::new(new_handler, self.pipeline_chain, self.pipelines.clone());
+        self.fallback = Some(Box::new(dispatcher));
+    }
+
+    /// Serves an OpenAPI document describing the routes of this `Router` at `/openapi.json`. The
+    /// document is generated once the `Router` has been built, and requests for it are dispatched
+    /// via the pipeline chain given to `build_router`. See the `openapi` module for an example.
+    ///
+    /// # Panics
+    ///
+    /// When the `Router` is built, if `openapi` describes an operation which doesn't match any
+    /// route.
+    pub fn openapi(&mut self, openapi: OpenApi)
+    where
+        P: RefUnwindSafe,
+    {
+        let handler = openapi.handler();
+        self.get(OPENAPI_PATH).to_new_handler(handler.clone());
+        self.openapi = Some(handler);
+    }
 }
 
 /// A scoped builder, which is created by `DrawRoutes::scope` and passed to the provided closure.
@@ -232,28 +436,154 @@ where
 
 /// A delegated builder, which is created by `DrawRoutes::delegate` and returned. The `DrawRoutes`
 /// trait has documentation for using this type.
-pub struct DelegateRouteBuilder<'a, C, P>
+pub struct DelegateRouteBuilder<'a, C, P, M = AnyRouteMatcher>
 where
     C: PipelineHandleChain<P> + Copy + Send + Sync + 'static,
     P: Send + Sync + 'static,
+    M: RouteMatcher + Send + Sync + 'static,
 {
     node_builder: &'a mut Node,
+    matcher: M,
     pipeline_chain: C,
     pipelines: PipelineSet<P>,
 }
 
-type DelegatedRoute = RouteImpl<AnyRouteMatcher, NoopPathExtractor, NoopQueryStringExtractor>;
-
-impl<'a, C, P> DelegateRouteBuilder<'a, C, P>
+impl<'a, C, P, M> DelegateRouteBuilder<'a, C, P, M>
 where
     C: PipelineHandleChain<P> + Copy + Send + Sync + 'static,
     P: RefUnwindSafe + Send + Sync + 'static,
+    M: RouteMatcher + Send + Sync + 'static,
 {
+    /// Adds an additional `RouteMatcher` to the delegated route, which must also match before the
+    /// request is dispatched to the delegated `Router`. When several delegated routes share a
+    /// path, the first one which matches the request is used.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # extern crate gotham;
+    /// # extern crate hyper;
+    /// #
+    /// # use hyper::{Body, Response, StatusCode};
+    /// # use hyper::header::HOST;
+    /// # use gotham::router::Router;
+    /// # use gotham::router::builder::*;
+    /// # use gotham::router::route::matcher::HostRouteMatcher;
+    /// # use gotham::state::State;
+    /// # use gotham::test::TestServer;
+    /// #
+    /// # fn api_handler(state: State) -> (State, Response<Body>) {
+    /// #   (state, Response::builder().status(StatusCode::ACCEPTED).body(Body::empty()).unwrap())
+    /// # }
+    /// #
+    /// # fn www_handler(state: State) -> (State, Response<Body>) {
+    /// #   (state, Response::builder().status(StatusCode::OK).body(Body::empty()).unwrap())
+    /// # }
+    /// #
+    /// fn router() -> Router {
+    ///     let api_router = build_simple_router(|route| {
+    ///         route.get("/").to(api_handler);
+    ///     });
+    ///
+    ///     let www_router = build_simple_router(|route| {
+    ///         route.get("/").to(www_handler);
+    ///     });
+    ///
+    ///     build_simple_router(|route| {
+    ///         route
+    ///             .delegate("/")
+    ///             .add_route_matcher(HostRouteMatcher::new(vec!["api.example.com"]))
+    ///             .to_router(api_router);
+    ///
+    ///         route
+    ///             .delegate("/")
+    ///             .add_route_matcher(HostRouteMatcher::new(vec!["www.example.com"]))
+    ///             .to_router(www_router);
+    ///     })
+    /// }
+    /// #
+    /// # fn main() {
+    /// #   let test_server = TestServer::new(router()).unwrap();
+    /// #
+    /// #   let response = test_server.client()
+    /// #       .get("http://localhost/")
+    /// #       .with_header(HOST, "api.example.com".parse().unwrap())
+    /// #       .perform()
+    /// #       .unwrap();
+    /// #   assert_eq!(response.status(), StatusCode::ACCEPTED);
+    /// #
+    /// #   let response = test_server.client()
+    /// #       .get("http://localhost/")
+    /// #       .with_header(HOST, "www.example.com".parse().unwrap())
+    /// #       .perform()
+    /// #       .unwrap();
+    /// #   assert_eq!(response.status(), StatusCode::OK);
+    /// #
+    /// #   let response = test_server.client()
+    /// #       .get("http://localhost/")
+    /// #       .with_header(HOST, "example.org".parse().unwrap())
+    /// #       .perform()
+    /// #       .unwrap();
+    /// #   assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    /// # }
+    /// ```
+    pub fn add_route_matcher<NM>(
+        self,
+        matcher: NM,
+    ) -> DelegateRouteBuilder<'a, C, P, AndRouteMatcher<M, NM>>
+    where
+        NM: RouteMatcher + Send + Sync + 'static,
+    {
+        DelegateRouteBuilder {
+            node_builder: self.node_builder,
+            matcher: AndRouteMatcher::new(self.matcher, matcher),
+            pipeline_chain: self.pipeline_chain,
+            pipelines: self.pipelines,
+        }
+    }
+
+    /// Directs the delegated route to a Hyper `NewService`, such as an existing application which
+    /// is being migrated into Gotham. The delegated prefix is removed from the request path before
+    /// the request is forwarded. See `gotham::handler::service::ServiceHandler` for an example.
+    pub fn to_service<S>(self, new_service: S)
+    where
+        S: NewService<ReqBody = Body, ResBody = Body> + Send + Sync + RefUnwindSafe + 'static,
+        S::Future: Send + 'static,
+        S::Service: Send + 'static,
+        <S::Service as Service>::Future: Send + 'static,
+    {
+        let handler = ServiceHandler::new(new_service);
+        let dispatcher = DispatcherImpl::new(handler, self.pipeline_chain, self.pipelines);
+        let route: RouteImpl<M, NoopPathExtractor, NoopQueryStringExtractor> = RouteImpl::new(
+            self.matcher,
+            Box::new(dispatcher),
+            Extractors::new(),
+            Delegation::External,
+        );
+
+        self.node_builder.add_route(Box::new(route));
+    }
+
+    /// Directs the delegated route to a `ProxyHandler`, which forwards requests to an upstream
+    /// HTTP server. The delegated prefix is removed from the request path before the request is
+    /// forwarded. See `gotham::handler::proxy::ProxyHandler` for an example.
+    pub fn to_proxy(self, proxy: ProxyHandler) {
+        let dispatcher = DispatcherImpl::new(proxy, self.pipeline_chain, self.pipelines);
+        let route: RouteImpl<M, NoopPathExtractor, NoopQueryStringExtractor> = RouteImpl::new(
+            self.matcher,
+            Box::new(dispatcher),
+            Extractors::new(),
+            Delegation::External,
+        );
+
+        self.node_builder.add_route(Box::new(route));
+    }
+
     /// Directs the delegated route to the given `Router`.
     pub fn to_router(self, router: Router) {
         let dispatcher = DispatcherImpl::new(router, self.pipeline_chain, self.pipelines);
-        let route: DelegatedRoute = DelegatedRoute::new(
-            AnyRouteMatcher::new(),
+        let route: RouteImpl<M, NoopPathExtractor, NoopQueryStringExtractor> = RouteImpl::new(
+            self.matcher,
             Box::new(dispatcher),
             Extractors::new(),
             Delegation::External,
@@ -289,6 +619,17 @@ where
     PE: PathExtractor<Body> + Send + Sync + 'static,
     QSE: QueryStringExtractor<Body> + Send + Sync + 'static,
 {
+    /// Names the path of this route, so that it can be generated later using the `UrlFor` value
+    /// in `State`. See `gotham::router::url_for::UrlFor` for an example.
+    ///
+    /// # Panics
+    ///
+    /// When the `Router` is built, if the same name has been given to more than one path.
+    pub fn named(self, name: &str) -> Self {
+        self.node_builder.add_name(name);
+        self
+    }
+
     /// Coerces the type of the internal `PhantomData`, to replace an extractor by changing the
     /// type parameter without changing anything else.
     fn coerce<NPE, NQSE>(self) -> SingleRouteBuilder<'a, M, C, P, NPE, NQSE>
@@ -311,12 +652,16 @@ mod tests {
     use super::*;
 
     use futures::{Future, Stream};
+    use hyper::header::{ACCEPT, CONTENT_TYPE};
     use hyper::service::Service;
-    use hyper::{Body, Request, Response, StatusCode};
+    use hyper::{Body, Method, Request, Response, StatusCode};
+    use mime;
 
     use middleware::session::NewSessionMiddleware;
     use pipeline::new_pipeline;
     use router::response::extender::StaticResponseExtender;
+    use router::route::matcher::content_type::ContentTypeHeaderRouteMatcher;
+    use router::route::matcher::AcceptHeaderRouteMatcher;
     use service::GothamService;
     use state::{State, StateData};
 
@@ -332,6 +677,18 @@ mod tests {
         fn extend(_: &mut State, _: &mut Response<Body>) {}
     }
 
+    #[derive(Deserialize)]
+    struct GlobParams {
+        rest: Vec<String>,
+    }
+
+    impl StateData for GlobParams {}
+
+    impl StaticResponseExtender for GlobParams {
+        type ResBody = Body;
+        fn extend(_: &mut State, _: &mut Response<Body>) {}
+    }
+
     #[derive(Deserialize)]
     struct AddParams {
         x: u64,
@@ -384,6 +741,15 @@ mod tests {
             (state, response)
         }
 
+        pub fn named_glob(mut state: State) -> (State, Response<Body>) {
+            let params = state.take::<GlobParams>();
+            let response = Response::builder()
+                .status(StatusCode::OK)
+                .body(params.rest.join("|").into())
+                .unwrap();
+            (state, response)
+        }
+
         pub fn delegated(state: State) -> (State, Response<Body>) {
             let response = Response::builder()
                 .status(StatusCode::OK)
@@ -496,6 +862,11 @@ mod tests {
                 .with_query_string_extractor::<AddParams>()
                 .to(welcome::add);
 
+            route
+                .get("/files/*rest")
+                .with_path_extractor::<GlobParams>()
+                .to(welcome::named_glob);
+
             route.get(r"/literal/\:param/\*").to(welcome::literal);
 
             route.scope("/api", |route| {
@@ -539,6 +910,28 @@ mod tests {
         let response_bytes = response.into_body().concat2().wait().unwrap().to_vec();
         assert_eq!(&String::from_utf8(response_bytes).unwrap(), "Globbed");
 
+        let response = call(
+            Request::get("/files/some%20dir/file.txt")
+                .body(Body::empty())
+                .unwrap(),
+        );
+        assert_eq!(response.status(), StatusCode::OK);
+        let response_bytes = response.into_body().concat2().wait().unwrap().to_vec();
+        assert_eq!(
+            &String::from_utf8(response_bytes).unwrap(),
+            "some dir|file.txt"
+        );
+
+        for path in &[
+            "/files/some%20dir/..%2Ffile.txt",
+            "/files/some%20dir/../file.txt",
+            "/files/%2E%2E/file.txt",
+            "/files/./file.txt",
+        ] {
+            let response = call(Request::get(*path).body(Body::empty()).unwrap());
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        }
+
         let response = call(Request::get("/delegated/b").body(Body::empty()).unwrap());
         assert_eq!(response.status(), StatusCode::OK);
         let response_bytes = response.into_body().concat2().wait().unwrap().to_vec();
@@ -584,4 +977,164 @@ mod tests {
         let response_bytes = response.into_body().concat2().wait().unwrap().to_vec();
         assert_eq!(&response_bytes[..], b"It's a resource.");
     }
+
+    #[test]
+    fn extractor_failure_responds_with_bad_request() {
+        let router = build_simple_router(|route| {
+            route
+                .get("/add/:x/:y")
+                .with_path_extractor::<AddParams>()
+                .to(welcome::add);
+
+            route
+                .get("/add")
+                .with_query_string_extractor::<AddParams>()
+                .to(welcome::add);
+        });
+
+        let new_service = GothamService::new(router);
+
+        let call = move |req| {
+            let mut service = new_service.connect("127.0.0.1:10000".parse().unwrap());
+            service.call(req).wait().unwrap()
+        };
+
+        let response = call(Request::get("/add/16/71").body(Body::empty()).unwrap());
+        assert_eq!(response.status(), StatusCode::OK);
+        let response_bytes = response.into_body().concat2().wait().unwrap().to_vec();
+        assert_eq!(&String::from_utf8(response_bytes).unwrap(), "16 + 71 = 87");
+
+        let response = call(Request::get("/add/sixteen/71").body(Body::empty()).unwrap());
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = call(Request::get("/add?x=16&y=-1").body(Body::empty()).unwrap());
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = call(Request::get("/add?x=16").body(Body::empty()).unwrap());
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn media_type_route_selection() {
+        fn json(state: State) -> (State, Response<Body>) {
+            (state, Response::new(Body::from("json")))
+        }
+
+        fn html(state: State) -> (State, Response<Body>) {
+            (state, Response::new(Body::from("html")))
+        }
+
+        let router = build_simple_router(|route| {
+            route
+                .get("/resource")
+                .add_route_matcher(AcceptHeaderRouteMatcher::new(vec![mime::APPLICATION_JSON]))
+                .to(json);
+
+            route
+                .get("/resource")
+                .add_route_matcher(AcceptHeaderRouteMatcher::new(vec![mime::TEXT_HTML]))
+                .to(html);
+
+            route
+                .post("/resource")
+                .add_route_matcher(ContentTypeHeaderRouteMatcher::new(vec![
+                    mime::APPLICATION_JSON,
+                ]))
+                .to(json);
+        });
+
+        let new_service = GothamService::new(router);
+
+        let call = move |req| {
+            let mut service = new_service.connect("127.0.0.1:10000".parse().unwrap());
+            service.call(req).wait().unwrap()
+        };
+
+        let body =
+            |response: Response<Body>| response.into_body().concat2().wait().unwrap().to_vec();
+
+        let response = call(
+            Request::get("/resource")
+                .header(ACCEPT, "text/html;q=0.5, application/json;q=0.8")
+                .body(Body::empty())
+                .unwrap(),
+        );
+        assert_eq!(&body(response)[..], b"json");
+
+        let response = call(
+            Request::get("/resource")
+                .header(ACCEPT, "text/html, application/json;q=0.8")
+                .body(Body::empty())
+                .unwrap(),
+        );
+        assert_eq!(&body(response)[..], b"html");
+
+        let response = call(
+            Request::get("/resource")
+                .header(ACCEPT, "text/plain")
+                .body(Body::empty())
+                .unwrap(),
+        );
+        assert_eq!(response.status(), StatusCode::NOT_ACCEPTABLE);
+
+        let response = call(
+            Request::post("/resource")
+                .header(CONTENT_TYPE, "application/json; charset=utf-8")
+                .body(Body::empty())
+                .unwrap(),
+        );
+        assert_eq!(&body(response)[..], b"json");
+
+        let response = call(
+            Request::post("/resource")
+                .header(CONTENT_TYPE, "text/plain")
+                .body(Body::empty())
+                .unwrap(),
+        );
+        assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+
+    #[test]
+    fn describes_routes() {
+        let pipelines = new_pipeline_set();
+        let (pipelines, default) =
+            pipelines.add(new_pipeline().add(NewSessionMiddleware::default()).build());
+        let pipelines = finalize_pipeline_set(pipelines);
+
+        let delegated_router = build_simple_router(|route| {
+            route.get("/").to(welcome::index);
+        });
+
+        let router = build_router((default, ()), pipelines, |route| {
+            route.get("/").to(welcome::index);
+            route
+                .request(vec![Method::GET, Method::POST], "/users/:id")
+                .named("user")
+                .to(welcome::index);
+            route
+                .delegate_without_pipelines("/legacy")
+                .to_router(delegated_router);
+        });
+
+        let routes = router.routes();
+        assert_eq!(routes.len(), 3);
+
+        let find = |path: &str| routes.iter().find(|r| r.path() == path).unwrap();
+
+        let root = find("/");
+        assert_eq!(root.methods(), Some(&[Method::GET][..]));
+        assert!(root.names().is_empty());
+        assert_eq!(root.pipelines(), 1);
+        assert!(!root.is_delegated());
+
+        let user = find("/users/:id");
+        assert_eq!(user.methods(), Some(&[Method::GET, Method::POST][..]));
+        assert_eq!(user.names(), &["user".to_owned()]);
+        assert_eq!(user.pipelines(), 1);
+
+        let legacy = find("/legacy");
+        assert_eq!(legacy.methods(), None);
+        assert_eq!(legacy.pipelines(), 0);
+        assert!(legacy.is_delegated());
+    }
 }
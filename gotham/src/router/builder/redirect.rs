@@ -0,0 +1,196 @@
+//! Defines the `Handler` used for routes created by `DrawRoutes::redirect`.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use hyper::{Body, Response, StatusCode, Uri};
+
+use error::Result;
+use handler::{Handler, HandlerFuture, IntoHandlerFuture, NewHandler};
+use helpers::http::response::create_redirect;
+use router::response::extender::StaticResponseExtender;
+use router::url_for::fill_template;
+use state::{FromState, State, StateData};
+
+/// Captures every dynamic and glob segment of the request path, keyed by name.
+#[derive(Deserialize)]
+pub(super) struct RedirectParams(HashMap<String, Vec<String>>);
+
+impl StateData for RedirectParams {}
+
+impl StaticResponseExtender for RedirectParams {
+    type ResBody = Body;
+    fn extend(_: &mut State, _: &mut Response<Body>) {}
+}
+
+/// Responds with a redirect to `target`, having substituted the captured path segments. The query
+/// string of the request is retained, unless `target` includes a query string.
+#[derive(Clone)]
+pub(super) struct RedirectHandler {
+    // The scheme and authority of `target`, if it is an absolute URL.
+    origin: Arc<String>,
+    // The path template of `target`, and its query string if there is one.
+    template: Arc<String>,
+    query: Option<Arc<String>>,
+    status: StatusCode,
+}
+
+impl RedirectHandler {
+    /// Creates a `RedirectHandler` for a route at `path`.
+    ///
+    /// # Panics
+    ///
+    /// If `status` is not a redirection, or `target` refers to a segment which is not captured by
+    /// `path`.
+    pub(super) fn new(path: &str, target: &str, status: StatusCode) -> RedirectHandler {
+        assert!(
+            status.is_redirection(),
+            "redirect status `{}` is not a redirection",
+            status
+        );
+
+        let (origin, rest) = match target.find("://") {
+            Some(n) => match target[n + 3..].find('/') {
+                Some(m) => target.split_at(n + 3 + m),
+                None => (target, "/"),
+            },
+            None => ("", target),
+        };
+
+        let (template, query) = match rest.find('?') {
+            Some(n) => (&rest[..n], Some(Arc::new(rest[n + 1..].to_owned()))),
+            None => (rest, None),
+        };
+
+        assert!(
+            template.starts_with('/'),
+            "redirect target `{}` must be an absolute path or URL",
+            target
+        );
+
+        let captured = captured_names(path);
+        for name in captured_names(template) {
+            assert!(
+                captured.contains(&name),
+                "redirect target `{}` refers to `{}`, which is not captured by `{}`",
+                target,
+                name,
+                path
+            );
+        }
+
+        RedirectHandler {
+            origin: Arc::new(origin.to_owned()),
+            template: Arc::new(template.to_owned()),
+            query,
+            status,
+        }
+    }
+
+    fn location(&self, state: &State) -> String {
+        let RedirectParams(ref captured) = *RedirectParams::borrow_from(state);
+        // each captured segment is kept separate, so a decoded `/` within one is encoded again
+        let params: Vec<(&str, Vec<&str>)> = captured
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.iter().map(|s| s.as_str()).collect()))
+            .collect();
+
+        // all names in the template were checked against the route path in `new`
+        let mut location = self.origin.to_string();
+        location.push_str(&fill_template(&self.template, &params).unwrap_or_default());
+
+        let query = match self.query {
+            Some(ref query) => Some(query.as_str()),
+            None => Uri::borrow_from(state).query(),
+        };
+
+        if let Some(query) = query {
+            location.push('?');
+            location.push_str(query);
+        }
+
+        location
+    }
+}
+
+impl NewHandler for RedirectHandler {
+    type Instance = Self;
+
+    fn new_handler(&self) -> Result<Self::Instance> {
+        Ok(self.clone())
+    }
+}
+
+impl Handler for RedirectHandler {
+    fn handle(self, state: State) -> Box<HandlerFuture> {
+        let res = create_redirect(&state, self.status, self.location(&state));
+        (state, res).into_handler_future()
+    }
+}
+
+// Collects the names of the dynamic and glob segments in a path template.
+fn captured_names(path: &str) -> Vec<String> {
+    path.split('/')
+        .filter_map(|segment| match segment.chars().next() {
+            Some(':') => Some(segment[1..].split(':').next().unwrap_or("").to_owned()),
+            Some('*') if segment.len() == 1 => Some("*".to_owned()),
+            Some('*') => Some(segment[1..].to_owned()),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collects_captured_names() {
+        assert_eq!(
+            captured_names(r"/users/:id:[0-9]+/files/*path/\:literal"),
+            vec!["id".to_owned(), "path".to_owned()]
+        );
+        assert_eq!(captured_names("/assets/*"), vec!["*".to_owned()]);
+    }
+
+    #[test]
+    fn splits_absolute_targets() {
+        let handler =
+            RedirectHandler::new("/old", "https://example.com/new?a=b", StatusCode::FOUND);
+        assert_eq!(*handler.origin, "https://example.com");
+        assert_eq!(*handler.template, "/new");
+        assert_eq!(handler.query.as_ref().map(|q| q.as_str()), Some("a=b"));
+
+        let handler = RedirectHandler::new("/old", "https://example.com", StatusCode::FOUND);
+        assert_eq!(*handler.template, "/");
+    }
+
+    #[test]
+    fn encodes_slashes_within_captured_segments() {
+        let handler = RedirectHandler::new("/blog/*path", "/*path", StatusCode::FOUND);
+
+        let mut captured = HashMap::new();
+        captured.insert(
+            "path".to_owned(),
+            vec!["/evil.com".to_owned(), "a".to_owned()],
+        );
+
+        let mut state = State::new();
+        state.put(RedirectParams(captured));
+        state.put("/blog/%2Fevil.com/a".parse::<Uri>().unwrap());
+
+        assert_eq!(handler.location(&state), "/%2Fevil.com/a");
+    }
+
+    #[test]
+    #[should_panic(expected = "refers to `name`, which is not captured by `/users/:id`")]
+    fn rejects_unknown_segments() {
+        RedirectHandler::new("/users/:id", "/people/:name", StatusCode::FOUND);
+    }
+
+    #[test]
+    #[should_panic(expected = "is not a redirection")]
+    fn rejects_non_redirect_status() {
+        RedirectHandler::new("/old", "/new", StatusCode::OK);
+    }
+}
@@ -1,9 +1,13 @@
 use std::panic::RefUnwindSafe;
 
+use futures::Future;
+
 use extractor::{PathExtractor, QueryStringExtractor};
-use handler::assets::{DirHandler, FileHandler, FileOptions, FilePathExtractor};
-use handler::{Handler, NewHandler};
-use hyper::Body;
+use handler::assets::{
+    AssetHandler, AssetManifest, DirHandler, FileHandler, FileOptions, FilePathExtractor,
+};
+use handler::{Handler, HandlerError, HandlerFuture, NewHandler};
+use hyper::{Body, Response};
 use pipeline::chain::PipelineHandleChain;
 use router::builder::{
     ExtendRouteMatcher, ReplacePathExtractor, ReplaceQueryStringExtractor, SingleRouteBuilder,
@@ -11,6 +15,7 @@ use router::builder::{
 use router::route::dispatch::DispatcherImpl;
 use router::route::matcher::RouteMatcher;
 use router::route::{Delegation, Extractors, RouteImpl};
+use state::State;
 
 /// Describes the API for defining a single route, after determining which request paths will be
 /// dispatched here. The API here uses chained function calls to build and add the route into the
@@ -50,7 +55,7 @@ use router::route::{Delegation, Extractors, RouteImpl};
 /// # fn main() {
 /// #   let test_server = TestServer::new(router()).unwrap();
 /// #   let response = test_server.client()
-/// #       .get("https://example.com/request/path")
+/// #       .get("http://example.com/request/path")
 /// #       .perform()
 /// #       .unwrap();
 /// #   assert_eq!(response.status(), StatusCode::ACCEPTED);
@@ -95,7 +100,7 @@ pub trait DefineSingleRoute {
     /// # fn main() {
     /// #   let test_server = TestServer::new(router()).unwrap();
     /// #   let response = test_server.client()
-    /// #       .get("https://example.com/request/path")
+    /// #       .get("http://example.com/request/path")
     /// #       .perform()
     /// #       .unwrap();
     /// #   assert_eq!(response.status(), StatusCode::ACCEPTED);
@@ -105,6 +110,59 @@ pub trait DefineSingleRoute {
     where
         H: Handler + RefUnwindSafe + Copy + Send + Sync + 'static;
 
+    /// Directs the route to the given function, which returns any `Future` resolving to the
+    /// `State` and a response. This avoids boxing the future in every handler, or defining a
+    /// `NewHandler` type for handlers which return `impl Future`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # extern crate futures;
+    /// # extern crate gotham;
+    /// # extern crate hyper;
+    /// #
+    /// # use futures::{future, Future};
+    /// # use hyper::{Body, Response, StatusCode};
+    /// # use gotham::handler::HandlerError;
+    /// # use gotham::helpers::http::response::create_empty_response;
+    /// # use gotham::state::State;
+    /// # use gotham::router::Router;
+    /// # use gotham::router::builder::*;
+    /// # use gotham::test::TestServer;
+    /// #
+    /// fn my_handler(
+    ///     state: State,
+    /// ) -> impl Future<Item = (State, Response<Body>), Error = (State, HandlerError)> {
+    ///     let res = create_empty_response(&state, StatusCode::ACCEPTED);
+    ///     future::ok((state, res))
+    /// }
+    /// #
+    /// # fn router() -> Router {
+    ///
+    /// build_simple_router(|route| {
+    ///     route.get("/request/path").to_async(my_handler);
+    /// })
+    /// #
+    /// # }
+    /// #
+    /// # fn main() {
+    /// #   let test_server = TestServer::new(router()).unwrap();
+    /// #   let response = test_server.client()
+    /// #       .get("http://example.com/request/path")
+    /// #       .perform()
+    /// #       .unwrap();
+    /// #   assert_eq!(response.status(), StatusCode::ACCEPTED);
+    /// # }
+    /// ```
+    fn to_async<H, F>(self, handler: H)
+    where
+        Self: Sized,
+        H: Fn(State) -> F + RefUnwindSafe + Copy + Send + Sync + 'static,
+        F: Future<Item = (State, Response<Body>), Error = (State, HandlerError)> + Send + 'static,
+    {
+        self.to(move |state: State| -> Box<HandlerFuture> { Box::new(handler(state)) })
+    }
+
     /// Directs the route to the given `NewHandler`. This gives more control over how `Handler`
     /// values are constructed.
     ///
@@ -159,7 +217,7 @@ pub trait DefineSingleRoute {
     /// # fn main() {
     /// #   let test_server = TestServer::new(router()).unwrap();
     /// #   let response = test_server.client()
-    /// #       .get("https://example.com/request/path")
+    /// #       .get("http://example.com/request/path")
     /// #       .perform()
     /// #       .unwrap();
     /// #   assert_eq!(response.status(), StatusCode::ACCEPTED);
@@ -201,7 +259,7 @@ pub trait DefineSingleRoute {
     /// # fn main() {
     /// #   let test_server = TestServer::new(router()).unwrap();
     /// #   let response = test_server.client()
-    /// #       .get("https://example.com/doc.html")
+    /// #       .get("http://example.com/doc.html")
     /// #       .perform()
     /// #       .unwrap();
     /// #   assert_eq!(response.status(), StatusCode::OK);
@@ -248,7 +306,7 @@ pub trait DefineSingleRoute {
     /// # fn main() {
     /// #   let test_server = TestServer::new(router()).unwrap();
     /// #   let response = test_server.client()
-    /// #       .get("https://example.com/")
+    /// #       .get("http://example.com/")
     /// #       .perform()
     /// #       .unwrap();
     /// #   assert_eq!(response.status(), StatusCode::OK);
@@ -262,6 +320,49 @@ pub trait DefineSingleRoute {
         self.to_new_handler(FileHandler::new(options));
     }
 
+    /// Directs the route to serve the assets of an `AssetManifest`, at both their fingerprinted
+    /// and original paths. The route must match a glob, such as `/assets/*`, below the mount
+    /// given to the `AssetManifest`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # extern crate gotham;
+    /// # extern crate hyper;
+    /// #
+    /// # use hyper::StatusCode;
+    /// # use gotham::handler::assets::AssetManifest;
+    /// # use gotham::router::Router;
+    /// # use gotham::router::builder::*;
+    /// # use gotham::test::TestServer;
+    /// #
+    /// # fn router(assets: AssetManifest) -> Router {
+    /// build_simple_router(|route| {
+    ///     route.get("/assets/*").to_assets(assets);
+    /// })
+    /// # }
+    /// #
+    /// # fn main() {
+    /// #   let assets = AssetManifest::new("/assets", "resources/test/assets").unwrap();
+    /// #   let url = assets.url("doc.html").unwrap();
+    /// #   let test_server = TestServer::new(router(assets)).unwrap();
+    /// #   let response = test_server.client()
+    /// #       .get(&format!("http://example.com{}", url))
+    /// #       .perform()
+    /// #       .unwrap();
+    /// #   assert_eq!(response.status(), StatusCode::OK);
+    /// # }
+    /// ```
+    fn to_assets(self, manifest: AssetManifest)
+    where
+        Self: Sized,
+        Self: ReplacePathExtractor<FilePathExtractor>,
+        Self::Output: DefineSingleRoute,
+    {
+        self.with_path_extractor::<FilePathExtractor>()
+            .to_new_handler(AssetHandler::new(manifest));
+    }
+
     /// Applies a `PathExtractor` type to the current route, to extract path parameters into
     /// `State` with the given type.
     ///
@@ -319,7 +420,7 @@ pub trait DefineSingleRoute {
     /// # fn main() {
     /// #   let test_server = TestServer::new(router()).unwrap();
     /// #   let response = test_server.client()
-    /// #       .get("https://example.com/hello/world")
+    /// #       .get("http://example.com/hello/world")
     /// #       .perform()
     /// #       .unwrap();
     /// #   assert_eq!(response.status(), StatusCode::ACCEPTED);
@@ -387,7 +488,7 @@ pub trait DefineSingleRoute {
     /// # fn main() {
     /// #   let test_server = TestServer::new(router()).unwrap();
     /// #   let response = test_server.client()
-    /// #       .get("https://example.com/request/path?id=42")
+    /// #       .get("http://example.com/request/path?id=42")
     /// #       .perform()
     /// #       .unwrap();
     /// #   assert_eq!(response.status(), StatusCode::ACCEPTED);
@@ -433,14 +534,14 @@ pub trait DefineSingleRoute {
     /// #   let test_server = TestServer::new(router()).unwrap();
     /// #
     /// #   let response = test_server.client()
-    /// #       .get("https://example.com/request/path")
+    /// #       .get("http://example.com/request/path")
     /// #       .with_header(ACCEPT, mime::APPLICATION_JSON.to_string().parse().unwrap())
     /// #       .perform()
     /// #       .unwrap();
     /// #   assert_eq!(response.status(), StatusCode::ACCEPTED);
     /// #
     /// #   let response = test_server.client()
-    /// #       .get("https://example.com/request/path")
+    /// #       .get("http://example.com/request/path")
     /// #       .with_header(ACCEPT, mime::TEXT_PLAIN.to_string().parse().unwrap())
     /// #       .perform()
     /// #       .unwrap();
@@ -0,0 +1,145 @@
+//! Defines `RouteDescription`, which describes a route registered with a `Router`, and
+//! `RouteTemplate`, which identifies the route matched by a request.
+
+use hyper::Method;
+
+use openapi::Schema;
+use state::StateData;
+
+/// Describes a single route registered with a `Router`. Values of this type are returned from
+/// `Router::routes`, and are intended for generating documentation or logging the routes which
+/// have been mounted.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// #
+/// # use hyper::{Body, Method, Response};
+/// # use gotham::router::builder::*;
+/// # use gotham::state::State;
+/// #
+/// # fn handler(state: State) -> (State, Response<Body>) {
+/// #   (state, Response::new(Body::empty()))
+/// # }
+/// #
+/// # fn main() {
+/// let router = build_simple_router(|route| {
+///     route.get("/").to(handler);
+///     route.post("/users/:id").named("user").to(handler);
+/// });
+///
+/// let routes = router.routes();
+/// assert_eq!(routes.len(), 2);
+///
+/// assert_eq!(routes[1].path(), "/users/:id");
+/// assert_eq!(routes[1].methods(), Some(&[Method::POST][..]));
+/// assert_eq!(routes[1].names(), &["user".to_owned()]);
+/// # }
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct RouteDescription {
+    path: String,
+    methods: Option<Vec<Method>>,
+    names: Vec<String>,
+    pipelines: usize,
+    delegated: bool,
+    path_parameters: Option<Schema>,
+    query_parameters: Option<Schema>,
+}
+
+impl RouteDescription {
+    pub(crate) fn new(
+        path: String,
+        methods: Option<Vec<Method>>,
+        names: Vec<String>,
+        pipelines: usize,
+        delegated: bool,
+        path_parameters: Option<Schema>,
+        query_parameters: Option<Schema>,
+    ) -> RouteDescription {
+        RouteDescription {
+            path,
+            methods,
+            names,
+            pipelines,
+            delegated,
+            path_parameters,
+            query_parameters,
+        }
+    }
+
+    /// The path template of the route, using the same syntax as the router builder.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// The request methods accepted by the route, or `None` if it accepts any method.
+    pub fn methods(&self) -> Option<&[Method]> {
+        self.methods.as_ref().map(|m| m.as_slice())
+    }
+
+    /// The names given to the path of this route via `SingleRouteBuilder::named`.
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// The number of pipelines which a request passes through before reaching the handler.
+    pub fn pipelines(&self) -> usize {
+        self.pipelines
+    }
+
+    /// Whether the route delegates requests to another `Router`. The routes of a delegated
+    /// `Router` are not included in the description of its parent.
+    pub fn is_delegated(&self) -> bool {
+        self.delegated
+    }
+
+    /// Describes the `PathExtractor` of the route, or `None` if it does not extract anything from
+    /// the path.
+    pub fn path_parameters(&self) -> Option<&Schema> {
+        self.path_parameters.as_ref()
+    }
+
+    /// Describes the `QueryStringExtractor` of the route, or `None` if it does not extract
+    /// anything from the query string.
+    pub fn query_parameters(&self) -> Option<&Schema> {
+        self.query_parameters.as_ref()
+    }
+}
+
+/// The path template of the route which matched the request, such as `/users/:id`, using the same
+/// syntax as the router builder, along with the name given to it via `SingleRouteBuilder::named`.
+/// A `RouteTemplate` is placed into `State` by the `Router` before dispatching to a route.
+///
+/// Unlike the request path, the template does not vary with the values of dynamic segments, so is
+/// suitable for grouping requests in logs and metrics. When a request is delegated to another
+/// `Router`, the template of the delegating route in the top-level `Router` is retained.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RouteTemplate {
+    template: String,
+    name: Option<String>,
+}
+
+impl StateData for RouteTemplate {}
+
+impl RouteTemplate {
+    pub(crate) fn new(template: &str, name: Option<&str>) -> RouteTemplate {
+        RouteTemplate {
+            template: template.to_owned(),
+            name: name.map(str::to_owned),
+        }
+    }
+
+    /// The path template of the matched route.
+    pub fn as_str(&self) -> &str {
+        &self.template
+    }
+
+    /// The name of the matched route, or `None` if it was not named. When a path has been given
+    /// more than one name, the first is used.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_ref().map(String::as_str)
+    }
+}
@@ -1,37 +1,69 @@
 //! Defines the Gotham `Router` and supporting types.
 
 pub mod builder;
+pub mod description;
 pub mod non_match;
 pub mod response;
 pub mod route;
+pub mod trailing_slash;
 pub mod tree;
+pub mod url_for;
 
 use std::sync::Arc;
 
 use futures::{future, Future};
-use hyper::header::ALLOW;
-use hyper::{Body, Response, StatusCode};
+use hyper::body::Payload;
+use hyper::header::{
+    HeaderMap, ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_REQUEST_METHOD, ALLOW, CONTENT_LENGTH,
+};
+use hyper::{Body, Method, Response, StatusCode};
 
 use error::*;
+use events;
 use handler::{Handler, HandlerFuture, IntoResponse, NewHandler};
 use helpers::http::request::path::RequestPathSegments;
 use helpers::http::response::create_empty_response;
+use middleware::cors::CorsMiddleware;
+use middleware::hook::on_complete;
+use router::description::{RouteDescription, RouteTemplate};
+use router::non_match::RouteNonMatch;
 use router::response::finalizer::ResponseFinalizer;
+use router::route::dispatch::Dispatcher;
 use router::route::{Delegation, Route};
+use router::trailing_slash::TrailingSlash;
+use router::tree::node::Node;
 use router::tree::segment::SegmentMapping;
 use router::tree::Tree;
-use state::{request_id, State};
+use router::url_for::UrlFor;
+use state::{FromState, State};
 
 struct RouterData {
     tree: Tree,
     response_finalizer: ResponseFinalizer,
+    fallback: Option<Box<Dispatcher + Send + Sync>>,
+    trailing_slash: TrailingSlash,
+    cors: Option<CorsMiddleware>,
+    url_for: UrlFor,
 }
 
 impl RouterData {
-    fn new(tree: Tree, response_finalizer: ResponseFinalizer) -> RouterData {
+    fn new(
+        mut tree: Tree,
+        response_finalizer: ResponseFinalizer,
+        fallback: Option<Box<Dispatcher + Send + Sync>>,
+        trailing_slash: TrailingSlash,
+            cors: Option<CorsMiddleware>,
+    ) -> RouterData {
+        let url_for = UrlFor::new(tree.route_names());
+        tree.assign_templates();
+
         RouterData {
             tree,
             response_finalizer,
+            fallback,
+            trailing_slash,
+            cors,
+            url_for,
         }
     }
 }
@@ -67,54 +99,80 @@ impl Handler for Router {
     /// Handles the `Request` by determining the correct `Route` from the internal `Tree`, storing
     /// any path related variables in `State` and dispatching to the associated `Handler`.
     fn handle(self, mut state: State) -> Box<HandlerFuture> {
-        trace!("[{}] starting", request_id(&state));
+        request_trace!(&state, "starting");
+
+        // a delegated `Router` retains the `UrlFor` of the top-level `Router`
+        if !state.has::<UrlFor>() {
+            state.put(self.data.url_for.clone());
+        }
+
+        if let Some(res) = self.data.trailing_slash.intercept(&state) {
+            return self.finalize_response(Box::new(future::ok((state, res))));
+        }
 
+        let is_head = state.try_borrow::<Method>() == Some(&Method::HEAD);
+        let mut published = false;
         let future = match state.try_take::<RequestPathSegments>() {
             Some(rps) => {
                 if let Some((node, params, processed)) = self.data.tree.traverse(&rps.segments()) {
-                    match node.select_route(&state) {
+                    // a delegated `Router` retains the template of the top-level `Router`, which
+                    // publishes the events of the request
+                    if !state.has::<RouteTemplate>() {
+                        let name = node.names().first().map(String::as_str);
+                        state.put(RouteTemplate::new(node.template(), name));
+                        published = events::routed(&state, node.template());
+                    }
+
+                    match self.select_route(node, &mut state) {
                         Ok(route) => match route.delegation() {
                             Delegation::External => {
-                                trace!("[{}] delegating to secondary router", request_id(&state));
+                                request_trace!(&state, "delegating to secondary router");
 
                                 state.put(rps.into_subsegments(processed));
                                 route.dispatch(state)
                             }
                             Delegation::Internal => {
-                                trace!("[{}] dispatching to route", request_id(&state));
+                                request_trace!(&state, "dispatching to route");
                                 self.dispatch(state, params, route)
                             }
                         },
+                        Err(ref non_match) if non_match.status() == StatusCode::NOT_FOUND => {
+                            self.not_found(state)
+                        }
                         Err(non_match) => {
-                            let (status, allow) = non_match.deconstruct();
-
-                            trace!("[{}] responding with error status", request_id(&state));
-                            let mut res = create_empty_response(&state, status);
-                            if let StatusCode::METHOD_NOT_ALLOWED = status {
-                                for allowed in allow {
-                                    res.headers_mut().append(
-                                        ALLOW,
-                                        allowed.as_str().to_string().parse().unwrap(),
-                                    );
-                                }
-                            }
+                            request_trace!(&state, "responding with error status");
+                            let cors = self.data.cors.as_ref();
+                            let res = non_match_response(&state, non_match, cors);
                             Box::new(future::ok((state, res)))
                         }
                     }
                 } else {
-                    trace!("[{}] did not find routable node", request_id(&state));
-                    let res = create_empty_response(&state, StatusCode::NOT_FOUND);
-                    Box::new(future::ok((state, res)))
+                    request_trace!(&state, "did not find routable node");
+                    self.not_found(state)
                 }
             }
             None => {
-                trace!("[{}] invalid request path segments", request_id(&state));
+                request_trace!(&state, "invalid request path segments");
                 let res = create_empty_response(&state, StatusCode::INTERNAL_SERVER_ERROR);
                 Box::new(future::ok((state, res)))
             }
         };
 
-        self.finalize_response(future)
+        let future = if published {
+            on_complete(future, |state, outcome| {
+                events::handler_completed(state, outcome.status())
+            })
+        } else {
+            future
+        };
+
+        let future = self.finalize_response(future);
+
+        if is_head {
+            Box::new(future.map(|(state, res)| (state, strip_body(res))))
+        } else {
+            future
+        }
     }
 }
 
@@ -125,17 +183,41 @@ impl Router {
         note = "use the new `gotham::router::builder` API to construct a Router"
     )]
     pub fn new(tree: Tree, response_finalizer: ResponseFinalizer) -> Router {
-        Router::internal_new(tree, response_finalizer)
+        Router::internal_new(
+            tree,
+            response_finalizer,
+            None,
+            TrailingSlash::default(),
+            None,
+        )
     }
 
     /// Same as `new`, but private and not deprecated.
-    fn internal_new(tree: Tree, response_finalizer: ResponseFinalizer) -> Router {
-        let router_data = RouterData::new(tree, response_finalizer);
+    fn internal_new(
+        tree: Tree,
+        response_finalizer: ResponseFinalizer,
+        fallback: Option<Box<Dispatcher + Send + Sync>>,
+        trailing_slash: TrailingSlash,
+            cors: Option<CorsMiddleware>,
+    ) -> Router {
+        let router_data = RouterData::new(
+            tree,
+            response_finalizer,
+            fallback,
+            trailing_slash,
+            cors,
+        );
         Router {
             data: Arc::new(router_data),
         }
     }
 
+    /// Describes every route registered with this `Router`, in the order they are found in the
+    /// route tree. See `RouteDescription` for an example.
+    pub fn routes(&self) -> Vec<RouteDescription> {
+        self.data.tree.describe_routes()
+    }
+
     fn dispatch<'a>(
         &self,
         mut state: State,
@@ -144,49 +226,88 @@ impl Router {
     ) -> Box<HandlerFuture> {
         match route.extract_request_path(&mut state, params) {
             Ok(()) => {
-                trace!("[{}] extracted request path", request_id(&state));
+                request_trace!(&state, "extracted request path");
                 match route.extract_query_string(&mut state) {
                     Ok(()) => {
-                        trace!("[{}] extracted query string", request_id(&state));
-                        trace!("[{}] dispatching", request_id(&state));
+                        request_trace!(&state, "extracted query string");
+                        request_trace!(&state, "dispatching");
                         route.dispatch(state)
                     }
                     Err(_) => {
-                        error!("[{}] the server cannot or will not process the request due to a client error within the query string",
-                               request_id(&state));
+                        request_error!(
+                            &state,
+                            "the server cannot or will not process the request due to a client error within the query string"
+                        );
 
-                        let mut res = Response::new(Body::empty());
+                        let mut res = create_empty_response(&state, StatusCode::BAD_REQUEST);
                         route.extend_response_on_query_string_error(&mut state, &mut res);
                         Box::new(future::ok((state, res)))
                     }
                 }
             }
             Err(_) => {
-                error!(
-                    "[{}] the server cannot or will not process the request due to a client error on the request path",
-                    request_id(&state)
+                request_error!(
+                    &state,
+                    "the server cannot or will not process the request due to a client error on the request path"
                 );
-                let mut res = Response::new(Body::empty());
+                let mut res = create_empty_response(&state, StatusCode::BAD_REQUEST);
                 route.extend_response_on_path_error(&mut state, &mut res);
                 Box::new(future::ok((state, res)))
             }
         }
     }
 
+    /// Responds to a request which did not match any `Route`, via the fallback `Handler` when one
+    /// has been configured.
+    fn not_found(&self, state: State) -> Box<HandlerFuture> {
+        match self.data.fallback {
+            Some(ref fallback) => {
+                request_trace!(&state, "dispatching to fallback");
+                fallback.dispatch(state)
+            }
+            None => {
+                let res = create_empty_response(&state, StatusCode::NOT_FOUND);
+                Box::new(future::ok((state, res)))
+            }
+        }
+    }
+
+    /// Selects the `Route` from `node` which will handle the request. A `HEAD` request which
+    /// matches no route is answered by the `GET` route for the same path, when one exists.
+    fn select_route<'n>(
+        &self,
+        node: &'n Node,
+        state: &mut State,
+    ) -> ::std::result::Result<&'n Box<Route<ResBody = Body> + Send + Sync>, RouteNonMatch> {
+        match node.select_route(state) {
+            Err(ref non_match)
+                if *state.borrow::<Method>() == Method::HEAD && non_match.allows_get() =>
+            {
+                request_trace!(state, "answering HEAD with GET route");
+
+                state.put(Method::GET);
+                let route = node.select_route(state);
+                state.put(Method::HEAD);
+                route
+            }
+            result => result,
+        }
+    }
+
     fn finalize_response(&self, result: Box<HandlerFuture>) -> Box<HandlerFuture> {
         let response_finalizer = self.data.response_finalizer.clone();
         let f = result
             .or_else(|(state, err)| {
-                trace!(
-                    "[{}] converting error into http response \
+                request_trace!(
+                    &state,
+                    "converting error into http response \
                      during finalization: {:?}",
-                    request_id(&state),
                     err
                 );
                 let response = err.into_response(&state);
                 future::ok((state, response))
             }).and_then(move |(state, res)| {
-                trace!("[{}] handler complete", request_id(&state));
+                request_trace!(&state, "handler complete");
                 response_finalizer.finalize(state, res)
             });
 
@@ -194,11 +315,86 @@ impl Router {
     }
 }
 
+// Creates the response sent when no route matched the request. An `OPTIONS` request which was
+// only rejected due to the request method is answered with the methods the path supports, or by
+// the CORS configuration of the `Router` when it is a preflight request.
+fn non_match_response(
+    state: &State,
+    non_match: RouteNonMatch,
+    cors: Option<&CorsMiddleware>,
+) -> Response<Body> {
+    let (status, mut allow) = non_match.deconstruct();
+
+    // `GET` routes will also answer `HEAD` requests
+    if allow.contains(&Method::GET) && !allow.contains(&Method::HEAD) {
+        allow.push(Method::HEAD);
+    }
+
+    let automatic_options =
+        status == StatusCode::METHOD_NOT_ALLOWED && *state.borrow::<Method>() == Method::OPTIONS;
+
+    if automatic_options && !allow.contains(&Method::OPTIONS) {
+        allow.push(Method::OPTIONS);
+    }
+
+    allow.sort_unstable_by(|a, b| a.as_ref().cmp(b.as_ref()));
+
+    if automatic_options {
+        let is_preflight =
+            HeaderMap::borrow_from(state).contains_key(ACCESS_CONTROL_REQUEST_METHOD);
+
+        if is_preflight {
+            if let Some(res) = cors.and_then(|cors| cors.route_preflight(state, &allow)) {
+                request_trace!(state, "answering CORS preflight from route tree");
+                return res;
+            }
+        }
+
+        request_trace!(state, "answering OPTIONS from route tree");
+        let mut res = create_empty_response(state, StatusCode::OK);
+        let allow = allow
+            .iter()
+            .map(|m| m.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        if is_preflight {
+            res.headers_mut()
+                .insert(ACCESS_CONTROL_ALLOW_METHODS, allow.parse().unwrap());
+        }
+
+        res.headers_mut().insert(ALLOW, allow.parse().unwrap());
+        return res;
+    }
+
+    let mut res = create_empty_response(state, status);
+    if let StatusCode::METHOD_NOT_ALLOWED = status {
+        for allowed in allow {
+            res.headers_mut()
+                .append(ALLOW, allowed.as_str().to_string().parse().unwrap());
+        }
+    }
+    res
+}
+
+// Discards the body of a response to a `HEAD` request, keeping the `Content-Length` that the
+// equivalent `GET` request would have received when the body size is known.
+fn strip_body(mut res: Response<Body>) -> Response<Body> {
+    if !res.headers().contains_key(CONTENT_LENGTH) {
+        if let Some(len) = res.body().content_length() {
+            res.headers_mut()
+                .insert(CONTENT_LENGTH, len.to_string().parse().unwrap());
+        }
+    }
+
+    *res.body_mut() = Body::empty();
+    res
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use hyper::header::{HeaderMap, CONTENT_LENGTH};
-    use hyper::{Body, Method, Uri};
+    use hyper::Uri;
     use std::str::FromStr;
 
     use extractor::{NoopPathExtractor, NoopQueryStringExtractor};
@@ -402,4 +598,212 @@ mod tests {
             Err(_) => panic!("Router should have correctly handled request"),
         };
     }
+
+    #[test]
+    fn head_request_answered_by_get_route() {
+        use futures::Stream;
+        use router::builder::{build_simple_router, DefineSingleRoute, DrawRoutes};
+
+        fn body_handler(state: State) -> (State, Response<Body>) {
+            (state, Response::new(Body::from("Hello, world!")))
+        }
+
+        let router = build_simple_router(|route| {
+            route.get("/").to(body_handler);
+        });
+
+        match send_request(router.clone(), Method::HEAD, "https://test.gotham.rs") {
+            Ok((_state, res)) => {
+                assert_eq!(res.status(), StatusCode::OK);
+                assert_eq!(res.headers().get(CONTENT_LENGTH).unwrap(), "13");
+
+                let body = res.into_body().concat2().wait().unwrap();
+                assert!(body.is_empty());
+            }
+            Err(_) => panic!("Router should have handled request"),
+        };
+
+        match send_request(router, Method::POST, "https://test.gotham.rs") {
+            Ok((_state, res)) => {
+                assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED);
+
+                let allow = res
+                    .headers()
+                    .get_all(ALLOW)
+                    .iter()
+                    .map(|v| v.to_str().unwrap())
+                    .collect::<Vec<_>>();
+                assert_eq!(allow, vec!["GET", "HEAD"]);
+            }
+            Err(_) => panic!("Router should have handled request"),
+        };
+    }
+
+    #[test]
+    fn options_request_answered_from_route_tree() {
+        use router::builder::{build_simple_router, DefineSingleRoute, DrawRoutes};
+
+        let router = build_simple_router(|route| {
+            route.get("/").to(handler);
+            route.post("/").to(handler);
+        });
+
+        match send_request(router, Method::OPTIONS, "https://test.gotham.rs") {
+            Ok((_state, res)) => {
+                assert_eq!(res.status(), StatusCode::OK);
+                assert_eq!(
+                    res.headers().get(ALLOW).unwrap(),
+                    "GET, HEAD, OPTIONS, POST"
+                );
+                assert!(res.headers().get(ACCESS_CONTROL_ALLOW_METHODS).is_none());
+            }
+            Err(_) => panic!("Router should have handled request"),
+        };
+    }
+
+    #[test]
+    fn preflight_answered_by_router_cors_config() {
+        use hyper::header::{ACCESS_CONTROL_ALLOW_ORIGIN, ORIGIN};
+        use router::builder::{build_simple_router, DefineSingleRoute, DrawRoutes};
+
+        let router = build_simple_router(|route| {
+            route.cors(CorsMiddleware::default().with_origins(vec!["https://example.com"]));
+            route.post("/").to(handler);
+            route.put("/").to(handler);
+        });
+
+        let preflight = |origin: &str, method: &str| {
+            let uri = Uri::from_str("https://test.gotham.rs").unwrap();
+            let mut headers = HeaderMap::new();
+            headers.insert(ORIGIN, origin.parse().unwrap());
+            headers.insert(ACCESS_CONTROL_REQUEST_METHOD, method.parse().unwrap());
+
+            let mut state = State::new();
+            state.put(RequestPathSegments::new(uri.path()));
+            state.put(Method::OPTIONS);
+            state.put(uri);
+            state.put(headers);
+            set_request_id(&mut state);
+
+            match router.clone().handle(state).wait() {
+                Ok((_state, res)) => res,
+                Err(_) => panic!("Router should have handled request"),
+            }
+        };
+
+        let res = preflight("https://example.com", "POST");
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        assert_eq!(
+            res.headers().get(ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(),
+            "https://example.com"
+        );
+        assert!(res.headers().get(ALLOW).is_none());
+
+        // not allowed by the CORS configuration
+        let res = preflight("https://example.com", "PUT");
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        let res = preflight("https://example.org", "POST");
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        // allowed by the CORS configuration, but not accepted by any route
+        let res = preflight("https://example.com", "GET");
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn dispatches_to_fallback_when_no_route_matches() {
+        use router::builder::{build_simple_router, DefineSingleRoute, DrawRoutes};
+
+        fn fallback(state: State) -> (State, Response<Body>) {
+            let res = create_empty_response(&state, StatusCode::IM_A_TEAPOT);
+            (state, res)
+        }
+
+        let router = build_simple_router(|route| {
+            route.get("/").to(handler);
+            route.fallback(fallback);
+        });
+
+        match send_request(
+            router.clone(),
+            Method::GET,
+            "https://test.gotham.rs/missing",
+        ) {
+            Ok((_state, res)) => assert_eq!(res.status(), StatusCode::IM_A_TEAPOT),
+            Err(_) => panic!("Router should have handled request"),
+        };
+
+        match send_request(router.clone(), Method::GET, "https://test.gotham.rs") {
+            Ok((_state, res)) => assert_eq!(res.status(), StatusCode::OK),
+            Err(_) => panic!("Router should have handled request"),
+        };
+
+        match send_request(router, Method::POST, "https://test.gotham.rs") {
+            Ok((_state, res)) => assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED),
+            Err(_) => panic!("Router should have handled request"),
+        };
+    }
+
+    #[test]
+    fn collects_named_route_templates() {
+        use router::builder::{build_simple_router, DefineSingleRoute, DrawRoutes};
+
+        let router = build_simple_router(|route| {
+            route.get("/").named("root").to(handler);
+            route.scope("/users", |route| {
+                route.get("/:id").named("user").to(handler);
+                route.put("/:id").named("user").to(handler);
+                route.get("/:id/files/*path").named("files").to(handler);
+            });
+            route.get(r"/literal/\:param").named("literal").to(handler);
+        });
+
+        let url_for = &router.data.url_for;
+        assert_eq!(url_for.template("root"), Some("/"));
+        assert_eq!(url_for.template("user"), Some("/users/:id"));
+        assert_eq!(url_for.template("files"), Some("/users/:id/files/*path"));
+        assert_eq!(url_for.template("literal"), Some(r"/literal/\:param"));
+        assert_eq!(url_for.template("missing"), None);
+    }
+
+    #[test]
+    fn puts_matched_route_template_into_state() {
+        use router::builder::{build_simple_router, DefineSingleRoute, DrawRoutes};
+
+        let router = build_simple_router(|route| {
+            route.get("/").to(handler);
+            route.scope("/users", |route| {
+                route.get("/:id/files/*path").named("files").to(handler);
+            });
+        });
+
+        match send_request(router.clone(), Method::GET, "https://test.gotham.rs/") {
+            Ok((state, _res)) => {
+                let template = state.borrow::<RouteTemplate>();
+                assert_eq!(template.as_str(), "/");
+                assert_eq!(template.name(), None);
+            }
+            Err(_) => panic!("Router should have handled request"),
+        };
+
+        match send_request(router, Method::GET, "https://test.gotham.rs/users/1/files/a/b") {
+            Ok((state, _res)) => {
+                let template = state.borrow::<RouteTemplate>();
+                assert_eq!(template.as_str(), "/users/:id/files/*path");
+                assert_eq!(template.name(), Some("files"));
+            }
+            Err(_) => panic!("Router should have handled request"),
+        };
+    }
+
+    #[test]
+    #[should_panic(expected = "route name `user` is used by more than one path")]
+    fn duplicate_route_names_panic() {
+        use router::builder::{build_simple_router, DefineSingleRoute, DrawRoutes};
+
+        build_simple_router(|route| {
+            route.get("/users/:id").named("user").to(handler);
+            route.get("/people/:id").named("user").to(handler);
+        });
+    }
 }
@@ -94,6 +94,30 @@ impl RouteNonMatch {
     pub(super) fn deconstruct(self) -> (StatusCode, Vec<Method>) {
         (self.status, self.allow.into())
     }
+
+    pub(super) fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// Reports this as a `405 Method Not Allowed` if `method` is absent from the allow list, as a
+    /// `Route` which doesn't accept the request method has no say over other kinds of mismatch,
+    /// such as the media type. Used by `Node::select_route` before aggregating results.
+    pub(super) fn for_method(self, method: &Method) -> RouteNonMatch {
+        if self.allow.contains(method) {
+            self
+        } else {
+            RouteNonMatch {
+                status: StatusCode::METHOD_NOT_ALLOWED,
+                ..self
+            }
+        }
+    }
+
+    /// Determines if this is a `405 Method Not Allowed` which would have been permitted as a
+    /// `GET` request. Used by the `Router` to answer `HEAD` requests with `GET` routes.
+    pub(super) fn allows_get(&self) -> bool {
+        self.status == StatusCode::METHOD_NOT_ALLOWED && self.allow.get
+    }
 }
 
 impl From<RouteNonMatch> for StatusCode {
@@ -153,6 +177,21 @@ impl MethodSet {
         }
     }
 
+    fn contains(&self, method: &Method) -> bool {
+        match *method {
+            Method::CONNECT => self.connect,
+            Method::DELETE => self.delete,
+            Method::GET => self.get,
+            Method::HEAD => self.head,
+            Method::OPTIONS => self.options,
+            Method::PATCH => self.patch,
+            Method::POST => self.post,
+            Method::PUT => self.put,
+            Method::TRACE => self.trace,
+            _ => self.other.contains(method),
+        }
+    }
+
     fn union(self, other: MethodSet) -> MethodSet {
         MethodSet {
             connect: self.connect || other.connect,
@@ -398,4 +437,20 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn method_mismatch_tests() {
+        let (status, allow_list) = RouteNonMatch::new(StatusCode::UNSUPPORTED_MEDIA_TYPE)
+            .with_allow_list(&[Method::POST])
+            .for_method(&Method::GET)
+            .deconstruct();
+        assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(&allow_list[..], &[Method::POST]);
+
+        let (status, _) = RouteNonMatch::new(StatusCode::UNSUPPORTED_MEDIA_TYPE)
+            .with_allow_list(&[Method::POST])
+            .for_method(&Method::POST)
+            .deconstruct();
+        assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
 }
@@ -1,7 +1,7 @@
 //! Defines functionality for extending a Response.
 
 use hyper::{body::Payload, Body, Response};
-use state::{request_id, State};
+use state::State;
 use std::panic::RefUnwindSafe;
 
 /// Extend the `Response` based on current `State` and `Response` data.
@@ -24,10 +24,7 @@ where
     F: Fn(&mut State, &mut Response<B>) + Send + Sync + RefUnwindSafe,
 {
     fn extend(&self, state: &mut State, res: &mut Response<B>) {
-        trace!(
-            "[{}] running closure based response extender",
-            request_id(&state)
-        );
+        request_trace!(&state, "running closure based response extender");
         self(state, res);
     }
 }
@@ -41,20 +38,20 @@ impl StaticResponseExtender for NoopResponseExtender {
     type ResBody = Body;
 
     fn extend(state: &mut State, _res: &mut Response<Body>) {
-        trace!(
-            "[{}] NoopResponseExtender invoked, does not make any changes to Response",
-            request_id(&state)
+        request_trace!(
+            &state,
+            "NoopResponseExtender invoked, does not make any changes to Response"
         );
-        trace!("[{}] no response body, no change made", request_id(&state));
+        request_trace!(&state, "no response body, no change made");
     }
 }
 
 impl ResponseExtender<Body> for NoopResponseExtender {
     fn extend(&self, state: &mut State, _res: &mut Response<Body>) {
-        trace!(
-            "[{}] NoopResponseExtender invoked on instance, does not make any changes to Response",
-            request_id(&state)
+        request_trace!(
+            &state,
+            "NoopResponseExtender invoked on instance, does not make any changes to Response"
         );
-        trace!("[{}] no response body, no change made", request_id(&state));
+        request_trace!(&state, "no response body, no change made");
     }
 }
@@ -8,7 +8,7 @@ use futures::future;
 use hyper::{Body, Response, StatusCode};
 
 use handler::HandlerFuture;
-use state::{request_id, State};
+use state::State;
 
 use router::response::extender::ResponseExtender;
 
@@ -65,19 +65,11 @@ impl ResponseFinalizer {
     pub fn finalize(&self, mut state: State, mut res: Response<Body>) -> Box<HandlerFuture> {
         match self.data.get(&res.status()) {
             Some(extender) => {
-                trace!(
-                    "[{}] invoking {} response extender",
-                    request_id(&state),
-                    res.status()
-                );
+                request_trace!(&state, "invoking {} response extender", res.status());
                 extender.extend(&mut state, &mut res);
             }
             None => {
-                trace!(
-                    "[{}] no response extender for {}",
-                    request_id(&state),
-                    res.status()
-                );
+                request_trace!(&state, "no response extender for {}", res.status());
             }
         }
 
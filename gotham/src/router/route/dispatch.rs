@@ -6,12 +6,18 @@ use std::panic::RefUnwindSafe;
 use handler::{Handler, HandlerFuture, IntoHandlerError, NewHandler};
 use pipeline::chain::PipelineHandleChain;
 use pipeline::set::PipelineSet;
-use state::{request_id, State};
+use state::State;
 
 /// Used by `Router` to dispatch requests via pipelines and finally into the configured `Handler`.
 pub trait Dispatcher: RefUnwindSafe {
     /// Dispatches a request via pipelines and `Handler` represented by this `Dispatcher`.
     fn dispatch(&self, state: State) -> Box<HandlerFuture>;
+
+    /// Returns the number of `Pipeline` instances a request is dispatched through before reaching
+    /// the `Handler`.
+    fn pipeline_count(&self) -> usize {
+        0
+    }
 }
 
 /// Default implementation of the `Dispatcher` trait.
@@ -59,16 +65,20 @@ where
     fn dispatch(&self, state: State) -> Box<HandlerFuture> {
         match self.new_handler.new_handler() {
             Ok(h) => {
-                trace!("[{}] cloning handler", request_id(&state));
+                request_trace!(&state, "cloning handler");
                 self.pipeline_chain
                     .call(&self.pipelines, state, move |state| h.handle(state))
             }
             Err(e) => {
-                trace!("[{}] error cloning handler", request_id(&state));
+                request_trace!(&state, "error cloning handler");
                 Box::new(future::err((state, e.compat().into_handler_error())))
             }
         }
     }
+
+    fn pipeline_count(&self) -> usize {
+        self.pipeline_chain.pipeline_count()
+    }
 }
 
 #[cfg(test)]
@@ -1,19 +1,21 @@
 //! Defines the `AcceptHeaderRouterMatcher`.
 
-use hyper::header::{HeaderMap, HeaderValue, ACCEPT};
+use hyper::header::{HeaderMap, ACCEPT};
 use hyper::StatusCode;
 use mime;
 
-use error;
 use router::non_match::RouteNonMatch;
 use router::route::RouteMatcher;
-use state::{request_id, FromState, State};
+use state::{FromState, State};
 
 /// A `RouteMatcher` that succeeds when the `Request` has been made with an `Accept` header that
 /// includes one or more supported media types. A missing `Accept` header, or the value of `*/*`
 /// will also positvely match.
 ///
-/// Quality values within `Accept` header values are not considered by this matcher.
+/// Quality values within the `Accept` header are respected: a media type with `q=0` is never
+/// matched, and where sibling routes at the same path both match, the `Router` invokes the route
+/// whose media types the client prefers. Media ranges such as `image/*` will match a supported
+/// `image/png`, and vice versa.
 ///
 /// # Examples
 ///
@@ -59,12 +61,18 @@ use state::{request_id, FromState, State};
 /// headers.insert(ACCEPT, "application/json".parse().unwrap());
 /// state.put(headers);
 /// assert!(matcher.is_match(&state).is_ok());
-
+///
 /// // Accept header of `image/*`
 /// let mut headers = HeaderMap::new();
 /// headers.insert(ACCEPT, "image/*".parse().unwrap());
 /// state.put(headers);
 /// assert!(matcher.is_match(&state).is_ok());
+///
+/// // Supported types which have been refused using `q=0`
+/// let mut headers = HeaderMap::new();
+/// headers.insert(ACCEPT, "application/json;q=0, text/plain".parse().unwrap());
+/// state.put(headers);
+/// assert!(matcher.is_match(&state).is_err());
 /// #
 /// #   });
 /// # }
@@ -81,41 +89,145 @@ impl AcceptHeaderRouteMatcher {
             supported_media_types,
         }
     }
+
+    /// Determines the highest quality value given by the `Accept` header to any supported media
+    /// type. A missing `Accept` header accepts everything, with a quality of `1.0`.
+    fn best_quality(&self, state: &State) -> Result<f32, RouteNonMatch> {
+        let ranges = match accepted_ranges(HeaderMap::borrow_from(state)) {
+            // The client has not specified an `Accept` header.
+            None => return Ok(1.0),
+            Some(ranges) => ranges,
+        };
+
+        let best = self
+            .supported_media_types
+            .iter()
+            .filter_map(|supported| {
+                // The most specific media range which covers the type determines its quality.
+                ranges
+                    .iter()
+                    .filter(|&&(ref range, _)| media_types_match(range, supported))
+                    .max_by_key(|&&(ref range, _)| specificity(range))
+                    .map(|&(_, q)| q)
+            })
+            .fold(0.0, f32::max);
+
+        if best > 0.0 {
+            Ok(best)
+        } else {
+            request_trace!(
+                &state,
+                "did not provide an Accept with media types supported by this Route"
+            );
+            Err(RouteNonMatch::new(StatusCode::NOT_ACCEPTABLE))
+        }
+    }
 }
 
 impl RouteMatcher for AcceptHeaderRouteMatcher {
     /// Determines if the `Request` was made using an `Accept` header that includes one or more
-    /// supported media types. A missing `Accept` header, or the value of `*/*` will also positvely
-    /// match.
-    ///
-    /// Quality values within `Accept` header values are not considered by the matcher, as the
-    /// matcher is only able to indicate whether a successful match has been found.
+    /// supported media types with a non-zero quality value. A missing `Accept` header, or the value
+    /// of `*/*` will also positvely match.
     fn is_match(&self, state: &State) -> Result<(), RouteNonMatch> {
-        // Request method is valid, ensure valid Accept header
-        match HeaderMap::borrow_from(state).get(ACCEPT) {
-            // The client has not specified an `Accept` header.
-            None => Ok(()),
-
-            // Or the header is any type, so it's fine.
-            Some(header) if header == "*/*" => Ok(()),
-
-            // Otherwise we have to validate the header is a match.
-            Some(mime_header) => parse_mime_type(mime_header)
-                .map_err(|_| RouteNonMatch::new(StatusCode::NOT_ACCEPTABLE))
-                .and_then(|mime_type| {
-                    if self.supported_media_types.contains(&mime_type) {
-                        return Ok(());
-                    }
-                    trace!(
-                        "[{}] did not provide an Accept with media types supported by this Route",
-                        request_id(&state)
-                    );
-                    Err(RouteNonMatch::new(StatusCode::NOT_ACCEPTABLE))
-                }),
-        }
+        self.best_quality(state).map(|_| ())
+    }
+
+    /// Returns the highest quality value given by the `Accept` header to a supported media type.
+    fn quality(&self, state: &State) -> f32 {
+        self.best_quality(state).unwrap_or(0.0)
     }
 }
 
-fn parse_mime_type(hv: &HeaderValue) -> error::Result<mime::Mime> {
-    Ok(hv.to_str()?.parse()?)
+// Parses each media range from the `Accept` header(s), with its quality value. Ranges which fail
+// to parse are ignored.
+fn accepted_ranges(headers: &HeaderMap) -> Option<Vec<(mime::Mime, f32)>> {
+    let mut values = headers.get_all(ACCEPT).iter().peekable();
+    values.peek()?;
+
+    let ranges = values
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|range| range.trim().parse::<mime::Mime>().ok())
+        .map(|range| {
+            let q = range
+                .get_param("q")
+                .and_then(|q| q.as_str().parse::<f32>().ok())
+                .map(|q| q.max(0.0).min(1.0))
+                .unwrap_or(1.0);
+            (range, q)
+        })
+        .collect();
+
+    Some(ranges)
+}
+
+fn media_types_match(a: &mime::Mime, b: &mime::Mime) -> bool {
+    let types = a.type_() == mime::STAR || b.type_() == mime::STAR || a.type_() == b.type_();
+    let subtypes =
+        a.subtype() == mime::STAR || b.subtype() == mime::STAR || a.subtype() == b.subtype();
+    types && subtypes
+}
+
+fn specificity(range: &mime::Mime) -> u8 {
+    match (range.type_() == mime::STAR, range.subtype() == mime::STAR) {
+        (true, _) => 0,
+        (false, true) => 1,
+        (false, false) => 2,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quality(matcher: &AcceptHeaderRouteMatcher, accept: &str) -> Result<f32, RouteNonMatch> {
+        let mut state = State::new();
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT, accept.parse().unwrap());
+        state.put(headers);
+        matcher.best_quality(&state)
+    }
+
+    #[test]
+    fn respects_quality_values() {
+        let matcher = AcceptHeaderRouteMatcher::new(vec![mime::APPLICATION_JSON]);
+
+        assert_eq!(quality(&matcher, "application/json").ok(), Some(1.0));
+        assert_eq!(
+            quality(&matcher, "text/html, application/json;q=0.5").ok(),
+            Some(0.5)
+        );
+        assert_eq!(quality(&matcher, "text/html, */*; q=0.1").ok(), Some(0.1));
+        assert!(quality(&matcher, "application/json;q=0").is_err());
+        assert!(quality(&matcher, "text/html").is_err());
+    }
+
+    #[test]
+    fn most_specific_range_wins() {
+        let matcher = AcceptHeaderRouteMatcher::new(vec![mime::TEXT_PLAIN]);
+
+        assert_eq!(
+            quality(&matcher, "*/*;q=1, text/*;q=0.8, text/plain;q=0.3").ok(),
+            Some(0.3)
+        );
+        assert!(quality(&matcher, "*/*, text/plain;q=0").is_err());
+    }
+
+    #[test]
+    fn matches_wildcard_types() {
+        let matcher = AcceptHeaderRouteMatcher::new(vec![mime::IMAGE_STAR]);
+        assert!(quality(&matcher, "image/png").is_ok());
+        assert!(quality(&matcher, "text/plain").is_err());
+
+        let matcher = AcceptHeaderRouteMatcher::new(vec![mime::IMAGE_PNG]);
+        assert!(quality(&matcher, "image/*").is_ok());
+    }
+
+    #[test]
+    fn ignores_invalid_ranges() {
+        let matcher = AcceptHeaderRouteMatcher::new(vec![mime::APPLICATION_JSON]);
+
+        assert!(quality(&matcher, "nonsense, application/json").is_ok());
+        assert!(quality(&matcher, "nonsense").is_err());
+    }
 }
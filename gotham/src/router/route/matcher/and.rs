@@ -1,5 +1,7 @@
 //! Defines the type `AndRouteMatcher`
 
+use hyper::Method;
+
 use router::non_match::RouteNonMatch;
 use router::route::RouteMatcher;
 use state::State;
@@ -83,4 +85,15 @@ where
             (Err(e), Err(e1)) => Err(e.intersection(e1)),
         }
     }
+
+    fn quality(&self, state: &State) -> f32 {
+        self.t.quality(state) * self.u.quality(state)
+    }
+
+    fn methods(&self) -> Option<Vec<Method>> {
+        match (self.t.methods(), self.u.methods()) {
+            (Some(t), Some(u)) => Some(t.into_iter().filter(|m| u.contains(m)).collect()),
+            (t, u) => t.or(u),
+        }
+    }
 }
@@ -6,11 +6,14 @@ use mime;
 
 use router::non_match::RouteNonMatch;
 use router::route::RouteMatcher;
-use state::{request_id, FromState, State};
+use state::{FromState, State};
 
 /// A `RouteMatcher` that succeeds when the `Request` has been made with a `Content-Type` header
 /// that includes a supported media type. The matcher will fail if the Content-Type
-/// header is missing.
+/// header is missing or cannot be parsed.
+///
+/// Parameters such as `charset` are ignored unless the supported media type specifies them, and
+/// supported media types such as `text/*` will match any subtype.
 ///
 /// # Examples
 ///
@@ -51,6 +54,18 @@ use state::{request_id, FromState, State};
 /// headers.insert(CONTENT_TYPE, "application/json".parse().unwrap());
 /// state.put(headers);
 /// assert!(matcher.is_match(&state).is_ok());
+///
+/// // Content type header with additional parameters
+/// let mut headers = HeaderMap::new();
+/// headers.insert(CONTENT_TYPE, "application/json; charset=utf-8".parse().unwrap());
+/// state.put(headers);
+/// assert!(matcher.is_match(&state).is_ok());
+///
+/// // Content type header which is not a media type
+/// let mut headers = HeaderMap::new();
+/// headers.insert(CONTENT_TYPE, "not a media type".parse().unwrap());
+/// state.put(headers);
+/// assert!(matcher.is_match(&state).is_err());
 /// #
 /// #   });
 /// # }
@@ -78,15 +93,29 @@ impl RouteMatcher for ContentTypeHeaderRouteMatcher {
 
             // Header was provided.
             Some(content_type) => {
-                let mime = content_type.to_str().unwrap().parse().unwrap();
+                let requested = match content_type
+                    .to_str()
+                    .ok()
+                    .and_then(|ct| ct.parse::<mime::Mime>().ok())
+                {
+                    Some(requested) => requested,
+                    None => {
+                        request_trace!(&state, "provided a Content-Type which could not be parsed");
+                        return Err(RouteNonMatch::new(StatusCode::UNSUPPORTED_MEDIA_TYPE));
+                    }
+                };
 
-                if self.supported_media_types.contains(&mime) {
+                if self
+                    .supported_media_types
+                    .iter()
+                    .any(|supported| media_type_matches(supported, &requested))
+                {
                     return Ok(());
                 }
 
-                trace!(
-                    "[{}] did not specify a Content-Type with a media type supported by this Route",
-                    request_id(&state)
+                request_trace!(
+                    &state,
+                    "did not specify a Content-Type with a media type supported by this Route"
                 );
 
                 Err(RouteNonMatch::new(StatusCode::UNSUPPORTED_MEDIA_TYPE))
@@ -94,3 +123,13 @@ impl RouteMatcher for ContentTypeHeaderRouteMatcher {
         }
     }
 }
+
+// Compares the media type of the request with a supported media type, which may use a `*` subtype
+// and may require parameters.
+fn media_type_matches(supported: &mime::Mime, requested: &mime::Mime) -> bool {
+    supported.type_() == requested.type_()
+        && (supported.subtype() == mime::STAR || supported.subtype() == requested.subtype())
+        && supported
+            .params()
+            .all(|(name, value)| requested.get_param(name) == Some(value))
+}
@@ -0,0 +1,156 @@
+//! Defines the `HostRouteMatcher`.
+
+use hyper::header::{HeaderMap, HOST};
+use hyper::{StatusCode, Uri};
+
+use router::non_match::RouteNonMatch;
+use router::route::RouteMatcher;
+use state::{FromState, State};
+
+/// A `RouteMatcher` that succeeds when the `Request` has been made to one of the given hostnames,
+/// as indicated by the `Host` header (or the request URI, when it contains an authority).
+///
+/// Hostnames are compared case-insensitively and without the port. A hostname beginning with `*.`
+/// matches any subdomain, but not the bare domain itself.
+///
+/// ```rust
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// #
+/// # use hyper::header::{HeaderMap, HOST};
+/// # use hyper::Uri;
+/// # use gotham::router::route::matcher::host::HostRouteMatcher;
+/// # use gotham::router::route::matcher::RouteMatcher;
+/// # use gotham::state::State;
+/// #
+/// # fn main() {
+/// #   State::with_new(|state| {
+/// #
+/// let matcher = HostRouteMatcher::new(vec!["example.com", "*.example.org"]);
+///
+/// state.put("/".parse::<Uri>().unwrap());
+///
+/// let mut headers = HeaderMap::new();
+/// headers.insert(HOST, "example.com:7878".parse().unwrap());
+/// state.put(headers);
+/// assert!(matcher.is_match(&state).is_ok());
+///
+/// let mut headers = HeaderMap::new();
+/// headers.insert(HOST, "api.example.org".parse().unwrap());
+/// state.put(headers);
+/// assert!(matcher.is_match(&state).is_ok());
+///
+/// let mut headers = HeaderMap::new();
+/// headers.insert(HOST, "example.org".parse().unwrap());
+/// state.put(headers);
+/// assert!(matcher.is_match(&state).is_err());
+/// #
+/// #   });
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct HostRouteMatcher {
+    hosts: Vec<String>,
+}
+
+impl HostRouteMatcher {
+    /// Creates a new `HostRouteMatcher` accepting any of the given hostnames.
+    pub fn new<S>(hosts: Vec<S>) -> Self
+    where
+        S: AsRef<str>,
+    {
+        HostRouteMatcher {
+            hosts: hosts
+                .iter()
+                .map(|h| h.as_ref().to_ascii_lowercase())
+                .collect(),
+        }
+    }
+}
+
+impl RouteMatcher for HostRouteMatcher {
+    fn is_match(&self, state: &State) -> Result<(), RouteNonMatch> {
+        let host = request_host(state).map(|h| h.to_ascii_lowercase());
+
+        let matched = match host {
+            Some(ref host) => self.hosts.iter().any(|expected| {
+                if expected.starts_with("*.") {
+                    host.ends_with(&expected[1..])
+                } else {
+                    host == expected
+                }
+            }),
+            None => false,
+        };
+
+        if matched {
+            Ok(())
+        } else {
+            request_trace!(state, "did not match request host {:?}", host);
+            Err(RouteNonMatch::new(StatusCode::NOT_FOUND))
+        }
+    }
+}
+
+// Determines the hostname the request was made to, without any port.
+fn request_host(state: &State) -> Option<&str> {
+    let authority = HeaderMap::borrow_from(state)
+        .get(HOST)
+        .and_then(|h| h.to_str().ok())
+        .or_else(|| Uri::borrow_from(state).authority_part().map(|a| a.as_str()))?;
+
+    // strip the userinfo (only valid in the URI) and the port, respecting IPv6 literals
+    let authority = authority.rsplit('@').next().unwrap_or(authority);
+    match authority.rfind(':') {
+        Some(n) if !authority[n..].contains(']') => Some(&authority[..n]),
+        _ => Some(authority),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use hyper::Uri;
+    use std::str::FromStr;
+
+    fn check(matcher: &HostRouteMatcher, host: Option<&str>, uri: &str) -> bool {
+        let mut state = State::new();
+        let mut headers = HeaderMap::new();
+        if let Some(host) = host {
+            headers.insert(HOST, host.parse().unwrap());
+        }
+        state.put(headers);
+        state.put(Uri::from_str(uri).unwrap());
+        matcher.is_match(&state).is_ok()
+    }
+
+    #[test]
+    fn matches_exact_hosts() {
+        let matcher = HostRouteMatcher::new(vec!["Example.com", "[::1]"]);
+
+        assert!(check(&matcher, Some("example.com"), "/"));
+        assert!(check(&matcher, Some("EXAMPLE.COM:8080"), "/"));
+        assert!(check(&matcher, Some("[::1]:8080"), "/"));
+        assert!(!check(&matcher, Some("www.example.com"), "/"));
+        assert!(!check(&matcher, None, "/"));
+    }
+
+    #[test]
+    fn matches_wildcard_hosts() {
+        let matcher = HostRouteMatcher::new(vec!["*.example.com"]);
+
+        assert!(check(&matcher, Some("api.example.com"), "/"));
+        assert!(check(&matcher, Some("a.b.example.com"), "/"));
+        assert!(!check(&matcher, Some("example.com"), "/"));
+        assert!(!check(&matcher, Some("badexample.com"), "/"));
+    }
+
+    #[test]
+    fn falls_back_to_uri_authority() {
+        let matcher = HostRouteMatcher::new(vec!["example.com"]);
+
+        assert!(check(&matcher, None, "https://example.com:443/path"));
+        assert!(!check(&matcher, None, "https://example.org/path"));
+    }
+}
@@ -4,23 +4,40 @@ pub mod accept;
 pub mod and;
 pub mod any;
 pub mod content_type;
+pub mod host;
 
 pub use self::accept::AcceptHeaderRouteMatcher;
 pub use self::and::AndRouteMatcher;
 pub use self::any::AnyRouteMatcher;
+pub use self::host::HostRouteMatcher;
 
 use std::panic::RefUnwindSafe;
 
 use hyper::{Method, StatusCode};
 
 use router::non_match::RouteNonMatch;
-use state::{request_id, FromState, State};
+use state::{FromState, State};
 
 /// Determines if conditions required for the associated `Route` to be invoked by the `Router` have
 /// been met.
 pub trait RouteMatcher: RefUnwindSafe + Clone {
     /// Determines if the `Request` meets pre-defined conditions.
     fn is_match(&self, state: &State) -> Result<(), RouteNonMatch>;
+
+    /// Indicates how strongly the `Request` prefers the associated `Route`, between `0.0` and
+    /// `1.0`. Where several `Route` instances at the same path match a `Request`, the `Router`
+    /// invokes the one with the highest quality.
+    ///
+    /// Only called after `is_match` has succeeded. The default implementation returns `1.0`.
+    fn quality(&self, _state: &State) -> f32 {
+        1.0
+    }
+
+    /// The request methods which this matcher accepts, or `None` if it does not consider the
+    /// request method. This is used to describe routes, and does not affect matching.
+    fn methods(&self) -> Option<Vec<Method>> {
+        None
+    }
 }
 
 /// Allow various types to represent themselves as a `RouteMatcher`
@@ -94,20 +111,20 @@ impl RouteMatcher for MethodOnlyRouteMatcher {
     fn is_match(&self, state: &State) -> Result<(), RouteNonMatch> {
         let method = Method::borrow_from(state);
         if self.methods.iter().any(|m| m == method) {
-            trace!(
-                "[{}] matched request method {} to permitted method",
-                request_id(&state),
+            request_trace!(
+                &state,
+                "matched request method {} to permitted method",
                 method
             );
             Ok(())
         } else {
-            trace!(
-                "[{}] did not match request method {}",
-                request_id(&state),
-                method
-            );
+            request_trace!(&state, "did not match request method {}", method);
             Err(RouteNonMatch::new(StatusCode::METHOD_NOT_ALLOWED)
                 .with_allow_list(self.methods.as_slice()))
         }
     }
+
+    fn methods(&self) -> Option<Vec<Method>> {
+        Some(self.methods.clone())
+    }
 }
@@ -10,16 +10,17 @@ pub mod matcher;
 use std::marker::PhantomData;
 use std::panic::RefUnwindSafe;
 
-use hyper::{Body, Response, Uri};
+use hyper::{Body, Method, Response, Uri};
 
 use extractor::{self, PathExtractor, QueryStringExtractor};
 use handler::HandlerFuture;
 use helpers::http::request::query_string;
+use openapi::Schema;
 use router::non_match::RouteNonMatch;
 use router::route::dispatch::Dispatcher;
 use router::route::matcher::RouteMatcher;
 use router::tree::segment::SegmentMapping;
-use state::{request_id, State};
+use state::State;
 
 #[derive(Clone, Copy, PartialEq)]
 /// Indicates whether this `Route` will dispatch the request to an inner `Router` instance. To
@@ -55,9 +56,38 @@ pub trait Route: RefUnwindSafe {
     /// Determines if this `Route` should be invoked, based on the request data in `State.
     fn is_match(&self, state: &State) -> Result<(), RouteNonMatch>;
 
+    /// Indicates how strongly the `Request` prefers this `Route` over others which also match.
+    /// See `RouteMatcher::quality`.
+    fn quality(&self, _state: &State) -> f32 {
+        1.0
+    }
+
     /// Determines if this `Route` intends to delegate requests to a secondary `Router` instance.
     fn delegation(&self) -> Delegation;
 
+    /// The request methods accepted by this `Route`, or `None` if it is not restricted to any.
+    /// See `RouteMatcher::methods`.
+    fn methods(&self) -> Option<Vec<Method>> {
+        None
+    }
+
+    /// The number of `Pipeline` instances a request is dispatched through by this `Route`.
+    fn pipeline_count(&self) -> usize {
+        0
+    }
+
+    /// Describes the `PathExtractor` of this `Route`, or `None` if it does not extract anything.
+    /// This is used to describe routes, and does not affect matching.
+    fn path_parameters(&self) -> Option<Schema> {
+        None
+    }
+
+    /// Describes the `QueryStringExtractor` of this `Route`, or `None` if it does not extract
+    /// anything. This is used to describe routes, and does not affect matching.
+    fn query_parameters(&self) -> Option<Schema> {
+        None
+    }
+
     /// Extracts dynamic components of the `Request` path and stores the `PathExtractor` in `State`.
     fn extract_request_path<'a>(
         &self,
@@ -160,10 +190,30 @@ where
         self.matcher.is_match(state)
     }
 
+    fn quality(&self, state: &State) -> f32 {
+        self.matcher.quality(state)
+    }
+
     fn delegation(&self) -> Delegation {
         self.delegation
     }
 
+    fn methods(&self) -> Option<Vec<Method>> {
+        self.matcher.methods()
+    }
+
+    fn pipeline_count(&self) -> usize {
+        self.dispatcher.pipeline_count()
+    }
+
+    fn path_parameters(&self) -> Option<Schema> {
+        Schema::trace::<PE>()
+    }
+
+    fn query_parameters(&self) -> Option<Schema> {
+        Schema::trace::<QSE>()
+    }
+
     fn dispatch(&self, state: State) -> Box<HandlerFuture> {
         self.dispatcher.dispatch(state)
     }
@@ -176,7 +226,7 @@ where
         match extractor::internal::from_segment_mapping::<PE>(params) {
             Ok(val) => Ok(state.put(val)),
             Err(e) => {
-                debug!("[{}] path extractor failed: {}", request_id(&state), e);
+                request_debug!(&state, "path extractor failed: {}", e);
                 Err(ExtractorFailed)
             }
         }
@@ -196,11 +246,7 @@ where
         match result {
             Ok(val) => Ok(state.put(val)),
             Err(e) => {
-                debug!(
-                    "[{}] query string extractor failed: {}",
-                    request_id(&state),
-                    e
-                );
+                request_debug!(&state, "query string extractor failed: {}", e);
                 Err(ExtractorFailed)
             }
         }
@@ -0,0 +1,119 @@
+//! Defines the policy applied by the `Router` to request paths which end with a `/`.
+
+use hyper::{Body, Response, StatusCode, Uri};
+
+use helpers::http::response::{create_empty_response, create_permanent_redirect};
+use state::State;
+
+/// Determines how the `Router` treats a request path with a trailing slash, such as `/foo/`, when
+/// compared to the same path without one. The root path `/` is never affected.
+///
+/// Configured for a `Router` via `RouterBuilder::trailing_slash`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TrailingSlash {
+    /// `/foo/` and `/foo` are dispatched to the same route. This is the default behaviour.
+    Merge,
+
+    /// Requests for `/foo/` are answered with `404 Not Found`.
+    Strict,
+
+    /// Requests for `/foo/` are answered with a `308 Permanent Redirect` to `/foo`, retaining the
+    /// query string.
+    Redirect,
+}
+
+impl Default for TrailingSlash {
+    fn default() -> TrailingSlash {
+        TrailingSlash::Merge
+    }
+}
+
+impl TrailingSlash {
+    /// Creates the `Response` required by this policy, if the request should not be routed.
+    pub(super) fn intercept(self, state: &State) -> Option<Response<Body>> {
+        if let TrailingSlash::Merge = self {
+            return None;
+        }
+
+        let uri = match state.try_borrow::<Uri>() {
+            Some(uri) => uri,
+            None => return None,
+        };
+
+        let path = uri.path();
+        if path == "/" || !path.ends_with('/') {
+            return None;
+        }
+
+        match self {
+            TrailingSlash::Strict => {
+                request_trace!(state, "rejecting trailing slash");
+                Some(create_empty_response(state, StatusCode::NOT_FOUND))
+            }
+            _ => {
+                request_trace!(state, "redirecting trailing slash");
+                // leading slashes are collapsed, as `//host/path` is a protocol-relative URL
+                let mut location =
+                    format!("/{}", path.trim_end_matches('/').trim_start_matches('/'));
+                if let Some(query) = uri.query() {
+                    location.push('?');
+                    location.push_str(query);
+                }
+                Some(create_permanent_redirect(state, location))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use hyper::header::LOCATION;
+    use hyper::HeaderMap;
+    use std::str::FromStr;
+
+    use state::set_request_id;
+
+    fn intercept(policy: TrailingSlash, uri: &str) -> Option<Response<Body>> {
+        let mut state = State::new();
+        state.put(Uri::from_str(uri).unwrap());
+        state.put(HeaderMap::new());
+        set_request_id(&mut state);
+        policy.intercept(&state)
+    }
+
+    #[test]
+    fn merge_never_intercepts() {
+        assert!(intercept(TrailingSlash::Merge, "https://example.com/foo/").is_none());
+    }
+
+    #[test]
+    fn strict_rejects_trailing_slash() {
+        assert!(intercept(TrailingSlash::Strict, "https://example.com/").is_none());
+        assert!(intercept(TrailingSlash::Strict, "https://example.com/foo").is_none());
+
+        let res = intercept(TrailingSlash::Strict, "https://example.com/foo/").unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn redirect_removes_trailing_slash() {
+        assert!(intercept(TrailingSlash::Redirect, "https://example.com/").is_none());
+        assert!(intercept(TrailingSlash::Redirect, "https://example.com/foo").is_none());
+
+        let res = intercept(TrailingSlash::Redirect, "https://example.com/foo/?a=b").unwrap();
+        assert_eq!(res.status(), StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(res.headers().get(LOCATION).unwrap(), "/foo?a=b");
+
+        let res = intercept(TrailingSlash::Redirect, "https://example.com//").unwrap();
+        assert_eq!(res.headers().get(LOCATION).unwrap(), "/");
+
+        // never redirects to another host
+        let res = intercept(TrailingSlash::Redirect, "https://example.com//evil.com/").unwrap();
+        assert_eq!(res.headers().get(LOCATION).unwrap(), "/evil.com");
+
+        let res = intercept(TrailingSlash::Redirect, "https://example.com///evil.com//").unwrap();
+        assert_eq!(res.headers().get(LOCATION).unwrap(), "/evil.com");
+    }
+}
@@ -1,7 +1,10 @@
 //! Defines a hierarchial `Tree` with subtrees of `Node`.
 
+use std::collections::HashMap;
+
 use helpers::http::PercentDecoded;
 use hyper::Body;
+use router::description::RouteDescription;
 use router::route::Route;
 use router::tree::node::Node;
 use router::tree::segment::{SegmentMapping, SegmentType};
@@ -52,6 +55,25 @@ impl Tree {
     }
 
     /// Attempt to acquire a path from the `Tree` which matches the `Request` path and is routable.
+    /// Collects the path templates of all named nodes in the `Tree`, keyed by name.
+    pub(crate) fn route_names(&self) -> HashMap<String, String> {
+        let mut names = HashMap::new();
+        self.root.collect_names("", &mut names);
+        names
+    }
+
+    /// Records the path template of every `Node` in the `Tree`.
+    pub(crate) fn assign_templates(&mut self) {
+        self.root.assign_templates("");
+    }
+
+    /// Describes every `Route` in the `Tree`.
+    pub(crate) fn describe_routes(&self) -> Vec<RouteDescription> {
+        let mut routes = Vec::new();
+        self.root.collect_routes("", &mut routes);
+        routes
+    }
+
     pub(crate) fn traverse<'a>(
         &'a self,
         req_path_segments: &'a [PercentDecoded],
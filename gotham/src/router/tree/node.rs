@@ -1,12 +1,13 @@
 //! Defines `Node` for `Tree`.
 
-use hyper::{Body, StatusCode};
+use hyper::{Body, Method, StatusCode};
 
 use helpers::http::PercentDecoded;
+use router::description::RouteDescription;
 use router::non_match::RouteNonMatch;
 use router::route::{Delegation, Route};
 use router::tree::segment::{SegmentMapping, SegmentType};
-use state::{request_id, State};
+use state::State;
 
 use std::cmp::Ordering;
 use std::collections::HashMap;
@@ -21,6 +22,17 @@ pub struct Node {
     segment_type: SegmentType,
     routes: Vec<Box<Route<ResBody = Body> + Send + Sync>>,
     children: Vec<Node>,
+    names: Vec<String>,
+    template: String,
+}
+
+// Removes the trailing `/` from a path produced by `Node::prefixed_path`, except for the root.
+fn template(path: &str) -> String {
+    if path.len() > 1 {
+        path[..path.len() - 1].to_owned()
+    } else {
+        path.to_owned()
+    }
 }
 
 impl Node {
@@ -31,6 +43,8 @@ impl Node {
             segment: segment.to_string(),
             routes: vec![],
             children: vec![],
+            names: vec![],
+            template: String::new(),
         }
     }
 
@@ -47,6 +61,91 @@ impl Node {
         self
     }
 
+    /// Adds a name to this `Node`, which can be used to generate a path to it via `UrlFor`.
+    pub fn add_name(&mut self, name: &str) -> &mut Self {
+        if !self.names.iter().any(|n| n == name) {
+            self.names.push(name.to_owned());
+        }
+        self
+    }
+
+    /// Collects the path templates of all named `Node` instances within this subtree, keyed by
+    /// name. The template uses the same syntax as the router builder, beginning with `prefix`.
+    pub(crate) fn collect_names(&self, prefix: &str, names: &mut HashMap<String, String>) {
+        let path = self.prefixed_path(prefix);
+
+        for name in &self.names {
+            if names.insert(name.clone(), template(&path)).is_some() {
+                panic!("route name `{}` is used by more than one path", name);
+            }
+        }
+
+        for child in &self.children {
+            child.collect_names(&path, names);
+        }
+    }
+
+    /// Records the path template of every `Node` within this subtree, beginning with `prefix`, so
+    /// that it can be reported for requests which match the `Node`.
+    pub(crate) fn assign_templates(&mut self, prefix: &str) {
+        let path = self.prefixed_path(prefix);
+        self.template = template(&path);
+
+        for child in &mut self.children {
+            child.assign_templates(&path);
+        }
+    }
+
+    /// The path template of this `Node`, in the syntax of the router builder, as recorded by
+    /// `assign_templates`.
+    pub(crate) fn template(&self) -> &str {
+        &self.template
+    }
+
+    /// The names given to the path of this `Node` via `add_name`, in the order they were added.
+    pub(crate) fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// Collects a `RouteDescription` for every `Route` within this subtree, in the order they
+    /// were added to each `Node`, with parents preceding their children.
+    pub(crate) fn collect_routes(&self, prefix: &str, routes: &mut Vec<RouteDescription>) {
+        let path = self.prefixed_path(prefix);
+
+        for route in &self.routes {
+            routes.push(RouteDescription::new(
+                template(&path),
+                route.methods(),
+                self.names.clone(),
+                route.pipeline_count(),
+                route.delegation() == Delegation::External,
+                route.path_parameters(),
+                route.query_parameters(),
+            ));
+        }
+
+        for child in &self.children {
+            child.collect_routes(&path, routes);
+        }
+    }
+
+    // Appends this segment to the path of the parent `Node`, in the syntax of the router builder,
+    // followed by a `/`.
+    fn prefixed_path(&self, prefix: &str) -> String {
+        match self.segment_type {
+            _ if prefix.is_empty() => "/".to_owned(),
+            SegmentType::Static if self.segment.starts_with(|c| c == ':' || c == '*') => {
+                format!("{}\\{}/", prefix, self.segment)
+            }
+            SegmentType::Static => format!("{}{}/", prefix, self.segment),
+            SegmentType::Constrained { .. } | SegmentType::Dynamic => {
+                format!("{}:{}/", prefix, self.segment)
+            }
+            SegmentType::Glob if self.segment == "*" => format!("{}*/", prefix),
+            SegmentType::Glob => format!("{}*{}/", prefix, self.segment),
+        }
+    }
+
     /// Borrows a child `Node` based on the defined segment bounds.
     pub fn borrow_child(&self, segment: &str, segment_type: SegmentType) -> Option<&Node> {
         self.children
@@ -115,8 +214,8 @@ impl Node {
     /// Determines if a `Route` instance associated with this `Node` is willing to `Handle` the
     /// request.
     ///
-    /// Where multiple `Route` instances could possibly handle the `Request` only the first, ordered
-    /// per creation, is invoked.
+    /// Where multiple `Route` instances could possibly handle the `Request`, the one reporting the
+    /// highest `Route::quality` is invoked. Ties are won by the first, ordered per creation.
     ///
     /// Where no `Route` instances will accept the `Request` the resulting Error will be the
     /// union of the `RouteNonMatch` values returned from each `Route`.
@@ -128,15 +227,28 @@ impl Node {
         state: &State,
     ) -> Result<&Box<Route<ResBody = Body> + Send + Sync>, RouteNonMatch> {
         let mut err = Ok(());
+        let mut best: Option<(&Box<Route<ResBody = Body> + Send + Sync>, f32)> = None;
 
-        // check for matching routes
+        // check for matching routes, keeping the most preferred
         for r in self.routes.iter() {
             match r.is_match(state) {
                 Ok(()) => {
-                    trace!("[{}] found matching route", request_id(state));
-                    return Ok(r);
+                    let quality = r.quality(state);
+                    if quality >= 1.0 {
+                        request_trace!(state, "found matching route");
+                        return Ok(r);
+                    }
+
+                    if best.map_or(true, |(_, q)| quality > q) {
+                        best = Some((r, quality));
+                    }
                 }
                 Err(e) => {
+                    let e = match state.try_borrow::<Method>() {
+                        Some(method) => e.for_method(method),
+                        None => e,
+                    };
+
                     // concat errors
                     err = match err {
                         Err(e0) => Err(e.union(e0)),
@@ -146,18 +258,23 @@ impl Node {
             }
         }
 
+        if let Some((r, _)) = best {
+            request_trace!(state, "found matching route");
+            return Ok(r);
+        }
+
         // unpack required for types
         if let Err(e) = err {
-            trace!(
-                "[{}] no matching route, using error status code from route",
-                request_id(state)
+            request_trace!(
+                state,
+                "no matching route, using error status code from route"
             );
             return Err(e);
         }
 
-        trace!(
-            "[{}] invalid state, no routes. sending internal server error",
-            request_id(state)
+        request_trace!(
+            state,
+            "invalid state, no routes. sending internal server error"
         );
 
         // error because we shouldn't arrive here due to match_node/1
@@ -202,6 +319,9 @@ impl Node {
                 // Globbing matches everything, so we append the segment value
                 // to the parameters against the child segment name.
                 SegmentType::Glob => {
+                    if !is_glob_segment(segment.as_ref()) {
+                        continue;
+                    }
                     params
                         .entry(&child.segment)
                         .or_insert_with(|| vec![])
@@ -249,6 +369,9 @@ impl Node {
         // continue the nesting by just shifting the path segments and calling
         // `inner_match_node` on ourself again (to simulate wildcards).
         if let SegmentType::Glob = self.segment_type {
+            if !is_glob_segment(segment.as_ref()) {
+                return None;
+            }
             // push the segment to the parameters of the glob
             if let Some(path) = params.get_mut(self.segment()) {
                 path.push(&segment);
@@ -261,6 +384,13 @@ impl Node {
     }
 }
 
+/// Determines if a request path segment may be captured by a glob. Dot segments and segments
+/// containing a percent-encoded `/` are refused, as joining the captured values would otherwise
+/// escape the path the glob was intended to cover.
+fn is_glob_segment(segment: &str) -> bool {
+    segment != "." && segment != ".." && !segment.contains('/')
+}
+
 impl Eq for Node {}
 impl PartialEq for Node {
     /// Compares two `Node` values for equality based on the segments they represent.
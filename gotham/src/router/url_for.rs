@@ -0,0 +1,298 @@
+//! Defines `UrlFor`, which generates request paths from the names given to routes.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use hyper::header::{HeaderMap, HOST};
+use hyper::Uri;
+use url::form_urlencoded;
+use url::percent_encoding::{utf8_percent_encode, PATH_SEGMENT_ENCODE_SET};
+
+use state::{scheme, FromState, State, StateData};
+
+/// Generates paths for routes which were named when building the `Router`, via
+/// `SingleRouteBuilder::named`. A `UrlFor` value is placed into `State` by the `Router` before
+/// dispatching each request.
+///
+/// Only routes within the top-level `Router` are available; names given to routes within a
+/// delegated `Router` are not visible.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// # extern crate mime;
+/// #
+/// # use hyper::{Body, Response, StatusCode};
+/// # use gotham::helpers::http::response::create_response;
+/// # use gotham::state::{FromState, State};
+/// # use gotham::router::Router;
+/// # use gotham::router::builder::*;
+/// # use gotham::router::url_for::UrlFor;
+/// # use gotham::test::TestServer;
+/// #
+/// fn index(state: State) -> (State, Response<Body>) {
+///     let path = UrlFor::borrow_from(&state)
+///         .path("user", &[("id", "42")])
+///         .unwrap();
+///
+///     let res = create_response(&state, StatusCode::OK, mime::TEXT_PLAIN, path);
+///     (state, res)
+/// }
+/// #
+/// # fn show(state: State) -> (State, Response<Body>) {
+/// #   (state, Response::new(Body::empty()))
+/// # }
+///
+/// fn router() -> Router {
+///     build_simple_router(|route| {
+///         route.get("/").to(index);
+///         route.get("/users/:id").named("user").to(show);
+///     })
+/// }
+/// #
+/// # fn main() {
+/// #   let test_server = TestServer::new(router()).unwrap();
+/// #   let response = test_server.client()
+/// #       .get("http://example.com/")
+/// #       .perform()
+/// #       .unwrap();
+/// #   assert_eq!(response.status(), StatusCode::OK);
+/// #   assert_eq!(response.read_body().unwrap(), b"/users/42");
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct UrlFor {
+    templates: Arc<HashMap<String, String>>,
+}
+
+impl StateData for UrlFor {}
+
+impl UrlFor {
+    pub(crate) fn new(templates: HashMap<String, String>) -> UrlFor {
+        UrlFor {
+            templates: Arc::new(templates),
+        }
+    }
+
+    /// Returns the path template of the named route, e.g. `/users/:id`.
+    pub fn template(&self, name: &str) -> Option<&str> {
+        self.templates.get(name).map(|t| t.as_str())
+    }
+
+    /// Builds the path to the named route, substituting `params` for dynamic and glob segments.
+    /// Values are percent-encoded, except for `/` within a glob value which separates segments.
+    /// Empty segments within a glob value are omitted.
+    ///
+    /// Returns `None` if there is no route with the given name, or a required parameter is
+    /// missing from `params`.
+    pub fn path(&self, name: &str, params: &[(&str, &str)]) -> Option<String> {
+        let params: Vec<(&str, Vec<&str>)> = params
+            .iter()
+            .map(|&(k, v)| (k, v.split('/').collect()))
+            .collect();
+
+        fill_template(self.template(name)?, &params)
+    }
+
+    /// Builds the path to the named route as `path` does, and appends `query` as an
+    /// `application/x-www-form-urlencoded` query string.
+    pub fn path_with_query(
+        &self,
+        name: &str,
+        params: &[(&str, &str)],
+        query: &[(&str, &str)],
+    ) -> Option<String> {
+        let mut path = self.path(name, params)?;
+
+        if !query.is_empty() {
+            let query = form_urlencoded::Serializer::new(String::new())
+                .extend_pairs(query)
+                .finish();
+
+            path.push('?');
+            path.push_str(&query);
+        }
+
+        Some(path)
+    }
+
+    /// Builds an absolute URL to the named route, using the path from `path` along with the
+    /// `Scheme` of the request and the host taken from its `Host` header. When the request passed
+    /// through a proxy trusted by `ForwardedMiddleware`, the scheme is the one used by the client.
+    ///
+    /// Returns `None` in the same cases as `path`, or if the host of the request is unknown.
+    pub fn url(&self, state: &State, name: &str, params: &[(&str, &str)]) -> Option<String> {
+        let path = self.path(name, params)?;
+
+        let host = HeaderMap::try_borrow_from(state)
+            .and_then(|headers| headers.get(HOST))
+            .and_then(|host| host.to_str().ok())
+            .map(|host| host.to_owned())
+            .or_else(|| {
+                Uri::try_borrow_from(state)
+                    .and_then(|uri| uri.authority_part())
+                    .map(|authority| authority.as_str().to_owned())
+            })?;
+
+        Some(format!("{}://{}{}", scheme(state).as_str(), host, path))
+    }
+}
+
+/// Substitutes `params` into a path template using the syntax of the router builder. Returns `None`
+/// if a required parameter is missing from `params`.
+///
+/// Each parameter is given as the segments of its value. A dynamic segment is filled with the
+/// segments joined by an encoded `/`, while a glob is filled with each non-empty segment in turn,
+/// so that a `/` within a segment is always encoded and can't change the structure of the path.
+pub(crate) fn fill_template(template: &str, params: &[(&str, Vec<&str>)]) -> Option<String> {
+    if template == "/" {
+        return Some("/".to_owned());
+    }
+
+    let mut path = String::new();
+
+    for segment in template[1..].split('/') {
+        path.push('/');
+
+        match segment.chars().next() {
+            Some(':') => {
+                let value = lookup(params, &segment[1..])?.join("/");
+                path.extend(utf8_percent_encode(&value, PATH_SEGMENT_ENCODE_SET));
+            }
+            Some('*') => {
+                let key = if segment.len() == 1 {
+                    segment
+                } else {
+                    &segment[1..]
+                };
+                let encoded = lookup(params, key)?
+                    .iter()
+                    .filter(|s| !s.is_empty())
+                    .map(|s| utf8_percent_encode(s, PATH_SEGMENT_ENCODE_SET).to_string())
+                    .collect::<Vec<_>>();
+                path.push_str(&encoded.join("/"));
+            }
+            Some('\\') => path.push_str(&segment[1..]),
+            _ => path.push_str(segment),
+        }
+    }
+
+    Some(path)
+}
+
+fn lookup<'a, 'b>(params: &'a [(&str, Vec<&'b str>)], key: &str) -> Option<&'a [&'b str]> {
+    params
+        .iter()
+        .find(|&&(k, _)| k == key)
+        .map(|&(_, ref v)| v.as_slice())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use state::Scheme;
+
+    fn url_for() -> UrlFor {
+        let mut templates = HashMap::new();
+        templates.insert("root".to_owned(), "/".to_owned());
+        templates.insert("user".to_owned(), "/users/:id".to_owned());
+        templates.insert("post".to_owned(), "/users/:id/posts/:post_id".to_owned());
+        templates.insert("assets".to_owned(), "/assets/*".to_owned());
+        templates.insert("files".to_owned(), "/files/*path".to_owned());
+        templates.insert("literal".to_owned(), r"/literal/\:param".to_owned());
+        UrlFor::new(templates)
+    }
+
+    #[test]
+    fn builds_paths_from_templates() {
+        let url_for = url_for();
+
+        assert_eq!(url_for.path("root", &[]).unwrap(), "/");
+        assert_eq!(url_for.path("user", &[("id", "1")]).unwrap(), "/users/1");
+        assert_eq!(
+            url_for
+                .path("post", &[("post_id", "2"), ("id", "1")])
+                .unwrap(),
+            "/users/1/posts/2"
+        );
+        assert_eq!(
+            url_for.path("assets", &[("*", "a/b.css")]).unwrap(),
+            "/assets/a/b.css"
+        );
+        assert_eq!(
+            url_for.path("files", &[("path", "a b/c")]).unwrap(),
+            "/files/a%20b/c"
+        );
+        assert_eq!(url_for.path("literal", &[]).unwrap(), "/literal/:param");
+    }
+
+    #[test]
+    fn omits_empty_glob_segments() {
+        let url_for = url_for();
+
+        assert_eq!(
+            url_for.path("files", &[("path", "/evil.com//a/")]).unwrap(),
+            "/files/evil.com/a"
+        );
+        assert_eq!(url_for.path("files", &[("path", "")]).unwrap(), "/files/");
+    }
+
+    #[test]
+    fn encodes_slashes_within_segments() {
+        let params = vec![("path", vec!["/evil.com", "a"]), ("id", vec!["b/c"])];
+
+        assert_eq!(fill_template("/*path", &params).unwrap(), "/%2Fevil.com/a");
+        assert_eq!(fill_template("/:id", &params).unwrap(), "/b%2Fc");
+    }
+
+    #[test]
+    fn encodes_parameters() {
+        let url_for = url_for();
+
+        assert_eq!(
+            url_for.path("user", &[("id", "a/b c")]).unwrap(),
+            "/users/a%2Fb%20c"
+        );
+        assert_eq!(
+            url_for
+                .path_with_query("user", &[("id", "1")], &[("q", "a b"), ("x", "&")])
+                .unwrap(),
+            "/users/1?q=a+b&x=%26"
+        );
+    }
+
+    #[test]
+    fn missing_names_and_parameters() {
+        let url_for = url_for();
+
+        assert!(url_for.path("unknown", &[]).is_none());
+        assert!(url_for.path("user", &[]).is_none());
+        assert!(url_for.path("user", &[("other", "1")]).is_none());
+    }
+
+    #[test]
+    fn builds_urls_from_request() {
+        let url_for = url_for();
+
+        let mut state = State::new();
+        assert!(url_for.url(&state, "user", &[("id", "1")]).is_none());
+
+        state.put("http://example.com/".parse::<Uri>().unwrap());
+        assert_eq!(
+            url_for.url(&state, "user", &[("id", "1")]).unwrap(),
+            "http://example.com/users/1"
+        );
+
+        let mut headers = HeaderMap::new();
+        headers.insert(HOST, "example.org:8080".parse().unwrap());
+        state.put(headers);
+        state.put(Scheme::Https);
+        assert_eq!(
+            url_for.url(&state, "user", &[("id", "1")]).unwrap(),
+            "https://example.org:8080/users/1"
+        );
+    }
+}
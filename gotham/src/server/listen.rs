@@ -0,0 +1,107 @@
+//! Defines the addresses a server can listen on, and the connections accepted from them.
+
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+#[cfg(unix)]
+use std::path::PathBuf;
+
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+#[cfg(unix)]
+use tokio::net::UnixStream;
+
+use server::ServerBuilder;
+use service::DeferContinue;
+use state::Scheme;
+
+/// An address which a server can listen on.
+///
+/// `From` is implemented for `SocketAddr`, and for `PathBuf` on Unix platforms, so that a list of
+/// addresses can be given to `ServerBuilder::start_all` without naming this type.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ListenAddr {
+    /// A TCP socket address. IPv6 addresses only accept IPv6 connections, so the same port can be
+    /// bound for both IPv4 and IPv6.
+    Tcp(SocketAddr),
+
+    /// The path of a Unix domain socket.
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+impl From<SocketAddr> for ListenAddr {
+    fn from(addr: SocketAddr) -> ListenAddr {
+        ListenAddr::Tcp(addr)
+    }
+}
+
+#[cfg(unix)]
+impl From<PathBuf> for ListenAddr {
+    fn from(path: PathBuf) -> ListenAddr {
+        ListenAddr::Unix(path)
+    }
+}
+
+impl fmt::Display for ListenAddr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ListenAddr::Tcp(ref addr) => write!(f, "http://{}", addr),
+            #[cfg(unix)]
+            ListenAddr::Unix(ref path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+/// A connection accepted by a listener.
+pub(crate) trait Connection: AsyncRead + AsyncWrite + Send + 'static {
+    /// The address of the client, if the transport has one.
+    fn client_addr(&self) -> Option<SocketAddr>;
+
+    /// The scheme which the client connected with, if the transport knows it.
+    fn scheme(&self) -> Option<Scheme> {
+        None
+    }
+
+    /// Applies the socket options set on the `ServerBuilder`.
+    fn configure(&self, builder: &ServerBuilder) -> io::Result<()>;
+}
+
+impl Connection for TcpStream {
+    fn client_addr(&self) -> Option<SocketAddr> {
+        self.peer_addr().ok()
+    }
+
+    fn configure(&self, builder: &ServerBuilder) -> io::Result<()> {
+        self.set_nodelay(builder.nodelay)?;
+        self.set_keepalive(builder.tcp_keepalive)
+    }
+}
+
+impl<T> Connection for DeferContinue<T>
+where
+    T: Connection,
+{
+    fn client_addr(&self) -> Option<SocketAddr> {
+        self.get_ref().client_addr()
+    }
+
+    fn scheme(&self) -> Option<Scheme> {
+        self.get_ref().scheme()
+    }
+
+    fn configure(&self, builder: &ServerBuilder) -> io::Result<()> {
+        self.get_ref().configure(builder)
+    }
+}
+
+#[cfg(unix)]
+impl Connection for UnixStream {
+    fn client_addr(&self) -> Option<SocketAddr> {
+        None
+    }
+
+    fn configure(&self, _builder: &ServerBuilder) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,811 @@
+//! Defines the `ServerBuilder` type, which configures the listening socket, accepted connections
+//! and HTTP protocol used to serve a Gotham application.
+
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::{future, Future, Stream};
+use hyper::server::conn::Http;
+use log::Log;
+use net2::TcpBuilder;
+use tokio::executor;
+use tokio::net::TcpListener;
+#[cfg(unix)]
+use tokio::net::UnixListener;
+use tokio::reactor::Handle;
+use tokio::runtime::{self, Runtime, TaskExecutor};
+
+use client::HttpClient;
+use config::Config;
+use events::{ConnectionInfo, Event, EventBus, Subscriber};
+use handler::NewHandler;
+use helpers::clock::Clock;
+use helpers::random::RandomSource;
+use reporting::ErrorReporter;
+use service::{ContinueGate, DeferContinue, GothamService, RequestLimits};
+use task::Scheduler;
+
+mod listen;
+mod shutdown;
+
+pub(crate) use self::listen::Connection;
+pub use self::listen::ListenAddr;
+use self::shutdown::{GracefulConnection, Hook, Shutdown};
+
+/// Configures and starts a Gotham server.
+///
+/// The `gotham::start` family of functions use the default configuration, which is suitable for
+/// most applications. `ServerBuilder` allows the listening socket, accepted connections and the
+/// HTTP protocol to be tuned for a particular deployment.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// #
+/// # use std::time::Duration;
+/// # use hyper::{Body, Response};
+/// # use gotham::ServerBuilder;
+/// # use gotham::state::State;
+/// #
+/// fn hello(state: State) -> (State, Response<Body>) {
+///     (state, Response::new(Body::from("Hello, world!")))
+/// }
+///
+/// # fn main() {
+/// ServerBuilder::new()
+///     .with_threads(4)
+///     .with_backlog(2048)
+///     .with_nodelay(true)
+///     .with_tcp_keepalive(Some(Duration::from_secs(60)))
+///     .with_max_connections(10_000)
+///     .start("127.0.0.1:7878", || Ok(hello));
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct ServerBuilder {
+    threads: usize,
+    backlog: i32,
+    nodelay: bool,
+    tcp_keepalive: Option<Duration>,
+    max_connections: Option<usize>,
+    http: Http,
+    limits: RequestLimits,
+    signals: bool,
+    shutdown_signal: Option<Shutdown>,
+    shutdown_hooks: Vec<Hook>,
+    reload_hooks: Vec<Hook>,
+    scheduler: Option<Scheduler>,
+    logger: Option<Arc<Log>>,
+    error_reporter: Option<Arc<ErrorReporter>>,
+    clock: Option<Arc<Clock>>,
+    random_source: Option<Arc<RandomSource>>,
+    http_client: Option<HttpClient>,
+    event_subscribers: Vec<Arc<Subscriber>>,
+}
+
+impl Default for ServerBuilder {
+    fn default() -> ServerBuilder {
+        ServerBuilder {
+            threads: ::num_cpus::get(),
+            backlog: 1024,
+            nodelay: false,
+            tcp_keepalive: None,
+            max_connections: None,
+            http: Http::new(),
+            limits: RequestLimits::default(),
+            signals: false,
+            shutdown_signal: None,
+            shutdown_hooks: Vec::new(),
+            reload_hooks: Vec::new(),
+            scheduler: None,
+            logger: None,
+            error_reporter: None,
+            clock: None,
+            random_source: None,
+            http_client: None,
+            event_subscribers: Vec::new(),
+        }
+    }
+}
+
+impl ServerBuilder {
+    /// Creates a `ServerBuilder` with the default configuration: one thread per CPU, a listen
+    /// backlog of 1024, and no limit on the number of connections.
+    pub fn new() -> ServerBuilder {
+        ServerBuilder::default()
+    }
+
+    /// Sets the number of threads used to serve connections, when the server is started with
+    /// `start`.
+    pub fn with_threads(self, threads: usize) -> ServerBuilder {
+        ServerBuilder { threads, ..self }
+    }
+
+    /// Sets the maximum number of pending connections which the operating system will queue for
+    /// the listening socket.
+    pub fn with_backlog(self, backlog: i32) -> ServerBuilder {
+        ServerBuilder { backlog, ..self }
+    }
+
+    /// Sets the `TCP_NODELAY` option on accepted connections, disabling Nagle's algorithm.
+    pub fn with_nodelay(self, nodelay: bool) -> ServerBuilder {
+        ServerBuilder { nodelay, ..self }
+    }
+
+    /// Sets the TCP keep-alive interval of accepted connections, or disables TCP keep-alive if
+    /// `None`.
+    pub fn with_tcp_keepalive(self, tcp_keepalive: Option<Duration>) -> ServerBuilder {
+        ServerBuilder {
+            tcp_keepalive,
+            ..self
+        }
+    }
+
+    /// Sets whether HTTP/1 connections are kept alive between requests.
+    pub fn with_http_keep_alive(mut self, keep_alive: bool) -> ServerBuilder {
+        self.http.keep_alive(keep_alive);
+        self
+    }
+
+    /// Sets the maximum number of connections which are served at once. Connections accepted
+    /// while at the limit are closed immediately.
+    pub fn with_max_connections(self, max_connections: usize) -> ServerBuilder {
+        ServerBuilder {
+            max_connections: Some(max_connections),
+            ..self
+        }
+    }
+
+    /// Sets the maximum length of the target of a request, which is its path and query string, or
+    /// the full URI when one is sent in the request line. Longer requests are rejected with
+    /// `414 URI Too Long` before reaching the handler.
+    pub fn with_max_uri_length(mut self, max_uri_length: usize) -> ServerBuilder {
+        self.limits.max_uri_length = Some(max_uri_length);
+        self
+    }
+
+    /// Sets the maximum number of bytes of headers in a request, counting the name and value of
+    /// each header along with its separators. Requests with more are rejected with
+    /// `431 Request Header Fields Too Large` before reaching the handler.
+    ///
+    /// When the URI length is also limited, hyper's read buffer is sized to match, so that a
+    /// request far over the limits is rejected before it has been fully read.
+    pub fn with_max_header_bytes(mut self, max_header_bytes: usize) -> ServerBuilder {
+        self.limits.max_header_bytes = Some(max_header_bytes);
+        self
+    }
+
+    /// Sets the maximum number of headers in a request. Requests with more are rejected with
+    /// `431 Request Header Fields Too Large` before reaching the handler. Regardless of this
+    /// limit, hyper rejects requests with more than 100 headers.
+    pub fn with_max_headers(mut self, max_headers: usize) -> ServerBuilder {
+        self.limits.max_headers = Some(max_headers);
+        self
+    }
+
+    /// Sets the hyper `Http` used to serve each connection, which allows any of its protocol
+    /// options to be configured.
+    pub fn with_http(self, http: Http) -> ServerBuilder {
+        ServerBuilder { http, ..self }
+    }
+
+    /// Applies the settings of a `Config` which are given: the number of threads, backlog,
+    /// maximum number of connections and TCP keep-alive interval. The addresses of the `Config`
+    /// are given to `start_all` or `init_all`.
+    pub fn with_config(self, config: &Config) -> ServerBuilder {
+        ServerBuilder {
+            threads: config.threads().unwrap_or(self.threads),
+            backlog: config.backlog().unwrap_or(self.backlog),
+            max_connections: config.max_connections().or(self.max_connections),
+            tcp_keepalive: config.tcp_keepalive().or(self.tcp_keepalive),
+            ..self
+        }
+    }
+
+    /// Sets whether the server shuts down gracefully when the process receives `SIGTERM` or
+    /// `SIGINT` (or Ctrl-C on platforms without Unix signals), and runs the reload hooks when it
+    /// receives `SIGHUP`.
+    ///
+    /// During a graceful shutdown the server stops accepting connections, and each open
+    /// connection is closed once any request in progress has been responded to. When the server
+    /// was started with `start` or `start_all`, the shutdown hooks are run once every connection
+    /// has closed, and then `start` returns.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # extern crate gotham;
+    /// # extern crate hyper;
+    /// #
+    /// # use hyper::{Body, Response};
+    /// # use gotham::ServerBuilder;
+    /// # use gotham::state::State;
+    /// #
+    /// fn hello(state: State) -> (State, Response<Body>) {
+    ///     (state, Response::new(Body::from("Hello, world!")))
+    /// }
+    ///
+    /// # fn main() {
+    /// ServerBuilder::new()
+    ///     .with_signal_handling(true)
+    ///     .with_reload_hook(|| println!("reloading configuration"))
+    ///     .with_shutdown_hook(|| println!("flushing sessions"))
+    ///     .start("127.0.0.1:7878", || Ok(hello));
+    /// # }
+    /// ```
+    pub fn with_signal_handling(self, signals: bool) -> ServerBuilder {
+        ServerBuilder { signals, ..self }
+    }
+
+    /// Sets a future which begins a graceful shutdown of the server when it completes, in addition
+    /// to any signals enabled by `with_signal_handling`.
+    pub fn with_shutdown_signal<F>(self, signal: F) -> ServerBuilder
+    where
+        F: Future<Item = (), Error = ()> + Send + 'static,
+    {
+        let signal: Box<Future<Item = (), Error = ()> + Send> = Box::new(signal);
+
+        ServerBuilder {
+            shutdown_signal: Some(signal.shared()),
+            ..self
+        }
+    }
+
+    /// Adds a hook which is run once the server has shut down gracefully, such as to persist
+    /// in-memory data. Hooks are run in the order they were added.
+    pub fn with_shutdown_hook<F>(mut self, hook: F) -> ServerBuilder
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        self.shutdown_hooks.push(Arc::new(hook));
+        self
+    }
+
+    /// Adds a hook which is run each time the process receives `SIGHUP`, such as to reload
+    /// configuration. Hooks are only run when signal handling is enabled.
+    pub fn with_reload_hook<F>(mut self, hook: F) -> ServerBuilder
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        self.reload_hooks.push(Arc::new(hook));
+        self
+    }
+
+    /// Sets the `Scheduler` whose tasks run while the server is running. The tasks start with the
+    /// server, and stop during a graceful shutdown once any run in progress has completed.
+    pub fn with_scheduler(self, scheduler: Scheduler) -> ServerBuilder {
+        ServerBuilder {
+            scheduler: Some(scheduler),
+            ..self
+        }
+    }
+
+    /// Sets the logger which lines about each request are written to by the `request_*!` macros,
+    /// instead of the global logger of the `log` crate. This allows an application to send
+    /// request logs to a different destination than other logs, or to use a logger without
+    /// installing it globally.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # extern crate gotham;
+    /// # extern crate hyper;
+    /// # extern crate log;
+    /// #
+    /// # use hyper::{Body, Response};
+    /// # use log::{Log, Metadata, Record};
+    /// # use gotham::ServerBuilder;
+    /// # use gotham::state::State;
+    /// #
+    /// struct StderrLogger;
+    ///
+    /// impl Log for StderrLogger {
+    ///     fn enabled(&self, metadata: &Metadata) -> bool {
+    ///         metadata.level() <= log::Level::Info
+    ///     }
+    ///
+    ///     fn log(&self, record: &Record) {
+    ///         eprintln!("{} {}", record.level(), record.args());
+    ///     }
+    ///
+    ///     fn flush(&self) {}
+    /// }
+    ///
+    /// fn hello(state: State) -> (State, Response<Body>) {
+    ///     (state, Response::new(Body::from("Hello, world!")))
+    /// }
+    ///
+    /// # fn main() {
+    /// ServerBuilder::new()
+    ///     .with_logger(StderrLogger)
+    ///     .start("127.0.0.1:7878", || Ok(hello));
+    /// # }
+    /// ```
+    pub fn with_logger<L>(self, logger: L) -> ServerBuilder
+    where
+        L: Log + 'static,
+    {
+        ServerBuilder {
+            logger: Some(Arc::new(logger)),
+            ..self
+        }
+    }
+
+    /// Sets the `ErrorReporter` which is told about each request that fails with an error or
+    /// panics, such as to send the details to an error tracking service.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # extern crate gotham;
+    /// # extern crate hyper;
+    /// #
+    /// # use hyper::{Body, Response};
+    /// # use gotham::ServerBuilder;
+    /// # use gotham::reporting::ErrorReport;
+    /// # use gotham::state::State;
+    /// #
+    /// fn hello(state: State) -> (State, Response<Body>) {
+    ///     (state, Response::new(Body::from("Hello, world!")))
+    /// }
+    ///
+    /// # fn main() {
+    /// ServerBuilder::new()
+    ///     .with_error_reporter(|report: &ErrorReport| {
+    ///         eprintln!("{} failed: {}", report.context(), report.error());
+    ///     })
+    ///     .start("127.0.0.1:7878", || Ok(hello));
+    /// # }
+    /// ```
+    pub fn with_error_reporter<R>(self, reporter: R) -> ServerBuilder
+    where
+        R: ErrorReporter + 'static,
+    {
+        ServerBuilder {
+            error_reporter: Some(Arc::new(reporter)),
+            ..self
+        }
+    }
+
+    /// Sets the `Clock` which the time is read from while handling requests, instead of the system
+    /// clock. This is intended for tests, which can control the time with a `ManualClock`. See the
+    /// `helpers::clock` module for details.
+    pub fn with_clock<C>(self, clock: C) -> ServerBuilder
+    where
+        C: Clock + 'static,
+    {
+        ServerBuilder {
+            clock: Some(Arc::new(clock)),
+            ..self
+        }
+    }
+
+    /// Sets the `RandomSource` which random bytes are read from while handling requests, instead
+    /// of a secure random number generator. This is intended for tests, which can make generated
+    /// values reproducible with a `SeededRandom`. See the `helpers::random` module for details.
+    pub fn with_random_source<R>(self, random_source: R) -> ServerBuilder
+    where
+        R: RandomSource + 'static,
+    {
+        ServerBuilder {
+            random_source: Some(Arc::new(random_source)),
+            ..self
+        }
+    }
+
+    /// Sets the `HttpClient` which is placed into `State` for each request, so that handlers share
+    /// its connection pool when making requests to other services. See the `client` module for
+    /// details.
+    pub fn with_http_client(self, http_client: HttpClient) -> ServerBuilder {
+        ServerBuilder {
+            http_client: Some(http_client),
+            ..self
+        }
+    }
+
+    /// Adds a `Subscriber` which receives the events published as requests and connections are
+    /// handled. Subscribers are notified in the order they were added. See the `events` module
+    /// for details.
+    pub fn with_event_subscriber<S>(mut self, subscriber: S) -> ServerBuilder
+    where
+        S: Subscriber + 'static,
+    {
+        self.event_subscribers.push(Arc::new(subscriber));
+        self
+    }
+
+    /// Starts the server on a new `Runtime`, blocking the current thread until it has stopped.
+    pub fn start<NH, A>(self, addr: A, new_handler: NH)
+    where
+        NH: NewHandler + 'static,
+        A: ToSocketAddrs + 'static,
+    {
+        let runtime = new_runtime(self.threads);
+        let hooks = self.shutdown_hooks.clone();
+
+        self.start_on_executor(addr, new_handler, runtime.executor());
+        runtime.shutdown_on_idle().wait().unwrap();
+
+        run_shutdown_hooks(&hooks);
+    }
+
+    /// Starts the server on an existing `TaskExecutor`. The number of threads set with
+    /// `with_threads` is not used.
+    pub fn start_on_executor<NH, A>(self, addr: A, new_handler: NH, executor: TaskExecutor)
+    where
+        NH: NewHandler + 'static,
+        A: ToSocketAddrs + 'static,
+    {
+        executor.spawn(self.init(addr, new_handler));
+    }
+
+    /// Binds the listening socket, and returns a `Future` which serves connections accepted on it.
+    pub fn init<NH, A>(self, addr: A, new_handler: NH) -> impl Future<Item = (), Error = ()>
+    where
+        NH: NewHandler + 'static,
+        A: ToSocketAddrs + 'static,
+    {
+        let addr = match addr.to_socket_addrs().map(|ref mut i| i.next()) {
+            Ok(Some(a)) => a,
+            Ok(_) => panic!("unable to resolve listener address"),
+            Err(_) => panic!("unable to parse listener address"),
+        };
+
+        self.init_all(vec![addr], new_handler)
+    }
+
+    /// Starts the server on a new `Runtime`, serving the same application on each of `addrs`, and
+    /// blocks the current thread until it has stopped.
+    ///
+    /// This allows an application to listen on both IPv4 and IPv6 addresses, or on a Unix domain
+    /// socket alongside a TCP socket, from a single process.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # extern crate gotham;
+    /// # extern crate hyper;
+    /// #
+    /// # use std::net::SocketAddr;
+    /// # use std::path::PathBuf;
+    /// # use hyper::{Body, Response};
+    /// # use gotham::ServerBuilder;
+    /// # use gotham::server::ListenAddr;
+    /// # use gotham::state::State;
+    /// #
+    /// fn hello(state: State) -> (State, Response<Body>) {
+    ///     (state, Response::new(Body::from("Hello, world!")))
+    /// }
+    ///
+    /// # fn main() {
+    /// let addrs: Vec<ListenAddr> = vec![
+    ///     "0.0.0.0:7878".parse::<SocketAddr>().unwrap().into(),
+    ///     "[::]:7878".parse::<SocketAddr>().unwrap().into(),
+    ///     PathBuf::from("/tmp/gotham.sock").into(),
+    /// ];
+    ///
+    /// ServerBuilder::new().start_all(addrs, || Ok(hello));
+    /// # }
+    /// ```
+    pub fn start_all<NH, I>(self, addrs: I, new_handler: NH)
+    where
+        NH: NewHandler + 'static,
+        I: IntoIterator,
+        I::Item: Into<ListenAddr>,
+    {
+        let runtime = new_runtime(self.threads);
+        let hooks = self.shutdown_hooks.clone();
+
+        // collected so that the spawned `Future` doesn't borrow from `I`
+        let addrs: Vec<ListenAddr> = addrs.into_iter().map(Into::into).collect();
+        runtime.executor().spawn(self.init_all(addrs, new_handler));
+        runtime.shutdown_on_idle().wait().unwrap();
+
+        run_shutdown_hooks(&hooks);
+    }
+
+    /// Binds a listener for each of `addrs`, and returns a `Future` which serves the same
+    /// application on connections accepted by any of them. The limit set by
+    /// `with_max_connections` applies to the total across all listeners.
+    ///
+    /// The `Future` completes once the server has stopped accepting connections during a
+    /// graceful shutdown and scheduled tasks have stopped, while connections may still be closing.
+    /// Shutdown hooks are not run.
+    pub fn init_all<NH, I>(self, addrs: I, new_handler: NH) -> impl Future<Item = (), Error = ()>
+    where
+        NH: NewHandler + 'static,
+        I: IntoIterator,
+        I::Item: Into<ListenAddr>,
+    {
+        let mut triggers = Vec::new();
+        if self.signals {
+            triggers.push(shutdown::termination());
+        }
+        if let Some(ref signal) = self.shutdown_signal {
+            let signal: Box<Future<Item = (), Error = ()> + Send> =
+                Box::new(signal.clone().then(|_| Ok(())));
+            triggers.push(signal);
+        }
+        let shutdown = shutdown::shutdown(triggers);
+
+        let reload: Box<Future<Item = (), Error = ()> + Send> = if self.signals {
+            shutdown::reload(self.reload_hooks.clone(), shutdown.clone())
+        } else {
+            Box::new(future::ok(()))
+        };
+
+        let tasks: Box<Future<Item = (), Error = ()> + Send> = match self.scheduler {
+            Some(ref scheduler) => scheduler.run(shutdown.clone()),
+            None => Box::new(future::ok(())),
+        };
+
+        let service = GothamService::new(new_handler)
+            .with_logger(self.logger.clone())
+            .with_error_reporter(self.error_reporter.clone())
+            .with_clock(self.clock.clone())
+            .with_random_source(self.random_source.clone())
+            .with_http_client(self.http_client.clone())
+            .with_events(EventBus::new(&self.event_subscribers))
+            .with_limits(self.limits);
+        let builder = Arc::new(self);
+        let connections = Arc::new(AtomicUsize::new(0));
+
+        let servers = addrs
+            .into_iter()
+            .map(Into::into)
+            .map(|addr| {
+                info!(
+                    target: "gotham::start",
+                    " Gotham listening on {}",
+                    addr
+                );
+
+                match addr {
+                    ListenAddr::Tcp(addr) => {
+                        let listener = builder
+                            .tcp_listener(addr)
+                            .expect("unable to open TCP listener");
+                        serve(
+                            builder.clone(),
+                            listener.incoming(),
+                            service.clone(),
+                            connections.clone(),
+                            shutdown.clone(),
+                        )
+                    }
+                    #[cfg(unix)]
+                    ListenAddr::Unix(path) => {
+                        let listener =
+                            UnixListener::bind(&path).expect("unable to open Unix listener");
+                        serve(
+                            builder.clone(),
+                            listener.incoming(),
+                            service.clone(),
+                            connections.clone(),
+                            shutdown.clone(),
+                        )
+                    }
+                }
+            })
+            .collect::<Vec<_>>();
+
+        future::join_all(servers).join3(reload, tasks).map(|_| ())
+    }
+
+    /// Returns a `Future` which serves connections accepted on `listener`.
+    pub(crate) fn bind<NH>(
+        self,
+        listener: TcpListener,
+        new_handler: NH,
+    ) -> impl Future<Item = (), Error = ()>
+    where
+        NH: NewHandler + 'static,
+    {
+        self.bind_incoming(listener.incoming(), new_handler)
+    }
+
+    /// Returns a `Future` which serves the connections from `incoming`.
+    pub(crate) fn bind_incoming<NH, S>(
+        self,
+        incoming: S,
+        new_handler: NH,
+    ) -> impl Future<Item = (), Error = ()>
+    where
+        NH: NewHandler + 'static,
+        S: Stream<Error = io::Error> + Send + 'static,
+        S::Item: Connection,
+    {
+        let service = GothamService::new(new_handler)
+            .with_logger(self.logger.clone())
+            .with_error_reporter(self.error_reporter.clone())
+            .with_clock(self.clock.clone())
+            .with_random_source(self.random_source.clone())
+            .with_http_client(self.http_client.clone())
+            .with_events(EventBus::new(&self.event_subscribers));
+
+        serve(
+            Arc::new(self),
+            incoming,
+            service,
+            Arc::new(AtomicUsize::new(0)),
+            shutdown::shutdown(Vec::new()),
+        )
+    }
+
+    fn tcp_listener(&self, addr: SocketAddr) -> io::Result<TcpListener> {
+        self.bind_std(addr)
+            .and_then(|l| TcpListener::from_std(l, &Handle::default()))
+    }
+
+    fn bind_std(&self, addr: SocketAddr) -> io::Result<::std::net::TcpListener> {
+        let builder = match addr {
+            SocketAddr::V4(_) => TcpBuilder::new_v4()?,
+            SocketAddr::V6(_) => {
+                let builder = TcpBuilder::new_v6()?;
+                builder.only_v6(true)?;
+                builder
+            }
+        };
+
+        builder.reuse_address(true)?;
+        builder.bind(addr)?;
+        builder.listen(self.backlog)
+    }
+}
+
+// Serves each connection from `incoming` on the executor, while the number of open connections
+// (shared between all listeners) is within the limit, until `shutdown` completes.
+fn serve<NH, S>(
+    builder: Arc<ServerBuilder>,
+    incoming: S,
+    gotham_service: GothamService<NH>,
+    connections: Arc<AtomicUsize>,
+    shutdown: Shutdown,
+) -> Box<Future<Item = (), Error = ()> + Send>
+where
+    NH: NewHandler + 'static,
+    S: Stream<Error = io::Error> + Send + 'static,
+    S::Item: Connection,
+{
+    let mut protocol = builder.http.clone();
+    if let Some(size) = builder.limits.buffer_size() {
+        protocol.max_buf_size(size);
+    }
+
+    let events = EventBus::new(&builder.event_subscribers);
+
+    let stop = shutdown.clone().then(|_| {
+        info!(target: "gotham::start", " Gotham is no longer accepting connections");
+        Ok(())
+    });
+
+    let f = incoming
+        .map_err(|e| panic!("socket error = {:?}", e))
+        .for_each(move |socket| {
+            if let Some(max) = builder.max_connections {
+                if connections.load(Ordering::SeqCst) >= max {
+                    warn!(" closing connection, {} connections are already open", max);
+                    return Ok(());
+                }
+            }
+
+            if let Err(e) = socket.configure(&builder) {
+                warn!(" unable to configure accepted connection: {}", e);
+            }
+
+            let client_addr = socket.client_addr();
+            let gate = ContinueGate::new();
+            let service = match client_addr {
+                Some(addr) => gotham_service.connect(addr),
+                None => gotham_service.connect_local(),
+            }
+            .with_scheme(socket.scheme())
+            .with_continue_gate(gate.clone());
+
+            connections.fetch_add(1, Ordering::SeqCst);
+            let open = connections.clone();
+            let closed = events
+                .clone()
+                .map(|events| (events, ConnectionInfo::new(client_addr)));
+
+            // upgraded connections, such as to WebSocket, are handed to the handler once the
+            // response has been written
+            let conn = protocol
+                .serve_connection(DeferContinue::new(socket, gate), service)
+                .with_upgrades();
+            let conn =
+                GracefulConnection::new(conn, |conn| conn.graceful_shutdown(), shutdown.clone());
+            let handler = conn.then(move |_| {
+                open.fetch_sub(1, Ordering::SeqCst);
+                if let Some((events, connection)) = closed {
+                    events.publish(&Event::ConnectionClosed(&connection));
+                }
+                Ok(())
+            });
+
+            executor::spawn(handler);
+
+            Ok(())
+        })
+        .select(stop)
+        .then(|_| Ok(()));
+
+    Box::new(f)
+}
+
+fn run_shutdown_hooks(hooks: &[Hook]) {
+    for hook in hooks {
+        hook();
+    }
+}
+
+fn new_runtime(threads: usize) -> Runtime {
+    runtime::Builder::new()
+        .core_threads(threads)
+        .name_prefix("gotham-worker-")
+        .build()
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use hyper::{Body, Response};
+
+    use state::State;
+
+    fn handler(state: State) -> (State, Response<Body>) {
+        (state, Response::new(Body::empty()))
+    }
+
+    #[test]
+    fn binds_with_options() {
+        let builder = ServerBuilder::new()
+            .with_backlog(16)
+            .with_nodelay(true)
+            .with_tcp_keepalive(Some(Duration::from_secs(30)));
+
+        let listener = builder.bind_std("127.0.0.1:0".parse().unwrap()).unwrap();
+        assert!(listener.local_addr().unwrap().port() != 0);
+
+        let mut runtime = Runtime::new().unwrap();
+        let listener = TcpListener::from_std(listener, &Handle::default()).unwrap();
+        runtime.spawn(builder.bind(listener, || Ok(handler)));
+        runtime.shutdown_now().wait().unwrap();
+    }
+
+    #[test]
+    fn serves_multiple_addresses() {
+        let addrs: Vec<ListenAddr> = vec![
+            "127.0.0.1:0".parse::<SocketAddr>().unwrap().into(),
+            "127.0.0.1:0".parse::<SocketAddr>().unwrap().into(),
+        ];
+        assert_eq!(addrs[0].to_string(), "http://127.0.0.1:0");
+
+        let mut runtime = Runtime::new().unwrap();
+        runtime.spawn(ServerBuilder::new().init_all(addrs, || Ok(handler)));
+        runtime.shutdown_now().wait().unwrap();
+    }
+
+    #[test]
+    fn stops_accepting_on_shutdown_signal() {
+        let (tx, rx) = ::futures::sync::oneshot::channel();
+        let server = ServerBuilder::new()
+            .with_shutdown_signal(rx.map_err(|_| ()))
+            .init_all(vec!["127.0.0.1:0".parse::<SocketAddr>().unwrap()], || {
+                Ok(handler)
+            });
+
+        tx.send(()).unwrap();
+
+        let mut runtime = Runtime::new().unwrap();
+        runtime.block_on(server).unwrap();
+        runtime.shutdown_on_idle().wait().unwrap();
+    }
+}
@@ -0,0 +1,156 @@
+//! Defines the signals which begin a graceful shutdown of the server, and the hooks which are run
+//! in response to signals.
+
+use std::io;
+use std::sync::Arc;
+
+use futures::future::{self, Either, Shared};
+use futures::{Async, Future, Poll, Stream};
+use tokio_signal::IoFuture;
+
+/// A hook which is run by the server in response to a signal.
+pub(crate) type Hook = Arc<Fn() + Send + Sync>;
+
+/// A future which completes when the server should shut down, shared between all listeners and
+/// connections.
+pub(crate) type Shutdown = Shared<Box<Future<Item = (), Error = ()> + Send>>;
+
+/// Combines the `triggers` into a single `Shutdown`, which completes when any of them does. When
+/// there are no triggers, the `Shutdown` never completes.
+pub(crate) fn shutdown(triggers: Vec<Box<Future<Item = (), Error = ()> + Send>>) -> Shutdown {
+    let f: Box<Future<Item = (), Error = ()> + Send> = if triggers.is_empty() {
+        Box::new(future::empty())
+    } else {
+        Box::new(future::select_all(triggers).then(|_| Ok(())))
+    };
+
+    f.shared()
+}
+
+/// Returns a future which completes when the process receives `SIGTERM` or `SIGINT`.
+#[cfg(unix)]
+pub(crate) fn termination() -> Box<Future<Item = (), Error = ()> + Send> {
+    use tokio_signal::unix::{Signal, SIGINT, SIGTERM};
+
+    let signals = vec![
+        first(Signal::new(SIGTERM), "SIGTERM"),
+        first(Signal::new(SIGINT), "SIGINT"),
+    ];
+
+    Box::new(future::select_all(signals).map(|_| ()).map_err(|_| ()))
+}
+
+/// Returns a future which completes when the process receives Ctrl-C.
+#[cfg(not(unix))]
+pub(crate) fn termination() -> Box<Future<Item = (), Error = ()> + Send> {
+    first(::tokio_signal::ctrl_c(), "Ctrl-C")
+}
+
+/// Returns a future which runs each of the `hooks` whenever the process receives `SIGHUP`, until
+/// `shutdown` completes.
+#[cfg(unix)]
+pub(crate) fn reload(
+    hooks: Vec<Hook>,
+    shutdown: Shutdown,
+) -> Box<Future<Item = (), Error = ()> + Send> {
+    use tokio_signal::unix::{Signal, SIGHUP};
+
+    if hooks.is_empty() {
+        return Box::new(future::ok(()));
+    }
+
+    let f = Signal::new(SIGHUP)
+        .flatten_stream()
+        .for_each(move |_| {
+            info!(target: "gotham::start", " received SIGHUP, running reload hooks");
+            for hook in &hooks {
+                hook();
+            }
+            Ok(())
+        })
+        .map_err(|e| warn!(" unable to listen for SIGHUP: {}", e))
+        .select2(shutdown)
+        .then(|_| Ok(()));
+
+    Box::new(f)
+}
+
+/// Platforms without Unix signals have no `SIGHUP`, so the hooks are never run.
+#[cfg(not(unix))]
+pub(crate) fn reload(
+    _hooks: Vec<Hook>,
+    _shutdown: Shutdown,
+) -> Box<Future<Item = (), Error = ()> + Send> {
+    Box::new(future::ok(()))
+}
+
+// Completes when the first signal is received from `signal`. If the signal cannot be listened
+// for, a warning is logged and the future never completes.
+fn first<S>(signal: IoFuture<S>, name: &'static str) -> Box<Future<Item = (), Error = ()> + Send>
+where
+    S: Stream<Error = io::Error> + Send + 'static,
+{
+    let f = signal
+        .flatten_stream()
+        .into_future()
+        .then(move |result| match result {
+            Ok(_) => {
+                info!(target: "gotham::start", " received {}, shutting down", name);
+                Either::A(future::ok(()))
+            }
+            Err((e, _)) => {
+                warn!(" unable to listen for {}: {}", name, e);
+                Either::B(future::empty())
+            }
+        });
+
+    Box::new(f)
+}
+
+/// Serves a connection until it completes, asking it to close once any in-flight request has been
+/// responded to when shutdown begins.
+pub(crate) struct GracefulConnection<C> {
+    conn: C,
+    drain: fn(&mut C),
+    shutdown: Option<Shutdown>,
+}
+
+impl<C> GracefulConnection<C>
+where
+    C: Future<Item = (), Error = ::hyper::Error>,
+{
+    /// Creates a `GracefulConnection` serving `conn`, where `drain` asks `conn` to close. It is
+    /// given separately, as the hyper connection which supports upgrades can't be named.
+    pub(crate) fn new(conn: C, drain: fn(&mut C), shutdown: Shutdown) -> GracefulConnection<C> {
+        GracefulConnection {
+            conn,
+            drain,
+            shutdown: Some(shutdown),
+        }
+    }
+}
+
+impl<C> Future for GracefulConnection<C>
+where
+    C: Future<Item = (), Error = ::hyper::Error>,
+{
+    type Item = ();
+    type Error = ::hyper::Error;
+
+    fn poll(&mut self) -> Poll<(), ::hyper::Error> {
+        let shutting_down = match self.shutdown {
+            Some(ref mut shutdown) => match shutdown.poll() {
+                Ok(Async::NotReady) => false,
+                _ => true,
+            },
+            None => false,
+        };
+
+        if shutting_down {
+            self.shutdown = None;
+            (self.drain)(&mut self.conn);
+        }
+
+        self.conn.poll()
+    }
+}
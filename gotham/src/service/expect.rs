@@ -0,0 +1,283 @@
+//! Defines `ContinueGate`, which defers the `100 Continue` response to a request carrying
+//! `Expect: 100-continue` until the handler reads the request body.
+//!
+//! hyper writes `100 Continue` as soon as it has parsed the head of such a request, which would
+//! have the client send a body that the handler may never read. Instead, the connection is
+//! wrapped in a `DeferContinue`, which withholds the `100 Continue` written by hyper, and the
+//! request `Body` is watched by the `ContinueGate`, so that the `100 Continue` is only written
+//! once the `Body` is polled. Where the handler responds without reading the `Body`, the
+//! `100 Continue` is dropped and the client never sends the body.
+
+use std::io::{self, Read, Write};
+use std::sync::{Arc, Mutex};
+
+use bytes::Buf;
+use futures::task::AtomicTask;
+use futures::{Async, Poll, Stream};
+use hyper::{Body, Chunk};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+// The interim response which hyper writes for a request with `Expect: 100-continue`.
+const CONTINUE: &[u8] = b"HTTP/1.1 100 Continue\r\n\r\n";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Stage {
+    // No `100 Continue` is expected from hyper.
+    Idle,
+
+    // The request expects `100 Continue`, which hyper hasn't written yet.
+    Armed { read: bool },
+
+    // hyper has written `100 Continue`, which is withheld as the `Body` hasn't been read.
+    Withheld,
+
+    // The `Body` has been read, so the withheld `100 Continue` is due, of which `n` bytes have
+    // been written.
+    Due(usize),
+}
+
+/// Tracks the `100 Continue` response for the requests made over a single connection, which is
+/// shared between the connection and the request `Body` given to the handler.
+#[derive(Clone)]
+pub(crate) struct ContinueGate {
+    stage: Arc<Mutex<Stage>>,
+    task: Arc<AtomicTask>,
+}
+
+impl ContinueGate {
+    pub(crate) fn new() -> ContinueGate {
+        ContinueGate {
+            stage: Arc::new(Mutex::new(Stage::Idle)),
+            task: Arc::new(AtomicTask::new()),
+        }
+    }
+
+    /// Prepares to withhold the `100 Continue` for a request expecting it, and returns its `Body`
+    /// watched so that the `100 Continue` is released when it is read.
+    pub(crate) fn watch(&self, body: Body) -> Body {
+        self.set(Stage::Armed { read: false });
+
+        Body::wrap_stream(WatchedBody {
+            body,
+            gate: Some(self.clone()),
+        })
+    }
+
+    // Called the first time the `Body` is polled.
+    fn release(&self) {
+        let mut stage = self.stage.lock().expect("ContinueGate: poisoned lock");
+        match *stage {
+            Stage::Armed { .. } => *stage = Stage::Armed { read: true },
+            Stage::Withheld => {
+                *stage = Stage::Due(0);
+
+                // the connection may be waiting for a body which the client won't send until it
+                // has received `100 Continue`
+                self.task.notify();
+            }
+            Stage::Idle | Stage::Due(_) => (),
+        }
+    }
+
+    // Determines whether `buf`, which is about to be written to the connection, starts with a
+    // `100 Continue` which must be withheld.
+    fn withhold(&self, buf: &[u8]) -> bool {
+        let mut stage = self.stage.lock().expect("ContinueGate: poisoned lock");
+        match *stage {
+            Stage::Armed { read: false } if buf.starts_with(CONTINUE) => {
+                *stage = Stage::Withheld;
+                true
+            }
+            Stage::Armed { read: true } if buf.starts_with(CONTINUE) => {
+                *stage = Stage::Idle;
+                false
+            }
+            // the handler is responding without having read the `Body`
+            Stage::Withheld => {
+                *stage = Stage::Idle;
+                false
+            }
+            _ => false,
+        }
+    }
+
+    fn get(&self) -> Stage {
+        *self.stage.lock().expect("ContinueGate: poisoned lock")
+    }
+
+    fn set(&self, stage: Stage) {
+        *self.stage.lock().expect("ContinueGate: poisoned lock") = stage;
+    }
+}
+
+// The request `Body`, which releases the `100 Continue` when first polled.
+struct WatchedBody {
+    body: Body,
+    gate: Option<ContinueGate>,
+}
+
+impl Stream for WatchedBody {
+    type Item = Chunk;
+    type Error = ::hyper::Error;
+
+    fn poll(&mut self) -> Poll<Option<Chunk>, ::hyper::Error> {
+        if let Some(gate) = self.gate.take() {
+            gate.release();
+        }
+
+        self.body.poll()
+    }
+}
+
+/// A connection which withholds the `100 Continue` written by hyper until the `ContinueGate`
+/// releases it.
+pub(crate) struct DeferContinue<T> {
+    io: T,
+    gate: ContinueGate,
+}
+
+impl<T> DeferContinue<T>
+where
+    T: AsyncRead + AsyncWrite,
+{
+    pub(crate) fn new(io: T, gate: ContinueGate) -> DeferContinue<T> {
+        DeferContinue { io, gate }
+    }
+
+    pub(crate) fn get_ref(&self) -> &T {
+        &self.io
+    }
+
+    // Writes the remainder of a released `100 Continue`, before anything else is read or written.
+    fn write_due(&mut self) -> io::Result<()> {
+        while let Stage::Due(n) = self.gate.get() {
+            match self.io.write(&CONTINUE[n..])? {
+                0 => return Err(io::ErrorKind::WriteZero.into()),
+                written if n + written == CONTINUE.len() => self.gate.set(Stage::Idle),
+                written => self.gate.set(Stage::Due(n + written)),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<T> Read for DeferContinue<T>
+where
+    T: AsyncRead + AsyncWrite,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.gate.task.register();
+        self.write_due()?;
+        self.io.read(buf)
+    }
+}
+
+impl<T> Write for DeferContinue<T>
+where
+    T: AsyncRead + AsyncWrite,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.gate.task.register();
+        self.write_due()?;
+
+        if self.gate.withhold(buf) {
+            return Ok(CONTINUE.len());
+        }
+
+        self.io.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_due()?;
+        self.io.flush()
+    }
+}
+
+impl<T> AsyncRead for DeferContinue<T>
+where
+    T: AsyncRead + AsyncWrite,
+{
+    unsafe fn prepare_uninitialized_buffer(&self, buf: &mut [u8]) -> bool {
+        self.io.prepare_uninitialized_buffer(buf)
+    }
+}
+
+impl<T> AsyncWrite for DeferContinue<T>
+where
+    T: AsyncRead + AsyncWrite,
+{
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        self.io.shutdown()
+    }
+
+    fn write_buf<B: Buf>(&mut self, buf: &mut B) -> Poll<usize, io::Error> {
+        // vectored writes are passed through unless a `100 Continue` may need to be withheld
+        if self.gate.get() != Stage::Idle {
+            let n = match self.write(buf.bytes()) {
+                Ok(n) => n,
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(Async::NotReady),
+                Err(e) => return Err(e),
+            };
+            buf.advance(n);
+            return Ok(Async::Ready(n));
+        }
+
+        self.io.write_buf(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    use futures::{future, Future};
+
+    // Writes each of `writes` to a `DeferContinue`, reading the `Body` of a request expecting
+    // `100 Continue` before the writes listed in `read_before`, and returns the bytes written to
+    // the connection.
+    fn written(writes: &[&[u8]], read_before: Option<usize>) -> Vec<u8> {
+        let gate = ContinueGate::new();
+        let mut body = Some(gate.watch(Body::from("hello")));
+        let mut io = DeferContinue::new(Cursor::new(Vec::new()), gate);
+
+        // the connection registers the current task, so it has to be used from within one
+        future::lazy(move || {
+            for (i, buf) in writes.iter().enumerate() {
+                if read_before == Some(i) {
+                    let body = body.take().unwrap().concat2().wait().unwrap();
+                    assert_eq!(&body[..], b"hello");
+                }
+
+                io.write_all(buf).unwrap();
+            }
+
+            Ok::<_, ()>(io.io.into_inner())
+        })
+        .wait()
+        .unwrap()
+    }
+
+    #[test]
+    fn withholds_continue_until_body_is_read() {
+        let ok: &[u8] = b"HTTP/1.1 200 OK\r\n\r\n";
+        assert_eq!(
+            &written(&[CONTINUE, ok], Some(1))[..],
+            &b"HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 200 OK\r\n\r\n"[..]
+        );
+
+        // hyper writes `100 Continue` itself when the body is read before it gets the chance
+        assert_eq!(
+            &written(&[CONTINUE, ok], Some(0))[..],
+            &b"HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 200 OK\r\n\r\n"[..]
+        );
+    }
+
+    #[test]
+    fn drops_continue_when_body_is_not_read() {
+        let rejected: &[u8] = b"HTTP/1.1 413 Payload Too Large\r\n\r\n";
+        assert_eq!(&written(&[CONTINUE, rejected], None)[..], rejected);
+    }
+}
@@ -0,0 +1,158 @@
+//! Defines `RequestLimits`, which bound the size of the request line and headers.
+
+use std::cmp;
+
+use hyper::{HeaderMap, StatusCode, Uri};
+
+// The smallest read buffer which hyper accepts.
+const MIN_BUFFER_SIZE: usize = 8192;
+
+// The room left in the read buffer for the method, version and line endings when sizing it from
+// the limits.
+const BUFFER_SLACK: usize = 1024;
+
+/// Limits on the request line and headers, which are checked before a request is dispatched to
+/// the handler. A request exceeding them is answered with `414 URI Too Long` or
+/// `431 Request Header Fields Too Large`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct RequestLimits {
+    pub(crate) max_uri_length: Option<usize>,
+    pub(crate) max_header_bytes: Option<usize>,
+    pub(crate) max_headers: Option<usize>,
+}
+
+impl RequestLimits {
+    /// The status of the response rejecting a request with the given target and headers, or
+    /// `None` when the request is within the limits.
+    pub(crate) fn check(&self, uri: &Uri, headers: &HeaderMap) -> Option<StatusCode> {
+        if exceeds(self.max_uri_length, uri_length(uri)) {
+            return Some(StatusCode::URI_TOO_LONG);
+        }
+
+        if exceeds(self.max_headers, headers.len())
+            || exceeds(self.max_header_bytes, header_bytes(headers))
+        {
+            return Some(StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE);
+        }
+
+        None
+    }
+
+    /// The size of the buffer hyper reads the request line and headers into, when both are
+    /// limited. A request which doesn't fit is rejected by hyper with
+    /// `431 Request Header Fields Too Large` before it is parsed, so the buffer is left large
+    /// enough for every request which `check` would allow.
+    pub(crate) fn buffer_size(&self) -> Option<usize> {
+        match (self.max_uri_length, self.max_header_bytes) {
+            (Some(uri), Some(headers)) => Some(cmp::max(
+                MIN_BUFFER_SIZE,
+                uri.saturating_add(headers).saturating_add(BUFFER_SLACK),
+            )),
+            _ => None,
+        }
+    }
+}
+
+fn exceeds(limit: Option<usize>, value: usize) -> bool {
+    limit.map(|limit| value > limit).unwrap_or(false)
+}
+
+// The length of the request target, as it appeared in the request line.
+fn uri_length(uri: &Uri) -> usize {
+    let absolute = match (uri.scheme_part(), uri.authority_part()) {
+        (Some(scheme), Some(authority)) => scheme.as_str().len() + 3 + authority.as_str().len(),
+        (None, Some(authority)) => authority.as_str().len(),
+        _ => 0,
+    };
+
+    absolute
+        + uri
+            .path_and_query()
+            .map(|path| path.as_str().len())
+            .unwrap_or(0)
+}
+
+// The number of bytes the headers occupied in the request, as `name: value\r\n` lines.
+fn header_bytes(headers: &HeaderMap) -> usize {
+    headers
+        .iter()
+        .map(|(name, value)| name.as_str().len() + value.len() + 4)
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use hyper::header::{HeaderValue, ACCEPT, HOST};
+
+    fn headers(count: usize) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(HOST, HeaderValue::from_static("example.com"));
+        for _ in 1..count {
+            headers.append(ACCEPT, HeaderValue::from_static("*/*"));
+        }
+        headers
+    }
+
+    #[test]
+    fn measures_requests() {
+        assert_eq!(uri_length(&"/a/b?c=d".parse().unwrap()), 8);
+        assert_eq!(uri_length(&"http://example.com/a".parse().unwrap()), 20);
+
+        // "host: example.com\r\n" and "accept: */*\r\n"
+        assert_eq!(header_bytes(&headers(2)), 19 + 13);
+    }
+
+    #[test]
+    fn checks_limits() {
+        let uri = "/abcdefghij".parse().unwrap();
+        let unlimited = RequestLimits::default();
+        assert_eq!(unlimited.check(&uri, &headers(50)), None);
+
+        let limits = RequestLimits {
+            max_uri_length: Some(11),
+            max_header_bytes: Some(100),
+            max_headers: Some(5),
+        };
+        assert_eq!(limits.check(&uri, &headers(5)), None);
+        assert_eq!(
+            limits.check(&"/abcdefghijk".parse().unwrap(), &headers(1)),
+            Some(StatusCode::URI_TOO_LONG)
+        );
+        assert_eq!(
+            limits.check(&uri, &headers(6)),
+            Some(StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE)
+        );
+
+        let limits = RequestLimits {
+            max_headers: None,
+            ..limits
+        };
+        assert_eq!(
+            limits.check(&uri, &headers(8)),
+            Some(StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE)
+        );
+    }
+
+    #[test]
+    fn sizes_read_buffer() {
+        let limits = RequestLimits {
+            max_uri_length: Some(1024),
+            ..RequestLimits::default()
+        };
+        assert_eq!(limits.buffer_size(), None);
+
+        let limits = RequestLimits {
+            max_header_bytes: Some(2048),
+            ..limits
+        };
+        assert_eq!(limits.buffer_size(), Some(MIN_BUFFER_SIZE));
+
+        let limits = RequestLimits {
+            max_header_bytes: Some(64 * 1024),
+            ..limits
+        };
+        assert_eq!(limits.buffer_size(), Some(64 * 1024 + 1024 + BUFFER_SLACK));
+    }
+}
@@ -8,18 +8,32 @@ use std::thread;
 
 use failure;
 
-use futures::Future;
+use futures::{future, Future};
 use http::request;
 use hyper::service::Service;
 use hyper::{Body, Request, Response};
 
+use client::HttpClient;
+use events::{self, EventBus, RequestEvents};
 use handler::NewHandler;
+use helpers::clock::{put_clock, Clock};
+use helpers::http::request::expect::expects_continue;
 use helpers::http::request::path::RequestPathSegments;
+use helpers::http::response::create_empty_response;
+use helpers::random::{put_random_source, RandomSource};
+use log::Log;
+use logging::RequestLogger;
+use reporting::ErrorReporter;
 use state::client_addr::put_client_addr;
-use state::{set_request_id, State};
+use state::{set_request_id, FromState, Scheme, State};
 
+mod expect;
+mod limits;
 mod trap;
 
+pub(crate) use self::expect::{ContinueGate, DeferContinue};
+pub(crate) use self::limits::RequestLimits;
+
 /// Wraps a `NewHandler` which will be used to serve requests. Used in `gotham::os::*` to bind
 /// incoming connections to `ConnectedGothamService` values.
 pub(crate) struct GothamService<T>
@@ -27,6 +41,13 @@ where
     T: NewHandler + 'static,
 {
     handler: Arc<T>,
+    logger: Option<Arc<Log>>,
+    error_reporter: Option<Arc<ErrorReporter>>,
+    clock: Option<Arc<Clock>>,
+    random_source: Option<Arc<RandomSource>>,
+    http_client: Option<HttpClient>,
+    events: Option<EventBus>,
+    limits: RequestLimits,
 }
 
 impl<T> GothamService<T>
@@ -36,25 +57,159 @@ where
     pub(crate) fn new(handler: T) -> GothamService<T> {
         GothamService {
             handler: Arc::new(handler),
+            logger: None,
+            error_reporter: None,
+            clock: None,
+            random_source: None,
+            http_client: None,
+            events: None,
+            limits: RequestLimits::default(),
+        }
+    }
+
+    /// Sets the logger which lines about each request are written to, instead of the global
+    /// logger.
+    pub(crate) fn with_logger(self, logger: Option<Arc<Log>>) -> GothamService<T> {
+        GothamService { logger, ..self }
+    }
+
+    /// Sets the `ErrorReporter` which is told about each request that fails with an error or
+    /// panics.
+    pub(crate) fn with_error_reporter(
+        self,
+        error_reporter: Option<Arc<ErrorReporter>>,
+    ) -> GothamService<T> {
+        GothamService {
+            error_reporter,
+            ..self
         }
     }
 
+    /// Sets the `Clock` which the time is read from while handling requests.
+    pub(crate) fn with_clock(self, clock: Option<Arc<Clock>>) -> GothamService<T> {
+        GothamService { clock, ..self }
+    }
+
+    /// Sets the `RandomSource` which random bytes are read from while handling requests.
+    pub(crate) fn with_random_source(
+        self,
+        random_source: Option<Arc<RandomSource>>,
+    ) -> GothamService<T> {
+        GothamService {
+            random_source,
+            ..self
+        }
+    }
+
+    /// Sets the `HttpClient` which handlers make requests to other services with.
+    pub(crate) fn with_http_client(self, http_client: Option<HttpClient>) -> GothamService<T> {
+        GothamService {
+            http_client,
+            ..self
+        }
+    }
+
+    /// Sets the `EventBus` which the events of each request are published to.
+    pub(crate) fn with_events(self, events: Option<EventBus>) -> GothamService<T> {
+        GothamService { events, ..self }
+    }
+
+    /// Sets the limits on the request line and headers, which requests are checked against before
+    /// being dispatched.
+    pub(crate) fn with_limits(self, limits: RequestLimits) -> GothamService<T> {
+        GothamService { limits, ..self }
+    }
+
     pub(crate) fn connect(&self, client_addr: SocketAddr) -> ConnectedGothamService<T> {
         ConnectedGothamService {
-            client_addr,
+            client_addr: Some(client_addr),
+            scheme: None,
+            continue_gate: None,
             handler: self.handler.clone(),
+            logger: self.logger.clone(),
+            error_reporter: self.error_reporter.clone(),
+            clock: self.clock.clone(),
+            random_source: self.random_source.clone(),
+            http_client: self.http_client.clone(),
+            events: self.events.clone(),
+            limits: self.limits,
+        }
+    }
+
+    /// Connects a client over a transport which has no client address, such as a Unix domain
+    /// socket.
+    pub(crate) fn connect_local(&self) -> ConnectedGothamService<T> {
+        ConnectedGothamService {
+            client_addr: None,
+            scheme: None,
+            continue_gate: None,
+            handler: self.handler.clone(),
+            logger: self.logger.clone(),
+            error_reporter: self.error_reporter.clone(),
+            clock: self.clock.clone(),
+            random_source: self.random_source.clone(),
+            http_client: self.http_client.clone(),
+            events: self.events.clone(),
+            limits: self.limits,
+        }
+    }
+}
+
+impl<T> Clone for GothamService<T>
+where
+    T: NewHandler + 'static,
+{
+    fn clone(&self) -> GothamService<T> {
+        GothamService {
+            handler: self.handler.clone(),
+            logger: self.logger.clone(),
+            error_reporter: self.error_reporter.clone(),
+            clock: self.clock.clone(),
+            random_source: self.random_source.clone(),
+            http_client: self.http_client.clone(),
+            events: self.events.clone(),
+            limits: self.limits,
         }
     }
 }
 
 /// A `GothamService` which has been connected to a client. The major difference is that a
-/// `client_addr` has been assigned (as this isn't available from Hyper).
+/// `client_addr` has been assigned (as this isn't available from Hyper), unless the client is
+/// connected over a transport without addresses.
 pub(crate) struct ConnectedGothamService<T>
 where
     T: NewHandler + 'static,
 {
     handler: Arc<T>,
-    client_addr: SocketAddr,
+    client_addr: Option<SocketAddr>,
+    scheme: Option<Scheme>,
+    continue_gate: Option<ContinueGate>,
+    logger: Option<Arc<Log>>,
+    error_reporter: Option<Arc<ErrorReporter>>,
+    clock: Option<Arc<Clock>>,
+    random_source: Option<Arc<RandomSource>>,
+    http_client: Option<HttpClient>,
+    events: Option<EventBus>,
+    limits: RequestLimits,
+}
+
+impl<T> ConnectedGothamService<T>
+where
+    T: NewHandler + 'static,
+{
+    /// Sets the `Scheme` of requests made over the connection, when the transport knows it.
+    pub(crate) fn with_scheme(self, scheme: Option<Scheme>) -> ConnectedGothamService<T> {
+        ConnectedGothamService { scheme, ..self }
+    }
+
+    /// Sets the `ContinueGate` of the connection, which defers `100 Continue` until the handler
+    /// reads the body of a request expecting it.
+    pub(crate) fn with_continue_gate(self, gate: ContinueGate) -> ConnectedGothamService<T> {
+        ConnectedGothamService {
+            continue_gate: Some(gate),
+            ..self
+        }
+    }
 }
 
 impl<T> Service for ConnectedGothamService<T>
@@ -69,7 +224,29 @@ where
     fn call(&mut self, req: Request<Self::ReqBody>) -> Self::Future {
         let mut state = State::new();
 
-        put_client_addr(&mut state, self.client_addr);
+        if let Some(client_addr) = self.client_addr {
+            put_client_addr(&mut state, client_addr);
+        }
+
+        if let Some(scheme) = self.scheme {
+            state.put(scheme);
+        }
+
+        if let Some(ref logger) = self.logger {
+            state.put(RequestLogger::new(logger.clone()));
+        }
+
+        if let Some(ref clock) = self.clock {
+            put_clock(&mut state, clock.clone());
+        }
+
+        if let Some(ref random_source) = self.random_source {
+            put_random_source(&mut state, random_source.clone());
+        }
+
+        if let Some(ref http_client) = self.http_client {
+            state.put(http_client.clone());
+        }
 
         let (
             request::Parts {
@@ -83,11 +260,18 @@ where
             body,
         ) = req.into_parts();
 
+        let rejected = self.limits.check(&uri, &headers);
+
         state.put(RequestPathSegments::new(uri.path()));
         state.put(method);
         state.put(uri);
         state.put(version);
         state.put(headers);
+
+        let body = match self.continue_gate {
+            Some(ref gate) if expects_continue(&state) => gate.watch(body),
+            _ => body,
+        };
         state.put(body);
 
         {
@@ -99,7 +283,23 @@ where
             );
         };
 
-        trap::call_handler(&*self.handler, AssertUnwindSafe(state))
+        if let Some(ref events) = self.events {
+            events::request_started(&mut state, events);
+        }
+
+        if let Some(status) = rejected {
+            request_info!(&state, "rejected request exceeding limits with {}", status);
+            if let Some(events) = RequestEvents::try_borrow_from(&state) {
+                events.response_written(status);
+            }
+            return Box::new(future::ok(create_empty_response(&state, status)));
+        }
+
+        trap::call_handler(
+            &*self.handler,
+            AssertUnwindSafe(state),
+            self.error_reporter.clone(),
+        )
     }
 }
 
@@ -107,11 +307,12 @@ where
 mod tests {
     use super::*;
 
-    use hyper::{Body, StatusCode};
+    use futures::Stream;
+    use hyper::{Body, HeaderMap, Method, StatusCode, Uri, Version};
 
     use helpers::http::response::create_empty_response;
     use router::builder::*;
-    use state::State;
+    use state::{peer_addr, request_id, FromState, State};
 
     fn handler(state: State) -> (State, Response<Body>) {
         let res = create_empty_response(&state, StatusCode::ACCEPTED);
@@ -132,6 +333,36 @@ mod tests {
         assert_eq!(response.status(), StatusCode::ACCEPTED);
     }
 
+    #[test]
+    fn rejects_requests_exceeding_limits() {
+        let service = GothamService::new(|| Ok(handler)).with_limits(RequestLimits {
+            max_uri_length: Some(16),
+            max_headers: Some(1),
+            ..RequestLimits::default()
+        });
+        let call = |req| {
+            let f = service
+                .connect("127.0.0.1:10000".parse().unwrap())
+                .call(req);
+            f.wait().unwrap().status()
+        };
+
+        let req = Request::get("/short").body(Body::empty()).unwrap();
+        assert_eq!(call(req), StatusCode::ACCEPTED);
+
+        let req = Request::get("/much/too/long/a/path")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(call(req), StatusCode::URI_TOO_LONG);
+
+        let req = Request::get("/short")
+            .header("Accept", "*/*")
+            .header("Accept-Language", "en")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(call(req), StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE);
+    }
+
     #[test]
     fn router() {
         let router = build_simple_router(|route| {
@@ -149,4 +380,34 @@ mod tests {
         let response = f.wait().unwrap();
         assert_eq!(response.status(), StatusCode::ACCEPTED);
     }
+
+    #[test]
+    fn request_data_in_state() {
+        fn handler(mut state: State) -> (State, Response<Body>) {
+            assert_eq!(*Method::borrow_from(&state), Method::PUT);
+            assert_eq!(Uri::borrow_from(&state).path(), "/items/1");
+            assert_eq!(*Version::borrow_from(&state), Version::HTTP_11);
+            assert_eq!(HeaderMap::borrow_from(&state)["x-item"], "one");
+            assert_eq!(peer_addr(&state), Some("127.0.0.1:10000".parse().unwrap()));
+            assert!(!request_id(&state).is_empty());
+
+            let body = Body::take_from(&mut state).concat2().wait().unwrap();
+            assert_eq!(&body[..], b"item");
+
+            let res = create_empty_response(&state, StatusCode::ACCEPTED);
+            (state, res)
+        }
+
+        let service = GothamService::new(|| Ok(handler));
+
+        let req = Request::put("http://localhost/items/1")
+            .header("x-item", "one")
+            .body(Body::from("item"))
+            .unwrap();
+        let f = service
+            .connect("127.0.0.1:10000".parse().unwrap())
+            .call(req);
+        let response = f.wait().unwrap();
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+    }
 }
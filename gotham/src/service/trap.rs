@@ -4,31 +4,55 @@
 use std::any::Any;
 use std::error::Error;
 use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::Arc;
 use std::{io, mem};
 
 use failure;
 use futures::future::{self, Future, FutureResult, IntoFuture};
 use futures::Async;
-use hyper::{Body, Response, StatusCode};
+use hyper::{Body, Method, Response, StatusCode, Uri};
 
+use cookies::write_cookies;
+use events::RequestEvents;
 use handler::{Handler, HandlerError, IntoResponse, NewHandler};
-use state::{request_id, State};
+use reporting::{report, ErrorReporter, ReportedError};
+use state::{request_id, FromState, State};
 
 type CompatError = failure::Compat<failure::Error>;
+type ResponseFuture<'a> = Box<Future<Item = Response<Body>, Error = CompatError> + Send + 'a>;
 
 /// Instantiates a `Handler` from the given `NewHandler`, and invokes it with the request. If a
 /// panic occurs from `NewHandler::new_handler` or `Handler::handle`, it is trapped and will result
-/// in a `500 Internal Server Error` response.
+/// in a `500 Internal Server Error` response. The panic is logged along with the request ID,
+/// method and path, and the worker continues to serve other requests.
+///
+/// Changes made to the `CookieJar` in `State` are sent as `Set-Cookie` headers, on both successful
+/// and error responses.
+///
+/// Errors and panics are passed to `reporter`, when one is given. For a panic, the report holds a
+/// copy of the request data taken before the handler was invoked, since the `State` is lost.
+///
+/// When the events of the request are being published, the final response is published as
+/// `ResponseWritten`, including the `500 Internal Server Error` response to a panic.
 ///
 /// Timing information is recorded and logged, except in the case of a panic where the timer is
 /// moved and cannot be recovered.
 pub(super) fn call_handler<'a, T>(
     t: &T,
     state: AssertUnwindSafe<State>,
-) -> Box<Future<Item = Response<Body>, Error = CompatError> + Send + 'a>
+    reporter: Option<Arc<ErrorReporter>>,
+) -> ResponseFuture<'a>
 where
     T: NewHandler + 'a,
 {
+    // The `State` is consumed by the handler, so the details required to log a panic are taken
+    // beforehand.
+    let context = request_context(&state);
+    let panic_reporting = reporter
+        .clone()
+        .map(|reporter| (reporter, state.copy_request_data()));
+    let events = RequestEvents::try_borrow_from(&state).cloned();
+
     let res = catch_unwind(move || {
         // Hyper doesn't allow us to present an affine-typed `Handler` interface directly. We have
         // to emulate the promise given by hyper's documentation, by creating a `Handler` value and
@@ -40,26 +64,72 @@ where
                 let AssertUnwindSafe(state) = state;
 
                 handler.handle(state).then(move |result| match result {
-                    Ok((_state, res)) => future::ok(res),
-                    Err((state, err)) => finalize_error_response(state, err),
+                    Ok((state, mut res)) => {
+                        write_cookies(&state, &mut res);
+                        future::ok(res)
+                    }
+                    Err((state, err)) => finalize_error_response(state, err, reporter),
                 })
             })
     });
 
-    if let Ok(f) = res {
-        return Box::new(
-            UnwindSafeFuture::new(f)
-                .catch_unwind()
-                .then(finalize_catch_unwind_response), // must be Future<Item = impl Payload>
-        );
+    let f: ResponseFuture<'a> = match res {
+        // must be Future<Item = impl Payload>
+        Ok(f) => {
+            Box::new(UnwindSafeFuture::new(f).catch_unwind().then(move |result| {
+                finalize_catch_unwind_response(&context, result, panic_reporting)
+            }))
+        }
+        Err(payload) => Box::new(finalize_panic_response(
+            &context,
+            &*payload,
+            panic_reporting,
+        )),
+    };
+
+    match events {
+        Some(events) => Box::new(f.map(move |res| {
+            events.response_written(res.status());
+            res
+        })),
+        None => f,
+    }
+}
+
+/// Describes the request being processed, for use in log messages after the `State` has been
+/// moved into the handler.
+fn request_context(state: &State) -> String {
+    let method = Method::try_borrow_from(state)
+        .map(Method::as_str)
+        .unwrap_or("-");
+
+    let path = Uri::try_borrow_from(state).map(Uri::path).unwrap_or("-");
+
+    format!("{}][{} {}", request_id(state), method, path)
+}
+
+/// Extracts the message given to `panic!`, when it is available.
+fn panic_message(payload: &(Any + Send)) -> &str {
+    if let Some(s) = payload.downcast_ref::<&'static str>() {
+        s
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s
+    } else {
+        "Box<Any>"
     }
+}
 
-    Box::new(finalize_panic_response())
+fn internal_server_error() -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::INTERNAL_SERVER_ERROR)
+        .body(Body::default())
+        .unwrap()
 }
 
 fn finalize_error_response(
     state: State,
     err: HandlerError,
+    reporter: Option<Arc<ErrorReporter>>,
 ) -> FutureResult<Response<Body>, CompatError> {
     {
         // HandlerError::cause() is far more interesting for logging, but the
@@ -75,38 +145,65 @@ fn finalize_error_response(
             err_description
         );
     }
-    future::ok(err.into_response(&state))
+    if let Some(reporter) = reporter {
+        report(&*reporter, &state, ReportedError::Handler(&err));
+    }
+    let mut res = err.into_response(&state);
+    write_cookies(&state, &mut res);
+    future::ok(res)
 }
 
-fn finalize_panic_response() -> FutureResult<Response<Body>, CompatError> {
-    error!("[PANIC][A panic occurred while invoking the handler]");
+fn finalize_panic_response(
+    context: &str,
+    payload: &(Any + Send),
+    reporting: Option<(Arc<ErrorReporter>, State)>,
+) -> FutureResult<Response<Body>, CompatError> {
+    error!(
+        "[PANIC][{}][A panic occurred while invoking the handler: {}]",
+        context,
+        panic_message(payload)
+    );
+    if let Some((reporter, state)) = reporting {
+        report(
+            &*reporter,
+            &state,
+            ReportedError::Panic(panic_message(payload)),
+        );
+    }
 
-    future::ok(
-        Response::builder()
-            .status(StatusCode::INTERNAL_SERVER_ERROR)
-            .body(Body::default())
-            .unwrap(),
-    )
+    future::ok(internal_server_error())
 }
 
 fn finalize_catch_unwind_response(
+    context: &str,
     result: Result<Result<Response<Body>, CompatError>, Box<Any + Send>>,
+    reporting: Option<(Arc<ErrorReporter>, State)>,
 ) -> FutureResult<Response<Body>, CompatError> {
-    let response = result
-        .unwrap_or_else(|_| {
-            let e = io::Error::new(
-                io::ErrorKind::Other,
-                "Attempting to poll the future caused a panic",
+    let response = match result {
+        Ok(Ok(response)) => response,
+        Ok(Err(e)) => {
+            error!("[ERROR][{}][Error: {}]", context, e);
+            if let Some((reporter, state)) = reporting {
+                report(&*reporter, &state, ReportedError::Internal(&e));
+            }
+            internal_server_error()
+        }
+        Err(payload) => {
+            error!(
+                "[PANIC][{}][A panic occurred while polling the future: {}]",
+                context,
+                panic_message(&*payload)
             );
-
-            Err(failure::Error::from(e).compat())
-        }).unwrap_or_else(|_| {
-            error!("[PANIC][A panic occurred while polling the future]");
-            Response::builder()
-                .status(StatusCode::INTERNAL_SERVER_ERROR)
-                .body(Body::default())
-                .unwrap()
-        });
+            if let Some((reporter, state)) = reporting {
+                report(
+                    &*reporter,
+                    &state,
+                    ReportedError::Panic(panic_message(&*payload)),
+                );
+            }
+            internal_server_error()
+        }
+    };
 
     future::ok(response)
 }
@@ -167,7 +264,7 @@ mod tests {
 
     use std::io;
 
-    use hyper::{HeaderMap, Method, StatusCode};
+    use hyper::{Body, HeaderMap, Version};
 
     use handler::{HandlerFuture, IntoHandlerError};
     use helpers::http::response::create_empty_response;
@@ -187,7 +284,7 @@ mod tests {
         state.put(Method::GET);
         set_request_id(&mut state);
 
-        let r = call_handler(&new_handler, AssertUnwindSafe(state));
+        let r = call_handler(&new_handler, AssertUnwindSafe(state), None);
         let response = r.wait().unwrap();
         assert_eq!(response.status(), StatusCode::ACCEPTED);
     }
@@ -214,7 +311,7 @@ mod tests {
         state.put(Method::GET);
         set_request_id(&mut state);
 
-        let r = call_handler(&new_handler, AssertUnwindSafe(state));
+        let r = call_handler(&new_handler, AssertUnwindSafe(state), None);
         let response = r.wait().unwrap();
         assert_eq!(response.status(), StatusCode::ACCEPTED);
     }
@@ -235,7 +332,7 @@ mod tests {
         state.put(Method::GET);
         set_request_id(&mut state);
 
-        let r = call_handler(&new_handler, AssertUnwindSafe(state));
+        let r = call_handler(&new_handler, AssertUnwindSafe(state), None);
         let response = r.wait().unwrap();
         assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
     }
@@ -254,7 +351,7 @@ mod tests {
         state.put(Method::GET);
         set_request_id(&mut state);
 
-        let r = call_handler(&new_handler, AssertUnwindSafe(state));
+        let r = call_handler(&new_handler, AssertUnwindSafe(state), None);
         let response = r.wait().unwrap();
         assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
     }
@@ -273,7 +370,7 @@ mod tests {
         state.put(Method::GET);
         set_request_id(&mut state);
 
-        let r = call_handler(&new_handler, AssertUnwindSafe(state));
+        let r = call_handler(&new_handler, AssertUnwindSafe(state), None);
         let response = r.wait().unwrap();
         assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
     }
@@ -296,8 +393,91 @@ mod tests {
         state.put(Method::GET);
         set_request_id(&mut state);
 
-        let r = call_handler(&new_handler, AssertUnwindSafe(state));
+        let r = call_handler(&new_handler, AssertUnwindSafe(state), None);
         let response = r.wait().unwrap();
         assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
     }
+
+    #[test]
+    fn errors_and_panics_are_reported() {
+        use reporting::ErrorReport;
+        use std::sync::Mutex;
+
+        fn state(method: Method) -> AssertUnwindSafe<State> {
+            let mut state = State::new();
+            state.put(HeaderMap::new());
+            state.put(method);
+            set_request_id(&mut state);
+            AssertUnwindSafe(state)
+        }
+
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let received = reports.clone();
+        let reporter: Arc<ErrorReporter> = Arc::new(move |report: &ErrorReport| {
+            let method = Method::borrow_from(report.state()).clone();
+            received
+                .lock()
+                .unwrap()
+                .push((method, report.status(), report.error().to_string()));
+        });
+
+        let error_handler = || {
+            Ok(|state| {
+                let err = io::Error::new(io::ErrorKind::Other, "handler failed");
+                Box::new(future::err((state, err.into_handler_error()))) as Box<HandlerFuture>
+            })
+        };
+        let panic_handler = || {
+            Ok(|_| {
+                let val: Option<Box<HandlerFuture>> = None;
+                Box::new(future::lazy(move || val.expect("test panic"))) as Box<HandlerFuture>
+            })
+        };
+
+        call_handler(&error_handler, state(Method::GET), Some(reporter.clone()))
+            .wait()
+            .unwrap();
+        call_handler(&panic_handler, state(Method::POST), Some(reporter))
+            .wait()
+            .unwrap();
+
+        let reports = reports.lock().unwrap();
+        assert_eq!(
+            *reports,
+            vec![
+                (
+                    Method::GET,
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "handler failed".to_owned()
+                ),
+                (
+                    Method::POST,
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "panic: test panic".to_owned()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn panic_messages() {
+        let payload = catch_unwind(|| panic!("static message")).unwrap_err();
+        assert_eq!(panic_message(&*payload), "static message");
+
+        let payload = catch_unwind(|| panic!("formatted {}", "message")).unwrap_err();
+        assert_eq!(panic_message(&*payload), "formatted message");
+    }
+
+    #[test]
+    fn request_context_describes_request() {
+        let mut state = State::new();
+        state.put(Method::POST);
+        state.put("/some/path?q=1".parse::<Uri>().unwrap());
+        state.put(Version::HTTP_11);
+        state.put(HeaderMap::new());
+        state.put(Body::empty());
+        let id = set_request_id(&mut state).to_owned();
+
+        assert_eq!(request_context(&state), format!("{}][POST /some/path", id));
+    }
 }
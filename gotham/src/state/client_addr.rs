@@ -5,16 +5,29 @@ use std::net::SocketAddr;
 
 struct ClientAddr {
     addr: SocketAddr,
+    peer: SocketAddr,
 }
 
 impl StateData for ClientAddr {}
 
 pub(crate) fn put_client_addr(state: &mut State, addr: SocketAddr) {
-    state.put(ClientAddr { addr })
+    state.put(ClientAddr { addr, peer: addr })
 }
 
-/// Returns the client `SocketAddr` as reported by hyper, if one was present. Certain connections
-/// do not report a client address, in which case this will return `None`.
+/// Replaces the client address with one reported by a trusted proxy, retaining the address of the
+/// connected peer.
+pub(crate) fn forward_client_addr(state: &mut State, addr: SocketAddr) {
+    if let Some(client_addr) = ClientAddr::try_borrow_mut_from(state) {
+        client_addr.addr = addr;
+    }
+}
+
+/// Returns the client `SocketAddr`, if one was present. Certain connections do not report a
+/// client address, in which case this will return `None`.
+///
+/// This is the address of the connected peer as reported by hyper, unless a trusted proxy has
+/// reported the original client address to `ForwardedMiddleware`. When the address comes from a
+/// proxy which did not report the client port, the port is `0`.
 ///
 /// # Examples
 ///
@@ -59,3 +72,9 @@ pub(crate) fn put_client_addr(state: &mut State, addr: SocketAddr) {
 pub fn client_addr(state: &State) -> Option<SocketAddr> {
     ClientAddr::try_borrow_from(&state).map(|c| c.addr)
 }
+
+/// Returns the `SocketAddr` of the connected peer as reported by hyper, if one was present. Unlike
+/// `client_addr`, this is never replaced by an address reported by a proxy.
+pub fn peer_addr(state: &State) -> Option<SocketAddr> {
+    ClientAddr::try_borrow_from(&state).map(|c| c.peer)
+}
@@ -7,15 +7,14 @@ use state::request_id::RequestId;
 
 /// A marker trait for types that can be stored in `State`.
 ///
-/// This is typically implemented using `#[derive(StateData)]`, which is provided by the
-/// `gotham_derive` crate.
+/// This is typically implemented using `#[derive(StateData)]`. The derive is provided by the
+/// `gotham_derive` crate, and is re-exported alongside this trait when the `derive` feature is
+/// enabled, which it is by default.
 ///
 /// ```rust
 /// # extern crate gotham;
-/// # #[macro_use]
-/// # extern crate gotham_derive;
 /// #
-/// # use gotham::state::{FromState, State};
+/// # use gotham::state::{FromState, State, StateData};
 /// #
 /// #[derive(StateData)]
 /// struct MyStateData {
@@ -4,14 +4,20 @@ pub(crate) mod client_addr;
 mod data;
 mod from_state;
 pub mod request_id;
+pub mod scheme;
 
-use std::any::{Any, TypeId};
+use std::any::{self, Any, TypeId};
 use std::collections::HashMap;
 
-pub use state::client_addr::client_addr;
+use hyper::{HeaderMap, Method, Uri, Version};
+
+#[cfg(feature = "derive")]
+pub use gotham_derive::StateData;
+pub use state::client_addr::{client_addr, peer_addr};
 pub use state::data::StateData;
 pub use state::from_state::FromState;
 pub use state::request_id::request_id;
+pub use state::scheme::{scheme, Scheme};
 
 pub(crate) use state::request_id::set_request_id;
 
@@ -19,6 +25,14 @@ pub(crate) use state::request_id::set_request_id;
 /// storage must implement the `gotham::state::StateData` trait to allow its storage. The
 /// `gotham_derive` crate provides a custom derive for `StateData` to make this more convenient.
 ///
+/// Before the `Router` is invoked, Gotham puts the parts of the request into `State`, so that
+/// middleware and handlers can borrow them without the `Request` being passed alongside:
+///
+/// * `hyper::Method`, `hyper::Uri`, `hyper::Version` and `hyper::HeaderMap`;
+/// * `hyper::Body`, which can be taken to read the request body;
+/// * the request ID, available via `request_id`;
+/// * the client address, available via `client_addr` and `peer_addr`.
+///
 /// # Examples
 ///
 /// ```rust
@@ -44,6 +58,7 @@ pub(crate) use state::request_id::set_request_id;
 /// ```
 pub struct State {
     data: HashMap<TypeId, Box<Any + Send>>,
+    type_names: HashMap<TypeId, &'static str>,
 }
 
 impl State {
@@ -53,9 +68,46 @@ impl State {
     pub(crate) fn new() -> State {
         State {
             data: HashMap::new(),
+            type_names: HashMap::new(),
         }
     }
 
+    /// Creates a new `State` container holding copies of the request data which Gotham stores
+    /// before invoking the `Router`: the request method, URI, HTTP version, headers, request ID and
+    /// client address, along with any `Scheme` reported by a proxy. The request body is not
+    /// copied.
+    ///
+    /// This is for internal Gotham use, where a response is required after the original `State`
+    /// has been given up, such as when a handler is abandoned.
+    pub(crate) fn copy_request_data(&self) -> State {
+        let mut state = State::new();
+
+        if let Some(method) = self.try_borrow::<Method>() {
+            state.put(method.clone());
+        }
+        if let Some(uri) = self.try_borrow::<Uri>() {
+            state.put(uri.clone());
+        }
+        if let Some(version) = self.try_borrow::<Version>() {
+            state.put(*version);
+        }
+        if let Some(headers) = self.try_borrow::<HeaderMap>() {
+            state.put(headers.clone());
+        }
+        if let Some(request_id) = self.try_borrow::<request_id::RequestId>() {
+            state.put(request_id.clone());
+        }
+        if let (Some(addr), Some(peer)) = (client_addr(self), peer_addr(self)) {
+            client_addr::put_client_addr(&mut state, peer);
+            client_addr::forward_client_addr(&mut state, addr);
+        }
+        if let Some(scheme) = self.try_borrow::<Scheme>() {
+            state.put(*scheme);
+        }
+
+        state
+    }
+
     /// Creates a new, empty `State` and yields it mutably into the provided closure. This is
     /// intended only for use in the documentation tests for `State`, since the `State` container
     /// cannot be constructed otherwise.
@@ -111,6 +163,7 @@ impl State {
         let type_id = TypeId::of::<T>();
         trace!(" inserting record to state for type_id `{:?}`", type_id);
         self.data.insert(type_id, Box::new(t));
+        self.type_names.insert(type_id, any::type_name::<T>());
     }
 
     /// Determines if the current value exists in `State` storage.
@@ -198,7 +251,8 @@ impl State {
     ///
     /// # Panics
     ///
-    /// If a value of type `T` is not present in `State`.
+    /// If a value of type `T` is not present in `State`. The panic message names `T` and the types
+    /// which are present.
     ///
     /// # Examples
     ///
@@ -227,8 +281,10 @@ impl State {
     where
         T: StateData,
     {
-        self.try_borrow()
-            .expect("required type is not present in State container")
+        match self.try_borrow::<T>() {
+            Some(t) => t,
+            None => missing::<T>(&self.type_names),
+        }
     }
 
     /// Tries to mutably borrow a value from the `State` storage.
@@ -280,7 +336,8 @@ impl State {
     ///
     /// # Panics
     ///
-    /// If a value of type `T` is not present in `State`.
+    /// If a value of type `T` is not present in `State`. The panic message names `T` and the types
+    /// which are present.
     ///
     /// # Examples
     ///
@@ -320,6 +377,10 @@ impl State {
     where
         T: StateData,
     {
+        if !self.has::<T>() {
+            missing::<T>(&self.type_names)
+        }
+
         self.try_borrow_mut()
             .expect("required type is not present in State container")
     }
@@ -368,6 +429,7 @@ impl State {
             " taking ownership from state data for type_id `{:?}`",
             type_id
         );
+        self.type_names.remove(&type_id);
         self.data
             .remove(&type_id)
             .and_then(|b| b.downcast::<T>().ok())
@@ -378,7 +440,8 @@ impl State {
     ///
     /// # Panics
     ///
-    /// If a value of type `T` is not present in `State`.
+    /// If a value of type `T` is not present in `State`. The panic message names `T` and the types
+    /// which are present.
     ///
     /// # Examples
     ///
@@ -411,7 +474,63 @@ impl State {
     where
         T: StateData,
     {
-        self.try_take()
-            .expect("required type is not present in State container")
+        match self.try_take::<T>() {
+            Some(t) => t,
+            None => missing::<T>(&self.type_names),
+        }
+    }
+
+    /// Returns the names of the types which are present in `State`, in sorted order.
+    pub(crate) fn type_names(&self) -> Vec<&'static str> {
+        let mut names = self.type_names.values().cloned().collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+}
+
+// Panics with a message naming the missing type `T`, and the types which are present in `State`.
+fn missing<T>(type_names: &HashMap<TypeId, &'static str>) -> !
+where
+    T: StateData,
+{
+    let mut present = type_names.values().cloned().collect::<Vec<_>>();
+    present.sort();
+
+    panic!(
+        "required type `{}` is not present in State container (present: [{}])",
+        any::type_name::<T>(),
+        present.join(", ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Present;
+    impl StateData for Present {}
+
+    struct Absent;
+    impl StateData for Absent {}
+
+    #[test]
+    #[should_panic(
+        expected = "required type `gotham::state::tests::Absent` is not present in \
+                    State container (present: [gotham::state::tests::Present])"
+    )]
+    fn missing_type_panic_names_types() {
+        let mut state = State::new();
+        state.put(Present);
+        state.borrow::<Absent>();
+    }
+
+    #[test]
+    fn take_forgets_type_name() {
+        let mut state = State::new();
+        state.put(Present);
+        state.take::<Present>();
+
+        assert!(state.try_borrow::<Present>().is_none());
+        assert!(state.type_names.is_empty());
     }
 }
@@ -3,27 +3,41 @@
 use hyper::header::HeaderMap;
 use uuid::Uuid;
 
+use helpers::http::header::X_REQUEST_ID;
 use state::{FromState, State};
 
 /// A container type for the value returned by `request_id`.
-pub(super) struct RequestId {
+#[derive(Clone)]
+pub(crate) struct RequestId {
     val: String,
 }
 
+impl RequestId {
+    pub(crate) fn as_str(&self) -> &str {
+        &self.val
+    }
+}
+
 /// Sets a unique identifier for the request if it has not already been stored.
 ///
 /// The unique identifier chosen depends on the the request headers:
 ///
-/// 1. If the header `X-Request-ID` is provided this value is used as-is;
+/// 1. If the header `X-Request-ID` is provided this value is used as-is, provided it is made up
+///    of at most 200 visible ASCII characters;
 /// 2. Alternatively creates and stores a UUID v4 value.
 ///
 /// This function is invoked by `GothamService` before handing control to its `Router`, to ensure
 /// that a value for `RequestId` is always available.
 pub(crate) fn set_request_id<'a>(state: &'a mut State) -> &'a str {
     if !state.has::<RequestId>() {
-        let request_id = match HeaderMap::borrow_from(state).get("X-Request-ID") {
-            Some(ex_req_id) => {
-                let id = String::from_utf8(ex_req_id.as_bytes().into()).unwrap();
+        let external = HeaderMap::borrow_from(state)
+            .get(X_REQUEST_ID)
+            .and_then(|ex_req_id| ex_req_id.to_str().ok())
+            .filter(|id| is_valid_request_id(id))
+            .map(|id| id.to_owned());
+
+        let request_id = match external {
+            Some(id) => {
                 trace!(
                     "[{}] RequestId set from external source via X-Request-ID header",
                     id
@@ -42,6 +56,11 @@ pub(crate) fn set_request_id<'a>(state: &'a mut State) -> &'a str {
     request_id(state)
 }
 
+// Determines whether an external request ID is safe to use, and to include in log output.
+fn is_valid_request_id(id: &str) -> bool {
+    !id.is_empty() && id.len() <= 200 && id.bytes().all(|b| b.is_ascii_graphic())
+}
+
 /// Returns the request ID associated with the current request.
 ///
 /// This is typically used for logging and correlating events that occurred within a request.
@@ -83,6 +102,21 @@ mod tests {
         assert_eq!("1-2-3-4", request_id(&state));
     }
 
+    #[test]
+    fn ignores_an_invalid_external_request_id() {
+        let long = "a".repeat(201);
+        for invalid in &["", "1 2 3 4", long.as_str()] {
+            let mut state = State::new();
+
+            let mut headers = HeaderMap::new();
+            headers.insert("X-Request-ID", invalid.parse().unwrap());
+            state.put(headers);
+
+            let r = set_request_id(&mut state);
+            assert_eq!(4, Uuid::parse_str(r).unwrap().get_version_num());
+        }
+    }
+
     #[test]
     fn sets_a_unique_request_id() {
         let mut state = State::new();
@@ -0,0 +1,68 @@
+//! Defines storage for the scheme used by the client to make a request
+
+use state::{FromState, State, StateData};
+
+/// The scheme used by the client to make a request.
+///
+/// Gotham itself serves plain HTTP, so a request is only known to have been made over HTTPS when a
+/// `Scheme` has been placed into `State` by middleware such as `ForwardedMiddleware`, which trusts
+/// a TLS terminating proxy to report it. The scheme of an absolute-form request URI is chosen by
+/// the client, so it is never trusted.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Scheme {
+    /// The `http` scheme.
+    Http,
+    /// The `https` scheme.
+    Https,
+}
+
+impl StateData for Scheme {}
+
+impl Scheme {
+    /// The name of the scheme, as used in a URL.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Scheme::Http => "http",
+            Scheme::Https => "https",
+        }
+    }
+
+    pub(crate) fn parse(scheme: &str) -> Option<Scheme> {
+        if scheme.eq_ignore_ascii_case("https") {
+            Some(Scheme::Https)
+        } else if scheme.eq_ignore_ascii_case("http") {
+            Some(Scheme::Http)
+        } else {
+            None
+        }
+    }
+}
+
+/// Returns the `Scheme` used by the client to make the request.
+///
+/// This is the `Scheme` held in `State` if one is present, otherwise `Scheme::Http`.
+pub fn scheme(state: &State) -> Scheme {
+    Scheme::try_borrow_from(state)
+        .cloned()
+        .unwrap_or(Scheme::Http)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use hyper::Uri;
+
+    #[test]
+    fn determines_scheme() {
+        let mut state = State::new();
+        assert_eq!(scheme(&state), Scheme::Http);
+
+        // the client chooses the scheme of an absolute-form request URI
+        state.put("http://example.com/".parse::<Uri>().unwrap());
+        assert_eq!(scheme(&state), Scheme::Http);
+
+        state.put(Scheme::Https);
+        assert_eq!(scheme(&state), Scheme::Https);
+    }
+}
@@ -0,0 +1,482 @@
+//! Defines helpers for running work outside of the event loop which is serving requests.
+//!
+//! Handlers are run on the same threads which drive connections, so a handler which blocks (such
+//! as by performing synchronous database queries or file I/O) prevents every other connection on
+//! that thread from making progress. `spawn_blocking` moves such work onto a separate pool of
+//! threads, and resolves back on the event loop once it has completed.
+//!
+//! Work which the response does not depend on, such as sending emails or webhooks, can be
+//! continued after responding: futures with `spawn`, and blocking work with a `JobQueue`. Work
+//! which recurs for as long as the server is running is registered with a `Scheduler`.
+
+use futures::Future;
+use futures_cpupool::{Builder, CpuPool};
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use tokio::executor;
+
+use handler::{HandlerError, IntoHandlerError};
+use state::{FromState, State, StateData};
+
+mod schedule;
+
+pub use self::schedule::{InvalidSchedule, Schedule, Scheduler};
+
+/// A pool of threads for running blocking work via `spawn_blocking`.
+///
+/// The pool is made available to handlers by adding it to `State`, usually with a
+/// `StateMiddleware`. Cloning a `BlockingPool` shares the underlying threads.
+pub struct BlockingPool {
+    pool: AssertUnwindSafe<CpuPool>,
+}
+
+impl BlockingPool {
+    /// Creates a `BlockingPool` with `size` threads.
+    pub fn new(size: usize) -> BlockingPool {
+        BlockingPool::with_cpu_pool(
+            Builder::new()
+                .pool_size(size)
+                .name_prefix("gotham-blocking-")
+                .create(),
+        )
+    }
+
+    /// Creates a `BlockingPool` which runs work on an existing `CpuPool`.
+    pub fn with_cpu_pool(pool: CpuPool) -> BlockingPool {
+        BlockingPool {
+            pool: AssertUnwindSafe(pool),
+        }
+    }
+}
+
+impl Default for BlockingPool {
+    /// Creates a `BlockingPool` with one thread per CPU.
+    fn default() -> BlockingPool {
+        BlockingPool::new(::num_cpus::get())
+    }
+}
+
+impl Clone for BlockingPool {
+    fn clone(&self) -> BlockingPool {
+        BlockingPool::with_cpu_pool(self.pool.clone())
+    }
+}
+
+impl StateData for BlockingPool {}
+
+/// Runs `f` on the `BlockingPool` in `State`, returning a future which resolves to the `State` and
+/// the value returned by `f` once it has completed. An error returned by `f` is converted into a
+/// `HandlerError`.
+///
+/// `State` is not sent to the pool, so `f` must take anything it needs from `State` before being
+/// spawned.
+///
+/// # Panics
+///
+/// If there is no `BlockingPool` in `State`.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate futures;
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// # extern crate mime;
+/// #
+/// # use std::fs;
+/// # use futures::Future;
+/// # use hyper::StatusCode;
+/// # use gotham::handler::HandlerFuture;
+/// # use gotham::helpers::http::response::create_response;
+/// # use gotham::middleware::state::StateMiddleware;
+/// # use gotham::pipeline::single_middleware;
+/// # use gotham::pipeline::single::single_pipeline;
+/// # use gotham::router::Router;
+/// # use gotham::router::builder::*;
+/// # use gotham::state::State;
+/// # use gotham::task::{spawn_blocking, BlockingPool};
+/// # use gotham::test::TestServer;
+/// #
+/// fn read_manifest(state: State) -> Box<HandlerFuture> {
+///     let f = spawn_blocking(state, || fs::read_to_string("Cargo.toml")).map(|(state, manifest)| {
+///         let res = create_response(&state, StatusCode::OK, mime::TEXT_PLAIN, manifest);
+///         (state, res)
+///     });
+///
+///     Box::new(f)
+/// }
+///
+/// fn router() -> Router {
+///     let pool = BlockingPool::new(4);
+///     let (chain, pipelines) = single_pipeline(single_middleware(StateMiddleware::new(pool)));
+///
+///     build_router(chain, pipelines, |route| {
+///         route.get("/manifest").to(read_manifest);
+///     })
+/// }
+/// #
+/// # fn main() {
+/// #   let test_server = TestServer::new(router()).unwrap();
+/// #   let response = test_server
+/// #       .client()
+/// #       .get("http://example.com/manifest")
+/// #       .perform()
+/// #       .unwrap();
+/// #   assert_eq!(response.status(), StatusCode::OK);
+/// # }
+/// ```
+pub fn spawn_blocking<F, R, E>(
+    state: State,
+    f: F,
+) -> impl Future<Item = (State, R), Error = (State, HandlerError)>
+where
+    F: FnOnce() -> Result<R, E> + Send + 'static,
+    R: Send + 'static,
+    E: IntoHandlerError + Send + 'static,
+{
+    let work = BlockingPool::borrow_from(&state).pool.spawn_fn(f);
+
+    work.then(move |result| match result {
+        Ok(r) => Ok((state, r)),
+        Err(e) => Err((state, e.into_handler_error())),
+    })
+}
+
+/// Spawns `f` onto the executor of the running server, which runs it to completion independently
+/// of the request which spawned it, so that a handler can respond without waiting for it.
+///
+/// Spawned futures are not waited for when the server shuts down. Work which must complete before
+/// the process exits should be given to a `JobQueue` instead.
+///
+/// # Panics
+///
+/// If called outside of the server's executor, such as from a thread of a `BlockingPool`.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate futures;
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// #
+/// # use futures::future;
+/// # use hyper::{Body, Response, StatusCode};
+/// # use gotham::state::State;
+/// # use gotham::test::TestServer;
+/// #
+/// fn handler(state: State) -> (State, Response<Body>) {
+///     gotham::spawn(future::lazy(|| {
+///         // ... notify another service of the request.
+///         Ok(())
+///     }));
+///
+///     (state, Response::new(Body::from("accepted")))
+/// }
+/// #
+/// # fn main() {
+/// #   let test_server = TestServer::new(|| Ok(handler)).unwrap();
+/// #   let response = test_server.client().get("http://localhost/").perform().unwrap();
+/// #   assert_eq!(response.status(), StatusCode::OK);
+/// # }
+/// ```
+pub fn spawn<F>(f: F)
+where
+    F: Future<Item = (), Error = ()> + Send + 'static,
+{
+    executor::spawn(f);
+}
+
+type Job = Box<FnOnce() + Send>;
+
+/// A bounded queue of background jobs, run in the order they were enqueued by a fixed number of
+/// worker threads.
+///
+/// Jobs are closures which may block, such as by sending an email with a synchronous client.
+/// A job which panics is logged and does not stop its worker. The queue is made available to
+/// handlers by adding it to `State`, usually with a `StateMiddleware`, and cloning a `JobQueue`
+/// shares the underlying queue and workers.
+///
+/// `JobQueue::shutdown` stops accepting jobs and waits for the queued jobs to complete, so it is
+/// usually run as a shutdown hook of the server, to complete the queued jobs before the process
+/// exits.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// #
+/// # use hyper::{Body, Response, StatusCode};
+/// # use gotham::ServerBuilder;
+/// # use gotham::middleware::state::StateMiddleware;
+/// # use gotham::pipeline::single_middleware;
+/// # use gotham::pipeline::single::single_pipeline;
+/// # use gotham::router::Router;
+/// # use gotham::router::builder::*;
+/// # use gotham::state::{FromState, State};
+/// # use gotham::task::JobQueue;
+/// # use gotham::test::TestServer;
+/// #
+/// # fn send_welcome_email() {}
+/// #
+/// fn sign_up(state: State) -> (State, Response<Body>) {
+///     let status = match JobQueue::borrow_from(&state).enqueue(send_welcome_email) {
+///         Ok(()) => StatusCode::ACCEPTED,
+///         Err(_) => StatusCode::SERVICE_UNAVAILABLE,
+///     };
+///
+///     let mut res = Response::new(Body::empty());
+///     *res.status_mut() = status;
+///     (state, res)
+/// }
+///
+/// fn router(jobs: JobQueue) -> Router {
+///     let (chain, pipelines) = single_pipeline(single_middleware(StateMiddleware::new(jobs)));
+///
+///     build_router(chain, pipelines, |route| {
+///         route.post("/sign-up").to(sign_up);
+///     })
+/// }
+///
+/// # fn main() {
+/// let jobs = JobQueue::new(2, 1000);
+/// let server = ServerBuilder::new().with_shutdown_hook({
+///     let jobs = jobs.clone();
+///     move || jobs.shutdown()
+/// });
+/// #   let test_server = TestServer::new(router(jobs.clone())).unwrap();
+/// #   let response = test_server
+/// #       .client()
+/// #       .post("http://localhost/sign-up", "", "text/plain".parse().unwrap())
+/// #       .perform()
+/// #       .unwrap();
+/// #   assert_eq!(response.status(), StatusCode::ACCEPTED);
+/// #   jobs.shutdown();
+/// #   let _ = server;
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct JobQueue {
+    inner: Arc<Queue>,
+}
+
+struct Queue {
+    sender: Mutex<Option<SyncSender<Job>>>,
+    workers: Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl StateData for JobQueue {}
+
+impl JobQueue {
+    /// Creates a `JobQueue` with `workers` threads, which holds up to `capacity` jobs waiting for
+    /// a worker.
+    pub fn new(workers: usize, capacity: usize) -> JobQueue {
+        let (sender, receiver) = sync_channel(capacity);
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let workers = (0..workers)
+            .map(|i| {
+                let receiver = receiver.clone();
+                thread::Builder::new()
+                    .name(format!("gotham-job-{}", i))
+                    .spawn(move || work(&receiver))
+                    .expect("unable to spawn job worker")
+            })
+            .collect();
+
+        JobQueue {
+            inner: Arc::new(Queue {
+                sender: Mutex::new(Some(sender)),
+                workers: Mutex::new(workers),
+            }),
+        }
+    }
+
+    /// Adds `job` to the queue, or returns an error without running it when the queue is full or
+    /// has been shut down.
+    pub fn enqueue<F>(&self, job: F) -> Result<(), EnqueueError>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        match *self.inner.sender.lock().unwrap() {
+            Some(ref sender) => sender.try_send(Box::new(job)).map_err(|e| match e {
+                TrySendError::Full(_) => EnqueueError::Full,
+                TrySendError::Disconnected(_) => EnqueueError::Closed,
+            }),
+            None => Err(EnqueueError::Closed),
+        }
+    }
+
+    /// Stops accepting jobs, and blocks until the jobs already in the queue have completed.
+    ///
+    /// This must not be called from a job, which would wait for itself to complete.
+    pub fn shutdown(&self) {
+        self.inner.sender.lock().unwrap().take();
+
+        let workers = mem::replace(&mut *self.inner.workers.lock().unwrap(), Vec::new());
+        for worker in workers {
+            let _ = worker.join();
+        }
+    }
+}
+
+// Runs jobs from the queue until it has been shut down and emptied.
+fn work(receiver: &Mutex<Receiver<Job>>) {
+    loop {
+        let job = match receiver.lock().unwrap().recv() {
+            Ok(job) => job,
+            Err(_) => return,
+        };
+
+        if panic::catch_unwind(AssertUnwindSafe(job)).is_err() {
+            error!(" background job panicked");
+        }
+    }
+}
+
+/// The error returned when a job can't be added to a `JobQueue`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EnqueueError {
+    /// The queue already holds as many jobs as its capacity.
+    Full,
+
+    /// The queue has been shut down.
+    Closed,
+}
+
+impl Display for EnqueueError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.description())
+    }
+}
+
+impl Error for EnqueueError {
+    fn description(&self) -> &str {
+        match *self {
+            EnqueueError::Full => "the job queue is full",
+            EnqueueError::Closed => "the job queue has been shut down",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use hyper::StatusCode;
+    use std::io;
+    use std::thread;
+
+    #[test]
+    fn runs_on_pool_and_restores_state() {
+        let mut state = State::new();
+        state.put(BlockingPool::new(1));
+
+        let caller = thread::current().id();
+        let (state, id) = spawn_blocking(state, move || Ok::<_, io::Error>(thread::current().id()))
+            .wait()
+            .map_err(|(_, err)| err)
+            .unwrap();
+
+        assert!(id != caller);
+        assert!(state.has::<BlockingPool>());
+    }
+
+    #[test]
+    fn converts_errors() {
+        let mut state = State::new();
+        state.put(BlockingPool::new(1));
+
+        let result = spawn_blocking(state, || -> Result<(), io::Error> {
+            Err(io::Error::new(io::ErrorKind::Other, "failed"))
+        })
+        .wait();
+
+        match result {
+            Err((_, err)) => assert_eq!(err.status(), StatusCode::INTERNAL_SERVER_ERROR),
+            Ok(_) => panic!("expected an error"),
+        }
+    }
+
+    #[test]
+    fn spawns_onto_server_executor() {
+        use futures::future;
+        use hyper::{Body, Response};
+        use std::sync::mpsc::channel;
+        use std::time::Duration;
+        use test::TestServer;
+
+        let (tx, rx) = channel();
+        let tx = Mutex::new(tx);
+        let test_server = TestServer::new(move || {
+            let tx = tx.lock().unwrap().clone();
+            Ok(move |state: State| {
+                spawn(future::lazy(move || {
+                    tx.send("spawned").unwrap();
+                    Ok(())
+                }));
+                (state, Response::new(Body::empty()))
+            })
+        })
+        .unwrap();
+
+        let response = test_server
+            .client()
+            .get("http://localhost/")
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(rx.recv_timeout(Duration::from_secs(5)), Ok("spawned"));
+    }
+
+    #[test]
+    fn runs_queued_jobs_before_shutdown() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let jobs = JobQueue::new(2, 10);
+        let completed = Arc::new(AtomicUsize::new(0));
+
+        for i in 0..5 {
+            let completed = completed.clone();
+            jobs.enqueue(move || {
+                if i == 2 {
+                    panic!("job failed");
+                }
+                completed.fetch_add(1, Ordering::SeqCst);
+            })
+            .unwrap();
+        }
+
+        jobs.shutdown();
+        assert_eq!(completed.load(Ordering::SeqCst), 4);
+        assert_eq!(jobs.enqueue(|| ()), Err(EnqueueError::Closed));
+    }
+
+    #[test]
+    fn rejects_jobs_when_full() {
+        use std::sync::mpsc::channel;
+
+        let jobs = JobQueue::new(1, 1);
+        let (started_tx, started_rx) = channel();
+        let (release_tx, release_rx) = channel::<()>();
+
+        // occupy the only worker, then fill the queue
+        jobs.enqueue(move || {
+            started_tx.send(()).unwrap();
+            release_rx.recv().unwrap();
+        })
+        .unwrap();
+        started_rx.recv().unwrap();
+        jobs.enqueue(|| ()).unwrap();
+
+        assert_eq!(jobs.enqueue(|| ()), Err(EnqueueError::Full));
+
+        release_tx.send(()).unwrap();
+        jobs.shutdown();
+    }
+}
@@ -0,0 +1,557 @@
+//! Defines `Scheduler`, which runs recurring tasks for as long as the server is running.
+
+use chrono::prelude::*;
+use chrono::Duration as ChronoDuration;
+use futures::future::{self, Either, Loop};
+use futures::{Future, IntoFuture};
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::timer::Delay;
+
+use task::BlockingPool;
+
+/// When a task registered with a `Scheduler` runs.
+///
+/// ```rust
+/// # extern crate gotham;
+/// # use std::time::Duration;
+/// # use gotham::task::Schedule;
+/// # fn main() {
+/// // Every thirty seconds, starting thirty seconds after the server starts.
+/// let every_thirty_seconds = Schedule::every(Duration::from_secs(30));
+///
+/// // At 03:15 UTC on weekdays.
+/// let weekday_mornings = Schedule::cron("15 3 * * 1-5").unwrap();
+///
+/// // Every five minutes, on the hour and at five minute intervals past it.
+/// let every_five_minutes = Schedule::cron("*/5 * * * *").unwrap();
+/// # assert_ne!(every_thirty_seconds, every_five_minutes);
+/// # assert_ne!(weekday_mornings, every_five_minutes);
+/// # }
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Schedule {
+    kind: Kind,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Kind {
+    Every(Duration),
+    Cron(Cron),
+}
+
+impl Schedule {
+    /// Runs a task repeatedly, `interval` apart. A run which takes longer than `interval` delays
+    /// the next run, rather than runs being made to catch up.
+    ///
+    /// # Panics
+    ///
+    /// If `interval` is zero.
+    pub fn every(interval: Duration) -> Schedule {
+        assert!(
+            interval > Duration::from_secs(0),
+            "interval must not be zero"
+        );
+
+        Schedule {
+            kind: Kind::Every(interval),
+        }
+    }
+
+    /// Runs a task at the times matching a cron expression, evaluated in UTC.
+    ///
+    /// The expression has five fields separated by whitespace: the minute (0-59), hour (0-23),
+    /// day of the month (1-31), month (1-12) and day of the week (0-7, where both 0 and 7 are
+    /// Sunday). Each field is `*`, a value, a range such as `1-5`, or a comma separated list of
+    /// them, and `*` or a range may be followed by a step such as `*/15`. As with cron, when both
+    /// the day of the month and the day of the week are restricted, a day matching either runs
+    /// the task.
+    pub fn cron(expression: &str) -> Result<Schedule, InvalidSchedule> {
+        Cron::parse(expression)
+            .map(|cron| Schedule {
+                kind: Kind::Cron(cron),
+            })
+            .map_err(|reason| InvalidSchedule {
+                expression: expression.to_owned(),
+                reason,
+            })
+    }
+
+    // The time until the next run, given the time that the previous run was due, or `None` if
+    // the schedule never runs again.
+    fn next(&self, previous: Option<Instant>) -> Option<Instant> {
+        let now = Instant::now();
+
+        match self.kind {
+            Kind::Every(interval) => {
+                let next = previous.unwrap_or(now) + interval;
+                Some(if next < now { now } else { next })
+            }
+            Kind::Cron(ref cron) => {
+                let wall = Utc::now();
+                cron.next_after(wall)
+                    .and_then(|next| (next - wall).to_std().ok())
+                    .map(|wait| now + wait)
+            }
+        }
+    }
+}
+
+/// The error returned by `Schedule::cron` for an invalid cron expression.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InvalidSchedule {
+    expression: String,
+    reason: &'static str,
+}
+
+impl Display for InvalidSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid schedule `{}`: {}", self.expression, self.reason)
+    }
+}
+
+impl Error for InvalidSchedule {
+    fn description(&self) -> &str {
+        "invalid schedule"
+    }
+}
+
+// A parsed cron expression, holding a bit for each value of each field which matches.
+#[derive(Clone, Debug, PartialEq)]
+struct Cron {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl Cron {
+    fn parse(expression: &str) -> Result<Cron, &'static str> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err("expected five fields");
+        }
+
+        let mut weekdays = parse_field(fields[4], 0, 7)?;
+        if weekdays & (1 << 7) != 0 {
+            weekdays |= 1;
+        }
+
+        Ok(Cron {
+            minutes: parse_field(fields[0], 0, 59)?,
+            hours: parse_field(fields[1], 0, 23)?,
+            days: parse_field(fields[2], 1, 31)?,
+            months: parse_field(fields[3], 1, 12)?,
+            weekdays,
+            any_day: fields[2].starts_with('*'),
+            any_weekday: fields[4].starts_with('*'),
+        })
+    }
+
+    fn matches_day(&self, date: &DateTime<Utc>) -> bool {
+        let day = self.days & (1 << date.day()) != 0;
+        let weekday = self.weekdays & (1 << date.weekday().num_days_from_sunday()) != 0;
+
+        match (self.any_day, self.any_weekday) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        }
+    }
+
+    // The first time after `after` which matches, searching up to five years ahead so that
+    // expressions which never match, such as `0 0 31 2 *`, end.
+    fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let limit = after + ChronoDuration::days(5 * 366);
+        let mut t = after.with_second(0)?.with_nanosecond(0)? + ChronoDuration::minutes(1);
+
+        while t < limit {
+            if self.months & (1 << t.month()) == 0 {
+                let (year, month) = if t.month() == 12 {
+                    (t.year() + 1, 1)
+                } else {
+                    (t.year(), t.month() + 1)
+                };
+                t = Utc.ymd(year, month, 1).and_hms(0, 0, 0);
+            } else if !self.matches_day(&t) {
+                t = t.date().succ().and_hms(0, 0, 0);
+            } else if self.hours & (1 << t.hour()) == 0 {
+                t = t.with_minute(0)? + ChronoDuration::hours(1);
+            } else if self.minutes & (1 << t.minute()) == 0 {
+                t = t + ChronoDuration::minutes(1);
+            } else {
+                return Some(t);
+            }
+        }
+
+        None
+    }
+}
+
+// Parses a field of a cron expression into a bit for each matching value.
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64, &'static str> {
+    let mut bits = 0;
+
+    for item in field.split(',') {
+        let (range, step) = match item.find('/') {
+            Some(i) => {
+                let step = item[i + 1..].parse::<u32>().map_err(|_| "invalid step")?;
+                if step == 0 {
+                    return Err("invalid step");
+                }
+                (&item[..i], step)
+            }
+            None => (item, 1),
+        };
+
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some(i) = range.find('-') {
+            (parse_value(&range[..i])?, parse_value(&range[i + 1..])?)
+        } else if step == 1 {
+            let value = parse_value(range)?;
+            (value, value)
+        } else {
+            return Err("a step must follow `*` or a range");
+        };
+
+        if start < min || end > max || start > end {
+            return Err("value out of range");
+        }
+
+        let mut value = start;
+        while value <= end {
+            bits |= 1 << value;
+            value += step;
+        }
+    }
+
+    Ok(bits)
+}
+
+fn parse_value(value: &str) -> Result<u32, &'static str> {
+    value.parse().map_err(|_| "invalid value")
+}
+
+type Run = Arc<Fn() -> Box<Future<Item = (), Error = ()> + Send> + Send + Sync>;
+
+#[derive(Clone)]
+struct Task {
+    name: String,
+    schedule: Schedule,
+    run: Run,
+    blocking: bool,
+}
+
+/// Runs recurring tasks, such as removing expired data or flushing metrics, while the server is
+/// running.
+///
+/// Each task runs according to its `Schedule`, either on the event loop which is serving
+/// requests, or on a `BlockingPool` for tasks which block. A task does not run again until its
+/// previous run has completed. The tasks start when the server starts, once the scheduler has
+/// been given to `ServerBuilder::with_scheduler`, and stop when the server shuts down gracefully,
+/// after any run in progress has completed. Errors returned by a task are logged.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # extern crate futures;
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// #
+/// # use std::io;
+/// # use std::time::Duration;
+/// # use futures::future;
+/// # use hyper::{Body, Response};
+/// # use gotham::ServerBuilder;
+/// # use gotham::state::State;
+/// # use gotham::task::{Schedule, Scheduler};
+/// #
+/// # fn hello(state: State) -> (State, Response<Body>) {
+/// #   (state, Response::new(Body::from("Hello, world!")))
+/// # }
+/// #
+/// # fn main() {
+/// let scheduler = Scheduler::new()
+///     .with_task("refresh-cache", Schedule::every(Duration::from_secs(60)), || {
+///         // ... fetch the data to cache.
+///         future::ok::<(), io::Error>(())
+///     })
+///     .with_blocking_task("vacuum", Schedule::cron("0 4 * * *").unwrap(), || {
+///         // ... run a synchronous database query.
+///         Ok::<(), io::Error>(())
+///     });
+///
+/// ServerBuilder::new()
+///     .with_signal_handling(true)
+///     .with_scheduler(scheduler)
+///     .start("127.0.0.1:7878", || Ok(hello));
+/// # }
+/// ```
+#[derive(Clone, Default)]
+pub struct Scheduler {
+    tasks: Vec<Task>,
+    pool: Option<BlockingPool>,
+}
+
+impl Scheduler {
+    /// Creates a `Scheduler` with no tasks.
+    pub fn new() -> Scheduler {
+        Scheduler::default()
+    }
+
+    /// Adds a task which runs on the event loop, by running the future returned by `task` to
+    /// completion. The `name` of the task is used when logging.
+    pub fn with_task<F, R, E>(mut self, name: &str, schedule: Schedule, task: F) -> Scheduler
+    where
+        F: Fn() -> R + Send + Sync + 'static,
+        R: IntoFuture<Item = (), Error = E>,
+        R::Future: Send + 'static,
+        E: Display + 'static,
+    {
+        let name = name.to_owned();
+        let log_name = name.clone();
+
+        let run: Run = Arc::new(move || {
+            let name = log_name.clone();
+            Box::new(
+                task()
+                    .into_future()
+                    .map_err(move |e| warn!(" scheduled task {} failed: {}", name, e)),
+            )
+        });
+
+        self.tasks.push(Task {
+            name,
+            schedule,
+            run,
+            blocking: false,
+        });
+        self
+    }
+
+    /// Adds a task which runs on the `BlockingPool` of the scheduler, for work which blocks the
+    /// thread it runs on. The `name` of the task is used when logging.
+    pub fn with_blocking_task<F, E>(mut self, name: &str, schedule: Schedule, task: F) -> Scheduler
+    where
+        F: Fn() -> Result<(), E> + Send + Sync + 'static,
+        E: Display + Send + 'static,
+    {
+        let name = name.to_owned();
+        let log_name = name.clone();
+        let task = Arc::new(task);
+
+        let run: Run = Arc::new(move || -> Box<Future<Item = (), Error = ()> + Send> {
+            let task = task.clone();
+            let name = log_name.clone();
+            Box::new(future::lazy(move || {
+                match panic::catch_unwind(AssertUnwindSafe(|| task())) {
+                    Ok(Ok(())) => (),
+                    Ok(Err(e)) => warn!(" scheduled task {} failed: {}", name, e),
+                    Err(_) => error!(" scheduled task {} panicked", name),
+                }
+                Ok::<(), ()>(())
+            }))
+        });
+
+        self.tasks.push(Task {
+            name,
+            schedule,
+            run,
+            blocking: true,
+        });
+        self
+    }
+
+    /// Sets the `BlockingPool` which blocking tasks run on. By default, a pool with a single
+    /// thread is created when the scheduler starts, if it has any blocking tasks.
+    pub fn with_blocking_pool(self, pool: BlockingPool) -> Scheduler {
+        Scheduler {
+            pool: Some(pool),
+            ..self
+        }
+    }
+
+    /// Returns a `Future` which runs the tasks until `stop` completes, and then completes once any
+    /// runs in progress have completed.
+    pub(crate) fn run<S>(&self, stop: S) -> Box<Future<Item = (), Error = ()> + Send>
+    where
+        S: Future + Clone + Send + 'static,
+    {
+        let pool = match self.pool {
+            Some(ref pool) => Some(pool.clone()),
+            None if self.tasks.iter().any(|task| task.blocking) => Some(BlockingPool::new(1)),
+            None => None,
+        };
+
+        let tasks: Vec<_> = self
+            .tasks
+            .iter()
+            .map(|task| {
+                let pool = if task.blocking { pool.clone() } else { None };
+                let run = task.run.clone();
+                let run = move || -> Box<Future<Item = (), Error = ()> + Send> {
+                    match pool {
+                        Some(ref pool) => Box::new(pool.pool.spawn(run())),
+                        None => run(),
+                    }
+                };
+
+                schedule_task(task.name.clone(), task.schedule.clone(), run, stop.clone())
+            })
+            .collect();
+
+        Box::new(future::join_all(tasks).map(|_| ()))
+    }
+}
+
+// Runs `run` according to `schedule` until `stop` completes.
+fn schedule_task<F, S>(
+    name: String,
+    schedule: Schedule,
+    run: F,
+    stop: S,
+) -> Box<Future<Item = (), Error = ()> + Send>
+where
+    F: Fn() -> Box<Future<Item = (), Error = ()> + Send> + Send + Sync + 'static,
+    S: Future + Clone + Send + 'static,
+{
+    let run = Arc::new(run);
+    let f = future::loop_fn(None, move |previous| {
+        let due = match schedule.next(previous) {
+            Some(due) => due,
+            None => {
+                info!(" scheduled task {} will not run again", name);
+                return Either::A(future::ok(Loop::Break(())));
+            }
+        };
+
+        let name = name.clone();
+        let run = run.clone();
+        let wait = Delay::new(due)
+            .select2(stop.clone())
+            .then(|result| match result {
+                Ok(Either::A(_)) => Ok::<_, ()>(true),
+                _ => Ok(false),
+            });
+
+        let runs = wait.and_then(move |due_now| {
+            if !due_now {
+                return Either::A(future::ok(Loop::Break(())));
+            }
+
+            debug!(" running scheduled task {}", name);
+            Either::B(run().then(move |_| Ok(Loop::Continue(Some(due)))))
+        });
+
+        Either::B(runs)
+    });
+
+    Box::new(f)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::sync::oneshot;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use tokio::runtime::Runtime;
+
+    fn at(value: &str) -> DateTime<Utc> {
+        value.parse().unwrap()
+    }
+
+    fn next(expression: &str, after: &str) -> Option<DateTime<Utc>> {
+        match Schedule::cron(expression).unwrap().kind {
+            Kind::Cron(cron) => cron.next_after(at(after)),
+            Kind::Every(_) => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn parses_cron_expressions() {
+        assert!(Schedule::cron("* * * * *").is_ok());
+        assert!(Schedule::cron("0,30 */2 1-15/7 * 1-5").is_ok());
+
+        let invalid = Schedule::cron("60 * * * *").unwrap_err();
+        assert_eq!(
+            invalid.to_string(),
+            "invalid schedule `60 * * * *`: value out of range"
+        );
+        assert!(Schedule::cron("* * * *").is_err());
+        assert!(Schedule::cron("*/0 * * * *").is_err());
+        assert!(Schedule::cron("5/10 * * * *").is_err());
+        assert!(Schedule::cron("* * 0 * *").is_err());
+        assert!(Schedule::cron("a * * * *").is_err());
+    }
+
+    #[test]
+    fn finds_next_cron_time() {
+        let after = "2019-01-31T10:07:30Z";
+
+        assert_eq!(next("* * * * *", after), Some(at("2019-01-31T10:08:00Z")));
+        assert_eq!(
+            next("*/15 * * * *", after),
+            Some(at("2019-01-31T10:15:00Z"))
+        );
+        assert_eq!(next("0 9 * * *", after), Some(at("2019-02-01T09:00:00Z")));
+        assert_eq!(next("0 0 1 * *", after), Some(at("2019-02-01T00:00:00Z")));
+        assert_eq!(next("0 0 * 12 *", after), Some(at("2019-12-01T00:00:00Z")));
+        assert_eq!(next("0 0 29 2 *", after), Some(at("2020-02-29T00:00:00Z")));
+        assert_eq!(next("0 0 31 2 *", after), None);
+
+        // 2019-02-02 is a Saturday, and both 0 and 7 are Sunday
+        assert_eq!(next("0 12 * * 6", after), Some(at("2019-02-02T12:00:00Z")));
+        assert_eq!(next("0 12 * * 7", after), Some(at("2019-02-03T12:00:00Z")));
+
+        // either the day of the month or the day of the week may match
+        assert_eq!(next("0 0 15 * 6", after), Some(at("2019-02-02T00:00:00Z")));
+    }
+
+    #[test]
+    fn runs_tasks_until_stopped() {
+        let reactor_runs = Arc::new(AtomicUsize::new(0));
+        let blocking_runs = Arc::new(AtomicUsize::new(0));
+        let interval = Schedule::every(Duration::from_millis(10));
+
+        let scheduler = {
+            let reactor_runs = reactor_runs.clone();
+            let blocking_runs = blocking_runs.clone();
+
+            Scheduler::new()
+                .with_task("reactor", interval.clone(), move || {
+                    reactor_runs.fetch_add(1, Ordering::SeqCst);
+                    Ok::<(), String>(())
+                })
+                .with_blocking_task("blocking", interval.clone(), move || {
+                    if blocking_runs.fetch_add(1, Ordering::SeqCst) == 0 {
+                        panic!("first run fails");
+                    }
+                    Err("later runs fail".to_owned())
+                })
+        };
+
+        let (tx, rx) = oneshot::channel::<()>();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(200));
+            tx.send(()).unwrap();
+        });
+
+        let mut runtime = Runtime::new().unwrap();
+        runtime.block_on(scheduler.run(rx.shared())).unwrap();
+
+        assert!(reactor_runs.load(Ordering::SeqCst) >= 2);
+        assert!(blocking_runs.load(Ordering::SeqCst) >= 2);
+
+        let stopped = reactor_runs.load(Ordering::SeqCst);
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(reactor_runs.load(Ordering::SeqCst), stopped);
+    }
+}
@@ -0,0 +1,188 @@
+//! Defines helpers for rendering HTML responses from templates, using the Tera template engine.
+//!
+//! Templates are loaded from a directory once, when the application starts, and are shared by
+//! every request. `Templates` is made available to handlers by adding it to `State`, usually with
+//! a `StateMiddleware`, and `render` produces a response from a template and a context.
+//!
+//! This module is only available when the `templates` feature is enabled.
+
+use std::panic::AssertUnwindSafe;
+use std::path::Path;
+use std::sync::Arc;
+
+use hyper::{Body, Response, StatusCode};
+use mime;
+use serde::Serialize;
+
+use handler::{HandlerError, IntoHandlerError};
+use helpers::http::response::create_response;
+use state::{FromState, State, StateData};
+
+pub use tera::{Context, Tera};
+
+/// A set of compiled templates, which is shared by clones of the `Templates`.
+pub struct Templates {
+    tera: AssertUnwindSafe<Arc<Tera>>,
+}
+
+impl Templates {
+    /// Loads and compiles every template in `dir` and its subdirectories. Each template is named
+    /// by its path relative to `dir`, such as `users/show.html`.
+    ///
+    /// An error is returned if any of the templates can't be parsed, so this is usually called
+    /// once when the application starts, rather than when a template is first used.
+    pub fn new<P>(dir: P) -> ::tera::Result<Templates>
+    where
+        P: AsRef<Path>,
+    {
+        let glob = format!("{}/**/*", dir.as_ref().display());
+        Ok(Templates::with_tera(Tera::new(&glob)?))
+    }
+
+    /// Creates `Templates` from a `Tera` instance which has already been configured, such as with
+    /// additional filters or functions.
+    pub fn with_tera(tera: Tera) -> Templates {
+        Templates {
+            tera: AssertUnwindSafe(Arc::new(tera)),
+        }
+    }
+
+    /// Returns the template engine.
+    pub fn engine(&self) -> &Tera {
+        &self.tera
+    }
+}
+
+impl Clone for Templates {
+    fn clone(&self) -> Templates {
+        Templates {
+            tera: AssertUnwindSafe(self.tera.0.clone()),
+        }
+    }
+}
+
+impl StateData for Templates {}
+
+/// Renders the template `name` with `context`, using the `Templates` in `State`, and returns a
+/// `200 OK` response with the `text/html; charset=utf-8` content type.
+///
+/// When the template can't be rendered, the error is returned as a `HandlerError` which results
+/// in a `500 Internal Server Error` response, so the return value can be used as the response of
+/// a handler directly.
+///
+/// # Panics
+///
+/// If `Templates` is not present in `State`.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// #
+/// # use hyper::header::CONTENT_TYPE;
+/// # use hyper::{Body, Response, StatusCode};
+/// # use gotham::handler::HandlerError;
+/// # use gotham::middleware::state::StateMiddleware;
+/// # use gotham::pipeline::single_middleware;
+/// # use gotham::pipeline::single::single_pipeline;
+/// # use gotham::router::Router;
+/// # use gotham::router::builder::*;
+/// # use gotham::state::State;
+/// # use gotham::templates::{render, Context, Templates, Tera};
+/// # use gotham::test::TestServer;
+/// #
+/// fn greet(state: State) -> (State, Result<Response<Body>, HandlerError>) {
+///     let mut context = Context::new();
+///     context.insert("name", "world");
+///
+///     let res = render(&state, "greet.html", &context);
+///     (state, res)
+/// }
+///
+/// fn router(templates: Templates) -> Router {
+///     let (chain, pipelines) = single_pipeline(single_middleware(StateMiddleware::new(templates)));
+///
+///     build_router(chain, pipelines, |route| {
+///         route.get("/").to(greet);
+///     })
+/// }
+/// #
+/// # fn main() {
+/// // Usually `Templates::new("templates")`, to load the templates from a directory.
+/// let mut tera = Tera::default();
+/// tera.add_raw_template("greet.html", "<p>Hello, {{ name }}!</p>").unwrap();
+/// let templates = Templates::with_tera(tera);
+/// #
+/// # let test_server = TestServer::new(router(templates)).unwrap();
+/// # let response = test_server
+/// #     .client()
+/// #     .get("http://localhost/")
+/// #     .perform()
+/// #     .unwrap();
+/// #
+/// # assert_eq!(response.status(), StatusCode::OK);
+/// # assert_eq!(response.headers()[CONTENT_TYPE], "text/html; charset=utf-8");
+/// # assert_eq!(response.read_utf8_body().unwrap(), "<p>Hello, world!</p>");
+/// # }
+/// ```
+pub fn render<T>(state: &State, name: &str, context: &T) -> Result<Response<Body>, HandlerError>
+where
+    T: Serialize,
+{
+    match Templates::borrow_from(state).engine().render(name, context) {
+        Ok(body) => Ok(create_response(
+            state,
+            StatusCode::OK,
+            mime::TEXT_HTML_UTF_8,
+            body,
+        )),
+        Err(e) => {
+            request_error!(state, "unable to render template {}: {}", name, e);
+            Err(e.into_handler_error())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    use state::set_request_id;
+
+    fn state() -> State {
+        let mut tera = Tera::default();
+        tera.add_raw_template(
+            "list.html",
+            "{% for item in items %}<li>{{ item }}</li>{% endfor %}",
+        )
+        .unwrap();
+
+        let mut state = State::new();
+        state.put(Templates::with_tera(tera));
+        set_request_id(&mut state);
+        state
+    }
+
+    #[test]
+    fn renders_html() {
+        let mut context = HashMap::new();
+        context.insert("items", vec!["a", "<b>"]);
+
+        let res = render(&state(), "list.html", &context).unwrap();
+
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            res.headers()[::hyper::header::CONTENT_TYPE],
+            "text/html; charset=utf-8"
+        );
+    }
+
+    #[test]
+    fn missing_template_is_an_error() {
+        let err = render(&state(), "missing.html", &Context::new()).unwrap_err();
+        assert_eq!(err.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
@@ -0,0 +1,326 @@
+//! Defines `Bench`, which measures how long a handler or router takes to serve requests, so that
+//! changes to the performance of the dispatch and middleware path can be tracked.
+//!
+//! Requests are given directly to the same service which the server uses for each connection, on
+//! a single-threaded runtime, so the measurements don't include the time spent on the network or
+//! parsing HTTP. Each response body is read before the request is considered complete.
+//!
+//! Allocations are counted when `CountingAllocator` is installed as the global allocator of the
+//! program running the benchmark. All allocations made by the process while the requests are
+//! served are counted, so other threads should be idle.
+//!
+//! # Examples
+//!
+//! ```rust
+//! # extern crate gotham;
+//! # extern crate hyper;
+//! #
+//! # use hyper::{Body, Request, Response};
+//! # use gotham::router::Router;
+//! # use gotham::router::builder::*;
+//! # use gotham::state::State;
+//! use gotham::test::bench::{Bench, CountingAllocator};
+//!
+//! #[global_allocator]
+//! static ALLOCATOR: CountingAllocator = CountingAllocator;
+//!
+//! fn hello(state: State) -> (State, Response<Body>) {
+//!     (state, Response::new(Body::from("Hello, world!")))
+//! }
+//!
+//! fn router() -> Router {
+//!     build_simple_router(|route| {
+//!         route.get("/hello").to(hello);
+//!     })
+//! }
+//!
+//! # fn main() {
+//! let report = Bench::new(router())
+//!     .with_requests(200)
+//!     .run(|_| Request::get("/hello").body(Body::empty()).unwrap())
+//!     .unwrap();
+//!
+//! println!("{}", report);
+//! assert_eq!(report.requests(), 200);
+//! assert!(report.percentile(50.0) <= report.percentile(99.0));
+//! assert!(report.allocations_per_request().unwrap() > 0.0);
+//! # }
+//! ```
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use futures::{Future, Stream};
+use hyper::service::Service;
+use hyper::{Body, Request};
+use tokio::runtime::current_thread::Runtime;
+
+use handler::NewHandler;
+use service::GothamService;
+
+use error::*;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// A global allocator which counts allocations for `Bench`, and otherwise behaves as the system
+/// allocator.
+///
+/// It is installed in the program running the benchmark with
+/// `#[global_allocator] static ALLOCATOR: CountingAllocator = CountingAllocator;`.
+pub struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+/// Serves a number of requests with a handler or router, and reports how long they took.
+pub struct Bench<NH>
+where
+    NH: NewHandler + 'static,
+{
+    new_handler: NH,
+    requests: usize,
+    warmup: usize,
+}
+
+impl<NH> Bench<NH>
+where
+    NH: NewHandler + 'static,
+{
+    /// Creates a `Bench` which serves 1000 requests with the `Handler` created by `new_handler`,
+    /// after 100 requests to warm up which aren't measured.
+    pub fn new(new_handler: NH) -> Bench<NH> {
+        Bench {
+            new_handler,
+            requests: 1000,
+            warmup: 100,
+        }
+    }
+
+    /// Sets the number of requests which are measured.
+    pub fn with_requests(self, requests: usize) -> Bench<NH> {
+        Bench { requests, ..self }
+    }
+
+    /// Sets the number of requests which are served before measuring begins.
+    pub fn with_warmup(self, warmup: usize) -> Bench<NH> {
+        Bench { warmup, ..self }
+    }
+
+    /// Serves the requests one at a time, creating each with `request`, which is given the index
+    /// of the request, counting from zero for both the warm up and measured requests.
+    ///
+    /// An error is returned if the service fails to respond to a request, or the response body
+    /// can't be read.
+    pub fn run<F>(self, mut request: F) -> Result<BenchReport>
+    where
+        F: FnMut(usize) -> Request<Body>,
+    {
+        let mut runtime = Runtime::new()?;
+        let mut service = GothamService::new(self.new_handler)
+            .connect(SocketAddr::new(IpAddr::from([127, 0, 0, 1]), 10000));
+
+        let mut serve = |req: Request<Body>| -> Result<()> {
+            let f = service
+                .call(req)
+                .map_err(|e| e.into_inner())
+                .and_then(|res| res.into_body().concat2().map_err(Error::from));
+            runtime.block_on(f).map(|_| ())
+        };
+
+        for i in 0..self.warmup {
+            serve(request(i))?;
+        }
+
+        let mut latencies = Vec::with_capacity(self.requests);
+        let allocations = ALLOCATIONS.load(Ordering::SeqCst);
+        let started = Instant::now();
+
+        for i in self.warmup..self.warmup + self.requests {
+            let req = request(i);
+            let start = Instant::now();
+            serve(req)?;
+            latencies.push(start.elapsed());
+        }
+
+        let total = started.elapsed();
+        let allocations = ALLOCATIONS.load(Ordering::SeqCst) - allocations;
+        latencies.sort();
+
+        Ok(BenchReport {
+            latencies,
+            total,
+            // requests always allocate, so no allocations means `CountingAllocator` isn't in use
+            allocations: if allocations > 0 {
+                Some(allocations)
+            } else {
+                None
+            },
+        })
+    }
+}
+
+/// The measurements made by `Bench::run`.
+#[derive(Clone, Debug)]
+pub struct BenchReport {
+    latencies: Vec<Duration>,
+    total: Duration,
+    allocations: Option<usize>,
+}
+
+impl BenchReport {
+    /// The number of requests which were measured.
+    pub fn requests(&self) -> usize {
+        self.latencies.len()
+    }
+
+    /// The time taken to serve all of the measured requests.
+    pub fn total(&self) -> Duration {
+        self.total
+    }
+
+    /// The number of requests served per second.
+    pub fn requests_per_second(&self) -> f64 {
+        self.requests() as f64 / as_secs_f64(self.total)
+    }
+
+    /// The mean time taken to serve a request.
+    pub fn mean(&self) -> Duration {
+        if self.latencies.is_empty() {
+            return Duration::from_secs(0);
+        }
+
+        let sum = self
+            .latencies
+            .iter()
+            .fold(Duration::from_secs(0), |sum, latency| sum + *latency);
+        sum / self.latencies.len() as u32
+    }
+
+    /// The shortest time taken to serve a request.
+    pub fn min(&self) -> Duration {
+        self.percentile(0.0)
+    }
+
+    /// The longest time taken to serve a request.
+    pub fn max(&self) -> Duration {
+        self.percentile(100.0)
+    }
+
+    /// The time within which `percentile` percent of the requests were served, such as `99.0` for
+    /// the 99th percentile.
+    pub fn percentile(&self, percentile: f64) -> Duration {
+        if self.latencies.is_empty() {
+            return Duration::from_secs(0);
+        }
+
+        let rank = (percentile / 100.0 * self.latencies.len() as f64).ceil() as usize;
+        let index = rank.max(1).min(self.latencies.len()) - 1;
+        self.latencies[index]
+    }
+
+    /// The mean number of allocations made per request, when `CountingAllocator` is the global
+    /// allocator.
+    pub fn allocations_per_request(&self) -> Option<f64> {
+        self.allocations
+            .map(|allocations| allocations as f64 / self.requests().max(1) as f64)
+    }
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} requests in {:?} ({:.0} req/s), latency mean={:?} min={:?} p50={:?} p90={:?} \
+             p99={:?} max={:?}",
+            self.requests(),
+            self.total,
+            self.requests_per_second(),
+            self.mean(),
+            self.min(),
+            self.percentile(50.0),
+            self.percentile(90.0),
+            self.percentile(99.0),
+            self.max()
+        )?;
+
+        match self.allocations_per_request() {
+            Some(allocations) => write!(f, ", {:.1} allocations/request", allocations),
+            None => Ok(()),
+        }
+    }
+}
+
+fn as_secs_f64(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + f64::from(duration.subsec_nanos()) / 1_000_000_000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use hyper::Response;
+
+    use state::State;
+
+    fn report(millis: &[u64]) -> BenchReport {
+        BenchReport {
+            latencies: millis.iter().map(|ms| Duration::from_millis(*ms)).collect(),
+            total: Duration::from_millis(millis.iter().sum()),
+            allocations: Some(millis.len() * 10),
+        }
+    }
+
+    #[test]
+    fn computes_distribution() {
+        let report = report(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10]);
+
+        assert_eq!(report.requests(), 10);
+        assert_eq!(report.min(), Duration::from_millis(1));
+        assert_eq!(report.max(), Duration::from_millis(10));
+        assert_eq!(report.percentile(50.0), Duration::from_millis(5));
+        assert_eq!(report.percentile(90.0), Duration::from_millis(9));
+        assert_eq!(report.percentile(99.0), Duration::from_millis(10));
+        assert_eq!(report.mean(), Duration::from_micros(5500));
+        assert_eq!(report.allocations_per_request(), Some(10.0));
+        assert!((report.requests_per_second() - 10.0 / 0.055).abs() < 0.001);
+    }
+
+    #[test]
+    fn measures_requests() {
+        fn handler(state: State) -> (State, Response<Body>) {
+            (state, Response::new(Body::from("bench")))
+        }
+
+        let mut seen = Vec::new();
+        let report = Bench::new(|| Ok(handler))
+            .with_requests(20)
+            .with_warmup(5)
+            .run(|i| {
+                seen.push(i);
+                Request::get("/").body(Body::empty()).unwrap()
+            })
+            .unwrap();
+
+        assert_eq!(report.requests(), 20);
+        assert_eq!(seen, (0..25).collect::<Vec<_>>());
+        assert!(report.min() <= report.percentile(50.0));
+        assert!(report.percentile(50.0) <= report.max());
+        assert!(report.to_string().starts_with("20 requests in "));
+    }
+}
@@ -0,0 +1,248 @@
+//! Defines `StateCapture`, which records values from the final `State` of requests handled by a
+//! `TestServer`.
+
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::panic::RefUnwindSafe;
+use std::sync::{Arc, Mutex};
+
+use futures::Future;
+
+use handler::{Handler, HandlerFuture, NewHandler};
+use state::{FromState, State, StateData};
+
+use error::*;
+
+type Extractor = Fn(&State, &mut CapturedState) + Send + Sync + RefUnwindSafe;
+
+/// Records selected values from the `State` of each request handled by a `TestServer`, once the
+/// handler and all middleware have finished with it, so that tests can make assertions about the
+/// work done on the server which isn't visible in the response.
+///
+/// The types to record are chosen with `StateCapture::with_type`, and must implement `Clone`. A
+/// `StateCapture` is given to a `TestServer` with `TestServer::with_state_capture`, and shares its
+/// records with its clones, so one clone can be kept by the test to read them.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate gotham;
+/// # #[macro_use]
+/// # extern crate gotham_derive;
+/// # extern crate hyper;
+/// #
+/// # use hyper::{Body, Response};
+/// # use gotham::state::State;
+/// # use gotham::test::{StateCapture, TestServer};
+/// #
+/// #[derive(Clone, StateData)]
+/// struct CurrentUser(String);
+///
+/// fn handler(mut state: State) -> (State, Response<Body>) {
+///     state.put(CurrentUser("ada".to_owned()));
+///     (state, Response::new(Body::empty()))
+/// }
+///
+/// # fn main() {
+/// let capture = StateCapture::new().with_type::<CurrentUser>();
+/// let test_server = TestServer::with_state_capture(|| Ok(handler), capture.clone()).unwrap();
+///
+/// test_server.client().get("/").perform().unwrap();
+///
+/// let state = capture.take().pop().unwrap();
+/// assert_eq!(state.get::<CurrentUser>().unwrap().0, "ada");
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct StateCapture {
+    extractors: Vec<Arc<Extractor>>,
+    captured: Arc<Mutex<Vec<CapturedState>>>,
+}
+
+impl StateCapture {
+    /// Creates a `StateCapture` which records no values until types are added with
+    /// `StateCapture::with_type`.
+    pub fn new() -> StateCapture {
+        StateCapture {
+            extractors: Vec::new(),
+            captured: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Records a clone of the `T` in the final `State` of each request, if there is one.
+    pub fn with_type<T>(mut self) -> StateCapture
+    where
+        T: StateData + Clone,
+    {
+        let extractor = |state: &State, captured: &mut CapturedState| {
+            if let Some(value) = T::try_borrow_from(state) {
+                captured
+                    .values
+                    .insert(TypeId::of::<T>(), Box::new(value.clone()));
+            }
+        };
+
+        self.extractors.push(Arc::new(extractor));
+        self
+    }
+
+    /// Removes and returns the values recorded so far, for each request in the order in which
+    /// the requests were completed.
+    pub fn take(&self) -> Vec<CapturedState> {
+        let mut captured = self.captured.lock().unwrap();
+        captured.drain(..).collect()
+    }
+
+    /// Wraps `new_handler` so that the `State` of each request it handles is recorded.
+    pub(super) fn wrap<NH>(self, new_handler: NH) -> CapturingNewHandler<NH>
+    where
+        NH: NewHandler,
+    {
+        CapturingNewHandler {
+            new_handler,
+            capture: self,
+        }
+    }
+
+    fn record(&self, state: &State) {
+        let mut captured = CapturedState {
+            values: HashMap::new(),
+        };
+
+        for extractor in &self.extractors {
+            extractor(state, &mut captured);
+        }
+
+        self.captured.lock().unwrap().push(captured);
+    }
+}
+
+impl Default for StateCapture {
+    fn default() -> StateCapture {
+        StateCapture::new()
+    }
+}
+
+/// The values recorded by a `StateCapture` from the final `State` of a single request.
+pub struct CapturedState {
+    values: HashMap<TypeId, Box<Any + Send>>,
+}
+
+impl CapturedState {
+    /// Returns the recorded `T`, if one was in the `State`.
+    pub fn get<T>(&self) -> Option<&T>
+    where
+        T: StateData,
+    {
+        self.values
+            .get(&TypeId::of::<T>())
+            .and_then(|value| value.downcast_ref::<T>())
+    }
+
+    /// Determines if a `T` was in the `State`.
+    pub fn has<T>(&self) -> bool
+    where
+        T: StateData,
+    {
+        self.get::<T>().is_some()
+    }
+}
+
+pub(super) struct CapturingNewHandler<NH> {
+    new_handler: NH,
+    capture: StateCapture,
+}
+
+impl<NH> NewHandler for CapturingNewHandler<NH>
+where
+    NH: NewHandler,
+{
+    type Instance = CapturingHandler<NH::Instance>;
+
+    fn new_handler(&self) -> Result<Self::Instance> {
+        Ok(CapturingHandler {
+            handler: self.new_handler.new_handler()?,
+            capture: self.capture.clone(),
+        })
+    }
+}
+
+pub(super) struct CapturingHandler<H> {
+    handler: H,
+    capture: StateCapture,
+}
+
+impl<H> Handler for CapturingHandler<H>
+where
+    H: Handler,
+{
+    fn handle(self, state: State) -> Box<HandlerFuture> {
+        let capture = self.capture;
+
+        Box::new(self.handler.handle(state).then(move |result| {
+            match result {
+                Ok((ref state, _)) | Err((ref state, _)) => capture.record(state),
+            }
+            result
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::future;
+    use hyper::{Body, Response, StatusCode, Uri};
+
+    use std::io;
+
+    use handler::{HandlerError, IntoHandlerError};
+    use test::TestServer;
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct Visited(&'static str);
+
+    impl StateData for Visited {}
+
+    #[derive(Clone)]
+    struct NeverSet;
+
+    impl StateData for NeverSet {}
+
+    fn handler(mut state: State) -> Box<HandlerFuture> {
+        state.put(Visited("handler"));
+
+        let result = if Uri::borrow_from(&state).path() == "/fail" {
+            let e = io::Error::new(io::ErrorKind::Other, "failed");
+            Err((state, e.into_handler_error()))
+        } else {
+            Ok((state, Response::new(Body::empty())))
+        };
+
+        Box::new(future::result::<_, (State, HandlerError)>(result))
+    }
+
+    #[test]
+    fn captures_final_state() {
+        let capture = StateCapture::new()
+            .with_type::<Visited>()
+            .with_type::<NeverSet>();
+        let test_server = TestServer::with_state_capture(|| Ok(handler), capture.clone()).unwrap();
+        let client = test_server.client();
+
+        let response = client.get("/").perform().unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = client.get("/fail").perform().unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        let captured = capture.take();
+        assert_eq!(captured.len(), 2);
+        for state in &captured {
+            assert_eq!(state.get::<Visited>(), Some(&Visited("handler")));
+            assert!(!state.has::<NeverSet>());
+        }
+
+        assert!(capture.take().is_empty());
+    }
+}
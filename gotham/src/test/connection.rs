@@ -0,0 +1,131 @@
+//! Defines the connections between a `TestClient` and its `TestServer`, which carry the client
+//! address chosen by the test.
+
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::net::{self, SocketAddr};
+use std::sync::{Arc, Mutex};
+
+use failure;
+use futures::{future, Future, Poll};
+use hyper::client::connect::{Connect, Connected, Destination};
+use net2::TcpBuilder;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio::reactor::Handle;
+
+use server::{Connection, ServerBuilder};
+
+use error::*;
+
+/// The client addresses chosen by tests, keyed by the local address of the client's socket, for
+/// connections which have not yet been accepted by the `TestServer`.
+pub(super) type ClientAddrs = Arc<Mutex<HashMap<SocketAddr, SocketAddr>>>;
+
+/// `TestConnect` represents the connection between a test client and the `TestServer` instance
+/// that created it. This type should never be used directly.
+pub(super) struct TestConnect {
+    pub(super) addr: SocketAddr,
+    pub(super) client_addr: SocketAddr,
+    pub(super) client_addrs: ClientAddrs,
+}
+
+impl TestConnect {
+    // Binds the socket for a new connection, and records the client address for the connection
+    // before it is made, so that it is known when the `TestServer` accepts it.
+    fn socket(&self) -> io::Result<net::TcpStream> {
+        let builder = TcpBuilder::new_v4()?;
+        builder.bind("127.0.0.1:0")?;
+        let socket = builder.to_tcp_stream()?;
+
+        self.client_addrs
+            .lock()
+            .unwrap()
+            .insert(socket.local_addr()?, self.client_addr);
+
+        Ok(socket)
+    }
+}
+
+impl Connect for TestConnect {
+    type Transport = TcpStream;
+    type Error = CompatError;
+    type Future =
+        Box<Future<Item = (Self::Transport, Connected), Error = Self::Error> + Send + Sync>;
+
+    fn connect(&self, dst: Destination) -> Self::Future {
+        // The `TestServer` serves plain HTTP, so an `https` request can't be sent to it until
+        // there is a TLS connector to send it with.
+        if dst.scheme() != "http" {
+            let message = format!("TestClient: unsupported scheme `{}`", dst.scheme());
+            return Box::new(future::err(failure::err_msg(message).compat()));
+        }
+
+        let socket = match self.socket() {
+            Ok(socket) => socket,
+            Err(e) => return Box::new(future::err(Error::from(e).compat())),
+        };
+
+        Box::new(
+            TcpStream::connect_std(socket, &self.addr, &Handle::default())
+                .inspect(|s| info!("Client TcpStream connected: {:?}", s))
+                .map(|s| (s, Connected::new()))
+                .map_err(|e| Error::from(e).compat()),
+        )
+    }
+}
+
+/// A connection accepted by a `TestServer`, which reports the client address chosen by the test
+/// for the `TestClient` that made it. Connections made by other clients report their real address.
+pub(super) struct TestConnection {
+    stream: TcpStream,
+    client_addr: Option<SocketAddr>,
+}
+
+impl TestConnection {
+    pub(super) fn new(stream: TcpStream, client_addrs: &ClientAddrs) -> TestConnection {
+        let client_addr = stream
+            .peer_addr()
+            .ok()
+            .and_then(|peer| client_addrs.lock().unwrap().remove(&peer));
+
+        TestConnection {
+            stream,
+            client_addr,
+        }
+    }
+}
+
+impl Read for TestConnection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stream.read(buf)
+    }
+}
+
+impl Write for TestConnection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+impl AsyncRead for TestConnection {}
+
+impl AsyncWrite for TestConnection {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        AsyncWrite::shutdown(&mut self.stream)
+    }
+}
+
+impl Connection for TestConnection {
+    fn client_addr(&self) -> Option<SocketAddr> {
+        self.client_addr.or_else(|| self.stream.client_addr())
+    }
+
+    fn configure(&self, builder: &ServerBuilder) -> io::Result<()> {
+        self.stream.configure(builder)
+    }
+}
@@ -0,0 +1,108 @@
+//! Decodes response bodies which have a `Content-Encoding`, such as those compressed by
+//! `CompressionMiddleware`.
+
+use std::io::Read;
+
+use brotli::Decompressor;
+use failure;
+use flate2::read::{DeflateDecoder, GzDecoder, ZlibDecoder};
+use hyper::header::{HeaderMap, CONTENT_ENCODING};
+
+use error::*;
+
+/// Reverses each of the encodings listed in the `Content-Encoding` headers, in the opposite order
+/// to which they were applied. A body without a `Content-Encoding` is returned unchanged.
+pub(super) fn decode_body(headers: &HeaderMap, body: Vec<u8>) -> Result<Vec<u8>> {
+    let mut encodings = Vec::new();
+    for value in headers.get_all(CONTENT_ENCODING) {
+        for encoding in value.to_str()?.split(',') {
+            encodings.push(encoding.trim().to_ascii_lowercase());
+        }
+    }
+
+    let mut body = body;
+    for encoding in encodings.iter().rev() {
+        let mut decoded = Vec::new();
+        match encoding.as_str() {
+            "identity" | "" => continue,
+            "gzip" | "x-gzip" => GzDecoder::new(&body[..]).read_to_end(&mut decoded)?,
+            // `deflate` is meant to be the zlib format, but some servers send raw deflate data
+            "deflate" => match ZlibDecoder::new(&body[..]).read_to_end(&mut decoded) {
+                Ok(len) => len,
+                Err(_) => {
+                    decoded.clear();
+                    DeflateDecoder::new(&body[..]).read_to_end(&mut decoded)?
+                }
+            },
+            "br" => Decompressor::new(&body[..], 4096).read_to_end(&mut decoded)?,
+            other => {
+                let message = format!("unsupported content encoding: {}", other);
+                return Err(failure::err_msg(message));
+            }
+        };
+        body = decoded;
+    }
+
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Write;
+
+    use brotli::CompressorWriter;
+    use flate2::write::{DeflateEncoder, GzEncoder, ZlibEncoder};
+    use flate2::Compression;
+    use hyper::header::HeaderValue;
+
+    fn encoded(encoding: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_ENCODING, HeaderValue::from_static(encoding));
+        headers
+    }
+
+    fn gzip(body: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(body).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn decodes_each_encoding() {
+        let mut zlib = ZlibEncoder::new(Vec::new(), Compression::default());
+        zlib.write_all(b"zlib").unwrap();
+
+        let mut deflate = DeflateEncoder::new(Vec::new(), Compression::default());
+        deflate.write_all(b"deflate").unwrap();
+
+        let mut brotli = Vec::new();
+        {
+            let mut writer = CompressorWriter::new(&mut brotli, 4096, 5, 22);
+            writer.write_all(b"brotli").unwrap();
+        }
+
+        let decode = |encoding, body| decode_body(&encoded(encoding), body).unwrap();
+        assert_eq!(decode("gzip", gzip(b"gzip")), b"gzip");
+        assert_eq!(decode("deflate", zlib.finish().unwrap()), b"zlib");
+        assert_eq!(decode("deflate", deflate.finish().unwrap()), b"deflate");
+        assert_eq!(decode("br", brotli), b"brotli");
+        assert_eq!(decode("identity", b"plain".to_vec()), b"plain");
+        assert_eq!(
+            decode_body(&HeaderMap::new(), b"plain".to_vec()).unwrap(),
+            b"plain"
+        );
+    }
+
+    #[test]
+    fn decodes_in_reverse_order() {
+        let body = gzip(&gzip(b"twice"));
+        assert_eq!(decode_body(&encoded("gzip, GZIP"), body).unwrap(), b"twice");
+    }
+
+    #[test]
+    fn rejects_unknown_encodings() {
+        assert!(decode_body(&encoded("compress"), b"body".to_vec()).is_err());
+    }
+}
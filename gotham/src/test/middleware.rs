@@ -0,0 +1,257 @@
+//! Defines `MiddlewareTester`, which runs a single `Middleware` without a `TestServer`.
+
+use std::net::{IpAddr, SocketAddr};
+
+use http::HttpTryFrom;
+use hyper::header::{HeaderMap, HeaderValue, IntoHeaderName};
+use hyper::{Body, Method, Request, Response, Uri};
+use tokio::runtime::current_thread::Runtime;
+
+use handler::{HandlerError, IntoHandlerFuture};
+use helpers::http::request::path::RequestPathSegments;
+use middleware::Middleware;
+use state::client_addr::put_client_addr;
+use state::{set_request_id, State, StateData};
+
+/// The outcome of running a `Middleware` with a `MiddlewareTester`, which is the `State` along
+/// with the response or error which the `Middleware` produced.
+pub type MiddlewareResult = Result<(State, Response<Body>), (State, HandlerError)>;
+
+/// Runs a single `Middleware` against a synthetic request, so that a `Middleware` can be unit
+/// tested without a `Router` or `TestServer`.
+///
+/// The `State` given to the `Middleware` is populated as it would be by the server, with the
+/// request method, URI, headers and body, a request ID and a client address of `127.0.0.1:10000`.
+/// The chain passed to the `Middleware` is a closure provided by the test, which stands in for the
+/// rest of the pipeline and the handler, so it can make assertions about the `State` it receives
+/// and choose the response. The future returned by the `Middleware` is run to completion, on a
+/// runtime which supports timers.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate gotham;
+/// # #[macro_use]
+/// # extern crate gotham_derive;
+/// # extern crate hyper;
+/// #
+/// # use hyper::{Body, Method, Response, StatusCode};
+/// # use gotham::handler::HandlerFuture;
+/// # use gotham::middleware::Middleware;
+/// # use gotham::state::{FromState, State};
+/// # use gotham::test::MiddlewareTester;
+/// #
+/// #[derive(StateData)]
+/// struct Greeting(&'static str);
+///
+/// #[derive(Clone, NewMiddleware)]
+/// struct GreetingMiddleware;
+///
+/// impl Middleware for GreetingMiddleware {
+///     fn call<Chain>(self, mut state: State, chain: Chain) -> Box<HandlerFuture>
+///     where
+///         Chain: FnOnce(State) -> Box<HandlerFuture> + Send + 'static,
+///     {
+///         state.put(Greeting("hello"));
+///         chain(state)
+///     }
+/// }
+///
+/// # fn main() {
+/// let result = MiddlewareTester::new(Method::GET, "/greet").run(GreetingMiddleware, |state| {
+///     assert_eq!(Greeting::borrow_from(&state).0, "hello");
+///     (state, Response::new(Body::empty()))
+/// });
+///
+/// let (state, response) = result.map_err(|(_, err)| err).unwrap();
+/// assert_eq!(response.status(), StatusCode::OK);
+/// assert!(state.has::<Greeting>());
+/// # }
+/// ```
+pub struct MiddlewareTester {
+    state: State,
+}
+
+impl MiddlewareTester {
+    /// Creates a `MiddlewareTester` for a request with `method` and `uri`, and an empty body.
+    ///
+    /// # Panics
+    ///
+    /// If `uri` is not a valid URI.
+    pub fn new<U>(method: Method, uri: U) -> MiddlewareTester
+    where
+        Uri: HttpTryFrom<U>,
+    {
+        let (parts, body) = Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(Body::empty())
+            .expect("MiddlewareTester: invalid request")
+            .into_parts();
+
+        let mut state = State::new();
+        put_client_addr(
+            &mut state,
+            SocketAddr::new(IpAddr::from([127, 0, 0, 1]), 10000),
+        );
+        state.put(RequestPathSegments::new(parts.uri.path()));
+        state.put(parts.method);
+        state.put(parts.uri);
+        state.put(parts.version);
+        state.put(parts.headers);
+        state.put(body);
+
+        MiddlewareTester { state }
+    }
+
+    /// Adds the given header to the request.
+    pub fn with_header<N>(mut self, name: N, value: HeaderValue) -> MiddlewareTester
+    where
+        N: IntoHeaderName,
+    {
+        self.state.borrow_mut::<HeaderMap>().append(name, value);
+        self
+    }
+
+    /// Sets the body of the request.
+    pub fn with_body<B>(mut self, body: B) -> MiddlewareTester
+    where
+        B: Into<Body>,
+    {
+        self.state.put(body.into());
+        self
+    }
+
+    /// Sets the address of the client which made the request.
+    pub fn with_client_addr(mut self, addr: SocketAddr) -> MiddlewareTester {
+        put_client_addr(&mut self.state, addr);
+        self
+    }
+
+    /// Puts `data` into the `State` before the `Middleware` is called, such as data which would
+    /// have been added by an earlier `Middleware` in the pipeline.
+    pub fn with_state_data<T>(mut self, data: T) -> MiddlewareTester
+    where
+        T: StateData,
+    {
+        self.state.put(data);
+        self
+    }
+
+    /// Calls `middleware` with the request, using `chain` as the rest of the pipeline, and waits
+    /// for the result.
+    ///
+    /// # Panics
+    ///
+    /// If a runtime can't be created to run the `Middleware`.
+    pub fn run<M, C, R>(self, middleware: M, chain: C) -> MiddlewareResult
+    where
+        M: Middleware,
+        C: FnOnce(State) -> R + Send + 'static,
+        R: IntoHandlerFuture,
+    {
+        let mut state = self.state;
+        set_request_id(&mut state);
+
+        let f = middleware.call(state, move |state| chain(state).into_handler_future());
+
+        Runtime::new()
+            .expect("MiddlewareTester: unable to create runtime")
+            .block_on(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    use futures::future;
+    use hyper::header::ACCEPT;
+    use hyper::StatusCode;
+
+    use handler::HandlerFuture;
+    use middleware::timeout::RequestTimeout;
+    use state::{client_addr, request_id, FromState};
+
+    #[derive(Clone)]
+    struct Halt;
+
+    impl Middleware for Halt {
+        fn call<Chain>(self, state: State, _chain: Chain) -> Box<HandlerFuture>
+        where
+            Chain: FnOnce(State) -> Box<HandlerFuture> + Send + 'static,
+        {
+            let mut res = Response::new(Body::empty());
+            *res.status_mut() = StatusCode::FORBIDDEN;
+            Box::new(future::ok((state, res)))
+        }
+    }
+
+    #[derive(Clone)]
+    struct Passthrough;
+
+    impl Middleware for Passthrough {
+        fn call<Chain>(self, state: State, chain: Chain) -> Box<HandlerFuture>
+        where
+            Chain: FnOnce(State) -> Box<HandlerFuture> + Send + 'static,
+        {
+            chain(state)
+        }
+    }
+
+    #[test]
+    fn populates_request_state() {
+        let addr = "10.0.0.1:4000".parse().unwrap();
+        let (state, res) = MiddlewareTester::new(Method::POST, "/items?page=2")
+            .with_header(ACCEPT, HeaderValue::from_static("text/plain"))
+            .with_client_addr(addr)
+            .run(Passthrough, move |state| {
+                assert_eq!(*Method::borrow_from(&state), Method::POST);
+                assert_eq!(Uri::borrow_from(&state).query(), Some("page=2"));
+                assert_eq!(HeaderMap::borrow_from(&state)[ACCEPT], "text/plain");
+                assert_eq!(client_addr(&state), Some(addr));
+                assert!(!request_id(&state).is_empty());
+                (state, Response::new(Body::empty()))
+            })
+            .map_err(|(_, err)| err)
+            .unwrap();
+
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(*Method::borrow_from(&state), Method::POST);
+    }
+
+    #[test]
+    fn chain_is_not_called_when_halted() {
+        let (_, res) = MiddlewareTester::new(Method::GET, "/")
+            .run(Halt, |_state: State| -> (State, Response<Body>) {
+                panic!("chain was called")
+            })
+            .map_err(|(_, err)| err)
+            .unwrap();
+
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn supports_timers() {
+        use tokio::timer::Delay;
+
+        use futures::Future;
+        use std::time::Instant;
+
+        let middleware = RequestTimeout::new(Duration::from_millis(10));
+        let (_, res) = MiddlewareTester::new(Method::GET, "/")
+            .run(middleware, |state| -> Box<HandlerFuture> {
+                let delay = Delay::new(Instant::now() + Duration::from_secs(5));
+                Box::new(delay.then(move |_| {
+                    Ok::<_, (State, HandlerError)>((state, Response::new(Body::empty())))
+                }))
+            })
+            .map_err(|(_, err)| err)
+            .unwrap();
+
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+}
@@ -1,45 +1,79 @@
 //! Contains helpers for Gotham applications to use during testing.
 //!
+//! The `TestServer` and its clients communicate over loopback TCP, rather than any
+//! platform-specific transport, so the same tests run on Unix and Windows.
+//!
 //! See the `TestServer` type for example usage.
 
 use std::fmt;
+use std::mem;
 use std::net::{self, IpAddr, SocketAddr};
 use std::ops::{Deref, DerefMut};
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, RwLock};
 use std::time::{Duration, Instant};
 
 use failure;
 
-use futures::{future, Future, Stream};
+use bytes::Bytes;
+use cookie::{Cookie, CookieJar};
+use futures::future::{self, Loop};
+use futures::{Future, Stream};
 use http::HttpTryFrom;
-use hyper::client::{
-    connect::{Connect, Connected, Destination},
-    Client,
-};
-use hyper::header::CONTENT_TYPE;
-use hyper::{Body, Method, Response, Uri};
+use hyper::client::Client;
+use hyper::header::{AsHeaderName, HeaderMap, HeaderValue, CONTENT_TYPE, COOKIE, SET_COOKIE};
+use hyper::{Body, Method, Request, Response, StatusCode, Uri};
 use mime;
-use tokio::net::{TcpListener, TcpStream};
+use net2::TcpBuilder;
+use serde::de::DeserializeOwned;
+use serde_json;
+use tokio::net::TcpListener;
+use tokio::reactor::Handle;
 use tokio::runtime::Runtime;
 use tokio::timer::Delay;
 
 use handler::NewHandler;
+use server::ServerBuilder;
+
+use self::connection::{ClientAddrs, TestConnect, TestConnection};
+use self::redirect::SentRequest;
 
 use error::*;
 
+pub mod bench;
+mod capture;
+mod connection;
+mod decode;
+mod middleware;
+mod multipart;
+mod raw;
+mod redirect;
 mod request;
+mod websocket;
 
+pub use self::capture::{CapturedState, StateCapture};
+pub use self::middleware::{MiddlewareResult, MiddlewareTester};
+pub use self::multipart::MultipartForm;
+pub use self::raw::{ConnectionOutcome, RawResponse};
+pub use self::redirect::Redirect;
 pub use self::request::TestRequest;
+pub use self::websocket::{TestWebSocket, WebSocketMessage};
 
 struct TestServerData {
     addr: SocketAddr,
-    timeout: u64,
+    client_addrs: ClientAddrs,
+    timeout: Duration,
     runtime: RwLock<Runtime>,
 }
 
 /// The `TestServer` type, which is used as a harness when writing test cases for Hyper services
 /// (which Gotham's `Router` is). An instance of `TestServer` is run asynchronously within the
-/// current thread, and is only accessible by a client returned from the `TestServer`.
+/// current thread, listening on a TCP port of `127.0.0.1` which is assigned by the OS. Requests
+/// are usually made with a client returned from the `TestServer`, but any HTTP client can connect
+/// to the address returned by `TestServer::addr`.
+///
+/// Gotham serves plain HTTP, so the `TestServer` doesn't use TLS, and a request made by a
+/// `TestClient` to an `https` URI fails. Behaviours which depend on the scheme can be tested by
+/// sending `X-Forwarded-Proto` through a trusted proxy, or with a `MiddlewareTester`.
 ///
 /// # Examples
 ///
@@ -85,21 +119,66 @@ impl TestServer {
         TestServer::with_timeout(new_handler, 10)
     }
 
-    /// Sets the request timeout to `timeout` seconds and returns a new `TestServer`.
+    /// Sets the request timeout to `timeout` seconds and returns a new `TestServer`. The timeout
+    /// of an individual request can be changed with `TestRequest::with_timeout`.
     pub fn with_timeout<NH: NewHandler + 'static>(
         new_handler: NH,
         timeout: u64,
+    ) -> Result<TestServer> {
+        TestServer::start(ServerBuilder::new(), new_handler, timeout)
+    }
+
+    /// Creates a `TestServer` which serves requests as configured by `builder`, such as with a
+    /// `Clock` or `RandomSource` which makes the behaviour of the application reproducible. The
+    /// timeout is set to 10 seconds, and the number of threads and listen backlog of `builder`
+    /// are not used.
+    pub fn with_server_builder<NH: NewHandler + 'static>(
+        builder: ServerBuilder,
+        new_handler: NH,
+    ) -> Result<TestServer> {
+        TestServer::start(builder, new_handler, 10)
+    }
+
+    /// Creates a `TestServer` which records values from the final `State` of each request with
+    /// `capture`, so that tests can check the work done by the handler and middleware. The timeout
+    /// is set to 10 seconds.
+    pub fn with_state_capture<NH: NewHandler + 'static>(
+        new_handler: NH,
+        capture: StateCapture,
+    ) -> Result<TestServer> {
+        TestServer::new(capture.wrap(new_handler))
+    }
+
+    fn start<NH: NewHandler + 'static>(
+        builder: ServerBuilder,
+        new_handler: NH,
+        timeout: u64,
     ) -> Result<TestServer> {
         let mut runtime = Runtime::new()?;
-        let listener = TcpListener::bind(&"127.0.0.1:0".parse()?)?;
+
+        // Allow the port to be bound again straight away once the `TestServer` has gone, so that
+        // test suites creating many servers don't exhaust the ports held in `TIME_WAIT`.
+        let socket = TcpBuilder::new_v4()?;
+        socket.reuse_address(true)?;
+        socket.bind("127.0.0.1:0")?;
+        let listener = TcpListener::from_std(socket.listen(1024)?, &Handle::default())?;
         let addr = listener.local_addr()?;
 
-        let service_stream = super::bind_server(listener, new_handler);
+        let client_addrs = ClientAddrs::default();
+        let incoming = {
+            let client_addrs = client_addrs.clone();
+            listener
+                .incoming()
+                .map(move |stream| TestConnection::new(stream, &client_addrs))
+        };
+
+        let service_stream = builder.bind_incoming(incoming, new_handler);
         runtime.spawn(service_stream);
 
         let data = TestServerData {
             addr,
-            timeout,
+            client_addrs,
+            timeout: Duration::from_secs(timeout),
             runtime: RwLock::new(runtime),
         };
 
@@ -108,6 +187,75 @@ impl TestServer {
         })
     }
 
+    /// Returns the address which the `TestServer` is listening on, so that requests can be made
+    /// with other HTTP clients and tools, such as a load generator, over a real TCP connection.
+    ///
+    /// ```rust
+    /// # extern crate hyper;
+    /// # extern crate gotham;
+    /// #
+    /// # use std::io::{Read, Write};
+    /// # use std::net::TcpStream;
+    /// # use gotham::state::State;
+    /// # use hyper::{Body, Response};
+    /// #
+    /// # fn my_handler(state: State) -> (State, Response<Body>) {
+    /// #   (state, Response::new(Body::from("Hello")))
+    /// # }
+    /// #
+    /// # fn main() {
+    /// use gotham::test::TestServer;
+    ///
+    /// let test_server = TestServer::new(|| Ok(my_handler)).unwrap();
+    ///
+    /// let mut stream = TcpStream::connect(test_server.addr()).unwrap();
+    /// stream
+    ///     .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+    ///     .unwrap();
+    ///
+    /// let mut response = String::new();
+    /// stream.read_to_string(&mut response).unwrap();
+    /// assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    /// assert!(response.ends_with("\r\n\r\nHello"));
+    /// # }
+    /// ```
+    pub fn addr(&self) -> SocketAddr {
+        self.data.addr
+    }
+
+    /// Writes `request` to a new connection to the `TestServer`, without checking that it is
+    /// valid HTTP, and returns what the server sent back along with how the connection ended.
+    ///
+    /// The connection is closed for writing once `request` is written, and then read until the
+    /// server closes it or the server timeout has elapsed. This allows tests, including fuzz
+    /// tests, to send malformed requests and check how header parsing, routing and error
+    /// responses handle them.
+    ///
+    /// ```rust
+    /// # extern crate hyper;
+    /// # extern crate gotham;
+    /// #
+    /// # use gotham::state::State;
+    /// # use hyper::{Body, Response, StatusCode};
+    /// #
+    /// # fn my_handler(state: State) -> (State, Response<Body>) {
+    /// #   (state, Response::new(Body::from("Hello")))
+    /// # }
+    /// #
+    /// # fn main() {
+    /// use gotham::test::{ConnectionOutcome, TestServer};
+    ///
+    /// let test_server = TestServer::new(|| Ok(my_handler)).unwrap();
+    ///
+    /// let response = test_server.send_raw(b"GET / HTTP/1.1\r\nHost\r\n\r\n").unwrap();
+    /// assert_eq!(response.status(), Some(StatusCode::BAD_REQUEST));
+    /// assert_eq!(response.outcome(), ConnectionOutcome::Closed);
+    /// # }
+    /// ```
+    pub fn send_raw(&self, request: &[u8]) -> Result<RawResponse> {
+        Ok(raw::send_raw(self.data.addr, request, self.data.timeout)?)
+    }
+
     /// Returns a client connected to the `TestServer`. The transport is handled internally, and
     /// the server will see a default socket address of `127.0.0.1:10000` as the source address for
     /// the connection.
@@ -132,36 +280,45 @@ impl TestServer {
     /// Returns a client connected to the `TestServer`. The transport is handled internally, and
     /// the server will see `client_addr` as the source address for the connection. The
     /// `client_addr` can be any valid `SocketAddr`, and need not be contactable.
+    ///
+    /// This allows behaviour which depends on the client address to be tested, such as rate
+    /// limiting or the handling of headers from trusted proxies. Similarly, the host of a request
+    /// is taken from its URI, so a request to `http://example.com/` is seen as a request for the
+    /// host `example.com`.
     pub fn client_with_address(&self, client_addr: net::SocketAddr) -> TestClient {
         self.try_client_with_address(client_addr)
             .expect("TestServer: unable to spawn client")
     }
 
-    fn try_client_with_address(&self, _client_addr: net::SocketAddr) -> Result<TestClient> {
-        // We're creating a private TCP-based pipe here. Bind to an ephemeral port, connect to
-        // it and then immediately discard the listener.
+    fn try_client_with_address(&self, client_addr: net::SocketAddr) -> Result<TestClient> {
+        // Each connection made by the client is a new loopback TCP connection to the listener of
+        // the `TestServer`, which is told the address to report for it by `TestConnect`.
 
         let client = Client::builder().build(TestConnect {
             addr: self.data.addr,
+            client_addr,
+            client_addrs: self.data.client_addrs.clone(),
         });
 
         Ok(TestClient {
             client,
             test_server: self.clone(),
+            cookies: Mutex::new(CookieJar::new()),
+            max_redirects: 0,
         })
     }
 
-    /// Runs the event loop until the response future is completed.
+    /// Runs the event loop until the response future is completed, or `timeout` has elapsed.
     ///
     /// If the future came from a different instance of `TestServer`, the event loop will run until
     /// the timeout is triggered.
-    fn run_request<F>(&self, f: F) -> Result<F::Item>
+    fn run_request<F>(&self, f: F, timeout: Duration) -> Result<F::Item>
     where
         F: Future + Send + 'static,
         F::Error: failure::Fail + Sized,
         F::Item: Send,
     {
-        let timeout = Delay::new(Instant::now() + Duration::from_secs(self.data.timeout));
+        let timeout = Delay::new(Instant::now() + timeout);
         let might_expire = self.run_future(f.select2(timeout).map_err(|either| {
             let e: failure::Error = match either {
                 future::Either::A((req_err, _)) => {
@@ -205,15 +362,147 @@ impl BodyReader for TestServer {
             .map(|chunk| chunk.into_iter().collect());
         self.run_future(f)
     }
+
+    fn read_chunks(&mut self, body: Body, count: usize) -> Result<(Vec<Vec<u8>>, Body)> {
+        if count == 0 {
+            return Ok((Vec::new(), body));
+        }
+
+        let f = future::loop_fn((body, Vec::new()), move |(body, mut chunks)| {
+            body.into_future()
+                .map_err(|(e, _)| e)
+                .map(move |(chunk, body)| match chunk {
+                    Some(chunk) => {
+                        chunks.push(chunk.to_vec());
+                        if chunks.len() < count {
+                            Loop::Continue((body, chunks))
+                        } else {
+                            Loop::Break((chunks, body))
+                        }
+                    }
+                    None => Loop::Break((chunks, Body::empty())),
+                })
+        });
+
+        let timeout = self.data.timeout;
+        self.run_request(f, timeout)
+    }
 }
 
 /// Client interface for issuing requests to a `TestServer`.
+///
+/// Like a browser, the `TestClient` keeps the cookies set by each response in a cookie jar, and
+/// sends them with each of its subsequent requests, so that flows relying on a session can be
+/// tested across several requests. Cookies are sent regardless of their `Domain`, `Path` and
+/// `Secure` attributes, since every request is made to the same `TestServer`.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// #
+/// # use hyper::header::COOKIE;
+/// # use hyper::{Body, HeaderMap, Response};
+/// # use gotham::cookies::{cookie_jar, Cookie};
+/// # use gotham::state::{FromState, State};
+/// # use gotham::test::TestServer;
+/// #
+/// fn handler(mut state: State) -> (State, Response<Body>) {
+///     let received = HeaderMap::borrow_from(&state)
+///         .get(COOKIE)
+///         .map(|value| value.to_str().unwrap().to_owned())
+///         .unwrap_or_default();
+///
+///     cookie_jar(&mut state).add(Cookie::new("visited", "true"));
+///     (state, Response::new(Body::from(received)))
+/// }
+///
+/// # fn main() {
+/// let test_server = TestServer::new(|| Ok(handler)).unwrap();
+/// let client = test_server.client();
+///
+/// let first = client.get("/").perform().unwrap();
+/// assert_eq!(first.read_utf8_body().unwrap(), "");
+/// assert_eq!(client.cookie("visited").unwrap().value(), "true");
+///
+/// let second = client.get("/").perform().unwrap();
+/// assert_eq!(second.read_utf8_body().unwrap(), "visited=true");
+///
+/// client.clear_cookies();
+/// assert!(client.cookies().is_empty());
+/// # }
+/// ```
 pub struct TestClient {
     client: Client<TestConnect, Body>,
     test_server: TestServer,
+    cookies: Mutex<CookieJar>,
+    max_redirects: usize,
 }
 
 impl TestClient {
+    /// Makes this `TestClient` follow up to `max` redirects from each request, so that the
+    /// response to a request is the response from the final location. The redirects which were
+    /// followed are returned by `TestResponse::redirects`, and when there are more than `max` the
+    /// last redirect response is returned.
+    ///
+    /// The cookies set by each response are sent with the next request. As browsers do, a `POST`
+    /// request which is redirected by a `301`, `302` or `303` response is followed with a `GET`
+    /// request, so that a post/redirect/get flow can be tested with a single request.
+    ///
+    /// ```rust
+    /// # extern crate gotham;
+    /// # extern crate hyper;
+    /// # extern crate mime;
+    /// #
+    /// # use hyper::header::LOCATION;
+    /// # use hyper::{Body, Response, StatusCode};
+    /// # use gotham::helpers::http::response::create_response;
+    /// # use gotham::router::Router;
+    /// # use gotham::router::builder::*;
+    /// # use gotham::state::State;
+    /// # use gotham::test::TestServer;
+    /// #
+    /// fn create(state: State) -> (State, Response<Body>) {
+    ///     let mut res = create_response(&state, StatusCode::SEE_OTHER, mime::TEXT_PLAIN, "");
+    ///     res.headers_mut().insert(LOCATION, "/items/1".parse().unwrap());
+    ///     (state, res)
+    /// }
+    ///
+    /// fn show(state: State) -> (State, Response<Body>) {
+    ///     let res = create_response(&state, StatusCode::OK, mime::TEXT_PLAIN, "item 1");
+    ///     (state, res)
+    /// }
+    ///
+    /// fn router() -> Router {
+    ///     build_simple_router(|route| {
+    ///         route.post("/items").to(create);
+    ///         route.get("/items/1").to(show);
+    ///     })
+    /// }
+    ///
+    /// # fn main() {
+    /// let test_server = TestServer::new(router()).unwrap();
+    /// let client = test_server.client().follow_redirects(5);
+    ///
+    /// let response = client
+    ///     .post("/items", "name=one", mime::APPLICATION_WWW_FORM_URLENCODED)
+    ///     .perform()
+    ///     .unwrap();
+    ///
+    /// assert_eq!(response.redirects().len(), 1);
+    /// assert_eq!(response.redirects()[0].status(), StatusCode::SEE_OTHER);
+    /// assert_eq!(response.redirects()[0].location(), "http://localhost/items/1");
+    /// assert_eq!(response.read_utf8_body().unwrap(), "item 1");
+    /// # }
+    /// ```
+    pub fn follow_redirects(self, max: usize) -> TestClient {
+        TestClient {
+            max_redirects: max,
+            ..self
+        }
+    }
+
     /// Begin constructing a HEAD request using this `TestClient`.
     pub fn head<U>(&self, uri: U) -> TestRequest
     where
@@ -307,29 +596,217 @@ impl TestClient {
 
     /// Send a constructed request using this `TestClient`, and await the response.
     pub fn perform(&self, req: TestRequest) -> Result<TestResponse> {
-        let req_future = self.client.request(req.request()).map_err(|e| {
-            warn!("Error from test client request {:?}", e);
-            failure::err_msg("request failed").compat()
-        });
+        let mut responses = self.perform_all(vec![req])?;
+        Ok(responses.remove(0))
+    }
 
-        self.test_server
-            .run_request(req_future)
-            .map(|response| TestResponse {
-                response,
-                reader: Box::new(self.test_server.clone()),
-            })
+    /// Sends a constructed request as the opening handshake of a WebSocket connection, and
+    /// returns the connection once the server has accepted the handshake. The `Upgrade`,
+    /// `Connection` and `Sec-WebSocket-*` headers of the handshake are added to the request.
+    ///
+    /// See `TestWebSocket` for example usage.
+    pub fn websocket(&self, req: TestRequest) -> Result<TestWebSocket> {
+        websocket::connect(self, req)
+    }
+
+    /// Sends each of the constructed requests using this `TestClient` at the same time, and awaits
+    /// all of the responses, which are returned in the same order as the requests. Each request
+    /// is made on its own connection, so the requests are in flight concurrently. The requests are
+    /// allowed the longest of their timeouts to complete.
+    ///
+    /// ```rust
+    /// # extern crate gotham;
+    /// # extern crate hyper;
+    /// #
+    /// # use hyper::{Body, Response, StatusCode};
+    /// # use gotham::state::State;
+    /// # use gotham::test::TestServer;
+    /// #
+    /// # fn my_handler(state: State) -> (State, Response<Body>) {
+    /// #   (state, Response::new(Body::empty()))
+    /// # }
+    /// #
+    /// # fn main() {
+    /// let test_server = TestServer::new(|| Ok(my_handler)).unwrap();
+    /// let client = test_server.client();
+    ///
+    /// let requests = (0..10).map(|i| client.get(format!("/items/{}", i).as_str()));
+    /// let responses = client.perform_all(requests).unwrap();
+    ///
+    /// assert_eq!(responses.len(), 10);
+    /// assert!(responses.iter().all(|r| r.status() == StatusCode::OK));
+    /// # }
+    /// ```
+    pub fn perform_all<'a, I>(&self, reqs: I) -> Result<Vec<TestResponse>>
+    where
+        I: IntoIterator<Item = TestRequest<'a>>,
+    {
+        let mut futures = Vec::new();
+        let mut sent = Vec::new();
+        let mut timeout = None;
+        for req in reqs {
+            timeout = timeout.max(Some(req.timeout().unwrap_or(self.test_server.data.timeout)));
+
+            let mut request = req.request();
+            if self.max_redirects > 0 {
+                let sent_request = self.buffer_request(request)?;
+                request = sent_request.to_request();
+                sent.push(Some(sent_request));
+            } else {
+                sent.push(None);
+            }
+
+            self.send_cookies(&mut request)?;
+
+            futures.push(self.client.request(request).map_err(|e| {
+                warn!("Error from test client request {:?}", e);
+                failure::err_msg("request failed").compat()
+            }));
+        }
+
+        let timeout = timeout.unwrap_or(self.test_server.data.timeout);
+        let responses = self
+            .test_server
+            .run_request(future::join_all(futures), timeout)?;
+
+        responses
+            .into_iter()
+            .zip(sent)
+            .map(|(response, sent)| self.follow(response, sent, timeout))
+            .collect()
+    }
+
+    /// Returns the cookies which this `TestClient` sends with its requests.
+    pub fn cookies(&self) -> Vec<Cookie<'static>> {
+        self.cookies.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Returns the cookie named `name` which this `TestClient` sends with its requests, if any.
+    pub fn cookie(&self, name: &str) -> Option<Cookie<'static>> {
+        self.cookies.lock().unwrap().get(name).cloned()
+    }
+
+    /// Removes all cookies from the cookie jar of this `TestClient`, as if a new browser session
+    /// had been started.
+    pub fn clear_cookies(&self) {
+        *self.cookies.lock().unwrap() = CookieJar::new();
+    }
+
+    // Reads the body of `request`, so that it can be sent again if the request is redirected.
+    fn buffer_request(&self, request: Request<Body>) -> Result<SentRequest> {
+        let (parts, body) = request.into_parts();
+        let body = self.test_server.run_future(body.concat2())?;
+        Ok(SentRequest::new(
+            parts.method,
+            parts.uri,
+            parts.headers,
+            Bytes::from(body),
+        ))
+    }
+
+    // Stores the cookies set by `response`, and follows the redirects from it when `sent` is the
+    // request which it responds to.
+    fn follow(
+        &self,
+        mut response: Response<Body>,
+        mut sent: Option<SentRequest>,
+        timeout: Duration,
+    ) -> Result<TestResponse> {
+        self.store_cookies(response.headers());
+
+        let mut redirects = Vec::new();
+        while redirects.len() < self.max_redirects {
+            let (next, redirect) = match sent {
+                Some(ref sent) => match sent.redirect(&response)? {
+                    Some(next) => next,
+                    None => break,
+                },
+                None => break,
+            };
+
+            let mut request = next.to_request();
+            self.send_cookies(&mut request)?;
+
+            let f = self.client.request(request).map_err(|e| {
+                warn!("Error from test client request {:?}", e);
+                failure::err_msg("request failed").compat()
+            });
+            response = self.test_server.run_request(f, timeout)?;
+            self.store_cookies(response.headers());
+
+            redirects.push(redirect);
+            sent = Some(next);
+        }
+
+        Ok(TestResponse {
+            response,
+            reader: Box::new(self.test_server.clone()),
+            redirects,
+        })
+    }
+
+    // Adds the cookies in the jar to the `Cookie` header of `request`, after any cookies which
+    // were added to the request directly.
+    fn send_cookies(&self, request: &mut Request<Body>) -> Result<()> {
+        let jar = self.cookies.lock().unwrap();
+        let pairs: Vec<String> = jar
+            .iter()
+            .map(|cookie| format!("{}={}", cookie.name(), cookie.value()))
+            .collect();
+
+        if pairs.is_empty() {
+            return Ok(());
+        }
+
+        let value = match request.headers().get(COOKIE).and_then(|v| v.to_str().ok()) {
+            Some(existing) => format!("{}; {}", existing, pairs.join("; ")),
+            None => pairs.join("; "),
+        };
+        request
+            .headers_mut()
+            .insert(COOKIE, HeaderValue::from_str(&value)?);
+        Ok(())
+    }
+
+    // Updates the jar with the cookies set by a response. A cookie which has already expired is
+    // removed from the jar, which is how servers delete cookies.
+    fn store_cookies(&self, headers: &HeaderMap) {
+        let mut jar = self.cookies.lock().unwrap();
+
+        for cookie in set_cookies(headers) {
+            let expired = cookie.max_age().map_or(false, |age| age.num_seconds() <= 0);
+            if expired {
+                jar.remove(Cookie::named(cookie.name().to_owned()));
+            } else {
+                jar.add(cookie);
+            }
+        }
     }
 }
 
+// Parses the cookies in the `Set-Cookie` headers, ignoring any which cannot be parsed.
+fn set_cookies(headers: &HeaderMap) -> Vec<Cookie<'static>> {
+    headers
+        .get_all(SET_COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .filter_map(|value| Cookie::parse(value.to_owned()).ok())
+        .collect()
+}
+
 trait BodyReader {
     /// Runs the underlying event loop until the response body has been fully read. An `Ok(_)`
     /// response holds a buffer containing all bytes of the response body.
     fn read_body(&mut self, response: Response<Body>) -> Result<Vec<u8>>;
+
+    /// Runs the underlying event loop until `count` chunks of `body` have arrived, or the body
+    /// has ended, and returns the chunks along with the rest of the body.
+    fn read_chunks(&mut self, body: Body, count: usize) -> Result<(Vec<Vec<u8>>, Body)>;
 }
 
 /// Wrapping struct for the `Response` returned by a `TestClient`. Provides access to the
-/// `Response` value via the `Deref` and `DerefMut` traits, and also provides a function for
-/// awaiting a completed response body.
+/// `Response` value via the `Deref` and `DerefMut` traits, helpers for checking the status, headers
+/// and cookies of the `Response`, and functions for awaiting a completed response body.
 ///
 /// # Examples
 ///
@@ -367,6 +844,7 @@ trait BodyReader {
 pub struct TestResponse {
     response: Response<Body>,
     reader: Box<BodyReader>,
+    redirects: Vec<Redirect>,
 }
 
 impl Deref for TestResponse {
@@ -392,39 +870,138 @@ impl fmt::Debug for TestResponse {
 impl TestResponse {
     /// Awaits the body of the underlying `Response`, and returns it. This will cause the event
     /// loop to execute until the `Response` body has been fully read into the `Vec<u8>`.
+    ///
+    /// The body is returned as it was sent, even when it has a `Content-Encoding`, so that the
+    /// encoding itself can be tested. `read_decoded_body` returns the decoded body.
     pub fn read_body(mut self) -> Result<Vec<u8>> {
         self.reader.read_body(self.response)
     }
 
+    /// Awaits the body of the underlying `Response`, and returns it after reversing the `gzip`,
+    /// `deflate` and `br` encodings listed in the `Content-Encoding` header, so that assertions
+    /// can be made about the content of responses compressed by `CompressionMiddleware`. An error
+    /// is returned for any other encoding.
+    ///
+    /// `read_utf8_body` and `read_json` decode the body in the same way.
+    pub fn read_decoded_body(self) -> Result<Vec<u8>> {
+        let headers = self.headers().clone();
+        let body = self.read_body()?;
+        decode::decode_body(&headers, body)
+    }
+
+    /// Awaits the next chunk of the body of the underlying `Response`, and returns it, or `None`
+    /// when the body has ended. This allows a streaming body, such as a feed of server-sent
+    /// events, to be checked as it arrives, without waiting for it to end. The rest of the body
+    /// can be read by later calls, including to `read_body`.
+    ///
+    /// Chunks are returned as they are received by the client, so a chunk written by the handler
+    /// may be received in several parts when it is large. If no chunk arrives within the timeout
+    /// of the `TestServer`, an error is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # extern crate futures;
+    /// # extern crate gotham;
+    /// # extern crate hyper;
+    /// #
+    /// # use futures::{stream, Async, Stream};
+    /// # use hyper::{Body, Response};
+    /// # use gotham::state::State;
+    /// # use gotham::test::TestServer;
+    /// #
+    /// // Sends two events, and then keeps the stream open.
+    /// fn events(state: State) -> (State, Response<Body>) {
+    ///     let events = vec!["data: one\n\n", "data: two\n\n"];
+    ///     let events = stream::iter_ok::<_, hyper::Error>(events)
+    ///         .chain(stream::poll_fn(|| Ok(Async::NotReady)));
+    ///
+    ///     (state, Response::new(Body::wrap_stream(events)))
+    /// }
+    ///
+    /// # fn main() {
+    /// let test_server = TestServer::new(|| Ok(events)).unwrap();
+    /// let mut response = test_server.client().get("/events").perform().unwrap();
+    ///
+    /// assert_eq!(response.read_chunk().unwrap().unwrap(), b"data: one\n\n");
+    /// assert_eq!(response.read_chunk().unwrap().unwrap(), b"data: two\n\n");
+    /// # }
+    /// ```
+    pub fn read_chunk(&mut self) -> Result<Option<Vec<u8>>> {
+        Ok(self.read_chunks(1)?.pop())
+    }
+
+    /// Awaits the next `count` chunks of the body of the underlying `Response`, and returns them.
+    /// Fewer chunks are returned if the body ends first. The event loop is run only until the
+    /// chunks have arrived, as described for `read_chunk`.
+    pub fn read_chunks(&mut self, count: usize) -> Result<Vec<Vec<u8>>> {
+        let body = mem::replace(self.response.body_mut(), Body::empty());
+        let (chunks, rest) = self.reader.read_chunks(body, count)?;
+        *self.response.body_mut() = rest;
+        Ok(chunks)
+    }
+
     /// Awaits the UTF-8 encoded body of the underlying `Response`, and returns the `String`. This
     /// will cause the event loop to execute until the `Response` body has been fully read and the
     /// `String` created.
     pub fn read_utf8_body(self) -> Result<String> {
-        let buf = self.read_body()?;
+        let buf = self.read_decoded_body()?;
         let s = String::from_utf8(buf)?;
         Ok(s)
     }
-}
 
-/// `TestConnect` represents the connection between a test client and the `TestServer` instance
-/// that created it. This type should never be used directly.
-struct TestConnect {
-    addr: SocketAddr,
-}
+    /// Awaits the body of the underlying `Response`, and deserializes it from JSON.
+    pub fn read_json<T>(self) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        let buf = self.read_decoded_body()?;
+        Ok(serde_json::from_slice(&buf)?)
+    }
 
-impl Connect for TestConnect {
-    type Transport = TcpStream;
-    type Error = CompatError;
-    type Future =
-        Box<Future<Item = (Self::Transport, Connected), Error = Self::Error> + Send + Sync>;
-
-    fn connect(&self, _dst: Destination) -> Self::Future {
-        Box::new(
-            TcpStream::connect(&self.addr)
-                .inspect(|s| info!("Client TcpStream connected: {:?}", s))
-                .map(|s| (s, Connected::new()))
-                .map_err(|e| Error::from(e).compat()),
-        )
+    /// Asserts that the `Response` has the given status.
+    ///
+    /// # Panics
+    ///
+    /// If the status of the `Response` is not `status`.
+    pub fn assert_status(&self, status: StatusCode) -> &TestResponse {
+        assert_eq!(
+            self.status(),
+            status,
+            "unexpected response status, with headers {:?}",
+            self.headers()
+        );
+        self
+    }
+
+    /// Returns the value of the header `name`, if it is present and contains only visible ASCII
+    /// characters.
+    pub fn header<N>(&self, name: N) -> Option<&str>
+    where
+        N: AsHeaderName,
+    {
+        self.headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+    }
+
+    /// Returns the cookies set by the `Set-Cookie` headers of the `Response`, ignoring any which
+    /// cannot be parsed.
+    pub fn cookies(&self) -> Vec<Cookie<'static>> {
+        set_cookies(self.headers())
+    }
+
+    /// Returns the cookie named `name` set by the `Response`, if any.
+    pub fn cookie(&self, name: &str) -> Option<Cookie<'static>> {
+        self.cookies()
+            .into_iter()
+            .find(|cookie| cookie.name() == name)
+    }
+
+    /// Returns the redirects which were followed to get the `Response`, in the order they were
+    /// followed, when the `TestClient` follows redirects.
+    pub fn redirects(&self) -> &[Redirect] {
+        &self.redirects
     }
 }
 
@@ -438,7 +1015,7 @@ mod tests {
     use hyper::{Body, Response, StatusCode, Uri};
     use mime;
 
-    use handler::{Handler, HandlerFuture, IntoHandlerError, NewHandler};
+    use handler::{Handler, HandlerError, HandlerFuture, IntoHandlerError, NewHandler};
     use helpers::http::response::create_response;
     use state::{client_addr, FromState, State};
 
@@ -536,7 +1113,6 @@ mod tests {
     }
 
     #[test]
-    #[ignore] // We trade using the mainline server setup code for this behavior.
     fn sets_client_addr() {
         let ticks = SystemTime::now()
             .duration_since(UNIX_EPOCH)
@@ -565,6 +1141,253 @@ mod tests {
         assert_eq!(received_addr, client_addr);
     }
 
+    #[test]
+    fn sends_raw_requests() {
+        let test_server = TestServer::new(|| {
+            Ok(TestHandler {
+                response: "raw".to_owned(),
+            })
+        })
+        .unwrap();
+
+        let response = test_server
+            .send_raw(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+        assert_eq!(response.status(), Some(StatusCode::OK));
+        assert_eq!(response.outcome(), ConnectionOutcome::Closed);
+        assert!(response.bytes().ends_with(b"\r\n\r\nraw"));
+
+        let response = test_server.send_raw(b"NOT HTTP AT ALL\r\n\r\n").unwrap();
+        assert_eq!(response.status(), Some(StatusCode::BAD_REQUEST));
+        assert_eq!(response.outcome(), ConnectionOutcome::Closed);
+
+        let response = test_server.send_raw(b"GET / HTTP/1.1\r\nHo").unwrap();
+        assert_ne!(response.outcome(), ConnectionOutcome::TimedOut);
+        assert_eq!(response.status(), None);
+    }
+
+    #[test]
+    fn response_helpers() {
+        use cookies::cookie_jar;
+        use hyper::header::LOCATION;
+
+        #[derive(Deserialize)]
+        struct Greeting {
+            message: String,
+        }
+
+        fn handler(mut state: State) -> (State, Response<Body>) {
+            cookie_jar(&mut state).add(Cookie::build("session", "abc123").path("/").finish());
+            cookie_jar(&mut state).add(Cookie::new("theme", "dark"));
+            let mut res = create_response(
+                &state,
+                StatusCode::CREATED,
+                mime::APPLICATION_JSON,
+                r#"{"message":"hello"}"#,
+            );
+            res.headers_mut()
+                .insert(LOCATION, "/greetings/1".parse().unwrap());
+            (state, res)
+        }
+
+        let test_server = TestServer::new(|| Ok(handler)).unwrap();
+        let response = test_server.client().get("/").perform().unwrap();
+
+        response.assert_status(StatusCode::CREATED);
+        assert_eq!(response.header(LOCATION), Some("/greetings/1"));
+        assert_eq!(response.header("x-missing"), None);
+        assert_eq!(response.cookies().len(), 2);
+
+        let session = response.cookie("session").unwrap();
+        assert_eq!(session.value(), "abc123");
+        assert_eq!(session.path(), Some("/"));
+        assert!(response.cookie("missing").is_none());
+
+        let greeting: Greeting = response.read_json().unwrap();
+        assert_eq!(greeting.message, "hello");
+    }
+
+    #[test]
+    #[should_panic(expected = "unexpected response status")]
+    fn assert_status_panics() {
+        let test_server = TestServer::new(|| {
+            Ok(|state: State| {
+                let res = create_response(&state, StatusCode::NOT_FOUND, mime::TEXT_PLAIN, "");
+                (state, res)
+            })
+        })
+        .unwrap();
+
+        test_server
+            .client()
+            .get("/")
+            .perform()
+            .unwrap()
+            .assert_status(StatusCode::OK);
+    }
+
+    #[test]
+    fn keeps_session_across_requests() {
+        use middleware::session::{NewSessionMiddleware, SessionData};
+        use pipeline::new_pipeline;
+        use pipeline::single::single_pipeline;
+        use router::builder::*;
+
+        #[derive(Default, Serialize, Deserialize)]
+        struct Visits(u32);
+
+        fn visit(mut state: State) -> (State, Response<Body>) {
+            let visits = {
+                let session = SessionData::<Visits>::borrow_mut_from(&mut state);
+                session.0 += 1;
+                session.0
+            };
+            let res = create_response(&state, StatusCode::OK, mime::TEXT_PLAIN, visits.to_string());
+            (state, res)
+        }
+
+        fn logout(mut state: State) -> (State, Response<Body>) {
+            SessionData::<Visits>::take_from(&mut state)
+                .discard(&mut state)
+                .unwrap();
+            let res = create_response(&state, StatusCode::OK, mime::TEXT_PLAIN, "");
+            (state, res)
+        }
+
+        let (chain, pipelines) = single_pipeline(
+            new_pipeline()
+                .add(NewSessionMiddleware::default().with_session_type::<Visits>())
+                .build(),
+        );
+        let test_server = TestServer::new(build_router(chain, pipelines, |route| {
+            route.get("/").to(visit);
+            route.get("/logout").to(logout);
+        }))
+        .unwrap();
+
+        let client = test_server.client();
+        let visit = |expected: &str| {
+            let response = client.get("/").perform().unwrap();
+            assert_eq!(response.read_utf8_body().unwrap(), expected);
+        };
+
+        visit("1");
+        visit("2");
+        assert!(client.cookie("_gotham_session").is_some());
+
+        client.get("/logout").perform().unwrap();
+        assert!(client.cookie("_gotham_session").is_none());
+        visit("1");
+
+        client.clear_cookies();
+        assert!(client.cookies().is_empty());
+        visit("1");
+    }
+
+    #[test]
+    fn performs_requests_concurrently() {
+        use futures::sync::oneshot;
+        use std::sync::Mutex;
+
+        // A request to `/wait` only completes once a request to `/release` has been received, so
+        // the requests must be in flight at the same time.
+        let (tx, rx) = oneshot::channel::<()>();
+        let channel = Arc::new(Mutex::new((Some(tx), Some(rx))));
+
+        let test_server = TestServer::with_timeout(
+            move || {
+                let channel = channel.clone();
+                Ok(move |state: State| -> Box<HandlerFuture> {
+                    let path = Uri::borrow_from(&state).path().to_owned();
+                    let mut channel = channel.lock().unwrap();
+                    let done: Box<Future<Item = (), Error = ()> + Send> = match path.as_str() {
+                        "/wait" => Box::new(channel.1.take().unwrap().map_err(|_| ())),
+                        "/release" => {
+                            channel.0.take().unwrap().send(()).unwrap();
+                            Box::new(future::ok(()))
+                        }
+                        _ => Box::new(future::ok(())),
+                    };
+
+                    Box::new(done.then(move |_| {
+                        let res = create_response(&state, StatusCode::OK, mime::TEXT_PLAIN, path);
+                        Ok::<_, (State, HandlerError)>((state, res))
+                    }))
+                })
+            },
+            5,
+        )
+        .unwrap();
+
+        let client = test_server.client();
+        let responses = client
+            .perform_all(vec![client.get("/wait"), client.get("/release")])
+            .unwrap();
+
+        let bodies: Vec<String> = responses
+            .into_iter()
+            .map(|response| response.read_utf8_body().unwrap())
+            .collect();
+        assert_eq!(bodies, vec!["/wait", "/release"]);
+
+        // The client can be used again after concurrent requests.
+        let response = client.get("/").perform().unwrap();
+        assert_eq!(response.read_utf8_body().unwrap(), "/");
+    }
+
+    #[test]
+    fn per_request_timeout() {
+        fn handler(state: State) -> Box<HandlerFuture> {
+            let f = Delay::new(Instant::now() + Duration::from_millis(500)).then(move |_| {
+                let res = create_response(&state, StatusCode::OK, mime::TEXT_PLAIN, "done");
+                Ok::<_, (State, HandlerError)>((state, res))
+            });
+            Box::new(f)
+        }
+
+        let test_server = TestServer::new(|| Ok(handler)).unwrap();
+        let client = test_server.client();
+
+        let started = Instant::now();
+        let result = client
+            .get("/")
+            .with_timeout(Duration::from_millis(50))
+            .perform();
+        assert!(result.is_err());
+        assert!(started.elapsed() < Duration::from_millis(500));
+
+        let response = client.get("/").perform().unwrap();
+        assert_eq!(response.read_utf8_body().unwrap(), "done");
+    }
+
+    #[test]
+    fn accepts_external_connections() {
+        use std::io::{Read, Write};
+
+        let new_service = || {
+            Ok(TestHandler {
+                response: "".to_owned(),
+            })
+        };
+
+        let test_server = TestServer::new(new_service).unwrap();
+        let addr = test_server.addr();
+        assert!(addr.ip().is_loopback());
+        assert_ne!(addr.port(), 0);
+
+        let mut stream = net::TcpStream::connect(addr).unwrap();
+        let local_addr = stream.local_addr().unwrap();
+        stream
+            .write_all(b"GET /myaddr HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with(&format!("\r\n\r\n{}", local_addr)));
+    }
+
     #[test]
     fn async_echo() {
         fn handler(mut state: State) -> Box<HandlerFuture> {
@@ -614,4 +1437,173 @@ mod tests {
         assert_eq!(content_length, &format!("{}", buf.len()));
         assert_eq!(data, &buf);
     }
+
+    #[test]
+    fn reads_chunks_incrementally() {
+        use futures::stream;
+
+        fn handler(state: State) -> (State, Response<Body>) {
+            let chunks = stream::iter_ok(vec!["one", "two", "three"]).and_then(|chunk| {
+                Delay::new(Instant::now() + Duration::from_millis(10)).map(move |_| chunk)
+            });
+
+            (state, Response::new(Body::wrap_stream(chunks)))
+        }
+
+        let test_server = TestServer::new(|| Ok(handler)).unwrap();
+        let mut response = test_server.client().get("/").perform().unwrap();
+
+        assert_eq!(response.read_chunk().unwrap().unwrap(), b"one");
+        assert_eq!(
+            response.read_chunks(5).unwrap(),
+            vec![b"two".to_vec(), b"three".to_vec()]
+        );
+        assert!(response.read_chunk().unwrap().is_none());
+        assert!(response.read_chunks(0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn reads_chunks_then_body() {
+        use std::io;
+
+        fn handler(state: State) -> (State, Response<Body>) {
+            let chunks = futures::stream::iter_ok::<_, io::Error>(vec!["a", "b", "c"]);
+            (state, Response::new(Body::wrap_stream(chunks)))
+        }
+
+        let test_server = TestServer::new(|| Ok(handler)).unwrap();
+        let mut response = test_server.client().get("/").perform().unwrap();
+
+        let mut body = response.read_chunk().unwrap().unwrap();
+        body.extend(response.read_body().unwrap());
+        assert_eq!(body, b"abc");
+    }
+
+    #[test]
+    fn rejects_https_requests() {
+        use state::scheme;
+
+        fn handler(state: State) -> (State, Response<Body>) {
+            let body = format!(
+                "{} {}",
+                scheme(&state).as_str(),
+                Uri::borrow_from(&state).path()
+            );
+            (state, Response::new(Body::from(body)))
+        }
+
+        let test_server = TestServer::new(|| Ok(handler)).unwrap();
+        let client = test_server.client();
+
+        assert!(client.get("https://localhost/secure").perform().is_err());
+
+        let response = client.get("http://localhost/plain").perform().unwrap();
+        assert_eq!(response.read_utf8_body().unwrap(), "http /plain");
+
+        // the scheme of an absolute-form request line is chosen by the client, and not trusted
+        let request = "GET https://localhost/spoofed HTTP/1.1\r\n\
+                       Host: localhost\r\nConnection: close\r\n\r\n";
+        let response = test_server.send_raw(request.as_bytes()).unwrap();
+        assert!(response.bytes().ends_with(b"http /spoofed"));
+    }
+
+    #[test]
+    fn follows_redirects() {
+        use hyper::header::LOCATION;
+
+        fn handler(state: State) -> (State, Response<Body>) {
+            let (status, location, body) = match Uri::borrow_from(&state).path() {
+                "/login" => (StatusCode::FOUND, "/home", String::new()),
+                "/loop" => (StatusCode::TEMPORARY_REDIRECT, "/loop", String::new()),
+                _ => {
+                    let headers = HeaderMap::borrow_from(&state);
+                    let body = format!(
+                        "{} {}",
+                        Method::borrow_from(&state),
+                        headers.get(COOKIE).unwrap().to_str().unwrap()
+                    );
+                    (StatusCode::OK, "", body)
+                }
+            };
+
+            let mut res = create_response(&state, status, mime::TEXT_PLAIN, body);
+            if status == StatusCode::FOUND {
+                res.headers_mut()
+                    .insert(SET_COOKIE, "user=ada".parse().unwrap());
+            }
+            if !location.is_empty() {
+                res.headers_mut()
+                    .insert(LOCATION, location.parse().unwrap());
+            }
+            (state, res)
+        }
+
+        let test_server = TestServer::new(|| Ok(handler)).unwrap();
+
+        let client = test_server.client();
+        let response = client
+            .post("/login", "", mime::TEXT_PLAIN)
+            .perform()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FOUND);
+        assert!(response.redirects().is_empty());
+
+        let client = test_server.client().follow_redirects(3);
+        let response = client
+            .post("/login", "", mime::TEXT_PLAIN)
+            .perform()
+            .unwrap();
+        assert_eq!(response.redirects().len(), 1);
+        assert_eq!(response.redirects()[0].uri(), "http://localhost/login");
+        assert_eq!(response.read_utf8_body().unwrap(), "GET user=ada");
+
+        let response = client.put("/loop", "", mime::TEXT_PLAIN).perform().unwrap();
+        assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+        assert_eq!(response.redirects().len(), 3);
+    }
+
+    #[test]
+    fn decodes_compressed_bodies() {
+        use hyper::header::{ACCEPT_ENCODING, CONTENT_ENCODING};
+
+        use middleware::compression::CompressionMiddleware;
+        use pipeline::new_pipeline;
+        use pipeline::single::single_pipeline;
+        use router::builder::*;
+
+        fn handler(state: State) -> (State, Response<Body>) {
+            let body = json!({ "message": "Hello, world! ".repeat(100) }).to_string();
+            let res = create_response(&state, StatusCode::OK, mime::APPLICATION_JSON, body);
+            (state, res)
+        }
+
+        let (chain, pipelines) =
+            single_pipeline(new_pipeline().add(CompressionMiddleware::default()).build());
+        let router = build_router(chain, pipelines, |route| {
+            route.get("/").to(handler);
+        });
+
+        let test_server = TestServer::new(router).unwrap();
+        let expected = json!({ "message": "Hello, world! ".repeat(100) });
+
+        for encoding in &["gzip", "deflate", "br"] {
+            let request = || {
+                test_server
+                    .client()
+                    .get("/")
+                    .with_header(ACCEPT_ENCODING, encoding.parse().unwrap())
+                    .perform()
+                    .unwrap()
+            };
+
+            let response = request();
+            assert_eq!(response.headers()[CONTENT_ENCODING], *encoding);
+            let raw = response.read_body().unwrap();
+            assert_ne!(raw, expected.to_string().into_bytes());
+
+            let value: serde_json::Value = request().read_json().unwrap();
+            assert_eq!(value, expected);
+            assert_eq!(request().read_utf8_body().unwrap(), expected.to_string());
+        }
+    }
 }
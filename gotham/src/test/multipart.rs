@@ -0,0 +1,187 @@
+//! Defines `MultipartForm`, which builds `multipart/form-data` bodies for test requests.
+
+use mime::{self, Mime};
+use rand::distributions::Alphanumeric;
+use rand::{self, Rng};
+
+/// Builds a `multipart/form-data` body, as sent by a browser when submitting a form which uploads
+/// files, to be sent with `TestRequest::with_multipart_body`.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// # extern crate mime;
+/// #
+/// # use hyper::{Body, Method, Response, StatusCode};
+/// # use gotham::state::State;
+/// # use gotham::test::{MultipartForm, TestServer};
+/// #
+/// # fn upload(state: State) -> (State, Response<Body>) {
+/// #   (state, Response::new(Body::empty()))
+/// # }
+/// #
+/// # fn main() {
+/// let test_server = TestServer::new(|| Ok(upload)).unwrap();
+///
+/// let form = MultipartForm::new()
+///     .with_field("title", "Holiday")
+///     .with_file("photo", "beach.png", mime::IMAGE_PNG, &b"\x89PNG"[..]);
+///
+/// let response = test_server
+///     .client()
+///     .build_request(Method::POST, "/photos")
+///     .with_multipart_body(form)
+///     .perform()
+///     .unwrap();
+///
+/// assert_eq!(response.status(), StatusCode::OK);
+/// # }
+/// ```
+pub struct MultipartForm {
+    boundary: String,
+    parts: Vec<Part>,
+}
+
+struct Part {
+    name: String,
+    file: Option<(String, Mime)>,
+    content: Vec<u8>,
+}
+
+impl MultipartForm {
+    /// Creates an empty `MultipartForm`, with a random boundary.
+    pub fn new() -> MultipartForm {
+        let boundary: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(24)
+            .collect();
+
+        MultipartForm {
+            boundary: format!("gotham-boundary-{}", boundary),
+            parts: Vec::new(),
+        }
+    }
+
+    /// Adds a text field named `name` with `value`.
+    pub fn with_field(mut self, name: &str, value: &str) -> MultipartForm {
+        self.parts.push(Part {
+            name: name.to_owned(),
+            file: None,
+            content: value.as_bytes().to_vec(),
+        });
+        self
+    }
+
+    /// Adds a file field named `name`, holding a file called `filename` with the type `mime` and
+    /// the given `content`.
+    pub fn with_file<C>(
+        mut self,
+        name: &str,
+        filename: &str,
+        mime: Mime,
+        content: C,
+    ) -> MultipartForm
+    where
+        C: Into<Vec<u8>>,
+    {
+        self.parts.push(Part {
+            name: name.to_owned(),
+            file: Some((filename.to_owned(), mime)),
+            content: content.into(),
+        });
+        self
+    }
+
+    /// Returns the `Content-Type` of the body, which includes the boundary.
+    pub(super) fn content_type(&self) -> Mime {
+        format!("{}; boundary={}", mime::MULTIPART_FORM_DATA, self.boundary)
+            .parse()
+            .expect("multipart content type is valid")
+    }
+
+    /// Encodes the fields of the form as the body.
+    pub(super) fn into_body(self) -> Vec<u8> {
+        let mut body = Vec::new();
+
+        for part in self.parts {
+            body.extend_from_slice(format!("--{}\r\n", self.boundary).as_bytes());
+            body.extend_from_slice(
+                format!(
+                    "Content-Disposition: form-data; name=\"{}\"",
+                    escape(&part.name)
+                )
+                .as_bytes(),
+            );
+
+            if let Some((filename, mime)) = part.file {
+                body.extend_from_slice(
+                    format!(
+                        "; filename=\"{}\"\r\nContent-Type: {}",
+                        escape(&filename),
+                        mime
+                    )
+                    .as_bytes(),
+                );
+            }
+
+            body.extend_from_slice(b"\r\n\r\n");
+            body.extend_from_slice(&part.content);
+            body.extend_from_slice(b"\r\n");
+        }
+
+        body.extend_from_slice(format!("--{}--\r\n", self.boundary).as_bytes());
+        body
+    }
+}
+
+impl Default for MultipartForm {
+    fn default() -> MultipartForm {
+        MultipartForm::new()
+    }
+}
+
+// Escapes a field name or filename for a quoted parameter, as browsers do.
+fn escape(value: &str) -> String {
+    value
+        .replace('"', "%22")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_fields_and_files() {
+        let mut form = MultipartForm::new()
+            .with_field("title", "Holiday")
+            .with_file("photo", "a \"b\".txt", mime::TEXT_PLAIN, "hello");
+        form.boundary = "XyZ".to_owned();
+
+        assert_eq!(
+            form.content_type().as_ref(),
+            "multipart/form-data; boundary=XyZ"
+        );
+        assert_eq!(
+            String::from_utf8(form.into_body()).unwrap(),
+            "--XyZ\r\n\
+             Content-Disposition: form-data; name=\"title\"\r\n\
+             \r\n\
+             Holiday\r\n\
+             --XyZ\r\n\
+             Content-Disposition: form-data; name=\"photo\"; filename=\"a %22b%22.txt\"\r\n\
+             Content-Type: text/plain\r\n\
+             \r\n\
+             hello\r\n\
+             --XyZ--\r\n"
+        );
+    }
+
+    #[test]
+    fn boundaries_are_random() {
+        assert_ne!(MultipartForm::new().boundary, MultipartForm::new().boundary);
+    }
+}
@@ -0,0 +1,135 @@
+//! Defines `RawResponse`, which records what a `TestServer` sent back for raw bytes written to a
+//! connection with `TestServer::send_raw`.
+
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpStream};
+use std::str;
+use std::time::{Duration, Instant};
+
+use hyper::StatusCode;
+
+/// How a connection used by `TestServer::send_raw` ended.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConnectionOutcome {
+    /// The server closed the connection.
+    Closed,
+
+    /// The server reset the connection.
+    Reset,
+
+    /// The connection was still open when the timeout elapsed.
+    TimedOut,
+}
+
+/// The bytes sent by a `TestServer` in response to the raw bytes written by
+/// `TestServer::send_raw`, and how the connection ended.
+#[derive(Clone, Debug)]
+pub struct RawResponse {
+    bytes: Vec<u8>,
+    outcome: ConnectionOutcome,
+}
+
+impl RawResponse {
+    /// All bytes received from the server, which may hold several responses, or none.
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// How the connection ended.
+    pub fn outcome(&self) -> ConnectionOutcome {
+        self.outcome
+    }
+
+    /// The status of the first response received, if the bytes begin with a valid status line.
+    pub fn status(&self) -> Option<StatusCode> {
+        let line = self.bytes.split(|b| *b == b'\n').next()?;
+        let line = str::from_utf8(line).ok()?;
+
+        let mut parts = line.trim_right().splitn(3, ' ');
+        if !parts.next()?.starts_with("HTTP/") {
+            return None;
+        }
+
+        StatusCode::from_bytes(parts.next()?.as_bytes()).ok()
+    }
+}
+
+/// Writes `request` to a new connection to `addr`, closes the connection for writing, and then
+/// reads until the server closes the connection or `timeout` has elapsed.
+pub(super) fn send_raw(
+    addr: SocketAddr,
+    request: &[u8],
+    timeout: Duration,
+) -> io::Result<RawResponse> {
+    let deadline = Instant::now() + timeout;
+    let mut stream = TcpStream::connect(addr)?;
+
+    // the server may close the connection before reading all of a malformed request, which is an
+    // outcome to report rather than an error
+    match stream.write_all(request) {
+        Ok(()) => stream.shutdown(Shutdown::Write)?,
+        Err(ref e) if is_reset(e) => (),
+        Err(e) => return Err(e),
+    }
+
+    let mut bytes = Vec::new();
+    let mut buf = [0u8; 4096];
+    let outcome = loop {
+        let now = Instant::now();
+        if now >= deadline {
+            break ConnectionOutcome::TimedOut;
+        }
+
+        stream.set_read_timeout(Some(deadline - now))?;
+        match stream.read(&mut buf) {
+            Ok(0) => break ConnectionOutcome::Closed,
+            Ok(n) => bytes.extend_from_slice(&buf[..n]),
+            Err(ref e) if is_reset(e) => break ConnectionOutcome::Reset,
+            Err(ref e)
+                if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut =>
+            {
+                break ConnectionOutcome::TimedOut
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+            Err(e) => return Err(e),
+        }
+    };
+
+    Ok(RawResponse { bytes, outcome })
+}
+
+fn is_reset(e: &io::Error) -> bool {
+    match e.kind() {
+        io::ErrorKind::ConnectionReset
+        | io::ErrorKind::ConnectionAborted
+        | io::ErrorKind::BrokenPipe => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(bytes: &[u8]) -> RawResponse {
+        RawResponse {
+            bytes: bytes.to_vec(),
+            outcome: ConnectionOutcome::Closed,
+        }
+    }
+
+    #[test]
+    fn parses_status() {
+        assert_eq!(
+            response(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n").status(),
+            Some(StatusCode::NOT_FOUND)
+        );
+        assert_eq!(
+            response(b"HTTP/1.1 200\r\n\r\n").status(),
+            Some(StatusCode::OK)
+        );
+        assert_eq!(response(b"").status(), None);
+        assert_eq!(response(b"garbage").status(), None);
+        assert_eq!(response(b"HTTP/1.1 abc\r\n").status(), None);
+    }
+}
@@ -0,0 +1,196 @@
+//! Defines the types used by `TestClient` to follow redirects.
+
+use bytes::Bytes;
+use hyper::header::{HeaderMap, CONTENT_LENGTH, CONTENT_TYPE, LOCATION};
+use hyper::{Body, Method, Request, Response, StatusCode, Uri};
+use url::Url;
+
+use error::*;
+
+/// A redirect which was followed by a `TestClient`, as returned by `TestResponse::redirects`.
+#[derive(Clone, Debug)]
+pub struct Redirect {
+    uri: Uri,
+    status: StatusCode,
+    location: Uri,
+}
+
+impl Redirect {
+    /// The URI of the request which was redirected.
+    pub fn uri(&self) -> &Uri {
+        &self.uri
+    }
+
+    /// The status of the redirect response.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// The URI which the request was redirected to, resolved against the URI of the request.
+    pub fn location(&self) -> &Uri {
+        &self.location
+    }
+}
+
+/// A request which has been sent by a `TestClient`, kept so that it can be sent again to the
+/// location of a redirect.
+pub(super) struct SentRequest {
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+impl SentRequest {
+    pub(super) fn new(method: Method, uri: Uri, headers: HeaderMap, body: Bytes) -> SentRequest {
+        SentRequest {
+            method,
+            uri,
+            headers,
+            body,
+        }
+    }
+
+    /// Creates a copy of the request to be sent.
+    pub(super) fn to_request(&self) -> Request<Body> {
+        let mut request = Request::new(Body::from(self.body.clone()));
+        *request.method_mut() = self.method.clone();
+        *request.uri_mut() = self.uri.clone();
+        *request.headers_mut() = self.headers.clone();
+        request
+    }
+
+    /// Returns the request to send when `response` redirects this request, along with the
+    /// `Redirect` which it follows, or `None` when `response` isn't a redirect.
+    ///
+    /// As browsers do, a `POST` request redirected by a `301 Moved Permanently` or `302 Found`
+    /// response, and any request other than `HEAD` redirected by a `303 See Other` response, is
+    /// changed to a `GET` request without a body. Requests redirected by a `307 Temporary
+    /// Redirect` or `308 Permanent Redirect` response are sent again unchanged.
+    pub(super) fn redirect(
+        &self,
+        response: &Response<Body>,
+    ) -> Result<Option<(SentRequest, Redirect)>> {
+        let status = response.status();
+        let to_get = match status {
+            StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND => self.method == Method::POST,
+            StatusCode::SEE_OTHER => self.method != Method::HEAD,
+            StatusCode::TEMPORARY_REDIRECT | StatusCode::PERMANENT_REDIRECT => false,
+            _ => return Ok(None),
+        };
+
+        let location = match response.headers().get(LOCATION) {
+            Some(location) => location.to_str()?,
+            None => return Ok(None),
+        };
+
+        let location: Uri = Url::parse(&self.uri.to_string())?
+            .join(location)?
+            .as_str()
+            .parse()?;
+
+        let mut next = SentRequest {
+            method: self.method.clone(),
+            uri: location.clone(),
+            headers: self.headers.clone(),
+            body: self.body.clone(),
+        };
+
+        if to_get {
+            next.method = Method::GET;
+            next.body = Bytes::new();
+            next.headers.remove(CONTENT_TYPE);
+            next.headers.remove(CONTENT_LENGTH);
+        }
+
+        let redirect = Redirect {
+            uri: self.uri.clone(),
+            status,
+            location,
+        };
+
+        Ok(Some((next, redirect)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use hyper::header::HeaderValue;
+
+    fn sent(method: Method) -> SentRequest {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+        SentRequest::new(
+            method,
+            "http://localhost/a/b?c=d".parse().unwrap(),
+            headers,
+            Bytes::from("body"),
+        )
+    }
+
+    fn response(status: StatusCode, location: &'static str) -> Response<Body> {
+        Response::builder()
+            .status(status)
+            .header(LOCATION, location)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[test]
+    fn resolves_locations() {
+        let (next, redirect) = sent(Method::GET)
+            .redirect(&response(StatusCode::FOUND, "../e?f=g"))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(next.uri, "http://localhost/e?f=g");
+        assert_eq!(redirect.uri(), "http://localhost/a/b?c=d");
+        assert_eq!(redirect.status(), StatusCode::FOUND);
+        assert_eq!(redirect.location(), "http://localhost/e?f=g");
+    }
+
+    #[test]
+    fn switches_to_get() {
+        let (next, _) = sent(Method::POST)
+            .redirect(&response(StatusCode::SEE_OTHER, "/done"))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(next.method, Method::GET);
+        assert!(next.body.is_empty());
+        assert!(next.headers.get(CONTENT_TYPE).is_none());
+
+        let (next, _) = sent(Method::PUT)
+            .redirect(&response(StatusCode::FOUND, "/done"))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(next.method, Method::PUT);
+    }
+
+    #[test]
+    fn keeps_method_and_body() {
+        let (next, _) = sent(Method::POST)
+            .redirect(&response(StatusCode::TEMPORARY_REDIRECT, "/retry"))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(next.method, Method::POST);
+        assert_eq!(next.body, "body");
+        assert_eq!(next.headers[CONTENT_TYPE], "text/plain");
+    }
+
+    #[test]
+    fn ignores_other_responses() {
+        let request = sent(Method::GET);
+        assert!(request
+            .redirect(&response(StatusCode::NOT_MODIFIED, "/"))
+            .unwrap()
+            .is_none());
+
+        let ok = Response::new(Body::empty());
+        assert!(request.redirect(&ok).unwrap().is_none());
+    }
+}
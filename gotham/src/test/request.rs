@@ -1,19 +1,80 @@
 use std::ops::Deref;
 use std::ops::DerefMut;
+use std::time::Duration;
 
+use base64;
 use http::HttpTryFrom;
-use hyper::header::{HeaderValue, IntoHeaderName};
+use hyper::header::{HeaderValue, IntoHeaderName, AUTHORIZATION, CONTENT_TYPE, COOKIE};
 use hyper::{Body, Method, Request, Uri};
+use mime;
+use serde::Serialize;
+use serde_json;
+use url::form_urlencoded;
 
-use test::{TestClient, TestResponse};
+use test::{MultipartForm, TestClient, TestResponse, TestWebSocket};
 
 use error::*;
 
 /// Builder API for constructing `TestServer` requests. When the request is built,
 /// `RequestBuilder::perform` will issue the request and provide access to the response.
+///
+/// A URI without a scheme and authority, such as `/users?page=2`, is requested from
+/// `http://localhost`.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// # #[macro_use]
+/// # extern crate serde_derive;
+/// #
+/// # use hyper::header::{AUTHORIZATION, CONTENT_TYPE, COOKIE};
+/// # use hyper::{Body, HeaderMap, Method, Response, StatusCode};
+/// # use gotham::state::{FromState, State};
+/// # use gotham::test::TestServer;
+/// #
+/// # fn handler(state: State) -> (State, Response<Body>) {
+/// #   let echo = {
+/// #       let headers = HeaderMap::borrow_from(&state);
+/// #       format!(
+/// #           "{} {} {}",
+/// #           headers[CONTENT_TYPE].to_str().unwrap(),
+/// #           headers[COOKIE].to_str().unwrap(),
+/// #           headers[AUTHORIZATION].to_str().unwrap(),
+/// #       )
+/// #   };
+/// #   (state, Response::new(Body::from(echo)))
+/// # }
+/// #
+/// #[derive(Serialize)]
+/// struct NewUser {
+///     name: String,
+/// }
+///
+/// # fn main() {
+/// let test_server = TestServer::new(|| Ok(handler)).unwrap();
+///
+/// let response = test_server
+///     .client()
+///     .build_request(Method::POST, "/users")
+///     .with_json_body(&NewUser { name: "Ada".to_owned() })
+///     .with_cookie("session", "abc123")
+///     .with_basic_auth("admin", "secret")
+///     .perform()
+///     .unwrap();
+///
+/// assert_eq!(response.status(), StatusCode::OK);
+/// # assert_eq!(
+/// #   response.read_utf8_body().unwrap(),
+/// #   "application/json session=abc123 Basic YWRtaW46c2VjcmV0"
+/// # );
+/// # }
+/// ```
 pub struct TestRequest<'a> {
     client: &'a TestClient,
     request: Request<Body>,
+    timeout: Option<Duration>,
 }
 
 impl<'a> Deref for TestRequest<'a> {
@@ -35,13 +96,28 @@ impl<'a> TestRequest<'a> {
     where
         Uri: HttpTryFrom<U>,
     {
+        let mut request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(Body::empty())
+            .unwrap();
+
+        if request.uri().authority_part().is_none() {
+            let absolute = format!(
+                "http://localhost{}",
+                request
+                    .uri()
+                    .path_and_query()
+                    .map(|pq| pq.as_str())
+                    .unwrap_or("/")
+            );
+            *request.uri_mut() = absolute.parse().unwrap();
+        }
+
         TestRequest {
             client,
-            request: Request::builder()
-                .method(method)
-                .uri(uri)
-                .body(Body::empty())
-                .unwrap(),
+            request,
+            timeout: None,
         }
     }
 
@@ -50,11 +126,31 @@ impl<'a> TestRequest<'a> {
         self.client.perform(self)
     }
 
+    /// Send a constructed request as the opening handshake of a WebSocket connection using the
+    /// `TestClient`, and await the connection.
+    pub fn websocket(self) -> Result<TestWebSocket> {
+        self.client.websocket(self)
+    }
+
     /// Extracts the request from this `TestRequest`.
     pub(super) fn request(self) -> Request<Body> {
         self.request
     }
 
+    /// The timeout set by `TestRequest::with_timeout`, if any.
+    pub(super) fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// Sets the time allowed for the response to this request, instead of the timeout of the
+    /// `TestServer`, so that a slow request doesn't require a long timeout for every request.
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        TestRequest {
+            timeout: Some(timeout),
+            ..self
+        }
+    }
+
     /// Adds the given header into the underlying `Request`.
     pub fn with_header<N>(mut self, name: N, value: HeaderValue) -> Self
     where
@@ -63,4 +159,190 @@ impl<'a> TestRequest<'a> {
         self.headers_mut().insert(name, value);
         self
     }
+
+    /// Adds a cookie to the `Cookie` header of the underlying `Request`, keeping any cookies which
+    /// were already added.
+    ///
+    /// # Panics
+    ///
+    /// If `name` or `value` can't be sent in a header.
+    pub fn with_cookie(self, name: &str, value: &str) -> Self {
+        let cookie = match self.headers().get(COOKIE).and_then(|v| v.to_str().ok()) {
+            Some(existing) => format!("{}; {}={}", existing, name, value),
+            None => format!("{}={}", name, value),
+        };
+
+        let cookie = HeaderValue::from_str(&cookie).expect("cookie is a valid header value");
+        self.with_header(COOKIE, cookie)
+    }
+
+    /// Sets an `Authorization` header with the given credentials, using the `Basic` scheme.
+    pub fn with_basic_auth(self, username: &str, password: &str) -> Self {
+        let credentials = base64::encode(&format!("{}:{}", username, password));
+        let value = HeaderValue::from_str(&format!("Basic {}", credentials)).unwrap();
+        self.with_header(AUTHORIZATION, value)
+    }
+
+    /// Sets the body of the underlying `Request`, and a `Content-Type` header of `mime`.
+    pub fn with_body<B>(mut self, body: B, mime: mime::Mime) -> Self
+    where
+        B: Into<Body>,
+    {
+        *self.body_mut() = body.into();
+        let content_type = HeaderValue::from_str(mime.as_ref()).unwrap();
+        self.with_header(CONTENT_TYPE, content_type)
+    }
+
+    /// Sets the body of the underlying `Request` to `value` serialized as JSON, with a
+    /// `Content-Type` of `application/json`.
+    ///
+    /// # Panics
+    ///
+    /// If `value` can't be serialized as JSON.
+    pub fn with_json_body<T>(self, value: &T) -> Self
+    where
+        T: Serialize,
+    {
+        let body = serde_json::to_vec(value).expect("request body serializes as JSON");
+        self.with_body(body, mime::APPLICATION_JSON)
+    }
+
+    /// Sets the body of the underlying `Request` to the given fields, encoded as
+    /// `application/x-www-form-urlencoded`.
+    pub fn with_form_body(self, fields: &[(&str, &str)]) -> Self {
+        let body = form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(fields)
+            .finish();
+        self.with_body(body, mime::APPLICATION_WWW_FORM_URLENCODED)
+    }
+
+    /// Sets the body of the underlying `Request` to the fields and files of `form`, encoded as
+    /// `multipart/form-data`.
+    pub fn with_multipart_body(self, form: MultipartForm) -> Self {
+        let content_type = form.content_type();
+        self.with_body(form.into_body(), content_type)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::{Future, Stream};
+    use hyper::header::HeaderName;
+    use hyper::{HeaderMap, Response, StatusCode};
+
+    use handler::{HandlerFuture, IntoHandlerError};
+    use state::{FromState, State};
+    use test::TestServer;
+
+    // Responds with the URI, content type, cookies and body of the request.
+    fn echo(mut state: State) -> Box<HandlerFuture> {
+        let f = Body::take_from(&mut state)
+            .concat2()
+            .then(move |body| match body {
+                Ok(body) => {
+                    let echo = {
+                        let headers = HeaderMap::borrow_from(&state);
+                        let header = |name: HeaderName| {
+                            headers
+                                .get(name)
+                                .map(|v| v.to_str().unwrap().to_owned())
+                                .unwrap_or_default()
+                        };
+                        format!(
+                            "{}|{}|{}|{}",
+                            Uri::borrow_from(&state),
+                            header(CONTENT_TYPE),
+                            header(COOKIE),
+                            String::from_utf8(body.to_vec()).unwrap()
+                        )
+                    };
+                    Ok((state, Response::new(Body::from(echo))))
+                }
+                Err(e) => Err((state, e.into_handler_error())),
+            });
+
+        Box::new(f)
+    }
+
+    fn perform<F>(f: F) -> String
+    where
+        F: for<'a> FnOnce(&'a TestClient) -> TestRequest<'a>,
+    {
+        let test_server = TestServer::new(|| Ok(echo)).unwrap();
+        let client = test_server.client();
+        let response = f(&client).perform().unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        response.read_utf8_body().unwrap()
+    }
+
+    #[test]
+    fn relative_uris_request_localhost() {
+        assert_eq!(
+            perform(|client| client.get("/users?page=2")),
+            "/users?page=2|||"
+        );
+    }
+
+    #[test]
+    fn accumulates_cookies() {
+        assert_eq!(
+            perform(|client| client
+                .get("http://example.com/")
+                .with_cookie("a", "1")
+                .with_cookie("b", "2")),
+            "/||a=1; b=2|"
+        );
+    }
+
+    #[test]
+    fn sends_json_bodies() {
+        let value = json!({ "name": "Ada" });
+        assert_eq!(
+            perform(|client| client
+                .post("/users", "", mime::TEXT_PLAIN)
+                .with_json_body(&value)),
+            "/users|application/json||{\"name\":\"Ada\"}"
+        );
+    }
+
+    #[test]
+    fn sends_form_bodies() {
+        assert_eq!(
+            perform(|client| client
+                .put("/users/1", "", mime::TEXT_PLAIN)
+                .with_form_body(&[("name", "Ada Lovelace"), ("role", "a&b")])),
+            "/users/1|application/x-www-form-urlencoded||name=Ada+Lovelace&role=a%26b"
+        );
+    }
+
+    #[test]
+    fn sends_multipart_bodies() {
+        let form = MultipartForm::new().with_file("file", "a.txt", mime::TEXT_PLAIN, "hello");
+        let echo = perform(move |client| {
+            client
+                .build_request(Method::POST, "/upload")
+                .with_multipart_body(form)
+        });
+
+        let parts: Vec<&str> = echo.split('|').collect();
+        assert!(parts[1].starts_with("multipart/form-data; boundary="));
+        let boundary = &parts[1]["multipart/form-data; boundary=".len()..];
+        assert!(parts[3].starts_with(&format!("--{}\r\n", boundary)));
+        assert!(parts[3].contains("\r\n\r\nhello\r\n"));
+        assert!(parts[3].ends_with(&format!("--{}--\r\n", boundary)));
+    }
+
+    #[test]
+    fn sends_basic_auth() {
+        let test_server = TestServer::new(|| Ok(echo)).unwrap();
+        let client = test_server.client();
+        let request = client.get("/").with_basic_auth("Aladdin", "open sesame");
+
+        assert_eq!(
+            request.headers()[AUTHORIZATION],
+            "Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ=="
+        );
+    }
 }
@@ -0,0 +1,502 @@
+//! Defines `TestWebSocket`, which exchanges WebSocket messages with a `TestServer` over a
+//! connection upgraded by `TestClient::websocket`.
+
+use std::io;
+use std::time::Duration;
+
+use base64;
+use failure;
+use futures::Future;
+use hyper::header::{
+    HeaderValue, CONNECTION, SEC_WEBSOCKET_ACCEPT, SEC_WEBSOCKET_KEY, SEC_WEBSOCKET_VERSION,
+    UPGRADE,
+};
+use hyper::upgrade::Upgraded;
+use hyper::StatusCode;
+use rand;
+use ring::digest;
+use tokio::io::{read_exact, write_all, AsyncRead, AsyncWrite};
+
+use test::{TestClient, TestRequest, TestServer};
+
+use error::*;
+
+// Appended to the `Sec-WebSocket-Key` of a handshake before it is hashed, per RFC 6455.
+const ACCEPT_GUID: &[u8] = b"258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+const CONTINUATION: u8 = 0x0;
+const TEXT: u8 = 0x1;
+const BINARY: u8 = 0x2;
+const CLOSE: u8 = 0x8;
+const PING: u8 = 0x9;
+const PONG: u8 = 0xa;
+
+/// A message sent or received by a `TestWebSocket`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum WebSocketMessage {
+    /// A text message, which is valid UTF-8.
+    Text(String),
+
+    /// A binary message.
+    Binary(Vec<u8>),
+
+    /// A ping, which the peer should answer with a pong carrying the same data.
+    Ping(Vec<u8>),
+
+    /// A pong, answering a ping.
+    Pong(Vec<u8>),
+
+    /// A close message, with the status code and reason for closing, if given.
+    Close(Option<(u16, String)>),
+}
+
+impl WebSocketMessage {
+    fn into_frame(self) -> Frame {
+        let (opcode, payload) = match self {
+            WebSocketMessage::Text(text) => (TEXT, text.into_bytes()),
+            WebSocketMessage::Binary(data) => (BINARY, data),
+            WebSocketMessage::Ping(data) => (PING, data),
+            WebSocketMessage::Pong(data) => (PONG, data),
+            WebSocketMessage::Close(None) => (CLOSE, Vec::new()),
+            WebSocketMessage::Close(Some((code, reason))) => {
+                let mut payload = vec![(code >> 8) as u8, code as u8];
+                payload.extend_from_slice(reason.as_bytes());
+                (CLOSE, payload)
+            }
+        };
+
+        Frame {
+            fin: true,
+            opcode,
+            payload,
+        }
+    }
+
+    fn from_payload(opcode: u8, payload: Vec<u8>) -> Result<WebSocketMessage> {
+        let message = match opcode {
+            TEXT => WebSocketMessage::Text(String::from_utf8(payload)?),
+            BINARY => WebSocketMessage::Binary(payload),
+            PING => WebSocketMessage::Ping(payload),
+            PONG => WebSocketMessage::Pong(payload),
+            CLOSE if payload.len() < 2 => WebSocketMessage::Close(None),
+            CLOSE => {
+                let code = u16::from(payload[0]) << 8 | u16::from(payload[1]);
+                let reason = String::from_utf8(payload[2..].to_vec())?;
+                WebSocketMessage::Close(Some((code, reason)))
+            }
+            _ => {
+                let message = format!("TestWebSocket: unknown opcode {:#x}", opcode);
+                return Err(failure::err_msg(message));
+            }
+        };
+
+        Ok(message)
+    }
+}
+
+/// A WebSocket connection to a `TestServer`, created by `TestClient::websocket`, which sends and
+/// receives messages synchronously by running the `TestServer`'s event loop until each message
+/// has been exchanged, or the timeout of the handshake request has elapsed.
+///
+/// Messages are sent unfragmented. Messages received in fragments are reassembled, and control
+/// messages, such as pings, are returned as they arrive rather than being answered, so that a
+/// test can see everything which the server sends.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # extern crate gotham;
+/// # extern crate hyper;
+/// #
+/// # use hyper::{Body, Response};
+/// # use gotham::state::State;
+/// # use gotham::test::{TestServer, WebSocketMessage};
+/// #
+/// # fn echo(state: State) -> (State, Response<Body>) {
+/// #   (state, Response::new(Body::empty()))
+/// # }
+/// #
+/// # fn main() {
+/// // `echo` answers the handshake, and sends back each message it receives.
+/// let test_server = TestServer::new(|| Ok(echo)).unwrap();
+/// let mut socket = test_server.client().get("http://localhost/echo").websocket().unwrap();
+///
+/// socket.send(WebSocketMessage::Text("hello".to_owned())).unwrap();
+/// assert_eq!(socket.recv().unwrap(), WebSocketMessage::Text("hello".to_owned()));
+///
+/// socket.close().unwrap();
+/// # }
+/// ```
+pub struct TestWebSocket {
+    io: Option<Upgraded>,
+    fragments: Option<(u8, Vec<u8>)>,
+    test_server: TestServer,
+    timeout: Duration,
+}
+
+impl TestWebSocket {
+    /// Sends `message` to the server.
+    pub fn send(&mut self, message: WebSocketMessage) -> Result<()> {
+        let io = self.take_io()?;
+
+        // frames sent by a client are always masked
+        let f = write_frame(io, &message.into_frame(), Some(rand::random()));
+        self.io = Some(self.test_server.run_request(f, self.timeout)?);
+        Ok(())
+    }
+
+    /// Waits for the next message from the server.
+    pub fn recv(&mut self) -> Result<WebSocketMessage> {
+        loop {
+            let io = self.take_io()?;
+            let (io, frame) = self.test_server.run_request(read_frame(io), self.timeout)?;
+            self.io = Some(io);
+
+            // control messages may arrive between the fragments of another message
+            if frame.opcode >= CLOSE {
+                return WebSocketMessage::from_payload(frame.opcode, frame.payload);
+            }
+
+            let (opcode, payload) = match (self.fragments.take(), frame.opcode) {
+                (None, CONTINUATION) => {
+                    return Err(failure::err_msg(
+                        "TestWebSocket: received a continuation without a message",
+                    ));
+                }
+                (None, opcode) => (opcode, frame.payload),
+                (Some((opcode, mut payload)), CONTINUATION) => {
+                    payload.extend(frame.payload);
+                    (opcode, payload)
+                }
+                (Some(_), _) => {
+                    return Err(failure::err_msg(
+                        "TestWebSocket: received a message before the previous one ended",
+                    ));
+                }
+            };
+
+            if frame.fin {
+                return WebSocketMessage::from_payload(opcode, payload);
+            }
+
+            self.fragments = Some((opcode, payload));
+        }
+    }
+
+    /// Closes the connection normally, waiting for the server to acknowledge it. Any messages
+    /// which the server sends in the meantime are discarded.
+    pub fn close(mut self) -> Result<()> {
+        self.send(WebSocketMessage::Close(Some((1000, String::new()))))?;
+
+        loop {
+            if let WebSocketMessage::Close(_) = self.recv()? {
+                return Ok(());
+            }
+        }
+    }
+
+    // The connection is held by the future for each exchange, and isn't returned when it fails.
+    fn take_io(&mut self) -> Result<Upgraded> {
+        self.io
+            .take()
+            .ok_or_else(|| failure::err_msg("TestWebSocket: the connection has failed"))
+    }
+}
+
+/// Performs the opening handshake of a WebSocket connection with `request`.
+pub(super) fn connect(client: &TestClient, mut request: TestRequest) -> Result<TestWebSocket> {
+    let key = base64::encode(&rand::random::<[u8; 16]>());
+    let timeout = request.timeout().unwrap_or(client.test_server.data.timeout);
+
+    {
+        let headers = request.headers_mut();
+        headers.insert(CONNECTION, HeaderValue::from_static("upgrade"));
+        headers.insert(UPGRADE, HeaderValue::from_static("websocket"));
+        headers.insert(SEC_WEBSOCKET_VERSION, HeaderValue::from_static("13"));
+        headers.insert(SEC_WEBSOCKET_KEY, HeaderValue::from_str(&key)?);
+    }
+
+    let response = client.perform(request)?;
+    if response.status() != StatusCode::SWITCHING_PROTOCOLS {
+        let message = format!(
+            "TestWebSocket: handshake refused with {}",
+            response.status()
+        );
+        return Err(failure::err_msg(message));
+    }
+
+    let accepted = response
+        .headers()
+        .get(SEC_WEBSOCKET_ACCEPT)
+        .map_or(false, |accept| accept == &accept_key(key.as_bytes())[..]);
+    if !accepted {
+        return Err(failure::err_msg(
+            "TestWebSocket: handshake answered with the wrong Sec-WebSocket-Accept",
+        ));
+    }
+
+    let test_server = client.test_server.clone();
+    let io = test_server.run_request(response.response.into_body().on_upgrade(), timeout)?;
+
+    Ok(TestWebSocket {
+        io: Some(io),
+        fragments: None,
+        test_server,
+        timeout,
+    })
+}
+
+// The `Sec-WebSocket-Accept` value which answers the `Sec-WebSocket-Key` of a handshake.
+fn accept_key(key: &[u8]) -> String {
+    let mut context = digest::Context::new(&digest::SHA1);
+    context.update(key);
+    context.update(ACCEPT_GUID);
+    base64::encode(context.finish().as_ref())
+}
+
+// A single frame of a WebSocket message.
+struct Frame {
+    fin: bool,
+    opcode: u8,
+    payload: Vec<u8>,
+}
+
+impl Frame {
+    fn encode(&self, mask: Option<[u8; 4]>) -> Vec<u8> {
+        let len = self.payload.len();
+        let mut buf = Vec::with_capacity(len + 14);
+
+        buf.push(if self.fin { 0x80 } else { 0 } | self.opcode);
+
+        let masked = if mask.is_some() { 0x80 } else { 0 };
+        if len < 126 {
+            buf.push(masked | len as u8);
+        } else if len <= 0xffff {
+            buf.push(masked | 126);
+            buf.extend_from_slice(&[(len >> 8) as u8, len as u8]);
+        } else {
+            buf.push(masked | 127);
+            buf.extend((0..8).rev().map(|i| (len as u64 >> (i * 8)) as u8));
+        }
+
+        match mask {
+            Some(key) => {
+                buf.extend_from_slice(&key);
+                buf.extend(
+                    self.payload
+                        .iter()
+                        .zip(key.iter().cycle())
+                        .map(|(b, k)| b ^ k),
+                );
+            }
+            None => buf.extend_from_slice(&self.payload),
+        }
+
+        buf
+    }
+}
+
+// Reads a frame from `io`, unmasking its payload if it is masked.
+fn read_frame<T>(io: T) -> Box<Future<Item = (T, Frame), Error = io::Error> + Send>
+where
+    T: AsyncRead + Send + 'static,
+{
+    let f = read_exact(io, [0u8; 2])
+        .and_then(|(io, head)| {
+            // the extended payload length, followed by the masking key
+            let extended = match head[1] & 0x7f {
+                126 => 2,
+                127 => 8,
+                _ => 0,
+            };
+            let mask = if head[1] & 0x80 != 0 { 4 } else { 0 };
+
+            read_exact(io, vec![0; extended + mask]).map(move |(io, rest)| (io, head, rest))
+        })
+        .and_then(|(io, head, rest)| {
+            let (extended, mask) = match head[1] & 0x80 {
+                0 => (&rest[..], None),
+                _ => {
+                    let (extended, key) = rest.split_at(rest.len() - 4);
+                    (extended, Some([key[0], key[1], key[2], key[3]]))
+                }
+            };
+
+            let len = match head[1] & 0x7f {
+                126 | 127 => extended.iter().fold(0, |len, b| len << 8 | u64::from(*b)),
+                len => u64::from(len),
+            };
+
+            read_exact(io, vec![0; len as usize]).map(move |(io, mut payload)| {
+                if let Some(key) = mask {
+                    for (b, k) in payload.iter_mut().zip(key.iter().cycle()) {
+                        *b ^= k;
+                    }
+                }
+
+                let frame = Frame {
+                    fin: head[0] & 0x80 != 0,
+                    opcode: head[0] & 0x0f,
+                    payload,
+                };
+
+                (io, frame)
+            })
+        });
+
+    Box::new(f)
+}
+
+// Writes `frame` to `io`, masked with `mask` if it is given.
+fn write_frame<T>(
+    io: T,
+    frame: &Frame,
+    mask: Option<[u8; 4]>,
+) -> Box<Future<Item = T, Error = io::Error> + Send>
+where
+    T: AsyncWrite + Send + 'static,
+{
+    Box::new(write_all(io, frame.encode(mask)).map(|(io, _)| io))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    use futures::future::{self, Loop};
+    use hyper::header::HeaderMap;
+    use hyper::{Body, Response};
+    use tokio;
+
+    use state::{FromState, State};
+
+    // Answers the handshake, and then echoes each message it receives in two fragments, preceded
+    // by a ping, until the client closes the connection.
+    fn echo(mut state: State) -> (State, Response<Body>) {
+        let accept = {
+            let key = HeaderMap::borrow_from(&state)[SEC_WEBSOCKET_KEY].as_bytes();
+            accept_key(key)
+        };
+
+        let upgrade = Body::take_from(&mut state)
+            .on_upgrade()
+            .map_err(|e| panic!("upgrade failed: {}", e))
+            .and_then(|io| {
+                future::loop_fn(io, |io| {
+                    read_frame(io).and_then(|(io, frame)| {
+                        if frame.opcode == CLOSE {
+                            let f = write_frame(io, &frame, None).map(Loop::Break);
+                            return Box::new(f) as Box<Future<Item = _, Error = _> + Send>;
+                        }
+
+                        let mut first = frame.payload;
+                        let second = first.split_off(first.len() / 2);
+                        let ping = Frame {
+                            fin: true,
+                            opcode: PING,
+                            payload: b"ping".to_vec(),
+                        };
+                        let first = Frame {
+                            fin: false,
+                            opcode: frame.opcode,
+                            payload: first,
+                        };
+                        let second = Frame {
+                            fin: true,
+                            opcode: CONTINUATION,
+                            payload: second,
+                        };
+
+                        let f = write_frame(io, &first, None)
+                            .and_then(move |io| write_frame(io, &ping, None))
+                            .and_then(move |io| write_frame(io, &second, None))
+                            .map(Loop::Continue);
+                        Box::new(f)
+                    })
+                })
+                .map(|_| ())
+                .map_err(|e| panic!("echo failed: {}", e))
+            });
+        tokio::spawn(upgrade);
+
+        let res = Response::builder()
+            .status(StatusCode::SWITCHING_PROTOCOLS)
+            .header(CONNECTION, "upgrade")
+            .header(UPGRADE, "websocket")
+            .header(SEC_WEBSOCKET_ACCEPT, accept)
+            .body(Body::empty())
+            .unwrap();
+
+        (state, res)
+    }
+
+    fn refuse(state: State) -> (State, Response<Body>) {
+        (state, Response::new(Body::empty()))
+    }
+
+    #[test]
+    fn computes_accept_key() {
+        // the example given by RFC 6455
+        assert_eq!(
+            accept_key(b"dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    #[test]
+    fn encodes_payload_lengths() {
+        for &len in &[0, 125, 126, 0xffff, 0x10000] {
+            let frame = Frame {
+                fin: true,
+                opcode: BINARY,
+                payload: vec![7; len],
+            };
+
+            let encoded = Cursor::new(frame.encode(Some([1, 2, 3, 4])));
+            let (_, decoded) = read_frame(encoded).wait().unwrap();
+            assert!(decoded.fin);
+            assert_eq!(decoded.opcode, BINARY);
+            assert_eq!(decoded.payload, frame.payload);
+        }
+    }
+
+    #[test]
+    fn exchanges_messages() {
+        let test_server = TestServer::new(|| Ok(echo)).unwrap();
+        let mut socket = test_server.client().get("/echo").websocket().unwrap();
+
+        socket
+            .send(WebSocketMessage::Text("hello".to_owned()))
+            .unwrap();
+        assert_eq!(
+            socket.recv().unwrap(),
+            WebSocketMessage::Ping(b"ping".to_vec())
+        );
+        assert_eq!(
+            socket.recv().unwrap(),
+            WebSocketMessage::Text("hello".to_owned())
+        );
+
+        socket
+            .send(WebSocketMessage::Binary(vec![1; 70000]))
+            .unwrap();
+        assert_eq!(
+            socket.recv().unwrap(),
+            WebSocketMessage::Ping(b"ping".to_vec())
+        );
+        assert_eq!(
+            socket.recv().unwrap(),
+            WebSocketMessage::Binary(vec![1; 70000])
+        );
+
+        socket.close().unwrap();
+    }
+
+    #[test]
+    fn refused_handshake() {
+        let test_server = TestServer::new(|| Ok(refuse)).unwrap();
+        assert!(test_server.client().get("/").websocket().is_err());
+    }
+}
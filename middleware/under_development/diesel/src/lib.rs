@@ -11,6 +11,7 @@
 
 extern crate diesel;
 extern crate futures;
+extern crate futures_cpupool;
 extern crate gotham;
 #[macro_use]
 extern crate gotham_derive;
@@ -26,6 +27,7 @@ use std::panic::{catch_unwind, AssertUnwindSafe};
 use std::process;
 
 use futures::{future, Future};
+use futures_cpupool::CpuPool;
 
 use gotham::handler::HandlerFuture;
 use gotham::middleware::{Middleware, NewMiddleware};
@@ -45,6 +47,7 @@ where
     T: Connection + 'static,
 {
     pool: AssertUnwindSafe<r2d2::Pool<ConnectionManager<T>>>,
+    cpu_pool: AssertUnwindSafe<CpuPool>,
 }
 
 /// Instance created by DieselMiddleware for each request that implements
@@ -54,6 +57,7 @@ where
     T: Connection + 'static,
 {
     pool: r2d2::Pool<ConnectionManager<T>>,
+    cpu_pool: CpuPool,
 }
 
 impl<T> DieselMiddleware<T>
@@ -86,6 +90,16 @@ where
     pub fn with_pool(pool: Pool<ConnectionManager<T>>) -> Self {
         DieselMiddleware {
             pool: AssertUnwindSafe(pool),
+            cpu_pool: AssertUnwindSafe(CpuPool::new_num_cpus()),
+        }
+    }
+
+    /// Sets the thread pool used by `state_data::run_with_conn` to run blocking database work
+    /// away from the event loop. By default, a pool with one thread per CPU is used.
+    pub fn with_cpu_pool(self, cpu_pool: CpuPool) -> Self {
+        DieselMiddleware {
+            cpu_pool: AssertUnwindSafe(cpu_pool),
+            ..self
         }
     }
 }
@@ -98,7 +112,10 @@ where
 
     fn new_middleware(&self) -> io::Result<Self::Instance> {
         match catch_unwind(|| self.pool.clone()) {
-            Ok(pool) => Ok(DieselMiddlewareImpl { pool }),
+            Ok(pool) => Ok(DieselMiddlewareImpl {
+                pool,
+                cpu_pool: self.cpu_pool.clone(),
+            }),
             Err(_) => {
                 error!(
                     "PANIC: r2d2::Pool::clone caused a panic, unable to rescue with a HTTP error"
@@ -120,6 +137,7 @@ where
         match catch_unwind(|| self.pool.clone()) {
             Ok(pool) => DieselMiddleware {
                 pool: AssertUnwindSafe(pool),
+                cpu_pool: AssertUnwindSafe(self.cpu_pool.clone()),
             },
             Err(_) => {
                 error!("PANIC: r2d2::Pool::clone caused a panic");
@@ -139,7 +157,7 @@ where
         Chain: FnOnce(State) -> Box<HandlerFuture>,
     {
         trace!("[{}] pre chain", request_id(&state));
-        state.put(Diesel::<T>::new(self.pool));
+        state.put(Diesel::<T>::new(self.pool, self.cpu_pool));
 
         let f = chain(state).and_then(move |(state, response)| {
             {
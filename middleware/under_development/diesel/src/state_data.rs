@@ -2,6 +2,8 @@
 //! pool so a connection can be established if required by Middleware or Handlers.
 
 use diesel::Connection;
+use futures_cpupool::{CpuFuture, CpuPool};
+use gotham::handler::{HandlerError, IntoHandlerError};
 use gotham::state::{FromState, State};
 use r2d2::{Error, Pool, PooledConnection};
 use r2d2_diesel::ConnectionManager;
@@ -28,6 +30,47 @@ where
     Diesel::borrow_from(s).conn()
 }
 
+/// Runs `f` with a Diesel connection on the thread pool of the `DieselMiddleware`, so that the
+/// blocking database work does not stall the event loop. The connection is returned to the pool
+/// once `f` completes.
+///
+/// The returned future can be composed with the rest of an asynchronous `Handler`. It fails with a
+/// `HandlerError` if a connection can not be provided, or if `f` fails.
+///
+/// ```rust,no_run
+/// # extern crate diesel;
+/// # extern crate futures_cpupool;
+/// # extern crate gotham;
+/// # extern crate gotham_middleware_diesel;
+/// #
+/// # use diesel::sqlite::SqliteConnection;
+/// # use diesel::{sql_query, RunQueryDsl};
+/// # use futures_cpupool::CpuFuture;
+/// # use gotham::handler::HandlerError;
+/// # use gotham::state::State;
+/// # use gotham_middleware_diesel::state_data::run_with_conn;
+/// #
+/// fn clear_sessions(state: &State) -> CpuFuture<usize, HandlerError> {
+///     run_with_conn(state, |conn: &SqliteConnection| {
+///         sql_query("DELETE FROM sessions").execute(conn)
+///     })
+/// }
+/// #
+/// # fn main() {}
+/// ```
+///
+/// # Panics
+/// If the `DieselMiddleware` has not been run for the request.
+pub fn run_with_conn<T, F, R, E>(s: &State, f: F) -> CpuFuture<R, HandlerError>
+where
+    T: Connection + 'static,
+    F: FnOnce(&T) -> Result<R, E> + Send + 'static,
+    R: Send + 'static,
+    E: IntoHandlerError,
+{
+    Diesel::<T>::borrow_from(s).run(f)
+}
+
 /// Provides access to a Diesel connection within an r2d2 pool via Gotham State
 #[derive(StateData)]
 pub struct Diesel<T>
@@ -35,18 +78,35 @@ where
     T: Connection + 'static,
 {
     pool: Pool<ConnectionManager<T>>,
+    cpu_pool: CpuPool,
 }
 
 impl<T> Diesel<T>
 where
     T: Connection + 'static,
 {
-    pub(crate) fn new(pool: Pool<ConnectionManager<T>>) -> Self {
-        Diesel { pool }
+    pub(crate) fn new(pool: Pool<ConnectionManager<T>>, cpu_pool: CpuPool) -> Self {
+        Diesel { pool, cpu_pool }
     }
 
     /// Provides access to a Diesel connection from our r2d2 backed connection pool.
     pub fn conn(&self) -> Result<PooledConnection<ConnectionManager<T>>, Error> {
         self.pool.get()
     }
+
+    /// Runs `f` with a Diesel connection on the thread pool of the `DieselMiddleware`. See
+    /// `run_with_conn` for details.
+    pub fn run<F, R, E>(&self, f: F) -> CpuFuture<R, HandlerError>
+    where
+        F: FnOnce(&T) -> Result<R, E> + Send + 'static,
+        R: Send + 'static,
+        E: IntoHandlerError,
+    {
+        let pool = self.pool.clone();
+
+        self.cpu_pool.spawn_fn(move || {
+            let conn = pool.get().map_err(|e| e.into_handler_error())?;
+            f(&conn).map_err(|e| e.into_handler_error())
+        })
+    }
 }